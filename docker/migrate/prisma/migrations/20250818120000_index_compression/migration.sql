@@ -0,0 +1,17 @@
+/*
+  Warnings:
+
+  - A unique constraint covering the columns `[component_id,architecture,compression]` on the table `debian_repository_index_packages` will be added. If there are existing duplicate values, this will fail.
+
+*/
+-- AlterEnum
+ALTER TYPE "debian_repository_index_compression" ADD VALUE 'zstd';
+
+-- DropIndex
+DROP INDEX "debian_repository_index_packages_component_id_architecture_key";
+
+-- AlterTable
+ALTER TABLE "debian_repository_release" ADD COLUMN     "index_compression" "debian_repository_index_compression"[] DEFAULT ARRAY[]::"debian_repository_index_compression"[];
+
+-- CreateIndex
+CREATE UNIQUE INDEX "debian_repository_index_packages_component_id_architecture__key" ON "debian_repository_index_packages"("component_id", "architecture", "compression");
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "debian_repository_release" ADD COLUMN     "declared_architectures" "debian_repository_architecture"[] DEFAULT ARRAY[]::"debian_repository_architecture"[],
+ADD COLUMN     "declared_components" TEXT[] DEFAULT ARRAY[]::TEXT[];
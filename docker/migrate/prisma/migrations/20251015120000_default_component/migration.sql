@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "debian_repository_release" ADD COLUMN     "default_component" TEXT;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "debian_repository_package" ADD COLUMN     "changelog" BYTEA,
+ADD COLUMN     "copyright" BYTEA;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "debian_repository" ADD COLUMN     "allowed_architectures" "debian_repository_architecture"[] DEFAULT ARRAY[]::"debian_repository_architecture"[],
+ADD COLUMN     "allowed_components" TEXT[] DEFAULT ARRAY[]::TEXT[];
@@ -0,0 +1,5 @@
+-- DropIndex
+DROP INDEX "debian_repository_package_tenant_id_package_version_archite_key";
+
+-- CreateIndex
+CREATE INDEX "debian_repository_package_tenant_id_package_version_archite_idx" ON "debian_repository_package"("tenant_id", "package", "version", "architecture");
@@ -0,0 +1,5 @@
+-- CreateEnum
+CREATE TYPE "debian_repository_contents_encoding" AS ENUM ('gzip');
+
+-- AlterTable
+ALTER TABLE "debian_repository_index_packages" ADD COLUMN     "contents_encoding" "debian_repository_contents_encoding";
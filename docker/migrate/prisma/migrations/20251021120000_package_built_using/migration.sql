@@ -0,0 +1,9 @@
+-- AlterTable
+ALTER TABLE "debian_repository_package" ADD COLUMN     "built_using" TEXT,
+ADD COLUMN     "static_built_using" TEXT;
+
+-- Backfill from the stored control paragraphs of existing packages.
+UPDATE "debian_repository_package"
+SET
+    "built_using" = "paragraph"->>'Built-Using',
+    "static_built_using" = "paragraph"->>'Static-Built-Using';
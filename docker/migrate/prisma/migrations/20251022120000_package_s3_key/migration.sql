@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "debian_repository_package" ADD COLUMN     "s3_key" TEXT;
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "debian_repository_index_packages" ADD COLUMN     "sha512sum" TEXT;
@@ -0,0 +1,2 @@
+-- AlterEnum
+ALTER TYPE "debian_repository_architecture" ADD VALUE 'all';
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "attune_tenant_api_token" ADD COLUMN     "scopes" TEXT[] DEFAULT ARRAY[]::TEXT[];
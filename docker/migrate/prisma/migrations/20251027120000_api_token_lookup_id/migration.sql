@@ -0,0 +1,7 @@
+-- AlterTable
+ALTER TABLE "attune_tenant_api_token" ADD COLUMN     "lookup_id" TEXT,
+ADD COLUMN     "token_hash" TEXT,
+ALTER COLUMN "token" DROP NOT NULL;
+
+-- CreateIndex
+CREATE UNIQUE INDEX "attune_tenant_api_token_lookup_id_key" ON "attune_tenant_api_token"("lookup_id");
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "debian_repository" ADD COLUMN     "webhook_secret" TEXT,
+ADD COLUMN     "webhook_url" TEXT;
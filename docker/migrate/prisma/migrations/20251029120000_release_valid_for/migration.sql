@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "debian_repository_release" ADD COLUMN     "valid_for_seconds" BIGINT;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "debian_repository_release" ADD COLUMN     "but_automatic_upgrades" BOOLEAN NOT NULL DEFAULT false,
+ADD COLUMN     "not_automatic" BOOLEAN NOT NULL DEFAULT false;
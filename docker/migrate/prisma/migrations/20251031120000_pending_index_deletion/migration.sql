@@ -0,0 +1,19 @@
+-- CreateTable
+CREATE TABLE "pending_index_deletion" (
+    "id" BIGSERIAL NOT NULL,
+    "repository_id" BIGINT NOT NULL,
+    "s3_bucket" TEXT NOT NULL,
+    "s3_key" TEXT NOT NULL,
+    "created_at" TIMESTAMPTZ(6) NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "pending_index_deletion_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "pending_index_deletion_created_at_idx" ON "pending_index_deletion"("created_at");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "pending_index_deletion_s3_bucket_s3_key_key" ON "pending_index_deletion"("s3_bucket", "s3_key");
+
+-- AddForeignKey
+ALTER TABLE "pending_index_deletion" ADD CONSTRAINT "pending_index_deletion_repository_id_fkey" FOREIGN KEY ("repository_id") REFERENCES "debian_repository"("id") ON DELETE CASCADE ON UPDATE CASCADE;
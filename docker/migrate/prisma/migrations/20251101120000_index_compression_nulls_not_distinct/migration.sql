@@ -0,0 +1,18 @@
+-- The uncompressed Packages index of each (component, architecture) has a NULL
+-- compression, and NULLs are distinct by default, so the unique index didn't
+-- prevent concurrent publishes from creating two uncompressed indexes. Keep the
+-- most recently updated one of any duplicates, and then treat NULLs as equal.
+DELETE FROM "debian_repository_index_packages" AS duplicate
+USING "debian_repository_index_packages" AS kept
+WHERE
+    duplicate."component_id" = kept."component_id"
+    AND duplicate."architecture" = kept."architecture"
+    AND duplicate."compression" IS NULL
+    AND kept."compression" IS NULL
+    AND (duplicate."updated_at", duplicate."id") < (kept."updated_at", kept."id");
+
+-- DropIndex
+DROP INDEX "debian_repository_index_packages_component_id_architecture__key";
+
+-- CreateIndex
+CREATE UNIQUE INDEX "debian_repository_index_packages_component_id_architecture__key" ON "debian_repository_index_packages"("component_id", "architecture", "compression") NULLS NOT DISTINCT;
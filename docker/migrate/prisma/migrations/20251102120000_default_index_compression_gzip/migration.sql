@@ -0,0 +1,11 @@
+-- Publish gzip-compressed Packages indexes by default, so that clients don't
+-- download the uncompressed indexes on every update. Existing distributions
+-- start publishing Packages.gz the next time each of their indexes changes.
+-- Distributions can opt out with `attune apt dist edit --index-compression`.
+
+-- AlterTable
+ALTER TABLE "debian_repository_release" ALTER COLUMN "index_compression" SET DEFAULT ARRAY['gz']::"debian_repository_index_compression"[];
+
+UPDATE "debian_repository_release"
+SET "index_compression" = array_append("index_compression", 'gz')
+WHERE "index_compression" IS NULL OR NOT ('gz' = ANY("index_compression"));
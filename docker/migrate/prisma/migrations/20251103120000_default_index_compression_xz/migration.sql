@@ -0,0 +1,12 @@
+-- Also publish xz-compressed Packages indexes by default, which are smaller
+-- than the gzip-compressed ones for clients that support them. As with gzip,
+-- existing distributions start publishing Packages.xz the next time each of
+-- their indexes changes, and can opt out with
+-- `attune apt dist edit --index-compression`.
+
+-- AlterTable
+ALTER TABLE "debian_repository_release" ALTER COLUMN "index_compression" SET DEFAULT ARRAY['gz', 'xz']::"debian_repository_index_compression"[];
+
+UPDATE "debian_repository_release"
+SET "index_compression" = array_append("index_compression", 'xz')
+WHERE "index_compression" IS NULL OR NOT ('xz' = ANY("index_compression"));
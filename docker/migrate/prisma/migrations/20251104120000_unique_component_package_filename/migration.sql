@@ -0,0 +1,8 @@
+-- Packages are no longer unique per tenant by (name, version, architecture),
+-- since a rebuild can be uploaded to replace a package. A component must
+-- still publish only one build of each (name, version, architecture). The
+-- pool filename is derived from exactly those fields, so this is enforced by
+-- making pool filenames unique within a component.
+
+-- CreateIndex
+CREATE UNIQUE INDEX "debian_repository_component_package_component_id_filename_key" ON "debian_repository_component_package"("component_id", "filename");
@@ -1,17 +1,156 @@
 //! Authentication and authorization.
 
+use std::{fmt, str::FromStr};
+
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString},
+};
 use axum::{
-    extract::{FromRef, FromRequestParts},
-    http::request,
+    extract::{FromRef, FromRequestParts, MatchedPath, RawPathParams},
+    http::{Method, StatusCode, request},
+    response::{IntoResponse as _, Response},
 };
+use percent_encoding::percent_decode_str;
 use sha2::{Digest as _, Sha256};
 use sqlx::PgPool;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::api::ErrorResponse;
 
 /// An extractor for tenants authenticated via API token.
+///
+/// Extracting a tenant also checks that the token's scopes allow the request,
+/// so handlers don't need to check scopes themselves.
 #[derive(Debug, Clone, Copy)]
 pub struct TenantID(pub i64);
 
-fn parse_api_token(header: &axum::http::header::HeaderMap) -> Result<&str, &'static str> {
+/// A restriction on what an API token can do.
+///
+/// A token without scopes has full access, as every token did before scopes
+/// existed. Otherwise:
+///
+/// - `read` only allows requests that don't change anything (`GET` and `HEAD`),
+///   unless the token also has `write`.
+/// - `write` allows every request.
+/// - `repo:<name>` only allows requests to the named repositories. Tokens with
+///   repository scopes can still upload packages (which aren't scoped to a
+///   repository), and listing or searching repositories and packages only
+///   returns those in the named repositories. They can't create repositories.
+///
+/// A token with only repository scopes can read and write those repositories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenScope {
+    Read,
+    Write,
+    Repository(String),
+}
+
+impl FromStr for TokenScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            _ => match s.strip_prefix("repo:") {
+                Some("") => Err(String::from("repository scope must name a repository")),
+                Some(name) => Ok(Self::Repository(name.to_string())),
+                None => Err(format!(
+                    "unknown scope {s:?}, expected `read`, `write`, or `repo:<name>`"
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read => write!(f, "read"),
+            Self::Write => write!(f, "write"),
+            Self::Repository(name) => write!(f, "repo:{name}"),
+        }
+    }
+}
+
+/// The scopes of an API token.
+///
+/// Extracting a [`TenantID`] adds the token's scopes to the request's
+/// extensions, so handlers that list across repositories can extract
+/// `Extension<TokenScopes>` (after the `TenantID`) to filter their results.
+#[derive(Debug, Clone, Default)]
+pub struct TokenScopes(pub Vec<TokenScope>);
+
+impl TokenScopes {
+    /// Parse the scopes stored for a token.
+    ///
+    /// Ignoring a scope that can't be parsed (e.g. one added by a newer
+    /// version) could grant more access than intended, so this fails instead.
+    pub fn parse(scopes: &[String]) -> Result<Self, String> {
+        scopes
+            .iter()
+            .map(|scope| scope.parse())
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// The repositories that the token is restricted to, or `None` if it can
+    /// access every repository.
+    pub fn repositories(&self) -> Option<Vec<String>> {
+        let repositories = self
+            .0
+            .iter()
+            .filter_map(|scope| match scope {
+                TokenScope::Repository(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        (!repositories.is_empty()).then_some(repositories)
+    }
+
+    /// Check that the scopes allow a request.
+    ///
+    /// `repository` is the (decoded) repository the request is routed to, if
+    /// any, and `creates_repository` is whether the request would create a
+    /// repository.
+    pub fn check(
+        &self,
+        method: &Method,
+        repository: Option<&str>,
+        creates_repository: bool,
+    ) -> Result<(), ErrorResponse> {
+        let read_only = self.0.contains(&TokenScope::Read) && !self.0.contains(&TokenScope::Write);
+        if read_only && !matches!(*method, Method::GET | Method::HEAD) {
+            return Err(insufficient_scope("API token is read-only"));
+        }
+
+        let Some(repositories) = self.repositories() else {
+            return Ok(());
+        };
+        if creates_repository {
+            return Err(insufficient_scope(
+                "API token is restricted to specific repositories, and can't create repositories",
+            ));
+        }
+        match repository {
+            Some(repository) if !repositories.iter().any(|name| name == repository) => {
+                Err(insufficient_scope(format!(
+                    "API token does not have access to repository {repository:?}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn insufficient_scope(message: impl Into<String>) -> ErrorResponse {
+    ErrorResponse::new(StatusCode::FORBIDDEN, "INSUFFICIENT_SCOPE", message)
+}
+
+pub(crate) fn parse_api_token(
+    header: &axum::http::header::HeaderMap,
+) -> Result<&str, &'static str> {
     let header = header
         .get("Authorization")
         .ok_or("`Authorization` header is missing")?;
@@ -24,40 +163,535 @@ fn parse_api_token(header: &axum::http::header::HeaderMap) -> Result<&str, &'sta
     Ok(token)
 }
 
+/// The repository that a request is routed to, if any.
+///
+/// Repository names are percent-encoded in paths on top of the URL's own
+/// encoding (see `decode_repo_name`), so they are decoded again here.
+async fn request_repository<S: Send + Sync>(
+    parts: &mut request::Parts,
+    state: &S,
+) -> Option<String> {
+    // Routes without parameters have no path parameters to extract.
+    let params = RawPathParams::from_request_parts(parts, state).await.ok()?;
+    params
+        .iter()
+        .find(|(key, _)| *key == "repository_name")
+        .map(|(_, name)| percent_decode_str(name).decode_utf8_lossy().into_owned())
+}
+
+/// Whether a request creates a repository, either directly or by cloning one.
+fn creates_repository(parts: &request::Parts) -> bool {
+    let Some(path) = parts.extensions.get::<MatchedPath>() else {
+        return false;
+    };
+    parts.method == Method::POST
+        && (path.as_str().ends_with("/repositories")
+            || path
+                .as_str()
+                .ends_with("/repositories/{repository_name}/clone"))
+}
+
+/// The prefix of tokens created by [`NewApiToken::generate`]. These tokens
+/// are `attune_<lookup ID>_<secret>`.
+const TOKEN_PREFIX: &str = "attune_";
+
+/// A newly generated API token, and what to store to verify it.
+///
+/// Tokens are looked up by their lookup ID, and their secret is checked
+/// against an argon2id hash, so that a leaked database doesn't reveal
+/// usable tokens. Legacy tokens (like `ATTUNE_API_TOKEN`) are stored as
+/// unsalted SHA256 hashes instead, and are still accepted.
+pub struct NewApiToken {
+    /// The token to give to the client. It isn't stored anywhere.
+    pub token: String,
+    pub lookup_id: String,
+    /// The argon2id hash of the token's secret, in PHC string format.
+    pub token_hash: String,
+}
+
+impl NewApiToken {
+    pub fn generate() -> Self {
+        let lookup_id = hex::encode(rand::random::<[u8; 8]>());
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+        let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>())
+            .expect("16 bytes is a valid salt length");
+        let token_hash = Argon2::default()
+            .hash_password(secret.as_bytes(), &salt)
+            .expect("could not hash API token")
+            .to_string();
+        Self {
+            token: format!("{TOKEN_PREFIX}{lookup_id}_{secret}"),
+            lookup_id,
+            token_hash,
+        }
+    }
+}
+
+/// Split a token into its lookup ID and secret, if it was created by
+/// [`NewApiToken::generate`].
+fn split_token(token: &str) -> Option<(&str, &str)> {
+    let (lookup_id, secret) = token.strip_prefix(TOKEN_PREFIX)?.split_once('_')?;
+    (!lookup_id.is_empty() && !secret.is_empty()).then_some((lookup_id, secret))
+}
+
+/// Check a token's secret against its argon2id hash. The comparison is
+/// constant-time.
+async fn verify_secret(secret: &str, token_hash: String) -> bool {
+    // Hashing is deliberately slow, so it runs off of the async runtime.
+    let secret = secret.to_string();
+    tokio::task::spawn_blocking(move || {
+        PasswordHash::new(&token_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(secret.as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .unwrap_or(false)
+}
+
+/// A stored API token that matches the one a client presented.
+///
+/// When rate limiting is enabled, the rate limiter authenticates the request
+/// first (to rate limit it by its token), and leaves the token in the request's
+/// extensions so that it isn't verified again.
+#[derive(Debug, Clone)]
+pub(crate) struct ApiToken {
+    pub(crate) id: i64,
+    tenant_id: i64,
+    scopes: Vec<String>,
+    expires_at: Option<OffsetDateTime>,
+    expired: bool,
+}
+
+/// Find the stored token that matches a presented token.
+///
+/// Tokens that look like they have a lookup ID are found by it and verified
+/// against their hash. Otherwise (or if no token has that lookup ID, since a
+/// legacy token could have any value) they are found by their SHA256 hash.
+pub(crate) async fn find_token(db: &PgPool, token: &str) -> Result<Option<ApiToken>, sqlx::Error> {
+    if let Some((lookup_id, secret)) = split_token(token) {
+        let found = sqlx::query!(
+            r#"
+            SELECT
+                attune_tenant_api_token.id,
+                attune_tenant.id AS tenant_id,
+                attune_tenant_api_token.token_hash AS "token_hash!",
+                attune_tenant_api_token.scopes AS "scopes!",
+                attune_tenant_api_token.expires_at,
+                COALESCE(attune_tenant_api_token.expires_at <= NOW(), FALSE) AS "expired!"
+            FROM attune_tenant
+                JOIN attune_tenant_api_token ON attune_tenant_api_token.tenant_id = attune_tenant.id
+            WHERE attune_tenant_api_token.lookup_id = $1
+                AND attune_tenant_api_token.token_hash IS NOT NULL;
+            "#,
+            lookup_id,
+        )
+        .fetch_optional(db)
+        .await?;
+        if let Some(found) = found {
+            return Ok(verify_secret(secret, found.token_hash)
+                .await
+                .then_some(ApiToken {
+                    id: found.id,
+                    tenant_id: found.tenant_id,
+                    scopes: found.scopes,
+                    expires_at: found.expires_at,
+                    expired: found.expired,
+                }));
+        }
+    }
+
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT
+            attune_tenant_api_token.id,
+            attune_tenant.id AS tenant_id,
+            attune_tenant_api_token.scopes AS "scopes!",
+            attune_tenant_api_token.expires_at,
+            COALESCE(attune_tenant_api_token.expires_at <= NOW(), FALSE) AS "expired!"
+        FROM attune_tenant
+            JOIN attune_tenant_api_token ON attune_tenant_api_token.tenant_id = attune_tenant.id
+        WHERE attune_tenant_api_token.token = $1;
+        "#,
+        Sha256::digest(token).as_slice().to_vec(),
+    )
+    .fetch_optional(db)
+    .await
+}
+
 impl<S> FromRequestParts<S> for TenantID
 where
     PgPool: FromRef<S>,
     S: Send + Sync,
 {
-    type Rejection = (axum::http::StatusCode, &'static str);
+    type Rejection = Response;
 
     async fn from_request_parts(
         parts: &mut request::Parts,
         state: &S,
     ) -> Result<Self, Self::Rejection> {
-        let token = parse_api_token(&parts.headers)
-            .map_err(|msg| (axum::http::StatusCode::UNAUTHORIZED, msg))?;
-        let db = PgPool::from_ref(state);
-        let tenant_id = sqlx::query!(
+        let token = match parts.extensions.get::<ApiToken>() {
+            Some(token) => Some(token.clone()),
+            None => {
+                let token = parse_api_token(&parts.headers)
+                    .map_err(|msg| (StatusCode::UNAUTHORIZED, msg).into_response())?;
+                let db = PgPool::from_ref(state);
+                find_token(&db, token).await.map_err(|_err| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Could not validate API token",
+                    )
+                        .into_response()
+                })?
+            }
+        };
+        let Some(token) = token else {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid API token\n").into_response());
+        };
+        // Tokens without an expiry never expire. Expiry is checked against the
+        // database's clock, like the rest of the server's timestamps.
+        if let Some(expires_at) = token.expires_at
+            && token.expired
+        {
+            return Err(ErrorResponse::new(
+                StatusCode::UNAUTHORIZED,
+                "TOKEN_EXPIRED",
+                format!(
+                    "API token expired at {}",
+                    expires_at.format(&Rfc3339).unwrap_or_default()
+                ),
+            )
+            .into_response());
+        }
+
+        let repository = request_repository(parts, state).await;
+        let scopes = TokenScopes::parse(&token.scopes)
+            .map_err(|err| insufficient_scope(format!("API token has invalid scopes: {err}")))
+            .and_then(|scopes| {
+                scopes.check(
+                    &parts.method,
+                    repository.as_deref(),
+                    creates_repository(parts),
+                )?;
+                Ok(scopes)
+            })
+            .map_err(|err| err.into_response())?;
+        parts.extensions.insert(scopes);
+        Ok(TenantID(token.tenant_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AttuneTestServer, AttuneTestServerConfig};
+
+    #[test]
+    fn check_scopes() {
+        let scopes = |scopes: &[&str]| {
+            TokenScopes(scopes.iter().map(|scope| scope.parse().unwrap()).collect())
+        };
+        let allowed = |scopes: &TokenScopes, method: Method, repo: Option<&str>, create: bool| {
+            scopes.check(&method, repo, create).is_ok()
+        };
+
+        // Unscoped tokens have full access.
+        let full = scopes(&[]);
+        assert!(allowed(&full, Method::POST, Some("a"), false));
+        assert!(allowed(&full, Method::POST, None, true));
+
+        let read = scopes(&["read"]);
+        assert!(allowed(&read, Method::GET, Some("a"), false));
+        assert!(!allowed(&read, Method::POST, Some("a"), false));
+        assert!(!allowed(&read, Method::DELETE, None, false));
+        assert!(allowed(
+            &scopes(&["read", "write"]),
+            Method::POST,
+            None,
+            false
+        ));
+
+        let repo = scopes(&["repo:a", "repo:b"]);
+        assert!(allowed(&repo, Method::POST, Some("a"), false));
+        assert!(allowed(&repo, Method::GET, Some("b"), false));
+        assert!(!allowed(&repo, Method::GET, Some("c"), false));
+        assert!(allowed(&repo, Method::POST, None, false));
+        assert!(!allowed(&repo, Method::POST, None, true));
+
+        let read_repo = scopes(&["read", "repo:a"]);
+        assert!(allowed(&read_repo, Method::GET, Some("a"), false));
+        assert!(!allowed(&read_repo, Method::POST, Some("a"), false));
+
+        assert_eq!(
+            "repo:a".parse::<TokenScope>().unwrap(),
+            TokenScope::Repository(String::from("a"))
+        );
+        assert_eq!(
+            TokenScope::Repository(String::from("a")).to_string(),
+            "repo:a"
+        );
+        assert!("repo:".parse::<TokenScope>().is_err());
+        assert!(TokenScopes::parse(&[String::from("read"), String::from("admin")]).is_err());
+    }
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn enforce_token_scopes(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool.clone(),
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        let (tenant_id, _) = server.create_test_tenant("enforce_token_scopes").await;
+        server.create_repository(tenant_id, "allowed").await;
+        server.create_repository(tenant_id, "other").await;
+
+        let add_token = async |token: &str, scopes: &[&str]| {
+            sqlx::query!(
+                r#"
+                INSERT INTO attune_tenant_api_token (tenant_id, name, token, scopes)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                tenant_id.0,
+                token,
+                Sha256::digest(token).as_slice().to_vec(),
+                &scopes
+                    .iter()
+                    .map(|scope| scope.to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        };
+        add_token("read-token", &["read"]).await;
+        add_token("repo-token", &["repo:allowed"]).await;
+
+        let request = async |method: Method, path: &str, token: &str| {
+            server
+                .http
+                .method(method, &format!("/api/v0{path}"))
+                .authorization_bearer(token)
+                .json(&serde_json::json!({ "name": "created" }))
+                .await
+        };
+
+        // Read-only tokens can read, but not change anything.
+        request(Method::GET, "/repositories/other", "read-token")
+            .await
+            .assert_status_ok();
+        let response = request(Method::POST, "/repositories", "read-token").await;
+        response.assert_status(StatusCode::FORBIDDEN);
+        assert_eq!(response.json::<ErrorResponse>().error, "INSUFFICIENT_SCOPE");
+
+        // Repository tokens can only use their repositories.
+        request(Method::GET, "/repositories/allowed", "repo-token")
+            .await
+            .assert_status_ok();
+        request(Method::GET, "/repositories/other", "repo-token")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+        request(Method::POST, "/repositories", "repo-token")
+            .await
+            .assert_status(StatusCode::FORBIDDEN);
+    }
+
+    /// Endpoints that list across repositories only return the repositories
+    /// that a token is restricted to.
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "../server/repo/index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn filter_results_by_repository_scope(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool.clone(),
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+
+        // Publish the fixture's amd64 package in a second repository too.
+        sqlx::query!(
             r#"
-            SELECT attune_tenant.id
-            FROM attune_tenant
-                JOIN attune_tenant_api_token ON attune_tenant_api_token.tenant_id = attune_tenant.id
-            WHERE attune_tenant_api_token.token = $1;
-            "#,
-            Sha256::digest(token).as_slice().to_vec(),
+            WITH repository AS (
+                INSERT INTO debian_repository (tenant_id, name, s3_bucket, s3_prefix, created_at, updated_at)
+                VALUES (1, 'other', 'attune-test-0', '1/other', NOW(), NOW())
+                RETURNING id
+            ), release AS (
+                INSERT INTO debian_repository_release (repository_id, distribution, suite, codename, contents, created_at, updated_at)
+                SELECT id, 'stable', 'stable', 'stable', '', NOW(), NOW() FROM repository
+                RETURNING id
+            ), component AS (
+                INSERT INTO debian_repository_component (release_id, name, created_at, updated_at)
+                SELECT id, 'main', NOW(), NOW() FROM release
+                RETURNING id
+            )
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            SELECT id, 1001, 'pool/main/t/test-package/test-package_1.0.0_amd64.deb', NOW(), NOW()
+            FROM component
+            "#
         )
-        .fetch_optional(&db)
+        .execute(&pool)
         .await
-        .map_err(|_err| {
-            (
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                "Could not validate API token",
+        .unwrap();
+        for (token, scopes) in [
+            ("full-token", vec![]),
+            ("repo-token", vec![String::from("repo:test-multi-arch")]),
+        ] {
+            sqlx::query!(
+                r#"
+                INSERT INTO attune_tenant_api_token (tenant_id, name, token, scopes)
+                VALUES (1, $1, $2, $3)
+                "#,
+                token,
+                Sha256::digest(token).as_slice().to_vec(),
+                &scopes,
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let repositories = async |path: &str, token: &str| {
+            let response = server
+                .http
+                .get(&format!("/api/v0{path}"))
+                .authorization_bearer(token)
+                .json(&serde_json::json!({}))
+                .await;
+            response.assert_status_ok();
+            let response = response.json::<serde_json::Value>();
+            let items = response
+                .get("packages")
+                .or_else(|| response.get("repositories"))
+                .unwrap();
+            let mut names = items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| {
+                    item.get("repository")
+                        .or_else(|| item.get("name"))
+                        .unwrap()
+                        .as_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            names.sort();
+            names.dedup();
+            names
+        };
+
+        for path in [
+            "/packages/search?name=test-package",
+            "/packages",
+            "/repositories",
+        ] {
+            assert_eq!(
+                repositories(path, "full-token").await,
+                ["other", "test-multi-arch"],
+                "{path}"
+            );
+            assert_eq!(
+                repositories(path, "repo-token").await,
+                ["test-multi-arch"],
+                "{path}"
+            );
+        }
+    }
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn reject_expired_tokens(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool.clone(),
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        let (tenant_id, _) = server.create_test_tenant("reject_expired_tokens").await;
+        for (token, expires_in) in [
+            ("expired-token", Some(-60.0)),
+            ("expiring-token", Some(3600.0)),
+            ("eternal-token", None),
+        ] {
+            sqlx::query!(
+                r#"
+                INSERT INTO attune_tenant_api_token (tenant_id, name, token, expires_at)
+                VALUES ($1, $2, $3, NOW() + make_interval(secs => $4))
+                "#,
+                tenant_id.0,
+                token,
+                Sha256::digest(token).as_slice().to_vec(),
+                expires_in,
             )
-        })?;
-        match tenant_id {
-            Some(tenant_id) => Ok(TenantID(tenant_id.id)),
-            None => Err((axum::http::StatusCode::UNAUTHORIZED, "Invalid API token\n")),
+            .execute(&pool)
+            .await
+            .unwrap();
         }
+        let list = async |token: &str| {
+            server
+                .http
+                .get("/api/v0/repositories")
+                .authorization_bearer(token)
+                .json(&serde_json::json!({}))
+                .await
+        };
+
+        let response = list("expired-token").await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+        assert_eq!(response.json::<ErrorResponse>().error, "TOKEN_EXPIRED");
+        list("expiring-token").await.assert_status_ok();
+        list("eternal-token").await.assert_status_ok();
+    }
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn verify_hashed_tokens(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool.clone(),
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        let (tenant_id, legacy_token) = server.create_test_tenant("verify_hashed_tokens").await;
+        let token = NewApiToken::generate();
+        sqlx::query!(
+            r#"
+            INSERT INTO attune_tenant_api_token (tenant_id, name, lookup_id, token_hash)
+            VALUES ($1, 'hashed', $2, $3)
+            "#,
+            tenant_id.0,
+            token.lookup_id,
+            token.token_hash,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            split_token(&token.token),
+            Some((
+                token.lookup_id.as_str(),
+                token.token.rsplit_once('_').unwrap().1
+            ))
+        );
+        let list = async |token: &str| {
+            server
+                .http
+                .get("/api/v0/repositories")
+                .authorization_bearer(token)
+                .json(&serde_json::json!({}))
+                .await
+        };
+
+        list(&token.token).await.assert_status_ok();
+        // The secret must match, not just the lookup ID.
+        let wrong_secret = format!("{TOKEN_PREFIX}{}_{}", token.lookup_id, "0".repeat(64));
+        list(&wrong_secret)
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        // Legacy SHA256 tokens still work.
+        list(&legacy_token).await.assert_status_ok();
     }
 }
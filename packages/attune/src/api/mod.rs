@@ -2,9 +2,11 @@ use percent_encoding::{AsciiSet, CONTROLS};
 
 pub mod auth;
 pub mod error;
+pub mod pagination;
 
-pub use auth::TenantID;
+pub use auth::{NewApiToken, TenantID, TokenScope, TokenScopes};
 pub use error::ErrorResponse;
+pub use pagination::PageParams;
 
 // This is taken from reqwest, see: https://docs.rs/url/2.5.4/src/url/parser.rs.html#38
 pub const PATH_SEGMENT_PERCENT_ENCODE_SET: &AsciiSet = &CONTROLS
@@ -0,0 +1,103 @@
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::api::ErrorResponse;
+
+/// The number of rows returned per page when no limit is given.
+pub const DEFAULT_PAGE_LIMIT: i64 = 100;
+
+/// The largest number of rows that can be requested per page.
+pub const MAX_PAGE_LIMIT: i64 = 1000;
+
+/// Query parameters for paginated list endpoints.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PageParams {
+    /// The maximum number of rows to return.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    /// The `next_cursor` of the previous page.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+}
+
+impl PageParams {
+    /// The validated page size.
+    pub fn limit(&self) -> Result<i64, ErrorResponse> {
+        match self.limit {
+            None => Ok(DEFAULT_PAGE_LIMIT),
+            Some(limit) if (1..=MAX_PAGE_LIMIT).contains(&limit) => Ok(limit),
+            Some(limit) => Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_LIMIT",
+                format!("limit must be between 1 and {MAX_PAGE_LIMIT}, got {limit}"),
+            )),
+        }
+    }
+
+    /// The key of the last row of the previous page, if there is one.
+    pub fn after<K: DeserializeOwned>(&self) -> Result<Option<K>, ErrorResponse> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|key| serde_json::from_slice(&key).ok())
+            .map(Some)
+            .ok_or_else(|| {
+                ErrorResponse::new(StatusCode::BAD_REQUEST, "INVALID_CURSOR", "invalid cursor")
+            })
+    }
+}
+
+/// Build the cursor for the page after `rows`.
+///
+/// Handlers fetch one row more than the limit, so that a full page followed by
+/// no more rows doesn't return a cursor to an empty page. The extra row is
+/// removed from `rows`.
+pub fn next_cursor<T, K: Serialize>(
+    rows: &mut Vec<T>,
+    limit: i64,
+    key: impl Fn(&T) -> K,
+) -> Option<String> {
+    let limit = usize::try_from(limit).expect("limit is positive");
+    if rows.len() <= limit {
+        return None;
+    }
+    rows.truncate(limit);
+    let last = rows.last().expect("limit is positive");
+    let key = serde_json::to_vec(&key(last)).expect("cursor key is serializable");
+    Some(URL_SAFE_NO_PAD.encode(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let mut rows = vec![(1, 10), (2, 20), (3, 30)];
+        let cursor = next_cursor(&mut rows, 2, |&(a, b)| (a, b));
+        assert_eq!(rows, vec![(1, 10), (2, 20)]);
+
+        let params = PageParams {
+            limit: None,
+            cursor,
+        };
+        assert_eq!(params.after::<(i64, i64)>().unwrap(), Some((2, 20)));
+
+        let mut rows = vec![1, 2];
+        assert_eq!(next_cursor(&mut rows, 2, |&id| id), None);
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        let params = PageParams {
+            limit: Some(0),
+            cursor: Some(String::from("not a cursor")),
+        };
+        assert_eq!(params.limit().unwrap_err().error, "INVALID_LIMIT");
+        assert_eq!(params.after::<i64>().unwrap_err().error, "INVALID_CURSOR");
+    }
+}
@@ -0,0 +1,97 @@
+use std::{fmt, io::Write as _, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// A compression format for Packages indexes.
+///
+/// The uncompressed Packages index is always published. These are the formats
+/// of the compressed variants that can be published alongside it.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(
+    type_name = "debian_repository_index_compression",
+    rename_all = "lowercase"
+)]
+pub enum Compression {
+    /// Stored as `gz` in the database, matching the file extension.
+    #[sqlx(rename = "gz")]
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+/// The compressed variants that distributions publish unless they're
+/// configured otherwise. Clients download the smallest variant they support,
+/// so that `apt-get update` doesn't download the uncompressed index.
+pub const DEFAULT_INDEX_COMPRESSION: &[Compression] = &[Compression::Gzip, Compression::Xz];
+
+impl Compression {
+    /// The file extension of an index compressed with this format, including
+    /// the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => ".gz",
+            Compression::Xz => ".xz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Compress the given contents.
+    ///
+    /// Compression must be deterministic, because the Release file (which
+    /// contains the checksums of the compressed indexes) is generated and later
+    /// replayed when it's signed.
+    pub fn compress(&self, contents: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Gzip => {
+                // The gzip header includes a modification time and file name
+                // by default; leave them unset so the output only depends on
+                // the contents.
+                let mut encoder = flate2::GzBuilder::new()
+                    .mtime(0)
+                    .write(Vec::new(), flate2::Compression::best());
+                encoder
+                    .write_all(contents)
+                    .expect("could not compress with gzip");
+                encoder.finish().expect("could not compress with gzip")
+            }
+            Compression::Xz => {
+                // Use the same preset as the `xz` command line tool.
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder
+                    .write_all(contents)
+                    .expect("could not compress with xz");
+                encoder.finish().expect("could not compress with xz")
+            }
+            Compression::Zstd => zstd::encode_all(contents, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .expect("could not compress with zstd"),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Xz => write!(f, "xz"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "xz" => Ok(Compression::Xz),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!(
+                "unsupported index compression {s:?} (supported: gzip, xz, zstd)"
+            )),
+        }
+    }
+}
@@ -1,7 +1,15 @@
+mod compression;
 mod package;
 mod packages_index;
 mod release;
+pub mod version;
 
-pub use package::{Package, PackageByMeta, PublishedPackage, PublishedPackageByMeta};
-pub use packages_index::{PackagesIndex, PackagesIndexMeta};
+pub use compression::{Compression, DEFAULT_INDEX_COMPRESSION};
+pub use package::{
+    Package, PackageByMeta, PublishedPackage, PublishedPackageByMeta, normalize_architecture,
+};
+pub use packages_index::{
+    CompressedPackagesIndex, PackagesIndex, PackagesIndexMeta, PackagesOrder,
+};
 pub use release::{ReleaseFile, ReleaseMeta};
+pub use version::DebianVersion;
@@ -1,7 +1,13 @@
+use std::cmp::Ordering;
+
 use derivative::Derivative;
 use sqlx::{FromRow, Postgres, Transaction, types::JsonValue};
 
-use crate::api::{ErrorResponse, TenantID};
+use crate::{
+    api::{ErrorResponse, TenantID},
+    apt::DebianVersion,
+    server::pkg::{canonical_key, copy_source},
+};
 
 #[derive(FromRow, Clone, Debug)]
 pub struct Package {
@@ -13,6 +19,9 @@ pub struct Package {
     pub size: i64,
 
     pub s3_bucket: String,
+    /// The key of the package's canonical object in `s3_bucket`, if it was
+    /// recorded. See [`Package::canonical_key`].
+    pub s3_key: Option<String>,
 
     pub md5sum: String,
     pub sha1sum: String,
@@ -37,6 +46,7 @@ impl Package {
                     paragraph,
                     size,
                     s3_bucket,
+                    s3_key,
                     md5sum,
                     sha1sum,
                     sha256sum
@@ -72,6 +82,7 @@ impl Package {
                     paragraph,
                     size,
                     s3_bucket,
+                    s3_key,
                     md5sum,
                     sha1sum,
                     sha256sum
@@ -88,6 +99,16 @@ impl Package {
         .map_err(Into::into)
     }
 
+    /// The key of the package's canonical object in `s3_bucket`.
+    pub fn canonical_key(&self) -> String {
+        canonical_key(self.s3_key.as_deref(), &self.sha256sum)
+    }
+
+    /// The S3 copy source of the package's canonical object.
+    pub fn copy_source(&self) -> String {
+        copy_source(&self.s3_bucket, &self.canonical_key())
+    }
+
     pub fn pool_filename_in_component(&self, component: &str) -> String {
         // FIXME: This isn't actually correct! Some documentation online
         // indicates that the package name in the pool filename should
@@ -137,6 +158,20 @@ impl PublishedPackage {
         }
     }
 
+    /// Order packages by name, then by Debian version, then by architecture.
+    ///
+    /// Versions that can't be parsed sort after those that can, in string
+    /// order.
+    pub fn cmp_by_version(&self, other: &Self) -> Ordering {
+        let (a, b) = (&self.package, &other.package);
+        let version =
+            |version: &str| DebianVersion::parse(version).map_err(|_| version.to_string());
+        a.name
+            .cmp(&b.name)
+            .then_with(|| version(&a.version).cmp(&version(&b.version)))
+            .then_with(|| a.architecture.cmp(&b.architecture))
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub async fn query_from_meta<'a>(
         tx: &mut Transaction<'a, Postgres>,
@@ -163,6 +198,7 @@ impl PublishedPackage {
                 debian_repository_package.paragraph,
                 debian_repository_package.size,
                 debian_repository_package.s3_bucket,
+                debian_repository_package.s3_key,
                 debian_repository_package.md5sum,
                 debian_repository_package.sha1sum,
                 debian_repository_package.sha256sum,
@@ -180,7 +216,7 @@ impl PublishedPackage {
                 AND debian_repository_component.name = $4
                 AND debian_repository_package.package = $5
                 AND debian_repository_package.version = $6
-                AND debian_repository_package.architecture = $7::debian_repository_architecture
+                AND debian_repository_package.architecture::TEXT = $7
         "#,
             tenant_id.0,
             repository,
@@ -188,7 +224,7 @@ impl PublishedPackage {
             component,
             package,
             version,
-            architecture as _
+            architecture
         )
         .fetch_optional(&mut **tx)
         .await
@@ -203,6 +239,7 @@ impl PublishedPackage {
                         paragraph: row.paragraph,
                         size: row.size,
                         s3_bucket: row.s3_bucket,
+                        s3_key: row.s3_key,
                         md5sum: row.md5sum,
                         sha1sum: row.sha1sum,
                         sha256sum: row.sha256sum,
@@ -213,6 +250,50 @@ impl PublishedPackage {
         })
     }
 
+    /// List the architectures in which a package version is published in the
+    /// component, sorted by name.
+    pub async fn query_architectures<'a>(
+        tx: &mut Transaction<'a, Postgres>,
+        tenant_id: &TenantID,
+        repository: &str,
+        release: &str,
+        component: &str,
+        package: &str,
+        version: &str,
+    ) -> Result<Vec<String>, ErrorResponse> {
+        sqlx::query_scalar!(r#"
+            SELECT DISTINCT debian_repository_package.architecture::TEXT AS "architecture!: String"
+            FROM
+                debian_repository
+                JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
+                JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+                JOIN debian_repository_component_package ON debian_repository_component_package.component_id = debian_repository_component.id
+                JOIN debian_repository_package ON debian_repository_package.id = debian_repository_component_package.package_id
+            WHERE
+                debian_repository.tenant_id = $1
+                AND debian_repository.name = $2
+                AND debian_repository_release.distribution = $3
+                AND debian_repository_component.name = $4
+                AND debian_repository_package.package = $5
+                AND debian_repository_package.version = $6
+            ORDER BY 1
+        "#,
+            tenant_id.0,
+            repository,
+            release,
+            component,
+            package,
+            version,
+        )
+        .fetch_all(&mut **tx)
+        .await
+        .map_err(Into::into)
+    }
+
+    /// Load the packages published in the Packages index of the given
+    /// component and architecture. This includes the component's
+    /// `Architecture: all` packages, which are published in every
+    /// architecture's index.
     pub async fn query_from_packages_index<'a>(
         tx: &mut Transaction<'a, Postgres>,
         tenant_id: &TenantID,
@@ -236,6 +317,7 @@ impl PublishedPackage {
                 debian_repository_package.paragraph,
                 debian_repository_package.size,
                 debian_repository_package.s3_bucket,
+                debian_repository_package.s3_key,
                 debian_repository_package.md5sum,
                 debian_repository_package.sha1sum,
                 debian_repository_package.sha256sum,
@@ -251,7 +333,10 @@ impl PublishedPackage {
                 AND debian_repository.name = $2
                 AND debian_repository_release.distribution = $3
                 AND debian_repository_component.name = $4
-                AND debian_repository_package.architecture = $5::debian_repository_architecture
+                AND (
+                    debian_repository_package.architecture = $5::debian_repository_architecture
+                    OR debian_repository_package.architecture = 'all'
+                )
         "#, tenant_id.0, repository, release, component, architecture as _)
         .map(|row| {
             PublishedPackage {
@@ -262,6 +347,7 @@ impl PublishedPackage {
                     paragraph: row.paragraph,
                     size: row.size,
                     s3_bucket: row.s3_bucket,
+                    s3_key: row.s3_key,
                     md5sum: row.md5sum,
                     sha1sum: row.sha1sum,
                     sha256sum: row.sha256sum,
@@ -284,3 +370,38 @@ pub struct PublishedPackageByMeta(
 fn published_package_eq_by_meta(a: &PublishedPackage, b: &PublishedPackage) -> bool {
     package_eq_by_meta(&a.package, &b.package)
 }
+
+/// Normalize an architecture name given by a user to the Debian architecture
+/// name, e.g. `x86_64` to `amd64`.
+///
+/// Debian architecture names are lowercase, and tools like `uname -m` and
+/// other package formats use different names for the same architectures.
+/// Names without a known alias are only lowercased.
+pub fn normalize_architecture(architecture: &str) -> String {
+    let architecture = architecture.trim().to_lowercase();
+    let normalized = match architecture.as_str() {
+        "x86_64" | "x86-64" | "x64" => "amd64",
+        "aarch64" => "arm64",
+        "i486" | "i586" | "i686" | "x86" => "i386",
+        "armv7l" | "armv7hl" => "armhf",
+        "ppc64le" => "ppc64el",
+        _ => return architecture,
+    };
+    normalized.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_architecture_aliases() {
+        assert_eq!(normalize_architecture("amd64"), "amd64");
+        assert_eq!(normalize_architecture("x86_64"), "amd64");
+        assert_eq!(normalize_architecture("AMD64"), "amd64");
+        assert_eq!(normalize_architecture("aarch64"), "arm64");
+        assert_eq!(normalize_architecture("i686"), "i386");
+        assert_eq!(normalize_architecture("ppc64le"), "ppc64el");
+        assert_eq!(normalize_architecture("riscv64"), "riscv64");
+    }
+}
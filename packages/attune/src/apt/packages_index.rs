@@ -1,27 +1,42 @@
 use itertools::Itertools;
 use md5::Md5;
 use sha1::Sha1;
-use sha2::{Digest as _, Sha256};
+use sha2::{Digest as _, Sha256, Sha512};
 use sqlx::{FromRow, Postgres, Transaction};
 
 use crate::{
     api::{ErrorResponse, TenantID},
-    apt::{Package, PublishedPackage},
+    apt::{Compression, Package, PublishedPackage},
 };
 
 #[derive(Clone, Debug, FromRow)]
 pub struct PackagesIndexMeta {
     pub component: String,
     pub architecture: String,
+    /// The compression of the index, or `None` for the uncompressed index.
+    pub compression: Option<Compression>,
 
     pub size: i64,
 
     pub md5sum: String,
     pub sha1sum: String,
     pub sha256sum: String,
+    /// `None` for indexes that were last published before SHA512 sums were
+    /// recorded.
+    pub sha512sum: Option<String>,
 }
 
 impl PackagesIndexMeta {
+    /// The path of the index file, relative to the distribution directory.
+    pub fn path(&self) -> String {
+        format!(
+            "{}/binary-{}/Packages{}",
+            self.component,
+            self.architecture,
+            self.compression.map(|c| c.extension()).unwrap_or_default()
+        )
+    }
+
     pub async fn query_from_release<'a>(
         tx: &mut Transaction<'a, Postgres>,
         tenant_id: &TenantID,
@@ -32,10 +47,12 @@ impl PackagesIndexMeta {
             SELECT
                 debian_repository_component.name AS component,
                 debian_repository_index_packages.architecture::TEXT AS "architecture!: String",
+                debian_repository_index_packages.compression AS "compression: Compression",
                 debian_repository_index_packages.size,
                 debian_repository_index_packages.md5sum,
                 debian_repository_index_packages.sha1sum,
-                debian_repository_index_packages.sha256sum
+                debian_repository_index_packages.sha256sum,
+                debian_repository_index_packages.sha512sum
             FROM
                 debian_repository
                 JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
@@ -56,12 +73,25 @@ impl PackagesIndexMeta {
     }
 }
 
+/// The order of the stanzas in a Packages index.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PackagesOrder {
+    /// By name, then by version as a string, then by architecture.
+    #[default]
+    Name,
+    /// By name, then by Debian version, then by architecture, so that each
+    /// package's versions are listed from oldest to newest.
+    Version,
+}
+
 #[derive(Clone, Debug, FromRow)]
 pub struct PackagesIndex {
     #[sqlx(flatten)]
     pub meta: PackagesIndexMeta,
     pub contents: String,
     packages: Vec<PublishedPackage>,
+    #[sqlx(skip)]
+    order: PackagesOrder,
 }
 
 impl PackagesIndex {
@@ -70,36 +100,71 @@ impl PackagesIndex {
         architecture: &str,
         packages: Vec<PublishedPackage>,
     ) -> Self {
-        let rendered = Self::render(packages.iter());
+        let rendered = Self::render(packages.iter(), PackagesOrder::default());
         Self {
             meta: PackagesIndexMeta {
                 component: component.to_string(),
                 architecture: architecture.to_string(),
+                compression: None,
                 size: rendered.len() as i64,
                 md5sum: hex::encode(Md5::digest(&rendered)),
                 sha1sum: hex::encode(Sha1::digest(&rendered)),
                 sha256sum: hex::encode(Sha256::digest(&rendered)),
+                sha512sum: Some(hex::encode(Sha512::digest(&rendered))),
             },
             packages,
             contents: rendered,
+            order: PackagesOrder::default(),
         }
     }
 
-    fn render<'a>(packages: impl Iterator<Item = &'a PublishedPackage>) -> String {
+    /// Re-render this index with its stanzas in the given order.
+    pub fn sorted_by(mut self, order: PackagesOrder) -> Self {
+        self.order = order;
+        self.rerender();
+        self
+    }
+
+    fn render<'a>(
+        packages: impl Iterator<Item = &'a PublishedPackage>,
+        order: PackagesOrder,
+    ) -> String {
         let mut index = packages
-            .sorted_by_key(|published| {
-                let pkg = &published.package;
-                (&pkg.name, &pkg.version, &pkg.architecture)
+            .sorted_by(|a, b| match order {
+                PackagesOrder::Name => {
+                    let (a, b) = (&a.package, &b.package);
+                    (&a.name, &a.version, &a.architecture).cmp(&(
+                        &b.name,
+                        &b.version,
+                        &b.architecture,
+                    ))
+                }
+                PackagesOrder::Version => a.cmp_by_version(b),
             })
             .map(|published| {
                 let pkg = &published.package;
-                // TODO(#97): Sort fields by convention order.
-                let fields = pkg
+                let paragraph = pkg
                     .paragraph
                     .as_object()
                     .unwrap()
                     .into_iter()
-                    .map(|(k, v)| format!("{}: {}", k, v.as_str().unwrap()))
+                    .map(|(k, v)| (k.as_str(), fold_field_value(v.as_str().unwrap())))
+                    .collect::<Vec<_>>();
+                // Frontends use Description-md5 to look up the package's
+                // description in Translation files. Packages that were built
+                // with one already have it set in their control file.
+                let description_md5 = paragraph
+                    .iter()
+                    .find(|(k, _)| *k == "Description")
+                    .filter(|_| !paragraph.iter().any(|(k, _)| *k == "Description-md5"))
+                    .map(|(_, description)| {
+                        format!("Description-md5: {}", description_md5(description))
+                    });
+                // TODO(#97): Sort fields by convention order.
+                let fields = paragraph
+                    .iter()
+                    .map(|(k, v)| format!("{k}: {v}"))
+                    .chain(description_md5)
                     .chain(vec![
                         format!("Filename: {}", published.filename),
                         format!("Size: {}", pkg.size.to_string()),
@@ -119,6 +184,30 @@ impl PackagesIndex {
         index
     }
 
+    /// The packages in this index.
+    pub fn packages(&self) -> &[PublishedPackage] {
+        &self.packages
+    }
+
+    /// Compress this index, computing the size and checksums of the compressed
+    /// contents.
+    pub fn compressed(&self, compression: Compression) -> CompressedPackagesIndex {
+        let contents = compression.compress(self.contents.as_bytes());
+        CompressedPackagesIndex {
+            meta: PackagesIndexMeta {
+                component: self.meta.component.clone(),
+                architecture: self.meta.architecture.clone(),
+                compression: Some(compression),
+                size: contents.len() as i64,
+                md5sum: hex::encode(Md5::digest(&contents)),
+                sha1sum: hex::encode(Sha1::digest(&contents)),
+                sha256sum: hex::encode(Sha256::digest(&contents)),
+                sha512sum: Some(hex::encode(Sha512::digest(&contents))),
+            },
+            contents,
+        }
+    }
+
     /// Add a package to this Packages index. This will re-render the index,
     /// updating the size, checksums, and contents.
     ///
@@ -154,15 +243,58 @@ impl PackagesIndex {
 
     /// Re-render the index, updating the size, checksums, and contents.
     fn rerender(&mut self) {
-        let rendered = Self::render(self.packages.iter());
+        let rendered = Self::render(self.packages.iter(), self.order);
         self.meta.size = rendered.len() as i64;
         self.meta.md5sum = hex::encode(Md5::digest(&rendered));
         self.meta.sha1sum = hex::encode(Sha1::digest(&rendered));
         self.meta.sha256sum = hex::encode(Sha256::digest(&rendered));
+        self.meta.sha512sum = Some(hex::encode(Sha512::digest(&rendered)));
         self.contents = rendered;
     }
 }
 
+/// A compressed variant of a Packages index.
+#[derive(Clone, Debug)]
+pub struct CompressedPackagesIndex {
+    pub meta: PackagesIndexMeta,
+    pub contents: Vec<u8>,
+}
+
+/// Fold a control field value so that it can be written as a single stanza
+/// field. Every line after the first is a continuation line, which must start
+/// with a space; empty continuation lines are written as ` .` so that they
+/// don't terminate the stanza.
+///
+/// Values parsed from a control file are already folded, and are returned
+/// unchanged.
+fn fold_field_value(value: &str) -> String {
+    let mut lines = value.trim_end().lines();
+    let mut folded = lines.next().unwrap_or_default().trim_end().to_string();
+    for line in lines {
+        let line = line.trim_end();
+        folded.push('\n');
+        if line.trim_start().is_empty() {
+            folded.push_str(" .");
+        } else {
+            if !line.starts_with([' ', '\t']) {
+                folded.push(' ');
+            }
+            folded.push_str(line);
+        }
+    }
+    folded
+}
+
+/// Compute the `Description-md5` of a package description, in the same way as
+/// apt: the MD5 of the full description as it appears in the control file
+/// (the synopsis line followed by the extended description lines with their
+/// leading space), terminated by a single newline.
+fn description_md5(description: &str) -> String {
+    let mut contents = description.trim_end().to_string();
+    contents.push('\n');
+    hex::encode(Md5::digest(contents.as_bytes()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,7 +303,10 @@ mod tests {
     /// produce the empty string.
     #[test]
     fn empty_when_no_packages() {
-        assert_eq!(PackagesIndex::render(vec![].into_iter()), "");
+        assert_eq!(
+            PackagesIndex::render(vec![].into_iter(), PackagesOrder::Name),
+            ""
+        );
     }
 
     /// Rendering the same set of packages multiple times should produce the
@@ -188,6 +323,7 @@ mod tests {
                         paragraph: serde_json::Value::Object(serde_json::Map::new()),
                         size: 0,
                         s3_bucket: format!("fake_bucket_{i}"),
+                        s3_key: None,
                         md5sum: format!("fake_md5sum_{i}"),
                         sha1sum: format!("fake_sha1sum_{i}"),
                         sha256sum: format!("fake_sha256sum_{i}"),
@@ -201,6 +337,60 @@ mod tests {
         assert_eq!(first, second);
     }
 
+    /// Sorting by version uses Debian version ordering within each package.
+    #[test]
+    fn sorted_by_version() {
+        let packages = ["1.10", "1.0~rc1", "1:0.1", "1.9", "1.0"]
+            .into_iter()
+            .map(|version| {
+                PublishedPackage::from_package(
+                    Package {
+                        name: String::from("foo"),
+                        version: String::from(version),
+                        architecture: String::from("amd64"),
+                        paragraph: serde_json::json!({"Package": "foo", "Version": version}),
+                        size: 0,
+                        s3_bucket: String::from("fake_bucket"),
+                        s3_key: None,
+                        md5sum: String::from("fake_md5sum"),
+                        sha1sum: String::from("fake_sha1sum"),
+                        sha256sum: String::from("fake_sha256sum"),
+                    },
+                    "main",
+                )
+            })
+            .collect::<Vec<_>>();
+        let versions = |index: PackagesIndex| {
+            index
+                .contents
+                .lines()
+                .filter_map(|line| line.strip_prefix("Version: "))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        let index = PackagesIndex::from_packages("main", "amd64", packages);
+        assert_eq!(
+            versions(index.clone()),
+            ["1.0", "1.0~rc1", "1.10", "1.9", "1:0.1"]
+        );
+        let mut index = index.sorted_by(PackagesOrder::Version);
+        assert_eq!(
+            versions(index.clone()),
+            ["1.0~rc1", "1.0", "1.9", "1.10", "1:0.1"]
+        );
+
+        // The order is kept when the index changes.
+        let removed = index
+            .packages()
+            .iter()
+            .find(|published| published.package.version == "1.0~rc1")
+            .cloned()
+            .unwrap();
+        index.remove_package(removed);
+        assert_eq!(versions(index), ["1.0", "1.9", "1.10", "1:0.1"]);
+    }
+
     /// Adding a package that is already in the index is a no-op.
     #[test]
     fn idempotent_when_add_existing() {
@@ -211,6 +401,7 @@ mod tests {
             paragraph: serde_json::Value::Object(serde_json::Map::new()),
             size: 0,
             s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
             md5sum: String::from("fake_md5sum"),
             sha1sum: String::from("fake_sha1sum"),
             sha256sum: String::from("fake_sha256sum"),
@@ -223,6 +414,266 @@ mod tests {
         assert_eq!(before, after);
     }
 
+    /// Compressed indexes decompress back to the uncompressed index, and their
+    /// checksums are of the compressed contents.
+    #[test]
+    fn zstd_roundtrip() {
+        let package = Package {
+            name: String::from("foo"),
+            version: String::from("1.0.0"),
+            architecture: String::from("amd64"),
+            paragraph: serde_json::json!({"Package": "foo", "Version": "1.0.0"}),
+            size: 0,
+            s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
+            md5sum: String::from("fake_md5sum"),
+            sha1sum: String::from("fake_sha1sum"),
+            sha256sum: String::from("fake_sha256sum"),
+        };
+        let published = PublishedPackage::from_package(package, "main");
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+
+        let compressed = index.compressed(Compression::Zstd);
+        let decompressed = zstd::decode_all(compressed.contents.as_slice()).unwrap();
+        assert_eq!(decompressed, index.contents.as_bytes());
+        assert_eq!(compressed.meta.compression, Some(Compression::Zstd));
+        assert_eq!(compressed.meta.size, compressed.contents.len() as i64);
+        assert_eq!(
+            compressed.meta.sha256sum,
+            hex::encode(Sha256::digest(&compressed.contents))
+        );
+        assert_eq!(
+            compressed.meta.md5sum,
+            hex::encode(Md5::digest(&compressed.contents))
+        );
+        assert_eq!(compressed.meta.path(), "main/binary-amd64/Packages.zst");
+    }
+
+    /// Gzip output doesn't depend on when it was compressed, so that replaying
+    /// a change produces the same Release file.
+    #[test]
+    fn gzip_roundtrip() {
+        let package = Package {
+            name: String::from("foo"),
+            version: String::from("1.0.0"),
+            architecture: String::from("amd64"),
+            paragraph: serde_json::json!({"Package": "foo", "Version": "1.0.0"}),
+            size: 0,
+            s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
+            md5sum: String::from("fake_md5sum"),
+            sha1sum: String::from("fake_sha1sum"),
+            sha256sum: String::from("fake_sha256sum"),
+        };
+        let published = PublishedPackage::from_package(package, "main");
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+
+        let compressed = index.compressed(Compression::Gzip);
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(
+            &mut flate2::read::GzDecoder::new(compressed.contents.as_slice()),
+            &mut decompressed,
+        )
+        .unwrap();
+        assert_eq!(decompressed, index.contents);
+        assert_eq!(compressed.meta.compression, Some(Compression::Gzip));
+        assert_eq!(
+            compressed.contents,
+            index.compressed(Compression::Gzip).contents
+        );
+        assert_eq!(compressed.meta.path(), "main/binary-amd64/Packages.gz");
+    }
+
+    #[test]
+    fn xz_roundtrip() {
+        let package = Package {
+            name: String::from("foo"),
+            version: String::from("1.0.0"),
+            architecture: String::from("amd64"),
+            paragraph: serde_json::json!({"Package": "foo", "Version": "1.0.0"}),
+            size: 0,
+            s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
+            md5sum: String::from("fake_md5sum"),
+            sha1sum: String::from("fake_sha1sum"),
+            sha256sum: String::from("fake_sha256sum"),
+        };
+        let published = PublishedPackage::from_package(package, "main");
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+
+        let compressed = index.compressed(Compression::Xz);
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(
+            &mut xz2::read::XzDecoder::new(compressed.contents.as_slice()),
+            &mut decompressed,
+        )
+        .unwrap();
+        assert_eq!(decompressed, index.contents);
+        assert_eq!(compressed.meta.compression, Some(Compression::Xz));
+        assert_eq!(
+            compressed.meta.sha1sum,
+            hex::encode(Sha1::digest(&compressed.contents))
+        );
+        assert_eq!(compressed.meta.path(), "main/binary-amd64/Packages.xz");
+    }
+
+    /// Multi-line field values are folded into valid continuation lines, and
+    /// already-folded values are left alone.
+    #[test]
+    fn fold_multiline_fields() {
+        const FOLDED: &str = "A friendly greeting program\n The GNU hello program produces a familiar, friendly greeting.\n .\n It allows non-programmers to use a classic computer science tool.\n .\n Seriously, that is all it does.";
+        assert_eq!(fold_field_value("single line"), "single line");
+        assert_eq!(fold_field_value(FOLDED), FOLDED);
+        assert_eq!(
+            fold_field_value(
+                "A friendly greeting program\nThe GNU hello program produces a familiar, friendly greeting.\n\nIt allows non-programmers to use a classic computer science tool.\n   \nSeriously, that is all it does.\n"
+            ),
+            FOLDED
+        );
+
+        let package = Package {
+            name: String::from("hello"),
+            version: String::from("2.10-3"),
+            architecture: String::from("amd64"),
+            paragraph: serde_json::json!({
+                "Package": "hello",
+                "Description": "A friendly greeting program\nThe GNU hello program produces a familiar, friendly greeting.\n\nIt allows non-programmers to use a classic computer science tool.\n\nSeriously, that is all it does.",
+            }),
+            size: 0,
+            s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
+            md5sum: String::from("fake_md5sum"),
+            sha1sum: String::from("fake_sha1sum"),
+            sha256sum: String::from("fake_sha256sum"),
+        };
+        let published = PublishedPackage::from_package(package, "main");
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+        assert!(index.contents.contains(&format!("Description: {FOLDED}\n")));
+        // The stanza parses back into a single paragraph with the folded
+        // description.
+        let control = debian_packaging::control::ControlFile::parse_str(&index.contents).unwrap();
+        let paragraphs = control.paragraphs().collect::<Vec<_>>();
+        assert_eq!(paragraphs.len(), 1);
+        assert_eq!(paragraphs[0].field_str("Description"), Some(FOLDED));
+    }
+
+    /// Description-md5 matches what apt computes for the description, and is
+    /// emitted only if the control file doesn't already set it.
+    #[test]
+    fn description_md5() {
+        const DESCRIPTION: &str = "A friendly greeting program\n The GNU hello program produces a familiar, friendly greeting.\n .\n Seriously, that is all it does.";
+        const EXPECTED: &str = "29889a114fe048e68b45150acb3a6c23";
+        assert_eq!(super::description_md5(DESCRIPTION), EXPECTED);
+        // Trailing whitespace left over from parsing doesn't change the hash.
+        assert_eq!(
+            super::description_md5(&format!("{DESCRIPTION}\n")),
+            EXPECTED
+        );
+
+        let package = |paragraph| Package {
+            name: String::from("hello"),
+            version: String::from("2.10-3"),
+            architecture: String::from("amd64"),
+            paragraph,
+            size: 0,
+            s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
+            md5sum: String::from("fake_md5sum"),
+            sha1sum: String::from("fake_sha1sum"),
+            sha256sum: String::from("fake_sha256sum"),
+        };
+
+        let published = PublishedPackage::from_package(
+            package(serde_json::json!({"Package": "hello", "Description": DESCRIPTION})),
+            "main",
+        );
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+        assert!(
+            index
+                .contents
+                .contains(&format!("\nDescription-md5: {EXPECTED}\n"))
+        );
+
+        let published = PublishedPackage::from_package(
+            package(serde_json::json!({
+                "Package": "hello",
+                "Description": DESCRIPTION,
+                "Description-md5": "0123456789abcdef0123456789abcdef",
+            })),
+            "main",
+        );
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+        assert_eq!(index.contents.matches("Description-md5:").count(), 1);
+        assert!(
+            index
+                .contents
+                .contains("Description-md5: 0123456789abcdef0123456789abcdef\n")
+        );
+    }
+
+    /// Fields that aren't stored in their own columns, like `Multi-Arch` and
+    /// `Pre-Depends`, are published from the package's control paragraph.
+    #[test]
+    fn publish_unindexed_fields() {
+        const CONTROL: &str = indoc::indoc! {"
+            Package: libfoo1
+            Version: 1.2.3-1
+            Architecture: amd64
+            Maintainer: Attune <attune@example.com>
+            Multi-Arch: same
+            Essential: yes
+            Build-Essential: yes
+            Pre-Depends: libc6 (>= 2.34)
+            Suggests: foo-doc
+            Enhances: foo
+            Built-Using: rustc (= 1.89.0)
+            Description: A test library
+        "};
+        // Build the paragraph the same way the upload handler does.
+        let control = debian_packaging::control::ControlParagraph::from(
+            debian_packaging::debian_source_control::DebianSourceControlFile::from_reader(
+                CONTROL.as_bytes(),
+            )
+            .unwrap(),
+        );
+        let paragraph = serde_json::Value::Object(
+            control
+                .as_str_hash_map()
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), serde_json::Value::String(v.to_string())))
+                .collect(),
+        );
+        let package = Package {
+            name: String::from("libfoo1"),
+            version: String::from("1.2.3-1"),
+            architecture: String::from("amd64"),
+            paragraph,
+            size: 0,
+            s3_bucket: String::from("fake_bucket"),
+            s3_key: None,
+            md5sum: String::from("fake_md5sum"),
+            sha1sum: String::from("fake_sha1sum"),
+            sha256sum: String::from("fake_sha256sum"),
+        };
+        let published = PublishedPackage::from_package(package, "main");
+        let index = PackagesIndex::from_packages("main", "amd64", vec![published]);
+
+        let index = debian_packaging::control::ControlFile::parse_str(&index.contents).unwrap();
+        let paragraphs = index.paragraphs().collect::<Vec<_>>();
+        assert_eq!(paragraphs.len(), 1);
+        for (field, value) in [
+            ("Multi-Arch", "same"),
+            ("Essential", "yes"),
+            ("Build-Essential", "yes"),
+            ("Pre-Depends", "libc6 (>= 2.34)"),
+            ("Suggests", "foo-doc"),
+            ("Enhances", "foo"),
+            ("Built-Using", "rustc (= 1.89.0)"),
+        ] {
+            assert_eq!(paragraphs[0].field_str(field), Some(value), "{field}");
+        }
+    }
+
     // TODO: `debian_packaging::repository::ReleaseReader` provides a parser for
     // Packages indexes via `ControlParagraphReader` and
     // `BinaryPackageControlFile::from`. We can use that to create a
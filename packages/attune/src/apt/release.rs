@@ -1,12 +1,13 @@
 use std::{collections::BTreeSet, fmt::Write as _, io::Write as _};
 
+use itertools::Itertools as _;
 use sqlx::{FromRow, Postgres, Transaction};
 use tabwriter::{Alignment, TabWriter};
-use time::{OffsetDateTime, format_description::well_known::Rfc2822};
+use time::{Duration, OffsetDateTime, format_description::well_known::Rfc2822};
 
 use crate::{
     api::{ErrorResponse, TenantID},
-    apt::PackagesIndexMeta,
+    apt::{Compression, PackagesIndexMeta},
 };
 
 #[derive(FromRow, Debug)]
@@ -17,6 +18,32 @@ pub struct ReleaseMeta {
     pub version: Option<String>,
     pub suite: String,
     pub codename: String,
+
+    /// Compressed variants of Packages indexes to publish alongside each
+    /// uncompressed index.
+    pub index_compression: Vec<Compression>,
+
+    /// Architectures that are always listed in the Release file, even if they
+    /// have no packages.
+    pub declared_architectures: Vec<String>,
+    /// Components that are always listed in the Release file, even if they
+    /// have no packages.
+    pub declared_components: Vec<String>,
+
+    /// Whether to omit the legacy `MD5Sum` and `SHA1` sections, so that the
+    /// Release file only lists SHA256 and SHA512 checksums.
+    pub sha256_only: bool,
+
+    /// If set, the Release file's `Valid-Until` field is this many seconds
+    /// after its `Date`.
+    pub valid_for_seconds: Option<i64>,
+
+    /// Whether to set `NotAutomatic: yes`, so that APT doesn't install the
+    /// distribution's packages unless they're pinned or requested explicitly.
+    pub not_automatic: bool,
+    /// Whether to set `ButAutomaticUpgrades: yes`, so that packages installed
+    /// from a `NotAutomatic` distribution are still upgraded from it.
+    pub but_automatic_upgrades: bool,
 }
 
 impl ReleaseMeta {
@@ -33,7 +60,14 @@ impl ReleaseMeta {
                 debian_repository_release.version,
                 debian_repository_release.suite,
                 debian_repository_release.codename,
-                debian_repository_release.description
+                debian_repository_release.description,
+                debian_repository_release.index_compression AS "index_compression!: Vec<Compression>",
+                debian_repository_release.declared_architectures::TEXT[] AS "declared_architectures!",
+                debian_repository_release.declared_components AS "declared_components!",
+                debian_repository_release.sha256_only,
+                debian_repository_release.valid_for_seconds,
+                debian_repository_release.not_automatic,
+                debian_repository_release.but_automatic_upgrades
             FROM
                 debian_repository
                 JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
@@ -70,16 +104,36 @@ impl ReleaseFile {
         // is RFC 5322, but these formats are compatible. 5322 is a later
         // revision of 2822 that retains backwards compatibility.
         let date = release_ts.format(&Rfc2822).unwrap();
+        let valid_until = release.valid_for_seconds.map(|seconds| {
+            (release_ts + Duration::seconds(seconds))
+                .format(&Rfc2822)
+                .unwrap()
+        });
 
         // Prepare "Architectures" and "Components" fields. We use BTreeSets
         // instead of HashSets to get deterministic iterator order, since index
         // generation needs to be deterministically replayed.
-        let mut arch_set = BTreeSet::new();
-        let mut comp_set = BTreeSet::new();
+        //
+        // These list both the declared architectures and components, and
+        // those that currently have packages, so that declared ones don't
+        // disappear from the Release file when their last package is removed.
+        let mut arch_set = release
+            .declared_architectures
+            .iter()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>();
+        let mut comp_set = release
+            .declared_components
+            .iter()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>();
         for p in packages_indexes {
             arch_set.insert(p.architecture.as_str());
             comp_set.insert(p.component.as_str());
         }
+        // `Architecture: all` packages are published in the index of every
+        // architecture, so `all` isn't an architecture of its own.
+        arch_set.remove("all");
         let archs = arch_set
             .into_iter()
             .fold(String::new(), |acc_archs, arch| acc_archs + " " + arch);
@@ -89,6 +143,13 @@ impl ReleaseFile {
             .fold(String::new(), |acc_comps, comp| acc_comps + " " + comp);
         let comps = comps.strip_prefix(" ").unwrap_or("");
 
+        // Index fingerprints are also written in a deterministic order,
+        // regardless of the order in which the indexes were loaded.
+        let packages_indexes = packages_indexes
+            .iter()
+            .sorted_by_key(|index| (&index.component, &index.architecture, index.compression))
+            .collect::<Vec<_>>();
+
         // Write release fields.
         let mut release_file = vec![
             ("Origin", release.origin.clone()),
@@ -97,9 +158,20 @@ impl ReleaseFile {
             ("Suite", Some(release.suite.clone())),
             ("Codename", Some(release.codename.clone())),
             ("Date", Some(date)),
+            ("Valid-Until", valid_until),
+            (
+                "NotAutomatic",
+                release.not_automatic.then(|| String::from("yes")),
+            ),
+            (
+                "ButAutomaticUpgrades",
+                release.but_automatic_upgrades.then(|| String::from("yes")),
+            ),
             ("Architectures", Some(archs.to_string())),
             ("Components", Some(comps.to_string())),
             ("Description", release.description.clone()),
+            // Every index always has at least a SHA256 by-hash copy (see
+            // `query_repository_state`), so clients can always fetch by hash.
             ("Acquire-By-Hash", Some(String::from("yes"))),
         ]
         .into_iter()
@@ -110,43 +182,328 @@ impl ReleaseFile {
             acc
         });
 
-        // Write index fingerprints.
-        release_file += "MD5Sum:\n";
-        let mut md5writer = TabWriter::new(vec![])
-            .alignment(Alignment::Right)
-            .padding(1);
-        for index in packages_indexes {
-            // TODO(#94): Handle compressed indexes.
-            writeln!(
-                &mut md5writer,
-                " {}\t{}\t{}/binary-{}/Packages",
-                index.md5sum, index.size, index.component, index.architecture
-            )
-            .unwrap();
-        }
-        md5writer.flush().unwrap();
-        release_file = release_file + &String::from_utf8(md5writer.into_inner().unwrap()).unwrap();
-
-        release_file += "SHA256:\n";
-        let mut sha256writer = TabWriter::new(vec![])
-            .alignment(Alignment::Right)
-            .padding(1);
-        for index in packages_indexes {
-            // TODO(#94): Handle compressed indexes.
-            writeln!(
-                &mut sha256writer,
-                " {}\t{}\t{}/binary-{}/Packages",
-                index.sha256sum, index.size, index.component, index.architecture
-            )
-            .unwrap();
+        // Write index fingerprints. MD5Sum and SHA1 are legacy sections that
+        // only older clients read. Indexes without a SHA512 sum (because they
+        // haven't been published since SHA512 sums were recorded) are left out
+        // of the SHA512 section, and clients fall back to SHA256 for them.
+        for name in ["MD5Sum", "SHA1", "SHA256", "SHA512"] {
+            if release.sha256_only && matches!(name, "MD5Sum" | "SHA1") {
+                continue;
+            }
+            let checksums = packages_indexes
+                .iter()
+                .filter_map(|index| {
+                    let checksum = match name {
+                        "MD5Sum" => Some(&index.md5sum),
+                        "SHA1" => Some(&index.sha1sum),
+                        "SHA256" => Some(&index.sha256sum),
+                        _ => index.sha512sum.as_ref(),
+                    };
+                    checksum.map(|checksum| (checksum, index))
+                })
+                .collect::<Vec<_>>();
+            if checksums.is_empty() && name == "SHA512" {
+                continue;
+            }
+            writeln!(release_file, "{name}:").unwrap();
+            let mut writer = TabWriter::new(vec![])
+                .alignment(Alignment::Right)
+                .padding(1);
+            for (checksum, index) in checksums {
+                writeln!(
+                    &mut writer,
+                    " {}\t{}\t{}",
+                    checksum,
+                    index.size,
+                    index.path()
+                )
+                .unwrap();
+            }
+            writer.flush().unwrap();
+            release_file += &String::from_utf8(writer.into_inner().unwrap()).unwrap();
         }
-        sha256writer.flush().unwrap();
 
-        release_file =
-            release_file + &String::from_utf8(sha256writer.into_inner().unwrap()).unwrap();
         Self {
             meta: release,
             contents: release_file,
         }
     }
+
+    /// Parse the `Date` field of a Release file's contents.
+    pub fn parse_date(contents: &str) -> Option<OffsetDateTime> {
+        contents
+            .lines()
+            .find_map(|line| line.strip_prefix("Date: "))
+            .and_then(|date| OffsetDateTime::parse(date.trim(), &Rfc2822).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(declared_architectures: &[&str], declared_components: &[&str]) -> ReleaseMeta {
+        ReleaseMeta {
+            description: None,
+            origin: None,
+            label: None,
+            version: None,
+            suite: String::from("stable"),
+            codename: String::from("stable"),
+            index_compression: Vec::new(),
+            declared_architectures: declared_architectures
+                .iter()
+                .map(|arch| arch.to_string())
+                .collect(),
+            declared_components: declared_components
+                .iter()
+                .map(|comp| comp.to_string())
+                .collect(),
+            sha256_only: false,
+            valid_for_seconds: None,
+            not_automatic: false,
+            but_automatic_upgrades: false,
+        }
+    }
+
+    fn index(component: &str, architecture: &str) -> PackagesIndexMeta {
+        PackagesIndexMeta {
+            component: String::from(component),
+            architecture: String::from(architecture),
+            compression: None,
+            size: 0,
+            md5sum: String::from("md5sum"),
+            sha1sum: String::from("sha1sum"),
+            sha256sum: String::from("sha256sum"),
+            sha512sum: Some(String::from("sha512sum")),
+        }
+    }
+
+    /// Declared architectures and components are listed even if they have no
+    /// Packages index, but only indexes that exist are fingerprinted.
+    #[test]
+    fn declared_but_empty() {
+        let release_file = ReleaseFile::from_indexes(
+            release(&["amd64", "arm64"], &["contrib", "main"]),
+            OffsetDateTime::UNIX_EPOCH,
+            &vec![index("main", "amd64")],
+        );
+        assert!(
+            release_file
+                .contents
+                .contains("\nArchitectures: amd64 arm64\n")
+        );
+        assert!(
+            release_file
+                .contents
+                .contains("\nComponents: contrib main\n")
+        );
+        assert!(release_file.contents.contains("main/binary-amd64/Packages"));
+        assert!(!release_file.contents.contains("binary-arm64"));
+        assert!(!release_file.contents.contains("contrib/"));
+
+        // A declared distribution with no packages at all still lists them.
+        let release_file = ReleaseFile::from_indexes(
+            release(&["amd64"], &["main"]),
+            OffsetDateTime::UNIX_EPOCH,
+            &vec![],
+        );
+        assert!(release_file.contents.contains("\nArchitectures: amd64\n"));
+        assert!(release_file.contents.contains("\nComponents: main\n"));
+    }
+
+    /// Architectures and components that have packages are listed even if
+    /// they aren't declared.
+    #[test]
+    fn present_but_undeclared() {
+        let release_file = ReleaseFile::from_indexes(
+            release(&["amd64"], &["main"]),
+            OffsetDateTime::UNIX_EPOCH,
+            &vec![index("main", "amd64"), index("contrib", "riscv64")],
+        );
+        assert!(
+            release_file
+                .contents
+                .contains("\nArchitectures: amd64 riscv64\n")
+        );
+        assert!(
+            release_file
+                .contents
+                .contains("\nComponents: contrib main\n")
+        );
+
+        // Without any declarations, only present ones are listed.
+        let release_file = ReleaseFile::from_indexes(
+            release(&[], &[]),
+            OffsetDateTime::UNIX_EPOCH,
+            &vec![index("main", "arm64")],
+        );
+        assert!(release_file.contents.contains("\nArchitectures: arm64\n"));
+        assert!(release_file.contents.contains("\nComponents: main\n"));
+    }
+
+    #[test]
+    fn sha256_only() {
+        let indexes = vec![index("main", "amd64")];
+        let release_file =
+            ReleaseFile::from_indexes(release(&[], &[]), OffsetDateTime::UNIX_EPOCH, &indexes);
+        assert!(release_file.contents.contains("\nMD5Sum:\n md5sum"));
+        assert!(release_file.contents.contains("\nSHA1:\n sha1sum"));
+        assert!(release_file.contents.contains("\nSHA256:\n sha256sum"));
+
+        let release_file = ReleaseFile::from_indexes(
+            ReleaseMeta {
+                sha256_only: true,
+                ..release(&[], &[])
+            },
+            OffsetDateTime::UNIX_EPOCH,
+            &indexes,
+        );
+        assert!(!release_file.contents.contains("MD5Sum"));
+        assert!(!release_file.contents.contains("md5sum"));
+        assert!(!release_file.contents.contains("SHA1"));
+        assert!(release_file.contents.contains("\nSHA256:\n sha256sum"));
+        assert!(release_file.contents.contains("\nSHA512:\n sha512sum"));
+    }
+
+    #[test]
+    fn valid_until() {
+        let release_ts = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let release_file = ReleaseFile::from_indexes(release(&[], &[]), release_ts, &vec![]);
+        assert!(!release_file.contents.contains("Valid-Until"));
+
+        let release_file = ReleaseFile::from_indexes(
+            ReleaseMeta {
+                valid_for_seconds: Some(7 * 24 * 60 * 60),
+                ..release(&[], &[])
+            },
+            release_ts,
+            &vec![],
+        );
+        assert!(release_file.contents.contains(
+            "\nDate: Tue, 14 Nov 2023 22:13:20 +0000\nValid-Until: Tue, 21 Nov 2023 22:13:20 +0000\n"
+        ));
+    }
+
+    #[test]
+    fn not_automatic() {
+        let release_file =
+            ReleaseFile::from_indexes(release(&[], &[]), OffsetDateTime::UNIX_EPOCH, &vec![]);
+        assert!(!release_file.contents.contains("Automatic"));
+
+        let release_file = ReleaseFile::from_indexes(
+            ReleaseMeta {
+                not_automatic: true,
+                but_automatic_upgrades: true,
+                ..release(&[], &[])
+            },
+            OffsetDateTime::UNIX_EPOCH,
+            &vec![],
+        );
+        assert!(
+            release_file
+                .contents
+                .contains("\nNotAutomatic: yes\nButAutomaticUpgrades: yes\n")
+        );
+    }
+
+    /// Indexes that were published before SHA512 sums were recorded are only
+    /// left out of the SHA512 section.
+    #[test]
+    fn missing_sha512sum() {
+        let indexes = vec![
+            index("main", "amd64"),
+            PackagesIndexMeta {
+                sha512sum: None,
+                ..index("main", "arm64")
+            },
+        ];
+        let release_file =
+            ReleaseFile::from_indexes(release(&[], &[]), OffsetDateTime::UNIX_EPOCH, &indexes);
+        let (sha256, sha512) = release_file.contents.split_once("SHA512:\n").unwrap();
+        let sha256 = sha256.split_once("SHA256:\n").unwrap().1;
+        assert!(sha256.contains("main/binary-amd64/Packages"));
+        assert!(sha256.contains("main/binary-arm64/Packages"));
+        assert!(sha512.contains("main/binary-amd64/Packages"));
+        assert!(!sha512.contains("main/binary-arm64/Packages"));
+
+        // Without any SHA512 sums, the section is omitted entirely.
+        let indexes = vec![PackagesIndexMeta {
+            sha512sum: None,
+            ..index("main", "amd64")
+        }];
+        let release_file =
+            ReleaseFile::from_indexes(release(&[], &[]), OffsetDateTime::UNIX_EPOCH, &indexes);
+        assert!(!release_file.contents.contains("SHA512"));
+    }
+
+    /// Release files declare Acquire-By-Hash, and each checksum section lists
+    /// the by-hash copies that are published for its indexes.
+    #[test]
+    fn acquire_by_hash() {
+        for (sha256_only, expected) in [
+            (
+                false,
+                vec![
+                    "main/binary-amd64/by-hash/MD5Sum/md5sum",
+                    "main/binary-amd64/by-hash/SHA1/sha1sum",
+                    "main/binary-amd64/by-hash/SHA256/sha256sum",
+                    "main/binary-amd64/by-hash/SHA512/sha512sum",
+                ],
+            ),
+            (
+                true,
+                vec![
+                    "main/binary-amd64/by-hash/SHA256/sha256sum",
+                    "main/binary-amd64/by-hash/SHA512/sha512sum",
+                ],
+            ),
+        ] {
+            let release_file = ReleaseFile::from_indexes(
+                ReleaseMeta {
+                    sha256_only,
+                    ..release(&[], &[])
+                },
+                OffsetDateTime::UNIX_EPOCH,
+                &vec![index("main", "amd64")],
+            );
+            assert!(
+                release_file.contents.contains("\nAcquire-By-Hash: yes\n"),
+                "{}",
+                release_file.contents
+            );
+
+            // Clients fetch `<dir>/by-hash/<section>/<checksum>` for each entry.
+            let mut section = None;
+            let mut by_hash = Vec::new();
+            for line in release_file.contents.lines() {
+                match line.strip_prefix(' ') {
+                    Some(entry) => {
+                        let [checksum, _size, path] = entry
+                            .split_whitespace()
+                            .collect::<Vec<_>>()
+                            .try_into()
+                            .unwrap();
+                        let (dir, _) = path.rsplit_once('/').unwrap();
+                        by_hash.push(format!("{dir}/by-hash/{}/{checksum}", section.unwrap()));
+                    }
+                    None => section = line.strip_suffix(':'),
+                }
+            }
+            by_hash.sort();
+            assert_eq!(by_hash, expected, "{}", release_file.contents);
+        }
+    }
+
+    #[test]
+    fn parse_date() {
+        let release_ts = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let release_file =
+            ReleaseFile::from_indexes(release(&[], &[]), release_ts, &vec![index("main", "amd64")]);
+        assert_eq!(
+            ReleaseFile::parse_date(&release_file.contents),
+            Some(release_ts)
+        );
+        assert_eq!(ReleaseFile::parse_date("Origin: Test\n"), None);
+    }
 }
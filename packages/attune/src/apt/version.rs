@@ -0,0 +1,254 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// A Debian package version, of the form `[epoch:]upstream[-revision]`.
+///
+/// Versions are ordered the way `dpkg --compare-versions` orders them: by
+/// epoch, then upstream version, then revision. This differs from string
+/// ordering in several ways, e.g. `1.10` is newer than `1.9`, `1:0.1` is newer
+/// than `2.0`, and `1.0~rc1` is older than `1.0`.
+///
+/// Equality follows the same ordering, so `1.0`, `0:1.0`, and `1.0-0` are all
+/// equal, even though they display differently.
+#[derive(Clone, Debug)]
+pub struct DebianVersion {
+    epoch: u32,
+    upstream: String,
+    revision: Option<String>,
+}
+
+impl DebianVersion {
+    /// Parse a version, rejecting the versions that `dpkg` rejects.
+    pub fn parse(version: &str) -> Result<Self, String> {
+        let version = version.trim();
+        if version.is_empty() {
+            return Err(String::from("version string is empty"));
+        }
+        if version.contains(char::is_whitespace) {
+            return Err(format!("version {version:?} has embedded spaces"));
+        }
+
+        let (epoch, rest) = match version.split_once(':') {
+            Some((epoch, rest)) => {
+                if epoch.is_empty() {
+                    return Err(format!("epoch in version {version:?} is empty"));
+                }
+                if !epoch.bytes().all(|c| c.is_ascii_digit()) {
+                    return Err(format!("epoch in version {version:?} is not a number"));
+                }
+                let epoch = epoch
+                    .parse()
+                    .map_err(|_| format!("epoch in version {version:?} is too big"))?;
+                (epoch, rest)
+            }
+            None => (0, version),
+        };
+        let (upstream, revision) = match rest.rsplit_once('-') {
+            Some((_, "")) => {
+                return Err(format!("revision in version {version:?} is empty"));
+            }
+            Some((upstream, revision)) => (upstream, Some(revision.to_string())),
+            None => (rest, None),
+        };
+        if upstream.is_empty() {
+            return Err(format!("upstream version in {version:?} is empty"));
+        }
+
+        Ok(Self {
+            epoch,
+            upstream: upstream.to_string(),
+            revision,
+        })
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    pub fn upstream(&self) -> &str {
+        &self.upstream
+    }
+
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+}
+
+impl FromStr for DebianVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for DebianVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.epoch != 0 {
+            write!(f, "{}:", self.epoch)?;
+        }
+        write!(f, "{}", self.upstream)?;
+        if let Some(revision) = &self.revision {
+            write!(f, "-{revision}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for DebianVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .cmp(&other.epoch)
+            .then_with(|| compare_part(&self.upstream, &other.upstream))
+            .then_with(|| {
+                compare_part(
+                    self.revision.as_deref().unwrap_or_default(),
+                    other.revision.as_deref().unwrap_or_default(),
+                )
+            })
+    }
+}
+
+impl PartialOrd for DebianVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for DebianVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DebianVersion {}
+
+/// Compare two upstream versions or two revisions, using dpkg's `verrevcmp`.
+///
+/// The strings are compared as alternating runs of non-digits and digits.
+/// Non-digit runs are compared character by character, where `~` sorts before
+/// everything (including the end of the string), letters sort before other
+/// characters, and the end of the string sorts before anything but `~`. Digit
+/// runs are compared numerically.
+fn compare_part(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        while a.get(i).is_some_and(|c| !c.is_ascii_digit())
+            || b.get(j).is_some_and(|c| !c.is_ascii_digit())
+        {
+            let (ac, bc) = (order(a.get(i)), order(b.get(j)));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while let (Some(ac), Some(bc)) = (
+            a.get(i).filter(|c| c.is_ascii_digit()),
+            b.get(j).filter(|c| c.is_ascii_digit()),
+        ) {
+            if first_diff == Ordering::Equal {
+                first_diff = ac.cmp(bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        // The longer run of digits (without leading zeros) is the larger
+        // number.
+        if a.get(i).is_some_and(u8::is_ascii_digit) {
+            return Ordering::Greater;
+        }
+        if b.get(j).is_some_and(u8::is_ascii_digit) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+/// The weight of a character in a non-digit run. Digits and the end of the
+/// string weigh the same, since they both end the run.
+fn order(c: Option<&u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(c) if c.is_ascii_digit() => 0,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(*c),
+        Some(b'~') => -1,
+        Some(c) => i32::from(*c) + 256,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        DebianVersion::parse(a)
+            .unwrap()
+            .cmp(&DebianVersion::parse(b).unwrap())
+    }
+
+    #[test]
+    fn compare_dpkg_test_vectors() {
+        // From dpkg's lib/dpkg/t/t-version.c and `dpkg --compare-versions`.
+        let cases = [
+            ("0", "0", Ordering::Equal),
+            ("0", "00", Ordering::Equal),
+            ("1.0", "0:1.0", Ordering::Equal),
+            ("1.0", "1.0-0", Ordering::Equal),
+            ("1.01", "1.1", Ordering::Equal),
+            ("1.0~rc1", "1.0", Ordering::Less),
+            ("1.0~", "1.0", Ordering::Less),
+            ("1.0~~", "1.0~", Ordering::Less),
+            ("1.0~~a", "1.0~", Ordering::Less),
+            ("1.0~rc1", "1.0~rc2", Ordering::Less),
+            ("1.0", "1.0a", Ordering::Less),
+            ("1.0a", "1.0+", Ordering::Less),
+            ("1.0+", "1.0.", Ordering::Less),
+            ("1.9", "1.10", Ordering::Less),
+            ("1.2.3", "1.10", Ordering::Less),
+            ("1.0-1", "1.0-2", Ordering::Less),
+            ("1.0-9", "1.0-10", Ordering::Less),
+            ("1.0-1", "1.0-1ubuntu1", Ordering::Less),
+            ("1.0-1~bpo1", "1.0-1", Ordering::Less),
+            ("2.0", "1:0.1", Ordering::Less),
+            ("1:1.0", "2:0.1", Ordering::Less),
+            ("0:0foo", "0:0", Ordering::Greater),
+            ("0:0-0", "0:0-0", Ordering::Equal),
+            ("1.0-a-1", "1.0-a-2", Ordering::Less),
+            ("1.2-3", "1.2.3", Ordering::Less),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(cmp(a, b), expected, "{a} vs {b}");
+            assert_eq!(cmp(b, a), expected.reverse(), "{b} vs {a}");
+        }
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let version = DebianVersion::parse("2:1.0-a-1").unwrap();
+        assert_eq!(version.epoch(), 2);
+        assert_eq!(version.upstream(), "1.0-a");
+        assert_eq!(version.revision(), Some("1"));
+        assert_eq!(version.to_string(), "2:1.0-a-1");
+
+        for version in ["1.0", "1.0-0", "1:2.3~rc1-4ubuntu1"] {
+            assert_eq!(DebianVersion::parse(version).unwrap().to_string(), version);
+        }
+        assert_eq!(DebianVersion::parse("0:1.0").unwrap().to_string(), "1.0");
+
+        for invalid in ["", "1 .0", ":1.0", "a:1.0", "1:", "1.0-", "99999999999:1.0"] {
+            assert!(DebianVersion::parse(invalid).is_err(), "{invalid:?}");
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    process::ExitCode,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use attune::server::pkg::canonical_key;
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use clap::Args;
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{debug, error, info, warn};
+
+#[derive(Args)]
+pub struct GcArgs {
+    /// Only collect the pool objects of repositories with this name.
+    ///
+    /// Package objects aren't scoped to a repository, so they are only
+    /// collected when this isn't set.
+    #[arg(long)]
+    repository: Option<String>,
+    /// Ignore objects modified less than this many seconds ago.
+    ///
+    /// Uploads write their object before recording the package, so a recent
+    /// object without a referencing row may belong to an upload that is still
+    /// in progress. This must be longer than the longest upload.
+    #[arg(long, default_value_t = 24 * 60 * 60)]
+    min_age_seconds: u64,
+    /// Delete orphaned objects, instead of only reporting them.
+    #[arg(long)]
+    delete: bool,
+    /// Only report orphaned objects. This is the default unless `--delete` is
+    /// set.
+    #[arg(long, conflicts_with = "delete")]
+    dry_run: bool,
+}
+
+/// Machine-readable summary of a garbage collection run, printed to stdout as
+/// JSON.
+#[derive(Serialize, Default)]
+struct GcSummary {
+    orphans: Vec<Orphan>,
+    orphaned_objects: usize,
+    orphaned_bytes: u64,
+    /// Bytes freed by deleting orphans. Always zero unless `--delete` is set.
+    reclaimed_bytes: u64,
+}
+
+#[derive(Serialize, Clone)]
+struct Orphan {
+    bucket: String,
+    key: String,
+    bytes: u64,
+}
+
+/// An S3 prefix to collect, and the keys under it that the database refers
+/// to.
+struct Scope {
+    bucket: String,
+    prefix: String,
+    referenced: HashSet<String>,
+}
+
+/// Find (and with `--delete`, delete) S3 objects that no database row refers
+/// to.
+///
+/// Two kinds of objects are collected:
+///
+/// - Pool objects under each repository's `<prefix>/pool/`, which are
+///   referenced by the pool filenames of the packages in the repository's
+///   components.
+/// - Package objects under `packages/` and `pool/` at the root of each bucket,
+///   which are referenced by package rows, whether or not the packages are in
+///   any component. Removing a package from every component doesn't make its
+///   object collectable, since the package can still be added again.
+///
+/// Both are left behind by interrupted uploads and index changes, which the
+/// resync tests cover.
+pub async fn run(
+    db: PgPool,
+    s3: aws_sdk_s3::Client,
+    s3_bucket_name: String,
+    args: GcArgs,
+) -> ExitCode {
+    let min_modified = SystemTime::now()
+        .checked_sub(Duration::from_secs(args.min_age_seconds))
+        .unwrap_or(UNIX_EPOCH);
+    let min_modified = min_modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() as i64);
+
+    let scopes = match query_scopes(&db, &s3_bucket_name, args.repository.as_deref()).await {
+        Ok(scopes) => scopes,
+        Err(err) => {
+            error!(?err, "could not query referenced objects");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut summary = GcSummary::default();
+    for scope in &scopes {
+        match list_orphans(&s3, scope, min_modified).await {
+            Ok(orphans) => {
+                info!(bucket = %scope.bucket, prefix = %scope.prefix, orphans = orphans.len(), "listed orphaned objects");
+                summary.orphans.extend(orphans);
+            }
+            Err(err) => {
+                error!(bucket = %scope.bucket, prefix = %scope.prefix, %err, "could not list objects");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Objects can become referenced again while they are being listed, e.g.
+    // when a package that was removed is uploaded again. Check the database
+    // again, so that only objects that were unreferenced both before and
+    // after listing are collected.
+    let referenced = match query_scopes(&db, &s3_bucket_name, args.repository.as_deref()).await {
+        Ok(scopes) => scopes
+            .into_iter()
+            .flat_map(|scope| {
+                let bucket = scope.bucket;
+                scope
+                    .referenced
+                    .into_iter()
+                    .map(move |key| (bucket.clone(), key))
+            })
+            .collect::<HashSet<_>>(),
+        Err(err) => {
+            error!(?err, "could not query referenced objects");
+            return ExitCode::FAILURE;
+        }
+    };
+    summary
+        .orphans
+        .retain(|orphan| !referenced.contains(&(orphan.bucket.clone(), orphan.key.clone())));
+    summary.orphaned_objects = summary.orphans.len();
+    summary.orphaned_bytes = summary.orphans.iter().map(|orphan| orphan.bytes).sum();
+
+    let mut failed = false;
+    if args.delete {
+        let mut by_bucket = BTreeMap::<&str, Vec<&Orphan>>::new();
+        for orphan in &summary.orphans {
+            by_bucket.entry(&orphan.bucket).or_default().push(orphan);
+        }
+        for (bucket, orphans) in by_bucket {
+            for orphans in orphans.chunks(1000) {
+                match delete_objects(&s3, bucket, orphans).await {
+                    Ok((reclaimed, 0)) => summary.reclaimed_bytes += reclaimed,
+                    Ok((reclaimed, undeleted)) => {
+                        error!(%bucket, undeleted, "could not delete some orphaned objects");
+                        summary.reclaimed_bytes += reclaimed;
+                        failed = true;
+                    }
+                    Err(err) => {
+                        error!(%bucket, %err, "could not delete orphaned objects");
+                        failed = true;
+                    }
+                }
+            }
+        }
+    } else if summary.orphaned_objects > 0 {
+        warn!(
+            orphaned_objects = summary.orphaned_objects,
+            orphaned_bytes = summary.orphaned_bytes,
+            "found orphaned objects; run with --delete to delete them"
+        );
+    }
+
+    println!(
+        "{}",
+        serde_json::to_string(&summary).expect("could not serialize summary")
+    );
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Find the prefixes to collect, and the keys that the database refers to
+/// under each of them.
+async fn query_scopes(
+    db: &PgPool,
+    s3_bucket_name: &str,
+    repository: Option<&str>,
+) -> Result<Vec<Scope>, sqlx::Error> {
+    let mut scopes = Vec::new();
+
+    let repositories = sqlx::query!(
+        r#"
+        SELECT id, s3_bucket, s3_prefix
+        FROM debian_repository
+        WHERE $1::TEXT IS NULL OR name = $1
+        ORDER BY id
+        "#,
+        repository,
+    )
+    .fetch_all(db)
+    .await?;
+    for repo in repositories {
+        let filenames = sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT debian_repository_component_package.filename
+            FROM
+                debian_repository_component_package
+                JOIN debian_repository_component ON debian_repository_component_package.component_id = debian_repository_component.id
+                JOIN debian_repository_release ON debian_repository_component.release_id = debian_repository_release.id
+            WHERE debian_repository_release.repository_id = $1
+            "#,
+            repo.id,
+        )
+        .fetch_all(db)
+        .await?;
+        scopes.push(Scope {
+            prefix: format!("{}/pool/", repo.s3_prefix),
+            referenced: filenames
+                .into_iter()
+                .map(|filename| format!("{}/{filename}", repo.s3_prefix))
+                .collect(),
+            bucket: repo.s3_bucket,
+        });
+    }
+
+    if repository.is_none() {
+        let packages = sqlx::query!(
+            r#"
+            SELECT s3_bucket, s3_key, sha256sum
+            FROM debian_repository_package
+            "#,
+        )
+        .fetch_all(db)
+        .await?;
+        let mut referenced = BTreeMap::<String, HashSet<String>>::new();
+        referenced.entry(s3_bucket_name.to_string()).or_default();
+        for package in packages {
+            referenced
+                .entry(package.s3_bucket)
+                .or_default()
+                .insert(canonical_key(package.s3_key.as_deref(), &package.sha256sum));
+        }
+        for (bucket, referenced) in referenced {
+            // See `PackageKeyScheme` for the layout of package objects.
+            for prefix in ["packages/", "pool/"] {
+                scopes.push(Scope {
+                    bucket: bucket.clone(),
+                    prefix: prefix.to_string(),
+                    referenced: referenced.clone(),
+                });
+            }
+        }
+    }
+
+    Ok(scopes)
+}
+
+/// List the objects under a prefix that aren't referenced and were last
+/// modified before `min_modified`, in seconds since the epoch.
+async fn list_orphans(
+    s3: &aws_sdk_s3::Client,
+    scope: &Scope,
+    min_modified: i64,
+) -> Result<Vec<Orphan>, String> {
+    let mut pages = s3
+        .list_objects_v2()
+        .bucket(&scope.bucket)
+        .prefix(&scope.prefix)
+        .into_paginator()
+        .send();
+    let mut orphans = Vec::new();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| err.to_string())?;
+        for object in page.contents.unwrap_or_default() {
+            let Some(key) = object.key else {
+                continue;
+            };
+            if scope.referenced.contains(&key) {
+                continue;
+            }
+            if object
+                .last_modified
+                .is_none_or(|modified| modified.secs() >= min_modified)
+            {
+                debug!(?key, "skipping recent unreferenced object");
+                continue;
+            }
+            orphans.push(Orphan {
+                bucket: scope.bucket.clone(),
+                key,
+                bytes: object.size.unwrap_or_default() as u64,
+            });
+        }
+    }
+    Ok(orphans)
+}
+
+/// Delete up to 1000 objects from a bucket, returning the bytes reclaimed and
+/// the number of objects that couldn't be deleted.
+async fn delete_objects(
+    s3: &aws_sdk_s3::Client,
+    bucket: &str,
+    orphans: &[&Orphan],
+) -> Result<(u64, usize), String> {
+    let objects = orphans
+        .iter()
+        .map(|orphan| ObjectIdentifier::builder().key(&orphan.key).build())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    let delete = Delete::builder()
+        .set_objects(Some(objects))
+        .quiet(true)
+        .build()
+        .map_err(|err| err.to_string())?;
+    let deleted = s3
+        .delete_objects()
+        .bucket(bucket)
+        .delete(delete)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    // In quiet mode, only failed deletions are reported.
+    let failed = deleted
+        .errors
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|error| {
+            warn!(key = ?error.key, message = ?error.message, "could not delete object");
+            error.key
+        })
+        .collect::<HashSet<_>>();
+    let reclaimed = orphans
+        .iter()
+        .filter(|orphan| !failed.contains(&orphan.key))
+        .map(|orphan| orphan.bytes)
+        .sum();
+    Ok((reclaimed, failed.len()))
+}
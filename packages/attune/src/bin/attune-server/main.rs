@@ -1,11 +1,23 @@
-use aws_sdk_s3::config::BehaviorVersion;
-use clap::Parser;
+use std::{net::SocketAddr, path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
+
+use attune::{
+    logging::LogFormat,
+    server::{
+        cdn::CloudFrontInvalidator,
+        metrics::{MeteredObjectStore, Metrics},
+        object_store::{FilesystemObjectStore, ObjectStore, S3Encryption, S3ObjectStore},
+    },
+};
+use aws_sdk_s3::{config::BehaviorVersion, types::ServerSideEncryption};
+use clap::{Parser, Subcommand, ValueEnum};
 use git_version::git_version;
 use tokio::signal;
-use tracing::{info, trace};
-use tracing_subscriber::{
-    fmt::format::FmtSpan, layer::SubscriberExt as _, util::SubscriberInitExt as _,
-};
+use tracing::{error, info, trace};
+use url::Url;
+
+mod gc;
+mod resync_all;
+mod token;
 
 /// Attune control plane server, community edition
 ///
@@ -33,29 +45,237 @@ struct Args {
     /// the default user will not have an API token configured.
     #[arg(long, env = "ATTUNE_API_TOKEN")]
     default_api_token: Option<String>,
+    /// Public base URL of repositories, reported to clients when they create
+    /// repositories.
+    ///
+    /// `{bucket}` and `{prefix}` are replaced with the repository's S3 bucket
+    /// and prefix, e.g. `https://repo.example.com/{prefix}`.
+    #[arg(long, env = "ATTUNE_PUBLIC_BASE_URL")]
+    public_base_url: Option<String>,
+    /// Maximum number of repositories each tenant can create.
+    ///
+    /// If not set, tenants can create any number of repositories.
+    #[arg(long, env = "ATTUNE_MAX_REPOS_PER_TENANT")]
+    max_repos_per_tenant: Option<u32>,
+    /// Store Packages indexes gzipped in the database.
+    ///
+    /// This saves database space for repositories with many packages. It only
+    /// applies to indexes written from now on; indexes that are already stored
+    /// are read either way.
+    #[arg(long, env = "ATTUNE_COMPRESS_INDEX_CONTENTS")]
+    compress_index_contents: bool,
+    /// How uploaded packages are keyed in the S3 bucket.
+    ///
+    /// By default, packages are content-addressed, so identical uploads share
+    /// one object. The `pool` scheme stores them by name, version, and
+    /// architecture instead, which is easier to browse with external tools.
+    /// Changing this only affects packages uploaded afterwards.
+    #[arg(
+        long,
+        env = "ATTUNE_PACKAGE_KEY_SCHEME",
+        value_enum,
+        default_value_t = Default::default()
+    )]
+    package_key_scheme: attune::server::pkg::PackageKeyScheme,
+    /// Timeout for API requests, in seconds.
+    ///
+    /// This applies to all requests except package uploads, which are
+    /// configured separately with `--upload-timeout`.
+    #[arg(
+        long = "timeout",
+        env = "ATTUNE_REQUEST_TIMEOUT_SECONDS",
+        default_value_t = 600
+    )]
+    request_timeout_seconds: u64,
+    /// Timeout for package uploads, in seconds.
+    ///
+    /// Uploads of large packages can legitimately take much longer than other
+    /// requests, so this is usually longer than `--timeout`.
+    #[arg(
+        long = "upload-timeout",
+        env = "ATTUNE_UPLOAD_TIMEOUT_SECONDS",
+        default_value_t = 3600
+    )]
+    upload_timeout_seconds: u64,
+    /// Sustained number of requests per second allowed for each API token.
+    ///
+    /// If not set, requests are not rate limited.
+    #[arg(
+        long,
+        env = "ATTUNE_RATE_LIMIT_PER_SECOND",
+        value_parser = attune::server::rate_limit::parse_requests_per_second
+    )]
+    rate_limit_per_second: Option<f64>,
+    /// Number of requests each API token can make in a burst before being
+    /// rate limited.
+    ///
+    /// Only used if `--rate-limit-per-second` is set.
+    #[arg(
+        long,
+        env = "ATTUNE_RATE_LIMIT_BURST",
+        default_value_t = 50,
+        value_parser = attune::server::rate_limit::parse_burst
+    )]
+    rate_limit_burst: u32,
+    /// Where repository files are stored.
+    ///
+    /// The `fs` backend stores packages and published files under
+    /// `--storage-root`, for trying Attune without S3. Serve that directory
+    /// with a static file server to install packages from it. It only supports
+    /// uploading, publishing, and resyncing: endpoints that list or download
+    /// objects (like repository deletion and export) and the maintenance
+    /// commands other than `resync-all` still require S3.
+    #[arg(
+        long,
+        env = "ATTUNE_STORAGE_BACKEND",
+        value_enum,
+        default_value_t = StorageBackend::S3
+    )]
+    storage_backend: StorageBackend,
+    /// Directory to store repository files in, with the `fs` storage backend.
+    ///
+    /// Objects are stored at `<root>/<bucket>/<key>`, so repositories are
+    /// served from the same paths as path-style S3 URLs.
+    #[arg(
+        long,
+        env = "ATTUNE_STORAGE_ROOT",
+        required_if_eq("storage_backend", "fs")
+    )]
+    storage_root: Option<PathBuf>,
+    /// Maximum number of S3 requests the server makes concurrently, across
+    /// all API requests.
+    ///
+    /// Lower this if S3 (or an S3-compatible store) throttles the server.
+    #[arg(
+        long,
+        env = "ATTUNE_S3_MAX_CONCURRENCY",
+        default_value_t = attune::server::s3_concurrency::S3Concurrency::DEFAULT_LIMIT
+    )]
+    s3_max_concurrency: usize,
+    /// URL of the S3 API endpoint, for S3-compatible object storage like
+    /// MinIO, Ceph, or Backblaze B2.
+    ///
+    /// If not set, the endpoint is inferred from the AWS environment (e.g.
+    /// `AWS_ENDPOINT_URL_S3`), which defaults to AWS S3.
+    #[arg(long, env = "ATTUNE_S3_ENDPOINT_URL", value_parser = parse_s3_endpoint_url)]
+    s3_endpoint_url: Option<Url>,
+    /// Address buckets by path (`http://endpoint/bucket/key`) instead of by
+    /// subdomain (`http://bucket.endpoint/key`).
+    ///
+    /// Most S3-compatible object storage needs this unless it is configured
+    /// with a domain for virtual-hosted buckets.
+    #[arg(long, env = "ATTUNE_S3_FORCE_PATH_STYLE")]
+    s3_force_path_style: bool,
+    /// Server-side encryption for the indexes, Release files, and packages
+    /// that the server writes to S3.
+    ///
+    /// If not set, objects are encrypted with the bucket's default encryption
+    /// settings. With `aws:kms`, the server's credentials need
+    /// `kms:GenerateDataKey` and `kms:Decrypt` on the key.
+    #[arg(long, env = "ATTUNE_S3_SSE", value_enum)]
+    s3_sse: Option<S3Sse>,
+    /// KMS key ID or ARN to encrypt objects with when `--s3-sse` is
+    /// `aws:kms`.
+    ///
+    /// If not set, S3 uses the account's AWS managed key for S3.
+    #[arg(long, env = "ATTUNE_S3_SSE_KMS_KEY_ID")]
+    s3_sse_kms_key_id: Option<String>,
+    /// ID of a CloudFront distribution to invalidate after each change.
+    ///
+    /// After publishing a change, the server invalidates the distribution's
+    /// Release files and the Packages indexes that changed, so clients don't
+    /// see stale indexes until the CDN's cache expires. Paths are invalidated
+    /// by S3 key, so the CloudFront origin must be the root of the bucket. The
+    /// server's credentials need `cloudfront:CreateInvalidation`.
+    #[arg(long = "cdn-invalidate", env = "ATTUNE_CDN_DISTRIBUTION_ID")]
+    cdn_distribution_id: Option<String>,
+    /// Serve Prometheus metrics at `/api/v0/metrics`.
+    ///
+    /// Metrics cover HTTP requests, package upload sizes, index signing
+    /// latencies, and object store failures. The endpoint doesn't require an
+    /// API token, so don't expose it publicly.
+    #[arg(long, env = "ATTUNE_ENABLE_METRICS")]
+    enable_metrics: bool,
+    /// How long to keep the by-hash files of replaced Packages indexes after
+    /// a change is published, e.g. `10m`.
+    ///
+    /// Clients that fetched the previous Release file just before a change
+    /// was published still request its indexes, so deleting them right away
+    /// can fail those clients' updates. Set to `0` to delete them right away.
+    #[arg(
+        long,
+        env = "ATTUNE_INDEX_DELETION_GRACE",
+        value_parser = parse_index_deletion_grace,
+        default_value = "10m"
+    )]
+    index_deletion_grace: Duration,
+
+    /// Base path to serve the API under, for when a reverse proxy mounts
+    /// Attune under a subpath without stripping it.
+    ///
+    /// For example, with a base path of `/attune`, the API is served at
+    /// `/attune/api/v0`.
+    #[arg(long, env = "ATTUNE_BASE_PATH", default_value = "")]
+    base_path: String,
+    /// Apply pending database migrations at startup.
+    ///
+    /// If not set, the server refuses to start when the database schema
+    /// doesn't match the migrations this build expects.
+    #[arg(long, env = "ATTUNE_AUTO_MIGRATE")]
+    auto_migrate: bool,
+    /// Format of the logs written to stderr.
+    ///
+    /// Use `json` to write one JSON object per line, for log aggregators.
+    #[arg(long, env = "ATTUNE_LOG_FORMAT", value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// Maintenance command to run instead of starting the server.
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[tokio::main]
-async fn main() {
-    // Initialize tracing.
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
-                .with_file(true)
-                .with_line_number(true)
-                .with_target(true)
-                .with_thread_ids(true)
-                .with_thread_names(true)
-                .with_writer(std::io::stderr)
-                .pretty(),
-        )
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum S3Sse {
+    /// SSE-S3, with keys managed by S3.
+    #[value(name = "AES256")]
+    Aes256,
+    /// SSE-KMS, with keys managed by AWS KMS.
+    #[value(name = "aws:kms")]
+    AwsKms,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum StorageBackend {
+    /// S3, or an S3-compatible object store.
+    S3,
+    /// A directory on the local filesystem.
+    Fs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check and resynchronize all repositories from the database
+    ///
+    /// This restores S3 state for every distribution after rare race conditions
+    /// or crashes, and is intended to be run periodically (e.g. from cron).
+    ResyncAll(resync_all::ResyncAllArgs),
+    /// Find and delete S3 objects that the database no longer refers to
+    ///
+    /// Interrupted uploads and index changes can leave package and pool
+    /// objects behind. Without `--delete`, orphaned objects are only reported.
+    Gc(gc::GcArgs),
+    /// Manage the API tokens of the local tenant
+    Token(token::TokenArgs),
+}
 
+#[tokio::main]
+async fn main() -> ExitCode {
     // Parse CLI arguments.
     let args = Args::parse();
 
+    // Initialize tracing.
+    attune::logging::init(args.log_format);
+
     // Initialize database.
     let db_url = args.db_url;
     let db = sqlx::postgres::PgPoolOptions::new()
@@ -64,31 +284,211 @@ async fn main() {
         .await
         .expect("could not connect to database");
 
+    // Check that the database schema matches this build, so that version
+    // mismatches fail here instead of as query errors at request time.
+    let status = match attune::server::migrations::check(&db).await {
+        Ok(status) => status,
+        Err(err) => {
+            error!(?err, "could not check database migrations");
+            return ExitCode::FAILURE;
+        }
+    };
+    if !status.unknown.is_empty() {
+        error!(
+            unknown = ?status.unknown,
+            "database has migrations that this version of attune-server does not know about; upgrade attune-server"
+        );
+        return ExitCode::FAILURE;
+    }
+    if !status.pending.is_empty() {
+        if !args.auto_migrate {
+            error!(
+                pending = ?status.pending,
+                "database has pending migrations; apply them with `docker compose run migrate`, or set ATTUNE_AUTO_MIGRATE=true"
+            );
+            return ExitCode::FAILURE;
+        }
+        if let Err(err) = attune::server::migrations::apply_pending(&db, &status).await {
+            error!(?err, "could not apply database migrations");
+            return ExitCode::FAILURE;
+        }
+        info!(applied = ?status.pending, "applied database migrations");
+    }
+
     // Initialize AWS S3 client.
     let config = aws_config::defaults(BehaviorVersion::latest()).load().await;
-    let config = aws_sdk_s3::config::Builder::from(&config).build();
+    let credentials = config.credentials_provider();
+    let mut config = aws_sdk_s3::config::Builder::from(&config);
+    if let Some(endpoint_url) = &args.s3_endpoint_url {
+        // Url always has a path, so the root path has a trailing slash that
+        // would otherwise end up in every request path.
+        config = config.endpoint_url(endpoint_url.as_str().trim_end_matches('/'));
+    }
+    if args.s3_force_path_style {
+        config = config.force_path_style(true);
+    }
+    let config = config.build();
+    info!(
+        endpoint_url = ?args.s3_endpoint_url.as_ref().map(Url::as_str),
+        force_path_style = args.s3_force_path_style,
+        "configured S3 client"
+    );
     trace!(?config, "inferred AWS S3 configuration from environment");
     let s3 = aws_sdk_s3::Client::from_conf(config);
+    let encryption = match (args.s3_sse, args.s3_sse_kms_key_id) {
+        (None, None) => None,
+        (Some(S3Sse::Aes256), None) => Some(S3Encryption {
+            algorithm: ServerSideEncryption::Aes256,
+            kms_key_id: None,
+        }),
+        (Some(S3Sse::AwsKms), kms_key_id) => Some(S3Encryption {
+            algorithm: ServerSideEncryption::AwsKms,
+            kms_key_id,
+        }),
+        (_, Some(_)) => {
+            error!("--s3-sse-kms-key-id requires --s3-sse aws:kms");
+            return ExitCode::FAILURE;
+        }
+    };
+    info!(?encryption, "configured S3 server-side encryption");
+    let object_store: Arc<dyn ObjectStore> = match (args.storage_backend, args.storage_root) {
+        (StorageBackend::S3, _) => {
+            Arc::new(S3ObjectStore::new(s3.clone()).with_encryption(encryption))
+        }
+        (StorageBackend::Fs, Some(root)) => {
+            info!(?root, "storing repository files on the local filesystem");
+            Arc::new(FilesystemObjectStore::new(root))
+        }
+        (StorageBackend::Fs, None) => unreachable!("clap requires --storage-root"),
+    };
+    let metrics = args.enable_metrics.then(Metrics::new);
+    info!(enabled = metrics.is_some(), "configured metrics");
+    let object_store: Arc<dyn ObjectStore> = match &metrics {
+        Some(metrics) => Arc::new(MeteredObjectStore::new(object_store, metrics.clone())),
+        None => object_store,
+    };
+    let cdn = match (args.cdn_distribution_id, credentials) {
+        (None, _) => None,
+        (Some(distribution_id), Some(credentials)) => {
+            info!(%distribution_id, "invalidating CloudFront after each change");
+            Some(CloudFrontInvalidator::new(distribution_id, credentials))
+        }
+        (Some(_), None) => {
+            error!("--cdn-invalidate requires AWS credentials");
+            return ExitCode::FAILURE;
+        }
+    };
     let s3_bucket_name = args.s3_bucket_name;
 
+    // Run maintenance commands, if any.
+    if let Some(command) = args.command {
+        return match command {
+            Command::ResyncAll(args) => resync_all::run(db, object_store, args).await,
+            Command::Gc(args) => gc::run(db, s3, s3_bucket_name, args).await,
+            Command::Token(args) => token::run(db, args).await,
+        };
+    }
+
     // Initialize server.
+    let timeouts = attune::server::Timeouts {
+        request: Duration::from_secs(args.request_timeout_seconds),
+        upload: Duration::from_secs(args.upload_timeout_seconds),
+    };
+    info!(
+        request_timeout = ?timeouts.request,
+        upload_timeout = ?timeouts.upload,
+        "configured request timeouts"
+    );
+    let rate_limit = args.rate_limit_per_second.map(|requests_per_second| {
+        attune::server::rate_limit::RateLimit {
+            requests_per_second,
+            burst: args.rate_limit_burst,
+        }
+    });
+    info!(?rate_limit, "configured rate limit");
+    info!(
+        s3_max_concurrency = args.s3_max_concurrency,
+        "configured S3 concurrency"
+    );
+    let s3_concurrency =
+        attune::server::s3_concurrency::S3Concurrency::new(args.s3_max_concurrency);
+    info!(
+        index_deletion_grace = ?args.index_deletion_grace,
+        "configured index deletion grace period"
+    );
+    if !args.index_deletion_grace.is_zero() {
+        attune::server::repo::index::deletion::spawn_sweeper(
+            db.clone(),
+            object_store.clone(),
+            s3_concurrency.clone(),
+            args.index_deletion_grace,
+        );
+    }
     let app = attune::server::new(
         attune::server::ServerState {
             db,
             s3,
+            object_store,
             s3_bucket_name,
+            s3_concurrency,
+            public_base_url: args.public_base_url,
+            max_repos_per_tenant: args.max_repos_per_tenant,
+            index_contents_encoding: args
+                .compress_index_contents
+                .then_some(attune::server::repo::index::ContentsEncoding::Gzip),
+            package_key_scheme: args.package_key_scheme,
+            cdn,
+            metrics,
+            index_deletion_grace: args.index_deletion_grace,
         },
         args.default_api_token,
+        timeouts,
+        rate_limit,
     )
     .await;
+    let app = attune::server::with_base_path(app, &args.base_path);
 
     // Start server.
     info!(address = "0.0.0.0:3000", "starting server");
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown())
-        .await
-        .unwrap();
+    // Rate limiting keys requests with invalid tokens on the client's address.
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown())
+    .await
+    .unwrap();
+
+    ExitCode::SUCCESS
+}
+
+/// Parse an S3 endpoint URL, so that invalid URLs are rejected at startup
+/// rather than when the first S3 request is made.
+fn parse_s3_endpoint_url(endpoint_url: &str) -> Result<Url, String> {
+    let url = Url::parse(endpoint_url).map_err(|err| format!("invalid URL: {err}"))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "unsupported scheme {:?}, expected http or https",
+            url.scheme()
+        ));
+    }
+    if url.host().is_none() {
+        return Err(String::from("URL has no host"));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(String::from("URL must not have a query or fragment"));
+    }
+    Ok(url)
+}
+
+/// Parse the index deletion grace period, which unlike other durations can be
+/// zero.
+fn parse_index_deletion_grace(grace: &str) -> Result<Duration, String> {
+    match grace {
+        "0" => Ok(Duration::ZERO),
+        grace => attune::duration::parse(grace),
+    }
 }
 
 async fn shutdown() {
@@ -0,0 +1,192 @@
+use std::{process::ExitCode, sync::Arc};
+
+use attune::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        object_store::ObjectStore,
+        repo::sync::{
+            InconsistentSummary, SyncScope, check_s3_consistency, query_repository_state,
+            resync::resync_s3,
+        },
+    },
+};
+use clap::Args;
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::{error, info, warn};
+
+#[derive(Args)]
+pub struct ResyncAllArgs {
+    /// Only check distributions of repositories with this name.
+    #[arg(long)]
+    repository: Option<String>,
+    /// Report inconsistencies without fixing them.
+    ///
+    /// Exits with a non-zero status if any inconsistencies were found.
+    #[arg(long)]
+    check_only: bool,
+}
+
+/// Machine-readable summary of a resync run, printed to stdout as JSON.
+#[derive(Serialize)]
+struct ResyncAllSummary {
+    distributions: Vec<DistributionResult>,
+    inconsistent: usize,
+    failed: usize,
+}
+
+#[derive(Serialize)]
+struct DistributionResult {
+    tenant_id: i64,
+    repository: String,
+    distribution: String,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+enum Outcome {
+    Consistent,
+    Inconsistent {
+        #[serde(flatten)]
+        status: InconsistentSummary,
+    },
+    Resynced {
+        #[serde(flatten)]
+        status: InconsistentSummary,
+    },
+    Failed {
+        error: String,
+    },
+}
+
+/// Check (and unless `--check-only` is set, resync) every distribution of
+/// every repository against the database.
+///
+/// This is the periodic counterpart to the per-distribution sync endpoints,
+/// and is safe to run repeatedly: consistent distributions are left untouched.
+pub async fn run(db: PgPool, store: Arc<dyn ObjectStore>, args: ResyncAllArgs) -> ExitCode {
+    let distributions = match sqlx::query!(
+        r#"
+        SELECT
+            debian_repository.tenant_id,
+            debian_repository.name AS repository,
+            debian_repository_release.distribution
+        FROM
+            debian_repository
+            JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
+        WHERE
+            $1::TEXT IS NULL OR debian_repository.name = $1
+        ORDER BY debian_repository.id, debian_repository_release.id
+        "#,
+        args.repository,
+    )
+    .fetch_all(&db)
+    .await
+    {
+        Ok(distributions) => distributions,
+        Err(err) => {
+            error!(?err, "could not list distributions");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut results = Vec::with_capacity(distributions.len());
+    for dist in distributions {
+        let outcome = match resync_distribution(
+            &db,
+            store.as_ref(),
+            TenantID(dist.tenant_id),
+            &dist.repository,
+            &dist.distribution,
+            args.check_only,
+        )
+        .await
+        {
+            Ok(outcome) => outcome,
+            Err(err) => Outcome::Failed {
+                error: err.to_string(),
+            },
+        };
+        match &outcome {
+            Outcome::Consistent => {
+                info!(tenant_id = dist.tenant_id, repository = %dist.repository, distribution = %dist.distribution, "distribution is consistent");
+            }
+            Outcome::Inconsistent { status } | Outcome::Resynced { status } => {
+                warn!(tenant_id = dist.tenant_id, repository = %dist.repository, distribution = %dist.distribution, ?status, "distribution is inconsistent");
+            }
+            Outcome::Failed { error } => {
+                error!(tenant_id = dist.tenant_id, repository = %dist.repository, distribution = %dist.distribution, %error, "could not resync distribution");
+            }
+        }
+        results.push(DistributionResult {
+            tenant_id: dist.tenant_id,
+            repository: dist.repository,
+            distribution: dist.distribution,
+            outcome,
+        });
+    }
+
+    let summary = ResyncAllSummary {
+        inconsistent: results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Inconsistent { .. }))
+            .count(),
+        failed: results
+            .iter()
+            .filter(|result| matches!(result.outcome, Outcome::Failed { .. }))
+            .count(),
+        distributions: results,
+    };
+    println!(
+        "{}",
+        serde_json::to_string(&summary).expect("could not serialize summary")
+    );
+
+    if summary.failed > 0 || summary.inconsistent > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+async fn resync_distribution(
+    db: &PgPool,
+    store: &dyn ObjectStore,
+    tenant_id: TenantID,
+    repository: &str,
+    distribution: &str,
+    check_only: bool,
+) -> Result<Outcome, ErrorResponse> {
+    let mut tx = db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    let state = query_repository_state(
+        &mut tx,
+        &tenant_id,
+        repository.to_string(),
+        distribution.to_string(),
+        &SyncScope::default(),
+    )
+    .await?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+    let Some(state) = state else {
+        // The distribution has never been published, so there is nothing to
+        // sync.
+        return Ok(Outcome::Consistent);
+    };
+
+    let inconsistent_objects = check_s3_consistency(store, state).await?;
+    let status = InconsistentSummary::from(&inconsistent_objects);
+    Ok(if status.is_consistent() {
+        Outcome::Consistent
+    } else if check_only {
+        Outcome::Inconsistent { status }
+    } else {
+        resync_s3(store, inconsistent_objects).await?;
+        Outcome::Resynced { status }
+    })
+}
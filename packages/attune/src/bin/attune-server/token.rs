@@ -0,0 +1,170 @@
+use std::{process::ExitCode, time::Duration};
+
+use attune::api::{NewApiToken, TokenScope};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+use sqlx::PgPool;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tracing::{error, info};
+
+#[derive(Args)]
+pub struct TokenArgs {
+    #[command(subcommand)]
+    command: TokenCommand,
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Create an API token for the local tenant
+    ///
+    /// The token is printed once, and can't be recovered afterwards.
+    Add(AddArgs),
+    /// List the local tenant's API tokens and their scopes
+    List,
+}
+
+#[derive(Args)]
+struct AddArgs {
+    /// A name to identify the token by.
+    #[arg(long)]
+    name: String,
+    /// Restrict what the token can do: `read`, `write`, or `repo:<name>`.
+    ///
+    /// Can be given several times. A `read` token can only make requests that
+    /// don't change anything, and `repo:<name>` tokens can only use the named
+    /// repositories. Without any scopes, the token has full access.
+    #[arg(long = "scope")]
+    scopes: Vec<TokenScope>,
+    /// When the token stops working, as an RFC 3339 timestamp (e.g.
+    /// `2026-01-01T00:00:00Z`).
+    ///
+    /// Without this or `--ttl`, the token never expires.
+    #[arg(long, value_parser = parse_expires_at)]
+    expires_at: Option<OffsetDateTime>,
+    /// How long the token works for, as a number of seconds or with a unit
+    /// (`s`, `m`, `h`, or `d`), e.g. `90d`.
+    #[arg(long, conflicts_with = "expires_at", value_parser = attune::duration::parse)]
+    ttl: Option<Duration>,
+}
+
+/// A token, printed to stdout as JSON.
+#[derive(Serialize)]
+struct Token {
+    id: i64,
+    name: String,
+    scopes: Vec<String>,
+    /// When the token expires, if ever.
+    #[serde(with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+    expired: bool,
+    /// The token itself. Tokens are only printed when they're created,
+    /// since the database only stores their hashes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+/// Tokens created here belong to the single tenant that `attune-server`
+/// serves.
+const LOCAL_TENANT_ID: i64 = 1;
+
+pub async fn run(db: PgPool, args: TokenArgs) -> ExitCode {
+    let result = match args.command {
+        TokenCommand::Add(args) => add(&db, args).await.map(|token| vec![token]),
+        TokenCommand::List => list(&db).await,
+    };
+    match result {
+        Ok(tokens) => {
+            for token in tokens {
+                println!(
+                    "{}",
+                    serde_json::to_string(&token).expect("could not serialize token")
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            error!(%err, "could not manage API tokens");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn add(db: &PgPool, args: AddArgs) -> Result<Token, String> {
+    let token = NewApiToken::generate();
+    let mut scopes = Vec::<String>::new();
+    for scope in &args.scopes {
+        let scope = scope.to_string();
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    let expires_at = match (args.expires_at, args.ttl) {
+        (Some(expires_at), _) => Some(expires_at),
+        (None, Some(ttl)) => Some(OffsetDateTime::now_utc() + ttl),
+        (None, None) => None,
+    };
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO attune_tenant_api_token (tenant_id, name, lookup_id, token_hash, scopes, expires_at)
+        SELECT id, $2, $3, $4, $5, $6
+        FROM attune_tenant
+        WHERE id = $1
+        RETURNING id
+        "#,
+        LOCAL_TENANT_ID,
+        args.name,
+        token.lookup_id,
+        token.token_hash,
+        &scopes,
+        expires_at,
+    )
+    .fetch_optional(db)
+    .await
+    .map_err(|err| err.to_string())?
+    .ok_or("the local tenant doesn't exist yet; start the server once to create it")?;
+    info!(id, name = %args.name, ?scopes, ?expires_at, "created API token");
+    Ok(Token {
+        id,
+        name: args.name,
+        scopes,
+        expired: expires_at.is_some_and(|expires_at| expires_at <= OffsetDateTime::now_utc()),
+        expires_at,
+        token: Some(token.token),
+    })
+}
+
+async fn list(db: &PgPool) -> Result<Vec<Token>, String> {
+    let tokens = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            name,
+            scopes AS "scopes!",
+            expires_at,
+            COALESCE(expires_at <= NOW(), FALSE) AS "expired!"
+        FROM attune_tenant_api_token
+        WHERE tenant_id = $1
+        ORDER BY id
+        "#,
+        LOCAL_TENANT_ID,
+    )
+    .fetch_all(db)
+    .await
+    .map_err(|err| err.to_string())?;
+    Ok(tokens
+        .into_iter()
+        .map(|token| Token {
+            id: token.id,
+            name: token.name,
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+            expired: token.expired,
+            token: None,
+        })
+        .collect())
+}
+
+fn parse_expires_at(expires_at: &str) -> Result<OffsetDateTime, String> {
+    OffsetDateTime::parse(expires_at, &Rfc3339)
+        .map_err(|err| format!("invalid RFC 3339 timestamp: {err}"))
+}
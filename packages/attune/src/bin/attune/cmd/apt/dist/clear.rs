@@ -0,0 +1,73 @@
+use clap::Args;
+use colored::Colorize;
+use inquire::Confirm;
+
+use crate::{
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+};
+use attune::server::repo::dist::clear::{ClearDistributionRequest, ClearDistributionResponse};
+
+#[derive(Args, Debug)]
+pub struct ClearArgs {
+    /// The repository containing the distribution.
+    #[arg(long)]
+    repo: String,
+    /// The name of the distribution to clear.
+    #[arg(long)]
+    distribution: String,
+    /// Also delete the distribution itself after removing its packages.
+    #[arg(long)]
+    delete_release: bool,
+    /// Skip confirmation prompt and proceed with clearing
+    #[arg(short, long)]
+    yes: bool,
+}
+
+pub async fn run(ctx: Config, args: ClearArgs) -> Result<String, String> {
+    println!("{}", format!(
+        "Warning: This will irreversibly remove all packages from distribution {:?} in repository {:?} and delete its published index files.",
+        args.distribution,
+        args.repo
+    ).red());
+
+    if !args.yes {
+        let confirmed = Confirm::new("Are you sure you want to proceed?")
+            .with_default(false)
+            .prompt()
+            .map_err(|e| format!("Confirmation failed: {e}"))?;
+        if !confirmed {
+            return Ok(String::from("Operation cancelled"));
+        }
+    }
+
+    let mut url = build_distribution_url(&ctx, &args.repo, Some(&args.distribution));
+    url.path_segments_mut()
+        .expect("Invalid URL construction")
+        .push("clear");
+    let response = ctx
+        .client
+        .post(url)
+        .json(
+            &ClearDistributionRequest::builder()
+                .delete_release(args.delete_release)
+                .build(),
+        )
+        .send()
+        .await
+        .map(handle_api_response::<ClearDistributionResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+
+    Ok(format!(
+        "Distribution {:?} cleared: removed {} packages and {} orphaned pool files{}",
+        args.distribution,
+        response.removed_packages,
+        response.orphaned_pool_files,
+        if response.release_deleted {
+            ", and deleted the distribution"
+        } else {
+            ""
+        }
+    ))
+}
@@ -1,10 +1,15 @@
+use std::time::Duration;
+
 use clap::Args;
 
 use crate::{
     cmd::apt::dist::{build_distribution_url, handle_api_response},
     config::Config,
 };
-use attune::server::repo::dist::create::{CreateDistributionRequest, CreateDistributionResponse};
+use attune::{
+    apt::Compression,
+    server::repo::dist::create::{CreateDistributionRequest, CreateDistributionResponse},
+};
 
 #[derive(Args, Debug)]
 pub struct CreateArgs {
@@ -31,6 +36,40 @@ pub struct CreateArgs {
     #[arg(long)]
     codename: Option<String>,
 
+    /// Compressed variants of each Packages index to publish, as a
+    /// comma-separated list (e.g. "gzip,xz"). Defaults to "gzip,xz". Pass the
+    /// flag without a value to publish only uncompressed indexes.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    index_compression: Option<Vec<Compression>>,
+
+    /// The component that `attune apt pkg add` adds packages to when
+    /// `--component` is not given. Defaults to "main".
+    #[arg(long)]
+    default_component: Option<String>,
+
+    /// Only publish SHA256 checksums, omitting the legacy MD5 checksums from
+    /// the Release file. Only use this if every client supports SHA256.
+    #[arg(long)]
+    sha256_only: bool,
+
+    /// How long the Release file is valid for after it's generated (e.g.
+    /// "7d"). If set, the Release file has a `Valid-Until` field, so that
+    /// clients reject it once it's stale.
+    #[arg(long, value_parser = attune::duration::parse)]
+    valid_for: Option<Duration>,
+
+    /// Set `NotAutomatic: yes` in the Release file, so that APT doesn't
+    /// install packages from the distribution unless they're pinned or
+    /// requested explicitly (e.g. for an `experimental` distribution).
+    #[arg(long)]
+    not_automatic: bool,
+
+    /// Set `ButAutomaticUpgrades: yes` in the Release file, so that packages
+    /// installed from a `--not-automatic` distribution are still upgraded
+    /// from it.
+    #[arg(long)]
+    but_automatic_upgrades: bool,
+
     /// Optional metadata for the distribution.
     #[command(flatten)]
     metadata: DistMetadata,
@@ -65,6 +104,12 @@ pub async fn run(ctx: Config, args: CreateArgs) -> Result<String, String> {
         .maybe_origin(args.metadata.origin)
         .maybe_label(args.metadata.label)
         .maybe_version(args.metadata.version)
+        .maybe_index_compression(args.index_compression)
+        .maybe_default_component(args.default_component)
+        .sha256_only(args.sha256_only)
+        .maybe_valid_for_seconds(args.valid_for.map(|valid_for| valid_for.as_secs()))
+        .not_automatic(args.not_automatic)
+        .but_automatic_upgrades(args.but_automatic_upgrades)
         .build();
 
     let url = build_distribution_url(&ctx, &args.repo, None);
@@ -1,10 +1,15 @@
+use std::time::Duration;
+
 use clap::Args;
 
 use crate::{
     cmd::apt::dist::{build_distribution_url, handle_api_response},
     config::Config,
 };
-use attune::server::repo::dist::edit::{EditDistributionRequest, EditDistributionResponse};
+use attune::{
+    apt::Compression,
+    server::repo::dist::edit::{EditDistributionRequest, EditDistributionResponse},
+};
 
 #[derive(Args, Debug)]
 pub struct EditArgs {
@@ -39,6 +44,47 @@ pub struct EditMetadata {
     /// Update the distribution's codename.
     #[arg(long)]
     codename: Option<String>,
+    /// Update the compressed variants of each Packages index to publish, as a
+    /// comma-separated list (e.g. "gzip,xz"). Pass the flag without a value
+    /// to publish only uncompressed indexes.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    index_compression: Option<Vec<Compression>>,
+    /// Update the architectures to always list in the Release file, even when
+    /// they have no packages, as a comma-separated list (e.g. "amd64,arm64").
+    /// Pass the flag without a value to only list architectures that have
+    /// packages.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    architectures: Option<Vec<String>>,
+    /// Update the components to always list in the Release file, even when
+    /// they have no packages, as a comma-separated list (e.g. "main,contrib").
+    /// Pass the flag without a value to only list components that have
+    /// packages.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    components: Option<Vec<String>>,
+    /// Update the component that `attune apt pkg add` adds packages to when
+    /// `--component` is not given.
+    #[arg(long)]
+    default_component: Option<String>,
+    /// Update whether to only publish SHA256 checksums, omitting the legacy
+    /// MD5 checksums from the Release file.
+    #[arg(long)]
+    sha256_only: Option<bool>,
+
+    /// Update how long the Release file is valid for after it's generated
+    /// (e.g. "7d"), by setting its `Valid-Until` field. Pass the flag without
+    /// a value to stop setting `Valid-Until`.
+    #[arg(long, num_args = 0..=1, value_parser = attune::duration::parse)]
+    valid_for: Option<Option<Duration>>,
+    /// Update whether to set `NotAutomatic: yes` in the Release file, so that
+    /// APT doesn't install packages from the distribution unless they're
+    /// pinned or requested explicitly.
+    #[arg(long)]
+    not_automatic: Option<bool>,
+    /// Update whether to set `ButAutomaticUpgrades: yes` in the Release file,
+    /// so that packages installed from a `NotAutomatic` distribution are still
+    /// upgraded from it.
+    #[arg(long)]
+    but_automatic_upgrades: Option<bool>,
 }
 
 pub async fn run(ctx: Config, args: EditArgs) -> Result<String, String> {
@@ -49,6 +95,18 @@ pub async fn run(ctx: Config, args: EditArgs) -> Result<String, String> {
         .maybe_version(args.metadata.version)
         .maybe_suite(args.metadata.suite)
         .maybe_codename(args.metadata.codename)
+        .maybe_index_compression(args.metadata.index_compression)
+        .maybe_declared_architectures(args.metadata.architectures)
+        .maybe_declared_components(args.metadata.components)
+        .maybe_default_component(args.metadata.default_component)
+        .maybe_sha256_only(args.metadata.sha256_only)
+        .maybe_valid_for_seconds(
+            args.metadata
+                .valid_for
+                .map(|valid_for| valid_for.map_or(0, |valid_for| valid_for.as_secs())),
+        )
+        .maybe_not_automatic(args.metadata.not_automatic)
+        .maybe_but_automatic_upgrades(args.metadata.but_automatic_upgrades)
         .build();
 
     if !request.any_some() {
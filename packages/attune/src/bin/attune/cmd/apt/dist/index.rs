@@ -0,0 +1,314 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+use percent_encoding::percent_encode;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    SignedGpgContent,
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+};
+use attune::{
+    api::PATH_SEGMENT_PERCENT_ENCODE_SET,
+    server::repo::{
+        dist::release::ReleaseFileResponse,
+        index::{
+            PackageChange, PackageChangeAction,
+            generate::{GenerateIndexRequest, GenerateIndexResponse},
+            sign::{SignIndexRequest, SignIndexResponse},
+        },
+    },
+};
+
+#[derive(Args, Debug)]
+pub struct IndexCommand {
+    #[command(subcommand)]
+    subcommand: IndexSubCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexSubCommand {
+    /// Generate a Release file to sign, and save it to a bundle file
+    ///
+    /// Without a package change, the bundle re-signs the distribution's
+    /// current Release file.
+    Export(ExportArgs),
+
+    /// Sign a bundle file
+    ///
+    /// This doesn't contact the API server, so it can run on a separate
+    /// machine that holds the signing key.
+    SignOffline(SignOfflineArgs),
+
+    /// Submit the signatures in a signed bundle file
+    ///
+    /// This fails if the distribution changed since the bundle was exported,
+    /// in which case the bundle must be exported and signed again.
+    Submit(SubmitArgs),
+}
+
+impl IndexCommand {
+    /// Whether the command runs without the API server.
+    pub fn is_offline(&self) -> bool {
+        matches!(self.subcommand, IndexSubCommand::SignOffline(_))
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// The repository containing the distribution.
+    #[arg(long)]
+    repo: String,
+    /// The distribution to change.
+    #[arg(long)]
+    distribution: String,
+    /// The component to change.
+    #[arg(long, default_value = "main")]
+    component: String,
+    /// The file to write the bundle to.
+    #[arg(long)]
+    out: PathBuf,
+
+    /// Add the uploaded package with this SHA256 sum.
+    #[arg(long, conflicts_with = "remove_package")]
+    add_package: Option<String>,
+    /// Replace a package with the same name, version, and architecture.
+    #[arg(long, requires = "add_package")]
+    replace: bool,
+    /// Remove the package with this name.
+    #[arg(long, requires_all = ["version", "architecture"])]
+    remove_package: Option<String>,
+    /// The version of the package to remove.
+    #[arg(long, requires = "remove_package")]
+    version: Option<String>,
+    /// The architecture of the package to remove.
+    #[arg(long, requires = "remove_package")]
+    architecture: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SignOfflineArgs {
+    /// The bundle file to sign, which is updated in place.
+    bundle: PathBuf,
+
+    /// GPG key ID to sign the indexes with (see `gpg --list-secret-keys`)
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SubmitArgs {
+    /// The signed bundle file to submit.
+    bundle: PathBuf,
+}
+
+/// A Release file to sign offline, along with the change it was generated
+/// for, so that the sign request can be rebuilt from the bundle alone.
+#[derive(Serialize, Deserialize, Debug)]
+struct IndexBundle {
+    change: PackageChange,
+    release: String,
+    release_ts: OffsetDateTime,
+    /// Set once the bundle has been signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signatures: Option<SignedGpgContent>,
+}
+
+impl IndexBundle {
+    fn read(path: &Path) -> Result<Self, String> {
+        let bundle = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read bundle {path:?}: {err}"))?;
+        serde_json::from_str(&bundle).map_err(|err| format!("Invalid bundle {path:?}: {err}"))
+    }
+
+    fn write(&self, path: &Path) -> Result<(), String> {
+        let bundle = serde_json::to_string_pretty(self).expect("bundle is serializable");
+        std::fs::write(path, bundle)
+            .map_err(|err| format!("Failed to write bundle {path:?}: {err}"))
+    }
+}
+
+pub async fn handle_index(ctx: Config, command: IndexCommand) -> Result<String, String> {
+    match command.subcommand {
+        IndexSubCommand::Export(args) => export(ctx, args).await,
+        IndexSubCommand::SignOffline(args) => sign_offline(ctx, args).await,
+        IndexSubCommand::Submit(args) => submit(ctx, args).await,
+    }
+}
+
+async fn export(ctx: Config, args: ExportArgs) -> Result<String, String> {
+    let action = match (args.add_package, args.remove_package) {
+        (Some(package_sha256sum), _) => PackageChangeAction::Add {
+            package_sha256sum,
+            replace: args.replace,
+        },
+        (None, Some(name)) => PackageChangeAction::Remove {
+            name,
+            version: args.version.expect("version is required"),
+            architecture: args.architecture.expect("architecture is required"),
+        },
+        (None, None) => PackageChangeAction::Resign,
+    };
+    let change = PackageChange {
+        repository: args.repo.clone(),
+        distribution: args.distribution.clone(),
+        component: args.component,
+        architecture: None,
+        action,
+    };
+
+    let bundle = if let PackageChangeAction::Resign = change.action {
+        // Re-signing signs the current Release file as-is.
+        let mut url = build_distribution_url(&ctx, &args.repo, Some(&args.distribution));
+        url.path_segments_mut()
+            .expect("Invalid URL construction")
+            .push("release");
+        let release = ctx
+            .client
+            .get(url)
+            .send()
+            .await
+            .map(handle_api_response::<ReleaseFileResponse>)
+            .map_err(|err| format!("Failed to send request: {err}"))?
+            .await?;
+        if release.contents.is_empty() {
+            return Err(format!(
+                "Distribution {:?} has not been published",
+                args.distribution
+            ));
+        }
+        IndexBundle {
+            change,
+            release: release.contents,
+            release_ts: OffsetDateTime::now_utc(),
+            signatures: None,
+        }
+    } else {
+        let request = GenerateIndexRequest {
+            change,
+            release_ts: None,
+        };
+        let generated = ctx
+            .client
+            .get(index_url(&ctx, &args.repo))
+            .json(&request)
+            .send()
+            .await
+            .map(handle_api_response::<GenerateIndexResponse>)
+            .map_err(|err| format!("Failed to send request: {err}"))?
+            .await?;
+        IndexBundle {
+            change: request.change,
+            release: generated.release,
+            release_ts: generated.release_ts,
+            signatures: None,
+        }
+    };
+
+    bundle.write(&args.out)?;
+    Ok(format!("Exported index bundle to {:?}", args.out))
+}
+
+async fn sign_offline(ctx: Config, args: SignOfflineArgs) -> Result<String, String> {
+    let mut bundle = IndexBundle::read(&args.bundle)?;
+    let signatures = ctx
+        .signing_backend
+        .sign(
+            args.gpg_home_dir.as_deref(),
+            &args.key_id,
+            bundle.release.clone(),
+        )
+        .await
+        .map_err(|err| format!("Failed to sign bundle: {err:#}"))?;
+    bundle.signatures = Some(signatures);
+    bundle.write(&args.bundle)?;
+    Ok(format!("Signed index bundle {:?}", args.bundle))
+}
+
+async fn submit(ctx: Config, args: SubmitArgs) -> Result<String, String> {
+    let bundle = IndexBundle::read(&args.bundle)?;
+    let Some(signatures) = bundle.signatures else {
+        return Err(format!(
+            "Bundle {:?} has not been signed; run `attune apt dist index sign-offline` first",
+            args.bundle
+        ));
+    };
+    let url = index_url(&ctx, &bundle.change.repository);
+    let res = ctx
+        .client
+        .post(url)
+        .json(&SignIndexRequest {
+            change: bundle.change,
+            release_ts: bundle.release_ts,
+            clearsigned: signatures.clearsigned,
+            detachsigned: signatures.detachsigned,
+            public_key_cert: signatures.public_key_cert,
+        })
+        .send()
+        .await
+        .map(handle_api_response::<SignIndexResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+    Ok(format!(
+        "Submitted index bundle {:?} signed with key(s) {}",
+        args.bundle,
+        res.fingerprints.join(", ")
+    ))
+}
+
+fn index_url(ctx: &Config, repository: &str) -> reqwest::Url {
+    ctx.url(&format!(
+        "/api/v0/repositories/{}/index",
+        percent_encode(repository.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+    ))
+    .expect("Invalid URL construction")
+}
+
+#[cfg(test)]
+mod tests {
+    use async_tempfile::TempFile;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn submit_requires_signatures() {
+        let file = TempFile::new().await.expect("failed to create temp file");
+        let path = file.file_path().to_path_buf();
+        IndexBundle {
+            change: PackageChange {
+                repository: String::from("repo"),
+                distribution: String::from("stable"),
+                component: String::new(),
+                architecture: None,
+                action: PackageChangeAction::Resign,
+            },
+            release: String::from("Origin: test\n"),
+            release_ts: OffsetDateTime::now_utc(),
+            signatures: None,
+        }
+        .write(&path)
+        .unwrap();
+
+        // The bundle is rejected before any request is sent, so the endpoint
+        // doesn't need to exist.
+        let ctx = Config::new("token", "http://localhost:1");
+        let err = submit(ctx, SubmitArgs { bundle: path })
+            .await
+            .expect_err("unsigned bundle should be rejected");
+        assert!(err.contains("has not been signed"), "{err}");
+    }
+}
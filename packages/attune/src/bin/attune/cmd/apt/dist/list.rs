@@ -1,17 +1,31 @@
 use clap::Args;
+use itertools::Itertools as _;
 use tabled::settings::Style;
 
 use crate::{
     cmd::apt::dist::{build_distribution_url, handle_api_response},
     config::Config,
+    template::Template,
 };
-use attune::server::repo::dist::list::ListDistributionsResponse;
+use attune::server::repo::dist::list::{Distribution, ListDistributionsResponse};
 
 #[derive(Args, Debug)]
 pub struct ListArgs {
     /// The name of the repository.
     #[arg(long)]
     repo: String,
+    /// Also show the components and architectures published in each
+    /// distribution.
+    #[arg(long, conflicts_with = "json")]
+    wide: bool,
+    /// Print the full distribution metadata as JSON.
+    #[arg(long)]
+    json: bool,
+    /// Print each distribution with a template such as `{distribution}
+    /// {suite}`, instead of a table. Placeholders name fields of the JSON
+    /// output.
+    #[arg(long, conflicts_with_all = ["json", "wide"], value_parser = Template::parse::<Distribution>)]
+    format: Option<Template>,
 }
 
 pub async fn run(ctx: Config, args: ListArgs) -> Result<String, String> {
@@ -25,6 +39,15 @@ pub async fn run(ctx: Config, args: ListArgs) -> Result<String, String> {
         .map_err(|err| format!("Failed to send request: {err}"))?
         .await?;
 
+    if args.json {
+        return serde_json::to_string_pretty(&response)
+            .map_err(|err| format!("Failed to serialize response: {err}"));
+    }
+
+    if let Some(format) = args.format {
+        return Ok(format.render_all(&response.distributions));
+    }
+
     if response.distributions.is_empty() {
         return Ok(format!(
             "No distributions found in repository {:?}",
@@ -33,7 +56,7 @@ pub async fn run(ctx: Config, args: ListArgs) -> Result<String, String> {
     }
 
     let mut builder = tabled::builder::Builder::new();
-    builder.push_record([
+    let header = [
         "Name",
         "Suite",
         "Codename",
@@ -41,9 +64,32 @@ pub async fn run(ctx: Config, args: ListArgs) -> Result<String, String> {
         "Origin",
         "Label",
         "Version",
-    ]);
+        "Index Compression",
+    ];
+    if args.wide {
+        builder.push_record(header.into_iter().chain([
+            "Components",
+            "Architectures",
+            "Checksums",
+            "Valid For",
+        ]));
+    } else {
+        builder.push_record(header);
+    }
     for dist in response.distributions {
-        builder.push_record([
+        let wide = [
+            dist.components.join(", "),
+            dist.architectures.join(", "),
+            String::from(if dist.sha256_only {
+                "SHA256"
+            } else {
+                "MD5Sum, SHA256"
+            }),
+            dist.valid_for_seconds
+                .map(format_valid_for)
+                .unwrap_or(String::from("(unset)")),
+        ];
+        let record = [
             dist.distribution,
             dist.suite,
             dist.codename,
@@ -51,10 +97,30 @@ pub async fn run(ctx: Config, args: ListArgs) -> Result<String, String> {
             dist.origin.unwrap_or(String::from("(unset)")),
             dist.label.unwrap_or(String::from("(unset)")),
             dist.version.unwrap_or(String::from("(unset)")),
-        ]);
+            if dist.index_compression.is_empty() {
+                String::from("(none)")
+            } else {
+                dist.index_compression.iter().join(", ")
+            },
+        ];
+        if args.wide {
+            builder.push_record(record.into_iter().chain(wide));
+        } else {
+            builder.push_record(record);
+        }
     }
 
     let mut table = builder.build();
     table.with(Style::modern());
     Ok(table.to_string())
 }
+
+/// Format a validity period in the largest unit that it's a whole number of,
+/// e.g. "7d".
+fn format_valid_for(seconds: u64) -> String {
+    [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")]
+        .into_iter()
+        .find(|(unit, _)| seconds % unit == 0)
+        .map(|(unit, suffix)| format!("{}{suffix}", seconds / unit))
+        .unwrap_or_else(|| format!("{seconds}s"))
+}
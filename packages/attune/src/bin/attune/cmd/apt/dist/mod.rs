@@ -5,11 +5,18 @@ use percent_encoding::percent_encode;
 use crate::config::Config;
 use attune::api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET};
 
+mod clear;
 mod create;
 mod delete;
 mod edit;
+mod index;
 mod list;
+mod promote;
+mod resign;
 mod resync;
+mod show;
+mod sources;
+mod sync;
 
 #[derive(Args, Debug)]
 pub struct DistCommand {
@@ -17,8 +24,6 @@ pub struct DistCommand {
     subcommand: DistSubCommand,
 }
 
-// TODO(#103): Add a command that allows us to do a no-op re-signing of the
-// index, potentially with a different key.
 #[derive(Subcommand, Debug)]
 pub enum DistSubCommand {
     /// Create a new distribution
@@ -26,12 +31,26 @@ pub enum DistSubCommand {
     /// For details on the meanings of distribution ("Release") metadata fields,
     /// see <https://wiki.debian.org/DebianRepository/Format>.
     #[command(visible_aliases = ["new", "add"])]
-    Create(create::CreateArgs),
+    Create(Box<create::CreateArgs>),
 
     /// Show information about distributions
     #[command(visible_alias = "ls")]
     List(list::ListArgs),
 
+    /// Show a distribution's stored index files
+    ///
+    /// This shows exactly the Release file or Packages index that is published
+    /// for the distribution, which is useful for debugging.
+    Show(show::ShowArgs),
+
+    /// Print an APT sources snippet for a distribution
+    ///
+    /// This prints a DEB822-style stanza that can be saved to
+    /// `/etc/apt/sources.list.d/<name>.sources` on clients. With
+    /// `--inline-key`, the public signing key is embedded in the stanza so
+    /// that no separate keyring file is needed.
+    Sources(sources::SourcesArgs),
+
     /// Edit distribution metadata
     ///
     /// For details on the meanings of distribution ("Release") metadata fields,
@@ -40,12 +59,48 @@ pub enum DistSubCommand {
     /// Note that this will not actually update the published Release file until
     /// the next time you publish a package.
     #[command(visible_alias = "set")]
-    Edit(edit::EditArgs),
+    Edit(Box<edit::EditArgs>),
 
     /// Delete a distribution
     #[command(visible_alias = "rm")]
     Delete(delete::DeleteArgs),
 
+    /// Remove all packages from a distribution
+    ///
+    /// This removes every package from the distribution at once and deletes its
+    /// published index files, rather than removing and re-signing packages one
+    /// at a time.
+    Clear(clear::ClearArgs),
+
+    /// Move packages from a staging distribution into another distribution
+    ///
+    /// Packages added with `attune apt pkg add --staging` are published to a
+    /// separate staging distribution. Once they have been reviewed, this moves
+    /// them into the distribution, re-signing both.
+    Promote(promote::PromoteArgs),
+
+    /// Sign index changes on a separate machine
+    ///
+    /// `export` generates a Release file and saves it to a bundle file,
+    /// `sign-offline` signs the bundle without contacting the API server, and
+    /// `submit` publishes the signed bundle. This keeps the signing key off of
+    /// machines with network access to the API server.
+    Index(index::IndexCommand),
+
+    /// Sign a distribution's current Release file again
+    ///
+    /// This doesn't change any packages. It's useful for rotating the signing
+    /// key: re-sign with the new key, and the published InRelease and
+    /// Release.gpg files are replaced with the new signatures.
+    Resign(resign::ResignArgs),
+
+    /// Check whether a distribution's published files match the database
+    ///
+    /// This lists the published objects that are missing or out of date. With
+    /// `--json`, it prints the result as JSON for monitoring. Either way, the
+    /// command fails if any object is inconsistent.
+    Sync(sync::SyncArgs),
+
     /// Resynchronize repository from database
     ///
     /// This is only useful for self-hosted instances. This is primarily for
@@ -53,18 +108,35 @@ pub enum DistSubCommand {
     Resync(resync::DistResyncCommand),
 }
 
+impl DistCommand {
+    /// Whether the command runs without the API server.
+    pub fn is_offline(&self) -> bool {
+        match &self.subcommand {
+            DistSubCommand::Index(command) => command.is_offline(),
+            _ => false,
+        }
+    }
+}
+
 pub async fn handle_dist(ctx: Config, command: DistCommand) -> Result<String, String> {
     match command.subcommand {
-        DistSubCommand::Create(args) => create::run(ctx, args).await,
+        DistSubCommand::Create(args) => create::run(ctx, *args).await,
         DistSubCommand::List(args) => list::run(ctx, args).await,
-        DistSubCommand::Edit(args) => edit::run(ctx, args).await,
+        DistSubCommand::Show(args) => show::run(ctx, args).await,
+        DistSubCommand::Sources(args) => sources::run(ctx, args).await,
+        DistSubCommand::Edit(args) => edit::run(ctx, *args).await,
         DistSubCommand::Delete(args) => delete::run(ctx, args).await,
+        DistSubCommand::Clear(args) => clear::run(ctx, args).await,
+        DistSubCommand::Promote(args) => promote::run(ctx, args).await,
+        DistSubCommand::Index(command) => index::handle_index(ctx, command).await,
+        DistSubCommand::Resign(args) => resign::run(ctx, args).await,
+        DistSubCommand::Sync(args) => sync::run(ctx, args).await,
         DistSubCommand::Resync(args) => resync::run(ctx, args).await,
     }
 }
 
 /// Build URL for distribution API endpoints
-fn build_distribution_url(
+pub fn build_distribution_url(
     config: &Config,
     repository: &str,
     distribution: Option<&str>,
@@ -80,14 +152,11 @@ fn build_distribution_url(
             percent_encode(repository.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
         ),
     };
-    config
-        .endpoint
-        .join(&path)
-        .expect("Invalid URL construction")
+    config.url(&path).expect("Invalid URL construction")
 }
 
 /// Handle API response, accounting for the structured error type.
-async fn handle_api_response<T>(response: reqwest::Response) -> Result<T, String>
+pub async fn handle_api_response<T>(response: reqwest::Response) -> Result<T, String>
 where
     T: for<'de> serde::Deserialize<'de>,
 {
@@ -0,0 +1,131 @@
+use clap::Args;
+
+use crate::{
+    cmd::apt::pkg::{
+        add::{PkgAddCommand, add_package_with_retry},
+        list::list_all,
+        remove::{PkgRemoveCommand, remove_package_with_retry},
+    },
+    config::Config,
+};
+use attune::server::{pkg::list::PackageListParams, repo::dist::staging_distribution};
+
+#[derive(Args, Debug)]
+pub struct PromoteArgs {
+    /// The repository containing the distributions.
+    #[arg(long)]
+    repo: String,
+    /// The distribution to promote packages into.
+    #[arg(long)]
+    to: String,
+    /// The distribution to promote packages from.
+    ///
+    /// Defaults to the staging distribution of `--to`, which is where
+    /// `attune apt pkg add --staging` adds packages.
+    #[arg(long)]
+    from: Option<String>,
+    /// Only promote packages in this component.
+    #[arg(long)]
+    component: Option<String>,
+
+    /// GPG key ID to sign the indexes with (see `gpg --list-secret-keys`)
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// Move every package from one distribution to another, in the same
+/// component, re-signing both distributions.
+///
+/// Packages are already stored in the repository pool, so this only changes
+/// which distributions list them. Each package is added to the destination
+/// before it is removed from the source, so a package is never missing from
+/// both if promotion fails partway through.
+pub async fn run(ctx: Config, args: PromoteArgs) -> Result<String, String> {
+    let from = args
+        .from
+        .clone()
+        .unwrap_or_else(|| staging_distribution(&args.to));
+    if from == args.to {
+        return Err(String::from("cannot promote a distribution into itself"));
+    }
+
+    let packages = list_all(
+        &ctx,
+        &PackageListParams {
+            repository: Some(args.repo.clone()),
+            distribution: Some(from.clone()),
+            component: args.component.clone(),
+            name: None,
+            version: None,
+            architecture: None,
+        },
+    )
+    .await
+    .map_err(|err| format!("API error: {}", err.message))?;
+    if packages.is_empty() {
+        return Ok(format!("No packages to promote from {from:?}"));
+    }
+
+    let mut failed = 0;
+    for package in &packages {
+        let add = PkgAddCommand::builder()
+            .repo(&args.repo)
+            .distribution(&args.to)
+            .component(&package.component)
+            .key_id(args.key_id.clone())
+            .maybe_gpg_home_dir(args.gpg_home_dir.clone())
+            // Unused, since the package has already been uploaded.
+            .package_file(String::new())
+            .build();
+        let remove = PkgRemoveCommand::builder()
+            .repo(&args.repo)
+            .distribution(&from)
+            .component(&package.component)
+            .key_id(args.key_id.clone())
+            .maybe_gpg_home_dir(args.gpg_home_dir.clone())
+            .package(&package.name)
+            .version(&package.version)
+            .architecture(&package.architecture)
+            .build();
+        let result = match add_package_with_retry(&ctx, &add, &package.sha256sum).await {
+            Ok(_) => remove_package_with_retry(&ctx, &remove).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => println!(
+                "Promoted {} {} ({}) to {}/{}",
+                package.name, package.version, package.architecture, args.to, package.component
+            ),
+            Err(error) => {
+                eprintln!(
+                    "Unable to promote {} {} ({}): {error:#}",
+                    package.name, package.version, package.architecture
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    let summary = format!(
+        "{} packages promoted from {from:?} to {:?}, {failed} failed",
+        packages.len() - failed,
+        args.to
+    );
+    if failed > 0 {
+        Err(summary)
+    } else {
+        Ok(summary)
+    }
+}
@@ -0,0 +1,109 @@
+use clap::Args;
+use time::OffsetDateTime;
+
+use crate::{
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+};
+use attune::{
+    api::PATH_SEGMENT_PERCENT_ENCODE_SET,
+    server::repo::{
+        dist::release::ReleaseFileResponse,
+        index::{
+            PackageChange, PackageChangeAction,
+            sign::{SignIndexRequest, SignIndexResponse},
+        },
+    },
+};
+use percent_encoding::percent_encode;
+
+#[derive(Args, Debug)]
+pub struct ResignArgs {
+    /// The repository containing the distribution.
+    #[arg(long)]
+    repo: String,
+    /// The distribution to re-sign.
+    #[arg(long)]
+    distribution: String,
+
+    /// GPG key ID to sign the indexes with (see `gpg --list-secret-keys`)
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// Sign the distribution's current Release file again, without changing any
+/// packages.
+pub async fn run(ctx: Config, args: ResignArgs) -> Result<String, String> {
+    let mut url = build_distribution_url(&ctx, &args.repo, Some(&args.distribution));
+    url.path_segments_mut()
+        .expect("Invalid URL construction")
+        .push("release");
+    let release = ctx
+        .client
+        .get(url)
+        .send()
+        .await
+        .map(handle_api_response::<ReleaseFileResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+    if release.contents.is_empty() {
+        return Err(format!(
+            "Distribution {:?} has not been published",
+            args.distribution
+        ));
+    }
+
+    let sig = ctx
+        .signing_backend
+        .sign(args.gpg_home_dir.as_deref(), &args.key_id, release.contents)
+        .await
+        .map_err(|err| format!("Failed to sign Release file: {err:#}"))?;
+
+    let url = ctx
+        .url(&format!(
+            "/api/v0/repositories/{}/index",
+            percent_encode(args.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+        ))
+        .expect("Invalid URL construction");
+    let res = ctx
+        .client
+        .post(url)
+        .json(&SignIndexRequest {
+            change: PackageChange {
+                repository: args.repo.clone(),
+                distribution: args.distribution.clone(),
+                // Re-signing doesn't change any component.
+                component: String::new(),
+                architecture: None,
+                action: PackageChangeAction::Resign,
+            },
+            // The Release file isn't regenerated, so its date is unchanged.
+            release_ts: OffsetDateTime::now_utc(),
+            clearsigned: sig.clearsigned,
+            detachsigned: sig.detachsigned,
+            public_key_cert: sig.public_key_cert,
+        })
+        .send()
+        .await
+        .map(handle_api_response::<SignIndexResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+
+    Ok(format!(
+        "Re-signed distribution {:?} with key(s) {}",
+        args.distribution,
+        res.fingerprints.join(", ")
+    ))
+}
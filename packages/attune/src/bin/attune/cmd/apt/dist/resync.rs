@@ -2,10 +2,13 @@ use axum::http::StatusCode;
 use clap::Args;
 use percent_encoding::percent_encode;
 
-use crate::config::Config;
+use crate::{cmd::apt::dist::sync::check, config::Config};
 use attune::{
     api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
-    server::repo::sync::resync::ResyncRepositoryResponse,
+    server::repo::sync::{
+        SyncScope,
+        resync::{ResyncParams, ResyncRepositoryResponse},
+    },
 };
 
 #[derive(Args, Debug)]
@@ -16,34 +19,90 @@ pub struct DistResyncCommand {
     /// The name of the distribution to resync.
     #[arg(long)]
     name: String,
+    /// Only resync the Packages indexes and packages of this component.
+    ///
+    /// The Release files are always resynced, since they cover every
+    /// component and architecture.
+    #[arg(long)]
+    component: Option<String>,
+    /// Only resync the Packages indexes and packages of this architecture.
+    ///
+    /// The Release files are always resynced, since they cover every
+    /// component and architecture.
+    #[arg(long)]
+    architecture: Option<String>,
+    /// Rewrite every object of the distribution, not only the inconsistent
+    /// ones.
+    ///
+    /// Use this after upgrading Attune to apply changes in how objects are
+    /// written to an existing repository.
+    #[arg(long, conflicts_with = "check")]
+    force: bool,
+    /// Only list the inconsistent objects and why each is inconsistent (e.g.
+    /// missing or corrupted), without rewriting them.
+    ///
+    /// Like `attune apt dist sync`, this fails if the distribution is
+    /// inconsistent.
+    #[arg(long)]
+    check: bool,
+    /// Print the objects that were rewritten (or with `--check`, the check
+    /// result) as JSON.
+    #[arg(long)]
+    json: bool,
 }
 
 // TODO: We should move this command behind an EE or self-hosted build of the
 // CLI, because it doesn't make sense for cloud-hosted users to see this
 // command.
 pub async fn run(ctx: Config, cmd: DistResyncCommand) -> Result<String, String> {
+    let scope = SyncScope {
+        component: cmd.component,
+        architecture: cmd.architecture,
+    };
+    if cmd.check {
+        return check(&ctx, &cmd.repo, &cmd.name, &scope, cmd.json).await;
+    }
+
     let res = ctx
         .client
-        .get(
-            ctx.endpoint
-                .join(&format!(
-                    "/api/v0/repositories/{}/distributions/{}/sync",
-                    percent_encode(cmd.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET),
-                    percent_encode(cmd.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                ))
-                .unwrap(),
+        .post(
+            ctx.url(&format!(
+                "/api/v0/repositories/{}/distributions/{}/sync",
+                percent_encode(cmd.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET),
+                percent_encode(cmd.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+            ))
+            .unwrap(),
         )
+        .query(&scope)
+        .query(&ResyncParams { force: cmd.force })
         .send()
         .await
         .expect("Could not send API request");
     match res.status() {
         StatusCode::OK => {
-            let _repo = res
+            let res = res
                 .json::<ResyncRepositoryResponse>()
                 .await
                 .expect("Could not parse response");
-            // TODO: Print something informative about what was resynchronized.
-            Ok(format!("Distribution {:?} resynced!", cmd.name))
+            if cmd.json {
+                return serde_json::to_string_pretty(&res)
+                    .map_err(|err| format!("Failed to serialize response: {err}"));
+            }
+            let status = res.status;
+            let rewritten = [
+                status.release,
+                status.release_clearsigned,
+                status.release_detachsigned,
+            ]
+            .into_iter()
+            .filter(|rewritten| *rewritten)
+            .count()
+                + status.packages_indexes.len()
+                + status.packages.len();
+            Ok(format!(
+                "Distribution {:?} resynced! {rewritten} objects rewritten",
+                cmd.name
+            ))
         }
         _ => {
             let error = res
@@ -0,0 +1,76 @@
+use clap::{ArgGroup, Args};
+
+use crate::{
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+};
+use attune::server::repo::dist::{packages::PackagesFileResponse, release::ReleaseFileResponse};
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("file").required(true).args(["release", "packages"])))]
+pub struct ShowArgs {
+    /// The repository containing the distribution.
+    #[arg(long)]
+    repo: String,
+    /// The name of the distribution to show.
+    #[arg(long)]
+    name: String,
+    /// Show the distribution's stored Release file.
+    #[arg(long)]
+    release: bool,
+    /// Show the stored Packages index of a component and architecture.
+    #[arg(long, requires_all = ["component", "architecture"])]
+    packages: bool,
+    /// The component of the Packages index to show.
+    #[arg(long, requires = "packages")]
+    component: Option<String>,
+    /// The architecture of the Packages index to show.
+    #[arg(long, requires = "packages")]
+    architecture: Option<String>,
+}
+
+pub async fn run(ctx: Config, args: ShowArgs) -> Result<String, String> {
+    let mut url = build_distribution_url(&ctx, &args.repo, Some(&args.name));
+    if args.release {
+        url.path_segments_mut()
+            .expect("Invalid URL construction")
+            .push("release");
+        let release = ctx
+            .client
+            .get(url)
+            .send()
+            .await
+            .map(handle_api_response::<ReleaseFileResponse>)
+            .map_err(|err| format!("Failed to send request: {err}"))?
+            .await?;
+        if release.contents.is_empty() {
+            return Err(format!(
+                "Distribution {:?} has not been published",
+                args.name
+            ));
+        }
+        return Ok(release.contents);
+    }
+
+    let component = args.component.expect("component is required");
+    let architecture = args.architecture.expect("architecture is required");
+    url.path_segments_mut()
+        .expect("Invalid URL construction")
+        .extend([
+            "components",
+            &component,
+            &format!("binary-{architecture}"),
+            "packages",
+        ]);
+    let packages = ctx
+        .client
+        .get(url)
+        .send()
+        .await
+        .map(handle_api_response::<PackagesFileResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+    // The index already ends with a newline, which would otherwise be doubled
+    // when printed.
+    Ok(packages.contents.trim_end().to_string())
+}
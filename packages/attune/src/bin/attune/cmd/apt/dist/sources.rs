@@ -0,0 +1,183 @@
+use clap::Args;
+
+use crate::{
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+    gpg_export_public_key,
+};
+use attune::server::repo::dist::list::ListDistributionsResponse;
+
+#[derive(Args, Debug)]
+pub struct SourcesArgs {
+    /// The repository containing the distribution.
+    #[arg(long)]
+    repo: String,
+    /// The name of the distribution.
+    #[arg(long)]
+    name: String,
+    /// The URL at which the repository is served.
+    #[arg(long)]
+    uri: String,
+    /// The components to include. Defaults to all of the distribution's
+    /// components.
+    #[arg(long)]
+    component: Vec<String>,
+
+    /// The path of the keyring that clients will verify the repository with.
+    #[arg(long, conflicts_with = "inline_key")]
+    keyring: Option<String>,
+    /// Embed the public signing key in the snippet instead of referring to a
+    /// keyring, so that the snippet is self-contained.
+    #[arg(long)]
+    inline_key: bool,
+    /// The GPG key ID whose public key is embedded with `--inline-key`.
+    #[arg(long, requires = "inline_key")]
+    key_id: Option<String>,
+    /// The GPG home directory containing the key embedded with `--inline-key`.
+    #[arg(long, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// How clients find the key that the repository is signed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SignedBy {
+    None,
+    Keyring(String),
+    Inline(String),
+}
+
+pub async fn run(ctx: Config, args: SourcesArgs) -> Result<String, String> {
+    let response = ctx
+        .client
+        .get(build_distribution_url(&ctx, &args.repo, None))
+        .send()
+        .await
+        .map(handle_api_response::<ListDistributionsResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+    let distribution = response
+        .distributions
+        .into_iter()
+        .find(|dist| dist.distribution == args.name)
+        .ok_or_else(|| format!("Distribution {:?} not found", args.name))?;
+
+    let components = if args.component.is_empty() {
+        distribution.components
+    } else {
+        args.component
+    };
+    if components.is_empty() {
+        return Err(format!(
+            "Distribution {:?} has no components, specify them with --component",
+            args.name
+        ));
+    }
+
+    let signed_by = if args.inline_key {
+        let key = gpg_export_public_key(args.gpg_home_dir, args.key_id)
+            .await
+            .map_err(|err| format!("Failed to export public key: {err:#}"))?;
+        SignedBy::Inline(key)
+    } else if let Some(keyring) = args.keyring {
+        SignedBy::Keyring(keyring)
+    } else {
+        SignedBy::None
+    };
+
+    Ok(sources_stanza(
+        &args.uri,
+        &distribution.distribution,
+        &components,
+        &signed_by,
+    ))
+}
+
+/// Render a DEB822-style `.sources` stanza for the distribution.
+fn sources_stanza(uri: &str, suite: &str, components: &[String], signed_by: &SignedBy) -> String {
+    let mut stanza = format!(
+        "Types: deb\nURIs: {uri}\nSuites: {suite}\nComponents: {}\n",
+        components.join(" ")
+    );
+    match signed_by {
+        SignedBy::None => {}
+        SignedBy::Keyring(path) => stanza.push_str(&format!("Signed-By: {path}\n")),
+        SignedBy::Inline(key) => {
+            // The key is a multiline field value: every line of it is indented
+            // by a space, and since an empty line would end the stanza, blank
+            // lines in the key (such as the one after the armor header) are
+            // written as a single `.`.
+            stanza.push_str("Signed-By:\n");
+            for line in key.trim().lines() {
+                let line = line.trim_end();
+                if line.is_empty() {
+                    stanza.push_str(" .\n");
+                } else {
+                    stanza.push_str(&format!(" {line}\n"));
+                }
+            }
+        }
+    }
+    // The CLI prints a trailing newline.
+    stanza.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use debian_packaging::control::{ControlFile, ControlParagraph};
+
+    use super::*;
+
+    const KEY: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmDMEZ0AAABYJKwYBBAHaRw8BAQdA\ntGV4YW1wbGU=\n=AbCd\n-----END PGP PUBLIC KEY BLOCK-----\n";
+
+    fn parse(stanza: &str) -> ControlParagraph<'static> {
+        let mut paragraphs = ControlFile::parse_str(stanza)
+            .unwrap()
+            .into_paragraphs()
+            .collect::<Vec<_>>();
+        assert_eq!(paragraphs.len(), 1, "stanza must be a single paragraph");
+        paragraphs.pop().unwrap()
+    }
+
+    #[test]
+    fn inline_key_parses_as_one_field() {
+        let components = [String::from("main"), String::from("contrib")];
+        let stanza = sources_stanza(
+            "https://example.com/repo",
+            "stable",
+            &components,
+            &SignedBy::Inline(String::from(KEY)),
+        );
+        let paragraph = parse(&stanza);
+        assert_eq!(paragraph.field_str("Suites"), Some("stable"));
+        assert_eq!(paragraph.field_str("Components"), Some("main contrib"));
+
+        // Every line of the key is present, in order, as a continuation line.
+        let signed_by = paragraph.field("Signed-By").unwrap();
+        let lines = signed_by.iter_lines().map(str::trim).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "-----BEGIN PGP PUBLIC KEY BLOCK-----",
+                ".",
+                "mDMEZ0AAABYJKwYBBAHaRw8BAQdA",
+                "tGV4YW1wbGU=",
+                "=AbCd",
+                "-----END PGP PUBLIC KEY BLOCK-----",
+            ]
+        );
+    }
+
+    #[test]
+    fn keyring_path() {
+        let stanza = sources_stanza(
+            "https://example.com/repo",
+            "stable",
+            &[String::from("main")],
+            &SignedBy::Keyring(String::from("/etc/apt/keyrings/example.asc")),
+        );
+        assert_eq!(
+            parse(&stanza).field_str("Signed-By"),
+            Some("/etc/apt/keyrings/example.asc")
+        );
+    }
+}
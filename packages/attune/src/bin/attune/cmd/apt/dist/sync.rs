@@ -0,0 +1,96 @@
+use clap::Args;
+
+use crate::{
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+};
+use attune::server::repo::sync::{SyncScope, check::CheckConsistencyResponse};
+
+#[derive(Args, Debug)]
+pub struct SyncArgs {
+    /// The repository containing the distribution.
+    #[arg(long)]
+    repo: String,
+    /// The name of the distribution to check.
+    #[arg(long)]
+    distribution: String,
+    /// Only check the Packages indexes and packages of this component.
+    #[arg(long)]
+    component: Option<String>,
+    /// Only check the Packages indexes and packages of this architecture.
+    #[arg(long)]
+    architecture: Option<String>,
+    /// Print the check result as JSON.
+    ///
+    /// The command still exits with an error if the distribution is
+    /// inconsistent, so it can be used for monitoring.
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(ctx: Config, args: SyncArgs) -> Result<String, String> {
+    let scope = SyncScope {
+        component: args.component,
+        architecture: args.architecture,
+    };
+    check(&ctx, &args.repo, &args.distribution, &scope, args.json).await
+}
+
+/// Check a distribution, listing each inconsistent object and why it is
+/// inconsistent. Fails if the distribution is inconsistent.
+pub(super) async fn check(
+    ctx: &Config,
+    repo: &str,
+    distribution: &str,
+    scope: &SyncScope,
+    json: bool,
+) -> Result<String, String> {
+    let mut url = build_distribution_url(ctx, repo, Some(distribution));
+    url.path_segments_mut()
+        .expect("Invalid URL construction")
+        .push("sync");
+    let response = ctx
+        .client
+        .get(url)
+        .query(scope)
+        .send()
+        .await
+        .map(handle_api_response::<CheckConsistencyResponse>)
+        .map_err(|err| format!("Failed to send request: {err}"))?
+        .await?;
+
+    let consistent = response.status.is_consistent();
+    let output = if json {
+        serde_json::to_string_pretty(&response)
+            .map_err(|err| format!("Failed to serialize response: {err}"))?
+    } else {
+        let mut lines = Vec::new();
+        if let Some(problem) = &response.release_date_problem {
+            lines.push(format!(
+                "Warning: the published Release file may be rejected by APT clients: {problem}"
+            ));
+        }
+        if consistent {
+            lines.push(format!("Distribution {distribution:?} is consistent"));
+        } else {
+            lines.push(format!("Distribution {distribution:?} is inconsistent:"));
+            for (object, reason) in response.status.objects() {
+                match reason {
+                    Some(reason) => lines.push(format!("  {object}: {reason}")),
+                    None => lines.push(format!("  {object}")),
+                }
+            }
+        }
+        lines.join("\n")
+    };
+    if consistent {
+        return Ok(output);
+    }
+
+    // Print the result even though the command fails, so that callers can
+    // see which objects are inconsistent.
+    println!("{output}");
+    Err(format!(
+        "distribution {distribution:?} is inconsistent, run `attune apt dist resync` to fix it"
+    ))
+}
@@ -31,6 +31,16 @@ pub enum AptSubcommand {
     Package(pkg::PkgCommand),
 }
 
+impl AptCommand {
+    /// Whether the command runs without the API server.
+    pub fn is_offline(&self) -> bool {
+        match &self.subcommand {
+            AptSubcommand::Distribution(dist) => dist.is_offline(),
+            _ => false,
+        }
+    }
+}
+
 pub async fn handle_apt(ctx: Config, command: AptCommand) -> ExitCode {
     match command.subcommand {
         AptSubcommand::Repository(repo) => repo::handle_repo(ctx, repo).await,
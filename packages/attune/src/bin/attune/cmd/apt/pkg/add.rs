@@ -1,31 +1,66 @@
-use std::process::ExitCode;
+use std::{
+    iter::once,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use crate::{config::Config, gpg_sign, retry_delay_default, retry_infinite};
+use crate::{
+    cmd::apt::pkg::{
+        batch::apply_changes_with_retry,
+        preview::preview_change,
+        progress::{self, ProgressBar},
+    },
+    config::Config,
+    retry_delay_default, retry_infinite,
+};
 
 use bon::Builder;
 use clap::Args;
 use color_eyre::eyre::{Context as _, Result, bail};
 use http::StatusCode;
 use percent_encoding::percent_encode;
-use reqwest::multipart::{self, Part};
+use reqwest::{
+    Url,
+    multipart::{self, Part},
+};
 use sha2::{Digest as _, Sha256};
+use time::OffsetDateTime;
+use tokio::{sync::Semaphore, task::JoinSet};
 use tracing::{debug, instrument};
 
 use attune::{
     api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
     server::{
-        pkg::{info::PackageInfoResponse, upload::PackageUploadResponse},
+        pkg::{
+            info::PackageInfoResponse,
+            reference::PackageReferenceRequest,
+            upload::{PackageUploadParams, PackageUploadResponse},
+        },
         repo::{
+            dist::{DEFAULT_COMPONENT, list::ListDistributionsResponse, staging_distribution},
             index::{
                 PackageChange, PackageChangeAction,
+                batch::MAX_BATCH_CHANGES,
                 generate::{GenerateIndexRequest, GenerateIndexResponse},
                 sign::{SignIndexRequest, SignIndexResponse},
             },
             info::RepositoryInfoResponse,
+            sync::{
+                InconsistentSummary, SyncScope,
+                check::{CheckConsistencyResponse, CheckParams},
+            },
         },
     },
 };
 
+/// How many times to try downloading a package from a URL before giving up.
+const MAX_DOWNLOAD_ATTEMPTS: usize = 5;
+
 #[derive(Args, Debug, Builder, Clone)]
 pub struct PkgAddCommand {
     /// Name of the repository to add the package to
@@ -37,32 +72,157 @@ pub struct PkgAddCommand {
     #[builder(into)]
     pub distribution: String,
     /// Component to add the package to
-    #[arg(long, short, default_value = "main")]
+    ///
+    /// If not set, the distribution's default component is used, or "main" if
+    /// the distribution doesn't have one.
+    #[arg(long, short)]
     #[builder(into)]
-    pub component: String,
+    pub component: Option<String>,
 
     /// GPG key ID to sign the index with (see `gpg --list-secret-keys`)
     ///
-    /// If not set and there is only one signing key available, that key will be
-    /// used. Otherwise, the command will fail.
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
     #[arg(long, short)]
-    #[builder(into)]
-    pub key_id: Option<String>,
+    #[builder(default)]
+    pub key_id: Vec<String>,
     /// GPG home directory to use for signing.
     ///
     /// If not set, defaults to the standard GPG home directory
     /// for the platform.
-    #[arg(long, short)]
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
     #[builder(into)]
     pub gpg_home_dir: Option<String>,
 
-    /// Path to the package to add
+    /// Add every `.deb` file under the given directory, including its
+    /// subdirectories.
+    ///
+    /// All packages are added to the same distribution and component. A
+    /// summary of which packages succeeded and failed is printed at the end.
+    #[arg(long, short = 'R')]
+    #[builder(default)]
+    pub recursive: bool,
+
+    /// When adding several packages, the maximum number of packages to upload
+    /// at once.
+    ///
+    /// Lower this on slow or unreliable networks, or for small servers.
+    #[arg(long, default_value_t = 4)]
+    #[builder(default = 4)]
+    pub parallel: usize,
+
+    /// Add the package to the `<distribution>-staging` distribution instead
+    /// of the distribution itself.
+    ///
+    /// Staged packages can be reviewed, and then moved into the distribution
+    /// with `attune apt dist promote`.
+    #[arg(long)]
+    #[builder(default)]
+    pub staging: bool,
+
+    /// Treat the package file as the key of a `.deb` object that is already in
+    /// the API server's S3 bucket, rather than a local path. The object must be
+    /// under your tenant's staging prefix, `staging/<tenant ID>/`.
+    ///
+    /// The server reads the package from S3 and copies it into place, so its
+    /// contents are never uploaded from this machine.
+    #[arg(long, conflicts_with = "recursive")]
+    #[builder(default)]
+    pub from_s3: bool,
+
+    /// If a package with the same name, version, and architecture is already
+    /// in the component, replace it with this one.
+    ///
+    /// The old package is removed and the new one added in a single signed
+    /// change, so the index never lacks both. Without this flag, adding a
+    /// different build of a package that is already in the component fails.
+    #[arg(long, visible_alias = "overwrite")]
+    #[builder(default)]
+    pub replace: bool,
+
+    /// After the package is added, wait until the published Release file in
+    /// S3 is the one that was just signed, so that clients can see the
+    /// package once the command exits.
+    #[arg(long)]
+    #[builder(default)]
+    pub wait_consistent: bool,
+
+    /// How long to wait for with `--wait-consistent`, in seconds, before
+    /// failing.
+    #[arg(long, default_value_t = 120, requires = "wait_consistent")]
+    #[builder(default = 120)]
+    pub wait_timeout: u64,
+
+    /// Date the Release file with this time, in seconds since the Unix epoch,
+    /// instead of the current time. Use this for reproducible builds.
+    ///
+    /// Pass `source-date-epoch` to use the time in the `SOURCE_DATE_EPOCH`
+    /// environment variable. That variable is only read when asked for here,
+    /// since build environments often set it for unrelated reasons.
+    ///
+    /// The server rejects dates in the future, or more than a year in the
+    /// past.
+    #[arg(long, value_name = "SECONDS", value_parser = parse_release_date)]
+    pub release_date: Option<OffsetDateTime>,
+
+    /// Upload the package and show which indexes adding it would change,
+    /// without signing or publishing anything.
+    #[arg(
+        long,
+        conflicts_with_all = ["recursive", "more_package_files", "wait_consistent"]
+    )]
+    #[builder(default)]
+    pub dry_run: bool,
+
+    /// Don't show a progress bar while the package uploads.
+    ///
+    /// The progress bar is only shown when a single package is added from an
+    /// interactive terminal.
+    #[arg(long, short)]
+    #[builder(default)]
+    pub quiet: bool,
+
+    /// Fail if the package doesn't have this SHA256 sum, e.g. to check a
+    /// package downloaded from a URL before it is uploaded.
+    #[arg(
+        long,
+        value_name = "SHA256",
+        conflicts_with_all = ["recursive", "more_package_files", "from_s3"]
+    )]
+    #[builder(into)]
+    pub expected_sha256: Option<String>,
+
+    /// Path to the package to add, or to a directory of packages when
+    /// `--recursive` is set
+    ///
+    /// An `http://` or `https://` URL is downloaded and then uploaded like a
+    /// local package.
     #[builder(into)]
     pub package_file: String,
+
+    /// More packages (or directories of packages, with `--recursive`) to add
+    ///
+    /// Packages added together are added to the index in one signed change,
+    /// rather than signing the index once per package.
+    #[builder(default)]
+    pub more_package_files: Vec<String>,
 }
 
 #[instrument]
 pub async fn run(ctx: Config, command: PkgAddCommand) -> ExitCode {
+    let command = if command.staging {
+        PkgAddCommand {
+            distribution: staging_distribution(&command.distribution),
+            staging: false,
+            ..command
+        }
+    } else {
+        command
+    };
+
     match validate_repository_exists(&ctx, &command).await {
         Ok(true) => {}
         Ok(false) => {
@@ -75,9 +235,226 @@ pub async fn run(ctx: Config, command: PkgAddCommand) -> ExitCode {
         }
     }
 
-    let sha256sum = match retry_infinite(
-        || upload_file_content(&ctx, &command),
+    let command = match resolve_component(&ctx, &command).await {
+        Ok(component) => PkgAddCommand {
+            component: Some(component),
+            ..command
+        },
+        Err(error) => {
+            eprintln!("Unable to resolve component: {error:#?}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let path = Path::new(&command.package_file);
+    if !command.more_package_files.is_empty() || (!command.from_s3 && path.is_dir()) {
+        let mut package_files = Vec::new();
+        for package_file in once(&command.package_file).chain(&command.more_package_files) {
+            let path = Path::new(package_file);
+            if command.from_s3 || !path.is_dir() {
+                package_files.push(path.to_path_buf());
+                continue;
+            }
+            if !command.recursive {
+                eprintln!(
+                    "Error: {package_file:?} is a directory; pass --recursive to add every package in it"
+                );
+                return ExitCode::FAILURE;
+            }
+            match find_package_files(path) {
+                Ok(found) if found.is_empty() => {
+                    eprintln!("Error: no .deb files found in {package_file:?}");
+                    return ExitCode::FAILURE;
+                }
+                Ok(found) => package_files.extend(found),
+                Err(error) => {
+                    eprintln!("Unable to read directory {package_file:?}: {error}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        return add_package_files(&ctx, &command, package_files).await;
+    }
+
+    if command.dry_run {
+        return match preview_package_file(&ctx, &command).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(message) => {
+                eprintln!("{message}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    match add_package_file(&ctx, &command).await {
+        Ok(sha256sum) => {
+            tracing::info!(?sha256sum, "package added to index");
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_release_date(value: &str) -> Result<OffsetDateTime, String> {
+    let seconds = if value == "source-date-epoch" {
+        std::env::var("SOURCE_DATE_EPOCH")
+            .map_err(|err| format!("could not read SOURCE_DATE_EPOCH: {err}"))?
+    } else {
+        value.to_string()
+    };
+    let seconds = seconds
+        .parse::<i64>()
+        .map_err(|err| format!("expected seconds since the Unix epoch: {err}"))?;
+    OffsetDateTime::from_unix_timestamp(seconds).map_err(|err| err.to_string())
+}
+
+/// Add several package files, printing a summary of the results.
+///
+/// Packages are uploaded concurrently, up to `--parallel` at a time, and then
+/// added to the index together, so that the index is only signed once. If the
+/// server rejects the batch, the packages are added one at a time instead, so
+/// that a package that fails doesn't stop the others.
+async fn add_package_files(
+    ctx: &Config,
+    command: &PkgAddCommand,
+    package_files: Vec<PathBuf>,
+) -> ExitCode {
+    let permits = Arc::new(Semaphore::new(command.parallel.max(1)));
+    let mut uploads = JoinSet::new();
+    for (index, package_file) in package_files.iter().enumerate() {
+        let ctx = ctx.clone();
+        // Concurrent uploads would draw over each other's progress bars.
+        let command = PkgAddCommand {
+            package_file: package_file.to_string_lossy().to_string(),
+            quiet: true,
+            ..command.clone()
+        };
+        let permits = permits.clone();
+        uploads.spawn(async move {
+            let _permit = permits
+                .acquire_owned()
+                .await
+                .expect("upload semaphore is never closed");
+            (index, upload_package_file(&ctx, &command).await)
+        });
+    }
+    let mut uploaded = vec![None; package_files.len()];
+    while let Some(upload) = uploads.join_next().await {
+        let (index, result) = upload.expect("upload task panicked");
+        uploaded[index] = Some(result);
+    }
+
+    let uploaded = uploaded
+        .into_iter()
+        .map(|upload| upload.expect("every upload task reports a result"))
+        .collect::<Vec<_>>();
+
+    let sha256sums = uploaded
+        .iter()
+        .filter_map(|upload| upload.as_ref().ok().cloned())
+        .collect::<Vec<_>>();
+    let batch = add_uploaded_packages(ctx, command, &sha256sums).await;
+    let mut results = Vec::with_capacity(package_files.len());
+    for (package_file, upload) in package_files.into_iter().zip(uploaded) {
+        let result = match (upload, &batch) {
+            (Err(message), _) => Err(message),
+            (Ok(sha256sum), Ok(())) => Ok(sha256sum),
+            (Ok(sha256sum), Err(BatchError::Rejected)) => {
+                let command = PkgAddCommand {
+                    package_file: package_file.to_string_lossy().to_string(),
+                    ..command.clone()
+                };
+                add_uploaded_package(ctx, &command, sha256sum).await
+            }
+            (Ok(_), Err(BatchError::Failed(message))) => Err(message.clone()),
+        };
+        match &result {
+            Ok(sha256sum) => tracing::info!(?package_file, ?sha256sum, "package added to index"),
+            Err(message) => eprintln!("{}: {message}", package_file.display()),
+        }
+        results.push((package_file, result));
+    }
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    let mut builder = tabled::builder::Builder::new();
+    builder.push_record(["Package File", "Result", "Details"]);
+    for (package_file, result) in &results {
+        let (status, details) = match result {
+            Ok(sha256sum) => ("added", sha256sum.as_str()),
+            Err(message) => ("failed", message.as_str()),
+        };
+        builder.push_record([
+            package_file.display().to_string(),
+            status.to_string(),
+            details.to_string(),
+        ]);
+    }
+    println!("{}", builder.build());
+    println!("{} packages added, {failed} failed", results.len() - failed);
+
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Find all `.deb` files under the directory, recursively, in a stable order.
+fn find_package_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut package_files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            package_files.extend(find_package_files(&path)?);
+        } else if path.extension().is_some_and(|extension| extension == "deb") {
+            package_files.push(path);
+        }
+    }
+    package_files.sort();
+    Ok(package_files)
+}
+
+/// Upload a single package file and add it to the index, returning its SHA256
+/// sum or a message describing why it couldn't be added.
+pub async fn add_package_file(ctx: &Config, command: &PkgAddCommand) -> Result<String, String> {
+    let sha256sum = upload_package_file(ctx, command).await?;
+    add_uploaded_package(ctx, command, sha256sum).await
+}
+
+/// Upload a single package file and show how adding it would change the
+/// index, without adding it.
+async fn preview_package_file(ctx: &Config, command: &PkgAddCommand) -> Result<(), String> {
+    let sha256sum = upload_package_file(ctx, command).await?;
+    preview_change(
+        ctx,
+        package_change(command, &sha256sum),
+        command.release_date,
+    )
+    .await
+    .map_err(|error| match error.downcast::<ErrorResponse>() {
+        Ok(res) => format!("Unable to preview change to index: {}", res.message),
+        Err(other) => format!("Unable to preview change to index: {other:#?}"),
+    })
+}
+
+/// Upload a single package file (or register it, with `--from-s3`), returning
+/// its SHA256 sum.
+async fn upload_package_file(ctx: &Config, command: &PkgAddCommand) -> Result<String, String> {
+    match retry_infinite(
+        || async {
+            if command.from_s3 {
+                register_s3_object(ctx, command).await
+            } else {
+                upload_file_content(ctx, command).await
+            }
+        },
         |error| match error.downcast_ref::<ErrorResponse>() {
+            // A different package with the same name, version, and
+            // architecture won't go away by retrying.
+            Some(res) if res.error == "PACKAGE_VERSION_CONFLICT" => false,
             Some(res) => match res.status {
                 StatusCode::CONFLICT => {
                     tracing::warn!(error = ?res, "retrying upload");
@@ -91,20 +468,137 @@ pub async fn run(ctx: Config, command: PkgAddCommand) -> ExitCode {
     )
     .await
     {
-        Ok(sha256sum) => sha256sum,
-        Err(error) => {
-            eprintln!("Unable to upload file content: {error:#?}");
-            return ExitCode::FAILURE;
-        }
-    };
+        Ok(sha256sum) => Ok(sha256sum),
+        Err(error) => Err(match error.downcast::<ErrorResponse>() {
+            Ok(res) if res.error == "PACKAGE_VERSION_CONFLICT" => format!(
+                "Unable to upload file content: {}\nUse --replace (or --overwrite) to replace it.",
+                res.message
+            ),
+            Ok(res) => format!("Unable to upload file content: {res:#?}"),
+            Err(other) => format!("Unable to upload file content: {other:#?}"),
+        }),
+    }
+}
 
+/// Add an uploaded package to the index, returning its SHA256 sum.
+async fn add_uploaded_package(
+    ctx: &Config,
+    command: &PkgAddCommand,
+    sha256sum: String,
+) -> Result<String, String> {
     // TODO: Check whether the package needs to be added to the index. If the
     // package already exists in the (release, distribution, component), we can
     // skip re-signing.
 
-    // Add the package to the index, retrying if needed.
-    let res = retry_infinite(
-        || add_package(&ctx, &command, &sha256sum),
+    let release_sha256 = match add_package_with_retry(ctx, command, &sha256sum).await {
+        Ok(release_sha256) => release_sha256,
+        Err(error) => {
+            return Err(match error.downcast::<ErrorResponse>() {
+                Ok(res) => match res.error.as_str() {
+                    "INVALID_COMPONENT_NAME" => format!(
+                        "Error: Invalid component name {:?}: {}\nComponent names must contain only letters, numbers, underscores, and hyphens.",
+                        command.component.as_deref().unwrap_or(DEFAULT_COMPONENT),
+                        res.message
+                    ),
+                    "PACKAGE_VERSION_CONFLICT" => format!(
+                        "Unable to add package to index: {}\nUse --replace to replace the existing package.",
+                        res.message
+                    ),
+                    _ => format!("Unable to add package to index: {}", res.message),
+                },
+                Err(other) => format!("Unable to add package to index: {other:#?}"),
+            });
+        }
+    };
+
+    if command.wait_consistent {
+        wait_consistent(ctx, command, &release_sha256)
+            .await
+            .map_err(|error| format!("Package was added, but {error:#}"))?;
+    }
+    Ok(sha256sum)
+}
+
+/// Why a batch of packages couldn't be added to the index.
+enum BatchError {
+    /// The server rejected the batch, most likely because of one of its
+    /// packages, so they should be added one at a time to find out which.
+    Rejected,
+    /// The batch failed for a reason that would fail each package too, such
+    /// as a signing error.
+    Failed(String),
+}
+
+/// Add uploaded packages to the index in as few signed changes as possible.
+async fn add_uploaded_packages(
+    ctx: &Config,
+    command: &PkgAddCommand,
+    sha256sums: &[String],
+) -> Result<(), BatchError> {
+    let mut release_sha256 = None;
+    for sha256sums in sha256sums.chunks(MAX_BATCH_CHANGES) {
+        let changes = sha256sums
+            .iter()
+            .map(|sha256sum| PackageChange {
+                repository: command.repo.clone(),
+                distribution: command.distribution.clone(),
+                component: command
+                    .component
+                    .clone()
+                    .unwrap_or_else(|| String::from(DEFAULT_COMPONENT)),
+                architecture: None,
+                action: PackageChangeAction::Add {
+                    package_sha256sum: sha256sum.clone(),
+                    replace: command.replace,
+                },
+            })
+            .collect::<Vec<_>>();
+        let applied = apply_changes_with_retry(
+            ctx,
+            &command.repo,
+            &changes,
+            command.release_date,
+            &command.key_id,
+            command.gpg_home_dir.as_deref(),
+        )
+        .await;
+        match applied {
+            Ok(sha256) => release_sha256 = Some(sha256),
+            Err(error) => {
+                return Err(match error.downcast::<ErrorResponse>() {
+                    Ok(res) => {
+                        tracing::warn!(error = ?res, "batch rejected, adding packages one at a time");
+                        BatchError::Rejected
+                    }
+                    Err(other) => {
+                        BatchError::Failed(format!("Unable to add package to index: {other:#?}"))
+                    }
+                });
+            }
+        }
+    }
+
+    if command.wait_consistent
+        && let Some(release_sha256) = release_sha256
+    {
+        wait_consistent(ctx, command, &release_sha256)
+            .await
+            .map_err(|error| BatchError::Failed(format!("Package was added, but {error:#}")))?;
+    }
+    Ok(())
+}
+
+/// Add an already-uploaded package to the index, retrying if the index was
+/// changed concurrently.
+///
+/// Returns the hex-encoded SHA256 sum of the signed Release file.
+pub async fn add_package_with_retry(
+    ctx: &Config,
+    command: &PkgAddCommand,
+    sha256sum: &str,
+) -> Result<String> {
+    retry_infinite(
+        || add_package(ctx, command, sha256sum),
         |error| match error.downcast_ref::<ErrorResponse>() {
             Some(res) => match res.error.as_str() {
                 "CONCURRENT_INDEX_CHANGE" | "DETACHED_SIGNATURE_VERIFICATION_FAILED" => {
@@ -117,32 +611,7 @@ pub async fn run(ctx: Config, command: PkgAddCommand) -> ExitCode {
         },
         retry_delay_default,
     )
-    .await;
-    match res {
-        Ok(_) => {
-            tracing::info!(?sha256sum, "package added to index");
-            ExitCode::SUCCESS
-        }
-        Err(error) => match error.downcast::<ErrorResponse>() {
-            Ok(res) => match res.error.as_str() {
-                "INVALID_COMPONENT_NAME" => {
-                    eprintln!(
-                        "Error: Invalid component name {:?}: {}\nComponent names must contain only letters, numbers, underscores, and hyphens.",
-                        command.component, res.message
-                    );
-                    ExitCode::FAILURE
-                }
-                _ => {
-                    eprintln!("Unable to add package to index: {}", res.message);
-                    ExitCode::FAILURE
-                }
-            },
-            Err(other) => {
-                eprintln!("Unable to add package to index: {other:#?}");
-                ExitCode::FAILURE
-            }
-        },
-    }
+    .await
 }
 
 /// Ensure that the specified repository exists.
@@ -152,15 +621,14 @@ pub async fn validate_repository_exists(ctx: &Config, cmd: &PkgAddCommand) -> Re
     let res = ctx
         .client
         .get(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}",
-                        percent_encode(cmd.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}",
+                    percent_encode(cmd.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .unwrap(),
+                .as_str(),
+            )
+            .unwrap(),
         )
         .send()
         .await
@@ -188,6 +656,55 @@ pub async fn validate_repository_exists(ctx: &Config, cmd: &PkgAddCommand) -> Re
     }
 }
 
+/// Resolve the component to add packages to: the one given on the command line,
+/// or else the distribution's default component.
+#[instrument(skip(ctx, cmd))]
+pub async fn resolve_component(ctx: &Config, cmd: &PkgAddCommand) -> Result<String> {
+    if let Some(component) = &cmd.component {
+        return Ok(component.clone());
+    }
+    let res = ctx
+        .client
+        .get(
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/distributions",
+                    percent_encode(cmd.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+                )
+                .as_str(),
+            )
+            .unwrap(),
+        )
+        .send()
+        .await
+        .context("send api request")?;
+    match res.status() {
+        StatusCode::OK => {
+            let res = res
+                .json::<ListDistributionsResponse>()
+                .await
+                .context("parse response")?;
+            // Distributions that don't exist yet are created when the package
+            // is added, so they have no default.
+            let component = res
+                .distributions
+                .into_iter()
+                .find(|dist| dist.distribution == cmd.distribution)
+                .and_then(|dist| dist.default_component)
+                .unwrap_or_else(|| String::from(DEFAULT_COMPONENT));
+            debug!(?component, "resolved component");
+            Ok(component)
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .context("parse error response")?;
+            bail!(error);
+        }
+    }
+}
+
 /// Checksum the package file, and upload if needed.
 //
 // TODO: We might want to make this streaming for sufficiently large package
@@ -199,17 +716,96 @@ pub async fn validate_repository_exists(ctx: &Config, cmd: &PkgAddCommand) -> Re
 #[instrument(skip(ctx, cmd))]
 pub async fn upload_file_content(ctx: &Config, cmd: &PkgAddCommand) -> Result<String> {
     debug!("uploading file content");
+    let content = match package_url(&cmd.package_file) {
+        Some(url) => download_package(url).await?,
+        None => std::fs::read(&cmd.package_file).context("read package file")?,
+    };
+    if let Some(expected) = &cmd.expected_sha256 {
+        let sha256sum = hex::encode(Sha256::digest(&content));
+        if !sha256sum.eq_ignore_ascii_case(expected.trim()) {
+            bail!("package has SHA256 sum {sha256sum}, but {expected} was expected");
+        }
+    }
+    let show_progress = !cmd.quiet && progress::enabled();
+    upload_content(ctx, content, cmd.replace, show_progress).await
+}
+
+/// The URL of a package file argument, if it is an `http://` or `https://`
+/// URL rather than a local path.
+fn package_url(package_file: &str) -> Option<Url> {
+    Url::parse(package_file)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Download a package, retrying if the server is unreachable or returns a
+/// server error.
+async fn download_package(url: Url) -> Result<Vec<u8>> {
+    // The API client sends the API token with every request, so downloads use
+    // their own client.
+    static HTTP: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+
+    let attempts = AtomicUsize::new(0);
+    retry_infinite(
+        || {
+            let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+            let url = url.clone();
+            async move {
+                debug!(%url, attempt, "downloading package");
+                let res = HTTP
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .and_then(|res| res.error_for_status());
+                let res = match res {
+                    Ok(res) => res.bytes().await,
+                    Err(err) => Err(err),
+                };
+                res.map(|content| content.to_vec())
+                    .map_err(|err| (attempt, err))
+            }
+        },
+        |(attempt, err)| {
+            let retryable = err.is_connect()
+                || err.is_timeout()
+                || err.is_body()
+                || err.status().is_some_and(|status| {
+                    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+                });
+            if retryable && *attempt < MAX_DOWNLOAD_ATTEMPTS {
+                tracing::warn!(%url, attempt, %err, "retrying download");
+                return true;
+            }
+            false
+        },
+        retry_delay_default,
+    )
+    .await
+    .map_err(|(_, err)| err)
+    .with_context(|| format!("download {url}"))
+}
 
+/// Upload package content if the server doesn't already have it, returning
+/// its SHA256 sum.
+///
+/// If `replace` is set, the server accepts the package even if it has a
+/// different package with the same name, version, and architecture. If
+/// `show_progress` is set, a progress bar is drawn while the content uploads.
+#[instrument(skip(ctx, content))]
+pub async fn upload_content(
+    ctx: &Config,
+    content: Vec<u8>,
+    replace: bool,
+    show_progress: bool,
+) -> Result<String> {
     debug!("calculating SHA256 sum");
-    let content = std::fs::read(&cmd.package_file).context("read package file")?;
     let sha256sum = hex::encode(Sha256::digest(&content).as_slice());
     debug!(?sha256sum, "calculated SHA256 sum");
 
     let res = ctx
         .client
         .get(
-            ctx.endpoint
-                .join(format!("/api/v0/packages/{sha256sum}").as_str())
+            ctx.url(format!("/api/v0/packages/{sha256sum}").as_str())
                 .unwrap(),
         )
         .send()
@@ -227,15 +823,24 @@ pub async fn upload_file_content(ctx: &Config, cmd: &PkgAddCommand) -> Result<St
         }
         StatusCode::NOT_FOUND => {
             debug!(?sha256sum, "package does not exist, uploading");
-            let multipart = multipart::Form::new().part("file", Part::bytes(content));
+            let bar = show_progress.then(|| Arc::new(ProgressBar::new(content.len() as u64)));
+            let part = match &bar {
+                Some(bar) => progress::part(content, bar.clone()),
+                None => Part::bytes(content),
+            };
+            let multipart = multipart::Form::new().part("file", part);
 
             let res = ctx
                 .client
-                .post(ctx.endpoint.join("/api/v0/packages").unwrap())
+                .post(ctx.url("/api/v0/packages").unwrap())
+                .query(&PackageUploadParams { replace })
                 .multipart(multipart)
                 .send()
-                .await
-                .context("send api request")?;
+                .await;
+            if let Some(bar) = bar {
+                bar.finish();
+            }
+            let res = res.context("send api request")?;
             match res.status() {
                 StatusCode::OK => {
                     let uploaded = res
@@ -264,32 +869,77 @@ pub async fn upload_file_content(ctx: &Config, cmd: &PkgAddCommand) -> Result<St
     }
 }
 
+/// Register a package that is already in the server's S3 bucket.
+#[instrument(skip(ctx, cmd))]
+pub async fn register_s3_object(ctx: &Config, cmd: &PkgAddCommand) -> Result<String> {
+    debug!(key = ?cmd.package_file, "registering package from S3");
+    let res = ctx
+        .client
+        .post(ctx.url("/api/v0/packages/reference").unwrap())
+        .json(&PackageReferenceRequest {
+            key: cmd.package_file.clone(),
+            replace: cmd.replace,
+        })
+        .send()
+        .await
+        .context("send api request")?;
+    match res.status() {
+        StatusCode::OK => {
+            let registered = res
+                .json::<PackageUploadResponse>()
+                .await
+                .context("parse response")?;
+            debug!(?registered, "package registered");
+            Ok(registered.sha256sum)
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .context("parse error response")?;
+            bail!(error);
+        }
+    }
+}
+
+/// The change that adds the package to the index.
+fn package_change(command: &PkgAddCommand, sha256sum: &str) -> PackageChange {
+    PackageChange {
+        repository: command.repo.clone(),
+        distribution: command.distribution.clone(),
+        component: command
+            .component
+            .clone()
+            .unwrap_or_else(|| String::from(DEFAULT_COMPONENT)),
+        architecture: None,
+        action: PackageChangeAction::Add {
+            package_sha256sum: sha256sum.to_string(),
+            replace: command.replace,
+        },
+    }
+}
+
 /// Generate an index for the package, and sign it.
+///
+/// Returns the hex-encoded SHA256 sum of the signed Release file.
 #[instrument]
-pub async fn add_package(ctx: &Config, command: &PkgAddCommand, sha256sum: &str) -> Result<()> {
+pub async fn add_package(ctx: &Config, command: &PkgAddCommand, sha256sum: &str) -> Result<String> {
     debug!(?sha256sum, repo = ?command.repo, distribution = ?command.distribution, component = ?command.component, "adding package to index");
     let generate_index_request = GenerateIndexRequest {
-        change: PackageChange {
-            repository: command.repo.clone(),
-            distribution: command.distribution.clone(),
-            component: command.component.clone(),
-            action: PackageChangeAction::Add {
-                package_sha256sum: sha256sum.to_string(),
-            },
-        },
+        change: package_change(command, sha256sum),
+        release_ts: command.release_date,
     };
     let res = ctx
         .client
         .get(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}/index",
-                        percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/index",
+                    percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .unwrap(),
+                .as_str(),
+            )
+            .unwrap(),
         )
         .json(&generate_index_request)
         .send()
@@ -314,28 +964,26 @@ pub async fn add_package(ctx: &Config, command: &PkgAddCommand, sha256sum: &str)
     };
 
     // Sign index locally.
-    let sig = gpg_sign(
-        command.gpg_home_dir.as_deref(),
-        command.key_id.as_deref(),
-        index,
-    )
-    .await
-    .context("sign index")?;
+    let release_sha256 = hex::encode(Sha256::digest(index.as_bytes()));
+    let sig = ctx
+        .signing_backend
+        .sign(command.gpg_home_dir.as_deref(), &command.key_id, index)
+        .await
+        .context("sign index")?;
 
     // Submit signatures.
     debug!("submitting signatures");
     let res = ctx
         .client
         .post(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}/index",
-                        percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/index",
+                    percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .unwrap(),
+                .as_str(),
+            )
+            .unwrap(),
         )
         .json(&SignIndexRequest {
             change: generate_index_request.change,
@@ -354,7 +1002,7 @@ pub async fn add_package(ctx: &Config, command: &PkgAddCommand, sha256sum: &str)
                 .await
                 .context("parse response")?;
             debug!("signed index");
-            Ok(())
+            Ok(release_sha256)
         }
         status => {
             let body = res.text().await.context("read response")?;
@@ -366,11 +1014,111 @@ pub async fn add_package(ctx: &Config, command: &PkgAddCommand, sha256sum: &str)
     }
 }
 
+/// The published objects of a distribution don't yet match what was signed.
+#[derive(Debug)]
+struct NotConsistent(InconsistentSummary);
+
+impl std::fmt::Display for NotConsistent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "published objects are not yet consistent: {:?}", self.0)
+    }
+}
+
+impl std::error::Error for NotConsistent {}
+
+/// Wait until the distribution's published Release file is the one with the
+/// given SHA256 sum, and its Packages indexes and packages are published.
+#[instrument(skip(ctx, command))]
+pub async fn wait_consistent(
+    ctx: &Config,
+    command: &PkgAddCommand,
+    release_sha256: &str,
+) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(command.wait_timeout);
+    let check = async || {
+        let res = ctx
+            .client
+            .get(
+                ctx.url(&format!(
+                    "/api/v0/repositories/{}/distributions/{}/sync",
+                    percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET),
+                    percent_encode(
+                        command.distribution.as_bytes(),
+                        PATH_SEGMENT_PERCENT_ENCODE_SET
+                    )
+                ))
+                .unwrap(),
+            )
+            .query(&SyncScope {
+                component: command.component.clone(),
+                architecture: None,
+            })
+            .query(&CheckParams {
+                release_sha256: Some(release_sha256.to_string()),
+            })
+            .send()
+            .await
+            .context("send api request")?;
+        match res.status() {
+            StatusCode::OK => {
+                let res = res
+                    .json::<CheckConsistencyResponse>()
+                    .await
+                    .context("parse response")?;
+                if res.status.is_consistent() {
+                    if let Some(problem) = &res.release_date_problem {
+                        eprintln!(
+                            "Warning: the published Release file may be rejected by APT clients: {problem}"
+                        );
+                    }
+                    Ok(())
+                } else {
+                    bail!(NotConsistent(res.status))
+                }
+            }
+            _ => {
+                let error = res
+                    .json::<ErrorResponse>()
+                    .await
+                    .context("parse error response")?;
+                bail!(error);
+            }
+        }
+    };
+    retry_infinite(
+        check,
+        |error| {
+            if error.downcast_ref::<NotConsistent>().is_none() {
+                return false;
+            }
+            if Instant::now() >= deadline {
+                tracing::warn!("timed out waiting for published objects to be consistent");
+                return false;
+            }
+            debug!(?error, "waiting for published objects to be consistent");
+            true
+        },
+        retry_delay_default,
+    )
+    .await
+    .with_context(|| {
+        format!(
+            "timed out after {}s waiting for it to be published",
+            command.wait_timeout
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use std::fs::read_dir;
+    use std::fs::{create_dir_all, read_dir, write};
+
+    use async_tempfile::TempDir;
 
-    use attune::testing::{AttuneTestServer, AttuneTestServerConfig, MIGRATOR, gpg_key_id};
+    use attune::{
+        server::{pkg::list::PackageListResponse, repo::dist::create::CreateDistributionRequest},
+        testing::{AttuneTestServer, AttuneTestServerConfig, MIGRATOR, gpg_key_id},
+    };
     use workspace_root::get_workspace_root;
 
     use super::*;
@@ -417,7 +1165,7 @@ mod tests {
                     .repo(REPO_NAME)
                     .distribution("test")
                     .component("test")
-                    .key_id(&key_id)
+                    .key_id(vec![key_id.clone()])
                     .gpg_home_dir(gpg_home_dir)
                     .package_file(fixture.to_string_lossy())
                     .build();
@@ -456,4 +1204,115 @@ mod tests {
             "at least one concurrent index change or detached signature verification error expected",
         );
     }
+
+    #[tokio::test]
+    async fn find_package_files_recursively() {
+        let dir = TempDir::new().await.expect("failed to create temp dir");
+        let root = dir.dir_path();
+        create_dir_all(root.join("amd64/nested")).unwrap();
+        create_dir_all(root.join("arm64")).unwrap();
+        for file in [
+            "amd64/a.deb",
+            "amd64/nested/b.deb",
+            "arm64/c.deb",
+            "arm64/c.deb.sha256",
+            "README",
+        ] {
+            write(root.join(file), b"").unwrap();
+        }
+
+        let found = find_package_files(root).expect("failed to find package files");
+        assert_eq!(
+            found,
+            vec![
+                root.join("amd64/a.deb"),
+                root.join("amd64/nested/b.deb"),
+                root.join("arm64/c.deb"),
+            ]
+        );
+    }
+
+    #[test]
+    fn package_url_schemes() {
+        assert!(package_url("https://example.com/releases/foo_1.0_amd64.deb").is_some());
+        assert!(package_url("http://localhost:8000/foo_1.0_amd64.deb").is_some());
+        assert!(package_url("ftp://example.com/foo_1.0_amd64.deb").is_none());
+        assert!(package_url("dist/foo_1.0_amd64.deb").is_none());
+        assert!(package_url("/tmp/foo_1.0_amd64.deb").is_none());
+    }
+
+    #[test_log::test(sqlx::test(migrator = "MIGRATOR"))]
+    async fn add_to_default_component(pool: sqlx::PgPool) {
+        let (key_id, _gpg, gpg_home_dir) = gpg_key_id().await.expect("failed to create GPG key");
+
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+
+        const REPO_NAME: &str = "add_to_default_component";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+        server
+            .http
+            .post(&format!("/api/v0/repositories/{REPO_NAME}/distributions"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(
+                &CreateDistributionRequest::builder()
+                    .name("stable")
+                    .suite("stable")
+                    .codename("stable")
+                    .default_component("contrib")
+                    .build(),
+            )
+            .await
+            .assert_status_ok();
+
+        let fixture = read_dir(get_workspace_root().join("scripts/fixtures"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.is_file())
+            .expect("no package fixtures found");
+        let ctx = Config::new(api_token.clone(), server.base_url.clone());
+        let command = PkgAddCommand::builder()
+            .repo(REPO_NAME)
+            .distribution("stable")
+            .key_id(vec![key_id.clone()])
+            .gpg_home_dir(gpg_home_dir.dir_path().to_string_lossy())
+            .package_file(fixture.to_string_lossy())
+            .build();
+
+        // Without `--component`, the distribution's default is used. Other
+        // distributions fall back to `main`.
+        let component = resolve_component(&ctx, &command).await.unwrap();
+        assert_eq!(component, "contrib");
+        let unstable = PkgAddCommand {
+            distribution: String::from("unstable"),
+            ..command.clone()
+        };
+        assert_eq!(
+            resolve_component(&ctx, &unstable).await.unwrap(),
+            DEFAULT_COMPONENT
+        );
+
+        let command = PkgAddCommand {
+            component: Some(component),
+            ..command
+        };
+        let sha256sum = upload_file_content(&ctx, &command).await.unwrap();
+        add_package(&ctx, &command, &sha256sum).await.unwrap();
+
+        let packages = server
+            .http
+            .get("/api/v0/packages")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .add_query_param("repository", REPO_NAME)
+            .add_query_param("distribution", "stable")
+            .await
+            .json::<PackageListResponse>();
+        assert_eq!(packages.packages.len(), 1);
+        assert_eq!(packages.packages[0].component, "contrib");
+    }
 }
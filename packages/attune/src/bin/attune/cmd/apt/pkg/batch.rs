@@ -0,0 +1,121 @@
+//! Applying several package changes to the index with a single signature.
+
+use color_eyre::eyre::{Context as _, Result, bail};
+use http::StatusCode;
+use percent_encoding::percent_encode;
+use sha2::{Digest as _, Sha256};
+use time::OffsetDateTime;
+use tracing::{debug, instrument};
+
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::index::{
+        PackageChange,
+        batch::{GenerateBatchIndexRequest, SignBatchIndexRequest},
+        generate::GenerateIndexResponse,
+        sign::SignIndexResponse,
+    },
+};
+
+use crate::{config::Config, retry_delay_default, retry_infinite};
+
+/// Apply a batch of changes to one component of one distribution, retrying if
+/// the index was changed concurrently.
+///
+/// Returns the hex-encoded SHA256 sum of the signed Release file.
+pub async fn apply_changes_with_retry(
+    ctx: &Config,
+    repo: &str,
+    changes: &[PackageChange],
+    release_ts: Option<OffsetDateTime>,
+    key_id: &[String],
+    gpg_home_dir: Option<&str>,
+) -> Result<String> {
+    retry_infinite(
+        || apply_changes(ctx, repo, changes, release_ts, key_id, gpg_home_dir),
+        |error| match error.downcast_ref::<ErrorResponse>() {
+            Some(res) => match res.error.as_str() {
+                "CONCURRENT_INDEX_CHANGE" | "DETACHED_SIGNATURE_VERIFICATION_FAILED" => {
+                    tracing::warn!(error = ?res, "retrying signature: concurrent index change");
+                    true
+                }
+                _ => false,
+            },
+            None => false,
+        },
+        retry_delay_default,
+    )
+    .await
+}
+
+/// Apply a batch of changes to one component of one distribution.
+///
+/// Returns the hex-encoded SHA256 sum of the signed Release file.
+#[instrument(skip(ctx, changes))]
+pub async fn apply_changes(
+    ctx: &Config,
+    repo: &str,
+    changes: &[PackageChange],
+    release_ts: Option<OffsetDateTime>,
+    key_id: &[String],
+    gpg_home_dir: Option<&str>,
+) -> Result<String> {
+    let url = ctx
+        .url(&format!(
+            "/api/v0/repositories/{}/index/batch",
+            percent_encode(repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+        ))
+        .context("join endpoint")?;
+
+    let res = ctx
+        .client
+        .get(url.clone())
+        .json(&GenerateBatchIndexRequest {
+            changes: changes.to_vec(),
+            release_ts,
+        })
+        .send()
+        .await
+        .context("send api request")?;
+    let generated = api_response::<GenerateIndexResponse>(res).await?;
+    debug!(index = ?generated.release, "generated index to sign");
+
+    let release_sha256 = hex::encode(Sha256::digest(generated.release.as_bytes()));
+    let sig = ctx
+        .signing_backend
+        .sign(gpg_home_dir, key_id, generated.release)
+        .await
+        .context("sign index")?;
+
+    debug!("submitting signatures");
+    let res = ctx
+        .client
+        .post(url)
+        .json(&SignBatchIndexRequest {
+            changes: changes.to_vec(),
+            release_ts: generated.release_ts,
+            clearsigned: sig.clearsigned,
+            detachsigned: sig.detachsigned,
+            public_key_cert: sig.public_key_cert,
+        })
+        .send()
+        .await
+        .context("send api request")?;
+    api_response::<SignIndexResponse>(res).await?;
+    debug!("signed index");
+    Ok(release_sha256)
+}
+
+/// Parse a successful API response, or the error response of a failed one.
+async fn api_response<T: serde::de::DeserializeOwned>(res: reqwest::Response) -> Result<T> {
+    match res.status() {
+        StatusCode::OK => res.json::<T>().await.context("parse response"),
+        status => {
+            let body = res.text().await.context("read response")?;
+            debug!(?body, ?status, "error response");
+            let error =
+                serde_json::from_str::<ErrorResponse>(&body).context("parse error response")?;
+            bail!(error);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+
+use crate::config::Config;
+use attune::{api::ErrorResponse, server::pkg::info::PackageInfoResponse};
+
+#[derive(Args, Debug)]
+pub struct PkgInfoCommand {
+    /// The hex-encoded SHA256 sum of the package file.
+    sha256sum: String,
+    /// Print the package information as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(ctx: Config, command: PkgInfoCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .get(
+            ctx.url(&format!("/api/v0/packages/{}", command.sha256sum))
+                .unwrap(),
+        )
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let package = res
+                .json::<PackageInfoResponse>()
+                .await
+                .expect("Could not parse response");
+            if command.json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&package).expect("Could not serialize response")
+                );
+                return ExitCode::SUCCESS;
+            }
+            let mut builder = tabled::builder::Builder::new();
+            builder.push_record(["Package", &package.package]);
+            builder.push_record(["Version", &package.version]);
+            builder.push_record(["Architecture", &package.architecture]);
+            builder.push_record(["SHA256", &command.sha256sum]);
+            builder.push_record(["Built-Using", package.built_using.as_deref().unwrap_or("-")]);
+            builder.push_record([
+                "Static-Built-Using",
+                package.static_built_using.as_deref().unwrap_or("-"),
+            ]);
+            let mut table = builder.build();
+            table.with(tabled::settings::Style::modern());
+            println!("{table}");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error getting package: {}", error.message);
+            ExitCode::FAILURE
+        }
+    }
+}
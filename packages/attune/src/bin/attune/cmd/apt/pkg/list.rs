@@ -3,14 +3,17 @@ use std::process::ExitCode;
 use axum::http::StatusCode;
 use clap::Args;
 
-use crate::config::Config;
+use crate::{config::Config, template::Template};
 use attune::{
-    api::ErrorResponse,
-    server::pkg::list::{PackageListParams, PackageListResponse},
+    api::{ErrorResponse, PageParams},
+    server::pkg::list::{Package, PackageListParams, PackageListResponse},
 };
 
 #[derive(Args, Debug)]
 pub struct PkgListCommand {
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
     #[arg(short, long)]
     repository: Option<String>,
     #[arg(short, long)]
@@ -23,29 +26,51 @@ pub struct PkgListCommand {
     version: Option<String>,
     #[arg(short, long)]
     architecture: Option<String>,
+    /// Print each package with a template such as `{name} {version}`, instead
+    /// of a table. Placeholders name fields of the JSON output.
+    #[arg(long, conflicts_with = "json", value_parser = Template::parse::<Package>)]
+    format: Option<Template>,
+    /// Only list this many packages, instead of every matching package.
+    #[arg(long)]
+    limit: Option<i64>,
 }
 
 pub async fn run(ctx: Config, command: PkgListCommand) -> ExitCode {
-    let res = ctx
-        .client
-        .get(ctx.endpoint.join("/api/v0/packages").unwrap())
-        .query(&PackageListParams {
-            repository: command.repository,
-            distribution: command.distribution,
-            component: command.component,
-            name: command.name,
-            version: command.version,
-            architecture: command.architecture,
-        })
-        .send()
-        .await
-        .expect("Could not send API request");
-    match res.status() {
-        StatusCode::OK => {
-            let packages = res
-                .json::<PackageListResponse>()
-                .await
-                .expect("Could not parse response");
+    let params = PackageListParams {
+        repository: command.repository,
+        distribution: command.distribution,
+        component: command.component,
+        name: command.name,
+        version: command.version,
+        architecture: command.architecture,
+    };
+    let res = match command.limit {
+        Some(limit) => {
+            let page = PageParams {
+                limit: Some(limit),
+                cursor: None,
+            };
+            list_page(&ctx, &params, &page).await
+        }
+        None => list_all(&ctx, &params)
+            .await
+            .map(|packages| PackageListResponse {
+                packages,
+                next_cursor: None,
+            }),
+    };
+    match res {
+        Ok(res) => {
+            if command.json {
+                println!("{}", serde_json::to_string_pretty(&res).unwrap());
+                return ExitCode::SUCCESS;
+            }
+            if let Some(format) = command.format {
+                for package in &res.packages {
+                    println!("{}", format.render(package));
+                }
+                return ExitCode::SUCCESS;
+            }
             let mut builder = tabled::builder::Builder::new();
             builder.push_record([
                 "Package",
@@ -55,7 +80,7 @@ pub async fn run(ctx: Config, command: PkgListCommand) -> ExitCode {
                 "Distribution",
                 "Component",
             ]);
-            for package in packages.packages {
+            for package in res.packages {
                 builder.push_record([
                     package.name,
                     package.version,
@@ -69,13 +94,52 @@ pub async fn run(ctx: Config, command: PkgListCommand) -> ExitCode {
             println!("{table}");
             ExitCode::SUCCESS
         }
-        _ => {
-            let error = res
-                .json::<ErrorResponse>()
-                .await
-                .expect("Could not parse error response");
+        Err(error) => {
             eprintln!("Error listing packages: {}", error.message);
             ExitCode::FAILURE
         }
     }
 }
+
+/// List every package matching the parameters, following cursors until the
+/// last page.
+pub async fn list_all(
+    ctx: &Config,
+    params: &PackageListParams,
+) -> Result<Vec<Package>, ErrorResponse> {
+    let mut packages = Vec::new();
+    let mut page = PageParams::default();
+    loop {
+        let res = list_page(ctx, params, &page).await?;
+        packages.extend(res.packages);
+        match res.next_cursor {
+            Some(cursor) => page.cursor = Some(cursor),
+            None => return Ok(packages),
+        }
+    }
+}
+
+async fn list_page(
+    ctx: &Config,
+    params: &PackageListParams,
+    page: &PageParams,
+) -> Result<PackageListResponse, ErrorResponse> {
+    let res = ctx
+        .client
+        .get(ctx.url("/api/v0/packages").unwrap())
+        .query(params)
+        .query(page)
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => Ok(res
+            .json::<PackageListResponse>()
+            .await
+            .expect("Could not parse response")),
+        _ => Err(res
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not parse error response")),
+    }
+}
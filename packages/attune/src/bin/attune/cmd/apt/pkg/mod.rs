@@ -1,12 +1,20 @@
-use std::process::ExitCode;
+use std::{cmp::Ordering, process::ExitCode};
 
 use clap::{Args, Subcommand};
 
 use crate::config::Config;
+use attune::apt::version::DebianVersion;
 
-mod add;
-mod list;
-mod remove;
+pub mod add;
+pub mod batch;
+mod info;
+pub mod list;
+mod preview;
+mod progress;
+mod promote;
+mod prune;
+pub mod remove;
+mod search;
 
 #[derive(Args, Debug)]
 pub struct PkgCommand {
@@ -16,21 +24,47 @@ pub struct PkgCommand {
 
 #[derive(Subcommand, Debug)]
 pub enum PkgSubCommand {
-    /// Upload a new package
+    /// Upload a new package, or a directory of packages
     #[command(visible_aliases = ["new", "upload"])]
     Add(add::PkgAddCommand),
     /// Show information about packages
     #[command(visible_alias = "ls")]
     List(list::PkgListCommand),
+    /// Show a package's metadata, including its build provenance
+    ///
+    /// Packages are identified by the SHA256 sum of their file. The output
+    /// includes the `Built-Using` and `Static-Built-Using` fields, which record
+    /// the source packages that the package was built against.
+    Info(info::PkgInfoCommand),
+    /// Add a package from one distribution to another, without uploading it
+    /// again
+    Promote(promote::PkgPromoteCommand),
+    /// Remove old versions of packages, keeping only the newest
+    Prune(prune::PkgPruneCommand),
     /// Remove a package
     #[command(visible_aliases = ["rm", "delete"])]
     Remove(remove::PkgRemoveCommand),
+    /// Find packages by name across all repositories
+    Search(search::PkgSearchCommand),
 }
 
 pub async fn handle_pkg(ctx: Config, command: PkgCommand) -> ExitCode {
     match command.subcommand {
         PkgSubCommand::Add(add) => add::run(ctx, add).await,
         PkgSubCommand::List(list) => list::run(ctx, list).await,
+        PkgSubCommand::Info(info) => info::run(ctx, info).await,
+        PkgSubCommand::Promote(promote) => promote::run(ctx, promote).await,
+        PkgSubCommand::Prune(prune) => prune::run(ctx, prune).await,
         PkgSubCommand::Remove(remove) => remove::run(ctx, remove).await,
+        PkgSubCommand::Search(search) => search::run(ctx, search).await,
+    }
+}
+
+/// Compare versions using Debian version ordering, falling back to string
+/// ordering for versions that can't be parsed.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (DebianVersion::parse(a), DebianVersion::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
     }
 }
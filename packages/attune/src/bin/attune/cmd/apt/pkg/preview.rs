@@ -0,0 +1,271 @@
+//! Previews of package changes, for `--dry-run`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use color_eyre::eyre::{Context as _, Result, bail};
+use debian_packaging::{
+    io::ContentDigest,
+    repository::release::{ChecksumType, ReleaseFile},
+};
+use http::StatusCode;
+use percent_encoding::percent_encode;
+use time::OffsetDateTime;
+use tracing::debug;
+
+use crate::{cmd::apt::repo::du::format_bytes, config::Config};
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::{
+        dist::release::ReleaseFileResponse,
+        index::{
+            PackageChange,
+            generate::{GenerateIndexRequest, GenerateIndexResponse},
+        },
+    },
+};
+
+/// How an index file listed in a Release file would change.
+#[derive(Debug, PartialEq, Eq)]
+enum IndexChange {
+    Added { size: u64 },
+    Changed { from: u64, to: u64 },
+    Removed { size: u64 },
+}
+
+/// Generate the Release file that the change would produce, and print how its
+/// indexes differ from the current Release file. Nothing is signed, so the
+/// change isn't applied.
+pub async fn preview_change(
+    ctx: &Config,
+    change: PackageChange,
+    release_ts: Option<OffsetDateTime>,
+) -> Result<()> {
+    let repository = percent_encode(
+        change.repository.as_bytes(),
+        PATH_SEGMENT_PERCENT_ENCODE_SET,
+    );
+    let distribution = percent_encode(
+        change.distribution.as_bytes(),
+        PATH_SEGMENT_PERCENT_ENCODE_SET,
+    );
+
+    let res = ctx
+        .client
+        .get(
+            ctx.url(
+                format!("/api/v0/repositories/{repository}/distributions/{distribution}/release")
+                    .as_str(),
+            )
+            .context("join endpoint")?,
+        )
+        .send()
+        .await
+        .context("send API request")?;
+    let current = match res.status() {
+        StatusCode::OK => {
+            res.json::<ReleaseFileResponse>()
+                .await
+                .context("parse response")?
+                .contents
+        }
+        // The change would create the distribution.
+        StatusCode::NOT_FOUND => String::new(),
+        status => {
+            let body = res.text().await.context("read response")?;
+            debug!(?body, ?status, "error response");
+            let error =
+                serde_json::from_str::<ErrorResponse>(&body).context("parse error response")?;
+            bail!(error);
+        }
+    };
+
+    let distribution = change.distribution.clone();
+    let res = ctx
+        .client
+        .get(
+            ctx.url(format!("/api/v0/repositories/{repository}/index").as_str())
+                .context("join endpoint")?,
+        )
+        .json(&GenerateIndexRequest { change, release_ts })
+        .send()
+        .await
+        .context("send API request")?;
+    let generated = match res.status() {
+        StatusCode::OK => {
+            res.json::<GenerateIndexResponse>()
+                .await
+                .context("parse response")?
+                .release
+        }
+        status => {
+            let body = res.text().await.context("read response")?;
+            debug!(?body, ?status, "error response");
+            let error =
+                serde_json::from_str::<ErrorResponse>(&body).context("parse error response")?;
+            bail!(error);
+        }
+    };
+
+    let changes = changed_indexes(&current, &generated)?;
+    println!("{}", format_changes(&distribution, &changes));
+    Ok(())
+}
+
+/// The index files whose entries differ between two Release files. An empty
+/// Release file has no index files.
+fn changed_indexes(current: &str, generated: &str) -> Result<BTreeMap<String, IndexChange>> {
+    let current = index_files(current).context("parse current Release file")?;
+    let generated = index_files(generated).context("parse generated Release file")?;
+
+    let mut changes = BTreeMap::new();
+    for (path, (digest, size)) in &generated {
+        match current.get(path) {
+            None => {
+                changes.insert(path.clone(), IndexChange::Added { size: *size });
+            }
+            Some((current_digest, current_size)) if current_digest != digest => {
+                changes.insert(
+                    path.clone(),
+                    IndexChange::Changed {
+                        from: *current_size,
+                        to: *size,
+                    },
+                );
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, (_, size)) in current {
+        if !generated.contains_key(&path) {
+            changes.insert(path, IndexChange::Removed { size });
+        }
+    }
+    Ok(changes)
+}
+
+/// The SHA256 sums and sizes of the index files listed in a Release file, by
+/// path.
+fn index_files(release: &str) -> Result<BTreeMap<String, (ContentDigest, u64)>> {
+    if release.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let release = ReleaseFile::from_reader(release.as_bytes())?;
+    let Some(entries) = release.iter_index_files(ChecksumType::Sha256) else {
+        return Ok(BTreeMap::new());
+    };
+    entries
+        .map(|entry| {
+            let entry = entry?;
+            Ok((entry.path.to_string(), (entry.digest, entry.size)))
+        })
+        .collect()
+}
+
+/// The architecture of the index at `path`, e.g. "amd64" for
+/// `main/binary-amd64/Packages.gz`.
+fn architecture(path: &str) -> Option<&str> {
+    path.split('/')
+        .find_map(|segment| segment.strip_prefix("binary-"))
+}
+
+fn format_changes(distribution: &str, changes: &BTreeMap<String, IndexChange>) -> String {
+    if changes.is_empty() {
+        return format!(
+            "Dry run: no indexes of distribution {distribution:?} would change. Nothing was signed or published."
+        );
+    }
+    let mut lines = vec![format!(
+        "Dry run: these indexes of distribution {distribution:?} would change:"
+    )];
+    for (path, change) in changes {
+        lines.push(match change {
+            IndexChange::Added { size } => format!("  added    {path} ({})", format_bytes(*size)),
+            IndexChange::Changed { from, to } => format!(
+                "  changed  {path} ({} -> {})",
+                format_bytes(*from),
+                format_bytes(*to)
+            ),
+            IndexChange::Removed { size } => {
+                format!("  removed  {path} ({})", format_bytes(*size))
+            }
+        });
+    }
+    let architectures = changes
+        .keys()
+        .filter_map(|path| architecture(path))
+        .collect::<BTreeSet<_>>();
+    if !architectures.is_empty() {
+        lines.push(format!(
+            "Architectures: {}",
+            architectures.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    lines.push(String::from("Nothing was signed or published."));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(entries: &[(&str, &str, u64)]) -> String {
+        let mut release = String::from("Origin: test\nSuite: stable\nCodename: stable\nSHA256:\n");
+        for (sha256sum, path, size) in entries {
+            release.push_str(&format!(" {sha256sum} {size} {path}\n"));
+        }
+        release
+    }
+
+    #[test]
+    fn preview_changed_indexes() {
+        let a = "a".repeat(64);
+        let b = "b".repeat(64);
+        let c = "c".repeat(64);
+        let current = release(&[
+            (&a, "main/binary-amd64/Packages", 100),
+            (&b, "main/binary-arm64/Packages", 200),
+            (&c, "contrib/binary-i386/Packages", 300),
+        ]);
+        let generated = release(&[
+            (&a, "main/binary-amd64/Packages", 100),
+            (&c, "main/binary-arm64/Packages", 2048),
+            (&a, "main/binary-riscv64/Packages", 400),
+        ]);
+
+        let changes = changed_indexes(&current, &generated).unwrap();
+        assert_eq!(
+            changes.into_iter().collect::<Vec<_>>(),
+            vec![
+                (
+                    String::from("contrib/binary-i386/Packages"),
+                    IndexChange::Removed { size: 300 }
+                ),
+                (
+                    String::from("main/binary-arm64/Packages"),
+                    IndexChange::Changed {
+                        from: 200,
+                        to: 2048
+                    }
+                ),
+                (
+                    String::from("main/binary-riscv64/Packages"),
+                    IndexChange::Added { size: 400 }
+                ),
+            ]
+        );
+
+        let changes = changed_indexes("", &generated).unwrap();
+        assert_eq!(
+            format_changes("stable", &changes),
+            [
+                "Dry run: these indexes of distribution \"stable\" would change:",
+                "  added    main/binary-amd64/Packages (100 B)",
+                "  added    main/binary-arm64/Packages (2.0 KiB)",
+                "  added    main/binary-riscv64/Packages (400 B)",
+                "Architectures: amd64, arm64, riscv64",
+                "Nothing was signed or published.",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! A progress bar for package uploads.
+
+use std::{
+    io::{IsTerminal as _, Write as _},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use reqwest::multipart::Part;
+
+use crate::cmd::apt::repo::du::format_bytes;
+
+/// How much of the package is handed to the HTTP client at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The minimum time between redraws of the progress bar.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The width of the bar itself, in characters.
+const BAR_WIDTH: usize = 30;
+
+/// Whether a progress bar should be drawn. It is only drawn for interactive
+/// use, so that it doesn't end up in logs or piped output.
+pub fn enabled() -> bool {
+    std::io::stdout().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// A progress bar drawn on a single line of stderr.
+pub struct ProgressBar {
+    total: u64,
+    sent: AtomicU64,
+    start: Instant,
+    last_draw: Mutex<Option<Instant>>,
+}
+
+impl ProgressBar {
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            sent: AtomicU64::new(0),
+            start: Instant::now(),
+            last_draw: Mutex::new(None),
+        }
+    }
+
+    /// Record that `bytes` more bytes were sent, redrawing the bar if it
+    /// hasn't been drawn recently.
+    fn inc(&self, bytes: u64) {
+        let sent = self.sent.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let mut last_draw = self.last_draw.lock().unwrap();
+        let now = Instant::now();
+        let due = last_draw.is_none_or(|last| now.duration_since(last) >= REDRAW_INTERVAL);
+        if due || sent >= self.total {
+            *last_draw = Some(now);
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r{}", self.render(sent, now - self.start));
+            let _ = stderr.flush();
+        }
+    }
+
+    /// Clear the bar, so that whatever is printed next starts on a clean line.
+    pub fn finish(&self) {
+        if self.last_draw.lock().unwrap().is_some() {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+        }
+    }
+
+    /// Render the bar, e.g. `[=====>    ]  45% 12.3 MiB / 27.1 MiB, 2.4 MiB/s`.
+    fn render(&self, sent: u64, elapsed: Duration) -> String {
+        let fraction = if self.total == 0 {
+            1.0
+        } else {
+            (sent as f64 / self.total as f64).min(1.0)
+        };
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let bar = if filled < BAR_WIDTH {
+            format!(
+                "{}>{}",
+                "=".repeat(filled),
+                " ".repeat(BAR_WIDTH - filled - 1)
+            )
+        } else {
+            "=".repeat(BAR_WIDTH)
+        };
+        let rate = match elapsed.as_secs_f64() {
+            secs if secs > 0.0 => format_bytes((sent as f64 / secs) as u64),
+            _ => format_bytes(0),
+        };
+        format!(
+            "[{bar}] {:>3}% {} / {}, {rate}/s",
+            (fraction * 100.0) as u64,
+            format_bytes(sent),
+            format_bytes(self.total),
+        )
+    }
+}
+
+/// A multipart part that uploads `content`, advancing `bar` as the HTTP client
+/// reads it.
+pub fn part(content: Vec<u8>, bar: Arc<ProgressBar>) -> Part {
+    let content = Bytes::from(content);
+    let length = content.len();
+    let chunks = (0..length).step_by(CHUNK_SIZE).map(move |start| {
+        let chunk = content.slice(start..length.min(start + CHUNK_SIZE));
+        bar.inc(chunk.len() as u64);
+        Ok::<_, std::io::Error>(chunk)
+    });
+    Part::stream_with_length(
+        reqwest::Body::wrap_stream(futures_util::stream::iter(chunks)),
+        length as u64,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_progress() {
+        let bar = ProgressBar::new(10 * 1024 * 1024);
+        assert_eq!(
+            bar.render(0, Duration::ZERO),
+            "[>                             ]   0% 0 B / 10.0 MiB, 0 B/s"
+        );
+        assert_eq!(
+            bar.render(5 * 1024 * 1024, Duration::from_secs(2)),
+            "[===============>              ]  50% 5.0 MiB / 10.0 MiB, 2.5 MiB/s"
+        );
+        assert_eq!(
+            bar.render(10 * 1024 * 1024, Duration::from_secs(4)),
+            "[==============================] 100% 10.0 MiB / 10.0 MiB, 2.5 MiB/s"
+        );
+    }
+}
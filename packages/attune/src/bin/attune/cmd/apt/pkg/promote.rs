@@ -0,0 +1,151 @@
+use std::process::ExitCode;
+
+use clap::Args;
+use tracing::info;
+
+use crate::{
+    cmd::apt::pkg::{
+        add::{PkgAddCommand, add_package_with_retry},
+        list::list_all,
+    },
+    config::Config,
+};
+use attune::{
+    api::ErrorResponse,
+    server::pkg::list::{Package, PackageListParams},
+};
+
+#[derive(Args, Debug)]
+pub struct PkgPromoteCommand {
+    /// Name of the repository containing both distributions
+    #[arg(long, short)]
+    repo: String,
+    /// Distribution to promote the package from
+    #[arg(long)]
+    from: String,
+    /// Distribution to promote the package into
+    #[arg(long)]
+    to: String,
+    /// Component of the package, in both distributions
+    #[arg(long, short)]
+    component: String,
+
+    /// Name of the package to promote
+    #[arg(long, short)]
+    package: String,
+    /// Version of the package to promote
+    #[arg(long, short)]
+    version: String,
+    /// Architecture of the package to promote
+    #[arg(long, short)]
+    architecture: String,
+
+    /// GPG key ID to sign the index with (see `gpg --list-secret-keys`).
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// Add a package that is already in one distribution to another distribution,
+/// reusing its existing pool object instead of uploading it again.
+pub async fn run(ctx: Config, command: PkgPromoteCommand) -> ExitCode {
+    if command.from == command.to {
+        eprintln!("Error promoting package: cannot promote a package into its own distribution");
+        return ExitCode::FAILURE;
+    }
+
+    let source = match find_package(&ctx, &command, &command.from).await {
+        Ok(Some(package)) => package,
+        Ok(None) => {
+            eprintln!(
+                "Error promoting package: {} {} ({}) is not in {}/{}",
+                command.package,
+                command.version,
+                command.architecture,
+                command.from,
+                command.component
+            );
+            return ExitCode::FAILURE;
+        }
+        Err(error) => {
+            eprintln!("Error promoting package: {error:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match find_package(&ctx, &command, &command.to).await {
+        Ok(Some(target)) if target.sha256sum == source.sha256sum => {
+            println!(
+                "{} {} ({}) is already in {}/{}",
+                command.package,
+                command.version,
+                command.architecture,
+                command.to,
+                command.component
+            );
+            return ExitCode::SUCCESS;
+        }
+        // A different build of the package is rejected when it's added below.
+        Ok(_) => {}
+        Err(error) => {
+            eprintln!("Error promoting package: {error:#}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let add = PkgAddCommand::builder()
+        .repo(&command.repo)
+        .distribution(&command.to)
+        .component(&command.component)
+        .key_id(command.key_id.clone())
+        .maybe_gpg_home_dir(command.gpg_home_dir.clone())
+        // Unused, since the package has already been uploaded.
+        .package_file(String::new())
+        .build();
+    match add_package_with_retry(&ctx, &add, &source.sha256sum).await {
+        Ok(_) => {
+            info!(sha256sum = ?source.sha256sum, "package promoted");
+            println!(
+                "Promoted {} {} ({}) from {} to {}/{}",
+                command.package,
+                command.version,
+                command.architecture,
+                command.from,
+                command.to,
+                command.component
+            );
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error promoting package: {error:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Find the package to promote in a distribution.
+async fn find_package(
+    ctx: &Config,
+    command: &PkgPromoteCommand,
+    distribution: &str,
+) -> Result<Option<Package>, ErrorResponse> {
+    let params = PackageListParams {
+        repository: Some(command.repo.clone()),
+        distribution: Some(distribution.to_string()),
+        component: Some(command.component.clone()),
+        name: Some(command.package.clone()),
+        version: Some(command.version.clone()),
+        architecture: Some(command.architecture.clone()),
+    };
+    Ok(list_all(ctx, &params).await?.into_iter().next())
+}
@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, process::ExitCode};
+
+use clap::Args;
+use tracing::info;
+
+use crate::{
+    cmd::apt::pkg::{batch::apply_changes_with_retry, compare_versions, list::list_all},
+    config::Config,
+};
+use attune::server::{
+    pkg::list::{Package, PackageListParams},
+    repo::index::{PackageChange, PackageChangeAction, batch::MAX_BATCH_CHANGES},
+};
+
+#[derive(Args, Debug)]
+pub struct PkgPruneCommand {
+    /// Name of the repository to prune
+    #[arg(long, short)]
+    repo: String,
+    /// Distribution to prune
+    #[arg(long, short)]
+    distribution: String,
+    /// Component to prune
+    #[arg(long, short)]
+    component: String,
+
+    /// Number of versions of each package to keep, for each architecture
+    ///
+    /// The highest versions are kept, in Debian version order.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    keep: u32,
+    /// Print the packages that would be removed, without removing them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// GPG key ID to sign the index with (see `gpg --list-secret-keys`).
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// Remove all but the highest `--keep` versions of each package.
+///
+/// The packages are removed with a single signature per batch. Pool files that
+/// are no longer in any index are deleted by the server, as with `remove`.
+pub async fn run(ctx: Config, command: PkgPruneCommand) -> ExitCode {
+    let params = PackageListParams {
+        repository: Some(command.repo.clone()),
+        distribution: Some(command.distribution.clone()),
+        component: Some(command.component.clone()),
+        name: None,
+        version: None,
+        architecture: None,
+    };
+    let packages = match list_all(&ctx, &params).await {
+        Ok(packages) => packages,
+        Err(error) => {
+            eprintln!("Error listing packages: {error:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let pruned = prune(packages, command.keep as usize);
+    if pruned.is_empty() {
+        println!("No packages to prune");
+        return ExitCode::SUCCESS;
+    }
+    if command.dry_run {
+        for package in &pruned {
+            println!(
+                "Would remove {} {} ({})",
+                package.name, package.version, package.architecture
+            );
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    for packages in pruned.chunks(MAX_BATCH_CHANGES) {
+        let changes = packages
+            .iter()
+            .map(|package| PackageChange {
+                repository: command.repo.clone(),
+                distribution: command.distribution.clone(),
+                component: command.component.clone(),
+                architecture: None,
+                action: PackageChangeAction::Remove {
+                    name: package.name.clone(),
+                    version: package.version.clone(),
+                    architecture: package.architecture.clone(),
+                },
+            })
+            .collect::<Vec<_>>();
+        let applied = apply_changes_with_retry(
+            &ctx,
+            &command.repo,
+            &changes,
+            None,
+            &command.key_id,
+            command.gpg_home_dir.as_deref(),
+        )
+        .await;
+        if let Err(error) = applied {
+            eprintln!("Error pruning packages: {error:#?}");
+            return ExitCode::FAILURE;
+        }
+        for package in packages {
+            info!(?package.name, ?package.version, ?package.architecture, "package pruned");
+            println!(
+                "Removed {} {} ({})",
+                package.name, package.version, package.architecture
+            );
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Find the packages to remove so that at most `keep` versions of each
+/// package remain for each architecture. Packages are sorted by name,
+/// architecture, and version.
+fn prune(packages: Vec<Package>, keep: usize) -> Vec<Package> {
+    let mut by_key = BTreeMap::<(String, String), Vec<Package>>::new();
+    for package in packages {
+        by_key
+            .entry((package.name.clone(), package.architecture.clone()))
+            .or_default()
+            .push(package);
+    }
+
+    let mut pruned = Vec::new();
+    for mut versions in by_key.into_values() {
+        versions.sort_by(|a, b| compare_versions(&b.version, &a.version));
+        let mut old = versions.split_off(keep.min(versions.len()));
+        old.reverse();
+        pruned.extend(old);
+    }
+    pruned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, architecture: &str) -> Package {
+        Package {
+            repository: String::from("repo"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            name: String::from(name),
+            version: String::from(version),
+            architecture: String::from(architecture),
+            sha256sum: String::new(),
+        }
+    }
+
+    #[test]
+    fn prune_keeps_highest_debian_versions() {
+        let packages = vec![
+            package("app", "1.10", "amd64"),
+            package("app", "1:0.1", "amd64"),
+            package("app", "1.9", "amd64"),
+            package("app", "1.10~rc1", "amd64"),
+            package("app", "1.9", "arm64"),
+            package("lib", "2.0", "amd64"),
+        ];
+        let pruned = prune(packages, 2)
+            .into_iter()
+            .map(|package| (package.name, package.version, package.architecture))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pruned,
+            [
+                (
+                    String::from("app"),
+                    String::from("1.9"),
+                    String::from("amd64")
+                ),
+                (
+                    String::from("app"),
+                    String::from("1.10~rc1"),
+                    String::from("amd64")
+                ),
+            ]
+        );
+    }
+}
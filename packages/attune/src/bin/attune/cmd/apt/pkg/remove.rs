@@ -16,7 +16,9 @@ use attune::{
     },
 };
 
-use crate::{config::Config, gpg_sign, retry_delay_default, retry_infinite};
+use crate::{
+    cmd::apt::pkg::preview::preview_change, config::Config, retry_delay_default, retry_infinite,
+};
 
 #[derive(Args, Debug, Builder)]
 pub struct PkgRemoveCommand {
@@ -35,16 +37,19 @@ pub struct PkgRemoveCommand {
 
     /// GPG key ID to sign the index with (see `gpg --list-secret-keys`).
     ///
-    /// If not set and there is only one signing key available, that key will be
-    /// used. Otherwise, the command will fail.
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
     #[arg(long, short)]
-    #[builder(into)]
-    key_id: Option<String>,
+    #[builder(default)]
+    key_id: Vec<String>,
     /// GPG home directory to use for signing.
     ///
     /// If not set, defaults to the standard GPG home directory
     /// for the platform.
-    #[arg(long, short)]
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
     #[builder(into)]
     gpg_home_dir: Option<String>,
 
@@ -60,11 +65,44 @@ pub struct PkgRemoveCommand {
     #[arg(long, short)]
     #[builder(into)]
     architecture: String,
+
+    /// Show which indexes removing the package would change, without signing
+    /// or publishing anything.
+    #[arg(long)]
+    #[builder(default)]
+    dry_run: bool,
 }
 
 pub async fn run(ctx: Config, command: PkgRemoveCommand) -> ExitCode {
-    let res = retry_infinite(
-        || remove_package(&ctx, &command),
+    if command.dry_run {
+        return match preview_change(&ctx, package_change(&command), None).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("Error previewing package removal: {error:#?}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let res = remove_package_with_retry(&ctx, &command).await;
+
+    match res {
+        Ok(_) => {
+            info!(?command.package, "package removed from index");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error removing package from index: {error:#?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Remove a package from the index, retrying if the index was changed
+/// concurrently.
+pub async fn remove_package_with_retry(ctx: &Config, command: &PkgRemoveCommand) -> Result<()> {
+    retry_infinite(
+        || remove_package(ctx, command),
         |error| match error.downcast_ref::<ErrorResponse>() {
             Some(res) => match res.error.as_str() {
                 "CONCURRENT_INDEX_CHANGE" | "DETACHED_SIGNATURE_VERIFICATION_FAILED" => {
@@ -77,17 +115,21 @@ pub async fn run(ctx: Config, command: PkgRemoveCommand) -> ExitCode {
         },
         retry_delay_default,
     )
-    .await;
+    .await
+}
 
-    match res {
-        Ok(_) => {
-            info!(?command.package, "package removed from index");
-            ExitCode::SUCCESS
-        }
-        Err(error) => {
-            eprintln!("Error removing package from index: {error:#?}");
-            ExitCode::FAILURE
-        }
+/// The change that removes the package from the index.
+fn package_change(command: &PkgRemoveCommand) -> PackageChange {
+    PackageChange {
+        repository: command.repo.clone(),
+        distribution: command.distribution.clone(),
+        component: command.component.clone(),
+        architecture: None,
+        action: PackageChangeAction::Remove {
+            name: command.package.clone(),
+            version: command.version.clone(),
+            architecture: command.architecture.clone(),
+        },
     }
 }
 
@@ -95,29 +137,20 @@ pub async fn run(ctx: Config, command: PkgRemoveCommand) -> ExitCode {
 pub async fn remove_package(ctx: &Config, command: &PkgRemoveCommand) -> Result<()> {
     debug!("removing package from index");
     let generate_index_request = GenerateIndexRequest {
-        change: PackageChange {
-            repository: command.repo.clone(),
-            distribution: command.distribution.clone(),
-            component: command.component.clone(),
-            action: PackageChangeAction::Remove {
-                name: command.package.clone(),
-                version: command.version.clone(),
-                architecture: command.architecture.clone(),
-            },
-        },
+        change: package_change(command),
+        release_ts: None,
     };
     let res = ctx
         .client
         .get(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}/index",
-                        percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/index",
+                    percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .context("join endpoint")?,
+                .as_str(),
+            )
+            .context("join endpoint")?,
         )
         .json(&generate_index_request)
         .send()
@@ -142,28 +175,25 @@ pub async fn remove_package(ctx: &Config, command: &PkgRemoveCommand) -> Result<
     };
 
     // Sign index locally.
-    let sig = gpg_sign(
-        command.gpg_home_dir.as_deref(),
-        command.key_id.as_deref(),
-        index,
-    )
-    .await
-    .context("sign index")?;
+    let sig = ctx
+        .signing_backend
+        .sign(command.gpg_home_dir.as_deref(), &command.key_id, index)
+        .await
+        .context("sign index")?;
 
     // Submit signatures.
     debug!("submitting signatures");
     let res = ctx
         .client
         .post(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}/index",
-                        percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/index",
+                    percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .unwrap(),
+                .as_str(),
+            )
+            .unwrap(),
         )
         .json(&SignIndexRequest {
             change: generate_index_request.change,
@@ -243,7 +273,7 @@ mod tests {
                 .repo(REPO_NAME)
                 .distribution("test")
                 .component("test")
-                .key_id(&key_id)
+                .key_id(vec![key_id.clone()])
                 .gpg_home_dir(gpg_home_dir.dir_path().to_string_lossy())
                 .package_file(fixture.to_string_lossy())
                 .build();
@@ -258,7 +288,7 @@ mod tests {
 
         let res = ctx
             .client
-            .get(ctx.endpoint.join("/api/v0/packages").unwrap())
+            .get(ctx.url("/api/v0/packages").unwrap())
             .query(&PackageListParams {
                 repository: Some(REPO_NAME.to_string()),
                 distribution: Some("test".to_string()),
@@ -296,7 +326,7 @@ mod tests {
                     .repo(REPO_NAME)
                     .distribution("test")
                     .component("test")
-                    .key_id(key_id)
+                    .key_id(vec![key_id])
                     .gpg_home_dir(gpg_home_dir.dir_path().to_string_lossy())
                     .package(pkg.name)
                     .version(pkg.version)
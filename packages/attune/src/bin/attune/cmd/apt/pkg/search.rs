@@ -0,0 +1,121 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+
+use crate::{config::Config, template::Template};
+use attune::{
+    api::ErrorResponse,
+    server::pkg::{
+        list::Package,
+        search::{PackageSearchParams, PackageSearchResponse},
+    },
+};
+
+#[derive(Args, Debug)]
+pub struct PkgSearchCommand {
+    /// Find packages whose name contains this string, across all
+    /// repositories.
+    query: String,
+    /// Only find packages named exactly QUERY.
+    #[arg(long)]
+    exact: bool,
+    #[arg(short, long)]
+    version: Option<String>,
+    #[arg(short, long)]
+    architecture: Option<String>,
+    #[arg(short, long)]
+    distribution: Option<String>,
+    /// The maximum number of results to show.
+    #[arg(long)]
+    limit: Option<i64>,
+    /// The number of results to skip, for showing later pages.
+    #[arg(long)]
+    offset: Option<i64>,
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
+    /// Print each package with a template such as `{name} {version}`, instead
+    /// of a table. Placeholders name fields of the JSON output.
+    #[arg(long, conflicts_with = "json", value_parser = Template::parse::<Package>)]
+    format: Option<Template>,
+}
+
+pub async fn run(ctx: Config, command: PkgSearchCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .get(ctx.url("/api/v0/packages/search").unwrap())
+        .query(&PackageSearchParams {
+            name: command.query,
+            exact: command.exact,
+            version: command.version,
+            architecture: command.architecture,
+            distribution: command.distribution,
+            limit: command.limit,
+            offset: command.offset,
+        })
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let response = res
+                .json::<PackageSearchResponse>()
+                .await
+                .expect("Could not parse response");
+            if command.json {
+                println!("{}", serde_json::to_string_pretty(&response).unwrap());
+                return ExitCode::SUCCESS;
+            }
+            if let Some(format) = command.format {
+                for package in &response.packages {
+                    println!("{}", format.render(package));
+                }
+                // Keep stdout to one line per package for scripts.
+                if let Some(next_offset) = response.next_offset {
+                    eprintln!(
+                        "More results are available, use `--offset {next_offset}` to show them"
+                    );
+                }
+                return ExitCode::SUCCESS;
+            }
+            if response.packages.is_empty() {
+                println!("No matching packages found");
+                return ExitCode::SUCCESS;
+            }
+            let mut builder = tabled::builder::Builder::new();
+            builder.push_record([
+                "Package",
+                "Version",
+                "Architecture",
+                "Repository",
+                "Distribution",
+                "Component",
+            ]);
+            for package in response.packages {
+                builder.push_record([
+                    package.name,
+                    package.version,
+                    package.architecture,
+                    package.repository,
+                    package.distribution,
+                    package.component,
+                ]);
+            }
+            let table = builder.build();
+            println!("{table}");
+            if let Some(next_offset) = response.next_offset {
+                println!("More results are available, use `--offset {next_offset}` to show them");
+            }
+            ExitCode::SUCCESS
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error searching packages: {}", error.message);
+            ExitCode::FAILURE
+        }
+    }
+}
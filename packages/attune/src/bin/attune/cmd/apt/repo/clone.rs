@@ -0,0 +1,155 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+use percent_encoding::percent_encode;
+
+use crate::{
+    cmd::apt::pkg::{
+        add::{PkgAddCommand, add_package_with_retry},
+        list::list_all,
+    },
+    config::Config,
+};
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::{
+        pkg::list::PackageListParams,
+        repo::clone::{CloneRepositoryRequest, CloneRepositoryResponse},
+    },
+};
+
+#[derive(Args, Debug)]
+pub struct RepoCloneCommand {
+    /// The name of the repository to clone.
+    source: String,
+    /// A name that uniquely identifies the new repository.
+    destination: String,
+
+    /// Also add every package in the source repository to the same
+    /// distribution and component of the new repository, and sign the
+    /// resulting indexes.
+    ///
+    /// Without this, only the distributions, components, and their metadata
+    /// are cloned.
+    #[arg(long)]
+    with_packages: bool,
+    /// GPG key ID to sign the indexes with (see `gpg --list-secret-keys`)
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short, requires = "with_packages")]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform. Only used with `--with-packages`.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+pub async fn run(ctx: Config, command: RepoCloneCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .post(
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/clone",
+                    percent_encode(command.source.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+                )
+                .as_str(),
+            )
+            .unwrap(),
+        )
+        .json(&CloneRepositoryRequest {
+            destination: command.destination.clone(),
+        })
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let res = res
+                .json::<CloneRepositoryResponse>()
+                .await
+                .expect("Could not parse response");
+            println!(
+                "Repository {:?} cloned from {:?} with {} distributions and {} components",
+                res.name, command.source, res.distributions, res.components
+            );
+            if let Some(base_url) = res.base_url {
+                println!("Repository is served at {base_url}");
+            }
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error cloning repository: {}", error.message);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if !command.with_packages {
+        return ExitCode::SUCCESS;
+    }
+
+    let params = PackageListParams {
+        repository: Some(command.source.clone()),
+        distribution: None,
+        component: None,
+        name: None,
+        version: None,
+        architecture: None,
+    };
+    let packages = match list_all(&ctx, &params).await {
+        Ok(packages) => packages,
+        Err(error) => {
+            eprintln!("Error listing packages: {}", error.message);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Packages have already been uploaded, so they only need to be added to
+    // the new repository's indexes. This reuses the existing package objects.
+    let mut failed = 0;
+    for package in &packages {
+        let add = PkgAddCommand::builder()
+            .repo(&command.destination)
+            .distribution(&package.distribution)
+            .component(&package.component)
+            .key_id(command.key_id.clone())
+            .maybe_gpg_home_dir(command.gpg_home_dir.clone())
+            // Unused, since the package has already been uploaded.
+            .package_file(String::new())
+            .build();
+        match add_package_with_retry(&ctx, &add, &package.sha256sum).await {
+            Ok(_) => tracing::info!(?package, "package added to index"),
+            Err(error) => {
+                eprintln!(
+                    "Unable to add {} {} ({}) to {}/{}: {error:#}",
+                    package.name,
+                    package.version,
+                    package.architecture,
+                    package.distribution,
+                    package.component
+                );
+                failed += 1;
+            }
+        }
+    }
+    println!(
+        "{} packages added, {failed} failed",
+        packages.len() - failed
+    );
+
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
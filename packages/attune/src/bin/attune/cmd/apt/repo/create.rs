@@ -1,12 +1,24 @@
-use std::process::ExitCode;
+use std::{collections::HashSet, path::PathBuf, process::ExitCode};
 
 use axum::http::StatusCode;
 use clap::Args;
+use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::{
+    cmd::apt::dist::{build_distribution_url, handle_api_response},
+    config::Config,
+    gpg_export_public_key,
+};
 use attune::{
     api::ErrorResponse,
-    server::repo::create::{CreateRepositoryRequest, CreateRepositoryResponse},
+    apt::{Compression, normalize_architecture},
+    server::repo::{
+        create::{CreateRepositoryRequest, CreateRepositoryResponse},
+        dist::{
+            create::{CreateDistributionRequest, CreateDistributionResponse},
+            edit::{EditDistributionRequest, EditDistributionResponse},
+        },
+    },
 };
 
 #[derive(Args, Debug)]
@@ -14,16 +26,298 @@ pub struct RepoCreateCommand {
     /// A name that uniquely identifies this repository.
     name: String,
 
+    /// Only allow packages of these architectures to be added, as a
+    /// comma-separated list (e.g. "amd64,arm64"). By default, every
+    /// architecture is allowed.
+    #[arg(long, value_delimiter = ',', conflicts_with = "from_template")]
+    allowed_architectures: Vec<String>,
+    /// Only allow packages to be added to these components, as a
+    /// comma-separated list (e.g. "main"). By default, every component is
+    /// allowed.
+    #[arg(long, value_delimiter = ',', conflicts_with = "from_template")]
+    allowed_components: Vec<String>,
+
+    /// Create the repository and its distributions from a TOML template.
+    ///
+    /// The template declares the repository's allowlists, the distributions to
+    /// create along with their metadata and components, and the key that
+    /// packages will be signed with. For example:
+    ///
+    /// ```toml
+    /// allowed_architectures = ["amd64", "arm64"]
+    ///
+    /// [signing]
+    /// key_id = "ABCD1234"
+    ///
+    /// [defaults]
+    /// origin = "ACME Corp"
+    /// components = ["main"]
+    ///
+    /// [[distributions]]
+    /// name = "stable"
+    ///
+    /// [[distributions]]
+    /// name = "testing"
+    /// label = "ACME Testing"
+    /// ```
+    #[arg(long, value_name = "FILE")]
+    from_template: Option<PathBuf>,
+
+    /// Print what would be created from the template without creating
+    /// anything.
+    #[arg(long, requires = "from_template")]
+    dry_run: bool,
+
+    /// The GPG home directory in which to look for the template's signing
+    /// key. If not set, defaults to the standard GPG home directory for the
+    /// platform.
+    #[arg(long, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+
     /// Output in JSON format.
     #[arg(long)]
     json: bool,
 }
 
+/// A repository and its distributions, as declared in a template file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RepoTemplate {
+    #[serde(default)]
+    allowed_architectures: Vec<String>,
+    #[serde(default)]
+    allowed_components: Vec<String>,
+    #[serde(default)]
+    signing: SigningTemplate,
+    /// Settings that apply to every distribution that doesn't set them.
+    #[serde(default)]
+    defaults: DistributionDefaults,
+    #[serde(default)]
+    distributions: Vec<DistributionTemplate>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct SigningTemplate {
+    /// The key that packages in this repository are expected to be signed
+    /// with. It must be available locally.
+    key_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct DistributionDefaults {
+    origin: Option<String>,
+    label: Option<String>,
+    version: Option<String>,
+    index_compression: Option<Vec<Compression>>,
+    default_component: Option<String>,
+    components: Option<Vec<String>>,
+    architectures: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct DistributionTemplate {
+    name: String,
+    /// Defaults to the distribution's name.
+    suite: Option<String>,
+    /// Defaults to the distribution's name.
+    codename: Option<String>,
+    description: Option<String>,
+    origin: Option<String>,
+    label: Option<String>,
+    version: Option<String>,
+    index_compression: Option<Vec<Compression>>,
+    default_component: Option<String>,
+    /// Components to list in the Release file even before they have packages.
+    components: Option<Vec<String>>,
+    /// Architectures to list in the Release file even before they have
+    /// packages.
+    architectures: Option<Vec<String>>,
+}
+
+/// The requests that creating a repository from a template makes.
+#[derive(Serialize, Debug)]
+struct TemplatePlan {
+    repository: CreateRepositoryRequest,
+    distributions: Vec<PlannedDistribution>,
+    signing_key_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct PlannedDistribution {
+    create: CreateDistributionRequest,
+    /// Declared components and architectures can only be set by editing the
+    /// distribution after it's created.
+    declare: Option<EditDistributionRequest>,
+}
+
+#[derive(Serialize, Debug)]
+struct TemplateResult {
+    repository: CreateRepositoryResponse,
+    distributions: Vec<CreateDistributionResponse>,
+}
+
+impl TemplatePlan {
+    fn new(name: String, template: RepoTemplate) -> Result<Self, String> {
+        let RepoTemplate {
+            allowed_architectures,
+            allowed_components,
+            signing,
+            defaults,
+            distributions,
+        } = template;
+        let allowed_architectures = allowed_architectures
+            .iter()
+            .map(|arch| normalize_architecture(arch))
+            .collect::<Vec<_>>();
+
+        let mut names = HashSet::new();
+        let mut planned = Vec::with_capacity(distributions.len());
+        for dist in distributions {
+            if !names.insert(dist.name.clone()) {
+                return Err(format!(
+                    "distribution {:?} is declared more than once",
+                    dist.name
+                ));
+            }
+            let default_component = dist
+                .default_component
+                .or_else(|| defaults.default_component.clone());
+            let components = dist.components.or_else(|| defaults.components.clone());
+            let architectures = dist
+                .architectures
+                .or_else(|| defaults.architectures.clone());
+
+            // Catch declarations that the repository's allowlists would make
+            // unusable before anything is created.
+            if !allowed_components.is_empty() {
+                let disallowed = default_component
+                    .iter()
+                    .chain(components.iter().flatten())
+                    .find(|component| !allowed_components.contains(component));
+                if let Some(component) = disallowed {
+                    return Err(format!(
+                        "distribution {:?} uses component {component:?}, which is not in allowed_components",
+                        dist.name
+                    ));
+                }
+            }
+            if !allowed_architectures.is_empty() {
+                let disallowed = architectures
+                    .iter()
+                    .flatten()
+                    .find(|arch| !allowed_architectures.contains(&normalize_architecture(arch)));
+                if let Some(arch) = disallowed {
+                    return Err(format!(
+                        "distribution {:?} uses architecture {arch:?}, which is not in allowed_architectures",
+                        dist.name
+                    ));
+                }
+            }
+
+            let declare = (components.is_some() || architectures.is_some()).then(|| {
+                EditDistributionRequest::builder()
+                    .maybe_declared_components(components)
+                    .maybe_declared_architectures(architectures)
+                    .build()
+            });
+            let create = CreateDistributionRequest::builder()
+                .suite(dist.suite.unwrap_or_else(|| dist.name.clone()))
+                .codename(dist.codename.unwrap_or_else(|| dist.name.clone()))
+                .name(dist.name)
+                .maybe_description(dist.description)
+                .maybe_origin(dist.origin.or_else(|| defaults.origin.clone()))
+                .maybe_label(dist.label.or_else(|| defaults.label.clone()))
+                .maybe_version(dist.version.or_else(|| defaults.version.clone()))
+                .maybe_index_compression(
+                    dist.index_compression
+                        .or_else(|| defaults.index_compression.clone()),
+                )
+                .maybe_default_component(default_component)
+                .build();
+            planned.push(PlannedDistribution { create, declare });
+        }
+
+        Ok(Self {
+            repository: CreateRepositoryRequest {
+                name,
+                allowed_architectures,
+                allowed_components,
+            },
+            distributions: planned,
+            signing_key_id: signing.key_id,
+        })
+    }
+
+    fn print(&self) {
+        println!("Would create repository {:?}", self.repository.name);
+        if !self.repository.allowed_architectures.is_empty() {
+            println!(
+                "  allowed architectures: {}",
+                self.repository.allowed_architectures.join(", ")
+            );
+        }
+        if !self.repository.allowed_components.is_empty() {
+            println!(
+                "  allowed components: {}",
+                self.repository.allowed_components.join(", ")
+            );
+        }
+        if let Some(key_id) = &self.signing_key_id {
+            println!("  signed with key {key_id}");
+        }
+        for dist in &self.distributions {
+            let create = &dist.create;
+            println!("Would create distribution {:?}", create.name);
+            println!("  suite: {}", create.suite);
+            println!("  codename: {}", create.codename);
+            let fields = [
+                ("description", &create.description),
+                ("origin", &create.origin),
+                ("label", &create.label),
+                ("version", &create.version),
+                ("default component", &create.default_component),
+            ];
+            for (field, value) in fields {
+                if let Some(value) = value {
+                    println!("  {field}: {value}");
+                }
+            }
+            if !create.index_compression.is_empty() {
+                let compression = create
+                    .index_compression
+                    .iter()
+                    .map(|compression| compression.to_string())
+                    .collect::<Vec<_>>();
+                println!("  index compression: {}", compression.join(", "));
+            }
+            if let Some(declare) = &dist.declare {
+                if let Some(components) = &declare.declared_components {
+                    println!("  components: {}", components.join(", "));
+                }
+                if let Some(architectures) = &declare.declared_architectures {
+                    println!("  architectures: {}", architectures.join(", "));
+                }
+            }
+        }
+    }
+}
+
 pub async fn run(ctx: Config, command: RepoCreateCommand) -> ExitCode {
+    if let Some(path) = &command.from_template {
+        return run_template(ctx, &command, path).await;
+    }
+
     let res = ctx
         .client
-        .post(ctx.endpoint.join("/api/v0/repositories").unwrap())
-        .json(&CreateRepositoryRequest { name: command.name })
+        .post(ctx.url("/api/v0/repositories").unwrap())
+        .json(&CreateRepositoryRequest {
+            name: command.name,
+            allowed_architectures: command.allowed_architectures,
+            allowed_components: command.allowed_components,
+        })
         .send()
         .await
         .expect("Could not send API request");
@@ -39,10 +333,7 @@ pub async fn run(ctx: Config, command: RepoCreateCommand) -> ExitCode {
                 println!("{}", serde_json::to_string_pretty(&res).unwrap());
                 return ExitCode::SUCCESS;
             }
-            println!(
-                "Repository {:?} created in bucket {:?} at prefix {:?}",
-                res.name, res.s3_bucket, res.s3_prefix
-            );
+            print_created(&res);
             ExitCode::SUCCESS
         }
         _ => {
@@ -55,3 +346,251 @@ pub async fn run(ctx: Config, command: RepoCreateCommand) -> ExitCode {
         }
     }
 }
+
+fn print_created(res: &CreateRepositoryResponse) {
+    println!(
+        "Repository {:?} created in bucket {:?} at prefix {:?}",
+        res.name, res.s3_bucket, res.s3_prefix
+    );
+    if let Some(base_url) = &res.base_url {
+        println!("Repository is served at {base_url}");
+    }
+}
+
+async fn run_template(ctx: Config, command: &RepoCreateCommand, path: &PathBuf) -> ExitCode {
+    let template = match std::fs::read_to_string(path) {
+        Ok(template) => template,
+        Err(err) => {
+            eprintln!("Error reading template {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let plan = match toml::from_str::<RepoTemplate>(&template)
+        .map_err(|err| err.to_string())
+        .and_then(|template| TemplatePlan::new(command.name.clone(), template))
+    {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("Invalid template {}: {err}", path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Packages are signed locally, so check that the key is usable before
+    // creating a repository that nothing could be published to.
+    if let Some(key_id) = &plan.signing_key_id
+        && let Err(err) =
+            gpg_export_public_key(command.gpg_home_dir.clone(), Some(key_id.clone())).await
+    {
+        eprintln!("Signing key {key_id:?} is not available: {err:#}");
+        return ExitCode::FAILURE;
+    }
+
+    if command.dry_run {
+        if command.json {
+            println!("{}", serde_json::to_string_pretty(&plan).unwrap());
+        } else {
+            plan.print();
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let res = ctx
+        .client
+        .post(ctx.url("/api/v0/repositories").unwrap())
+        .json(&plan.repository)
+        .send()
+        .await
+        .expect("Could not send API request");
+    let repository = match handle_api_response::<CreateRepositoryResponse>(res).await {
+        Ok(repository) => repository,
+        Err(err) => {
+            eprintln!("Error creating repository: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if !command.json {
+        print_created(&repository);
+    }
+
+    let mut distributions = Vec::with_capacity(plan.distributions.len());
+    for dist in &plan.distributions {
+        let created = match create_distribution(&ctx, &repository.name, dist).await {
+            Ok(created) => created,
+            Err(err) => {
+                eprintln!("Error creating distribution {:?}: {err}", dist.create.name);
+                eprintln!(
+                    "Repository {:?} was created along with {} of {} distributions. Create the rest with `attune apt dist create`, or delete the repository and try again.",
+                    repository.name,
+                    distributions.len(),
+                    plan.distributions.len()
+                );
+                return ExitCode::FAILURE;
+            }
+        };
+        if !command.json {
+            println!("Distribution {:?} created", created.distribution);
+        }
+        distributions.push(created);
+    }
+
+    if command.json {
+        let result = TemplateResult {
+            repository,
+            distributions,
+        };
+        println!("{}", serde_json::to_string_pretty(&result).unwrap());
+    }
+    ExitCode::SUCCESS
+}
+
+async fn create_distribution(
+    ctx: &Config,
+    repository: &str,
+    dist: &PlannedDistribution,
+) -> Result<CreateDistributionResponse, String> {
+    let created = ctx
+        .client
+        .post(build_distribution_url(ctx, repository, None))
+        .json(&dist.create)
+        .send()
+        .await
+        .map_err(|err| format!("Failed to send request: {err}"))
+        .map(handle_api_response::<CreateDistributionResponse>)?
+        .await?;
+    if let Some(declare) = &dist.declare {
+        ctx.client
+            .put(build_distribution_url(
+                ctx,
+                repository,
+                Some(&dist.create.name),
+            ))
+            .json(declare)
+            .send()
+            .await
+            .map_err(|err| format!("Failed to send request: {err}"))
+            .map(handle_api_response::<EditDistributionResponse>)?
+            .await?;
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn plan_from_template() {
+        let template = toml::from_str::<RepoTemplate>(indoc! {r#"
+            allowed_architectures = ["x86_64", "arm64"]
+            allowed_components = ["main", "contrib"]
+
+            [signing]
+            key_id = "ABCD1234"
+
+            [defaults]
+            origin = "ACME Corp"
+            label = "ACME"
+            index_compression = ["zstd"]
+            components = ["main"]
+
+            [[distributions]]
+            name = "stable"
+            codename = "bookworm"
+            architectures = ["amd64"]
+
+            [[distributions]]
+            name = "testing"
+            label = "ACME Testing"
+            components = ["main", "contrib"]
+            default_component = "contrib"
+        "#})
+        .unwrap();
+        let plan = TemplatePlan::new(String::from("acme"), template).unwrap();
+
+        assert_eq!(plan.repository.name, "acme");
+        assert_eq!(plan.repository.allowed_architectures, ["amd64", "arm64"]);
+        assert_eq!(plan.repository.allowed_components, ["main", "contrib"]);
+        assert_eq!(plan.signing_key_id.as_deref(), Some("ABCD1234"));
+
+        let [stable, testing] = plan.distributions.as_slice() else {
+            panic!("expected two distributions");
+        };
+        assert_eq!(stable.create.suite, "stable");
+        assert_eq!(stable.create.codename, "bookworm");
+        assert_eq!(stable.create.origin.as_deref(), Some("ACME Corp"));
+        assert_eq!(stable.create.label.as_deref(), Some("ACME"));
+        assert_eq!(stable.create.index_compression, [Compression::Zstd]);
+        let declare = stable.declare.as_ref().unwrap();
+        assert_eq!(
+            declare.declared_components.as_deref(),
+            Some(&[String::from("main")][..])
+        );
+        assert_eq!(
+            declare.declared_architectures.as_deref(),
+            Some(&[String::from("amd64")][..])
+        );
+
+        assert_eq!(testing.create.codename, "testing");
+        assert_eq!(testing.create.label.as_deref(), Some("ACME Testing"));
+        assert_eq!(testing.create.default_component.as_deref(), Some("contrib"));
+        let declare = testing.declare.as_ref().unwrap();
+        assert_eq!(
+            declare.declared_components.as_deref(),
+            Some(&[String::from("main"), String::from("contrib")][..])
+        );
+        assert_eq!(declare.declared_architectures, None);
+    }
+
+    #[test]
+    fn reject_invalid_templates() {
+        let plan = |template: &str| {
+            toml::from_str::<RepoTemplate>(template)
+                .map_err(|err| err.to_string())
+                .and_then(|template| TemplatePlan::new(String::from("acme"), template))
+        };
+
+        let err = plan(indoc! {r#"
+            [[distributions]]
+            name = "stable"
+
+            [[distributions]]
+            name = "stable"
+        "#})
+        .unwrap_err();
+        assert_eq!(err, "distribution \"stable\" is declared more than once");
+
+        let err = plan(indoc! {r#"
+            allowed_components = ["main"]
+
+            [[distributions]]
+            name = "stable"
+            default_component = "contrib"
+        "#})
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "distribution \"stable\" uses component \"contrib\", which is not in allowed_components"
+        );
+
+        let err = plan(indoc! {r#"
+            allowed_architectures = ["amd64"]
+
+            [defaults]
+            architectures = ["arm64"]
+
+            [[distributions]]
+            name = "stable"
+        "#})
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "distribution \"stable\" uses architecture \"arm64\", which is not in allowed_architectures"
+        );
+
+        // Misspelled fields are reported rather than ignored.
+        assert!(plan("[[distributions]]\nname = \"stable\"\norigni = \"ACME\"\n").is_err());
+    }
+}
@@ -20,6 +20,13 @@ pub struct RepoDeleteCommand {
     /// Skip confirmation prompt and proceed with deletion
     #[arg(short, long)]
     yes: bool,
+
+    /// Also delete every object the repository has stored in S3.
+    ///
+    /// Without this, the repository's published indexes and pool files are
+    /// left in place after it is deleted.
+    #[arg(long)]
+    purge_objects: bool,
 }
 
 pub async fn run(ctx: Config, command: RepoDeleteCommand) -> ExitCode {
@@ -46,29 +53,42 @@ pub async fn run(ctx: Config, command: RepoDeleteCommand) -> ExitCode {
         }
     }
 
+    if command.purge_objects {
+        println!("Purging repository objects, this may take a while...");
+    }
     let res = ctx
         .client
         .delete(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}",
-                        percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}",
+                    percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .unwrap(),
+                .as_str(),
+            )
+            .unwrap(),
         )
-        .json(&DeleteRepositoryRequest {})
+        .json(&DeleteRepositoryRequest {
+            purge_objects: command.purge_objects,
+        })
         .send()
         .await
         .expect("Could not send API request");
     match res.status() {
         StatusCode::OK => {
-            res.json::<DeleteRepositoryResponse>()
+            let res = res
+                .json::<DeleteRepositoryResponse>()
                 .await
                 .expect("Could not parse response");
             println!("Repository deleted");
+            match res.purged_objects {
+                Some(purged) => println!("{purged} objects purged"),
+                None => println!(
+                    "{}",
+                    "Note: the repository's objects remain in S3; pass --purge-objects to delete them"
+                        .yellow()
+                ),
+            }
             ExitCode::SUCCESS
         }
         _ => {
@@ -0,0 +1,236 @@
+use std::{cmp::Ordering, collections::BTreeMap, process::ExitCode};
+
+use clap::Args;
+use serde::Serialize;
+use tabled::settings::Style;
+
+use crate::{
+    cmd::apt::pkg::{compare_versions, list::list_all},
+    config::Config,
+};
+use attune::{
+    api::ErrorResponse,
+    server::pkg::list::{Package, PackageListParams},
+};
+
+#[derive(Args, Debug)]
+pub struct RepoDiffCommand {
+    /// The repository containing both distributions.
+    #[arg(long)]
+    repo: String,
+    /// The distribution to compare from (e.g. "unstable").
+    #[arg(long)]
+    from: String,
+    /// The distribution to compare to (e.g. "stable").
+    #[arg(long)]
+    to: String,
+
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
+}
+
+/// How a package differs between two distributions.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ChangeKind {
+    Added,
+    Removed,
+    Upgraded,
+    Downgraded,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct PackageChange {
+    name: String,
+    architecture: String,
+    component: String,
+    change: ChangeKind,
+    from_version: Option<String>,
+    to_version: Option<String>,
+}
+
+type PackageKey = (String, String, String);
+
+pub async fn run(ctx: Config, cmd: RepoDiffCommand) -> ExitCode {
+    let from = match list_packages(&ctx, &cmd.repo, &cmd.from).await {
+        Ok(packages) => packages,
+        Err(error) => {
+            eprintln!("Error listing packages in {}: {}", cmd.from, error.message);
+            return ExitCode::FAILURE;
+        }
+    };
+    let to = match list_packages(&ctx, &cmd.repo, &cmd.to).await {
+        Ok(packages) => packages,
+        Err(error) => {
+            eprintln!("Error listing packages in {}: {}", cmd.to, error.message);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let changes = diff(from, to);
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+        return ExitCode::SUCCESS;
+    }
+    if changes.is_empty() {
+        println!("No differences between {} and {}", cmd.from, cmd.to);
+        return ExitCode::SUCCESS;
+    }
+    let mut builder = tabled::builder::Builder::new();
+    builder.push_record([
+        String::from("Change"),
+        String::from("Package"),
+        String::from("Architecture"),
+        String::from("Component"),
+        cmd.from,
+        cmd.to,
+    ]);
+    for change in changes {
+        let kind = match change.change {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Upgraded => "upgraded",
+            ChangeKind::Downgraded => "downgraded",
+        };
+        builder.push_record([
+            String::from(kind),
+            change.name,
+            change.architecture,
+            change.component,
+            change.from_version.unwrap_or_default(),
+            change.to_version.unwrap_or_default(),
+        ]);
+    }
+    let mut table = builder.build();
+    table.with(Style::modern());
+    println!("{table}");
+    ExitCode::SUCCESS
+}
+
+async fn list_packages(
+    ctx: &Config,
+    repository: &str,
+    distribution: &str,
+) -> Result<Vec<Package>, ErrorResponse> {
+    let params = PackageListParams {
+        repository: Some(repository.to_string()),
+        distribution: Some(distribution.to_string()),
+        component: None,
+        name: None,
+        version: None,
+        architecture: None,
+    };
+    list_all(ctx, &params).await
+}
+
+/// Compare the packages of two distributions, keyed by name, architecture,
+/// and component. Changes are sorted by key.
+///
+/// A distribution can contain several versions of the same package, in which
+/// case only the highest version is compared.
+fn diff(from: Vec<Package>, to: Vec<Package>) -> Vec<PackageChange> {
+    let from = latest_versions(from);
+    let mut to = latest_versions(to);
+
+    let mut changes = Vec::new();
+    for (key, from_version) in from {
+        let (name, architecture, component) = key.clone();
+        let change = match to.remove(&key) {
+            None => Some((ChangeKind::Removed, None)),
+            Some(to_version) => match compare_versions(&from_version, &to_version) {
+                Ordering::Less => Some((ChangeKind::Upgraded, Some(to_version))),
+                Ordering::Greater => Some((ChangeKind::Downgraded, Some(to_version))),
+                Ordering::Equal => None,
+            },
+        };
+        if let Some((change, to_version)) = change {
+            changes.push(PackageChange {
+                name,
+                architecture,
+                component,
+                change,
+                from_version: Some(from_version),
+                to_version,
+            });
+        }
+    }
+    for ((name, architecture, component), to_version) in to {
+        changes.push(PackageChange {
+            name,
+            architecture,
+            component,
+            change: ChangeKind::Added,
+            from_version: None,
+            to_version: Some(to_version),
+        });
+    }
+    changes.sort_by(|a, b| {
+        (&a.name, &a.architecture, &a.component).cmp(&(&b.name, &b.architecture, &b.component))
+    });
+    changes
+}
+
+fn latest_versions(packages: Vec<Package>) -> BTreeMap<PackageKey, String> {
+    let mut latest = BTreeMap::<PackageKey, String>::new();
+    for package in packages {
+        let key = (package.name, package.architecture, package.component);
+        match latest.get(&key) {
+            Some(existing) if compare_versions(existing, &package.version).is_ge() => {}
+            _ => {
+                latest.insert(key, package.version);
+            }
+        }
+    }
+    latest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, architecture: &str) -> Package {
+        Package {
+            repository: String::from("repo"),
+            distribution: String::from("dist"),
+            component: String::from("main"),
+            name: String::from(name),
+            version: String::from(version),
+            architecture: String::from(architecture),
+            sha256sum: String::new(),
+        }
+    }
+
+    #[test]
+    fn diff_uses_debian_version_ordering() {
+        let from = vec![
+            package("same", "1.0", "amd64"),
+            package("upgraded", "1.9", "amd64"),
+            package("downgraded", "1:0.1", "amd64"),
+            package("removed", "1.0", "amd64"),
+            package("multi", "1.0", "amd64"),
+            package("multi", "1.0~rc1", "amd64"),
+        ];
+        let to = vec![
+            package("same", "1.0", "amd64"),
+            package("upgraded", "1.10", "amd64"),
+            package("downgraded", "2.0", "amd64"),
+            package("added", "1.0", "arm64"),
+            package("multi", "1.0~rc1", "amd64"),
+        ];
+        let changes = diff(from, to)
+            .into_iter()
+            .map(|change| (change.name, change.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                (String::from("added"), ChangeKind::Added),
+                (String::from("downgraded"), ChangeKind::Downgraded),
+                (String::from("multi"), ChangeKind::Downgraded),
+                (String::from("removed"), ChangeKind::Removed),
+                (String::from("upgraded"), ChangeKind::Upgraded),
+            ]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+use colored::Colorize as _;
+use percent_encoding::percent_encode;
+use tabled::settings::Style;
+
+use crate::config::Config;
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::usage::{RepositoryUsageParams, RepositoryUsageResponse, Usage},
+};
+
+#[derive(Args, Debug)]
+pub struct RepoDuCommand {
+    /// The name of the repository.
+    name: String,
+
+    /// Also list the repository's objects in S3, to report the storage that
+    /// is actually used, including orphaned objects.
+    ///
+    /// This can take a while for large repositories.
+    #[arg(long)]
+    list_objects: bool,
+
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(ctx: Config, command: RepoDuCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .get(
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/usage",
+                    percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+                )
+                .as_str(),
+            )
+            .unwrap(),
+        )
+        .query(&RepositoryUsageParams {
+            list_objects: command.list_objects,
+        })
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let res = res
+                .json::<RepositoryUsageResponse>()
+                .await
+                .expect("Could not parse response");
+            if command.json {
+                println!("{}", serde_json::to_string_pretty(&res).unwrap());
+                return ExitCode::SUCCESS;
+            }
+            let mut builder = tabled::builder::Builder::new();
+            builder.push_record([
+                "Distribution",
+                "Pool objects",
+                "Pool size",
+                "Index objects",
+                "Index size",
+                "Total size",
+            ]);
+            let rows = res
+                .distributions
+                .iter()
+                .map(|dist| (dist.distribution.as_str(), &dist.usage))
+                .chain([("(total)", &res.total)]);
+            for (distribution, usage) in rows {
+                builder.push_record(usage_record(distribution, usage));
+            }
+            let mut table = builder.build();
+            table.with(Style::modern());
+            println!("{table}");
+            println!("Pool objects shared between distributions are counted once in the total.");
+
+            if let Some(objects) = res.objects {
+                println!(
+                    "S3: {} objects, {}",
+                    objects.objects,
+                    format_bytes(objects.bytes)
+                );
+                if objects.orphaned_objects > 0 {
+                    println!(
+                        "{}",
+                        format!(
+                            "{} orphaned objects, {}",
+                            objects.orphaned_objects,
+                            format_bytes(objects.orphaned_bytes)
+                        )
+                        .yellow()
+                    );
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error getting repository usage: {}", error.message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage_record(distribution: &str, usage: &Usage) -> [String; 6] {
+    [
+        distribution.to_string(),
+        usage.pool_objects.to_string(),
+        format_bytes(usage.pool_bytes),
+        usage.index_objects.to_string(),
+        format_bytes(usage.index_bytes),
+        format_bytes(usage.total_bytes()),
+    ]
+}
+
+/// Format a size in bytes with binary units, e.g. "1.5 MiB".
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_bytes_with_binary_units() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(1536 * 1024), "1.5 MiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+}
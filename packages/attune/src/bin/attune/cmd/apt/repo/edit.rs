@@ -19,24 +19,53 @@ pub struct RepoEditCommand {
     /// The new name for the repository.
     #[arg(long)]
     new_name: Option<String>,
+
+    /// Update the architectures that packages can be added in, as a
+    /// comma-separated list (e.g. "amd64,arm64"). Pass the flag without a
+    /// value to allow every architecture.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    allowed_architectures: Option<Vec<String>>,
+    /// Update the components that packages can be added to, as a
+    /// comma-separated list (e.g. "main"). Pass the flag without a value to
+    /// allow every component.
+    #[arg(long, value_delimiter = ',', num_args = 0..)]
+    allowed_components: Option<Vec<String>>,
+    /// Send a notification to this URL whenever a change to one of the
+    /// repository's distributions is published. Pass an empty string to stop
+    /// sending notifications.
+    ///
+    /// Notifications are signed with a secret, which is printed when the URL
+    /// is set.
+    #[arg(long)]
+    webhook_url: Option<String>,
 }
 
 pub async fn run(ctx: Config, command: RepoEditCommand) -> ExitCode {
+    if command.new_name.is_none()
+        && command.allowed_architectures.is_none()
+        && command.allowed_components.is_none()
+        && command.webhook_url.is_none()
+    {
+        eprintln!("No fields to update provided. Use --help to see available options.");
+        return ExitCode::FAILURE;
+    }
     let res = ctx
         .client
         .put(
-            ctx.endpoint
-                .join(
-                    format!(
-                        "/api/v0/repositories/{}",
-                        percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
-                    )
-                    .as_str(),
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}",
+                    percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
                 )
-                .unwrap(),
+                .as_str(),
+            )
+            .unwrap(),
         )
         .json(&EditRepositoryRequest {
-            new_name: command.new_name,
+            new_name: command.new_name.clone(),
+            allowed_architectures: command.allowed_architectures.clone(),
+            allowed_components: command.allowed_components.clone(),
+            webhook_url: command.webhook_url.clone(),
         })
         .send()
         .await
@@ -47,10 +76,33 @@ pub async fn run(ctx: Config, command: RepoEditCommand) -> ExitCode {
                 .json::<EditRepositoryResponse>()
                 .await
                 .expect("Could not parse response");
-            println!(
-                "Repository name changed from {:?} to {:?}",
-                command.name, repo.result.name
-            );
+            if command.new_name.is_some() {
+                println!(
+                    "Repository name changed from {:?} to {:?}",
+                    command.name, repo.result.name
+                );
+            }
+            if command.allowed_architectures.is_some() {
+                println!(
+                    "Allowed architectures: {}",
+                    allowlist(&repo.result.allowed_architectures)
+                );
+            }
+            if command.allowed_components.is_some() {
+                println!(
+                    "Allowed components: {}",
+                    allowlist(&repo.result.allowed_components)
+                );
+            }
+            if command.webhook_url.is_some() {
+                match (&repo.result.webhook_url, &repo.result.webhook_secret) {
+                    (Some(url), Some(secret)) => {
+                        println!("Webhook URL: {url}");
+                        println!("Webhook secret: {secret}");
+                    }
+                    _ => println!("Webhook removed"),
+                }
+            }
             ExitCode::SUCCESS
         }
         _ => {
@@ -63,3 +115,11 @@ pub async fn run(ctx: Config, command: RepoEditCommand) -> ExitCode {
         }
     }
 }
+
+fn allowlist(allowed: &[String]) -> String {
+    if allowed.is_empty() {
+        String::from("(all)")
+    } else {
+        allowed.join(", ")
+    }
+}
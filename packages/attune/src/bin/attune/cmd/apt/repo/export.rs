@@ -0,0 +1,199 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::SystemTime,
+};
+
+use clap::Args;
+use color_eyre::eyre::{Context as _, Result, bail};
+use http::StatusCode;
+use percent_encoding::percent_encode;
+use tracing::{debug, instrument};
+
+use crate::config::Config;
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::export::RepositoryObjectsResponse,
+};
+
+#[derive(Args, Debug)]
+pub struct RepoExportCommand {
+    /// The name of the repository.
+    name: String,
+
+    /// The tarball to write.
+    ///
+    /// The tarball contains the repository's `dists/` and `pool/` trees, so
+    /// it can be extracted into the root of any static web server and used as
+    /// an APT source. Use `attune apt repository import --from-tarball` to
+    /// load it into another Attune repository.
+    #[arg(long, short)]
+    output: PathBuf,
+}
+
+pub async fn run(ctx: Config, command: RepoExportCommand) -> ExitCode {
+    match export(&ctx, &command).await {
+        Ok(objects) => {
+            println!(
+                "Exported {objects} objects from repository {:?} to {}",
+                command.name,
+                command.output.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error exporting repository: {error:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[instrument(skip(ctx))]
+async fn export(ctx: &Config, command: &RepoExportCommand) -> Result<usize> {
+    let repo = percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET);
+    let res = ctx
+        .client
+        .get(
+            ctx.url(&format!("/api/v0/repositories/{repo}/objects"))
+                .unwrap(),
+        )
+        .send()
+        .await
+        .context("send api request")?;
+    let paths = match res.status() {
+        StatusCode::OK => {
+            res.json::<RepositoryObjectsResponse>()
+                .await
+                .context("parse response")?
+                .paths
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .context("parse error response")?;
+            bail!(error);
+        }
+    };
+    if paths.is_empty() {
+        bail!("repository {:?} has no published objects", command.name);
+    }
+
+    let output = File::create(&command.output)
+        .with_context(|| format!("create {}", command.output.display()))?;
+    let written = write_tarball(ctx, &repo.to_string(), &paths, output).await;
+    if written.is_err() {
+        // Don't leave a partial tarball behind.
+        let _ = std::fs::remove_file(&command.output);
+    }
+    written.with_context(|| format!("write {}", command.output.display()))?;
+    Ok(paths.len())
+}
+
+async fn write_tarball(ctx: &Config, repo: &str, paths: &[String], output: File) -> Result<()> {
+    let mut tarball = tar::Builder::new(output);
+    let mtime = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for path in paths {
+        debug!(?path, "exporting object");
+        let contents = download_object(ctx, repo, path).await?;
+        append(&mut tarball, path, &contents, mtime).with_context(|| format!("write {path:?}"))?;
+    }
+    tarball.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+async fn download_object(ctx: &Config, repo: &str, path: &str) -> Result<bytes::Bytes> {
+    let path_encoded = path
+        .split('/')
+        .map(|segment| {
+            percent_encode(segment.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET).to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+    let res = ctx
+        .client
+        .get(
+            ctx.url(&format!(
+                "/api/v0/repositories/{repo}/objects/{path_encoded}"
+            ))
+            .unwrap(),
+        )
+        .send()
+        .await
+        .with_context(|| format!("download {path:?}"))?;
+    match res.status() {
+        StatusCode::OK => res
+            .bytes()
+            .await
+            .with_context(|| format!("download {path:?}")),
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .context("parse error response")?;
+            bail!(error);
+        }
+    }
+}
+
+/// Append a file to the tarball at the given path.
+fn append<W: std::io::Write>(
+    tarball: &mut tar::Builder<W>,
+    path: impl AsRef<Path>,
+    contents: &[u8],
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    tarball.append_data(&mut header, path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tarball_preserves_paths() {
+        let mut tarball = tar::Builder::new(Vec::new());
+        append(&mut tarball, "dists/stable/InRelease", b"release", 0).unwrap();
+        append(
+            &mut tarball,
+            "pool/main/f/foo/foo_1.0_amd64.deb",
+            b"package",
+            0,
+        )
+        .unwrap();
+        let tarball = tarball.into_inner().unwrap();
+
+        let mut archive = tar::Archive::new(tarball.as_slice());
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut contents = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+                (entry.path().unwrap().display().to_string(), contents)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (
+                    String::from("dists/stable/InRelease"),
+                    String::from("release")
+                ),
+                (
+                    String::from("pool/main/f/foo/foo_1.0_amd64.deb"),
+                    String::from("package")
+                ),
+            ]
+        );
+    }
+}
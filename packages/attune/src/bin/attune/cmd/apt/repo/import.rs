@@ -0,0 +1,631 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use async_tempfile::TempDir;
+use clap::Args;
+use color_eyre::eyre::{Context as _, Result, bail};
+use debian_packaging::repository::{
+    BinaryPackageFetch, RepositoryRootReader, reader_from_str, release::ReleaseFile,
+};
+use futures_util::AsyncReadExt as _;
+use pgp::composed::{
+    CleartextSignedMessage, Deserializable as _, SignedPublicKey, StandaloneSignature,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use tracing::{debug, info, instrument};
+
+use crate::{
+    cmd::apt::pkg::{
+        add::{PkgAddCommand, add_package_with_retry, upload_content},
+        list::list_all,
+        remove::{PkgRemoveCommand, remove_package_with_retry},
+    },
+    config::Config,
+};
+use attune::server::pkg::list::{Package, PackageListParams};
+
+#[derive(Args, Debug)]
+pub struct RepoImportCommand {
+    /// The repository to import packages into.
+    #[arg(long)]
+    repo: String,
+    /// The distribution to import packages into. Defaults to the upstream
+    /// distribution.
+    #[arg(long)]
+    distribution: Option<String>,
+
+    /// The URL of the upstream repository (e.g.
+    /// "https://deb.debian.org/debian").
+    #[arg(long, required_unless_present = "from_tarball")]
+    upstream: Option<String>,
+    /// Import from a tarball made by `attune apt repository export`, instead
+    /// of from an upstream URL.
+    ///
+    /// The tarball is extracted into a temporary directory and imported like
+    /// any other upstream repository, so `--upstream-distribution` and
+    /// `--upstream-key` (or `--allow-unsigned`) still apply.
+    #[arg(long, conflicts_with = "upstream")]
+    from_tarball: Option<PathBuf>,
+    /// The upstream distribution to import from (e.g. "bookworm").
+    #[arg(long)]
+    upstream_distribution: String,
+    /// The upstream components to import. Defaults to every component listed
+    /// in the upstream Release file.
+    #[arg(long)]
+    component: Vec<String>,
+    /// The upstream architectures to import. Defaults to every architecture
+    /// listed in the upstream Release file.
+    #[arg(long)]
+    architecture: Vec<String>,
+
+    /// The public key that the upstream repository is signed with, either
+    /// ASCII-armored or as a binary keyring (such as the ones in
+    /// `/usr/share/keyrings`).
+    ///
+    /// The upstream `InRelease` (or `Release` and `Release.gpg`) file must
+    /// have a valid signature from this key, or nothing is imported.
+    #[arg(long, required_unless_present = "allow_unsigned")]
+    upstream_key: Option<PathBuf>,
+    /// Import without verifying the upstream repository's signature.
+    #[arg(long, conflicts_with = "upstream_key")]
+    allow_unsigned: bool,
+
+    /// Also remove packages that are no longer in the upstream repository.
+    ///
+    /// Only packages in the imported components and architectures are
+    /// removed.
+    #[arg(long)]
+    mirror_deletions: bool,
+
+    /// A file that records the upstream Release file seen by the last
+    /// successful import.
+    ///
+    /// If the upstream Release file hasn't changed since then, the import
+    /// finishes without fetching the upstream indexes. Use this when
+    /// refreshing a mirror periodically.
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// GPG key ID to sign the index with (see `gpg --list-secret-keys`).
+    ///
+    /// Repeat this flag to sign with several keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// What a previous import saw of the upstream repository.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct ImportState {
+    upstream: String,
+    upstream_distribution: String,
+    /// The SHA256 sum of the upstream `InRelease` (or `Release`) file.
+    release_sha256: String,
+}
+
+/// A package in the upstream repository.
+#[derive(Debug)]
+struct UpstreamPackage<'a> {
+    component: String,
+    sha256sum: String,
+    fetch: BinaryPackageFetch<'a>,
+}
+
+pub async fn run(ctx: Config, cmd: RepoImportCommand) -> ExitCode {
+    match import(&ctx, &cmd).await {
+        Ok(summary) => {
+            println!("{summary}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("Error importing packages: {error:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[instrument(skip(ctx))]
+async fn import(ctx: &Config, cmd: &RepoImportCommand) -> Result<String> {
+    let distribution = cmd
+        .distribution
+        .clone()
+        .unwrap_or_else(|| cmd.upstream_distribution.clone());
+    // Tarballs are extracted into a temporary directory, which is removed when
+    // it is dropped at the end of the import.
+    let (upstream_name, extracted) = match (&cmd.upstream, &cmd.from_tarball) {
+        (_, Some(tarball)) => {
+            let extracted = extract_tarball(tarball).await?;
+            (tarball.display().to_string(), Some(extracted))
+        }
+        (Some(upstream), None) => (upstream.clone(), None),
+        (None, None) => bail!("either --upstream or --from-tarball is required"),
+    };
+    let upstream = match &extracted {
+        Some(extracted) => reader_from_str(extracted.dir_path().display()),
+        None => reader_from_str(&upstream_name),
+    }
+    .with_context(|| format!("invalid upstream URL {upstream_name:?}"))?;
+    let upstream = upstream.as_ref();
+
+    // Verify the upstream Release file before trusting anything it lists.
+    let upstream_release = UpstreamRelease::fetch(upstream, &cmd.upstream_distribution).await?;
+    let verified = match &cmd.upstream_key {
+        Some(path) => Some(upstream_release.verify(&read_upstream_keys(path)?)?),
+        None => None,
+    };
+
+    // Check whether anything has changed upstream since the last import.
+    let state = ImportState {
+        upstream: upstream_name.clone(),
+        upstream_distribution: cmd.upstream_distribution.clone(),
+        release_sha256: upstream_release.sha256(),
+    };
+    if let Some(state_file) = &cmd.state_file
+        && read_state(state_file)?.as_ref() == Some(&state)
+    {
+        return Ok(format!(
+            "Upstream {} {} is unchanged since the last import",
+            upstream_name, cmd.upstream_distribution
+        ));
+    }
+
+    let release = upstream
+        .release_reader(&cmd.upstream_distribution)
+        .await
+        .context("fetch upstream Release file")?;
+    // The indexes are fetched through this reader, which downloads the Release
+    // file again and checks each index against it, so it must be the file
+    // that was verified above.
+    if let Some(verified) = &verified
+        && **release.release_file() != **verified
+    {
+        bail!("upstream Release file changed during import, try again");
+    }
+    let components = selected(
+        &cmd.component,
+        release.release_file().components(),
+        "components",
+    )?;
+    let architectures = selected(
+        &cmd.architecture,
+        release.release_file().architectures(),
+        "architectures",
+    )?;
+    debug!(?components, ?architectures, "importing from upstream");
+
+    let upstream_packages = {
+        let components = components.clone();
+        let architectures = architectures.clone();
+        release
+            .resolve_package_fetches(
+                Box::new(move |entry| {
+                    !entry.is_installer
+                        && components.contains(entry.component.as_ref())
+                        && architectures.contains(entry.architecture.as_ref())
+                }),
+                Box::new(|_| true),
+                4,
+            )
+            .await
+            .context("fetch upstream Packages indexes")?
+    };
+    let upstream_packages = upstream_packages
+        .into_iter()
+        .map(|fetch| {
+            let component = upstream_component(&fetch.path, &components);
+            let sha256sum = fetch
+                .control_file
+                .field_str("SHA256")
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    color_eyre::eyre::eyre!("upstream package {:?} has no SHA256 sum", fetch.path)
+                })?;
+            Ok(UpstreamPackage {
+                component,
+                sha256sum,
+                fetch,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Only packages that aren't already published in the same component are
+    // downloaded. Architecture-independent packages are listed once for each
+    // architecture upstream, so they are deduplicated here too.
+    let published = list_packages(ctx, &cmd.repo, &distribution).await?;
+    let mut seen = published
+        .iter()
+        .map(|package| (package.component.clone(), package.sha256sum.clone()))
+        .collect::<HashSet<_>>();
+    let upstream_keys = upstream_packages
+        .iter()
+        .map(|package| (package.component.clone(), package.sha256sum.clone()))
+        .collect::<HashSet<_>>();
+
+    let mut added = 0;
+    for package in upstream_packages {
+        if !seen.insert((package.component.clone(), package.sha256sum.clone())) {
+            continue;
+        }
+        info!(path = ?package.fetch.path, component = ?package.component, "importing package");
+        let path = package.fetch.path.clone();
+        // The download is checked against the size and hash listed in the
+        // upstream Packages index as it is read.
+        let mut content = Vec::new();
+        upstream
+            .fetch_binary_package_generic(package.fetch)
+            .await
+            .with_context(|| format!("download {path:?}"))?
+            .read_to_end(&mut content)
+            .await
+            .with_context(|| format!("download {path:?}"))?;
+        let sha256sum = upload_content(ctx, content, false, false)
+            .await
+            .with_context(|| format!("upload {path:?}"))?;
+        if sha256sum != package.sha256sum {
+            bail!(
+                "uploaded {path:?} has SHA256 sum {sha256sum}, but the upstream Packages index lists {}",
+                package.sha256sum
+            );
+        }
+        let add = PkgAddCommand::builder()
+            .repo(&cmd.repo)
+            .distribution(&distribution)
+            .component(&package.component)
+            .key_id(cmd.key_id.clone())
+            .maybe_gpg_home_dir(cmd.gpg_home_dir.clone())
+            .package_file(&path)
+            .build();
+        add_package_with_retry(ctx, &add, &sha256sum)
+            .await
+            .with_context(|| format!("add {path:?} to index"))?;
+        added += 1;
+    }
+
+    let mut removed = 0;
+    if cmd.mirror_deletions {
+        let imported_components = components
+            .iter()
+            .map(|component| attune_component(component))
+            .collect::<BTreeSet<_>>();
+        for package in published {
+            let imported = imported_components.contains(&package.component)
+                && (architectures.contains(&package.architecture) || package.architecture == "all");
+            if !imported
+                || upstream_keys.contains(&(package.component.clone(), package.sha256sum.clone()))
+            {
+                continue;
+            }
+            info!(?package, "removing package that is no longer upstream");
+            let remove = PkgRemoveCommand::builder()
+                .repo(&cmd.repo)
+                .distribution(&distribution)
+                .component(&package.component)
+                .key_id(cmd.key_id.clone())
+                .maybe_gpg_home_dir(cmd.gpg_home_dir.clone())
+                .package(&package.name)
+                .version(&package.version)
+                .architecture(&package.architecture)
+                .build();
+            remove_package_with_retry(ctx, &remove)
+                .await
+                .with_context(|| format!("remove {} {}", package.name, package.version))?;
+            removed += 1;
+        }
+    }
+
+    if let Some(state_file) = &cmd.state_file {
+        write_state(state_file, &state)?;
+    }
+    Ok(if cmd.mirror_deletions {
+        format!("{added} packages imported, {removed} packages removed")
+    } else {
+        format!("{added} packages imported")
+    })
+}
+
+/// The upstream Release file, as published.
+#[derive(Debug)]
+enum UpstreamRelease {
+    /// A clearsigned `InRelease` file.
+    InRelease(String),
+    /// A `Release` file with its detached `Release.gpg` signature, if any.
+    Release {
+        contents: String,
+        signature: Option<String>,
+    },
+}
+
+impl UpstreamRelease {
+    /// Fetch the upstream `InRelease` file, or the `Release` and `Release.gpg`
+    /// files if the upstream doesn't publish an `InRelease` file.
+    async fn fetch(upstream: &dyn RepositoryRootReader, distribution: &str) -> Result<Self> {
+        let distribution = distribution.trim_matches('/');
+        if let Ok(in_release) =
+            fetch_string(upstream, &format!("dists/{distribution}/InRelease")).await
+        {
+            return Ok(Self::InRelease(in_release));
+        }
+        let contents = fetch_string(upstream, &format!("dists/{distribution}/Release"))
+            .await
+            .context("fetch upstream Release file")?;
+        let signature = fetch_string(upstream, &format!("dists/{distribution}/Release.gpg"))
+            .await
+            .ok();
+        Ok(Self::Release {
+            contents,
+            signature,
+        })
+    }
+
+    /// The SHA256 sum of the `InRelease` (or `Release`) file.
+    fn sha256(&self) -> String {
+        let contents = match self {
+            Self::InRelease(contents) | Self::Release { contents, .. } => contents,
+        };
+        hex::encode(Sha256::digest(contents))
+    }
+
+    /// Verify the file's signature against any of the keys, returning the
+    /// signed Release file.
+    fn verify(&self, keys: &[SignedPublicKey]) -> Result<ReleaseFile<'static>> {
+        let signed = match self {
+            Self::InRelease(in_release) => {
+                let (message, _headers) = CleartextSignedMessage::from_string(in_release)
+                    .context("parse upstream InRelease file")?;
+                let signed_text = message.signed_text();
+                if !signed_by(message.signatures(), signed_text.as_bytes(), keys) {
+                    bail!("upstream InRelease file is not signed by the upstream key");
+                }
+                // The signed text is normalized to CRLF line endings.
+                signed_text.replace("\r\n", "\n")
+            }
+            Self::Release {
+                contents,
+                signature,
+            } => {
+                let Some(signature) = signature else {
+                    bail!("upstream repository has neither an InRelease nor a Release.gpg file");
+                };
+                // Release.gpg often holds several signatures, one for each
+                // archive key.
+                let (signatures, _headers) = StandaloneSignature::from_string_many(signature)
+                    .context("parse upstream Release.gpg file")?;
+                let signatures = signatures
+                    .collect::<Result<Vec<_>, _>>()
+                    .context("parse upstream Release.gpg file")?;
+                if !signed_by(&signatures, contents.as_bytes(), keys) {
+                    bail!("upstream Release file is not signed by the upstream key");
+                }
+                contents.clone()
+            }
+        };
+        ReleaseFile::from_reader(signed.as_bytes()).context("parse upstream Release file")
+    }
+}
+
+/// Whether any of the signatures over the data was made by any of the keys.
+///
+/// Repositories are usually signed with a signing subkey, so subkeys count
+/// too, as long as they are bound to their primary key. The keys themselves
+/// are trusted as given: distribution keyrings carry third-party
+/// certifications that can't be checked here, so a full `verify` of each key
+/// would reject them.
+fn signed_by(signatures: &[StandaloneSignature], data: &[u8], keys: &[SignedPublicKey]) -> bool {
+    signatures.iter().any(|signature| {
+        keys.iter().any(|key| {
+            signature.verify(key, data).is_ok()
+                || key.public_subkeys.iter().any(|subkey| {
+                    subkey.verify(&key.primary_key).is_ok()
+                        && signature.verify(subkey, data).is_ok()
+                })
+        })
+    })
+}
+
+async fn fetch_string(upstream: &dyn RepositoryRootReader, path: &str) -> Result<String> {
+    let mut contents = String::new();
+    upstream
+        .get_path(path)
+        .await?
+        .read_to_string(&mut contents)
+        .await?;
+    Ok(contents)
+}
+
+/// Extract a repository tarball into a temporary directory.
+async fn extract_tarball(path: &Path) -> Result<TempDir> {
+    let dir = TempDir::new()
+        .await
+        .context("create directory to extract tarball")?;
+    let tarball = std::fs::File::open(path).with_context(|| format!("open tarball {path:?}"))?;
+    tar::Archive::new(tarball)
+        .unpack(dir.dir_path())
+        .with_context(|| format!("extract tarball {path:?}"))?;
+    Ok(dir)
+}
+
+/// Read the upstream public keys from an ASCII-armored key file or a binary
+/// keyring.
+fn read_upstream_keys(path: &Path) -> Result<Vec<SignedPublicKey>> {
+    let contents =
+        std::fs::read(path).with_context(|| format!("read upstream key file {path:?}"))?;
+    let keys = match std::str::from_utf8(&contents) {
+        Ok(armored) if armored.trim_start().starts_with("-----BEGIN") => {
+            SignedPublicKey::from_string_many(armored)
+                .with_context(|| format!("parse upstream key file {path:?}"))?
+                .0
+        }
+        _ => SignedPublicKey::from_bytes_many(contents.as_slice())
+            .with_context(|| format!("parse upstream key file {path:?}"))?,
+    }
+    .collect::<Result<Vec<_>, _>>()
+    .with_context(|| format!("parse upstream key file {path:?}"))?;
+    if keys.is_empty() {
+        bail!("upstream key file {path:?} contains no keys");
+    }
+    Ok(keys)
+}
+
+/// The requested values, or all upstream values if none were requested.
+fn selected<'a>(
+    requested: &[String],
+    upstream: Option<Box<dyn Iterator<Item = &'a str> + 'a>>,
+    field: &str,
+) -> Result<BTreeSet<String>> {
+    if !requested.is_empty() {
+        return Ok(requested.iter().cloned().collect());
+    }
+    let Some(upstream) = upstream else {
+        bail!("upstream Release file doesn't list its {field}");
+    };
+    Ok(upstream.map(str::to_string).collect())
+}
+
+/// The upstream component of a package, from its path in the pool.
+///
+/// Packages are conventionally stored under `pool/<component>/`. Some
+/// upstream components contain slashes (such as `updates/main`), so the
+/// longest matching component wins.
+fn upstream_component(path: &str, components: &BTreeSet<String>) -> String {
+    let pool_path = path.strip_prefix("pool/").unwrap_or(path);
+    components
+        .iter()
+        .filter(|component| pool_path.starts_with(&format!("{component}/")))
+        .max_by_key(|component| component.len())
+        .or_else(|| components.first())
+        .map(|component| attune_component(component))
+        .unwrap_or_default()
+}
+
+/// The name of the Attune component that an upstream component is imported
+/// into. Attune component names can't contain slashes.
+fn attune_component(component: &str) -> String {
+    component.replace('/', "-")
+}
+
+async fn list_packages(ctx: &Config, repository: &str, distribution: &str) -> Result<Vec<Package>> {
+    let params = PackageListParams {
+        repository: Some(repository.to_string()),
+        distribution: Some(distribution.to_string()),
+        component: None,
+        name: None,
+        version: None,
+        architecture: None,
+    };
+    Ok(list_all(ctx, &params).await?)
+}
+
+fn read_state(path: &Path) -> Result<Option<ImportState>> {
+    match std::fs::read_to_string(path) {
+        Ok(state) => {
+            Ok(Some(serde_json::from_str(&state).with_context(|| {
+                format!("parse import state file {path:?}")
+            })?))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("read import state file {path:?}")),
+    }
+}
+
+fn write_state(path: &Path, state: &ImportState) -> Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("write import state file {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASE: &str = include_str!("fixtures/Release");
+    const RELEASE_GPG: &str = include_str!("fixtures/Release.gpg");
+    const IN_RELEASE: &str = include_str!("fixtures/InRelease");
+
+    fn keys(armored: &str) -> Vec<SignedPublicKey> {
+        SignedPublicKey::from_string_many(armored)
+            .unwrap()
+            .0
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    }
+
+    /// The fixtures are signed with a signing subkey of this key.
+    fn upstream_keys() -> Vec<SignedPublicKey> {
+        keys(include_str!("fixtures/upstream-key.asc"))
+    }
+
+    fn other_keys() -> Vec<SignedPublicKey> {
+        keys(include_str!("fixtures/other-key.asc"))
+    }
+
+    #[test]
+    fn verify_upstream_release() {
+        let release = ReleaseFile::from_reader(RELEASE.as_bytes()).unwrap();
+
+        let in_release = UpstreamRelease::InRelease(String::from(IN_RELEASE))
+            .verify(&upstream_keys())
+            .unwrap();
+        assert_eq!(*in_release, *release);
+        // This is how the release reader parses the same file.
+        let armored = ReleaseFile::from_armored_reader(IN_RELEASE.as_bytes()).unwrap();
+        assert_eq!(*in_release, *armored);
+
+        let detached = UpstreamRelease::Release {
+            contents: String::from(RELEASE),
+            signature: Some(String::from(RELEASE_GPG)),
+        }
+        .verify(&upstream_keys())
+        .unwrap();
+        assert_eq!(*detached, *release);
+    }
+
+    #[test]
+    fn reject_unverified_upstream_release() {
+        let in_release = UpstreamRelease::InRelease(String::from(IN_RELEASE));
+        assert!(in_release.verify(&other_keys()).is_err());
+
+        let tampered = UpstreamRelease::InRelease(IN_RELEASE.replace("amd64", "arm64"));
+        assert!(tampered.verify(&upstream_keys()).is_err());
+
+        let detached = UpstreamRelease::Release {
+            contents: RELEASE.replace("amd64", "arm64"),
+            signature: Some(String::from(RELEASE_GPG)),
+        };
+        assert!(detached.verify(&upstream_keys()).is_err());
+
+        let unsigned = UpstreamRelease::Release {
+            contents: String::from(RELEASE),
+            signature: None,
+        };
+        assert!(unsigned.verify(&upstream_keys()).is_err());
+    }
+
+    #[test]
+    fn component_from_pool_path() {
+        let components = BTreeSet::from([
+            String::from("main"),
+            String::from("contrib"),
+            String::from("updates/main"),
+        ]);
+        assert_eq!(
+            upstream_component("pool/main/h/hello/hello_2.10-3_amd64.deb", &components),
+            "main"
+        );
+        assert_eq!(
+            upstream_component(
+                "pool/updates/main/h/hello/hello_2.10-3_amd64.deb",
+                &components
+            ),
+            "updates-main"
+        );
+        assert_eq!(
+            upstream_component("pool/h/hello/hello_2.10-3_amd64.deb", &components),
+            "contrib"
+        );
+    }
+}
@@ -4,10 +4,10 @@ use axum::http::StatusCode;
 use clap::Args;
 use tabled::settings::Style;
 
-use crate::config::Config;
+use crate::{config::Config, template::Template};
 use attune::{
-    api::ErrorResponse,
-    server::repo::list::{ListRepositoryRequest, ListRepositoryResponse},
+    api::{ErrorResponse, PageParams},
+    server::repo::list::{ListRepositoryRequest, ListRepositoryResponse, Repository},
 };
 
 #[derive(Args, Debug)]
@@ -16,31 +16,61 @@ pub struct RepoListCommand {
     #[arg(long)]
     json: bool,
 
+    /// Print each repository with a template such as `{name}`, instead of a
+    /// table. Placeholders name fields of the JSON output.
+    #[arg(long, conflicts_with = "json", value_parser = Template::parse::<Repository>)]
+    format: Option<Template>,
+
     /// Filter repositories by name (substring match).
     #[arg(long)]
     name: Option<String>,
+
+    /// Only list this many repositories, instead of every matching
+    /// repository.
+    #[arg(long)]
+    limit: Option<i64>,
 }
 
 pub async fn run(ctx: Config, cmd: RepoListCommand) -> ExitCode {
-    let res = ctx
-        .client
-        .get(ctx.endpoint.join("/api/v0/repositories").unwrap())
-        .json(&ListRepositoryRequest { name: cmd.name })
-        .send()
-        .await
-        .expect("Could not send API request");
-    match res.status() {
-        StatusCode::OK => {
-            let res = res
-                .json::<ListRepositoryResponse>()
-                .await
-                .expect("Could not parse response");
+    let req = ListRepositoryRequest { name: cmd.name };
+    let mut page = PageParams {
+        limit: cmd.limit,
+        cursor: None,
+    };
+    let mut res = ListRepositoryResponse {
+        repositories: Vec::new(),
+        next_cursor: None,
+    };
+    // Follow cursors to the last page, unless a limit was given.
+    let res = loop {
+        match list_page(&ctx, &req, &page).await {
+            Ok(next) => {
+                res.repositories.extend(next.repositories);
+                match next.next_cursor {
+                    Some(cursor) if cmd.limit.is_none() => page.cursor = Some(cursor),
+                    next_cursor => {
+                        res.next_cursor = next_cursor;
+                        break Ok(res);
+                    }
+                }
+            }
+            Err(error) => break Err(error),
+        }
+    };
+    match res {
+        Ok(res) => {
             // TODO: In the managed cloud version of this CLI, we should hide
             // the S3 bucket and prefix fields because they're irrelevant.
             if cmd.json {
                 println!("{}", serde_json::to_string_pretty(&res).unwrap());
                 return ExitCode::SUCCESS;
             }
+            if let Some(format) = cmd.format {
+                for repo in &res.repositories {
+                    println!("{}", format.render(repo));
+                }
+                return ExitCode::SUCCESS;
+            }
             let mut builder = tabled::builder::Builder::new();
             builder.push_record([
                 String::from("Name"),
@@ -55,13 +85,34 @@ pub async fn run(ctx: Config, cmd: RepoListCommand) -> ExitCode {
             println!("{table}");
             ExitCode::SUCCESS
         }
-        _ => {
-            let error = res
-                .json::<ErrorResponse>()
-                .await
-                .expect("Could not parse error response");
+        Err(error) => {
             eprintln!("Error listing repositories: {}", error.message);
             ExitCode::FAILURE
         }
     }
 }
+
+async fn list_page(
+    ctx: &Config,
+    req: &ListRepositoryRequest,
+    page: &PageParams,
+) -> Result<ListRepositoryResponse, ErrorResponse> {
+    let res = ctx
+        .client
+        .get(ctx.url("/api/v0/repositories").unwrap())
+        .query(page)
+        .json(req)
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => Ok(res
+            .json::<ListRepositoryResponse>()
+            .await
+            .expect("Could not parse response")),
+        _ => Err(res
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not parse error response")),
+    }
+}
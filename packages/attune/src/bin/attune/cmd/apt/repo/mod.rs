@@ -4,10 +4,19 @@ use clap::{Args, Subcommand};
 
 use crate::config::Config;
 
+mod clone;
 mod create;
 mod delete;
+mod diff;
+pub(crate) mod du;
 mod edit;
+mod export;
+mod import;
 mod list;
+mod resync;
+mod stats;
+mod status;
+mod watch;
 
 #[derive(Args, Debug)]
 pub struct RepoCommand {
@@ -20,6 +29,9 @@ pub enum RepoSubCommand {
     /// Create a new repository
     #[command(visible_aliases = ["new", "add"])]
     Create(create::RepoCreateCommand),
+    /// Create a new repository with the same distributions and components as
+    /// an existing one
+    Clone(clone::RepoCloneCommand),
     /// Show information about repositories
     #[command(visible_alias = "ls")]
     List(list::RepoListCommand),
@@ -29,13 +41,58 @@ pub enum RepoSubCommand {
     /// Delete a repository
     #[command(visible_alias = "rm")]
     Delete(delete::RepoDeleteCommand),
+    /// Show which packages differ between two distributions
+    Diff(diff::RepoDiffCommand),
+    /// Export a repository's published files to a tarball
+    ///
+    /// The tarball contains the `dists/` and `pool/` trees exactly as they are
+    /// published, for transferring a repository to an air-gapped network.
+    Export(export::RepoExportCommand),
+    /// Import packages from an upstream APT repository
+    ///
+    /// Only packages that aren't already published are downloaded, so the
+    /// import can be rerun periodically to keep a mirror up to date.
+    Import(Box<import::RepoImportCommand>),
+    /// Show how much storage a repository uses
+    Du(du::RepoDuCommand),
+    /// Show how many packages a repository publishes, by distribution,
+    /// component, and architecture
+    Stats(stats::RepoStatsCommand),
+    /// Resynchronize every distribution of a repository from the database
+    ///
+    /// Distributions whose published files don't match the database, e.g.
+    /// after a crash, are rewritten. A distribution that fails doesn't stop
+    /// the others.
+    Resync(resync::RepoResyncCommand),
+    /// Show when each distribution last changed, and the packages staged for
+    /// it
+    ///
+    /// Package changes only take effect once their index is signed, so an
+    /// interrupted `pkg add` or `pkg remove` leaves the distribution as it
+    /// was. Interrupted promotions are reported here.
+    Status(status::RepoStatusCommand),
+    /// Publish `.deb` files from a directory whenever they change
+    ///
+    /// Useful for iterating on a package against a development repository:
+    /// each rebuilt package is added to the distribution as soon as the build
+    /// finishes.
+    Watch(watch::RepoWatchCommand),
 }
 
 pub async fn handle_repo(ctx: Config, command: RepoCommand) -> ExitCode {
     match command.subcommand {
         RepoSubCommand::Create(create) => create::run(ctx, create).await,
+        RepoSubCommand::Clone(clone) => clone::run(ctx, clone).await,
         RepoSubCommand::List(list) => list::run(ctx, list).await,
         RepoSubCommand::Edit(edit) => edit::run(ctx, edit).await,
         RepoSubCommand::Delete(delete) => delete::run(ctx, delete).await,
+        RepoSubCommand::Diff(diff) => diff::run(ctx, diff).await,
+        RepoSubCommand::Export(export) => export::run(ctx, export).await,
+        RepoSubCommand::Import(import) => import::run(ctx, *import).await,
+        RepoSubCommand::Du(du) => du::run(ctx, du).await,
+        RepoSubCommand::Stats(stats) => stats::run(ctx, stats).await,
+        RepoSubCommand::Resync(resync) => resync::run(ctx, resync).await,
+        RepoSubCommand::Status(status) => status::run(ctx, status).await,
+        RepoSubCommand::Watch(watch) => watch::run(ctx, watch).await,
     }
 }
@@ -0,0 +1,119 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+use colored::Colorize as _;
+use percent_encoding::percent_encode;
+use tabled::settings::Style;
+
+use crate::config::Config;
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::sync::{
+        InconsistentSummary,
+        resync::{DistributionResyncOutcome, ResyncAllDistributionsResponse, ResyncParams},
+    },
+};
+
+#[derive(Args, Debug)]
+pub struct RepoResyncCommand {
+    /// The name of the repository.
+    #[arg(long)]
+    repo: String,
+    /// Rewrite every object of every distribution, not only the inconsistent
+    /// ones.
+    ///
+    /// Use this after upgrading Attune to apply changes in how objects are
+    /// written to an existing repository.
+    #[arg(long)]
+    force: bool,
+
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
+}
+
+// TODO: Like `dist resync`, this only makes sense for self-hosted deployments.
+pub async fn run(ctx: Config, command: RepoResyncCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .post(
+            ctx.url(&format!(
+                "/api/v0/repositories/{}/sync",
+                percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+            ))
+            .unwrap(),
+        )
+        .query(&ResyncParams {
+            force: command.force,
+        })
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let res = res
+                .json::<ResyncAllDistributionsResponse>()
+                .await
+                .expect("Could not parse response");
+            let failed = res
+                .distributions
+                .iter()
+                .filter(|dist| matches!(dist.outcome, DistributionResyncOutcome::Failed { .. }))
+                .count();
+            if command.json {
+                println!("{}", serde_json::to_string_pretty(&res).unwrap());
+            } else if res.distributions.is_empty() {
+                println!("Repository {:?} has no distributions", command.repo);
+            } else {
+                let mut builder = tabled::builder::Builder::new();
+                builder.push_record(["Distribution", "Result"]);
+                for dist in &res.distributions {
+                    let result = match &dist.outcome {
+                        DistributionResyncOutcome::Consistent => String::from("consistent"),
+                        DistributionResyncOutcome::Resynced { status } => {
+                            format!("resynced, {} objects rewritten", rewritten_objects(status))
+                        }
+                        DistributionResyncOutcome::Failed { error } => {
+                            format!("failed: {error}").red().to_string()
+                        }
+                    };
+                    builder.push_record([dist.distribution.clone(), result]);
+                }
+                let mut table = builder.build();
+                table.with(Style::modern());
+                println!("{table}");
+                println!(
+                    "{} objects rewritten in total",
+                    rewritten_objects(&res.total)
+                );
+            }
+            if failed > 0 {
+                eprintln!("Error: {failed} distributions could not be resynced");
+                return ExitCode::FAILURE;
+            }
+            ExitCode::SUCCESS
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error resyncing repository: {}", error.message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn rewritten_objects(status: &InconsistentSummary) -> usize {
+    [
+        status.release,
+        status.release_clearsigned,
+        status.release_detachsigned,
+    ]
+    .into_iter()
+    .filter(|rewritten| *rewritten)
+    .count()
+        + status.packages_indexes.len()
+        + status.packages.len()
+}
@@ -0,0 +1,97 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+use percent_encoding::percent_encode;
+use tabled::settings::Style;
+
+use super::du::format_bytes;
+use crate::config::Config;
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::stats::{PackageStats, RepositoryStatsResponse},
+};
+
+#[derive(Args, Debug)]
+pub struct RepoStatsCommand {
+    /// The name of the repository.
+    #[arg(long)]
+    repo: String,
+
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(ctx: Config, command: RepoStatsCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .get(
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/stats",
+                    percent_encode(command.repo.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+                )
+                .as_str(),
+            )
+            .unwrap(),
+        )
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let res = res
+                .json::<RepositoryStatsResponse>()
+                .await
+                .expect("Could not parse response");
+            if command.json {
+                println!("{}", serde_json::to_string_pretty(&res).unwrap());
+                return ExitCode::SUCCESS;
+            }
+            let mut builder = tabled::builder::Builder::new();
+            builder.push_record([
+                "Distribution",
+                "Component",
+                "Architecture",
+                "Packages",
+                "Package names",
+                "Size",
+            ]);
+            for row in &res.breakdown {
+                builder.push_record(stats_record(
+                    [&row.distribution, &row.component, &row.architecture],
+                    &row.stats,
+                ));
+            }
+            builder.push_record(stats_record(["(total)", "", ""], &res.total));
+            let mut table = builder.build();
+            table.with(Style::modern());
+            println!("{table}");
+            println!(
+                "Packages published in several distributions or components are counted once in the total."
+            );
+            ExitCode::SUCCESS
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error getting repository stats: {}", error.message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn stats_record(scope: [&str; 3], stats: &PackageStats) -> [String; 6] {
+    let [distribution, component, architecture] = scope;
+    [
+        distribution.to_string(),
+        component.to_string(),
+        architecture.to_string(),
+        stats.packages.to_string(),
+        stats.package_names.to_string(),
+        format_bytes(stats.bytes),
+    ]
+}
@@ -0,0 +1,131 @@
+use std::process::ExitCode;
+
+use axum::http::StatusCode;
+use clap::Args;
+use colored::Colorize as _;
+use percent_encoding::percent_encode;
+use tabled::settings::Style;
+use time::format_description::well_known::Rfc3339;
+
+use crate::config::Config;
+use attune::{
+    api::{ErrorResponse, PATH_SEGMENT_PERCENT_ENCODE_SET},
+    server::repo::status::{RepositoryStatusResponse, StagedAction},
+};
+
+#[derive(Args, Debug)]
+pub struct RepoStatusCommand {
+    /// The name of the repository.
+    name: String,
+
+    /// Output in JSON format.
+    #[arg(long)]
+    json: bool,
+}
+
+pub async fn run(ctx: Config, command: RepoStatusCommand) -> ExitCode {
+    let res = ctx
+        .client
+        .get(
+            ctx.url(
+                format!(
+                    "/api/v0/repositories/{}/status",
+                    percent_encode(command.name.as_bytes(), PATH_SEGMENT_PERCENT_ENCODE_SET)
+                )
+                .as_str(),
+            )
+            .unwrap(),
+        )
+        .send()
+        .await
+        .expect("Could not send API request");
+    match res.status() {
+        StatusCode::OK => {
+            let res = res
+                .json::<RepositoryStatusResponse>()
+                .await
+                .expect("Could not parse response");
+            if command.json {
+                println!("{}", serde_json::to_string_pretty(&res).unwrap());
+                return ExitCode::SUCCESS;
+            }
+            if res.distributions.is_empty() {
+                println!("Repository {:?} has no distributions", command.name);
+                return ExitCode::SUCCESS;
+            }
+
+            let mut builder = tabled::builder::Builder::new();
+            builder.push_record(["Distribution", "Last updated", "Staged packages"]);
+            for dist in &res.distributions {
+                builder.push_record([
+                    dist.distribution.clone(),
+                    dist.updated_at.format(&Rfc3339).unwrap(),
+                    match &dist.staging_distribution {
+                        Some(_) => dist.staged.len().to_string(),
+                        None => String::from("-"),
+                    },
+                ]);
+            }
+            let mut table = builder.build();
+            table.with(Style::modern());
+            println!("{table}");
+
+            for dist in &res.distributions {
+                let Some(staging) = &dist.staging_distribution else {
+                    continue;
+                };
+                if dist.staged.is_empty() {
+                    continue;
+                }
+                println!();
+                println!("Staged in {staging} for {}:", dist.distribution);
+                let mut builder = tabled::builder::Builder::new();
+                builder.push_record([
+                    "Component",
+                    "Package",
+                    "Version",
+                    "Architecture",
+                    "On promotion",
+                ]);
+                for package in &dist.staged {
+                    builder.push_record([
+                        package.component.as_str(),
+                        package.name.as_str(),
+                        package.version.as_str(),
+                        package.architecture.as_str(),
+                        match package.action {
+                            StagedAction::Add => "add",
+                            StagedAction::Remove => "remove from staging",
+                        },
+                    ]);
+                }
+                let mut table = builder.build();
+                table.with(Style::modern());
+                println!("{table}");
+                if dist
+                    .staged
+                    .iter()
+                    .any(|package| package.action == StagedAction::Remove)
+                {
+                    println!(
+                        "{}",
+                        format!(
+                            "Some staged packages are already in {0}, so a promotion was interrupted. Run `attune apt dist promote --repo {1} --to {0}` to finish it.",
+                            dist.distribution, command.name
+                        )
+                        .yellow()
+                    );
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        _ => {
+            let error = res
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not parse error response");
+            eprintln!("Error getting repository status: {}", error.message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use clap::Args;
+use colored::Colorize as _;
+use notify::{Event, EventKind, RecursiveMode, Watcher as _};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::{
+    cmd::apt::pkg::add::{
+        PkgAddCommand, add_package_file, resolve_component, validate_repository_exists,
+    },
+    config::Config,
+};
+
+#[derive(Args, Debug)]
+pub struct RepoWatchCommand {
+    /// Name of the repository to publish packages to
+    #[arg(long, short)]
+    repo: String,
+    /// Distribution to publish packages to
+    #[arg(long, short, default_value = "stable")]
+    distribution: String,
+    /// Component to publish packages to
+    ///
+    /// If not set, the distribution's default component is used, or "main" if
+    /// the distribution doesn't have one.
+    #[arg(long, short)]
+    component: Option<String>,
+
+    /// GPG key ID to sign the index with (see `gpg --list-secret-keys`)
+    ///
+    /// If not set, `$ATTUNE_GPG_KEY_ID` or the `key_id` from the config file is
+    /// used. If neither is set and there is only one signing key available,
+    /// that key will be used. Otherwise, the command will fail. Repeat this
+    /// flag to sign with several keys, for example while rotating signing
+    /// keys.
+    #[arg(long, short)]
+    key_id: Vec<String>,
+    /// GPG home directory to use for signing.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+
+    /// Replace published packages with rebuilds that have the same name,
+    /// version, and architecture.
+    ///
+    /// Set this when rebuilding packages without bumping their versions.
+    #[arg(long)]
+    replace: bool,
+
+    /// How long to wait for changes to settle before publishing, in
+    /// milliseconds.
+    ///
+    /// Changes made while waiting are coalesced, so a package that is rebuilt
+    /// several times in quick succession is only published once, with its
+    /// latest contents.
+    #[arg(long, default_value_t = 500)]
+    debounce_ms: u64,
+
+    /// Directory to watch for `.deb` files, including its subdirectories
+    dir: PathBuf,
+}
+
+pub async fn run(ctx: Config, cmd: RepoWatchCommand) -> ExitCode {
+    if !cmd.dir.is_dir() {
+        eprintln!("Error: {:?} is not a directory", cmd.dir);
+        return ExitCode::FAILURE;
+    }
+
+    // Each changed package is published with the same flow as `pkg add`, so
+    // check the repository and resolve the component once up front.
+    let add = PkgAddCommand::builder()
+        .repo(&cmd.repo)
+        .distribution(&cmd.distribution)
+        .maybe_component(cmd.component.clone())
+        .key_id(cmd.key_id.clone())
+        .maybe_gpg_home_dir(cmd.gpg_home_dir.clone())
+        .replace(cmd.replace)
+        .package_file(cmd.dir.to_string_lossy())
+        .build();
+    match validate_repository_exists(&ctx, &add).await {
+        Ok(true) => {}
+        Ok(false) => {
+            eprintln!("Error: repository {:?} does not exist", cmd.repo);
+            return ExitCode::FAILURE;
+        }
+        Err(error) => {
+            eprintln!("Unable to validate repository: {error:#?}");
+            return ExitCode::FAILURE;
+        }
+    }
+    let add = match resolve_component(&ctx, &add).await {
+        Ok(component) => PkgAddCommand {
+            component: Some(component),
+            ..add
+        },
+        Err(error) => {
+            eprintln!("Unable to resolve component: {error:#?}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // The watcher calls back from its own thread, so forward its events into
+    // the async loop below.
+    let (events_tx, mut events) = mpsc::unbounded_channel();
+    let mut watcher = match notify::recommended_watcher(move |event| {
+        let _ = events_tx.send(event);
+    }) {
+        Ok(watcher) => watcher,
+        Err(error) => {
+            eprintln!("Unable to watch {:?}: {error}", cmd.dir);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(error) = watcher.watch(&cmd.dir, RecursiveMode::Recursive) {
+        eprintln!("Unable to watch {:?}: {error}", cmd.dir);
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "Watching {} for .deb files to publish to {} {} {}. Press Ctrl-C to stop.",
+        cmd.dir.display(),
+        cmd.repo,
+        cmd.distribution,
+        add.component.as_deref().unwrap_or_default(),
+    );
+
+    let debounce = Duration::from_millis(cmd.debounce_ms);
+    while let Some(event) = events.recv().await {
+        // Wait until nothing has changed for the debounce interval, so that
+        // partially written files aren't published, and each package file is
+        // published once no matter how many times it changed.
+        let mut changed = BTreeSet::new();
+        changed.extend(changed_package_files(event));
+        while let Ok(Some(event)) = tokio::time::timeout(debounce, events.recv()).await {
+            changed.extend(changed_package_files(event));
+        }
+
+        for path in changed {
+            // The file may have been removed or renamed while waiting.
+            if !path.is_file() {
+                debug!(?path, "changed package file no longer exists");
+                continue;
+            }
+            println!("{} {}", "Publishing".cyan(), path.display());
+            let command = PkgAddCommand {
+                package_file: path.to_string_lossy().to_string(),
+                ..add.clone()
+            };
+            match add_package_file(&ctx, &command).await {
+                Ok(sha256sum) => {
+                    println!("{} {} ({sha256sum})", "Published".green(), path.display())
+                }
+                Err(message) => eprintln!("{} {}: {message}", "Failed".red(), path.display()),
+            }
+        }
+        println!("Waiting for changes...");
+    }
+
+    // The channel only closes if the watcher stops.
+    eprintln!("Error: stopped watching {:?}", cmd.dir);
+    ExitCode::FAILURE
+}
+
+/// The `.deb` files that a filesystem event created or changed.
+fn changed_package_files(event: notify::Result<Event>) -> Vec<PathBuf> {
+    match event {
+        Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => event
+            .paths
+            .into_iter()
+            .filter(|path| is_package_file(path))
+            .collect(),
+        Ok(_) => Vec::new(),
+        Err(error) => {
+            eprintln!("{} {error}", "Watch error:".yellow());
+            Vec::new()
+        }
+    }
+}
+
+fn is_package_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "deb")
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode};
+
+    use super::*;
+
+    #[test]
+    fn only_created_or_changed_packages() {
+        let event = |kind, path: &str| Ok(Event::new(kind).add_path(PathBuf::from(path)));
+
+        assert_eq!(
+            changed_package_files(event(
+                EventKind::Create(CreateKind::File),
+                "build/foo_1.0_amd64.deb"
+            )),
+            [PathBuf::from("build/foo_1.0_amd64.deb")]
+        );
+        assert_eq!(
+            changed_package_files(event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                "build/foo_1.0_amd64.deb"
+            )),
+            [PathBuf::from("build/foo_1.0_amd64.deb")]
+        );
+        // Packages written to a temporary file and then renamed into place
+        // are published under their final name.
+        assert_eq!(
+            changed_package_files(Ok(Event::new(EventKind::Modify(ModifyKind::Name(
+                RenameMode::Both
+            )))
+            .add_path(PathBuf::from("build/.foo.tmp"))
+            .add_path(PathBuf::from("build/foo_1.0_amd64.deb")))),
+            [PathBuf::from("build/foo_1.0_amd64.deb")]
+        );
+        assert!(
+            changed_package_files(event(
+                EventKind::Remove(RemoveKind::File),
+                "build/foo_1.0_amd64.deb"
+            ))
+            .is_empty()
+        );
+        assert!(
+            changed_package_files(event(EventKind::Create(CreateKind::File), "build/foo.o"))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::{path::Path, process::ExitCode};
+
+use clap::{Args, Subcommand, ValueEnum};
+use color_eyre::eyre::{Context as _, Result};
+use reqwest::Url;
+
+use crate::config::{ConfigFile, DEFAULT_API_ENDPOINT, Profile};
+
+#[derive(Args, Debug)]
+pub struct ConfigCommand {
+    #[command(subcommand)]
+    subcommand: ConfigSubcommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigSubcommand {
+    /// Set a value in the config file
+    ///
+    /// Values are set in the profile selected with `--profile`, or in the
+    /// `[default]` section if no profile is selected.
+    Set(SetArgs),
+    /// Show the values of a profile
+    ///
+    /// Shows the values of the profile selected with `--profile`, including
+    /// those it uses from the `[default]` section. Flags and environment
+    /// variables aren't taken into account.
+    Show,
+}
+
+#[derive(Args, Debug)]
+struct SetArgs {
+    /// The key to set.
+    key: ConfigKey,
+    /// The value to set. An empty value removes the key.
+    value: String,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ConfigKey {
+    /// Attune API endpoint.
+    Endpoint,
+    /// Attune API token.
+    Token,
+    /// GPG key ID to sign with when commands aren't given a `--key-id`.
+    KeyId,
+}
+
+pub fn run(path: &Path, profile: Option<&str>, command: ConfigCommand) -> ExitCode {
+    let result = match command.subcommand {
+        ConfigSubcommand::Set(args) => set(path, profile, args),
+        ConfigSubcommand::Show => show(path, profile),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn set(path: &Path, profile: Option<&str>, args: SetArgs) -> Result<()> {
+    let mut file = ConfigFile::load(path)?;
+    let value = Some(args.value).filter(|value| !value.is_empty());
+    if let (ConfigKey::Endpoint, Some(endpoint)) = (args.key, &value) {
+        Url::parse(endpoint).with_context(|| format!("invalid API endpoint {endpoint:?}"))?;
+    }
+
+    let values = file.profile_mut(profile);
+    match args.key {
+        ConfigKey::Endpoint => values.endpoint = value,
+        ConfigKey::Token => values.token = value,
+        ConfigKey::KeyId => values.key_id = value,
+    }
+    file.save(path)?;
+    eprintln!("Updated {}", path.display());
+    Ok(())
+}
+
+fn show(path: &Path, profile: Option<&str>) -> Result<()> {
+    let values = ConfigFile::load(path)?.profile(profile)?;
+    println!("{}", format_profile(path, profile, &values));
+    Ok(())
+}
+
+fn format_profile(path: &Path, profile: Option<&str>, values: &Profile) -> String {
+    let endpoint = match &values.endpoint {
+        Some(endpoint) => endpoint.clone(),
+        None => format!("{DEFAULT_API_ENDPOINT} (built-in default)"),
+    };
+    let token = match &values.token {
+        Some(token) => mask_token(token),
+        None => String::from("(not set)"),
+    };
+    let key_id = values.key_id.as_deref().unwrap_or("(not set)");
+    [
+        format!("Config file: {}", path.display()),
+        format!("Profile: {}", profile.unwrap_or("default")),
+        format!("endpoint = {endpoint}"),
+        format!("token = {token}"),
+        format!("key_id = {key_id}"),
+    ]
+    .join("\n")
+}
+
+/// Hide all but the last 4 characters of an API token.
+fn mask_token(token: &str) -> String {
+    let visible = token.len().saturating_sub(4);
+    match token.get(visible..) {
+        Some(suffix) if visible > 0 => format!("****{suffix}"),
+        _ => String::from("****"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn format_profile_masks_token() {
+        let values = Profile {
+            endpoint: None,
+            token: Some(String::from("attune-secret-1234")),
+            key_id: Some(String::from("ABCD1234")),
+        };
+        assert_eq!(
+            format_profile(
+                &PathBuf::from("/home/user/.config/attune/config.toml"),
+                Some("staging"),
+                &values
+            ),
+            [
+                "Config file: /home/user/.config/attune/config.toml",
+                "Profile: staging",
+                "endpoint = https://api.attunehq.com (built-in default)",
+                "token = ****1234",
+                "key_id = ABCD1234",
+            ]
+            .join("\n")
+        );
+        assert_eq!(mask_token("1234"), "****");
+    }
+}
@@ -0,0 +1,222 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use attune::{api::ErrorResponse, server::compatibility::CompatibilityResponse};
+use axum::http::StatusCode;
+use clap::Args;
+use color_eyre::eyre::{Context as _, Result, bail};
+use colored::Colorize as _;
+use gpgme::{Context, Protocol};
+
+use crate::{SigningBackend, config::Config, gpg_sign};
+
+#[derive(Args, Debug)]
+pub struct DoctorCommand {
+    /// GPG key ID to check (see `gpg --list-secret-keys`)
+    ///
+    /// If not set, checks the `key_id` from the config file. If that isn't set
+    /// either, checks that exactly one signing key is available, since that's
+    /// the key other commands use when no key ID is given.
+    #[arg(long, short)]
+    key_id: Option<String>,
+    /// GPG home directory to check.
+    ///
+    /// If not set, defaults to the standard GPG home directory
+    /// for the platform.
+    #[arg(long, short, visible_alias = "gpg-home", env = "ATTUNE_GPG_HOME")]
+    gpg_home_dir: Option<String>,
+}
+
+/// The outcome of a single diagnostic check.
+enum Status {
+    Pass(String),
+    Warn(String),
+    Fail { problem: String, hint: &'static str },
+    Skip(&'static str),
+}
+
+/// Diagnose common setup problems, printing a checklist of results.
+///
+/// Unlike other commands, this runs without first checking API compatibility,
+/// since an unreachable or incompatible API server is one of the problems it
+/// diagnoses.
+pub async fn run(ctx: Config, command: DoctorCommand) -> ExitCode {
+    let api = check_api(&ctx).await;
+    let token = match api {
+        Status::Pass(_) | Status::Warn(_) => check_api_token(&ctx).await,
+        _ => Status::Skip("API server is not usable"),
+    };
+    let (passphrase_file, default_key_id) = match ctx.signing_backend {
+        SigningBackend::Gpg {
+            passphrase_file,
+            default_key_id,
+        } => (passphrase_file, default_key_id),
+        SigningBackend::Kms { .. } => (None, None),
+    };
+    let key_id = command.key_id.or(default_key_id);
+    let key = check_gpg_key(command.gpg_home_dir.clone(), key_id.clone()).await;
+    let signing = match key {
+        Status::Pass(_) => check_gpg_sign(command.gpg_home_dir, key_id, passphrase_file).await,
+        _ => Status::Skip("no usable GPG signing key"),
+    };
+
+    let checks = [
+        ("API server is reachable and compatible", api),
+        ("API token is valid", token),
+        ("GPG signing key is available", key),
+        ("GPG can sign a test payload", signing),
+    ];
+    let mut failed = false;
+    for (name, status) in checks {
+        match status {
+            Status::Pass(detail) => println!("{} {name}: {detail}", "[PASS]".green()),
+            Status::Warn(detail) => println!("{} {name}: {detail}", "[WARN]".yellow()),
+            Status::Fail { problem, hint } => {
+                failed = true;
+                println!("{} {name}: {problem}", "[FAIL]".red());
+                println!("       hint: {hint}");
+            }
+            Status::Skip(reason) => {
+                failed = true;
+                println!("{} {name}: skipped because {reason}", "[SKIP]".dimmed());
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+async fn check_api(ctx: &Config) -> Status {
+    let res = match ctx
+        .client
+        .get(ctx.url("/api/v0/compatibility").unwrap())
+        .send()
+        .await
+    {
+        Ok(res) => res,
+        Err(err) => {
+            return Status::Fail {
+                problem: format!("could not reach {}: {err}", ctx.endpoint),
+                hint: "check that ATTUNE_API_ENDPOINT (or --api-endpoint) points at a running Attune API server",
+            };
+        }
+    };
+    match res.status() {
+        StatusCode::OK => match res.json::<CompatibilityResponse>().await {
+            Ok(CompatibilityResponse::Ok) => {
+                Status::Pass(format!("{} is compatible", ctx.endpoint))
+            }
+            Ok(CompatibilityResponse::WarnUpgrade { latest }) => Status::Warn(format!(
+                "{} is compatible, but a new version of attune is available: {latest}",
+                ctx.endpoint
+            )),
+            Ok(CompatibilityResponse::Incompatible { minimum }) => Status::Fail {
+                problem: format!("CLI is too old for {} (minimum {minimum:?})", ctx.endpoint),
+                hint: "upgrade the attune CLI",
+            },
+            Err(err) => Status::Fail {
+                problem: format!("could not parse compatibility response: {err}"),
+                hint: "check that ATTUNE_API_ENDPOINT (or --api-endpoint) points at an Attune API server, not some other service",
+            },
+        },
+        status => Status::Fail {
+            problem: format!("compatibility check returned {status}"),
+            hint: "check that ATTUNE_API_ENDPOINT (or --api-endpoint) points at an Attune API server, not some other service",
+        },
+    }
+}
+
+async fn check_api_token(ctx: &Config) -> Status {
+    // Listing repositories is a read-only call that requires authentication.
+    let res = match ctx
+        .client
+        .get(ctx.url("/api/v0/repositories").unwrap())
+        .send()
+        .await
+    {
+        Ok(res) => res,
+        Err(err) => {
+            return Status::Fail {
+                problem: format!("could not send authenticated request: {err}"),
+                hint: "check your network connection to the API server",
+            };
+        }
+    };
+    match res.status() {
+        StatusCode::OK => Status::Pass(String::from("authenticated request succeeded")),
+        StatusCode::UNAUTHORIZED => Status::Fail {
+            problem: String::from("API server rejected the token"),
+            hint: "check that ATTUNE_API_TOKEN (or --api-token) is set to a valid API token",
+        },
+        status => Status::Fail {
+            problem: match res.json::<ErrorResponse>().await {
+                Ok(error) => format!("authenticated request returned {status}: {}", error.message),
+                Err(_) => format!("authenticated request returned {status}"),
+            },
+            hint: "check the API server's logs for details",
+        },
+    }
+}
+
+async fn check_gpg_key(gpg_home_dir: Option<String>, key_id: Option<String>) -> Status {
+    let keys = tokio::task::spawn_blocking(move || list_secret_keys(gpg_home_dir, key_id))
+        .await
+        .context("join background thread");
+    match keys.and_then(|keys| keys) {
+        Ok(keys) => Status::Pass(format!("using key {}", keys.join(", "))),
+        Err(err) => Status::Fail {
+            problem: format!("{err:#}"),
+            hint: "create a signing key with `gpg --quick-generate-key`, or pass --key-id to choose one of several keys",
+        },
+    }
+}
+
+/// List the IDs of the secret keys that signing would use.
+fn list_secret_keys(gpg_home_dir: Option<String>, key_id: Option<String>) -> Result<Vec<String>> {
+    let mut gpg = Context::from_protocol(Protocol::OpenPgp).context("create gpg context")?;
+    if let Some(gpg_home_dir) = gpg_home_dir {
+        gpg.set_engine_home_dir(&gpg_home_dir)
+            .with_context(|| format!("set engine home dir to: {gpg_home_dir:?}"))?;
+    }
+    let keys = gpg
+        .find_secret_keys(key_id.as_slice())
+        .context("list secret keys")?
+        .collect::<Result<Vec<_>, _>>()
+        .context("get secret key from list")?
+        .into_iter()
+        .map(|key| key.id().unwrap_or("(unknown)").to_string())
+        .collect::<Vec<_>>();
+    match (key_id, keys.len()) {
+        (Some(key_id), 0) => bail!("no secret key found with ID {key_id:?}"),
+        (None, 0) => bail!("no secret keys found"),
+        (None, 2..) => bail!(
+            "no GPG key ID specified and multiple GPG keys found: {}",
+            keys.join(", ")
+        ),
+        _ => Ok(keys),
+    }
+}
+
+async fn check_gpg_sign(
+    gpg_home_dir: Option<String>,
+    key_id: Option<String>,
+    passphrase_file: Option<PathBuf>,
+) -> Status {
+    match gpg_sign(
+        gpg_home_dir,
+        key_id,
+        passphrase_file,
+        "attune doctor test payload",
+    )
+    .await
+    {
+        Ok(_) => Status::Pass(String::from("clearsigned and detach-signed test payload")),
+        Err(err) => Status::Fail {
+            problem: format!("{err:#}"),
+            hint: "check that the key isn't expired and that gpg-agent can unlock it without a passphrase prompt",
+        },
+    }
+}
@@ -1 +1,3 @@
 pub mod apt;
+pub mod config;
+pub mod doctor;
@@ -1,11 +1,218 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    os::unix::fs::{OpenOptionsExt as _, PermissionsExt as _},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
 use attune::server::compatibility::{API_VERSION_HEADER, API_VERSION_HEADER_V0_2_0};
+use color_eyre::eyre::{Context as _, OptionExt as _, Result, bail};
 use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::SigningBackend;
+
+/// The API endpoint used when none is configured.
+pub const DEFAULT_API_ENDPOINT: &str = "https://api.attunehq.com";
+
+/// Defaults for the CLI's options, from a profile in the config file.
+///
+/// Flags and environment variables take precedence over these.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Attune API endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Attune API token.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    /// GPG key ID to sign with when commands aren't given a `--key-id`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+impl Profile {
+    /// Fill in the values that aren't set from `defaults`.
+    fn or(self, defaults: &Profile) -> Self {
+        Self {
+            endpoint: self.endpoint.or_else(|| defaults.endpoint.clone()),
+            token: self.token.or_else(|| defaults.token.clone()),
+            key_id: self.key_id.or_else(|| defaults.key_id.clone()),
+        }
+    }
+}
+
+/// The CLI config file, e.g.:
+///
+/// ```toml
+/// [default]
+/// endpoint = "https://attune.example.com"
+/// token = "..."
+///
+/// [profile.staging]
+/// endpoint = "https://attune.staging.example.com"
+/// key_id = "ABCD1234"
+/// ```
+///
+/// Named profiles use the values of `[default]` for the keys they don't set.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub default: Profile,
+    #[serde(
+        default,
+        rename = "profile",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl ConfigFile {
+    /// The path of the config file, `$XDG_CONFIG_HOME/attune/config.toml`,
+    /// which defaults to `~/.config/attune/config.toml`.
+    pub fn path() -> Result<PathBuf> {
+        let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+            _ => std::env::home_dir()
+                .ok_or_eyre("could not find the home directory")?
+                .join(".config"),
+        };
+        Ok(config_home.join("attune").join("config.toml"))
+    }
+
+    /// Load the config file. A missing file is an empty config.
+    ///
+    /// Warns if the file holds an API token and other users can read it.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).with_context(|| format!("read config file {path:?}")),
+        };
+        let config = toml::from_str::<Self>(&contents)
+            .with_context(|| format!("parse config file {path:?}"))?;
+
+        let has_token = is_set(&config.default.token)
+            || config
+                .profiles
+                .values()
+                .any(|profile| is_set(&profile.token));
+        if has_token {
+            let mode = fs::metadata(path)
+                .with_context(|| format!("read metadata of config file {path:?}"))?
+                .permissions()
+                .mode();
+            if mode & 0o004 != 0 {
+                eprintln!(
+                    "Warning: config file {path:?} contains an API token and is readable by other users. Run `chmod 600 {}` to fix this.",
+                    path.display()
+                );
+            }
+        }
+        Ok(config)
+    }
+
+    /// Write the config file, creating its directory if needed. New files
+    /// are only readable by their owner, since they may hold API tokens.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("create config directory {dir:?}"))?;
+        }
+        let contents = toml::to_string(self).context("serialize config file")?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("open config file {path:?}"))?;
+        std::io::Write::write_all(&mut file, contents.as_bytes())
+            .with_context(|| format!("write config file {path:?}"))
+    }
+
+    /// The values of the named profile, or of `[default]` if no profile is
+    /// named.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        match name {
+            None => Ok(self.default.clone()),
+            Some(name) => match self.profiles.get(name) {
+                Some(profile) => Ok(profile.clone().or(&self.default)),
+                None => bail!("profile {name:?} is not in the config file"),
+            },
+        }
+    }
+
+    /// The values set in the named profile, or in `[default]` if no profile
+    /// is named, adding the profile if it doesn't exist.
+    pub fn profile_mut(&mut self, name: Option<&str>) -> &mut Profile {
+        match name {
+            None => &mut self.default,
+            Some(name) => self.profiles.entry(name.to_string()).or_default(),
+        }
+    }
+}
+
+/// Whether an optional value is set to something other than an empty string.
+fn is_set(value: &Option<String>) -> bool {
+    value.as_deref().is_some_and(|value| !value.is_empty())
+}
+
+/// Resolve the API token from the configured sources.
+///
+/// In order of precedence, the token is read from the output of `command`,
+/// the contents of `file`, or `token` itself. Surrounding whitespace (such as
+/// a trailing newline) is removed from tokens read from commands and files.
+pub fn resolve_api_token(
+    token: Option<String>,
+    file: Option<PathBuf>,
+    command: Option<String>,
+) -> Result<String> {
+    let token = if let Some(command) = command {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .output()
+            .with_context(|| format!("run API token command {command:?}"))?;
+        if !output.status.success() {
+            bail!(
+                "API token command {command:?} failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout)
+            .context("API token command output is not valid UTF-8")?
+            .trim()
+            .to_string()
+    } else if let Some(file) = file {
+        std::fs::read_to_string(&file)
+            .with_context(|| format!("read API token file {file:?}"))?
+            .trim()
+            .to_string()
+    } else if let Some(token) = token {
+        token
+    } else {
+        bail!(
+            "no API token provided: set ATTUNE_API_TOKEN, --api-token-file, --api-token-command, or `attune config set token`"
+        );
+    };
+
+    if token.is_empty() {
+        bail!("API token is empty");
+    }
+    Ok(token)
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub client: Client,
     pub endpoint: Url,
+    pub signing_backend: SigningBackend,
 }
 
 impl Config {
@@ -13,8 +220,14 @@ impl Config {
         let api_token = api_token.into();
         let endpoint = endpoint.into();
 
-        // Parse server API endpoint.
-        let endpoint = Url::parse(&endpoint).expect("Invalid Attune API endpoint");
+        // Parse server API endpoint. The endpoint may have a base path (e.g.
+        // when Attune is served under a subpath by a reverse proxy), which
+        // must end with a slash so that API paths are joined onto it rather
+        // than replacing its last segment.
+        let mut endpoint = Url::parse(&endpoint).expect("Invalid Attune API endpoint");
+        if !endpoint.path().ends_with('/') {
+            endpoint.set_path(&format!("{}/", endpoint.path()));
+        }
 
         // Set up default headers.
         let mut headers = reqwest::header::HeaderMap::new();
@@ -39,6 +252,149 @@ impl Config {
 
         // Build default client.
         let client = Client::builder().default_headers(headers).build().unwrap();
-        Self { client, endpoint }
+        Self {
+            client,
+            endpoint,
+            signing_backend: SigningBackend::Gpg {
+                passphrase_file: None,
+                default_key_id: None,
+            },
+        }
+    }
+
+    /// Sign repository indexes with the given backend instead of GPG.
+    pub fn with_signing_backend(self, signing_backend: SigningBackend) -> Self {
+        Self {
+            signing_backend,
+            ..self
+        }
+    }
+
+    /// Resolve an API path (e.g. `/api/v0/repositories`) against the
+    /// endpoint, keeping the endpoint's base path.
+    pub fn url(&self, path: &str) -> Result<Url, url::ParseError> {
+        self.endpoint.join(path.trim_start_matches('/'))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_tempfile::TempFile;
+    use tokio::io::AsyncWriteExt as _;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn resolve_api_token_precedence() {
+        let mut file = TempFile::new().await.expect("failed to create temp file");
+        file.write_all(b"from-file\n").await.unwrap();
+        file.flush().await.unwrap();
+        let path = file.file_path().to_path_buf();
+
+        assert_eq!(
+            resolve_api_token(Some(String::from("from-flag")), None, None).unwrap(),
+            "from-flag"
+        );
+        assert_eq!(
+            resolve_api_token(Some(String::from("from-flag")), Some(path.clone()), None).unwrap(),
+            "from-file"
+        );
+        assert_eq!(
+            resolve_api_token(
+                Some(String::from("from-flag")),
+                Some(path),
+                Some(String::from("echo from-command")),
+            )
+            .unwrap(),
+            "from-command"
+        );
+    }
+
+    #[test]
+    fn url_keeps_endpoint_base_path() {
+        for (endpoint, expected) in [
+            (
+                "http://localhost:3000",
+                "http://localhost:3000/api/v0/repositories",
+            ),
+            (
+                "http://localhost:3000/",
+                "http://localhost:3000/api/v0/repositories",
+            ),
+            (
+                "https://example.com/attune",
+                "https://example.com/attune/api/v0/repositories",
+            ),
+            (
+                "https://example.com/attune/",
+                "https://example.com/attune/api/v0/repositories",
+            ),
+            (
+                "https://example.com/tools/attune/",
+                "https://example.com/tools/attune/api/v0/repositories",
+            ),
+        ] {
+            let config = Config::new("token", endpoint);
+            assert_eq!(
+                config.url("/api/v0/repositories").unwrap().as_str(),
+                expected,
+                "{endpoint}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn config_file_profiles() {
+        let dir = async_tempfile::TempDir::new()
+            .await
+            .expect("failed to create temp dir");
+        let path = dir.dir_path().join("attune").join("config.toml");
+
+        // A missing file is an empty config.
+        let mut file = ConfigFile::load(&path).unwrap();
+        assert_eq!(file, ConfigFile::default());
+        assert!(file.profile(Some("staging")).is_err());
+
+        file.profile_mut(None).endpoint = Some(String::from("https://attune.example.com"));
+        file.profile_mut(None).token = Some(String::from("default-token"));
+        file.profile_mut(Some("staging")).token = Some(String::from("staging-token"));
+        file.profile_mut(Some("staging")).key_id = Some(String::from("ABCD1234"));
+        file.save(&path).unwrap();
+
+        // Saved files are only readable by their owner, since they hold tokens.
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let file = ConfigFile::load(&path).unwrap();
+        assert_eq!(
+            file.profile(None).unwrap(),
+            Profile {
+                endpoint: Some(String::from("https://attune.example.com")),
+                token: Some(String::from("default-token")),
+                key_id: None,
+            }
+        );
+        // Named profiles fall back to the default values.
+        assert_eq!(
+            file.profile(Some("staging")).unwrap(),
+            Profile {
+                endpoint: Some(String::from("https://attune.example.com")),
+                token: Some(String::from("staging-token")),
+                key_id: Some(String::from("ABCD1234")),
+            }
+        );
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("[profile.staging]"), "{contents}");
+        fs::write(&path, "[default]\nendpont = \"typo\"\n").unwrap();
+        assert!(ConfigFile::load(&path).is_err());
+    }
+
+    #[test]
+    fn resolve_api_token_errors() {
+        assert!(resolve_api_token(None, None, None).is_err());
+        assert!(resolve_api_token(None, None, Some(String::from("exit 1"))).is_err());
+        assert!(resolve_api_token(None, None, Some(String::from("true"))).is_err());
+        assert!(resolve_api_token(None, Some(PathBuf::from("/nonexistent/token")), None).is_err());
     }
 }
@@ -0,0 +1,375 @@
+//! Signing with AWS KMS asymmetric keys.
+//!
+//! KMS only signs digests, so OpenPGP signature packets are built locally and
+//! KMS signs their hashes. The public key certificate is derived from the KMS
+//! public key, using the KMS key's creation date as the OpenPGP key creation
+//! time so that the key's fingerprint is the same every time.
+
+use std::fmt;
+
+use aws_config::BehaviorVersion;
+use aws_sdk_kms::{
+    primitives::Blob,
+    types::{KeySpec, KeyUsageType, MessageType, SigningAlgorithmSpec},
+};
+use chrono::{DateTime, SubsecRound as _, Utc};
+use color_eyre::eyre::{Context as _, OptionExt as _, Result, bail};
+use p256::pkcs8::DecodePublicKey as _;
+use pgp::{
+    composed::{
+        ArmorOptions, CleartextSignedMessage, SignedKeyDetails, SignedPublicKey,
+        StandaloneSignature,
+    },
+    crypto::{hash::HashAlgorithm, public_key::PublicKeyAlgorithm},
+    packet::{
+        KeyFlags, PubKeyInner, PublicKey, Signature, SignatureConfig, SignatureType, Subpacket,
+        SubpacketData, UserId,
+    },
+    types::{
+        EcdsaPublicParams, Fingerprint, KeyDetails, KeyId, KeyVersion, Mpi, PacketHeaderVersion,
+        Password, PublicParams, RsaPublicParams, SecretKeyTrait, SignatureBytes, SignedUser, Tag,
+    },
+};
+use tracing::debug;
+
+use crate::SignedGpgContent;
+
+/// Sign content with the AWS KMS asymmetric key with the given ARN.
+///
+/// AWS credentials and region are loaded from the environment, in the same
+/// way as the AWS CLI.
+pub async fn kms_sign(key_arn: &str, content: impl Into<Vec<u8>>) -> Result<SignedGpgContent> {
+    let content = String::from_utf8(content.into()).context("content is not valid UTF-8")?;
+    let config = aws_config::defaults(BehaviorVersion::latest()).load().await;
+    let kms = aws_sdk_kms::Client::new(&config);
+
+    let metadata = kms
+        .describe_key()
+        .key_id(key_arn)
+        .send()
+        .await
+        .context("describe KMS key")?
+        .key_metadata
+        .ok_or_eyre("KMS key has no metadata")?;
+    if metadata.key_usage() != Some(&KeyUsageType::SignVerify) {
+        bail!("KMS key {key_arn} is not a signing key");
+    }
+    let algorithm =
+        KeyAlgorithm::from_key_spec(metadata.key_spec().ok_or_eyre("KMS key has no key spec")?)?;
+    let created_at = metadata
+        .creation_date()
+        .ok_or_eyre("KMS key has no creation date")?;
+    let created_at = DateTime::from_timestamp(created_at.secs(), 0)
+        .ok_or_eyre("invalid KMS key creation date")?;
+
+    let public_key = kms
+        .get_public_key()
+        .key_id(key_arn)
+        .send()
+        .await
+        .context("get KMS public key")?
+        .public_key
+        .ok_or_eyre("KMS key has no public key")?;
+    let public_key = algorithm.public_key(public_key.as_ref(), created_at)?;
+    debug!(?public_key, fingerprint = %public_key.fingerprint(), "using KMS signing key");
+
+    // The key has no other name, so its ARN is used as the OpenPGP user ID.
+    let user_id = metadata.arn().unwrap_or(key_arn).to_string();
+
+    // Signing is synchronous in `pgp`, so each KMS request blocks a background
+    // thread until it completes.
+    let runtime = tokio::runtime::Handle::current();
+    let key_arn = key_arn.to_string();
+    let key = ExternalKey {
+        public_key,
+        algorithm,
+        sign_digest: move |digest: &[u8]| {
+            runtime.block_on(async {
+                let res = kms
+                    .sign()
+                    .key_id(&key_arn)
+                    .message(Blob::new(digest))
+                    .message_type(MessageType::Digest)
+                    .signing_algorithm(algorithm.signing_algorithm())
+                    .send()
+                    .await
+                    .context("sign digest with KMS")?;
+                Ok(res
+                    .signature
+                    .ok_or_eyre("KMS returned no signature")?
+                    .into_inner())
+            })
+        },
+    };
+    tokio::task::spawn_blocking(move || sign_with(&key, &user_id, &content))
+        .await
+        .context("join background thread")?
+}
+
+/// The kinds of KMS key that can make OpenPGP signatures.
+#[derive(Debug, Clone, Copy)]
+enum KeyAlgorithm {
+    Rsa,
+    EcdsaP256,
+}
+
+impl KeyAlgorithm {
+    fn from_key_spec(key_spec: &KeySpec) -> Result<Self> {
+        match key_spec {
+            KeySpec::Rsa2048 | KeySpec::Rsa3072 | KeySpec::Rsa4096 => Ok(Self::Rsa),
+            KeySpec::EccNistP256 => Ok(Self::EcdsaP256),
+            key_spec => bail!(
+                "unsupported KMS key spec {key_spec}: use an RSA or ECC_NIST_P256 signing key"
+            ),
+        }
+    }
+
+    fn signing_algorithm(self) -> SigningAlgorithmSpec {
+        match self {
+            Self::Rsa => SigningAlgorithmSpec::RsassaPkcs1V15Sha256,
+            Self::EcdsaP256 => SigningAlgorithmSpec::EcdsaSha256,
+        }
+    }
+
+    /// Build the OpenPGP public key packet from a DER-encoded
+    /// SubjectPublicKeyInfo, as returned by KMS.
+    fn public_key(self, der: &[u8], created_at: DateTime<Utc>) -> Result<PublicKey> {
+        let (algorithm, params) = match self {
+            Self::Rsa => (
+                PublicKeyAlgorithm::RSA,
+                PublicParams::RSA(RsaPublicParams {
+                    key: rsa::RsaPublicKey::from_public_key_der(der)
+                        .context("parse RSA public key")?,
+                }),
+            ),
+            Self::EcdsaP256 => (
+                PublicKeyAlgorithm::ECDSA,
+                PublicParams::ECDSA(EcdsaPublicParams::P256 {
+                    key: p256::PublicKey::from_public_key_der(der)
+                        .context("parse ECDSA public key")?,
+                }),
+            ),
+        };
+        let inner = PubKeyInner::new(KeyVersion::V4, algorithm, created_at, None, params)
+            .context("build public key")?;
+        PublicKey::from_inner(inner).context("build public key")
+    }
+
+    /// Convert a signature in the format returned by KMS into OpenPGP
+    /// signature MPIs.
+    fn signature_bytes(self, signature: &[u8]) -> Result<SignatureBytes> {
+        match self {
+            Self::Rsa => Ok(SignatureBytes::Mpis(vec![Mpi::from_slice(signature)])),
+            Self::EcdsaP256 => {
+                // ECDSA signatures are DER-encoded `(r, s)` pairs.
+                let signature =
+                    p256::ecdsa::Signature::from_der(signature).context("parse ECDSA signature")?;
+                Ok(SignatureBytes::Mpis(vec![
+                    Mpi::from_slice(&signature.r().to_bytes()),
+                    Mpi::from_slice(&signature.s().to_bytes()),
+                ]))
+            }
+        }
+    }
+}
+
+/// An OpenPGP signing key whose private key is held elsewhere, which signs
+/// digests with `sign_digest`.
+struct ExternalKey<F> {
+    public_key: PublicKey,
+    algorithm: KeyAlgorithm,
+    sign_digest: F,
+}
+
+impl<F> fmt::Debug for ExternalKey<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExternalKey")
+            .field("public_key", &self.public_key)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> KeyDetails for ExternalKey<F> {
+    fn version(&self) -> KeyVersion {
+        self.public_key.version()
+    }
+
+    fn fingerprint(&self) -> Fingerprint {
+        self.public_key.fingerprint()
+    }
+
+    fn key_id(&self) -> KeyId {
+        self.public_key.key_id()
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.public_key.algorithm()
+    }
+}
+
+impl<F> SecretKeyTrait for ExternalKey<F>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    fn create_signature(
+        &self,
+        _key_pw: &Password,
+        hash: HashAlgorithm,
+        data: &[u8],
+    ) -> pgp::errors::Result<SignatureBytes> {
+        if hash != HashAlgorithm::Sha256 {
+            return Err(format!("unsupported hash algorithm {hash:?}").into());
+        }
+        (self.sign_digest)(data)
+            .and_then(|signature| self.algorithm.signature_bytes(&signature))
+            .map_err(|err| format!("{err:#}").into())
+    }
+
+    fn hash_alg(&self) -> HashAlgorithm {
+        HashAlgorithm::Sha256
+    }
+}
+
+/// Clearsign and detach-sign content, and certify the key's public key with
+/// the given user ID.
+fn sign_with<F>(key: &ExternalKey<F>, user_id: &str, content: &str) -> Result<SignedGpgContent>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let clearsigned = CleartextSignedMessage::new(
+        content,
+        signature_config(key, SignatureType::Text)?,
+        key,
+        &Password::empty(),
+    )
+    .context("clearsign content")?
+    .to_armored_string(ArmorOptions::default())
+    .context("armor clearsigned content")?;
+    debug!(?content, ?clearsigned, "clearsigned index");
+
+    let detachsigned = signature_config(key, SignatureType::Binary)?
+        .sign(key, &Password::empty(), content.as_bytes())
+        .context("detach sign content")?;
+    let detachsigned = StandaloneSignature::new(detachsigned)
+        .to_armored_string(ArmorOptions::default())
+        .context("armor detached signature")?;
+    debug!(?content, ?detachsigned, "detachsigned index");
+
+    let public_key_cert = public_key_cert(key, user_id)?
+        .to_armored_string(ArmorOptions::default())
+        .context("armor public key")?;
+    debug!(?public_key_cert, "public key cert");
+
+    Ok(SignedGpgContent {
+        clearsigned,
+        detachsigned,
+        public_key_cert,
+    })
+}
+
+/// Self-certify the key's public key with the given user ID.
+///
+/// `SignedPublicKey::verify` requires a valid self-signature, and clients
+/// such as `gpg` refuse to import keys without a user ID.
+fn public_key_cert<F>(key: &ExternalKey<F>, user_id: &str) -> Result<SignedPublicKey>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let user_id = UserId::from_str(PacketHeaderVersion::New, user_id).context("build user ID")?;
+    let mut config = signature_config(key, SignatureType::CertPositive)?;
+    let mut key_flags = KeyFlags::default();
+    key_flags.set_certify(true);
+    key_flags.set_sign(true);
+    config
+        .hashed_subpackets
+        .push(Subpacket::regular(SubpacketData::KeyFlags(key_flags)).context("build key flags")?);
+    let certification: Signature = config
+        .sign_certification(
+            key,
+            &key.public_key,
+            &Password::empty(),
+            Tag::UserId,
+            &user_id,
+        )
+        .context("certify user ID")?;
+    Ok(SignedPublicKey::new(
+        key.public_key.clone(),
+        SignedKeyDetails::new(
+            Vec::new(),
+            Vec::new(),
+            vec![SignedUser::new(user_id, vec![certification])],
+            Vec::new(),
+        ),
+        Vec::new(),
+    ))
+}
+
+/// The configuration of a new signature by the key, including the subpackets
+/// that identify the key.
+fn signature_config<F>(key: &ExternalKey<F>, typ: SignatureType) -> Result<SignatureConfig>
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>>,
+{
+    let mut config = SignatureConfig::v4(typ, key.algorithm(), key.hash_alg());
+    config.hashed_subpackets = vec![
+        Subpacket::regular(SubpacketData::IssuerFingerprint(key.fingerprint()))
+            .context("build issuer fingerprint")?,
+        Subpacket::regular(SubpacketData::SignatureCreationTime(
+            Utc::now().trunc_subsecs(0),
+        ))
+        .context("build signature creation time")?,
+    ];
+    config.unhashed_subpackets =
+        vec![Subpacket::regular(SubpacketData::Issuer(key.key_id())).context("build issuer")?];
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::{
+        ecdsa::{SigningKey, signature::hazmat::PrehashSigner as _},
+        pkcs8::EncodePublicKey as _,
+    };
+    use pgp::composed::Deserializable as _;
+
+    use super::*;
+
+    /// Signatures made with digests signed by an external ECDSA key verify in
+    /// the same way as the index sign endpoint verifies them.
+    #[test]
+    fn external_ecdsa_signatures_verify() {
+        let signing_key = SigningKey::random(&mut p256::elliptic_curve::rand_core::OsRng);
+        let der = signing_key
+            .verifying_key()
+            .to_public_key_der()
+            .expect("encode public key");
+        let created_at = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let key = ExternalKey {
+            public_key: KeyAlgorithm::EcdsaP256
+                .public_key(der.as_bytes(), created_at)
+                .expect("build public key"),
+            algorithm: KeyAlgorithm::EcdsaP256,
+            sign_digest: |digest: &[u8]| {
+                let signature: p256::ecdsa::Signature = signing_key.sign_prehash(digest)?;
+                Ok(signature.to_der().as_bytes().to_vec())
+            },
+        };
+
+        let content = "Origin: test\nSuite: stable\n";
+        let signed = sign_with(&key, "test key", content).expect("sign content");
+
+        let (public_key, _) =
+            SignedPublicKey::from_string(&signed.public_key_cert).expect("parse public key");
+        public_key.verify().expect("verify public key");
+        assert_eq!(public_key.fingerprint(), key.fingerprint());
+        let (clearsigned, _) =
+            CleartextSignedMessage::from_string(&signed.clearsigned).expect("parse clearsigned");
+        clearsigned.verify(&public_key).expect("verify clearsigned");
+        let (detachsigned, _) =
+            StandaloneSignature::from_string(&signed.detachsigned).expect("parse detachsigned");
+        detachsigned
+            .verify(&public_key, content.as_bytes())
+            .expect("verify detachsigned");
+    }
+}
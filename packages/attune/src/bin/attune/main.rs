@@ -1,22 +1,30 @@
-use std::{iter::once, process::ExitCode, time::Duration};
+use std::{
+    iter::once,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-use attune::{api::ErrorResponse, server::compatibility::CompatibilityResponse};
+use attune::{
+    api::ErrorResponse, logging::LogFormat, server::compatibility::CompatibilityResponse,
+};
 use axum::http::StatusCode;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use color_eyre::{
     Result,
-    eyre::{Context as _, OptionExt, bail},
+    eyre::{Context as _, OptionExt, bail, eyre},
 };
 use colored::Colorize;
 use git_version::git_version;
-use gpgme::{Context, ExportMode, Protocol};
+use gpgme::{Context, ExportMode, PassphraseRequest, PinentryMode, Protocol};
+use serde::{Deserialize, Serialize};
 use tracing::debug;
-use tracing_subscriber::{
-    fmt::format::FmtSpan, layer::SubscriberExt as _, util::SubscriberInitExt as _,
-};
 
 mod cmd;
 mod config;
+mod kms;
+mod template;
+
+pub use attune::retry::{retry_delay_default, retry_infinite};
 
 /// Attune CLI
 ///
@@ -29,16 +37,87 @@ mod config;
 )]
 struct Args {
     /// Attune API token.
-    #[arg(long, env = "ATTUNE_API_TOKEN")]
-    api_token: String,
+    ///
+    /// Prefer `--api-token-file` or `--api-token-command`, which keep the token
+    /// out of shell history and process listings. If none of these are set,
+    /// the token from the config file is used.
+    #[arg(long, env = "ATTUNE_API_TOKEN", hide_env_values = true)]
+    api_token: Option<String>,
+
+    /// Read the Attune API token from this file.
+    ///
+    /// Takes precedence over `--api-token`.
+    #[arg(long, env = "ATTUNE_API_TOKEN_FILE")]
+    api_token_file: Option<PathBuf>,
+
+    /// Run this shell command and use its output as the Attune API token.
+    ///
+    /// This is useful for reading the token from a secret manager. Takes
+    /// precedence over `--api-token-file` and `--api-token`.
+    #[arg(long, env = "ATTUNE_API_TOKEN_COMMAND")]
+    api_token_command: Option<String>,
 
     /// Attune API endpoint.
+    ///
+    /// If not set, the endpoint from the config file is used, or
+    /// `https://api.attunehq.com` if that isn't set either.
+    #[arg(long, env = "ATTUNE_API_ENDPOINT")]
+    api_endpoint: Option<String>,
+
+    /// Profile of the config file (`~/.config/attune/config.toml`) to read
+    /// defaults from.
+    ///
+    /// If not set, the `[default]` section is used.
+    #[arg(long, env = "ATTUNE_PROFILE", global = true)]
+    profile: Option<String>,
+
+    /// How to sign repository indexes.
+    #[arg(
+        long,
+        env = "ATTUNE_SIGNING_BACKEND",
+        value_enum,
+        default_value_t,
+        global = true
+    )]
+    signing_backend: SigningBackendKind,
+
+    /// ARN of the AWS KMS asymmetric key to sign with.
+    ///
+    /// Required with `--signing-backend kms`. The key must be an RSA or
+    /// ECC_NIST_P256 signing key. AWS credentials are loaded in the same way
+    /// as the AWS CLI.
+    #[arg(
+        long,
+        env = "ATTUNE_KMS_KEY_ARN",
+        required_if_eq("signing_backend", "kms"),
+        global = true
+    )]
+    kms_key_arn: Option<String>,
+
+    /// GPG key ID to sign with when a command isn't given a `--key-id`.
+    ///
+    /// If not set, the `key_id` from the config file is used.
+    #[arg(long, env = "ATTUNE_GPG_KEY_ID", global = true)]
+    default_key_id: Option<String>,
+
+    /// Read the passphrase of the GPG signing key from this file.
+    ///
+    /// This allows signing with a passphrase-protected key without a pinentry
+    /// prompt, e.g. in CI. A trailing newline in the file is ignored.
+    #[arg(long, env = "ATTUNE_GPG_PASSPHRASE_FILE", global = true)]
+    passphrase_file: Option<PathBuf>,
+
+    /// Format of the logs written to stderr.
+    ///
+    /// Use `json` to write one JSON object per line, for log aggregators.
     #[arg(
         long,
-        env = "ATTUNE_API_ENDPOINT",
-        default_value = "https://api.attunehq.com"
+        env = "ATTUNE_LOG_FORMAT",
+        value_enum,
+        default_value_t,
+        global = true
     )]
-    api_endpoint: String,
+    log_format: LogFormat,
 
     /// Tool to run.
     #[command(subcommand)]
@@ -49,35 +128,100 @@ struct Args {
 enum ToolCommand {
     /// Manage APT repositories
     Apt(cmd::apt::AptCommand),
+    /// Manage the config file
+    ///
+    /// The config file (`~/.config/attune/config.toml`) holds defaults for the
+    /// API endpoint, API token, and GPG key ID, in a `[default]` section and in
+    /// named `[profile.<name>]` sections that are selected with `--profile`.
+    /// Flags and environment variables take precedence over it.
+    Config(cmd::config::ConfigCommand),
+    /// Diagnose common setup problems
+    ///
+    /// Checks that the API server is reachable and compatible, that the API
+    /// token is valid, and that GPG can sign with your key.
+    Doctor(cmd::doctor::DoctorCommand),
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    // Set up logging.
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
-                .with_file(true)
-                .with_line_number(true)
-                .with_target(true)
-                .with_thread_ids(true)
-                .with_thread_names(true)
-                .with_writer(std::io::stderr)
-                .pretty(),
-        )
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
-
     let args = Args::parse();
+
+    // Set up logging.
+    attune::logging::init(args.log_format);
     debug!(?args, "parsed arguments");
 
-    let ctx = config::Config::new(args.api_token, args.api_endpoint);
+    // Managing the config file doesn't need any of the settings in it.
+    let config_path = match config::ConfigFile::path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Error: could not find config file: {err:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let tool = match args.tool {
+        ToolCommand::Config(command) => {
+            return cmd::config::run(&config_path, args.profile.as_deref(), command);
+        }
+        tool => tool,
+    };
+    let profile = match config::ConfigFile::load(&config_path)
+        .and_then(|file| file.profile(args.profile.as_deref()))
+    {
+        Ok(profile) => profile,
+        Err(err) => {
+            eprintln!("Error: could not load config: {err:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    debug!(?config_path, profile = ?args.profile, "loaded config file");
+
+    // Offline commands never contact the API server, so they don't need an API
+    // token or a compatible server.
+    let offline = match &tool {
+        ToolCommand::Apt(command) => command.is_offline(),
+        ToolCommand::Config(_) => unreachable!("config commands are handled above"),
+        ToolCommand::Doctor(_) => false,
+    };
+
+    let api_token = match config::resolve_api_token(
+        args.api_token.or(profile.token),
+        args.api_token_file,
+        args.api_token_command,
+    ) {
+        Ok(api_token) => api_token,
+        Err(_) if offline => String::new(),
+        Err(err) => {
+            eprintln!("Error: could not read API token: {err:#}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let signing_backend = match args.signing_backend {
+        SigningBackendKind::Gpg => SigningBackend::Gpg {
+            passphrase_file: args.passphrase_file,
+            default_key_id: args.default_key_id.or(profile.key_id),
+        },
+        SigningBackendKind::Kms => SigningBackend::Kms {
+            key_arn: args.kms_key_arn.expect("KMS key ARN is required"),
+        },
+    };
+    let api_endpoint = args
+        .api_endpoint
+        .or(profile.endpoint)
+        .unwrap_or_else(|| String::from(config::DEFAULT_API_ENDPOINT));
+    let ctx = config::Config::new(api_token, api_endpoint).with_signing_backend(signing_backend);
+
+    // The doctor diagnoses compatibility problems itself, so it must run
+    // before the compatibility check below aborts.
+    let command = match tool {
+        ToolCommand::Doctor(command) => return cmd::doctor::run(ctx, command).await,
+        command if offline => return run_tool(ctx, command).await,
+        command => command,
+    };
 
     // Do a check for API version compatibility.
     let res = ctx
         .client
-        .get(ctx.endpoint.join("/api/v0/compatibility").unwrap())
+        .get(ctx.url("/api/v0/compatibility").unwrap())
         .send()
         .await
         .expect("Could not reach API server");
@@ -113,75 +257,113 @@ async fn main() -> ExitCode {
         }
     }
 
-    // Execute subcommand.
-    //
-    // TODO: We should update all the subcommands to return `Result<String,
-    // ErrorResponse>`       so that we can centralize retries, pretty printing,
-    // etc.
-    match args.tool {
-        ToolCommand::Apt(command) => cmd::apt::handle_apt(ctx, command).await,
-    }
+    run_tool(ctx, command).await
 }
 
-/// Infinitely retry an asynchronous function call.
-///
-/// - `operation` is the function to call.
-/// - `should_retry` evaluates whether the operation should be retried.
-/// - `retry_delay` provides the duration to wait before retrying.
-///
-/// Optionally, you can use [`retry_delay_default`] for default delay timings.
-pub async fn retry_infinite<T, E>(
-    operation: impl AsyncFn() -> Result<T, E>,
-    should_retry: impl Fn(&E) -> bool,
-    retry_delay: impl Fn(usize) -> Duration,
-) -> Result<T, E> {
-    for attempt in 0usize.. {
-        match operation().await {
-            Ok(value) => return Ok(value),
-            Err(e) => {
-                if should_retry(&e) {
-                    tokio::time::sleep(retry_delay(attempt)).await;
-                } else {
-                    return Err(e);
-                }
-            }
-        }
+/// Execute a subcommand.
+//
+// TODO: We should update all the subcommands to return `Result<String,
+// ErrorResponse>`       so that we can centralize retries, pretty printing,
+// etc.
+async fn run_tool(ctx: config::Config, command: ToolCommand) -> ExitCode {
+    match command {
+        ToolCommand::Apt(command) => cmd::apt::handle_apt(ctx, command).await,
+        ToolCommand::Config(_) => unreachable!("config commands are handled before loading config"),
+        ToolCommand::Doctor(_) => unreachable!("doctor is handled before compatibility checks"),
     }
-    unreachable!("loop is functionally infinite");
-}
-
-/// The default retry delay is a static delay of 2 seconds
-/// plus a random jitter of up to 2 seconds.
-pub fn retry_delay_default(_: usize) -> Duration {
-    const STATIC_RETRY_DELAY_MS: u64 = 2000;
-    Duration::from_millis(STATIC_RETRY_DELAY_MS + rand::random_range(0..STATIC_RETRY_DELAY_MS))
 }
 
 /// The result of signing content with a GPG key.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SignedGpgContent {
     pub clearsigned: String,
     pub detachsigned: String,
     pub public_key_cert: String,
 }
 
-/// Sign content with the named GPG key ID.
+/// The `--signing-backend` choices.
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum SigningBackendKind {
+    /// Sign with a GPG key from the local keyring.
+    #[default]
+    Gpg,
+    /// Sign with an AWS KMS asymmetric key.
+    Kms,
+}
+
+/// Where repository indexes are signed.
+#[derive(Debug, Clone)]
+pub enum SigningBackend {
+    /// Sign with a GPG key from the local keyring.
+    Gpg {
+        /// A file containing the passphrase of the signing key, if it's
+        /// passphrase-protected.
+        passphrase_file: Option<PathBuf>,
+        /// The key ID to sign with when no key IDs are given.
+        default_key_id: Option<String>,
+    },
+    /// Sign with an AWS KMS asymmetric key, so that the private key never
+    /// leaves KMS.
+    Kms { key_arn: String },
+}
+
+impl SigningBackend {
+    /// Sign content with this backend.
+    ///
+    /// The GPG home directory and key IDs only apply to GPG signing. If no key
+    /// IDs are given, the backend's default key ID is used, if any.
+    pub async fn sign(
+        &self,
+        gpg_home_dir: Option<impl Into<String>>,
+        key_ids: impl IntoIterator<Item = impl Into<String>>,
+        content: impl Into<Vec<u8>>,
+    ) -> Result<SignedGpgContent> {
+        match self {
+            SigningBackend::Gpg {
+                passphrase_file,
+                default_key_id,
+            } => {
+                let mut key_ids = key_ids.into_iter().map(Into::into).collect::<Vec<String>>();
+                if key_ids.is_empty() {
+                    key_ids.extend(default_key_id.clone());
+                }
+                gpg_sign(gpg_home_dir, key_ids, passphrase_file.as_deref(), content).await
+            }
+            SigningBackend::Kms { key_arn } => kms::kms_sign(key_arn, content).await,
+        }
+    }
+}
+
+/// Sign content with each of the named GPG key IDs, or with the only secret
+/// key if no key IDs are given.
+///
+/// Signing with several keys produces one signature per key in the same
+/// clearsigned and detached outputs, and exports all of their public keys,
+/// which lets clients trust either key while rotating from one to the other.
+///
+/// If a key is passphrase-protected, its passphrase is read from
+/// `passphrase_file`.
 pub async fn gpg_sign(
     gpg_home_dir: Option<impl Into<String>>,
-    key_id: Option<impl Into<String>>,
+    key_ids: impl IntoIterator<Item = impl Into<String>>,
+    passphrase_file: Option<impl Into<PathBuf>>,
     content: impl Into<Vec<u8>>,
 ) -> Result<SignedGpgContent> {
     let gpg_home = gpg_home_dir.map(|p| p.into());
-    let key_id = key_id.map(|k| k.into());
+    let key_ids = key_ids.into_iter().map(|k| k.into()).collect::<Vec<_>>();
+    let passphrase_file = passphrase_file.map(|p| p.into());
     let content = content.into();
-    tokio::task::spawn_blocking(move || gpg_sign_blocking(gpg_home, key_id, content))
-        .await
-        .context("join background thread")?
+    tokio::task::spawn_blocking(move || {
+        gpg_sign_blocking(gpg_home, key_ids, passphrase_file, content)
+    })
+    .await
+    .context("join background thread")?
 }
 
 fn gpg_sign_blocking(
     gpg_home: Option<String>,
-    key_id: Option<String>,
+    key_ids: Vec<String>,
+    passphrase_file: Option<PathBuf>,
     content: Vec<u8>,
 ) -> Result<SignedGpgContent> {
     let mut gpg = Context::from_protocol(Protocol::OpenPgp).context("create gpg context")?;
@@ -191,46 +373,61 @@ fn gpg_sign_blocking(
     }
 
     gpg.set_armor(true);
-    let key = match key_id {
-        Some(key_id) => gpg
-            .find_secret_keys([&key_id])
-            .context("list secret keys")?
-            .next()
-            .ok_or_eyre("get next key in list")?
-            .context("get secret key from list")?,
-        None => {
-            let mut all_secret_keys = gpg
-                .find_secret_keys([] as [&str; 0])
-                .context("list secret keys")?
-                .collect::<Result<Vec<_>, _>>()
-                .context("get secret key from list")?;
-            if all_secret_keys.len() == 1 {
-                all_secret_keys.pop().ok_or_eyre("pop solo secret key")?
-            } else {
-                bail!("no GPG key ID specified and multiple GPG keys found")
-            }
-        }
+    let keys = if key_ids.is_empty() {
+        vec![find_secret_key(&mut gpg, None)?]
+    } else {
+        key_ids
+            .into_iter()
+            .map(|key_id| find_secret_key(&mut gpg, Some(key_id)))
+            .collect::<Result<Vec<_>>>()?
     };
-    debug!(?key, "using signing key");
-    gpg.add_signer(&key).context("add signer")?;
-    // TODO: Configure passphrase provider?
+    for key in &keys {
+        debug!(?key, "using signing key");
+        gpg.add_signer(key).context("add signer")?;
+    }
 
     let mut clearsigned = Vec::new();
-    gpg.sign_clear(&content, &mut clearsigned)
-        .context("clearsign index")?;
+    let mut detachsigned = Vec::new();
+    let mut sign = |gpg: &mut Context| -> Result<(), gpgme::Error> {
+        gpg.sign_clear(&content, &mut clearsigned)?;
+        gpg.sign_detached(&content, &mut detachsigned)?;
+        Ok(())
+    };
+    match &passphrase_file {
+        Some(passphrase_file) => {
+            let passphrase = std::fs::read_to_string(passphrase_file)
+                .with_context(|| format!("read passphrase file {passphrase_file:?}"))?;
+            let passphrase = passphrase.trim_end_matches(['\n', '\r']);
+            // In loopback mode, gpg-agent asks us for the passphrase instead of
+            // prompting with pinentry.
+            gpg.set_pinentry_mode(PinentryMode::Loopback)
+                .context("set pinentry mode")?;
+            gpg.with_passphrase_provider(
+                |request: PassphraseRequest<'_>, out: &mut dyn std::io::Write| {
+                    // Fail rather than retrying the same passphrase forever.
+                    if request.prev_attempt_failed {
+                        return Err(gpgme::Error::BAD_PASSPHRASE);
+                    }
+                    out.write_all(format!("{passphrase}\n").as_bytes())?;
+                    Ok(())
+                },
+                &mut sign,
+            )
+        }
+        None => sign(&mut gpg),
+    }
+    .map_err(|err| sign_error(err, passphrase_file.as_deref()))?;
+
     let clearsigned =
         String::from_utf8(clearsigned).context("clearsigned index contained invalid characters")?;
     debug!(?content, ?clearsigned, "clearsigned index");
-    let mut detachsigned = Vec::new();
-    gpg.sign_detached(&content, &mut detachsigned)
-        .context("detach sign index")?;
     let detachsigned = String::from_utf8(detachsigned)
         .context("detachsigned index contained invalid characters")?;
     debug!(?content, ?detachsigned, "detachsigned index");
 
     let mut public_key_cert = Vec::new();
-    gpg.export_keys(once(&key), ExportMode::empty(), &mut public_key_cert)
-        .context("export key")?;
+    gpg.export_keys(&keys, ExportMode::empty(), &mut public_key_cert)
+        .context("export keys")?;
     let public_key_cert = String::from_utf8(public_key_cert)
         .context("public key cert contained invalid characters")?;
     debug!(?public_key_cert, "public key cert");
@@ -241,3 +438,130 @@ fn gpg_sign_blocking(
         public_key_cert,
     })
 }
+
+/// Explain a GPG signing error, calling out passphrase problems since gpgme's
+/// own errors don't say what to do about them.
+fn sign_error(err: gpgme::Error, passphrase_file: Option<&Path>) -> color_eyre::Report {
+    let passphrase_errors = [
+        gpgme::Error::BAD_PASSPHRASE,
+        gpgme::Error::NO_PASSPHRASE,
+        gpgme::Error::NO_PIN_ENTRY,
+        gpgme::Error::CANCELED,
+        gpgme::Error::ENOTTY,
+    ];
+    if !passphrase_errors.iter().any(|e| e.code() == err.code()) {
+        return eyre!(err).wrap_err("sign index");
+    }
+    match passphrase_file {
+        Some(passphrase_file) => eyre!(
+            "could not unlock the GPG signing key with the passphrase in {passphrase_file:?}: {err}"
+        ),
+        None => eyre!(
+            "the GPG signing key is passphrase-protected and no passphrase is configured: set --passphrase-file or ATTUNE_GPG_PASSPHRASE_FILE ({err})"
+        ),
+    }
+}
+
+/// Export the armored public key of the named GPG key ID, which is the key
+/// that [`gpg_sign`] would sign with.
+pub async fn gpg_export_public_key(
+    gpg_home_dir: Option<impl Into<String>>,
+    key_id: Option<impl Into<String>>,
+) -> Result<String> {
+    let gpg_home = gpg_home_dir.map(|p| p.into());
+    let key_id = key_id.map(|k| k.into());
+    tokio::task::spawn_blocking(move || {
+        let mut gpg = Context::from_protocol(Protocol::OpenPgp).context("create gpg context")?;
+        if let Some(gpg_home) = gpg_home {
+            gpg.set_engine_home_dir(&gpg_home)
+                .with_context(|| format!("set engine home dir to: {gpg_home:?}"))?;
+        }
+        gpg.set_armor(true);
+        let key = find_secret_key(&mut gpg, key_id)?;
+        let mut public_key_cert = Vec::new();
+        gpg.export_keys(once(&key), ExportMode::empty(), &mut public_key_cert)
+            .context("export key")?;
+        String::from_utf8(public_key_cert).context("public key cert contained invalid characters")
+    })
+    .await
+    .context("join background thread")?
+}
+
+/// Find the named secret key, or the only secret key if no key ID is given.
+fn find_secret_key(gpg: &mut Context, key_id: Option<String>) -> Result<gpgme::Key> {
+    Ok(match key_id {
+        Some(key_id) => gpg
+            .find_secret_keys([&key_id])
+            .context("list secret keys")?
+            .next()
+            .ok_or_eyre("get next key in list")?
+            .context("get secret key from list")?,
+        None => {
+            let mut all_secret_keys = gpg
+                .find_secret_keys([] as [&str; 0])
+                .context("list secret keys")?
+                .collect::<Result<Vec<_>, _>>()
+                .context("get secret key from list")?;
+            if all_secret_keys.len() == 1 {
+                all_secret_keys.pop().ok_or_eyre("pop solo secret key")?
+            } else {
+                bail!(
+                    "no GPG key ID specified and multiple GPG keys found: pass --key-id, or set a default with ATTUNE_GPG_KEY_ID or `attune config set key-id <KEY_ID>`"
+                )
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use attune::testing::gpg_key_id;
+
+    use super::*;
+
+    /// Keys that only exist in the given GPG home directory can sign, which is
+    /// how `--gpg-home` is used with keys imported into a temporary home in
+    /// CI.
+    #[tokio::test]
+    async fn sign_with_gpg_home_dir() {
+        let (key_id, _gpg, gpg_home_dir) = gpg_key_id().await.expect("failed to create GPG key");
+        let gpg_home_dir = gpg_home_dir.dir_path().to_string_lossy().to_string();
+
+        let signed = gpg_sign(
+            Some(&gpg_home_dir),
+            Some(&key_id),
+            None::<PathBuf>,
+            "test content",
+        )
+        .await
+        .expect("could not sign with key in GPG home directory");
+        assert!(signed.clearsigned.contains("test content"));
+        assert!(!signed.detachsigned.is_empty());
+
+        // The key isn't found in the default home directory.
+        let signed = gpg_sign(
+            None::<String>,
+            Some(&key_id),
+            None::<PathBuf>,
+            "test content",
+        )
+        .await;
+        assert!(signed.is_err());
+    }
+
+    #[test]
+    fn sign_error_explains_passphrase_problems() {
+        let err = sign_error(gpgme::Error::NO_PIN_ENTRY, None).to_string();
+        assert!(err.contains("--passphrase-file"), "{err}");
+
+        let err = sign_error(
+            gpgme::Error::BAD_PASSPHRASE,
+            Some(Path::new("/run/secrets/gpg")),
+        )
+        .to_string();
+        assert!(err.contains("/run/secrets/gpg"), "{err}");
+
+        let err = sign_error(gpgme::Error::UNUSABLE_SECKEY, None).to_string();
+        assert!(!err.contains("passphrase"), "{err}");
+    }
+}
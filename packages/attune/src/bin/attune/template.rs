@@ -0,0 +1,146 @@
+use itertools::Itertools as _;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// A template that list commands render once per item with `--format`, such
+/// as `{name} {version}`.
+///
+/// Placeholders name fields of the listed items, which are the same as the
+/// fields in the command's JSON output. `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(String),
+}
+
+impl Template {
+    /// Parse a template whose placeholders must be fields of `T`.
+    ///
+    /// This is meant to be used as a `clap` value parser, so that unknown
+    /// placeholders are reported along with other usage errors.
+    pub fn parse<T: Serialize + Default>(template: &str) -> Result<Self, String> {
+        let fields = fields(&T::default());
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(String::from("unclosed placeholder")),
+                        }
+                    }
+                    let field = field.trim();
+                    if !fields.contains_key(field) {
+                        return Err(format!(
+                            "unknown field {field:?}, available fields: {}",
+                            fields.keys().join(", ")
+                        ));
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field.to_string()));
+                }
+                '}' => return Err(String::from("unmatched '}', use '}}' for a literal brace")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Render the template for one item.
+    pub fn render<T: Serialize>(&self, item: &T) -> String {
+        let fields = fields(item);
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.clone(),
+                Part::Field(field) => fields.get(field).map(render_value).unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Render the template for each item, one per line.
+    pub fn render_all<'a, T: Serialize + 'a>(
+        &self,
+        items: impl IntoIterator<Item = &'a T>,
+    ) -> String {
+        items.into_iter().map(|item| self.render(item)).join("\n")
+    }
+}
+
+fn fields<T: Serialize>(item: &T) -> Map<String, Value> {
+    match serde_json::to_value(item).expect("could not serialize item") {
+        Value::Object(fields) => fields,
+        value => panic!("templates can only render objects, got {value}"),
+    }
+}
+
+/// Render a field so that it's easy to consume from shell scripts: strings are
+/// unquoted, missing values are empty, and lists are comma-separated.
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(render_value).join(","),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Default)]
+    struct Item {
+        name: String,
+        version: Option<String>,
+        size: u64,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn render_fields() {
+        let template =
+            Template::parse::<Item>("{name} {version}\t{size} [{tags}] {{literal}}").unwrap();
+        let item = Item {
+            name: String::from("attune"),
+            version: None,
+            size: 42,
+            tags: vec![String::from("a"), String::from("b")],
+        };
+        assert_eq!(template.render(&item), "attune \t42 [a,b] {literal}");
+    }
+
+    #[test]
+    fn reject_invalid_templates() {
+        let err = Template::parse::<Item>("{name} {nmae}").unwrap_err();
+        assert_eq!(
+            err,
+            "unknown field \"nmae\", available fields: name, size, tags, version"
+        );
+        assert!(Template::parse::<Item>("{name").is_err());
+        assert!(Template::parse::<Item>("name}").is_err());
+    }
+}
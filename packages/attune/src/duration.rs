@@ -0,0 +1,48 @@
+//! Parsing durations given on the command line.
+
+use std::time::Duration;
+
+/// Parse a duration like `30`, `90s`, `15m`, `12h`, or `90d`.
+pub fn parse(duration: &str) -> Result<Duration, String> {
+    let (amount, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| format!("invalid duration {duration:?}, expected e.g. `90d`"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "unknown unit {unit:?}, expected `s`, `m`, `h`, or `d`"
+            ));
+        }
+    };
+    amount
+        .checked_mul(seconds)
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration {duration:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert!(parse("0d").is_err());
+        assert!(parse("7w").is_err());
+        assert!(parse("d").is_err());
+        assert!(parse("").is_err());
+    }
+}
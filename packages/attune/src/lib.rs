@@ -1,5 +1,8 @@
 pub mod api;
 pub mod apt;
+pub mod duration;
+pub mod logging;
+pub mod retry;
 pub mod server;
 
 // We can't make the whole module `#[cfg(test)]`, because the `MIGRATOR` it
@@ -0,0 +1,275 @@
+//! Logging setup shared by the binaries.
+//!
+//! Logs are human-readable by default. For log aggregators, they can instead
+//! be written as one JSON object per line, in the same shape as
+//! `tracing-subscriber`'s JSON formatter.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{
+        FmtContext, FormatEvent, FormatFields, FormattedFields,
+        format::{FmtSpan, Writer},
+    },
+    layer::SubscriberExt as _,
+    registry::LookupSpan,
+    util::SubscriberInitExt as _,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Multi-line, human-readable logs.
+    #[default]
+    Pretty,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Initialize tracing, logging to stderr in the given format and filtering
+/// with `RUST_LOG`.
+pub fn init(format: LogFormat) {
+    let span_events = FmtSpan::NEW | FmtSpan::CLOSE;
+    let (pretty, json) = match format {
+        LogFormat::Pretty => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_span_events(span_events)
+                    .with_file(true)
+                    .with_line_number(true)
+                    .with_target(true)
+                    .with_thread_ids(true)
+                    .with_thread_names(true)
+                    .with_writer(std::io::stderr)
+                    .pretty(),
+            ),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_span_events(span_events)
+                    .with_writer(std::io::stderr)
+                    .event_format(JsonFormat)
+                    .fmt_fields(JsonFields),
+            ),
+        ),
+    };
+    tracing_subscriber::registry()
+        .with(pretty)
+        .with(json)
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+}
+
+/// Formats events as JSON objects with their fields, metadata, and the
+/// fields of the spans they're in.
+///
+/// This always includes the file, line number, target, and thread, like the
+/// pretty formatter.
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+
+        let mut object = Map::new();
+        if let Ok(timestamp) = OffsetDateTime::now_utc().format(&Rfc3339) {
+            object.insert("timestamp".into(), timestamp.into());
+        }
+        object.insert("level".into(), metadata.level().as_str().into());
+        object.insert("fields".into(), Value::Object(fields.0));
+        object.insert("target".into(), metadata.target().into());
+        if let Some(file) = metadata.file() {
+            object.insert("filename".into(), file.into());
+        }
+        if let Some(line) = metadata.line() {
+            object.insert("line_number".into(), line.into());
+        }
+
+        // Spans are listed from the root, and the innermost one is also
+        // included on its own.
+        let spans = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut fields = span
+                    .extensions()
+                    .get::<FormattedFields<N>>()
+                    .and_then(|fields| serde_json::from_str::<Map<String, Value>>(fields).ok())
+                    .unwrap_or_default();
+                fields.insert("name".into(), span.name().into());
+                Value::Object(fields)
+            })
+            .collect::<Vec<_>>();
+        if let Some(span) = spans.last() {
+            object.insert("span".into(), span.clone());
+        }
+        if !spans.is_empty() {
+            object.insert("spans".into(), Value::Array(spans));
+        }
+
+        let thread = std::thread::current();
+        if let Some(name) = thread.name() {
+            object.insert("threadName".into(), name.into());
+        }
+        object.insert("threadId".into(), format!("{:?}", thread.id()).into());
+
+        writeln!(writer, "{}", Value::Object(object))
+    }
+}
+
+/// Formats span fields as a JSON object, so that [`JsonFormat`] can include
+/// them in events.
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(serde_json::from_str(&current.fields).unwrap_or_default());
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+/// Collects fields into a JSON object.
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0.insert(field.name().into(), value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{info, info_span};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn format_json_lines() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(buffer.clone())
+                .event_format(JsonFormat)
+                .fmt_fields(JsonFields),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!(
+                "upload",
+                repository = "debian",
+                size = tracing::field::Empty
+            );
+            let _guard = span.enter();
+            span.record("size", 42);
+            info!(sha256sum = "abc", replace = false, "uploaded package");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1, "{output}");
+        let line = serde_json::from_str::<Value>(lines[0]).unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(
+            line["fields"],
+            serde_json::json!({
+                "message": "uploaded package",
+                "sha256sum": "abc",
+                "replace": false,
+            })
+        );
+        assert_eq!(
+            line["span"],
+            serde_json::json!({ "name": "upload", "repository": "debian", "size": 42 })
+        );
+        assert_eq!(line["spans"].as_array().unwrap().len(), 1);
+        assert!(line["line_number"].is_u64());
+        assert!(line["timestamp"].is_string());
+    }
+}
@@ -0,0 +1,223 @@
+//! Invalidating CDN caches after publishing.
+//!
+//! Repositories served through a CDN keep serving cached Release files and
+//! Packages indexes until they expire, so clients can see stale indexes for a
+//! while after every change. When a CloudFront distribution is configured, the
+//! server invalidates the files that each change published.
+//!
+//! The AWS SDK for CloudFront isn't a dependency, so invalidations are created
+//! by calling the CloudFront API directly, signed with the same credentials as
+//! the S3 client.
+
+use std::time::SystemTime;
+
+use aws_sdk_s3::config::{ProvideCredentials as _, SharedCredentialsProvider};
+use aws_sigv4::{
+    http_request::{SignableBody, SignableRequest, SigningSettings, sign},
+    sign::v4::SigningParams,
+};
+use tracing::{debug, info, warn};
+
+/// The CloudFront API is global, and is signed for `us-east-1`.
+const CLOUDFRONT_ENDPOINT: &str = "https://cloudfront.amazonaws.com";
+const CLOUDFRONT_SIGNING_REGION: &str = "us-east-1";
+const CLOUDFRONT_API_VERSION: &str = "2020-05-31";
+
+/// Creates CloudFront invalidations for the files that changes publish.
+///
+/// Invalidation paths are the objects' S3 keys, so this assumes that the
+/// distribution's origin is the root of the repositories' bucket.
+#[derive(Clone, Debug)]
+pub struct CloudFrontInvalidator {
+    distribution_id: String,
+    credentials: SharedCredentialsProvider,
+    http: reqwest::Client,
+}
+
+impl CloudFrontInvalidator {
+    pub fn new(distribution_id: String, credentials: SharedCredentialsProvider) -> Self {
+        Self {
+            distribution_id,
+            credentials,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Invalidate the cached copies of some paths.
+    ///
+    /// The files have already been published by the time they are
+    /// invalidated, so failures are logged instead of returned: the CDN
+    /// serves the new files once its cached copies expire anyway.
+    pub async fn invalidate(&self, paths: Vec<String>) {
+        if paths.is_empty() {
+            return;
+        }
+        match self.create_invalidation(&paths).await {
+            Ok(invalidation_id) => info!(
+                distribution_id = %self.distribution_id,
+                %invalidation_id,
+                ?paths,
+                "created CDN invalidation"
+            ),
+            Err(err) => warn!(
+                distribution_id = %self.distribution_id,
+                ?paths,
+                %err,
+                "could not create CDN invalidation"
+            ),
+        }
+    }
+
+    /// Create an invalidation, returning its ID.
+    async fn create_invalidation(&self, paths: &[String]) -> Result<String, String> {
+        let url = format!(
+            "{CLOUDFRONT_ENDPOINT}/{CLOUDFRONT_API_VERSION}/distribution/{}/invalidation",
+            self.distribution_id
+        );
+        let body = invalidation_batch(paths, &uuid::Uuid::new_v4().to_string());
+
+        let credentials = self
+            .credentials
+            .provide_credentials()
+            .await
+            .map_err(|err| format!("could not load AWS credentials: {err}"))?;
+        let identity = credentials.into();
+        let params = SigningParams::builder()
+            .identity(&identity)
+            .region(CLOUDFRONT_SIGNING_REGION)
+            .name("cloudfront")
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()
+            .map_err(|err| format!("could not build signing parameters: {err}"))?
+            .into();
+        let headers = [("content-type", "application/xml")];
+        let signable = SignableRequest::new(
+            "POST",
+            &url,
+            headers.into_iter(),
+            SignableBody::Bytes(body.as_bytes()),
+        )
+        .map_err(|err| format!("could not sign request: {err}"))?;
+        let (instructions, _signature) = sign(signable, &params)
+            .map_err(|err| format!("could not sign request: {err}"))?
+            .into_parts();
+
+        let mut request = self.http.post(&url).body(body.clone());
+        for (name, value) in headers.into_iter().chain(instructions.headers()) {
+            request = request.header(name, value);
+        }
+        debug!(%url, %body, "creating CDN invalidation");
+        let response = request.send().await.map_err(|err| err.to_string())?;
+        let status = response.status();
+        let response = response.text().await.map_err(|err| err.to_string())?;
+        if !status.is_success() {
+            return Err(format!("CloudFront returned {status}: {response}"));
+        }
+        parse_invalidation_id(&response)
+            .map(String::from)
+            .ok_or_else(|| format!("could not find invalidation ID in response: {response}"))
+    }
+}
+
+/// The paths to invalidate after publishing a change to a distribution: its
+/// Release files, and the Packages indexes (in every compression) of the
+/// architectures whose indexes changed.
+///
+/// By-hash indexes are never overwritten, so they don't need invalidating.
+pub fn invalidation_paths<'a>(
+    s3_prefix: &str,
+    distribution: &str,
+    component: &str,
+    architectures: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let dist = format!("/{s3_prefix}/dists/{distribution}");
+    let mut paths = ["InRelease", "Release", "Release.gpg"]
+        .into_iter()
+        .map(|name| format!("{dist}/{name}"))
+        .collect::<Vec<_>>();
+    for architecture in architectures {
+        let path = format!("{dist}/{component}/binary-{architecture}/Packages*");
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// The body of a `CreateInvalidation` request.
+fn invalidation_batch(paths: &[String], caller_reference: &str) -> String {
+    let items = paths
+        .iter()
+        .map(|path| format!("<Path>{}</Path>", escape_xml(path)))
+        .collect::<String>();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><InvalidationBatch xmlns="http://cloudfront.amazonaws.com/doc/{CLOUDFRONT_API_VERSION}/"><Paths><Quantity>{}</Quantity><Items>{items}</Items></Paths><CallerReference>{}</CallerReference></InvalidationBatch>"#,
+        paths.len(),
+        escape_xml(caller_reference),
+    )
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Find the invalidation ID in a `CreateInvalidation` response. The
+/// invalidation's `Id` is the only one in the response.
+fn parse_invalidation_id(response: &str) -> Option<&str> {
+    let (_, rest) = response.split_once("<Id>")?;
+    let (id, _) = rest.split_once("</Id>")?;
+    Some(id.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_for_changed_indexes() {
+        assert_eq!(
+            invalidation_paths("abc/def", "stable", "main", ["amd64", "arm64", "amd64"]),
+            vec![
+                "/abc/def/dists/stable/InRelease",
+                "/abc/def/dists/stable/Release",
+                "/abc/def/dists/stable/Release.gpg",
+                "/abc/def/dists/stable/main/binary-amd64/Packages*",
+                "/abc/def/dists/stable/main/binary-arm64/Packages*",
+            ]
+        );
+        // Re-signing only changes the Release files.
+        assert_eq!(invalidation_paths("abc", "stable", "main", []).len(), 3);
+    }
+
+    #[test]
+    fn create_invalidation_request_and_response() {
+        let body = invalidation_batch(
+            &[
+                String::from("/abc/dists/stable/Release"),
+                String::from("/abc/dists/a&b/Release"),
+            ],
+            "ref",
+        );
+        assert!(
+            body.contains("<Paths><Quantity>2</Quantity><Items><Path>/abc/dists/stable/Release</Path><Path>/abc/dists/a&amp;b/Release</Path></Items></Paths>"),
+            "{body}"
+        );
+        assert!(
+            body.contains("<CallerReference>ref</CallerReference>"),
+            "{body}"
+        );
+
+        let response = r#"<?xml version="1.0"?>
+            <Invalidation xmlns="http://cloudfront.amazonaws.com/doc/2020-05-31/">
+                <Id>I2J0I21PCUYOIK</Id>
+                <Status>InProgress</Status>
+            </Invalidation>"#;
+        assert_eq!(parse_invalidation_id(response), Some("I2J0I21PCUYOIK"));
+        assert_eq!(parse_invalidation_id("<Error/>"), None);
+    }
+}
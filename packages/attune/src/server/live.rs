@@ -0,0 +1,16 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LivenessResponse {
+    alive: bool,
+}
+
+/// Report that the server process is up and handling requests.
+///
+/// Unlike the health check, this doesn't check the server's dependencies, so a
+/// database outage doesn't make orchestrators restart the server.
+#[axum::debug_handler]
+pub async fn handler() -> Json<LivenessResponse> {
+    Json(LivenessResponse { alive: true })
+}
@@ -0,0 +1,407 @@
+//! Prometheus metrics for operating the control plane.
+//!
+//! Metrics are opt-in: when they're enabled, the server records HTTP requests,
+//! package upload sizes, index signing latencies, and object store failures,
+//! and serves them in the Prometheus text format at `/api/v0/metrics`.
+//!
+//! There are only a handful of metrics, so they're kept in a small registry
+//! here instead of pulling in a metrics library.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use http::{StatusCode, header::CONTENT_TYPE};
+
+use crate::server::object_store::{ObjectStore, ObjectStoreError};
+
+/// A metric's name, help text, and (for histograms) bucket bounds.
+struct Desc {
+    name: &'static str,
+    help: &'static str,
+    buckets: &'static [f64],
+}
+
+const HTTP_REQUESTS: Desc = Desc {
+    name: "attune_http_requests_total",
+    help: "HTTP requests handled, by method, route, and status.",
+    buckets: &[],
+};
+const HTTP_REQUEST_DURATION: Desc = Desc {
+    name: "attune_http_request_duration_seconds",
+    help: "Time to handle HTTP requests, by method and route.",
+    buckets: &[
+        0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+    ],
+};
+const PACKAGE_UPLOAD_BYTES: Desc = Desc {
+    name: "attune_package_upload_bytes",
+    help: "Sizes of uploaded packages.",
+    buckets: &[16e3, 64e3, 256e3, 1e6, 4e6, 16e6, 64e6, 256e6, 1e9, 4e9],
+};
+const INDEX_SIGN_DURATION: Desc = Desc {
+    name: "attune_index_sign_duration_seconds",
+    help: "Time to apply and publish signed index changes, by whether they were batched.",
+    buckets: &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0],
+};
+const OBJECT_STORE_FAILURES: Desc = Desc {
+    name: "attune_object_store_failures_total",
+    help: "Failed object store (e.g. S3) operations, by operation.",
+    buckets: &[],
+};
+
+type Labels = Vec<(&'static str, String)>;
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// The number of observations in each bucket (not cumulative).
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    counters: BTreeMap<(&'static str, Labels), u64>,
+    histograms: BTreeMap<(&'static str, Labels), Histogram>,
+}
+
+/// The server's metrics. Cloning shares the same registry.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn increment(&self, desc: &Desc, labels: Labels) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry.counters.entry((desc.name, labels)).or_default() += 1;
+    }
+
+    fn observe(&self, desc: &Desc, labels: Labels, value: f64) {
+        let mut registry = self.registry.lock().unwrap();
+        let histogram = registry
+            .histograms
+            .entry((desc.name, labels))
+            .or_insert_with(|| Histogram {
+                buckets: vec![0; desc.buckets.len()],
+                ..Default::default()
+            });
+        if let Some(bucket) = desc.buckets.iter().position(|bound| value <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    pub fn record_http_request(
+        &self,
+        method: &str,
+        route: &str,
+        status: StatusCode,
+        duration: Duration,
+    ) {
+        self.increment(
+            &HTTP_REQUESTS,
+            vec![
+                ("method", method.to_string()),
+                ("route", route.to_string()),
+                ("status", status.as_u16().to_string()),
+            ],
+        );
+        self.observe(
+            &HTTP_REQUEST_DURATION,
+            vec![("method", method.to_string()), ("route", route.to_string())],
+            duration.as_secs_f64(),
+        );
+    }
+
+    pub fn record_package_upload(&self, bytes: u64) {
+        self.observe(&PACKAGE_UPLOAD_BYTES, Vec::new(), bytes as f64);
+    }
+
+    pub fn record_index_sign(&self, batch: bool, duration: Duration) {
+        self.observe(
+            &INDEX_SIGN_DURATION,
+            vec![("batch", batch.to_string())],
+            duration.as_secs_f64(),
+        );
+    }
+
+    pub fn record_object_store_failure(&self, operation: &'static str) {
+        self.increment(
+            &OBJECT_STORE_FAILURES,
+            vec![("operation", operation.to_string())],
+        );
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+        for desc in [&HTTP_REQUESTS, &OBJECT_STORE_FAILURES] {
+            writeln!(out, "# HELP {} {}", desc.name, desc.help).unwrap();
+            writeln!(out, "# TYPE {} counter", desc.name).unwrap();
+            for ((_, labels), value) in registry
+                .counters
+                .iter()
+                .filter(|((name, _), _)| *name == desc.name)
+            {
+                writeln!(out, "{}{} {value}", desc.name, format_labels(labels, None)).unwrap();
+            }
+        }
+        for desc in [
+            &HTTP_REQUEST_DURATION,
+            &PACKAGE_UPLOAD_BYTES,
+            &INDEX_SIGN_DURATION,
+        ] {
+            writeln!(out, "# HELP {} {}", desc.name, desc.help).unwrap();
+            writeln!(out, "# TYPE {} histogram", desc.name).unwrap();
+            for ((_, labels), histogram) in registry
+                .histograms
+                .iter()
+                .filter(|((name, _), _)| *name == desc.name)
+            {
+                let mut cumulative = 0;
+                for (bound, count) in desc.buckets.iter().zip(&histogram.buckets) {
+                    cumulative += count;
+                    let le = bound.to_string();
+                    writeln!(
+                        out,
+                        "{}_bucket{} {cumulative}",
+                        desc.name,
+                        format_labels(labels, Some(&le))
+                    )
+                    .unwrap();
+                }
+                writeln!(
+                    out,
+                    "{}_bucket{} {}",
+                    desc.name,
+                    format_labels(labels, Some("+Inf")),
+                    histogram.count
+                )
+                .unwrap();
+                let labels = format_labels(labels, None);
+                writeln!(out, "{}_sum{labels} {}", desc.name, histogram.sum).unwrap();
+                writeln!(out, "{}_count{labels} {}", desc.name, histogram.count).unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Format labels as `{name="value",...}`, with an optional `le` label for
+/// histogram buckets.
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', r"\\")
+                .replace('"', r#"\""#)
+                .replace('\n', r"\n");
+            format!(r#"{name}="{value}""#)
+        })
+        .collect::<Vec<_>>();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+/// Serve the metrics in the Prometheus text format.
+pub async fn handler(State(metrics): State<Option<Metrics>>) -> Response {
+    match metrics {
+        Some(metrics) => (
+            [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+            metrics.render(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Middleware that records the method, route, status, and duration of each
+/// request.
+///
+/// Requests are labelled by their route template (like
+/// `/api/v0/repositories/{repository_name}`) rather than their path, so that
+/// the number of series stays bounded.
+pub async fn record(State(metrics): State<Metrics>, request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request.extensions().get::<MatchedPath>().map_or_else(
+        || String::from("unmatched"),
+        |path| path.as_str().to_string(),
+    );
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics.record_http_request(&method, &route, response.status(), start.elapsed());
+    response
+}
+
+/// An [`ObjectStore`] that counts the failed operations of another store.
+#[derive(Debug)]
+pub struct MeteredObjectStore {
+    inner: Arc<dyn ObjectStore>,
+    metrics: Metrics,
+}
+
+impl MeteredObjectStore {
+    pub fn new(inner: Arc<dyn ObjectStore>, metrics: Metrics) -> Self {
+        Self { inner, metrics }
+    }
+
+    fn record<T>(
+        &self,
+        operation: &'static str,
+        result: Result<T, ObjectStoreError>,
+    ) -> Result<T, ObjectStoreError> {
+        // Missing objects are expected (e.g. by consistency checks), so they
+        // aren't counted as failures.
+        if let Err(ObjectStoreError::Other(_)) = result {
+            self.metrics.record_object_store_failure(operation);
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl ObjectStore for MeteredObjectStore {
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        contents: Bytes,
+        sha256sum: &str,
+    ) -> Result<(), ObjectStoreError> {
+        let result = self
+            .inner
+            .put_object(bucket, key, contents, sha256sum)
+            .await;
+        self.record("put_object", result)
+    }
+
+    async fn copy_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        source: &str,
+    ) -> Result<(), ObjectStoreError> {
+        let result = self.inner.copy_object(bucket, key, source).await;
+        self.record("copy_object", result)
+    }
+
+    async fn delete_objects(&self, bucket: &str, keys: &[String]) -> Result<(), ObjectStoreError> {
+        let result = self.inner.delete_objects(bucket, keys).await;
+        self.record("delete_objects", result)
+    }
+
+    async fn head_object_checksum(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<String>, ObjectStoreError> {
+        let result = self.inner.head_object_checksum(bucket, key).await;
+        self.record("head_object", result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+
+    use super::*;
+    use crate::{server::ServerState, testing::test_server_state};
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn serve_recorded_metrics(pool: sqlx::PgPool) {
+        let state = ServerState {
+            metrics: Some(Metrics::new()),
+            ..test_server_state(pool)
+        };
+        let app = crate::server::new(
+            state,
+            Some(String::from("test-api-token")),
+            Default::default(),
+            None,
+        )
+        .await;
+        let server = TestServer::new(app).unwrap();
+
+        server
+            .get("/api/v0/repositories/missing")
+            .authorization_bearer("test-api-token")
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+        let response = server.get("/api/v0/metrics").await;
+        response.assert_status_ok();
+        // Requests are labelled by route, not by path.
+        let text = response.text();
+        assert!(
+            text.contains(r#"attune_http_requests_total{method="GET",route="/api/v0/repositories/{repository_name}",status="404"} 1"#),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn render_prometheus_text() {
+        let metrics = Metrics::new();
+        metrics.record_http_request(
+            "GET",
+            "/api/v0/repositories/{repository_name}",
+            StatusCode::OK,
+            Duration::from_millis(20),
+        );
+        metrics.record_http_request(
+            "GET",
+            "/api/v0/repositories/{repository_name}",
+            StatusCode::OK,
+            Duration::from_millis(200),
+        );
+        metrics.record_package_upload(2_000_000);
+        metrics.record_object_store_failure("put_object");
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE attune_http_requests_total counter",
+            r#"attune_http_requests_total{method="GET",route="/api/v0/repositories/{repository_name}",status="200"} 2"#,
+            "# TYPE attune_http_request_duration_seconds histogram",
+            r#"attune_http_request_duration_seconds_bucket{method="GET",route="/api/v0/repositories/{repository_name}",le="0.025"} 1"#,
+            r#"attune_http_request_duration_seconds_bucket{method="GET",route="/api/v0/repositories/{repository_name}",le="0.25"} 2"#,
+            r#"attune_http_request_duration_seconds_bucket{method="GET",route="/api/v0/repositories/{repository_name}",le="+Inf"} 2"#,
+            r#"attune_http_request_duration_seconds_count{method="GET",route="/api/v0/repositories/{repository_name}"} 2"#,
+            r#"attune_package_upload_bytes_bucket{le="1000000"} 0"#,
+            r#"attune_package_upload_bytes_bucket{le="4000000"} 1"#,
+            "attune_package_upload_bytes_sum 2000000",
+            r#"attune_object_store_failures_total{operation="put_object"} 1"#,
+        ] {
+            assert!(
+                text.lines().any(|l| l == line),
+                "missing {line:?} in:\n{text}"
+            );
+        }
+
+        assert_eq!(
+            format_labels(&vec![("route", String::from("a\"b\\c"))], None),
+            r#"{route="a\"b\\c"}"#
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Startup checks that the database schema matches this build.
+//!
+//! Migrations are normally applied with Prisma (see `docker/migrate`), which
+//! records them in `_prisma_migrations`. Test databases are migrated with
+//! [`MIGRATOR`] instead, which records them in `_sqlx_migrations`. Either
+//! table counts as a record of applied migrations.
+
+use std::collections::BTreeSet;
+
+use sha2::{Digest as _, Sha256};
+use sqlx::{PgPool, Row as _};
+use tracing::info;
+use uuid::{ContextV7, Timestamp, Uuid};
+
+use crate::testing::MIGRATOR;
+
+/// How the database's applied migrations compare to this build's migrations.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct MigrationStatus {
+    /// Migrations this build expects that have not been applied.
+    pub pending: Vec<String>,
+    /// Applied migrations that this build doesn't know about, which usually
+    /// means the database was migrated by a newer version of Attune.
+    pub unknown: Vec<String>,
+}
+
+impl MigrationStatus {
+    /// Whether the database schema is exactly the one this build expects.
+    pub fn is_current(&self) -> bool {
+        self.pending.is_empty() && self.unknown.is_empty()
+    }
+}
+
+/// The names of this build's migrations, in Prisma's `<version>_<description>`
+/// format.
+fn expected_migrations() -> impl Iterator<Item = (String, &'static sqlx::migrate::Migration)> {
+    MIGRATOR.iter().map(|migration| {
+        (
+            format!("{}_{}", migration.version, migration.description),
+            migration,
+        )
+    })
+}
+
+/// Compare the database's applied migrations to this build's migrations.
+pub async fn check(db: &PgPool) -> Result<MigrationStatus, sqlx::Error> {
+    let mut applied = BTreeSet::new();
+    if table_exists(db, "_prisma_migrations").await? {
+        let rows = sqlx::query(
+            "SELECT migration_name FROM _prisma_migrations WHERE finished_at IS NOT NULL AND rolled_back_at IS NULL",
+        )
+        .fetch_all(db)
+        .await?;
+        for row in rows {
+            applied.insert(row.try_get::<String, _>("migration_name")?);
+        }
+    }
+    if table_exists(db, "_sqlx_migrations").await? {
+        let rows = sqlx::query("SELECT version, description FROM _sqlx_migrations WHERE success")
+            .fetch_all(db)
+            .await?;
+        for row in rows {
+            applied.insert(format!(
+                "{}_{}",
+                row.try_get::<i64, _>("version")?,
+                row.try_get::<String, _>("description")?
+            ));
+        }
+    }
+
+    let expected = expected_migrations()
+        .map(|(name, _)| name)
+        .collect::<BTreeSet<_>>();
+    Ok(MigrationStatus {
+        pending: expected.difference(&applied).cloned().collect(),
+        unknown: applied.difference(&expected).cloned().collect(),
+    })
+}
+
+async fn table_exists(db: &PgPool, table: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query("SELECT to_regclass($1) IS NOT NULL AS exists")
+        .bind(table)
+        .fetch_one(db)
+        .await?
+        .try_get("exists")
+}
+
+/// Apply pending migrations, recording them in `_prisma_migrations` so that
+/// later `prisma migrate deploy` runs see them as applied.
+pub async fn apply_pending(db: &PgPool, status: &MigrationStatus) -> Result<(), sqlx::Error> {
+    // This is the table Prisma creates before its first migration.
+    sqlx::raw_sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS _prisma_migrations (
+            id VARCHAR(36) PRIMARY KEY NOT NULL,
+            checksum VARCHAR(64) NOT NULL,
+            finished_at TIMESTAMPTZ,
+            migration_name VARCHAR(255) NOT NULL,
+            logs TEXT,
+            rolled_back_at TIMESTAMPTZ,
+            started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            applied_steps_count INTEGER NOT NULL DEFAULT 0
+        )
+        "#,
+    )
+    .execute(db)
+    .await?;
+
+    for (name, migration) in expected_migrations() {
+        if !status.pending.contains(&name) {
+            continue;
+        }
+        info!(?name, "applying migration");
+        let mut tx = db.begin().await?;
+        sqlx::raw_sql(&migration.sql).execute(&mut *tx).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO _prisma_migrations (id, checksum, finished_at, migration_name, started_at, applied_steps_count)
+            VALUES ($1, $2, NOW(), $3, NOW(), 1)
+            "#,
+        )
+        .bind(Uuid::new_v7(Timestamp::now(ContextV7::new())).to_string())
+        .bind(hex::encode(Sha256::digest(migration.sql.as_bytes())))
+        .bind(&name)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test databases are migrated with `MIGRATOR`, so they are current until
+    /// their recorded migrations are changed.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn detect_pending_and_unknown_migrations(pool: PgPool) {
+        assert!(check(&pool).await.unwrap().is_current());
+
+        let latest = MIGRATOR.iter().last().unwrap();
+        sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+            .bind(latest.version)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            r#"
+            INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+            VALUES (99990101000000, 'from_the_future', TRUE, '', 0)
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            check(&pool).await.unwrap(),
+            MigrationStatus {
+                pending: vec![format!("{}_{}", latest.version, latest.description)],
+                unknown: vec![String::from("99990101000000_from_the_future")],
+            }
+        );
+    }
+}
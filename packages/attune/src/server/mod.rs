@@ -1,9 +1,17 @@
+pub mod cdn;
 pub mod compatibility;
 pub mod health;
+pub mod live;
+pub mod metrics;
+pub mod migrations;
+pub mod object_store;
 pub mod pkg;
+pub mod rate_limit;
 pub mod repo;
+pub mod s3_concurrency;
+pub mod webhook;
 
-use std::{any::Any, time::Duration};
+use std::{any::Any, sync::Arc, time::Duration};
 
 use axum::{
     BoxError, Router,
@@ -13,7 +21,7 @@ use axum::{
     handler::Handler,
     middleware::Next,
     response::{IntoResponse, Response},
-    routing::{get, put},
+    routing::{get, post, put},
 };
 use http::StatusCode;
 use sha2::{Digest as _, Sha256};
@@ -23,17 +31,93 @@ use tower_http::{catch_panic::CatchPanicLayer, trace::TraceLayer};
 use tracing::warn;
 use uuid::{ContextV7, Timestamp, Uuid};
 
-use crate::{api::ErrorResponse, server::compatibility::API_VERSION_HEADER};
+use crate::{
+    api::ErrorResponse,
+    server::{
+        cdn::CloudFrontInvalidator,
+        compatibility::API_VERSION_HEADER,
+        metrics::Metrics,
+        object_store::ObjectStore,
+        pkg::PackageKeyScheme,
+        rate_limit::{RateLimit, RateLimiter},
+        repo::index::ContentsEncoding,
+        s3_concurrency::S3Concurrency,
+    },
+};
 
 #[derive(Clone, Debug, FromRef)]
 pub struct ServerState {
     pub db: PgPool,
+    /// The S3 client, for the operations that aren't abstracted by
+    /// `object_store` yet, like uploads, listing, and bulk deletion.
     pub s3: aws_sdk_s3::Client,
 
+    /// Where repository indexes and pool files are published.
+    pub object_store: Arc<dyn ObjectStore>,
+
     pub s3_bucket_name: String,
+
+    /// Limits the number of S3 requests in flight across all API requests.
+    pub s3_concurrency: S3Concurrency,
+
+    /// Template for the public base URL of each repository, with `{bucket}`
+    /// and `{prefix}` placeholders for the repository's S3 bucket and prefix.
+    ///
+    /// If unset, the server doesn't know where repositories are served from,
+    /// and doesn't report their base URLs.
+    pub public_base_url: Option<String>,
+
+    /// The maximum number of repositories that each tenant can have. If unset,
+    /// tenants can create any number of repositories.
+    pub max_repos_per_tenant: Option<u32>,
+
+    /// How Packages indexes are encoded when they are stored in the database.
+    /// If unset, they are stored as-is. Indexes that were stored with another
+    /// encoding can still be read.
+    pub index_contents_encoding: Option<ContentsEncoding>,
+
+    /// How newly uploaded packages are keyed in the S3 bucket.
+    pub package_key_scheme: PackageKeyScheme,
+
+    /// The CDN to invalidate published Release files and indexes in. If
+    /// unset, the server doesn't invalidate any caches.
+    pub cdn: Option<CloudFrontInvalidator>,
+
+    /// Where to record metrics. If unset, metrics aren't recorded, and the
+    /// metrics endpoint returns `404 Not Found`.
+    pub metrics: Option<Metrics>,
+
+    /// How long the by-hash files of replaced Packages indexes are kept after
+    /// a change is published, for clients that fetched the previous Release
+    /// file. If zero, they are deleted right away.
+    pub index_deletion_grace: Duration,
+}
+
+/// Request timeouts enforced by the server's middleware stack.
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    /// Timeout for API requests other than package uploads.
+    pub request: Duration,
+    /// Timeout for package uploads, which can legitimately take much longer
+    /// than other requests for large packages.
+    pub upload: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            request: Duration::from_secs(600),
+            upload: Duration::from_secs(3600),
+        }
+    }
 }
 
-pub async fn new(state: ServerState, default_api_token: Option<String>) -> Router {
+pub async fn new(
+    state: ServerState,
+    default_api_token: Option<String>,
+    timeouts: Timeouts,
+    rate_limit: Option<RateLimit>,
+) -> Router {
     // Initialize special single-tenant user.
     sqlx::query!(
         r#"
@@ -55,10 +139,14 @@ pub async fn new(state: ServerState, default_api_token: Option<String>) -> Route
                 .begin()
                 .await
                 .expect("could not start default user initialization");
-            sqlx::query!("DELETE FROM attune_tenant_api_token WHERE tenant_id = 1;")
-                .execute(&mut *tx)
-                .await
-                .expect("could not remove existing single-tenant API token");
+            // Only replace the default token, so that tokens added with
+            // `attune-server token add` survive restarts.
+            sqlx::query!(
+                "DELETE FROM attune_tenant_api_token WHERE tenant_id = 1 AND name = 'LOCAL_TENANT_API_TOKEN';"
+            )
+            .execute(&mut *tx)
+            .await
+            .expect("could not remove existing single-tenant API token");
             sqlx::query!(
                 r#"
                 INSERT INTO attune_tenant_api_token (tenant_id, name, token, created_at, updated_at)
@@ -95,7 +183,12 @@ pub async fn new(state: ServerState, default_api_token: Option<String>) -> Route
     // Configure routes.
     let api = Router::new()
         .route("/compatibility", get(compatibility::handler))
+        .route("/metrics", get(metrics::handler))
+        // `/health` checks that the server's dependencies are ready, while
+        // `/livez` only checks that the server is up.
         .route("/health", get(health::handler))
+        .route("/healthz", get(health::handler))
+        .route("/livez", get(live::handler))
         .route(
             "/repositories",
             get(repo::list::handler).post(repo::create::handler),
@@ -106,10 +199,46 @@ pub async fn new(state: ServerState, default_api_token: Option<String>) -> Route
                 .put(repo::edit::handler)
                 .delete(repo::delete::handler),
         )
+        .route(
+            "/repositories/{repository_name}/clone",
+            post(repo::clone::handler),
+        )
+        .route(
+            "/repositories/{repository_name}/status",
+            get(repo::status::handler),
+        )
+        .route(
+            "/repositories/{repository_name}/usage",
+            get(repo::usage::handler),
+        )
+        .route(
+            "/repositories/{repository_name}/stats",
+            get(repo::stats::handler),
+        )
+        .route(
+            "/repositories/{repository_name}/objects",
+            get(repo::export::list_handler),
+        )
+        .route(
+            "/repositories/{repository_name}/objects/{*path}",
+            get(repo::export::object_handler),
+        )
+        .route(
+            "/repositories/{repository_name}/changelogs/{package}/{version}",
+            get(repo::changelog::changelog_handler),
+        )
+        .route(
+            "/repositories/{repository_name}/changelogs/{package}/{version}/copyright",
+            get(repo::changelog::copyright_handler),
+        )
         .route(
             "/repositories/{repository_name}/index",
             get(repo::index::generate::handler).post(repo::index::sign::handler),
         )
+        .route(
+            "/repositories/{repository_name}/index/batch",
+            get(repo::index::batch::generate_handler).post(repo::index::batch::sign_handler),
+        )
         .route(
             "/repositories/{repository_name}/distributions",
             get(repo::dist::list::handler).post(repo::dist::create::handler),
@@ -118,25 +247,91 @@ pub async fn new(state: ServerState, default_api_token: Option<String>) -> Route
             "/repositories/{repository_name}/distributions/{distribution_name}",
             put(repo::dist::edit::handler).delete(repo::dist::delete::handler),
         )
+        .route(
+            "/repositories/{repository_name}/distributions/{distribution_name}/clear",
+            post(repo::dist::clear::handler),
+        )
+        .route(
+            "/repositories/{repository_name}/distributions/{distribution_name}/release",
+            get(repo::dist::release::handler),
+        )
+        .route(
+            "/repositories/{repository_name}/distributions/{distribution_name}/components/{component_name}/binary-{architecture}/packages",
+            get(repo::dist::packages::handler),
+        )
         .route(
             "/repositories/{repository_name}/distributions/{distribution_name}/sync",
             get(repo::sync::check::handler).post(repo::sync::resync::handler),
         )
+        .route(
+            "/repositories/{repository_name}/sync",
+            post(repo::sync::resync::repository_handler),
+        )
+        .route("/packages", get(pkg::list::handler))
+        .route("/packages/search", get(pkg::search::handler))
+        .route("/packages/{package_sha256sum}", get(pkg::info::handler))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_middleware_error))
+                .timeout(timeouts.request),
+        )
+        // Package uploads (including registering packages that are already in
+        // S3) get their own timeout, since large packages can legitimately
+        // take much longer to upload than other requests take to complete.
+        // This must be registered after the default timeout layer so that
+        // uploads aren't also subject to it.
         .route(
             "/packages",
-            get(pkg::list::handler).post(pkg::upload::handler.layer(DefaultBodyLimit::disable())),
+            post(
+                pkg::upload::handler
+                    // Leave room for the multipart framing around the package.
+                    .layer(DefaultBodyLimit::max(
+                        pkg::MAX_PACKAGE_SIZE as usize + 64 * 1024,
+                    ))
+                    .layer(
+                        ServiceBuilder::new()
+                            .layer(HandleErrorLayer::new(handle_middleware_error))
+                            .timeout(timeouts.upload),
+                    ),
+            ),
         )
-        .route("/packages/{package_sha256sum}", get(pkg::info::handler));
+        .route(
+            "/packages/reference",
+            post(
+                pkg::reference::handler.layer(
+                    ServiceBuilder::new()
+                        .layer(HandleErrorLayer::new(handle_middleware_error))
+                        .timeout(timeouts.upload),
+                ),
+            ),
+        );
+
+    // Rate limiting runs inside `handle_non_success`, so its `RATE_LIMITED`
+    // errors are passed through as-is.
+    let mut app = Router::new().nest("/api/v0", api);
+    if let Some(limit) = rate_limit {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            RateLimiter::in_memory(limit, state.db.clone()),
+            rate_limit::rate_limit,
+        ));
+    }
+    // Metrics are recorded outside of rate limiting, so that rate limited
+    // requests are counted too.
+    if let Some(metrics) = &state.metrics {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            metrics.clone(),
+            metrics::record,
+        ));
+    }
 
     // The intention of error handling middleware here is that:
     // - `handle_non_success` handles responses from handlers and axum itself,
     //   converting errors to `ErrorResponse`.
-    // - `handle_middleware_error` handles errors from the middleware stack,
-    //   converting them to `ErrorResponse`.
+    // - `handle_middleware_error` handles errors from the middleware stack (e.g.
+    //   timeouts, which are configured per-route above), converting them to
+    //   `ErrorResponse`.
     // - `handle_panic` handles panics, converting them to `ErrorResponse`.
-    Router::new()
-        .nest("/api/v0", api)
-        .layer(axum::middleware::from_fn(handle_non_success))
+    app.layer(axum::middleware::from_fn(handle_non_success))
         .layer(
             ServiceBuilder::new()
                 .layer(
@@ -168,13 +363,25 @@ pub async fn new(state: ServerState, default_api_token: Option<String>) -> Route
                         }
                     }),
                 )
-                .layer(CatchPanicLayer::custom(handle_panic))
-                .layer(HandleErrorLayer::new(handle_middleware_error))
-                .timeout(Duration::from_secs(600)),
+                .layer(CatchPanicLayer::custom(handle_panic)),
         )
         .with_state(state)
 }
 
+/// Serve the app under a base path, for deployments where a reverse proxy
+/// mounts Attune under a subpath (e.g. `https://example.com/attune/`) without
+/// stripping it from requests.
+///
+/// An empty base path (or `/`) serves the app at the root.
+pub fn with_base_path(app: Router, base_path: &str) -> Router {
+    let base_path = base_path.trim_matches('/');
+    if base_path.is_empty() {
+        app
+    } else {
+        Router::new().nest(&format!("/{base_path}"), app)
+    }
+}
+
 async fn handle_non_success(request: Request, next: Next) -> Response {
     let uri = request.uri().to_string();
     let response = next.run(request).await;
@@ -258,3 +465,25 @@ async fn handle_middleware_error(err: BoxError) -> ErrorResponse {
         format!("internal server error: {err}"),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use axum_test::TestServer;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn serve_under_base_path() {
+        let app = || Router::new().route("/api/v0/livez", get(live::handler));
+
+        for base_path in ["", "/"] {
+            let server = TestServer::new(with_base_path(app(), base_path)).unwrap();
+            server.get("/api/v0/livez").await.assert_status_ok();
+        }
+        for base_path in ["attune", "/attune", "/attune/"] {
+            let server = TestServer::new(with_base_path(app(), base_path)).unwrap();
+            server.get("/attune/api/v0/livez").await.assert_status_ok();
+            server.get("/api/v0/livez").await.assert_status_not_found();
+        }
+    }
+}
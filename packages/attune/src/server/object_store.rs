@@ -0,0 +1,264 @@
+//! Storage for published repository objects.
+//!
+//! Publishing and resyncing repositories only need a handful of operations on
+//! the object store, so they go through the [`ObjectStore`] trait instead of
+//! calling S3 directly. This leaves room for other backends (like a local
+//! filesystem or GCS) behind the same code paths. There are two
+//! implementations: [`S3ObjectStore`] for production, and
+//! [`FilesystemObjectStore`] for local development.
+
+pub mod fs;
+
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+use aws_sdk_s3::{
+    operation::{
+        copy_object::builders::CopyObjectFluentBuilder,
+        put_object::builders::PutObjectFluentBuilder,
+    },
+    types::{ChecksumAlgorithm, ChecksumMode, Delete, ObjectIdentifier, ServerSideEncryption},
+};
+use base64::Engine as _;
+use bytes::Bytes;
+use md5::{Digest as _, Md5};
+use thiserror::Error;
+
+pub use fs::FilesystemObjectStore;
+
+#[derive(Debug, Error)]
+pub enum ObjectStoreError {
+    #[error("object not found")]
+    NotFound,
+    #[error("{0}")]
+    Other(String),
+}
+
+/// An object store holding the published files of repositories.
+///
+/// Objects are addressed by bucket and key, as in S3. The SHA256 sums passed to
+/// and returned from the store are hex-encoded, to match the database.
+#[async_trait]
+pub trait ObjectStore: Debug + Send + Sync {
+    /// Write an object, failing if the stored contents don't match the
+    /// SHA256 sum.
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        contents: Bytes,
+        sha256sum: &str,
+    ) -> Result<(), ObjectStoreError>;
+
+    /// Copy an object. The source is `<bucket>/<key>`, as returned by
+    /// [`copy_source`](crate::server::pkg::copy_source).
+    async fn copy_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        source: &str,
+    ) -> Result<(), ObjectStoreError>;
+
+    /// Delete up to 1000 objects from a bucket. Deleting objects that don't
+    /// exist is not an error.
+    async fn delete_objects(&self, bucket: &str, keys: &[String]) -> Result<(), ObjectStoreError>;
+
+    /// Look up the SHA256 sum of an object, failing with
+    /// [`ObjectStoreError::NotFound`] if it doesn't exist.
+    ///
+    /// Returns `None` if the store has no checksum for the object. Checksums
+    /// that aren't plain SHA256 sums (like the composite checksums of S3
+    /// multipart uploads) are returned as-is.
+    async fn head_object_checksum(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<String>, ObjectStoreError>;
+}
+
+/// An [`ObjectStore`] backed by S3 (or an S3-compatible store).
+#[derive(Debug, Clone)]
+pub struct S3ObjectStore {
+    s3: aws_sdk_s3::Client,
+    encryption: Option<S3Encryption>,
+}
+
+impl S3ObjectStore {
+    pub fn new(s3: aws_sdk_s3::Client) -> Self {
+        Self {
+            s3,
+            encryption: None,
+        }
+    }
+
+    /// Encrypt every object this store writes with the given server-side
+    /// encryption, instead of relying on the bucket's default encryption.
+    pub fn with_encryption(mut self, encryption: Option<S3Encryption>) -> Self {
+        self.encryption = encryption;
+        self
+    }
+}
+
+/// Server-side encryption settings for the objects written to S3.
+///
+/// With SSE-KMS, the server's credentials need `kms:GenerateDataKey` on the key
+/// to write objects, and `kms:Decrypt` to copy packages into the pool. S3
+/// still reports the SHA256 checksums of encrypted objects, so consistency
+/// checks work the same way.
+#[derive(Debug, Clone)]
+pub struct S3Encryption {
+    pub algorithm: ServerSideEncryption,
+    /// The KMS key to encrypt with, for `aws:kms`. If unset, S3 uses the
+    /// account's AWS managed key.
+    pub kms_key_id: Option<String>,
+}
+
+impl S3Encryption {
+    fn put(&self, put: PutObjectFluentBuilder) -> PutObjectFluentBuilder {
+        put.server_side_encryption(self.algorithm.clone())
+            .set_ssekms_key_id(self.kms_key_id.clone())
+    }
+
+    fn copy(&self, copy: CopyObjectFluentBuilder) -> CopyObjectFluentBuilder {
+        copy.server_side_encryption(self.algorithm.clone())
+            .set_ssekms_key_id(self.kms_key_id.clone())
+    }
+}
+
+fn other(err: impl std::error::Error) -> ObjectStoreError {
+    ObjectStoreError::Other(err.to_string())
+}
+
+#[async_trait]
+impl ObjectStore for S3ObjectStore {
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        contents: Bytes,
+        sha256sum: &str,
+    ) -> Result<(), ObjectStoreError> {
+        let sha256sum = hex::decode(sha256sum).map_err(other)?;
+        let mut put = self.s3.put_object();
+        if let Some(encryption) = &self.encryption {
+            put = encryption.put(put);
+        }
+        put.bucket(bucket)
+            .key(key)
+            .content_md5(base64::engine::general_purpose::STANDARD.encode(Md5::digest(&contents)))
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .checksum_sha256(base64::engine::general_purpose::STANDARD.encode(sha256sum))
+            .body(contents.into())
+            .send()
+            .await
+            .map_err(other)?;
+        Ok(())
+    }
+
+    async fn copy_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        source: &str,
+    ) -> Result<(), ObjectStoreError> {
+        let mut copy = self.s3.copy_object();
+        if let Some(encryption) = &self.encryption {
+            copy = encryption.copy(copy);
+        }
+        copy.bucket(bucket)
+            .key(key)
+            .copy_source(source)
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .send()
+            .await
+            .map_err(other)?;
+        Ok(())
+    }
+
+    async fn delete_objects(&self, bucket: &str, keys: &[String]) -> Result<(), ObjectStoreError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let objects = keys
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(other)?;
+        let delete = Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true)
+            .build()
+            .map_err(other)?;
+        let deleted = self
+            .s3
+            .delete_objects()
+            .bucket(bucket)
+            .delete(delete)
+            .send()
+            .await
+            .map_err(other)?;
+
+        // In quiet mode, only failed deletions are reported.
+        match deleted.errors.unwrap_or_default().as_slice() {
+            [] => Ok(()),
+            errors => Err(ObjectStoreError::Other(format!(
+                "could not delete {} objects, including {:?}: {}",
+                errors.len(),
+                errors[0].key.as_deref().unwrap_or_default(),
+                errors[0].message.as_deref().unwrap_or("unknown error"),
+            ))),
+        }
+    }
+
+    async fn head_object_checksum(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<String>, ObjectStoreError> {
+        let head = self
+            .s3
+            .head_object()
+            .bucket(bucket)
+            .key(key)
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+            .map_err(|err| {
+                let err = err.into_service_error();
+                if err.is_not_found() {
+                    ObjectStoreError::NotFound
+                } else {
+                    other(err)
+                }
+            })?;
+        Ok(head.checksum_sha256().map(checksum_to_hex))
+    }
+}
+
+/// Convert a base64 S3 checksum to hex, to match the checksums in the
+/// database. Checksums that aren't a plain SHA256 sum (like the composite
+/// checksums of multipart uploads) are returned as-is.
+fn checksum_to_hex(checksum: &str) -> String {
+    base64::engine::general_purpose::STANDARD
+        .decode(checksum)
+        .ok()
+        .filter(|sha256sum| sha256sum.len() == 32)
+        .map(hex::encode)
+        .unwrap_or_else(|| checksum.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use sha2::Sha256;
+
+    use super::*;
+
+    #[test]
+    fn checksum_to_hex_decodes_sha256() {
+        let sha256sum = Sha256::digest(b"contents");
+        let checksum = base64::engine::general_purpose::STANDARD.encode(sha256sum);
+        assert_eq!(checksum_to_hex(&checksum), hex::encode(sha256sum));
+        // Composite checksums of multipart uploads aren't plain SHA256 sums.
+        assert_eq!(checksum_to_hex("abc=-2"), "abc=-2");
+    }
+}
@@ -0,0 +1,270 @@
+//! An object store on the local filesystem, for trying Attune without S3.
+
+use std::{
+    fs::File,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use percent_encoding::percent_decode_str;
+use sha2::{Digest as _, Sha256};
+use tracing::debug;
+
+use super::{ObjectStore, ObjectStoreError};
+
+/// An [`ObjectStore`] that keeps each object as a file at
+/// `<root>/<bucket>/<key>`.
+///
+/// Serving the root directory with any static file server gives the same
+/// layout as path-style S3 URLs, so repositories can be served from
+/// `http://<server>/{bucket}/{prefix}`.
+///
+/// Checksums aren't stored: [`head_object_checksum`] reads the whole file to
+/// compute its SHA256 sum, so a file that is modified outside of Attune shows
+/// up as a checksum mismatch, as it would in S3.
+///
+/// [`head_object_checksum`]: ObjectStore::head_object_checksum
+#[derive(Debug, Clone)]
+pub struct FilesystemObjectStore {
+    root: PathBuf,
+}
+
+impl FilesystemObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The path of an object, rejecting buckets and keys that could escape the
+    /// root directory.
+    fn path(&self, bucket: &str, key: &str) -> Result<PathBuf, ObjectStoreError> {
+        let mut path = self.root.clone();
+        for segment in std::iter::once(bucket).chain(key.split('/')) {
+            if segment.is_empty() || segment == "." || segment == ".." || segment.contains('\\') {
+                return Err(ObjectStoreError::Other(format!(
+                    "invalid object key {bucket:?}/{key:?}"
+                )));
+            }
+            path.push(segment);
+        }
+        Ok(path)
+    }
+}
+
+/// Run blocking filesystem operations off of the async runtime.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> Result<T, ObjectStoreError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| ObjectStoreError::Other(err.to_string()))?
+        .map_err(|err| match err.kind() {
+            io::ErrorKind::NotFound => ObjectStoreError::NotFound,
+            _ => ObjectStoreError::Other(err.to_string()),
+        })
+}
+
+/// Write a file by writing a temporary file next to it and renaming it into
+/// place, so that readers never see a partially written object.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> io::Result<()> {
+    let parent = path.parent().expect("object path has a parent");
+    std::fs::create_dir_all(parent)?;
+    let file_name = path.file_name().expect("object path has a file name");
+    let temp = parent.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        uuid::Uuid::new_v4()
+    ));
+    let result = File::create(&temp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match result.and_then(|()| std::fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for FilesystemObjectStore {
+    async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        contents: Bytes,
+        sha256sum: &str,
+    ) -> Result<(), ObjectStoreError> {
+        // Like S3, reject contents that don't match their checksum.
+        let actual = hex::encode(Sha256::digest(&contents));
+        if actual != sha256sum {
+            return Err(ObjectStoreError::Other(format!(
+                "checksum mismatch for {key:?}: expected {sha256sum}, got {actual}"
+            )));
+        }
+        let path = self.path(bucket, key)?;
+        debug!(?path, "writing object");
+        blocking(move || write_atomically(&path, |file| file.write_all(&contents))).await
+    }
+
+    async fn copy_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        source: &str,
+    ) -> Result<(), ObjectStoreError> {
+        // The source is `<bucket>/<key>`, with each segment percent-encoded.
+        let (source_bucket, source_key) = source
+            .split_once('/')
+            .ok_or_else(|| ObjectStoreError::Other(format!("invalid copy source {source:?}")))?;
+        let source_bucket = percent_decode_str(source_bucket).decode_utf8_lossy();
+        let source_key = source_key
+            .split('/')
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let source = self.path(&source_bucket, &source_key)?;
+        let path = self.path(bucket, key)?;
+        debug!(?source, ?path, "copying object");
+        blocking(move || {
+            let mut source = File::open(&source)?;
+            write_atomically(&path, |file| io::copy(&mut source, file).map(|_| ()))
+        })
+        .await
+    }
+
+    async fn delete_objects(&self, bucket: &str, keys: &[String]) -> Result<(), ObjectStoreError> {
+        let paths = keys
+            .iter()
+            .map(|key| self.path(bucket, key))
+            .collect::<Result<Vec<_>, _>>()?;
+        blocking(move || {
+            for path in paths {
+                debug!(?path, "deleting object");
+                match std::fs::remove_file(&path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn head_object_checksum(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<String>, ObjectStoreError> {
+        let path = self.path(bucket, key)?;
+        blocking(move || {
+            let mut hasher = Sha256::new();
+            io::copy(&mut File::open(&path)?, &mut hasher)?;
+            Ok(Some(hex::encode(hasher.finalize())))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_tempfile::TempDir;
+
+    use super::*;
+    use crate::server::pkg::copy_source;
+
+    #[tokio::test]
+    async fn round_trip_objects() {
+        let dir = TempDir::new().await.expect("failed to create temp dir");
+        let store = FilesystemObjectStore::new(dir.dir_path());
+        let sha256sum = hex::encode(Sha256::digest(b"contents"));
+
+        // Missing objects are reported as not found, so that the consistency
+        // check treats them like missing S3 objects.
+        assert!(matches!(
+            store.head_object_checksum("bucket", "1/repo/Release").await,
+            Err(ObjectStoreError::NotFound)
+        ));
+
+        store
+            .put_object(
+                "bucket",
+                "1/repo/Release",
+                Bytes::from_static(b"contents"),
+                &sha256sum,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .head_object_checksum("bucket", "1/repo/Release")
+                .await
+                .unwrap(),
+            Some(sha256sum.clone())
+        );
+        assert_eq!(
+            std::fs::read(dir.dir_path().join("bucket/1/repo/Release")).unwrap(),
+            b"contents"
+        );
+
+        // Contents that don't match their checksum are rejected.
+        assert!(
+            store
+                .put_object(
+                    "bucket",
+                    "1/repo/Release",
+                    Bytes::from_static(b"other"),
+                    &sha256sum
+                )
+                .await
+                .is_err()
+        );
+
+        // Copies use the same percent-encoded sources as S3.
+        store
+            .copy_object(
+                "bucket",
+                "1/repo/pool/a+b.deb",
+                &copy_source("bucket", "1/repo/Release"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .head_object_checksum("bucket", "1/repo/pool/a+b.deb")
+                .await
+                .unwrap(),
+            Some(sha256sum)
+        );
+
+        // Deleting missing objects isn't an error.
+        store
+            .delete_objects(
+                "bucket",
+                &[
+                    String::from("1/repo/Release"),
+                    String::from("1/repo/missing"),
+                ],
+            )
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.head_object_checksum("bucket", "1/repo/Release").await,
+            Err(ObjectStoreError::NotFound)
+        ));
+
+        // Keys can't escape the root directory.
+        assert!(
+            store
+                .head_object_checksum("bucket", "../outside")
+                .await
+                .is_err()
+        );
+    }
+}
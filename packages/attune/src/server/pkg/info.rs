@@ -16,6 +16,14 @@ pub struct PackageInfoResponse {
     pub package: String,
     pub version: String,
     pub architecture: String,
+    /// The source packages that the package was built against, from its
+    /// `Built-Using` field.
+    #[serde(default)]
+    pub built_using: Option<String>,
+    /// The source packages that were statically linked into the package, from
+    /// its `Static-Built-Using` field.
+    #[serde(default)]
+    pub static_built_using: Option<String>,
 }
 
 #[axum::debug_handler]
@@ -30,7 +38,9 @@ pub async fn handler(
         SELECT
             package,
             version,
-            architecture::TEXT AS "architecture!: String"
+            architecture::TEXT AS "architecture!: String",
+            built_using,
+            static_built_using
         FROM debian_repository_package
         WHERE tenant_id = $1 AND sha256sum = $2
         LIMIT 1
@@ -46,6 +56,8 @@ pub async fn handler(
             package: pkg.package,
             version: pkg.version,
             architecture: pkg.architecture,
+            built_using: pkg.built_using,
+            static_built_using: pkg.static_built_using,
         })),
         None => Err(ErrorResponse::new(
             StatusCode::NOT_FOUND,
@@ -54,3 +66,77 @@ pub async fn handler(
         )),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use debian_packaging::{
+        binary_package_control::BinaryPackageControlFile, control::ControlParagraph,
+        debian_source_control::DebianSourceControlFile,
+    };
+    use indoc::indoc;
+
+    use crate::{
+        server::pkg::upload::{HashesHex, insert_package},
+        testing::{AttuneTestServer, AttuneTestServerConfig},
+    };
+
+    use super::*;
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn show_built_using(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        let (tenant_id, api_token) = server.create_test_tenant("show_built_using").await;
+
+        let control_file = {
+            let contents = indoc! {"
+                Package: attune-test-package
+                Version: 1.0.0
+                Architecture: amd64
+                Maintainer: Attune <attune@example.com>
+                Built-Using: rustc (= 1.89.0), gcc-14 (= 14.2.0-19)
+                Static-Built-Using: rust-serde (= 1.0.219-1)
+                Description: A test package
+            "};
+            let dsc = DebianSourceControlFile::from_reader(contents.as_bytes()).unwrap();
+            BinaryPackageControlFile::from(ControlParagraph::from(dsc))
+        };
+        let hashes = HashesHex {
+            sha256sum: String::from("built_using_sha256sum"),
+            sha1sum: String::from("built_using_sha1sum"),
+            md5sum: String::from("built_using_md5sum"),
+        };
+        insert_package(
+            &server.db,
+            tenant_id,
+            "attune-dev-0",
+            &format!("packages/{}", hashes.sha256sum),
+            control_file,
+            &hashes,
+            42,
+        )
+        .await
+        .unwrap();
+
+        let response = server
+            .http
+            .get("/api/v0/packages/built_using_sha256sum")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_ok();
+        let response = response.json::<PackageInfoResponse>();
+        assert_eq!(response.package, "attune-test-package");
+        assert_eq!(
+            response.built_using.as_deref(),
+            Some("rustc (= 1.89.0), gcc-14 (= 14.2.0-19)")
+        );
+        assert_eq!(
+            response.static_built_using.as_deref(),
+            Some("rust-serde (= 1.0.219-1)")
+        );
+    }
+}
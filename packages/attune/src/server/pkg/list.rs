@@ -1,12 +1,12 @@
 use axum::{
-    Json,
+    Extension, Json,
     extract::{Query, State},
 };
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::{
-    api::{ErrorResponse, TenantID},
+    api::{ErrorResponse, PageParams, TenantID, TokenScopes, pagination::next_cursor},
     server::ServerState,
 };
 
@@ -21,7 +21,7 @@ pub struct PackageListParams {
     pub architecture: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Package {
     pub repository: String,
     pub distribution: String,
@@ -37,6 +37,9 @@ pub struct Package {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PackageListResponse {
     pub packages: Vec<Package>,
+    /// The cursor of the next page, if there are more packages.
+    #[serde(default)]
+    pub next_cursor: Option<String>,
 }
 
 #[axum::debug_handler]
@@ -44,11 +47,19 @@ pub struct PackageListResponse {
 pub async fn handler(
     State(state): State<ServerState>,
     tenant_id: TenantID,
+    Extension(scopes): Extension<TokenScopes>,
     params: Query<PackageListParams>,
+    Query(page): Query<PageParams>,
 ) -> Result<Json<PackageListResponse>, ErrorResponse> {
-    let packages = sqlx::query!(
+    // A package appears once per component it's in, so rows are keyed by both
+    // the package and the component.
+    let limit = page.limit()?;
+    let (after_package, after_component) = page.after::<(i64, i64)>()?.unwrap_or_default();
+    let mut packages = sqlx::query!(
         r#"
         SELECT
+            debian_repository_package.id AS package_id,
+            debian_repository_component.id AS component_id,
             debian_repository.name AS repository,
             debian_repository_release.distribution AS distribution,
             debian_repository_component.name AS component,
@@ -72,6 +83,10 @@ pub async fn handler(
             AND (debian_repository_package.package = $5 OR $5 IS NULL)
             AND (debian_repository_package.version = $6 OR $6 IS NULL)
             AND (debian_repository_package.architecture = $7::debian_repository_architecture OR $7 IS NULL)
+            AND (debian_repository_package.id, debian_repository_component.id) > ($8, $9)
+            AND (debian_repository.name = ANY($11) OR $11 IS NULL)
+        ORDER BY debian_repository_package.id ASC, debian_repository_component.id ASC
+        LIMIT $10
         "#,
         tenant_id.0,
         // These explicit typecasts are necessary because otherwise Postgres
@@ -83,21 +98,32 @@ pub async fn handler(
         &params.name as &Option<String>,
         &params.version as &Option<String>,
         &params.architecture as &Option<String>,
+        after_package,
+        after_component,
+        limit + 1,
+        scopes.repositories() as Option<Vec<String>>,
     )
     .fetch_all(&state.db)
     .await
-    .map_err(ErrorResponse::from)?
-    .into_iter()
-    .map(|pkg| Package {
-        repository: pkg.repository,
-        distribution: pkg.distribution,
-        component: pkg.component,
-        name: pkg.name,
-        version: pkg.version,
-        architecture: pkg.architecture,
-        sha256sum: pkg.sha256sum,
-    })
-    .collect::<Vec<_>>();
+    .map_err(ErrorResponse::from)?;
+    let next_cursor = next_cursor(&mut packages, limit, |pkg| {
+        (pkg.package_id, pkg.component_id)
+    });
+    let packages = packages
+        .into_iter()
+        .map(|pkg| Package {
+            repository: pkg.repository,
+            distribution: pkg.distribution,
+            component: pkg.component,
+            name: pkg.name,
+            version: pkg.version,
+            architecture: pkg.architecture,
+            sha256sum: pkg.sha256sum,
+        })
+        .collect::<Vec<_>>();
 
-    Ok(Json(PackageListResponse { packages }))
+    Ok(Json(PackageListResponse {
+        packages,
+        next_cursor,
+    }))
 }
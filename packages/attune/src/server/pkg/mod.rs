@@ -1,3 +1,133 @@
+use debian_packaging::binary_package_control::BinaryPackageControlFile;
+use percent_encoding::utf8_percent_encode;
+
+use crate::api::{PATH_SEGMENT_PERCENT_ENCODE_SET, TenantID};
+
 pub mod info;
 pub mod list;
+pub mod reference;
+pub mod search;
 pub mod upload;
+
+/// The largest package that can be uploaded or registered by reference.
+///
+/// Packages are read into memory to be parsed and hashed, so this bounds the
+/// memory that a single request can use.
+pub const MAX_PACKAGE_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// How the canonical copy of each package is keyed in the server's S3 bucket.
+///
+/// Packages are copied from their canonical location into the pools of the
+/// repositories that they're added to. Each package records the key it was
+/// stored at, so changing the scheme only affects packages uploaded afterwards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PackageKeyScheme {
+    /// Store packages at `packages/<sha256sum>`, so that identical uploads
+    /// share one object.
+    #[default]
+    ContentAddressed,
+    /// Store packages at
+    /// `pool/<tenant>/<name>/<sha256sum>/<name>_<version>_<arch>.deb`, which
+    /// is easier to browse with external tools. The SHA256 sum keeps
+    /// different builds of the same package version apart.
+    Pool,
+}
+
+impl PackageKeyScheme {
+    pub fn key(
+        self,
+        tenant_id: TenantID,
+        control_file: &BinaryPackageControlFile<'_>,
+        sha256sum: &str,
+    ) -> String {
+        match self {
+            Self::ContentAddressed => content_addressed_key(sha256sum),
+            Self::Pool => {
+                let name = control_file.package().unwrap();
+                let version = control_file.version().unwrap();
+                let architecture = control_file.architecture().unwrap();
+                format!(
+                    "pool/{}/{name}/{sha256sum}/{name}_{version}_{architecture}.deb",
+                    tenant_id.0
+                )
+            }
+        }
+    }
+}
+
+/// The key of a package's canonical object, given the key that was stored with
+/// it.
+///
+/// Packages that were uploaded before the key scheme was configurable don't
+/// have a stored key, and are always content-addressed.
+pub fn canonical_key(s3_key: Option<&str>, sha256sum: &str) -> String {
+    s3_key.map_or_else(|| content_addressed_key(sha256sum), String::from)
+}
+
+fn content_addressed_key(sha256sum: &str) -> String {
+    format!("packages/{sha256sum}")
+}
+
+/// The prefix under which a tenant may stage packages in the server's S3
+/// bucket, so that they can be registered by reference.
+///
+/// Only objects under this prefix can be registered, so that tenants can't
+/// register objects that belong to other tenants, like their packages or
+/// repository pools.
+pub fn staging_prefix(tenant_id: TenantID) -> String {
+    format!("staging/{}/", tenant_id.0)
+}
+
+/// The S3 copy source of an object, with each segment of its key
+/// percent-encoded.
+pub fn copy_source(bucket: &str, key: &str) -> String {
+    let key = key
+        .split('/')
+        .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT_PERCENT_ENCODE_SET).to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{bucket}/{key}")
+}
+
+#[cfg(test)]
+mod tests {
+    use debian_packaging::{
+        control::ControlParagraph, debian_source_control::DebianSourceControlFile,
+    };
+    use indoc::indoc;
+
+    use super::*;
+
+    #[test]
+    fn package_keys() {
+        let control_file = {
+            let contents = indoc! {"
+                Package: libstdc++6
+                Version: 14.2.0-19
+                Architecture: amd64
+                Maintainer: Attune <attune@example.com>
+                Description: A test package
+            "};
+            let dsc = DebianSourceControlFile::from_reader(contents.as_bytes()).unwrap();
+            BinaryPackageControlFile::from(ControlParagraph::from(dsc))
+        };
+
+        assert_eq!(
+            PackageKeyScheme::ContentAddressed.key(TenantID(1), &control_file, "abc123"),
+            "packages/abc123"
+        );
+        let key = PackageKeyScheme::Pool.key(TenantID(1), &control_file, "abc123");
+        assert_eq!(
+            key,
+            "pool/1/libstdc++6/abc123/libstdc++6_14.2.0-19_amd64.deb"
+        );
+        assert_eq!(
+            copy_source("attune-dev-0", &key),
+            "attune-dev-0/pool/1/libstdc++6/abc123/libstdc++6_14.2.0-19_amd64.deb"
+        );
+
+        // Packages without a stored key are content-addressed.
+        assert_eq!(canonical_key(None, "abc123"), "packages/abc123");
+        assert_eq!(canonical_key(Some(&key), "abc123"), key);
+    }
+}
@@ -0,0 +1,242 @@
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        pkg::{
+            MAX_PACKAGE_SIZE, copy_source, staging_prefix,
+            upload::{
+                Hashes, PackageUploadResponse, check_package_exists, insert_package,
+                insert_package_docs, parse_debian_package,
+            },
+        },
+    },
+};
+
+/// Register a package that is already stored in the server's S3 bucket.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageReferenceRequest {
+    /// The key of the `.deb` object in the server's S3 bucket. It must be
+    /// under the tenant's staging prefix, `staging/<tenant ID>/`.
+    pub key: String,
+    /// Accept the package even if the tenant already has a different package
+    /// with the same name, version, and architecture.
+    #[serde(default)]
+    pub replace: bool,
+}
+
+/// Register a package from an object that already exists in the server's S3
+/// bucket, rather than uploading its contents.
+///
+/// This is useful when packages are built by another job that writes directly
+/// to the bucket: the package is read from S3 to parse its control file and
+/// compute its hashes, and is then copied server-side into its canonical
+/// location, so its contents never pass through the client.
+///
+/// Only objects under the tenant's staging prefix can be registered. The
+/// referenced object is left in place.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Json(req): Json<PackageReferenceRequest>,
+) -> Result<Json<PackageUploadResponse>, ErrorResponse> {
+    let key = validate_key(tenant_id, &req.key)?;
+
+    // Read the referenced object. Its ETag is recorded so that the copy below
+    // fails if the object is replaced after it has been parsed.
+    let object = state
+        .s3
+        .get_object()
+        .bucket(&state.s3_bucket_name)
+        .key(key)
+        .send()
+        .await
+        .map_err(|err| {
+            let err = err.into_service_error();
+            if err.is_no_such_key() {
+                ErrorResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "OBJECT_NOT_FOUND",
+                    format!("object {key:?} not found"),
+                )
+            } else {
+                ErrorResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "S3_ERROR",
+                    format!("could not read object {key:?}: {err}"),
+                )
+            }
+        })?;
+    let e_tag = object.e_tag.clone();
+    match object.content_length {
+        Some(size) if (0..=MAX_PACKAGE_SIZE as i64).contains(&size) => {}
+        Some(size) => {
+            return Err(ErrorResponse::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "PACKAGE_TOO_LARGE",
+                format!(
+                    "object {key:?} is {size} bytes, but packages can be at most {MAX_PACKAGE_SIZE} bytes"
+                ),
+            ));
+        }
+        None => {
+            return Err(ErrorResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "S3_ERROR",
+                format!("could not read object {key:?}: size is unknown"),
+            ));
+        }
+    }
+    let value = object
+        .body
+        .collect()
+        .await
+        .map_err(|err| {
+            ErrorResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "S3_ERROR",
+                format!("could not read object {key:?}: {err}"),
+            )
+        })?
+        .into_bytes();
+
+    // Parse the package exactly as if it had been uploaded.
+    let (control_file, docs) = parse_debian_package(&value).await?;
+    let hex_hashes = Hashes::from_bytes(&value).hex();
+    let size = value.len() as i64;
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    if let Some(shortcircuit) =
+        check_package_exists(&mut *tx, tenant_id, &control_file, &hex_hashes, req.replace).await?
+    {
+        return Ok(shortcircuit);
+    }
+    let s3_key = state
+        .package_key_scheme
+        .key(tenant_id, &control_file, &hex_hashes.sha256sum);
+    let package_id = insert_package(
+        &mut *tx,
+        tenant_id,
+        &state.s3_bucket_name,
+        &s3_key,
+        control_file,
+        &hex_hashes,
+        size,
+    )
+    .await
+    .map_err(ErrorResponse::from)?;
+    insert_package_docs(&mut *tx, package_id, &docs)
+        .await
+        .map_err(ErrorResponse::from)?;
+
+    // Copy the package into its canonical location. As with uploads, this
+    // must complete before the transaction commits.
+    state
+        .s3
+        .copy_object()
+        .bucket(&state.s3_bucket_name)
+        .key(&s3_key)
+        .copy_source(copy_source(&state.s3_bucket_name, key))
+        .set_copy_source_if_match(e_tag)
+        .send()
+        .await
+        .map_err(|err| {
+            let precondition_failed = err
+                .raw_response()
+                .is_some_and(|res| res.status().as_u16() == StatusCode::PRECONDITION_FAILED);
+            if precondition_failed {
+                ErrorResponse::new(
+                    StatusCode::CONFLICT,
+                    "OBJECT_CHANGED",
+                    format!("object {key:?} changed while it was being registered"),
+                )
+            } else {
+                ErrorResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "S3_ERROR",
+                    format!("could not copy object {key:?}: {err}"),
+                )
+            }
+        })?;
+
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    Ok(Json(PackageUploadResponse {
+        sha256sum: hex_hashes.sha256sum,
+    }))
+}
+
+/// Check that the key names an object under the tenant's staging prefix,
+/// returning it without any leading slashes.
+fn validate_key(tenant_id: TenantID, key: &str) -> Result<&str, ErrorResponse> {
+    let key = key.trim_start_matches('/');
+    let prefix = staging_prefix(tenant_id);
+    let Some(name) = key.strip_prefix(&prefix) else {
+        return Err(ErrorResponse::new(
+            StatusCode::FORBIDDEN,
+            "OBJECT_NOT_STAGED",
+            format!("object {key:?} is not under the tenant's staging prefix {prefix:?}"),
+        ));
+    };
+    // Some S3-compatible stores normalize `..` segments, which could escape
+    // the staging prefix.
+    if name.is_empty() || name.split('/').any(|segment| segment == "..") {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_OBJECT_KEY",
+            format!("object key {key:?} does not name a staged object"),
+        ));
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staged_keys_only() {
+        let tenant_id = TenantID(7);
+        assert_eq!(
+            validate_key(tenant_id, "staging/7/build/pkg.deb").unwrap(),
+            "staging/7/build/pkg.deb"
+        );
+        assert_eq!(
+            validate_key(tenant_id, "/staging/7/pkg.deb").unwrap(),
+            "staging/7/pkg.deb"
+        );
+        for (key, status) in [
+            (
+                format!("packages/{}", "0".repeat(64)),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                String::from("pool/8/pkg/abc/pkg_1.0_amd64.deb"),
+                StatusCode::FORBIDDEN,
+            ),
+            (String::from("staging/8/pkg.deb"), StatusCode::FORBIDDEN),
+            (String::from("staging/70/pkg.deb"), StatusCode::FORBIDDEN),
+            (String::from("staging/7"), StatusCode::FORBIDDEN),
+            (String::from("staging/7/"), StatusCode::BAD_REQUEST),
+            (
+                String::from("staging/7/../8/pkg.deb"),
+                StatusCode::BAD_REQUEST,
+            ),
+        ] {
+            assert_eq!(
+                validate_key(tenant_id, &key).unwrap_err().status,
+                status,
+                "{key}"
+            );
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID, TokenScopes},
+    server::{ServerState, pkg::list::Package},
+};
+
+/// The default number of results returned per page.
+pub const DEFAULT_SEARCH_LIMIT: i64 = 50;
+/// The maximum number of results returned per page.
+pub const MAX_SEARCH_LIMIT: i64 = 500;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageSearchParams {
+    /// Matches packages whose name contains this string. Packages whose name
+    /// starts with it are listed first.
+    pub name: String,
+    /// Only match packages whose name is exactly `name`.
+    #[serde(default)]
+    pub exact: bool,
+    pub version: Option<String>,
+    pub architecture: Option<String>,
+    pub distribution: Option<String>,
+
+    /// The maximum number of results to return, up to [`MAX_SEARCH_LIMIT`].
+    pub limit: Option<i64>,
+    /// The number of results to skip, for fetching later pages.
+    pub offset: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackageSearchResponse {
+    /// Each publication of a matching package, i.e. a package that is
+    /// published in several distributions is listed once for each.
+    pub packages: Vec<Package>,
+    /// The offset of the next page of results, if there are more results.
+    pub next_offset: Option<i64>,
+}
+
+/// Search for packages by name across all of the tenant's repositories, or
+/// only those that the API token is restricted to.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Extension(scopes): Extension<TokenScopes>,
+    Query(params): Query<PackageSearchParams>,
+) -> Result<Json<PackageSearchResponse>, ErrorResponse> {
+    if params.name.is_empty() {
+        return Err(ErrorResponse::builder()
+            .status(axum::http::StatusCode::BAD_REQUEST)
+            .error("INVALID_SEARCH")
+            .message("search name must not be empty")
+            .build());
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    // Escape the name so that LIKE wildcards in it are matched literally.
+    let pattern = params
+        .name
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    // Exact matches compare with equality so that they use the package name
+    // index.
+    let exact_name = params.exact.then_some(&params.name);
+
+    // Fetch one extra result to find out whether there is another page.
+    let mut packages = sqlx::query!(
+        r#"
+        SELECT
+            debian_repository.name AS repository,
+            debian_repository_release.distribution AS distribution,
+            debian_repository_component.name AS component,
+
+            debian_repository_package.package AS name,
+            debian_repository_package.version,
+            debian_repository_package.architecture::TEXT AS "architecture!: String",
+
+            debian_repository_package.sha256sum
+        FROM
+            debian_repository_package
+            JOIN debian_repository_component_package ON debian_repository_package.id = debian_repository_component_package.package_id
+            JOIN debian_repository_component ON debian_repository_component_package.component_id = debian_repository_component.id
+            JOIN debian_repository_release ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository ON debian_repository_release.repository_id = debian_repository.id
+        WHERE
+            debian_repository_package.tenant_id = $1
+            AND debian_repository_package.package LIKE '%' || $2 || '%'
+            AND (debian_repository_package.package = $8 OR $8 IS NULL)
+            AND (debian_repository_package.version = $3 OR $3 IS NULL)
+            AND (debian_repository_package.architecture = $4::debian_repository_architecture OR $4 IS NULL)
+            AND (debian_repository_release.distribution = $7 OR $7 IS NULL)
+            AND (debian_repository.name = ANY($9) OR $9 IS NULL)
+        ORDER BY
+            debian_repository_package.package NOT LIKE $2 || '%',
+            debian_repository_package.package,
+            debian_repository_package.version,
+            debian_repository_package.architecture,
+            debian_repository.name,
+            debian_repository_release.distribution,
+            debian_repository_component.name
+        LIMIT $5
+        OFFSET $6
+        "#,
+        tenant_id.0,
+        pattern,
+        &params.version as &Option<String>,
+        &params.architecture as &Option<String>,
+        limit + 1,
+        offset,
+        &params.distribution as &Option<String>,
+        exact_name as Option<&String>,
+        scopes.repositories() as Option<Vec<String>>,
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(ErrorResponse::from)?
+    .into_iter()
+    .map(|pkg| Package {
+        repository: pkg.repository,
+        distribution: pkg.distribution,
+        component: pkg.component,
+        name: pkg.name,
+        version: pkg.version,
+        architecture: pkg.architecture,
+        sha256sum: pkg.sha256sum,
+    })
+    .collect::<Vec<_>>();
+
+    let next_offset = if packages.len() as i64 > limit {
+        packages.truncate(limit as usize);
+        Some(offset + limit)
+    } else {
+        None
+    };
+
+    Ok(Json(PackageSearchResponse {
+        packages,
+        next_offset,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AttuneTestServer, AttuneTestServerConfig};
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "../repo/index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn search_by_name(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        let search = async |query: &str| {
+            let response = server
+                .http
+                .get(&format!("/api/v0/packages/search?{query}"))
+                .add_header("authorization", "Bearer test-api-token")
+                .await;
+            response.assert_status_ok();
+            response.json::<PackageSearchResponse>()
+        };
+
+        // Substring matches find the package in every architecture.
+        let response = search("name=package").await;
+        assert_eq!(
+            response
+                .packages
+                .iter()
+                .map(|pkg| (pkg.name.as_str(), pkg.architecture.as_str()))
+                .collect::<Vec<_>>(),
+            [("test-package", "amd64"), ("test-package", "arm64")]
+        );
+        assert_eq!(response.packages[0].repository, "test-multi-arch");
+        assert_eq!(response.next_offset, None);
+
+        let response = search("name=test&architecture=arm64").await;
+        assert_eq!(response.packages.len(), 1);
+        assert_eq!(response.packages[0].architecture, "arm64");
+
+        // Results are paginated.
+        let response = search("name=test&limit=1").await;
+        assert_eq!(response.packages.len(), 1);
+        assert_eq!(response.packages[0].architecture, "amd64");
+        assert_eq!(response.next_offset, Some(1));
+        let response = search("name=test&limit=1&offset=1").await;
+        assert_eq!(response.packages.len(), 1);
+        assert_eq!(response.packages[0].architecture, "arm64");
+        assert_eq!(response.next_offset, None);
+
+        // Exact matches don't match substrings.
+        assert!(search("name=package&exact=true").await.packages.is_empty());
+        assert_eq!(
+            search("name=test-package&exact=true").await.packages.len(),
+            2
+        );
+
+        let response = search("name=test&distribution=stable").await;
+        assert_eq!(response.packages.len(), 2);
+        assert!(
+            search("name=test&distribution=missing")
+                .await
+                .packages
+                .is_empty()
+        );
+
+        // Wildcards are matched literally.
+        assert!(search("name=%25").await.packages.is_empty());
+        assert!(search("name=test_package").await.packages.is_empty());
+    }
+}
@@ -1,28 +1,39 @@
-use aws_sdk_s3::types::ChecksumAlgorithm;
 use axum::{
     Json,
-    extract::{Multipart, State},
+    extract::{Multipart, Query, State, multipart::MultipartError},
     http::StatusCode,
 };
-use base64::Engine;
+
 use bytes::Bytes;
 use debian_packaging::{
     binary_package_control::BinaryPackageControlFile,
-    deb::reader::{BinaryPackageEntry, BinaryPackageReader, ControlTarFile},
+    deb::reader::{BinaryPackageEntry, BinaryPackageReader, ControlTarFile, DataTarReader},
 };
 use digest::Digest as _;
+use flate2::read::GzDecoder;
+use futures_util::{AsyncReadExt as _, FutureExt as _, StreamExt as _};
 use md5::Md5;
 use serde::{Deserialize, Serialize};
 use sha1::Sha1;
 use sha2::Sha256;
 use sqlx::{Executor, Postgres, types::JsonValue};
-use tracing::instrument;
+use std::io::Read as _;
+use tracing::{debug, instrument};
 
 use crate::{
     api::{ErrorResponse, TenantID},
     server::ServerState,
 };
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PackageUploadParams {
+    /// Accept the package even if the tenant already has a different package
+    /// with the same name, version, and architecture, so that it can replace
+    /// that package in a component.
+    #[serde(default)]
+    pub replace: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PackageUploadResponse {
     pub sha256sum: String,
@@ -33,6 +44,7 @@ pub struct PackageUploadResponse {
 pub async fn handler(
     State(state): State<ServerState>,
     tenant_id: TenantID,
+    Query(params): Query<PackageUploadParams>,
     mut multipart: Multipart,
 ) -> Result<Json<PackageUploadResponse>, ErrorResponse> {
     // TODO: We currently hold the entire package in memory. This works for now,
@@ -40,39 +52,44 @@ pub async fn handler(
     // (from the request into S3 object storage, while parsing needed values
     // along the way).
 
-    // Parse the uploaded package.
-    let field = multipart
-        .next_field()
-        .await
-        .unwrap()
-        .expect("expected a file");
-    let name = field.name().unwrap().to_string();
-    if name != "file" {
+    // Find the uploaded package. Multipart libraries don't all keep the order
+    // of fields, so the `file` field can be anywhere, and other fields (e.g.
+    // metadata that some clients add) are skipped.
+    let mut value = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        let name = field.name().unwrap_or_default().to_string();
+        if name != "file" {
+            debug!(?name, "skipping unknown upload field");
+            continue;
+        }
+        if value.is_some() {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "COULD_NOT_PARSE_UPLOAD",
+                "expected one field named \"file\", got several",
+            ));
+        }
+        value = Some(field.bytes().await.map_err(multipart_error)?);
+    }
+    let Some(value) = value else {
         return Err(ErrorResponse::new(
             StatusCode::BAD_REQUEST,
-            "COULD_NOT_PARSE_UPLOAD".to_string(),
-            format!("expected field named \"file\", got {name:?}"),
+            "MISSING_FILE_FIELD",
+            "expected the package in a field named \"file\"",
         ));
-    }
+    };
 
     // Parse Debian package for control fields.
-    let value = field.bytes().await.unwrap();
-    let control_file = parse_debian_package(&value).await;
+    let (control_file, docs) = parse_debian_package(&value).await?;
     let hashes = Hashes::from_bytes(&value);
     let hex_hashes = hashes.hex();
     let size = value.len() as i64;
-
-    // Check that there are no more fields.
-    let None = multipart.next_field().await.unwrap() else {
-        return Err(ErrorResponse::new(
-            StatusCode::BAD_REQUEST,
-            "COULD_NOT_PARSE_UPLOAD".to_string(),
-            format!("unexpected unknown field {name:?}"),
-        ));
-    };
+    if let Some(metrics) = &state.metrics {
+        metrics.record_package_upload(value.len() as u64);
+    }
 
     // Begin database transaction.
-    let mut tx = state.db.begin().await.unwrap();
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
     sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
         .execute(&mut *tx)
         .await
@@ -83,39 +100,51 @@ pub async fn handler(
     //
     // If such a package exists AND the sha256sum is the same, we can skip the
     // rest of the handler. If such a package exists AND the sha256sum is NOT
-    // the same, then an error has occurred.
-    if let Some(shortcircuit) =
-        check_package_exists(&mut *tx, tenant_id, &control_file, &hex_hashes).await?
+    // the same, then an error has occurred, unless the upload is a replacement.
+    if let Some(shortcircuit) = check_package_exists(
+        &mut *tx,
+        tenant_id,
+        &control_file,
+        &hex_hashes,
+        params.replace,
+    )
+    .await?
     {
         return Ok(shortcircuit);
     }
 
     // Insert the package row into the database. At this point, integrity checks
     // may cause the upload to fail (e.g. if this package already exists).
-    insert_package(
+    let s3_key = state
+        .package_key_scheme
+        .key(tenant_id, &control_file, &hex_hashes.sha256sum);
+    let package_id = insert_package(
         &mut *tx,
         tenant_id,
         &state.s3_bucket_name,
+        &s3_key,
         control_file,
         &hex_hashes,
         size,
     )
     .await
     .map_err(ErrorResponse::from)?;
+    insert_package_docs(&mut *tx, package_id, &docs)
+        .await
+        .map_err(ErrorResponse::from)?;
 
     // Upload the package to S3.
     state
-        .s3
-        .put_object()
-        .bucket(&state.s3_bucket_name)
-        .key(format!("packages/{}", hex_hashes.sha256sum))
-        .body(value.into())
-        .content_md5(base64::engine::general_purpose::STANDARD.encode(&hashes.md5sum))
-        .checksum_algorithm(ChecksumAlgorithm::Sha256)
-        .checksum_sha256(base64::engine::general_purpose::STANDARD.encode(&hashes.sha256sum))
-        .send()
+        .object_store
+        .put_object(&state.s3_bucket_name, &s3_key, value, &hex_hashes.sha256sum)
         .await
-        .unwrap();
+        .map_err(|err| {
+            ErrorResponse::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "S3_ERROR",
+                format!("could not upload package: {err}"),
+            )
+        })?;
 
     // Commit the transaction. This must occur after the package is uploaded to
     // S3 so that a handler crash does not leave us in a state where the row
@@ -134,46 +163,172 @@ pub async fn handler(
     }))
 }
 
+/// Report a malformed multipart upload, e.g. one that was cut off.
+fn multipart_error(err: MultipartError) -> ErrorResponse {
+    ErrorResponse::new(err.status(), "COULD_NOT_PARSE_UPLOAD", err.body_text())
+}
+
+/// Parse the control file, changelog, and copyright file out of a Debian
+/// binary package.
+///
+/// Returns an `INVALID_DEB_PACKAGE` error if the bytes are not a well-formed
+/// `.deb`, or if the control file is missing fields that are needed to index
+/// the package, and a `MISSING_CONTROL_FILE` error if the package has no
+/// control file.
 #[instrument(skip(value))]
-async fn parse_debian_package(value: &Bytes) -> BinaryPackageControlFile<'static> {
-    let mut reader = BinaryPackageReader::new(value.as_ref()).unwrap();
-    let header_entry = reader.next_entry().unwrap().unwrap();
-    let BinaryPackageEntry::DebianBinary(_) = header_entry else {
-        panic!("expected a Debian binary package")
+pub(super) async fn parse_debian_package(
+    value: &Bytes,
+) -> Result<(BinaryPackageControlFile<'static>, PackageDocs), ErrorResponse> {
+    let invalid = |message: String| {
+        ErrorResponse::new(StatusCode::BAD_REQUEST, "INVALID_DEB_PACKAGE", message)
     };
-    let control_entry = reader.next_entry().unwrap().unwrap();
-    let BinaryPackageEntry::Control(mut control_reader) = control_entry else {
-        panic!("expected a control file")
+    let missing_control_file = |message: &str| {
+        ErrorResponse::new(StatusCode::BAD_REQUEST, "MISSING_CONTROL_FILE", message)
     };
-    let mut control_entries = control_reader.entries().unwrap();
+
+    let mut reader = BinaryPackageReader::new(value.as_ref())
+        .map_err(|err| invalid(format!("could not read package: {err}")))?;
+    let mut next_entry = || {
+        reader
+            .next_entry()
+            .transpose()
+            .map_err(|err| invalid(format!("could not read package: {err}")))?
+            .ok_or_else(|| invalid(String::from("package ended unexpectedly")))
+    };
+    let BinaryPackageEntry::DebianBinary(_) = next_entry()? else {
+        return Err(invalid(String::from("expected a Debian binary package")));
+    };
+    let BinaryPackageEntry::Control(mut control_reader) = next_entry()? else {
+        return Err(missing_control_file("expected a control archive"));
+    };
+    let mut control_entries = control_reader
+        .entries()
+        .map_err(|err| invalid(format!("could not read control archive: {err}")))?;
     let control_file = loop {
-        let (_, control_tar_file) = control_entries
+        let mut entry = control_entries
             .next()
-            .unwrap()
-            .unwrap()
+            .ok_or_else(|| missing_control_file("control archive has no control file"))?
+            .map_err(|err| invalid(format!("could not read control archive: {err}")))?;
+        let (_, control_tar_file) = entry
             .to_control_file()
-            .unwrap();
+            .map_err(|err| invalid(format!("could not read control archive: {err}")))?;
         if let ControlTarFile::Control(control_file) = control_tar_file {
             break control_file;
         }
     };
     // TODO(#95): Parse file paths for building Contents index.
-    let data_entry = reader.next_entry().unwrap().unwrap();
-    let BinaryPackageEntry::Data(_) = data_entry else {
-        panic!("expected a data file")
+    let BinaryPackageEntry::Data(data) = next_entry()? else {
+        return Err(invalid(String::from("expected a data file")));
     };
+
+    // These are all unwrapped when the package is inserted.
+    let package = control_file
+        .package()
+        .map_err(|err| invalid(format!("invalid Package field: {err}")))?;
+    control_file
+        .version()
+        .map_err(|err| invalid(format!("invalid Version field: {err}")))?;
     control_file
+        .architecture()
+        .map_err(|err| invalid(format!("invalid Architecture field: {err}")))?;
+    control_file
+        .maintainer()
+        .map_err(|err| invalid(format!("invalid Maintainer field: {err}")))?;
+    control_file
+        .description()
+        .map_err(|err| invalid(format!("invalid Description field: {err}")))?;
+    if let Some(Err(err)) = control_file.installed_size() {
+        return Err(invalid(format!("invalid Installed-Size field: {err}")));
+    }
+    for field in ["Depends", "Recommends", "Conflicts", "Provides", "Replaces"] {
+        if let Some(Err(err)) = control_file.field_dependency_list(field) {
+            return Err(invalid(format!("invalid {field} field: {err}")));
+        }
+    }
+
+    let docs = read_package_docs(data, package)
+        .map_err(|err| invalid(format!("could not read data archive: {err}")))?;
+    Ok((control_file, docs))
+}
+
+/// The largest changelog or copyright file that is stored, uncompressed.
+const MAX_DOC_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Documentation extracted from a package's data archive.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct PackageDocs {
+    /// The uncompressed Debian changelog.
+    pub(super) changelog: Option<Vec<u8>>,
+    pub(super) copyright: Option<Vec<u8>>,
+}
+
+/// Find the package's changelog and copyright file in its data archive.
+///
+/// Files that are missing, too large, or can't be decompressed are skipped,
+/// since they aren't needed to publish the package.
+fn read_package_docs(data: DataTarReader, package: &str) -> std::io::Result<PackageDocs> {
+    let doc_dir = format!("usr/share/doc/{package}/");
+    let read = async move {
+        let mut docs = PackageDocs::default();
+        let mut entries = data.into_inner().entries()?;
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let Some(name) = path.trim_start_matches("./").strip_prefix(&doc_dir) else {
+                continue;
+            };
+            let (doc, gzipped) = match name {
+                "changelog.Debian.gz" => (&mut docs.changelog, true),
+                // Native packages only have a `changelog.gz`, while other
+                // packages may have both it (the upstream changelog) and a
+                // `changelog.Debian.gz`, which is preferred.
+                "changelog.gz" if docs.changelog.is_none() => (&mut docs.changelog, true),
+                "copyright" => (&mut docs.copyright, false),
+                _ => continue,
+            };
+            if entry.header().size()? > MAX_DOC_SIZE {
+                debug!(?path, "skipping oversized doc file");
+                continue;
+            }
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).await?;
+            if gzipped {
+                let mut decompressed = Vec::new();
+                let decoded = GzDecoder::new(contents.as_slice())
+                    .take(MAX_DOC_SIZE + 1)
+                    .read_to_end(&mut decompressed);
+                match decoded {
+                    Ok(size) if size as u64 <= MAX_DOC_SIZE => contents = decompressed,
+                    result => {
+                        debug!(?path, ?result, "skipping undecompressable doc file");
+                        continue;
+                    }
+                }
+            }
+            *doc = Some(contents);
+        }
+        Ok(docs)
+    };
+    // The data archive is read through an async reader, but the package is
+    // already in memory, so reading it never waits and the future completes
+    // on its first poll. Polling it here, rather than awaiting it, keeps the
+    // (non-`Send`) reader out of the handler's future.
+    read.now_or_never()
+        .unwrap_or_else(|| Err(std::io::Error::other("data archive read did not complete")))
 }
 
 #[derive(Debug)]
-struct Hashes {
+pub(super) struct Hashes {
     sha256sum: Vec<u8>,
     sha1sum: Vec<u8>,
     md5sum: Vec<u8>,
 }
 
 impl Hashes {
-    fn from_bytes(bytes: &Bytes) -> Self {
+    pub(super) fn from_bytes(bytes: &Bytes) -> Self {
         // TODO: Can we make this faster? Parallelism? Streaming? Asynchrony?
         let sha256sum = Sha256::digest(bytes).to_vec();
         let sha1sum = Sha1::digest(bytes).to_vec();
@@ -185,7 +340,7 @@ impl Hashes {
         }
     }
 
-    fn hex(&self) -> HashesHex {
+    pub(super) fn hex(&self) -> HashesHex {
         HashesHex {
             sha256sum: hex::encode(&self.sha256sum),
             sha1sum: hex::encode(&self.sha1sum),
@@ -195,62 +350,72 @@ impl Hashes {
 }
 
 #[derive(Debug)]
-struct HashesHex {
-    sha256sum: String,
-    sha1sum: String,
-    md5sum: String,
+pub(super) struct HashesHex {
+    pub(super) sha256sum: String,
+    pub(super) sha1sum: String,
+    pub(super) md5sum: String,
 }
 
+/// Check whether the tenant already has a package with the same (name,
+/// version, architecture), returning it if it has the same contents.
+///
+/// A package with different contents is a `PACKAGE_VERSION_CONFLICT`, unless
+/// `replace` is set, in which case the new package can be inserted alongside
+/// it.
 #[instrument(skip(executor, control_file))]
-async fn check_package_exists<'c, E>(
+pub(super) async fn check_package_exists<'c, E>(
     executor: E,
     tenant_id: TenantID,
     control_file: &BinaryPackageControlFile<'static>,
     hashes: &HashesHex,
+    replace: bool,
 ) -> Result<Option<Json<PackageUploadResponse>>, ErrorResponse>
 where
     E: Executor<'c, Database = Postgres>,
 {
-    let existing = sqlx::query!(
+    let existing = sqlx::query_scalar!(
         r#"
-        SELECT id, sha256sum
+        SELECT sha256sum
         FROM debian_repository_package
         WHERE
             tenant_id = $1
             AND package = $2
             AND version = $3
             AND architecture = $4::debian_repository_architecture
-        LIMIT 1
         "#,
         tenant_id.0,
         control_file.package().unwrap(),
         control_file.version().unwrap().to_string(),
         control_file.architecture().unwrap() as _,
     )
-    .fetch_optional(executor)
+    .fetch_all(executor)
     .await
     .map_err(ErrorResponse::from)?;
-    if let Some(existing) = existing {
-        if existing.sha256sum == hashes.sha256sum {
-            return Ok(Some(Json(PackageUploadResponse {
-                sha256sum: existing.sha256sum,
-            })));
-        } else {
-            return Err(ErrorResponse::new(
-                StatusCode::BAD_REQUEST,
-                "PACKAGE_ALREADY_EXISTS",
-                "package already exists",
-            ));
-        }
+    if existing.contains(&hashes.sha256sum) {
+        return Ok(Some(Json(PackageUploadResponse {
+            sha256sum: hashes.sha256sum.clone(),
+        })));
+    }
+    if let Some(existing) = existing.first()
+        && !replace
+    {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            "PACKAGE_VERSION_CONFLICT",
+            format!(
+                "a different package with the same name, version, and architecture already exists (SHA256 {existing})"
+            ),
+        ));
     }
     Ok(None)
 }
 
 #[instrument(skip(executor, control_file))]
-async fn insert_package<'c, E>(
+pub(super) async fn insert_package<'c, E>(
     executor: E,
     tenant_id: TenantID,
     s3_bucket_name: &str,
+    s3_key: &str,
     control_file: BinaryPackageControlFile<'static>,
     hashes: &HashesHex,
     size: i64,
@@ -279,6 +444,7 @@ where
         INSERT INTO debian_repository_package (
             tenant_id,
             s3_bucket,
+            s3_key,
 
             package,
             version,
@@ -299,6 +465,9 @@ where
             provides,
             replaces,
 
+            built_using,
+            static_built_using,
+
             size,
             md5sum,
             sha1sum,
@@ -310,30 +479,34 @@ where
         VALUES (
             $1,
             $2,
-
             $3,
+
             $4,
-            $5::debian_repository_architecture,
+            $5,
+            $6::debian_repository_architecture,
 
-            $6,
             $7,
             $8,
             $9,
             $10,
             $11,
-
             $12,
 
             $13,
+
             $14,
             $15,
             $16,
             $17,
-
             $18,
+
             $19,
             $20,
+
             $21,
+            $22,
+            $23,
+            $24,
 
             NOW(),
             NOW()
@@ -342,6 +515,7 @@ where
         "#,
         tenant_id.0,
         s3_bucket_name,
+        s3_key,
         package_name,
         &version,
         architecture as _,
@@ -363,6 +537,8 @@ where
         control_file
             .field_dependency_list("replaces")
             .map(|d| d.unwrap().to_string()),
+        control_file.field_str("Built-Using"),
+        control_file.field_str("Static-Built-Using"),
         size,
         md5sum,
         sha1sum,
@@ -373,12 +549,45 @@ where
     Ok(inserted.id)
 }
 
+/// Store the package's changelog and copyright file, if it has them.
+#[instrument(skip(executor, docs))]
+pub(super) async fn insert_package_docs<'c, E>(
+    executor: E,
+    package_id: i64,
+    docs: &PackageDocs,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    if docs == &PackageDocs::default() {
+        return Ok(());
+    }
+    sqlx::query!(
+        r#"
+        UPDATE debian_repository_package
+        SET changelog = $2, copyright = $3
+        WHERE id = $1
+        "#,
+        package_id,
+        docs.changelog.as_deref(),
+        docs.copyright.as_deref(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use axum_test::multipart::{MultipartForm, Part};
+    use std::io::Write as _;
+
     use debian_packaging::{
-        control::ControlParagraph, debian_source_control::DebianSourceControlFile,
+        control::{ControlFile, ControlParagraph},
+        deb::builder::DebBuilder,
+        debian_source_control::DebianSourceControlFile,
     };
+    use flate2::{Compression, write::GzEncoder};
     use indoc::indoc;
     use tracing::debug;
 
@@ -387,8 +596,7 @@ mod tests {
     use super::*;
 
     /// Inserting a package with the same headers but different content should
-    /// fail in a way that does not cause the client to retry. This means it
-    /// must not fail with a 409.
+    /// fail with a version conflict, which the client doesn't retry.
     #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
     #[test_log::test]
     async fn cannot_insert_same_headers_different_content(pool: sqlx::PgPool) {
@@ -425,7 +633,7 @@ mod tests {
             .execute(&mut *tx)
             .await
             .unwrap();
-        let existing = check_package_exists(&mut *tx, tenant_id, &control_file, &hashes_a)
+        let existing = check_package_exists(&mut *tx, tenant_id, &control_file, &hashes_a, false)
             .await
             .unwrap();
         assert!(existing.is_none());
@@ -433,6 +641,7 @@ mod tests {
             &mut *tx,
             tenant_id,
             "attune-dev-0",
+            &format!("packages/{}", hashes_a.sha256sum),
             control_file.clone(),
             &hashes_a,
             42,
@@ -453,10 +662,299 @@ mod tests {
             .execute(&mut *tx)
             .await
             .unwrap();
-        let existing = check_package_exists(&mut *tx, tenant_id, &control_file, &hashes_b).await;
+        let existing =
+            check_package_exists(&mut *tx, tenant_id, &control_file, &hashes_b, false).await;
         debug!(?existing, "check existing");
-        let err_status = existing.err().unwrap().status;
-        assert!(err_status != StatusCode::CONFLICT && err_status != StatusCode::OK);
+        let err = existing.err().unwrap();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert_eq!(err.error, "PACKAGE_VERSION_CONFLICT");
+
+        // Unless the package is a replacement, which can then be inserted
+        // alongside the original.
+        let existing = check_package_exists(&mut *tx, tenant_id, &control_file, &hashes_b, true)
+            .await
+            .unwrap();
+        assert!(existing.is_none());
+        insert_package(
+            &mut *tx,
+            tenant_id,
+            "attune-dev-0",
+            &format!("packages/{}", hashes_b.sha256sum),
+            control_file.clone(),
+            &hashes_b,
+            42,
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Uploading a rebuild of a package the tenant already has is rejected
+    /// unless the upload is marked as a replacement.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    #[test_log::test]
+    async fn upload_rebuild_requires_replace(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const TEST_NAME: &str = "upload_rebuild_requires_replace";
+        let (tenant_id, api_token) = server.create_test_tenant(TEST_NAME).await;
+
+        // Insert the original build, with the same control fields as the
+        // package built below but different content.
+        let control_file = {
+            let contents = indoc! {"
+                Package: attune-test-package
+                Version: 1.0.0
+                Architecture: amd64
+                Maintainer: Attune <attune@example.com>
+                Description: A test package
+            "};
+            let dsc = DebianSourceControlFile::from_reader(contents.as_bytes()).unwrap();
+            let para = ControlParagraph::from(dsc);
+            BinaryPackageControlFile::from(para)
+        };
+        let hashes = HashesHex {
+            sha256sum: String::from("original build"),
+            sha1sum: String::from("original build"),
+            md5sum: String::from("original build"),
+        };
+        insert_package(
+            &server.db,
+            tenant_id,
+            "attune-dev-0",
+            &format!("packages/{}", hashes.sha256sum),
+            control_file,
+            &hashes,
+            42,
+        )
+        .await
+        .unwrap();
+
+        let deb = build_package(&[]);
+        let upload = MultipartForm::new().add_part("file", Part::bytes(deb.to_vec()));
+        let res = server
+            .http
+            .post("/api/v0/packages")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .multipart(upload)
+            .await;
+        assert_eq!(res.status_code(), StatusCode::CONFLICT);
+        assert_eq!(
+            res.json::<ErrorResponse>().error,
+            "PACKAGE_VERSION_CONFLICT"
+        );
+    }
+
+    /// Build a package that installs the given files.
+    fn build_package(files: &[(&str, Vec<u8>)]) -> Bytes {
+        let control_file = ControlFile::parse_str(indoc! {"
+            Package: attune-test-package
+            Version: 1.0.0
+            Architecture: amd64
+            Maintainer: Attune <attune@example.com>
+            Description: A test package
+        "})
+        .unwrap();
+        let mut builder = DebBuilder::new(control_file);
+        for (path, contents) in files {
+            builder = builder.install_file(*path, contents.clone()).unwrap();
+        }
+        let mut deb = Vec::new();
+        builder.write(&mut deb).unwrap();
+        Bytes::from(deb)
+    }
+
+    fn gzip(contents: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn extract_package_docs() {
+        let deb = build_package(&[
+            ("usr/bin/attune-test-package", b"#!/bin/sh\n".to_vec()),
+            (
+                "usr/share/doc/attune-test-package/changelog.gz",
+                gzip(b"upstream changelog"),
+            ),
+            (
+                "usr/share/doc/attune-test-package/changelog.Debian.gz",
+                gzip(b"attune-test-package (1.0.0) stable; urgency=low"),
+            ),
+            (
+                "usr/share/doc/attune-test-package/copyright",
+                b"Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/"
+                    .to_vec(),
+            ),
+            // Docs of other packages are ignored.
+            ("usr/share/doc/other-package/copyright", b"other".to_vec()),
+        ]);
+        let (control_file, docs) = parse_debian_package(&deb).await.unwrap();
+        assert_eq!(control_file.package().unwrap(), "attune-test-package");
+        assert_eq!(
+            docs.changelog.as_deref(),
+            Some(b"attune-test-package (1.0.0) stable; urgency=low".as_slice())
+        );
+        assert_eq!(
+            docs.copyright.as_deref(),
+            Some(
+                b"Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/"
+                    .as_slice()
+            )
+        );
+
+        // Native packages only have a `changelog.gz`, and a changelog that
+        // can't be decompressed is skipped rather than rejecting the package.
+        let deb = build_package(&[(
+            "usr/share/doc/attune-test-package/changelog.gz",
+            gzip(b"native changelog"),
+        )]);
+        let (_, docs) = parse_debian_package(&deb).await.unwrap();
+        assert_eq!(
+            docs.changelog.as_deref(),
+            Some(b"native changelog".as_slice())
+        );
+        assert_eq!(docs.copyright, None);
+
+        let deb = build_package(&[(
+            "usr/share/doc/attune-test-package/changelog.Debian.gz",
+            b"not gzipped".to_vec(),
+        )]);
+        let (_, docs) = parse_debian_package(&deb).await.unwrap();
+        assert_eq!(docs, PackageDocs::default());
+    }
+
+    /// Truncated packages are rejected rather than panicking, wherever they
+    /// are cut off.
+    #[tokio::test]
+    async fn reject_truncated_package() {
+        let deb = build_package(&[(
+            "usr/share/doc/attune-test-package/copyright",
+            b"copyright".to_vec(),
+        )]);
+        for len in 0..deb.len() {
+            if let Err(err) = parse_debian_package(&deb.slice(..len)).await {
+                assert_eq!(err.status, StatusCode::BAD_REQUEST);
+                assert!(
+                    ["INVALID_DEB_PACKAGE", "MISSING_CONTROL_FILE"].contains(&err.error.as_str()),
+                    "unexpected error for package truncated to {len} bytes: {err:?}"
+                );
+            }
+        }
+    }
+
+    /// Control fields that are stored when the package is inserted must parse.
+    #[tokio::test]
+    async fn reject_invalid_control_fields() {
+        for (field, value) in [("Installed-Size", "lots"), ("Depends", "libc6, (")] {
+            let control_file = ControlFile::parse_str(&format!(
+                "Package: attune-test-package\nVersion: 1.0.0\nArchitecture: amd64\nMaintainer: Attune <attune@example.com>\nDescription: A test package\n{field}: {value}\n"
+            ))
+            .unwrap();
+            let mut deb = Vec::new();
+            DebBuilder::new(control_file).write(&mut deb).unwrap();
+            let err = parse_debian_package(&Bytes::from(deb)).await.unwrap_err();
+            assert_eq!(err.error, "INVALID_DEB_PACKAGE");
+            assert!(err.message.contains(field), "{err:?}");
+        }
+    }
+
+    /// The `file` field is found wherever it is, and other fields are
+    /// skipped, but there must be only one `file` field.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    #[test_log::test]
+    async fn find_file_field_by_name(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const TEST_NAME: &str = "find_file_field_by_name";
+        let (_, api_token) = server.create_test_tenant(TEST_NAME).await;
+        let deb = build_package(&[]);
+
+        let upload = MultipartForm::new()
+            .add_text("metadata", "{}")
+            .add_part("file", Part::bytes(deb.to_vec()))
+            .add_text("comment", "built by CI");
+        let res = server
+            .http
+            .post("/api/v0/packages")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .multipart(upload)
+            .await;
+        res.assert_status_ok();
+        assert_eq!(
+            res.json::<PackageUploadResponse>().sha256sum,
+            Hashes::from_bytes(&deb).hex().sha256sum
+        );
+
+        let upload = MultipartForm::new()
+            .add_part("file", Part::bytes(deb.to_vec()))
+            .add_part("file", Part::bytes(deb.to_vec()));
+        let res = server
+            .http
+            .post("/api/v0/packages")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .multipart(upload)
+            .await;
+        res.assert_status_bad_request();
+        assert_eq!(res.json::<ErrorResponse>().error, "COULD_NOT_PARSE_UPLOAD");
+    }
+
+    /// Uploads without a `file` field are rejected.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    #[test_log::test]
+    async fn reject_missing_file_field(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const TEST_NAME: &str = "reject_missing_file_field";
+        let (_, api_token) = server.create_test_tenant(TEST_NAME).await;
+
+        let upload =
+            MultipartForm::new().add_part("package", Part::bytes(fixtures::TEST_PACKAGE_AMD64));
+        let res = server
+            .http
+            .post("/api/v0/packages")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .multipart(upload)
+            .await;
+        res.assert_status_bad_request();
+        assert_eq!(res.json::<ErrorResponse>().error, "MISSING_FILE_FIELD");
+    }
+
+    /// Files that aren't Debian packages are rejected before anything is
+    /// stored.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    #[test_log::test]
+    async fn reject_invalid_package(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const TEST_NAME: &str = "reject_invalid_package";
+        let (_, api_token) = server.create_test_tenant(TEST_NAME).await;
+
+        let upload = MultipartForm::new().add_part("file", Part::bytes(b"not a package".to_vec()));
+        let res = server
+            .http
+            .post("/api/v0/packages")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .multipart(upload)
+            .await;
+        res.assert_status_bad_request();
+        assert_eq!(res.json::<ErrorResponse>().error, "INVALID_DEB_PACKAGE");
     }
 
     #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
@@ -549,11 +1047,11 @@ mod tests {
             .unwrap();
 
         // Do concurrent SELECT queries.
-        let existing_a = check_package_exists(&mut *tx_a, tenant_id, &control_file, &hashes)
+        let existing_a = check_package_exists(&mut *tx_a, tenant_id, &control_file, &hashes, false)
             .await
             .unwrap();
         assert!(existing_a.is_none());
-        let existing_b = check_package_exists(&mut *tx_b, tenant_id, &control_file, &hashes)
+        let existing_b = check_package_exists(&mut *tx_b, tenant_id, &control_file, &hashes, false)
             .await
             .unwrap();
         assert!(existing_b.is_none());
@@ -563,6 +1061,7 @@ mod tests {
             &mut *tx_a,
             tenant_id,
             "attune-dev-0",
+            &format!("packages/{}", hashes.sha256sum),
             control_file.clone(),
             &hashes,
             42,
@@ -582,6 +1081,7 @@ mod tests {
             &mut *tx_b,
             tenant_id,
             "attune-dev-0",
+            &format!("packages/{}", hashes.sha256sum),
             control_file,
             &hashes,
             42,
@@ -0,0 +1,395 @@
+//! Per-token request rate limiting.
+//!
+//! Requests are rate limited using a token bucket for each API token: every
+//! request takes a token from its bucket, and buckets refill at a constant
+//! rate up to their burst size. Requests without an API token are not rate
+//! limited here, since they are rejected by authentication anyway.
+//!
+//! Tokens are authenticated before they are rate limited, and buckets are
+//! keyed on the ID of the authenticated token. Otherwise, a client could get a
+//! fresh burst by making up a new token for each request. Requests whose token
+//! doesn't authenticate share a bucket per client address instead. Behind a
+//! reverse proxy, that is the proxy's address, so all such requests share one
+//! bucket.
+//!
+//! Buckets are kept in a [`RateLimitStore`]. The OSS server is a single node,
+//! so it uses [`InMemoryRateLimitStore`]; deployments with several nodes can
+//! implement the trait on top of a shared store so that all nodes enforce the
+//! same limits.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::future::BoxFuture;
+use http::{HeaderValue, StatusCode, header::RETRY_AFTER};
+use sqlx::PgPool;
+use tracing::warn;
+
+use crate::api::{
+    ErrorResponse,
+    auth::{find_token, parse_api_token},
+};
+
+/// The configured rate limit for each API token.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    /// The sustained number of requests allowed per second.
+    pub requests_per_second: f64,
+    /// The number of requests that can be made in a burst before being rate
+    /// limited.
+    pub burst: u32,
+}
+
+/// Parse a sustained rate limit, which must be a positive number of requests
+/// per second.
+pub fn parse_requests_per_second(value: &str) -> Result<f64, String> {
+    let requests_per_second = value
+        .parse::<f64>()
+        .map_err(|err| format!("expected a number of requests per second: {err}"))?;
+    if !requests_per_second.is_finite() || requests_per_second <= 0.0 {
+        return Err(format!(
+            "expected a positive number of requests per second, got {value}"
+        ));
+    }
+    Ok(requests_per_second)
+}
+
+/// Parse a burst size, which must allow at least one request. A burst of zero
+/// would reject every request.
+pub fn parse_burst(value: &str) -> Result<u32, String> {
+    let burst = value
+        .parse::<u32>()
+        .map_err(|err| format!("expected a number of requests: {err}"))?;
+    if burst == 0 {
+        return Err(String::from(
+            "expected a burst of at least one request, got 0",
+        ));
+    }
+    Ok(burst)
+}
+
+/// Storage for rate limit buckets.
+pub trait RateLimitStore: Debug + Send + Sync {
+    /// Take a request from the bucket for `key`.
+    ///
+    /// Returns an error with the time until a request will next be allowed if
+    /// the bucket is empty.
+    fn acquire<'a>(&'a self, key: &'a str, limit: RateLimit)
+    -> BoxFuture<'a, Result<(), Duration>>;
+}
+
+/// A [`RateLimitStore`] that keeps buckets in the server's memory.
+#[derive(Debug, Default)]
+pub struct InMemoryRateLimitStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+/// Past this many buckets, full buckets are dropped, since they are no
+/// different from new buckets. If that isn't enough, e.g. because invalid
+/// tokens are being sent from many different addresses, the least recently
+/// used buckets are dropped too, down to half of this.
+const MAX_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+        self.updated = now;
+    }
+}
+
+impl InMemoryRateLimitStore {
+    fn acquire_at(&self, key: &str, limit: RateLimit, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst as f64
+            });
+        }
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(key) {
+            let mut updated = buckets
+                .values()
+                .map(|bucket| bucket.updated)
+                .collect::<Vec<_>>();
+            let (_, &mut cutoff, _) = updated.select_nth_unstable(MAX_BUCKETS / 2);
+            buckets.retain(|_, bucket| bucket.updated > cutoff);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: limit.burst as f64,
+            updated: now,
+        });
+        bucket.refill(limit, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / limit.requests_per_second,
+            ))
+        }
+    }
+}
+
+impl RateLimitStore for InMemoryRateLimitStore {
+    fn acquire<'a>(
+        &'a self,
+        key: &'a str,
+        limit: RateLimit,
+    ) -> BoxFuture<'a, Result<(), Duration>> {
+        Box::pin(async move { self.acquire_at(key, limit, Instant::now()) })
+    }
+}
+
+/// State for the rate limiting middleware.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    pub limit: RateLimit,
+    pub store: Arc<dyn RateLimitStore>,
+    /// The database to authenticate tokens against.
+    pub db: PgPool,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter that keeps its buckets in memory.
+    pub fn in_memory(limit: RateLimit, db: PgPool) -> Self {
+        Self {
+            limit,
+            store: Arc::new(InMemoryRateLimitStore::default()),
+            db,
+        }
+    }
+}
+
+/// Middleware that rejects requests over the rate limit of their API token
+/// with `429 Too Many Requests`.
+pub async fn rate_limit(
+    State(limiter): State<RateLimiter>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Ok(token) = parse_api_token(request.headers()) else {
+        return next.run(request).await;
+    };
+
+    let key = match find_token(&limiter.db, token).await {
+        Ok(Some(token)) => {
+            let key = format!("token:{}", token.id);
+            // Authentication reuses the token instead of verifying it again.
+            request.extensions_mut().insert(token);
+            key
+        }
+        Ok(None) => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(address)) => format!("address:{}", address.ip()),
+            None => String::from("address:unknown"),
+        },
+        Err(err) => {
+            // Authentication will fail the request too.
+            warn!(?err, "could not authenticate token for rate limiting");
+            return next.run(request).await;
+        }
+    };
+    match limiter.store.acquire(&key, limiter.limit).await {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+            let mut response = ErrorResponse::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .error("RATE_LIMITED")
+                .message(format!(
+                    "too many requests, retry after {retry_after} seconds"
+                ))
+                .build()
+                .into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, routing::get};
+    use axum_test::TestServer;
+    use sha2::{Digest as _, Sha256};
+
+    use super::*;
+
+    /// Serve a route that is rate limited to `burst` requests, refilling every
+    /// two seconds, with API tokens `first` and `second`.
+    async fn rate_limited_server(pool: PgPool, burst: u32) -> TestServer {
+        sqlx::query!(
+            r#"
+            INSERT INTO attune_tenant (id, display_name, subdomain, created_at, updated_at)
+            VALUES (1, 'TEST_TENANT', 'test', NOW(), NOW())
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        for token in ["first", "second"] {
+            sqlx::query!(
+                r#"
+                INSERT INTO attune_tenant_api_token (tenant_id, name, token)
+                VALUES (1, $1, $2)
+                "#,
+                token,
+                Sha256::digest(token).as_slice().to_vec(),
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let limiter = RateLimiter::in_memory(
+            RateLimit {
+                requests_per_second: 0.5,
+                burst,
+            },
+            pool,
+        );
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(axum::middleware::from_fn_with_state(limiter, rate_limit))
+            .layer(axum::middleware::from_fn(super::super::handle_non_success));
+        TestServer::new(app).unwrap()
+    }
+
+    /// Once a token's burst is used up, further requests are rejected until
+    /// the bucket refills. Other tokens are unaffected.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn throttle_after_burst(pool: PgPool) {
+        const BURST: u32 = 3;
+        let server = rate_limited_server(pool, BURST).await;
+
+        for _ in 0..BURST {
+            server
+                .get("/")
+                .add_header("authorization", "Bearer first")
+                .await
+                .assert_status_ok();
+        }
+        let response = server
+            .get("/")
+            .add_header("authorization", "Bearer first")
+            .await;
+        response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.header(RETRY_AFTER), "2");
+        assert_eq!(response.json::<ErrorResponse>().error, "RATE_LIMITED");
+
+        server
+            .get("/")
+            .add_header("authorization", "Bearer second")
+            .await
+            .assert_status_ok();
+    }
+
+    /// Tokens that don't authenticate share a bucket, so that making up a new
+    /// token doesn't get a new burst. Valid tokens are unaffected.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn share_bucket_between_invalid_tokens(pool: PgPool) {
+        const BURST: u32 = 3;
+        let server = rate_limited_server(pool, BURST).await;
+
+        for i in 0..BURST {
+            server
+                .get("/")
+                .add_header("authorization", format!("Bearer made-up-{i}"))
+                .await
+                .assert_status_ok();
+        }
+        server
+            .get("/")
+            .add_header("authorization", "Bearer made-up-again")
+            .await
+            .assert_status(StatusCode::TOO_MANY_REQUESTS);
+
+        server
+            .get("/")
+            .add_header("authorization", "Bearer first")
+            .await
+            .assert_status_ok();
+    }
+
+    #[test]
+    fn reject_zero_burst() {
+        assert_eq!(parse_burst("50"), Ok(50));
+        for value in ["0", "-1", "lots"] {
+            assert!(parse_burst(value).is_err(), "{value:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn reject_invalid_requests_per_second() {
+        assert_eq!(parse_requests_per_second("2.5"), Ok(2.5));
+        for value in ["0", "-1", "inf", "NaN", "fast"] {
+            assert!(
+                parse_requests_per_second(value).is_err(),
+                "{value:?} should be rejected"
+            );
+        }
+    }
+
+    /// The number of buckets is bounded, even when every request has a
+    /// different token and no bucket is full.
+    #[test]
+    fn bound_buckets() {
+        let store = InMemoryRateLimitStore::default();
+        let limit = RateLimit {
+            requests_per_second: 0.001,
+            burst: 1,
+        };
+        let start = Instant::now();
+        for i in 0..MAX_BUCKETS * 2 {
+            let now = start + Duration::from_millis(i as u64);
+            assert!(store.acquire_at(&format!("key-{i}"), limit, now).is_ok());
+            assert!(store.buckets.lock().unwrap().len() <= MAX_BUCKETS);
+        }
+
+        // The most recently used buckets are kept.
+        let now = start + Duration::from_millis(MAX_BUCKETS as u64 * 2);
+        let last = format!("key-{}", MAX_BUCKETS * 2 - 1);
+        assert!(store.acquire_at(&last, limit, now).is_err());
+    }
+
+    /// Buckets refill over time, up to their burst size.
+    #[test]
+    fn refill_up_to_burst() {
+        let store = InMemoryRateLimitStore::default();
+        let limit = RateLimit {
+            requests_per_second: 1.0,
+            burst: 2,
+        };
+        let start = Instant::now();
+        assert!(store.acquire_at("key", limit, start).is_ok());
+        assert!(store.acquire_at("key", limit, start).is_ok());
+        assert_eq!(
+            store.acquire_at("key", limit, start),
+            Err(Duration::from_secs(1))
+        );
+
+        let later = start + Duration::from_secs(10);
+        assert!(store.acquire_at("key", limit, later).is_ok());
+        assert!(store.acquire_at("key", limit, later).is_ok());
+        assert!(store.acquire_at("key", limit, later).is_err());
+    }
+}
@@ -0,0 +1,242 @@
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use percent_encoding::percent_decode_str;
+use sqlx::PgConnection;
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{ServerState, repo::decode_repo_name},
+};
+
+/// A documentation file extracted from a package when it was uploaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Doc {
+    Changelog,
+    Copyright,
+}
+
+/// Serve the uncompressed Debian changelog of a package published in the
+/// repository, for `apt-get changelog`.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn changelog_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path((repository_name, package, version)): Path<(String, String, String)>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    serve(
+        state,
+        tenant_id,
+        repository_name,
+        package,
+        version,
+        Doc::Changelog,
+    )
+    .await
+}
+
+/// Serve the copyright file of a package published in the repository.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn copyright_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path((repository_name, package, version)): Path<(String, String, String)>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    serve(
+        state,
+        tenant_id,
+        repository_name,
+        package,
+        version,
+        Doc::Copyright,
+    )
+    .await
+}
+
+async fn serve(
+    state: ServerState,
+    tenant_id: TenantID,
+    repository_name: String,
+    package: String,
+    version: String,
+    doc: Doc,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    // The path segments are percent-encoded; versions may contain `:` and `~`.
+    let repository_name = decode_repo_name(&repository_name)?;
+    let package = decode_segment(&package)?;
+    let version = decode_segment(&version)?;
+
+    let mut conn = state.db.acquire().await.map_err(ErrorResponse::from)?;
+    let contents = query_doc(
+        &mut conn,
+        &tenant_id,
+        &repository_name,
+        &package,
+        &version,
+        doc,
+    )
+    .await?
+    .ok_or_else(|| {
+        ErrorResponse::builder()
+            .status(StatusCode::NOT_FOUND)
+            .error("DOC_NOT_FOUND")
+            .message(match doc {
+                Doc::Changelog => "package has no changelog",
+                Doc::Copyright => "package has no copyright file",
+            })
+            .build()
+    })?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        contents,
+    ))
+}
+
+fn decode_segment(segment: &str) -> Result<String, ErrorResponse> {
+    match percent_decode_str(segment).decode_utf8() {
+        Ok(segment) => Ok(segment.to_string()),
+        Err(err) => Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_PATH".to_string(),
+            format!("invalid path segment: could not percent decode: {err}"),
+        )),
+    }
+}
+
+/// Find the document of a package version that is published in the
+/// repository.
+///
+/// Returns `Ok(None)` if the package version is published but doesn't have the
+/// document, and a 404 error if the package version isn't published at all.
+/// Every architecture of a version is built from the same source, so any of
+/// them will do.
+async fn query_doc(
+    conn: &mut PgConnection,
+    tenant_id: &TenantID,
+    repository_name: &str,
+    package: &str,
+    version: &str,
+    doc: Doc,
+) -> Result<Option<Vec<u8>>, ErrorResponse> {
+    let docs = sqlx::query!(
+        r#"
+        SELECT DISTINCT
+            debian_repository_package.id,
+            debian_repository_package.changelog,
+            debian_repository_package.copyright
+        FROM
+            debian_repository
+            JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
+            JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository_component_package ON debian_repository_component_package.component_id = debian_repository_component.id
+            JOIN debian_repository_package ON debian_repository_package.id = debian_repository_component_package.package_id
+        WHERE
+            debian_repository.tenant_id = $1
+            AND debian_repository.name = $2
+            AND debian_repository_package.package = $3
+            AND debian_repository_package.version = $4
+        ORDER BY debian_repository_package.id
+        "#,
+        tenant_id.0,
+        repository_name,
+        package,
+        version,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ErrorResponse::from)?;
+    if docs.is_empty() {
+        return Err(ErrorResponse::builder()
+            .status(StatusCode::NOT_FOUND)
+            .error("PACKAGE_NOT_FOUND")
+            .message("package version not found in repository")
+            .build());
+    }
+    Ok(docs.into_iter().find_map(|row| match doc {
+        Doc::Changelog => row.changelog,
+        Doc::Copyright => row.copyright,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn query_published_docs(pool: sqlx::PgPool) {
+        let mut conn = pool.acquire().await.unwrap();
+        let tenant_id = TenantID(1);
+
+        // Only the arm64 build has a changelog.
+        sqlx::query!(
+            r#"
+            UPDATE debian_repository_package
+            SET changelog = 'test-package (1.0.0) stable; urgency=low'
+            WHERE id = 1002
+            "#
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+        let changelog = query_doc(
+            &mut conn,
+            &tenant_id,
+            "test-multi-arch",
+            "test-package",
+            "1.0.0",
+            Doc::Changelog,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            changelog.as_deref(),
+            Some(b"test-package (1.0.0) stable; urgency=low".as_slice())
+        );
+
+        let copyright = query_doc(
+            &mut conn,
+            &tenant_id,
+            "test-multi-arch",
+            "test-package",
+            "1.0.0",
+            Doc::Copyright,
+        )
+        .await
+        .unwrap();
+        assert_eq!(copyright, None);
+
+        let err = query_doc(
+            &mut conn,
+            &tenant_id,
+            "test-multi-arch",
+            "test-package",
+            "2.0.0",
+            Doc::Changelog,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+
+        // Packages are only visible through repositories of their tenant.
+        let err = query_doc(
+            &mut conn,
+            &TenantID(2),
+            "test-multi-arch",
+            "test-package",
+            "1.0.0",
+            Doc::Changelog,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+}
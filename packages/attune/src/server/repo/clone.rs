@@ -0,0 +1,353 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{
+            check_repository_quota,
+            create::{repo_base_url, repo_prefix},
+            decode_repo_name,
+        },
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CloneRepositoryRequest {
+    /// The name of the new repository.
+    pub destination: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CloneRepositoryResponse {
+    pub id: i64,
+    pub name: String,
+    pub s3_bucket: String,
+    pub s3_prefix: String,
+    /// The public URL at which the new repository is served. This is only set
+    /// if the server is configured with a public base URL.
+    #[serde(default)]
+    pub base_url: Option<String>,
+
+    /// The number of distributions created in the new repository.
+    pub distributions: u64,
+    /// The number of components created in the new repository.
+    pub components: u64,
+}
+
+/// Create a new repository with the same distributions and components as an
+/// existing one.
+///
+/// Only the structure and metadata are cloned: the new repository's
+/// distributions have no packages and are unpublished. Packages can then be
+/// added to them with the usual index generate and sign flow, which reuses the
+/// existing package objects.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repository_name): Path<String>,
+    Json(req): Json<CloneRepositoryRequest>,
+) -> Result<Json<CloneRepositoryResponse>, ErrorResponse> {
+    let repository_name = decode_repo_name(&repository_name)?;
+
+    let mut tx = state.db.begin().await.unwrap();
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+
+    let source = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            allowed_architectures::TEXT[] AS "allowed_architectures!",
+            allowed_components AS "allowed_components!"
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        repository_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            axum::http::StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND".to_string(),
+            "repository not found".to_string(),
+        )
+    })?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT id
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        req.destination,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+    if existing.is_some() {
+        return Err(ErrorResponse::new(
+            axum::http::StatusCode::BAD_REQUEST,
+            "REPO_ALREADY_EXISTS".to_string(),
+            "repository already exists".to_string(),
+        ));
+    }
+    check_repository_quota(&mut *tx, tenant_id, state.max_repos_per_tenant).await?;
+
+    // The new repository is created exactly as if it were created through the
+    // create endpoint, with the same allowlists as the source.
+    let s3_bucket = state.s3_bucket_name;
+    let s3_prefix = repo_prefix(tenant_id, &req.destination);
+    let base_url = state
+        .public_base_url
+        .map(|template| repo_base_url(&template, &s3_bucket, &s3_prefix));
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO debian_repository (
+            name,
+            tenant_id,
+            s3_bucket,
+            s3_prefix,
+            allowed_architectures,
+            allowed_components,
+            created_at,
+            updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5::TEXT[]::debian_repository_architecture[], $6, NOW(), NOW())
+        RETURNING id, name
+        "#,
+        req.destination,
+        tenant_id.0,
+        s3_bucket,
+        s3_prefix,
+        &source.allowed_architectures,
+        &source.allowed_components,
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    // Copy the distributions' metadata, but not their contents or signatures:
+    // the new distributions are not published until packages are added.
+    let distributions = sqlx::query!(
+        r#"
+        INSERT INTO debian_repository_release (
+            repository_id,
+            distribution,
+            description,
+            origin,
+            label,
+            version,
+            suite,
+            codename,
+            index_compression,
+            declared_architectures,
+            declared_components,
+            default_component,
+            sha256_only,
+            valid_for_seconds,
+            not_automatic,
+            but_automatic_upgrades,
+            contents,
+            created_at,
+            updated_at
+        )
+        SELECT
+            $1,
+            distribution,
+            description,
+            origin,
+            label,
+            version,
+            suite,
+            codename,
+            index_compression,
+            declared_architectures,
+            declared_components,
+            default_component,
+            sha256_only,
+            valid_for_seconds,
+            not_automatic,
+            but_automatic_upgrades,
+            '',
+            NOW(),
+            NOW()
+        FROM debian_repository_release
+        WHERE repository_id = $2
+        "#,
+        inserted.id,
+        source.id,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .rows_affected();
+
+    let components = sqlx::query!(
+        r#"
+        INSERT INTO debian_repository_component (
+            release_id,
+            name,
+            created_at,
+            updated_at
+        )
+        SELECT
+            destination.id,
+            debian_repository_component.name,
+            NOW(),
+            NOW()
+        FROM
+            debian_repository_component
+            JOIN debian_repository_release source ON source.id = debian_repository_component.release_id
+            JOIN debian_repository_release destination ON destination.distribution = source.distribution
+        WHERE
+            source.repository_id = $1
+            AND destination.repository_id = $2
+        "#,
+        source.id,
+        inserted.id,
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .rows_affected();
+
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    Ok(Json(CloneRepositoryResponse {
+        id: inserted.id,
+        name: inserted.name,
+        s3_bucket,
+        s3_prefix,
+        base_url,
+        distributions,
+        components,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AttuneTestServer, AttuneTestServerConfig};
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn clone_copies_distributions_and_components(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "clone_copies_distributions_and_components";
+        const CLONE_NAME: &str = "clone_copies_distributions_and_components_clone";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        let release = sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_release (
+                repository_id,
+                distribution,
+                origin,
+                suite,
+                codename,
+                contents,
+                created_at,
+                updated_at
+            )
+            SELECT id, 'stable', 'Example', 'stable', 'bookworm', 'Origin: Example', NOW(), NOW()
+            FROM debian_repository
+            WHERE tenant_id = $1 AND name = $2
+            RETURNING id
+            "#,
+            tenant_id.0,
+            REPO_NAME,
+        )
+        .fetch_one(&server.db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component (release_id, name, created_at, updated_at)
+            VALUES ($1, 'main', NOW(), NOW()), ($1, 'contrib', NOW(), NOW())
+            "#,
+            release.id,
+        )
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+        let response = server
+            .http
+            .post(&format!("/api/v0/repositories/{REPO_NAME}/clone"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&CloneRepositoryRequest {
+                destination: String::from(CLONE_NAME),
+            })
+            .await;
+        response.assert_status_ok();
+        let response = response.json::<CloneRepositoryResponse>();
+        assert_eq!(response.name, CLONE_NAME);
+        assert_eq!(response.distributions, 1);
+        assert_eq!(response.components, 2);
+
+        let cloned = sqlx::query!(
+            r#"
+            SELECT
+                debian_repository_release.origin,
+                debian_repository_release.codename,
+                debian_repository_release.contents,
+                debian_repository_component.name AS component
+            FROM
+                debian_repository_release
+                JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+            WHERE debian_repository_release.repository_id = $1
+            ORDER BY debian_repository_component.name
+            "#,
+            response.id,
+        )
+        .fetch_all(&server.db)
+        .await
+        .unwrap();
+        assert_eq!(
+            cloned
+                .iter()
+                .map(|row| row.component.as_str())
+                .collect::<Vec<_>>(),
+            ["contrib", "main"]
+        );
+        for row in cloned {
+            assert_eq!(row.origin.as_deref(), Some("Example"));
+            assert_eq!(row.codename, "bookworm");
+            assert_eq!(row.contents, "");
+        }
+
+        // Cloning onto an existing repository fails.
+        let response = server
+            .http
+            .post(&format!("/api/v0/repositories/{REPO_NAME}/clone"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&CloneRepositoryRequest {
+                destination: String::from(CLONE_NAME),
+            })
+            .await;
+        response.assert_status_bad_request();
+        assert_eq!(
+            response.json::<ErrorResponse>().error,
+            "REPO_ALREADY_EXISTS"
+        );
+    }
+}
@@ -5,7 +5,10 @@ use tracing::instrument;
 
 use crate::{
     api::{ErrorResponse, TenantID},
-    server::ServerState,
+    server::{
+        ServerState,
+        repo::{check_repository_quota, validate_allowlists},
+    },
 };
 
 #[derive(Serialize)]
@@ -17,6 +20,14 @@ pub struct Repository {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CreateRepositoryRequest {
     pub name: String,
+    /// If not empty, only packages of these architectures can be added to the
+    /// repository. Example: `["amd64", "arm64"]`
+    #[serde(default)]
+    pub allowed_architectures: Vec<String>,
+    /// If not empty, packages can only be added to these components.
+    /// Example: `["main"]`
+    #[serde(default)]
+    pub allowed_components: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -25,6 +36,11 @@ pub struct CreateRepositoryResponse {
     pub name: String,
     pub s3_bucket: String,
     pub s3_prefix: String,
+    /// The public URL at which the repository is served, for use in APT
+    /// sources. This is only set if the server is configured with a public
+    /// base URL.
+    #[serde(default)]
+    pub base_url: Option<String>,
 }
 
 #[axum::debug_handler]
@@ -35,6 +51,16 @@ pub async fn handler(
     Json(req): Json<CreateRepositoryRequest>,
 ) -> Result<Json<CreateRepositoryResponse>, ErrorResponse> {
     let mut tx = state.db.begin().await.unwrap();
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    let allowed_architectures = validate_allowlists(
+        &mut *tx,
+        &req.allowed_architectures,
+        &req.allowed_components,
+    )
+    .await?;
 
     // Find or create a repository with the given name. If a repository already
     // exists, abort.
@@ -57,10 +83,14 @@ pub async fn handler(
             "repository already exists".to_string(),
         ));
     }
+    check_repository_quota(&mut *tx, tenant_id, state.max_repos_per_tenant).await?;
 
     // Insert repository row.
     let s3_bucket = state.s3_bucket_name;
     let s3_prefix = repo_prefix(tenant_id, &req.name);
+    let base_url = state
+        .public_base_url
+        .map(|template| repo_base_url(&template, &s3_bucket, &s3_prefix));
     let inserted = sqlx::query!(
         r#"
         INSERT INTO debian_repository (
@@ -68,16 +98,20 @@ pub async fn handler(
             tenant_id,
             s3_bucket,
             s3_prefix,
+            allowed_architectures,
+            allowed_components,
             created_at,
             updated_at
         )
-        VALUES ($1, $2, $3, $4, NOW(), NOW())
+        VALUES ($1, $2, $3, $4, $5::TEXT[]::debian_repository_architecture[], $6, NOW(), NOW())
         RETURNING id, name
         "#,
         req.name,
         tenant_id.0,
         s3_bucket,
         s3_prefix,
+        &allowed_architectures,
+        &req.allowed_components,
     )
     .fetch_one(&mut *tx)
     .await
@@ -92,9 +126,18 @@ pub async fn handler(
         name: inserted.name,
         s3_bucket,
         s3_prefix,
+        base_url,
     }))
 }
 
+/// Render the public base URL of a repository from the server's configured
+/// template.
+pub fn repo_base_url(template: &str, s3_bucket: &str, s3_prefix: &str) -> String {
+    template
+        .replace("{bucket}", s3_bucket)
+        .replace("{prefix}", s3_prefix)
+}
+
 pub fn repo_prefix(tenant_id: TenantID, repo_name: &str) -> String {
     format!(
         "{}/{}",
@@ -104,3 +147,55 @@ pub fn repo_prefix(tenant_id: TenantID, repo_name: &str) -> String {
         ))
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_server_state;
+
+    #[test]
+    fn render_repo_base_url() {
+        assert_eq!(
+            repo_base_url(
+                "http://localhost:9000/{bucket}/{prefix}",
+                "attune-dev-0",
+                "1/abc"
+            ),
+            "http://localhost:9000/attune-dev-0/1/abc"
+        );
+        assert_eq!(
+            repo_base_url("https://repo.example.com/{prefix}", "attune-dev-0", "1/abc"),
+            "https://repo.example.com/1/abc"
+        );
+    }
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn enforce_repository_quota(pool: sqlx::PgPool) {
+        let state = ServerState {
+            max_repos_per_tenant: Some(2),
+            ..test_server_state(pool)
+        };
+        let create = |name: &str| {
+            handler(
+                State(state.clone()),
+                TenantID(1),
+                Json(CreateRepositoryRequest {
+                    name: name.to_string(),
+                    allowed_architectures: Vec::new(),
+                    allowed_components: Vec::new(),
+                }),
+            )
+        };
+
+        // The fixture already has one repository, so the tenant can create one
+        // more.
+        let Json(created) = create("second").await.unwrap();
+        assert_eq!(created.name, "second");
+        let err = create("third").await.unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::CONFLICT);
+        assert_eq!(err.error, "REPOSITORY_QUOTA_EXCEEDED");
+    }
+}
@@ -4,18 +4,29 @@ use axum::{
     http::StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use tracing::instrument;
+use tracing::{debug, instrument};
 
 use crate::{
     api::{ErrorResponse, TenantID},
     server::{ServerState, repo::decode_repo_name},
 };
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct DeleteRepositoryRequest {}
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DeleteRepositoryRequest {
+    /// Whether to also delete every object stored under the repository's S3
+    /// prefix (its `dists/` and `pool/` trees).
+    ///
+    /// By default, objects are left in place.
+    #[serde(default)]
+    pub purge_objects: bool,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct DeleteRepositoryResponse {}
+pub struct DeleteRepositoryResponse {
+    /// The number of objects deleted from S3, if objects were purged.
+    #[serde(default)]
+    pub purged_objects: Option<u64>,
+}
 
 #[axum::debug_handler]
 #[instrument(skip(state))]
@@ -28,24 +39,192 @@ pub async fn handler(
     // The repository name in the path is percent-encoded.
     let name = decode_repo_name(&name)?;
 
-    let deleted = sqlx::query!(
+    let repo = sqlx::query!(
         r#"
-        DELETE FROM debian_repository
+        SELECT id, s3_bucket, s3_prefix
+        FROM debian_repository
         WHERE tenant_id = $1 AND name = $2
         "#,
         tenant_id.0,
         &name,
     )
-    .execute(&state.db)
+    .fetch_optional(&state.db)
     .await
-    .map_err(ErrorResponse::from)?;
-    if deleted.rows_affected() > 0 {
-        Ok(Json(DeleteRepositoryResponse {}))
-    } else {
-        Err(ErrorResponse::new(
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::new(
             StatusCode::NOT_FOUND,
             "REPO_NOT_FOUND".to_string(),
             "repository not found".to_string(),
-        ))
+        )
+    })?;
+
+    // Objects are purged before the repository is deleted, so that if the
+    // purge fails partway through, the deletion can simply be retried.
+    let purged_objects = if req.purge_objects {
+        Some(purge_objects(&state.s3, &repo.s3_bucket, &repo.s3_prefix).await?)
+    } else {
+        None
+    };
+
+    sqlx::query!(
+        r#"
+        DELETE FROM debian_repository
+        WHERE id = $1
+        "#,
+        repo.id,
+    )
+    .execute(&state.db)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    Ok(Json(DeleteRepositoryResponse { purged_objects }))
+}
+
+/// Delete every object under the repository's prefix, returning the number of
+/// objects deleted.
+#[instrument(skip(s3))]
+async fn purge_objects(
+    s3: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    s3_prefix: &str,
+) -> Result<u64, ErrorResponse> {
+    // List and delete a page at a time. Each page has at most 1000 keys, which
+    // is also the most that `DeleteObjects` accepts.
+    let mut pages = s3
+        .list_objects_v2()
+        .bucket(s3_bucket)
+        .prefix(format!("{s3_prefix}/"))
+        .into_paginator()
+        .send();
+    let mut purged = 0;
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            ErrorResponse::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .error("S3_LIST_FAILED")
+                .message(format!("could not list repository objects: {err}"))
+                .build()
+        })?;
+        let objects = page
+            .contents
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|object| object.key)
+            .map(|key| {
+                aws_sdk_s3::types::ObjectIdentifier::builder()
+                    .key(key)
+                    .build()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        if objects.is_empty() {
+            continue;
+        }
+        let count = objects.len() as u64;
+
+        // In quiet mode, only failed deletions are reported.
+        let delete = aws_sdk_s3::types::Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true)
+            .build()
+            .unwrap();
+        let deleted = s3
+            .delete_objects()
+            .bucket(s3_bucket)
+            .delete(delete)
+            .send()
+            .await
+            .map_err(|err| {
+                ErrorResponse::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .error("S3_DELETE_FAILED")
+                    .message(format!(
+                        "could not delete repository objects after deleting {purged}: {err}"
+                    ))
+                    .build()
+            })?;
+        if let Some(error) = deleted.errors.as_deref().and_then(|errors| errors.first()) {
+            return Err(ErrorResponse::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .error("S3_DELETE_FAILED")
+                .message(format!(
+                    "could not delete object {:?} after deleting {purged}: {}",
+                    error.key.as_deref().unwrap_or_default(),
+                    error.message.as_deref().unwrap_or_default(),
+                ))
+                .build());
+        }
+        purged += count;
+        debug!(?purged, "deleted repository objects");
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AttuneTestServer, AttuneTestServerConfig};
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    #[test_log::test]
+    async fn purge_deletes_repository_objects(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "purge_deletes_repository_objects";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        let s3_prefix = server.create_repository(tenant_id, REPO_NAME).await;
+
+        let keys = [
+            "dists/stable/Release",
+            "dists/stable/main/binary-amd64/Packages",
+            "pool/main/t/test-package/test-package_1.0.0_amd64.deb",
+        ];
+        for key in keys {
+            server
+                .s3
+                .put_object()
+                .bucket(&server.s3_bucket_name)
+                .key(format!("{s3_prefix}/{key}"))
+                .body(Vec::from(key).into())
+                .send()
+                .await
+                .unwrap();
+        }
+
+        let response = server
+            .http
+            .delete(&format!("/api/v0/repositories/{REPO_NAME}"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&DeleteRepositoryRequest {
+                purge_objects: true,
+            })
+            .await;
+        response.assert_status_ok();
+        assert_eq!(
+            response.json::<DeleteRepositoryResponse>().purged_objects,
+            Some(keys.len() as u64)
+        );
+
+        let remaining = server
+            .s3
+            .list_objects_v2()
+            .bucket(&server.s3_bucket_name)
+            .prefix(format!("{s3_prefix}/"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(remaining.key_count, Some(0));
+
+        let response = server
+            .http
+            .get(&format!("/api/v0/repositories/{REPO_NAME}"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_not_found();
     }
 }
@@ -0,0 +1,260 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use bon::Builder;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{decode_repo_name, dist::decode_dist_name},
+    },
+};
+
+/// Request to remove every package from a distribution in one operation.
+///
+/// This is the bulk counterpart to removing packages one at a time: all
+/// component-package associations under the distribution are removed in a
+/// single transaction, and the distribution's published files are removed
+/// without re-signing the index for each package.
+#[derive(Serialize, Deserialize, Debug, Default, Builder)]
+pub struct ClearDistributionRequest {
+    /// Whether to also delete the distribution itself.
+    ///
+    /// By default the distribution is kept (but reset to its never-published
+    /// state) so that it can be reused without being re-created.
+    #[serde(default)]
+    #[builder(default)]
+    pub delete_release: bool,
+}
+
+/// Response after successfully clearing a distribution.
+#[derive(Serialize, Deserialize, Debug, Builder)]
+pub struct ClearDistributionResponse {
+    /// The number of packages that were removed from the distribution.
+    pub removed_packages: u64,
+
+    /// The number of pool files that were no longer referenced by any
+    /// distribution in the repository, and were therefore deleted.
+    pub orphaned_pool_files: u64,
+
+    /// Whether the distribution itself was deleted.
+    pub release_deleted: bool,
+}
+
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path((repository_name, distribution_name)): Path<(String, String)>,
+    Json(req): Json<ClearDistributionRequest>,
+) -> Result<Json<ClearDistributionResponse>, ErrorResponse> {
+    let repository_name = decode_repo_name(&repository_name)?;
+    let distribution_name = decode_dist_name(&distribution_name)?;
+
+    // Clearing a distribution races with concurrent package changes in the
+    // same way that signing does, so it needs the same isolation level.
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+
+    let repo = sqlx::query!(
+        r#"
+        SELECT id, s3_bucket, s3_prefix
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        repository_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::builder()
+            .status(axum::http::StatusCode::NOT_FOUND)
+            .error("REPO_NOT_FOUND")
+            .message("repository not found")
+            .build()
+    })?;
+
+    let release = sqlx::query!(
+        r#"
+        SELECT id
+        FROM debian_repository_release
+        WHERE repository_id = $1 AND distribution = $2
+        "#,
+        repo.id,
+        distribution_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::builder()
+            .status(axum::http::StatusCode::NOT_FOUND)
+            .error("DISTRIBUTION_NOT_FOUND")
+            .message("distribution not found")
+            .build()
+    })?;
+
+    // Remove every package from every component of the distribution. The
+    // returned filenames are the pool files that may now be orphaned.
+    let removed = sqlx::query!(
+        r#"
+        DELETE FROM debian_repository_component_package cp
+        USING debian_repository_component c
+        WHERE cp.component_id = c.id AND c.release_id = $1
+        RETURNING cp.filename
+        "#,
+        release.id,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .into_iter()
+    .map(|row| row.filename)
+    .collect::<Vec<_>>();
+
+    // Pool files are shared between distributions of the same repository, so
+    // a pool file is only orphaned if no other distribution still references
+    // it.
+    let orphaned = sqlx::query!(
+        r#"
+        SELECT DISTINCT removed.filename AS "filename!"
+        FROM unnest($1::text[]) AS removed(filename)
+        WHERE NOT EXISTS (
+            SELECT 1
+            FROM debian_repository_component_package cp
+            JOIN debian_repository_component c ON c.id = cp.component_id
+            JOIN debian_repository_release r ON r.id = c.release_id
+            WHERE r.repository_id = $2 AND cp.filename = removed.filename
+        )
+        "#,
+        &removed,
+        repo.id,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .into_iter()
+    .map(|row| row.filename)
+    .collect::<Vec<_>>();
+
+    if req.delete_release {
+        // Cascade will handle the now-empty components and their indexes.
+        sqlx::query!(
+            r#"
+            DELETE FROM debian_repository_release
+            WHERE id = $1
+            "#,
+            release.id,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    } else {
+        // Cascade will handle the Packages indexes of the components.
+        sqlx::query!(
+            r#"
+            DELETE FROM debian_repository_component
+            WHERE release_id = $1
+            "#,
+            release.id,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+
+        // The published Release files are about to be deleted, so the
+        // distribution goes back to the state it was in when it was created.
+        sqlx::query!(
+            r#"
+            UPDATE debian_repository_release
+            SET
+                contents = '',
+                clearsigned = NULL,
+                detached = NULL,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+            release.id,
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    }
+
+    // Database state is correct, so we can commit the transaction.
+    // Now all we need to do is clean up S3 objects.
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    // List the whole `dists/<distribution>/` tree rather than computing keys
+    // from the database, so that objects left behind by earlier partial
+    // uploads are also cleaned up.
+    let dists_prefix = format!("{}/dists/{}/", repo.s3_prefix, distribution_name);
+    let dists_keys = state
+        .s3
+        .list_objects_v2()
+        .bucket(&repo.s3_bucket)
+        .prefix(&dists_prefix)
+        .into_paginator()
+        .send()
+        .try_collect()
+        .await
+        .map_err(|err| {
+            ErrorResponse::builder()
+                .status(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+                .error("S3_LIST_FAILED")
+                .message(format!("could not list distribution objects: {err}"))
+                .build()
+        })?
+        .into_iter()
+        .flat_map(|page| page.contents.unwrap_or_default())
+        .filter_map(|object| object.key);
+    let keys = dists_keys
+        .chain(
+            orphaned
+                .iter()
+                .map(|filename| format!("{}/{filename}", repo.s3_prefix)),
+        )
+        .collect::<Vec<_>>();
+    debug!(?keys, "deleting distribution objects");
+
+    // The database changes are already committed, so a failed deletion can't
+    // be rolled back. Report it rather than claiming the objects are gone.
+    let deletions = keys
+        .chunks(1000)
+        .map(|chunk| state.object_store.delete_objects(&repo.s3_bucket, chunk));
+    let errors = futures_util::future::join_all(deletions)
+        .await
+        .into_iter()
+        .filter_map(Result::err)
+        .collect::<Vec<_>>();
+    if let Some(err) = errors.first() {
+        return Err(ErrorResponse::builder()
+            .status(axum::http::StatusCode::BAD_GATEWAY)
+            .error("S3_DELETE_FAILED")
+            .message(format!(
+                "distribution was cleared, but {} of {} batches of its {} objects could not be deleted: {err}",
+                errors.len(),
+                keys.len().div_ceil(1000),
+                keys.len(),
+            ))
+            .build());
+    }
+
+    Ok(Json(
+        ClearDistributionResponse::builder()
+            .removed_packages(removed.len() as u64)
+            .orphaned_pool_files(orphaned.len() as u64)
+            .release_deleted(req.delete_release)
+            .build(),
+    ))
+}
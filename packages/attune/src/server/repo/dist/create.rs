@@ -8,7 +8,14 @@ use tracing::instrument;
 
 use crate::{
     api::{ErrorResponse, TenantID},
-    server::{ServerState, repo::decode_repo_name},
+    apt::{Compression, DEFAULT_INDEX_COMPRESSION},
+    server::{
+        ServerState,
+        repo::{
+            decode_repo_name,
+            dist::{validate_component_name, validate_dist_name, validate_valid_for},
+        },
+    },
 };
 
 /// Request to create a new distribution (release) within a package repository.
@@ -67,6 +74,53 @@ pub struct CreateDistributionRequest {
     /// APT examples: "11.0" for Debian 11, "22.04" for Ubuntu 22.04 LTS
     #[builder(into)]
     pub version: Option<String>,
+
+    /// Compressed variants of each Packages index to publish alongside the
+    /// uncompressed index. APT clients prefer compressed indexes when the
+    /// Release file lists them. Defaults to `DEFAULT_INDEX_COMPRESSION`; pass
+    /// an empty list to only publish uncompressed indexes.
+    /// Example: `["gzip", "xz"]`
+    #[serde(default = "default_index_compression")]
+    #[builder(default = default_index_compression())]
+    pub index_compression: Vec<Compression>,
+
+    /// The component that packages are added to when no component is given.
+    /// If unset, packages are added to `main`.
+    #[serde(default)]
+    #[builder(into)]
+    pub default_component: Option<String>,
+
+    /// Publish only SHA256 checksums, omitting the legacy `MD5Sum` section
+    /// from the Release file and the MD5Sum and SHA1 by-hash copies of each
+    /// Packages index. Only enable this if every client supports SHA256.
+    #[serde(default)]
+    #[builder(default)]
+    pub sha256_only: bool,
+
+    /// If set, the Release file's `Valid-Until` field is set to this many
+    /// seconds after its `Date`, so that clients reject it once it's stale.
+    /// The Release file is only regenerated when packages change, so
+    /// distributions that change less often than this become invalid.
+    #[serde(default)]
+    pub valid_for_seconds: Option<u64>,
+
+    /// Set `NotAutomatic: yes` in the Release file, so that APT doesn't
+    /// install the distribution's packages unless they're pinned or requested
+    /// explicitly. Useful for `experimental` distributions.
+    #[serde(default)]
+    #[builder(default)]
+    pub not_automatic: bool,
+
+    /// Set `ButAutomaticUpgrades: yes` in the Release file, so that packages
+    /// installed from a `NotAutomatic` distribution are still upgraded from
+    /// it.
+    #[serde(default)]
+    #[builder(default)]
+    pub but_automatic_upgrades: bool,
+}
+
+fn default_index_compression() -> Vec<Compression> {
+    DEFAULT_INDEX_COMPRESSION.to_vec()
 }
 
 /// Response after successfully creating a new distribution.
@@ -98,6 +152,11 @@ pub async fn handler(
     Json(req): Json<CreateDistributionRequest>,
 ) -> Result<Json<CreateDistributionResponse>, ErrorResponse> {
     let repository_name = decode_repo_name(&repository_name)?;
+    validate_dist_name(&req.name)?;
+    if let Some(component) = &req.default_component {
+        validate_component_name(component)?;
+    }
+    let valid_for_seconds = req.valid_for_seconds.map(validate_valid_for).transpose()?;
 
     let mut tx = state.db.begin().await.unwrap();
     let repo = sqlx::query!(
@@ -152,11 +211,17 @@ pub async fn handler(
             version,
             suite,
             codename,
+            index_compression,
+            default_component,
+            sha256_only,
+            valid_for_seconds,
+            not_automatic,
+            but_automatic_upgrades,
             contents,
             created_at,
             updated_at
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, '', NOW(), NOW())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, '', NOW(), NOW())
         RETURNING id, distribution
         "#,
         repo.id,
@@ -167,6 +232,12 @@ pub async fn handler(
         req.version,
         req.suite,
         req.codename,
+        &req.index_compression as _,
+        req.default_component,
+        req.sha256_only,
+        valid_for_seconds,
+        req.not_automatic,
+        req.but_automatic_upgrades,
     )
     .fetch_one(&mut *tx)
     .await
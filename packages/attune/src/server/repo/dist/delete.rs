@@ -7,8 +7,10 @@ use tracing::instrument;
 
 use crate::{
     api::{ErrorResponse, TenantID},
+    apt::Compression,
     server::{
         ServerState,
+        pkg::canonical_key,
         repo::{decode_repo_name, dist::decode_dist_name},
     },
 };
@@ -60,9 +62,11 @@ pub async fn handler(
         SELECT
             c.name,
             i.architecture::text as "architecture!: String",
+            i.compression AS "compression: Compression",
             i.md5sum,
             i.sha1sum,
-            i.sha256sum
+            i.sha256sum,
+            i.sha512sum
         FROM debian_repository_release r
         JOIN debian_repository_component c ON c.release_id = r.id
         JOIN debian_repository_index_packages i ON i.component_id = c.id
@@ -107,7 +111,7 @@ pub async fn handler(
             SELECT 1 FROM debian_repository_component_package cp
             WHERE cp.package_id = p.id
         )
-        RETURNING p.id, p.s3_bucket, p.sha256sum
+        RETURNING p.id, p.s3_bucket, p.s3_key, p.sha256sum
         "#,
         tenant_id.0,
     )
@@ -131,21 +135,33 @@ pub async fn handler(
 
         // Deletes component metadata files.
         keys.extend(components.iter().flat_map(|record| {
-            // TODO(#94): When compressed indexes are implemented, add their deletion here.
             let prefix = format!("{}/{}/binary-{}", prefix, record.name, record.architecture);
             [
-                format!("{prefix}/Packages"),
+                format!(
+                    "{prefix}/Packages{}",
+                    record
+                        .compression
+                        .map(|c| c.extension())
+                        .unwrap_or_default()
+                ),
                 format!("{prefix}/by-hash/SHA256/{}", record.sha256sum),
                 format!("{prefix}/by-hash/SHA1/{}", record.sha1sum),
                 format!("{prefix}/by-hash/MD5Sum/{}", record.md5sum),
             ]
+            .into_iter()
+            .chain(
+                record
+                    .sha512sum
+                    .as_ref()
+                    .map(|sha512sum| format!("{prefix}/by-hash/SHA512/{sha512sum}")),
+            )
         }));
 
         // Deletes orphaned package files.
         keys.extend(
             orphaned
                 .iter()
-                .map(|pkg| format!("packages/{}", pkg.sha256sum)),
+                .map(|pkg| canonical_key(pkg.s3_key.as_deref(), &pkg.sha256sum)),
         );
 
         keys
@@ -8,9 +8,13 @@ use tracing::instrument;
 
 use crate::{
     api::{ErrorResponse, TenantID},
+    apt::Compression,
     server::{
         ServerState,
-        repo::{decode_repo_name, dist::decode_dist_name},
+        repo::{
+            decode_repo_name,
+            dist::{decode_dist_name, validate_component_name, validate_valid_for},
+        },
     },
 };
 
@@ -59,6 +63,52 @@ pub struct EditDistributionRequest {
     /// "jammy"
     #[builder(into)]
     pub codename: Option<String>,
+
+    /// Compressed variants of each Packages index to publish alongside the
+    /// uncompressed index. This replaces the configured set; pass an empty
+    /// list to publish only uncompressed indexes. Existing indexes are
+    /// republished with the new set the next time they change.
+    /// Example: `["gzip", "xz"]`
+    pub index_compression: Option<Vec<Compression>>,
+
+    /// Architectures to always list in the Release file, even when they have
+    /// no packages. This replaces the declared set; pass an empty list to only
+    /// list architectures that have packages.
+    /// Example: `["amd64", "arm64"]`
+    pub declared_architectures: Option<Vec<String>>,
+
+    /// Components to always list in the Release file, even when they have no
+    /// packages. This replaces the declared set; pass an empty list to only
+    /// list components that have packages.
+    /// Example: `["main", "contrib"]`
+    pub declared_components: Option<Vec<String>>,
+
+    /// The component that packages are added to when no component is given.
+    /// Example: `"main"`
+    #[builder(into)]
+    pub default_component: Option<String>,
+
+    /// Publish only SHA256 checksums, omitting the legacy `MD5Sum` section
+    /// from the Release file and the MD5Sum and SHA1 by-hash copies of each
+    /// Packages index. Existing indexes are republished the next time they
+    /// change.
+    pub sha256_only: Option<bool>,
+
+    /// Set the Release file's `Valid-Until` field to this many seconds after
+    /// its `Date`, so that clients reject it once it's stale. Pass `0` to stop
+    /// setting `Valid-Until`. The Release file is republished with the new
+    /// setting the next time it changes.
+    pub valid_for_seconds: Option<u64>,
+
+    /// Whether to set `NotAutomatic: yes` in the Release file, so that APT
+    /// doesn't install the distribution's packages unless they're pinned or
+    /// requested explicitly.
+    pub not_automatic: Option<bool>,
+
+    /// Whether to set `ButAutomaticUpgrades: yes` in the Release file, so that
+    /// packages installed from a `NotAutomatic` distribution are still
+    /// upgraded from it.
+    pub but_automatic_upgrades: Option<bool>,
 }
 
 impl EditDistributionRequest {
@@ -70,6 +120,14 @@ impl EditDistributionRequest {
             || self.version.is_some()
             || self.suite.is_some()
             || self.codename.is_some()
+            || self.index_compression.is_some()
+            || self.declared_architectures.is_some()
+            || self.declared_components.is_some()
+            || self.default_component.is_some()
+            || self.sha256_only.is_some()
+            || self.valid_for_seconds.is_some()
+            || self.not_automatic.is_some()
+            || self.but_automatic_upgrades.is_some()
     }
 }
 
@@ -99,6 +157,21 @@ pub async fn handler(
     let repository_name = decode_repo_name(&repository_name)?;
     let distribution_name = decode_dist_name(&distribution_name)?;
 
+    for component in req
+        .declared_components
+        .iter()
+        .flatten()
+        .chain(&req.default_component)
+    {
+        validate_component_name(component)?;
+    }
+    // Zero clears the validity period.
+    let valid_for_seconds = req
+        .valid_for_seconds
+        .filter(|seconds| *seconds != 0)
+        .map(validate_valid_for)
+        .transpose()?;
+
     let mut tx = state.db.begin().await.unwrap();
     let repo = sqlx::query!(
         r#"
@@ -122,7 +195,23 @@ pub async fn handler(
 
     let dist = sqlx::query!(
         r#"
-        SELECT id, distribution, description, origin, label, version, suite, codename
+        SELECT
+            id,
+            distribution,
+            description,
+            origin,
+            label,
+            version,
+            suite,
+            codename,
+            index_compression AS "index_compression!: Vec<Compression>",
+            declared_architectures::TEXT[] AS "declared_architectures!",
+            declared_components AS "declared_components!",
+            default_component,
+            sha256_only,
+            valid_for_seconds,
+            not_automatic,
+            but_automatic_upgrades
         FROM debian_repository_release
         WHERE repository_id = $1 AND distribution = $2
         "#,
@@ -151,6 +240,14 @@ pub async fn handler(
             version = COALESCE($6, version),
             suite = COALESCE($7, suite),
             codename = COALESCE($8, codename),
+            index_compression = $9,
+            declared_architectures = $10::TEXT[]::debian_repository_architecture[],
+            declared_components = $11,
+            default_component = COALESCE($12, default_component),
+            sha256_only = $13,
+            valid_for_seconds = $14,
+            not_automatic = $15,
+            but_automatic_upgrades = $16,
             updated_at = NOW()
         WHERE id = $1 AND repository_id = $2
         RETURNING id, distribution
@@ -163,6 +260,19 @@ pub async fn handler(
         req.version.or(dist.version),
         req.suite.or(Some(dist.suite)),
         req.codename.or(Some(dist.codename)),
+        &req.index_compression.unwrap_or(dist.index_compression) as _,
+        &req.declared_architectures
+            .unwrap_or(dist.declared_architectures),
+        &req.declared_components.unwrap_or(dist.declared_components),
+        req.default_component.or(dist.default_component),
+        req.sha256_only.unwrap_or(dist.sha256_only),
+        match req.valid_for_seconds {
+            Some(_) => valid_for_seconds,
+            None => dist.valid_for_seconds,
+        },
+        req.not_automatic.unwrap_or(dist.not_automatic),
+        req.but_automatic_upgrades
+            .unwrap_or(dist.but_automatic_upgrades),
     )
     .fetch_one(&mut *tx)
     .await
@@ -8,6 +8,7 @@ use tracing::instrument;
 
 use crate::{
     api::{ErrorResponse, TenantID},
+    apt::Compression,
     server::{ServerState, repo::decode_repo_name},
 };
 
@@ -16,7 +17,7 @@ use crate::{
 ///
 /// Contains both required identifiers (name, suite, codename) and optional
 /// metadata that provides additional context for package managers and users.
-#[derive(Serialize, Deserialize, Debug, Builder)]
+#[derive(Serialize, Deserialize, Debug, Default, Builder)]
 pub struct Distribution {
     /// Unique database identifier for this distribution.
     pub id: i64,
@@ -63,6 +64,63 @@ pub struct Distribution {
     /// "jammy"
     #[builder(into)]
     pub codename: String,
+
+    /// Compressed variants of each Packages index that are published
+    /// alongside the uncompressed index.
+    #[serde(default)]
+    #[builder(default)]
+    pub index_compression: Vec<Compression>,
+
+    /// Components that are declared or currently have packages in this
+    /// distribution, sorted by name. These are the components listed in the
+    /// Release file.
+    #[serde(default)]
+    #[builder(default)]
+    pub components: Vec<String>,
+
+    /// Architectures that are declared or currently have packages in this
+    /// distribution, sorted by name. These are the architectures listed in
+    /// the Release file.
+    #[serde(default)]
+    #[builder(default)]
+    pub architectures: Vec<String>,
+
+    /// Components that are always listed in the Release file, even when they
+    /// have no packages.
+    #[serde(default)]
+    #[builder(default)]
+    pub declared_components: Vec<String>,
+
+    /// Architectures that are always listed in the Release file, even when
+    /// they have no packages.
+    #[serde(default)]
+    #[builder(default)]
+    pub declared_architectures: Vec<String>,
+
+    /// The component that packages are added to when no component is given.
+    #[serde(default)]
+    #[builder(into)]
+    pub default_component: Option<String>,
+
+    /// Whether the Release file only lists SHA256 checksums.
+    #[serde(default)]
+    #[builder(default)]
+    pub sha256_only: bool,
+
+    /// How many seconds after its `Date` the Release file is valid for, if
+    /// it has a `Valid-Until` field.
+    #[serde(default)]
+    pub valid_for_seconds: Option<u64>,
+
+    /// Whether the Release file has `NotAutomatic: yes`.
+    #[serde(default)]
+    #[builder(default)]
+    pub not_automatic: bool,
+
+    /// Whether the Release file has `ButAutomaticUpgrades: yes`.
+    #[serde(default)]
+    #[builder(default)]
+    pub but_automatic_upgrades: bool,
 }
 
 /// Response containing all distributions within a repository.
@@ -108,17 +166,45 @@ pub async fn handler(
     let distributions = sqlx::query!(
         r#"
         SELECT
-            id,
-            distribution,
-            description,
-            origin,
-            label,
-            version,
-            suite,
-            codename
-        FROM debian_repository_release
-        WHERE repository_id = $1
-        ORDER BY distribution
+            r.id,
+            r.distribution,
+            r.description,
+            r.origin,
+            r.label,
+            r.version,
+            r.suite,
+            r.codename,
+            r.index_compression AS "index_compression!: Vec<Compression>",
+            r.declared_components AS "declared_components!",
+            r.declared_architectures::TEXT[] AS "declared_architectures!",
+            r.default_component,
+            r.sha256_only,
+            r.valid_for_seconds,
+            r.not_automatic,
+            r.but_automatic_upgrades,
+            ARRAY(
+                SELECT c.name
+                FROM
+                    debian_repository_component c
+                    JOIN debian_repository_index_packages i ON i.component_id = c.id
+                WHERE c.release_id = r.id
+                UNION
+                SELECT unnest(r.declared_components)
+                ORDER BY 1
+            ) AS "components!",
+            ARRAY(
+                SELECT i.architecture::TEXT
+                FROM
+                    debian_repository_component c
+                    JOIN debian_repository_index_packages i ON i.component_id = c.id
+                WHERE c.release_id = r.id
+                UNION
+                SELECT unnest(r.declared_architectures)::TEXT
+                ORDER BY 1
+            ) AS "architectures!"
+        FROM debian_repository_release r
+        WHERE r.repository_id = $1
+        ORDER BY r.distribution
         "#,
         repo.id,
     )
@@ -136,6 +222,16 @@ pub async fn handler(
             .maybe_origin(row.origin)
             .maybe_label(row.label)
             .maybe_version(row.version)
+            .index_compression(row.index_compression)
+            .components(row.components)
+            .architectures(row.architectures)
+            .declared_components(row.declared_components)
+            .declared_architectures(row.declared_architectures)
+            .maybe_default_component(row.default_component)
+            .sha256_only(row.sha256_only)
+            .maybe_valid_for_seconds(row.valid_for_seconds.map(|seconds| seconds as u64))
+            .not_automatic(row.not_automatic)
+            .but_automatic_upgrades(row.but_automatic_upgrades)
             .build()
     })
     .collect();
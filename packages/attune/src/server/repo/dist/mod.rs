@@ -1,21 +1,117 @@
 use axum::http::StatusCode;
+use lazy_regex::lazy_regex;
 use percent_encoding::percent_decode_str;
 
 use crate::api::ErrorResponse;
 
+pub mod clear;
 pub mod create;
 pub mod delete;
 pub mod edit;
 pub mod list;
+pub mod packages;
+pub mod release;
+
+/// The component that packages are added to when neither the request nor the
+/// distribution specifies one.
+pub const DEFAULT_COMPONENT: &str = "main";
+
+/// The name of the distribution that packages for `distribution` are staged
+/// in before being promoted.
+pub fn staging_distribution(distribution: &str) -> String {
+    format!("{distribution}-staging")
+}
+
+pub(super) fn validate_component_name(component: &str) -> Result<(), ErrorResponse> {
+    if lazy_regex!(r"^[a-zA-Z0-9_-]+$").is_match(component) {
+        return Ok(());
+    }
+    Err(ErrorResponse::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .error("INVALID_COMPONENT_NAME")
+        .message(format!(
+            "component name {component:?} must contain only letters, numbers, underscores, and hyphens"
+        ))
+        .build())
+}
+
+/// Check a distribution name. Distribution names are part of the S3 keys and
+/// URLs of their indexes, so like component names, they may only contain
+/// letters, numbers, underscores, and hyphens, except that slashes may separate
+/// them into parts, as in `stable/updates`.
+pub(super) fn validate_dist_name(distribution: &str) -> Result<(), ErrorResponse> {
+    if lazy_regex!(r"^[a-zA-Z0-9_-]+(/[a-zA-Z0-9_-]+)*$").is_match(distribution) {
+        return Ok(());
+    }
+    Err(ErrorResponse::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .error("INVALID_DIST_NAME")
+        .message(format!(
+            "distribution name {distribution:?} must contain only letters, numbers, underscores, and hyphens, optionally separated by slashes"
+        ))
+        .build())
+}
+
+/// The longest that a Release file can be valid for.
+const MAX_VALID_FOR_SECONDS: u64 = 10 * 366 * 24 * 60 * 60;
+
+/// Check how long Release files are valid for, returning it as it's stored in
+/// the database.
+fn validate_valid_for(seconds: u64) -> Result<i64, ErrorResponse> {
+    if (1..=MAX_VALID_FOR_SECONDS).contains(&seconds) {
+        return Ok(seconds as i64);
+    }
+    Err(ErrorResponse::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .error("INVALID_VALID_FOR")
+        .message(format!(
+            "Release files must be valid for between 1 second and {} days",
+            MAX_VALID_FOR_SECONDS / (24 * 60 * 60)
+        ))
+        .build())
+}
 
 fn decode_dist_name(name: &str) -> Result<String, ErrorResponse> {
     // The distribution name in the path is percent-encoded.
-    match percent_decode_str(name).decode_utf8() {
-        Ok(name) => Ok(name.to_string()),
-        Err(err) => Err(ErrorResponse::new(
-            StatusCode::BAD_REQUEST,
-            "INVALID_DIST_NAME".to_string(),
-            format!("Invalid distribution name: could not percent decode: {err}"),
-        )),
+    let name = match percent_decode_str(name).decode_utf8() {
+        Ok(name) => name.to_string(),
+        Err(err) => {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_DIST_NAME".to_string(),
+                format!("Invalid distribution name: could not percent decode: {err}"),
+            ));
+        }
+    };
+    validate_dist_name(&name)?;
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dist_names() {
+        for valid in ["stable", "bookworm-backports", "my_dist2", "stable/updates"] {
+            assert!(validate_dist_name(valid).is_ok(), "{valid:?} is valid");
+        }
+        for invalid in [
+            "",
+            "with space",
+            "dot.dot",
+            "/stable",
+            "stable/",
+            "stable//updates",
+            "../stable",
+        ] {
+            let error = validate_dist_name(invalid).unwrap_err();
+            assert_eq!(error.error, "INVALID_DIST_NAME", "{invalid:?} is invalid");
+        }
+        assert_eq!(
+            decode_dist_name("stable%2Fupdates").unwrap(),
+            "stable/updates"
+        );
+        assert!(decode_dist_name("stable%20updates").is_err());
     }
 }
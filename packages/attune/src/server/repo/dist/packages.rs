@@ -0,0 +1,212 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{
+            decode_repo_name,
+            dist::decode_dist_name,
+            index::{ContentsEncoding, decode_contents},
+        },
+    },
+};
+
+/// The stored uncompressed `Packages` index of a component and architecture.
+///
+/// This is the same content that is published to the repository, so it can be
+/// used to inspect index state without access to the repository's storage.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PackagesFileResponse {
+    /// The contents of the `Packages` file.
+    pub contents: String,
+    pub size: i64,
+    pub md5sum: String,
+    pub sha1sum: String,
+    pub sha256sum: String,
+    /// `None` if the index hasn't been published since SHA512 sums were
+    /// recorded.
+    #[serde(default)]
+    pub sha512sum: Option<String>,
+}
+
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path((repository_name, distribution_name, component_name, architecture)): Path<(
+        String,
+        String,
+        String,
+        String,
+    )>,
+) -> Result<Json<PackagesFileResponse>, ErrorResponse> {
+    let repository_name = decode_repo_name(&repository_name)?;
+    let distribution_name = decode_dist_name(&distribution_name)?;
+
+    let mut tx = state.db.begin().await.unwrap();
+    let index = sqlx::query!(
+        r#"
+        SELECT
+            debian_repository_index_packages.contents,
+            debian_repository_index_packages.contents_encoding AS "contents_encoding: ContentsEncoding",
+            debian_repository_index_packages.size,
+            debian_repository_index_packages.md5sum,
+            debian_repository_index_packages.sha1sum,
+            debian_repository_index_packages.sha256sum,
+            debian_repository_index_packages.sha512sum
+        FROM
+            debian_repository
+            JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
+            JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository_index_packages ON debian_repository_index_packages.component_id = debian_repository_component.id
+        WHERE
+            debian_repository.tenant_id = $1
+            AND debian_repository.name = $2
+            AND debian_repository_release.distribution = $3
+            AND debian_repository_component.name = $4
+            AND debian_repository_index_packages.architecture::TEXT = $5
+            AND debian_repository_index_packages.compression IS NULL
+        "#,
+        tenant_id.0,
+        repository_name,
+        distribution_name,
+        component_name,
+        architecture,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::builder()
+            .status(axum::http::StatusCode::NOT_FOUND)
+            .error("INDEX_NOT_FOUND")
+            .message("Packages index not found")
+            .build()
+    })?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+    let contents = decode_contents(index.contents_encoding, index.contents)?;
+
+    Ok(Json(PackagesFileResponse {
+        contents: String::from_utf8_lossy(&contents).into_owned(),
+        size: index.size,
+        md5sum: index.md5sum,
+        sha1sum: index.sha1sum,
+        sha256sum: index.sha256sum,
+        sha512sum: index.sha512sum,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        server::repo::dist::release::ReleaseFileResponse,
+        testing::{AttuneTestServer, AttuneTestServerConfig},
+    };
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn show_stored_indexes(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "show_stored_indexes";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        let release = sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_release (
+                repository_id,
+                distribution,
+                suite,
+                codename,
+                contents,
+                clearsigned,
+                created_at,
+                updated_at
+            )
+            SELECT id, 'stable', 'stable', 'bookworm', 'Suite: stable', 'signed', NOW(), NOW()
+            FROM debian_repository
+            WHERE tenant_id = $1 AND name = $2
+            RETURNING id
+            "#,
+            tenant_id.0,
+            REPO_NAME,
+        )
+        .fetch_one(&server.db)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            WITH component AS (
+                INSERT INTO debian_repository_component (release_id, name, created_at, updated_at)
+                VALUES ($1, 'main', NOW(), NOW())
+                RETURNING id
+            )
+            INSERT INTO debian_repository_index_packages (
+                component_id,
+                architecture,
+                compression,
+                size,
+                contents,
+                md5sum,
+                sha1sum,
+                sha256sum,
+                created_at,
+                updated_at
+            )
+            SELECT id, 'amd64', NULL, 12, convert_to('Package: foo', 'UTF8'), 'md5', 'sha1', 'sha256', NOW(), NOW()
+            FROM component
+            "#,
+            release.id,
+        )
+        .execute(&server.db)
+        .await
+        .unwrap();
+
+        let response = server
+            .http
+            .get(&format!(
+                "/api/v0/repositories/{REPO_NAME}/distributions/stable/release"
+            ))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_ok();
+        let response = response.json::<ReleaseFileResponse>();
+        assert_eq!(response.contents, "Suite: stable");
+        assert_eq!(response.clearsigned.as_deref(), Some("signed"));
+        assert_eq!(response.detached, None);
+
+        let response = server
+            .http
+            .get(&format!(
+                "/api/v0/repositories/{REPO_NAME}/distributions/stable/components/main/binary-amd64/packages"
+            ))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_ok();
+        let response = response.json::<PackagesFileResponse>();
+        assert_eq!(response.contents, "Package: foo");
+        assert_eq!(response.sha256sum, "sha256");
+
+        let response = server
+            .http
+            .get(&format!(
+                "/api/v0/repositories/{REPO_NAME}/distributions/stable/components/main/binary-arm64/packages"
+            ))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_not_found();
+        assert_eq!(response.json::<ErrorResponse>().error, "INDEX_NOT_FOUND");
+    }
+}
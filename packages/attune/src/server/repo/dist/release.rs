@@ -0,0 +1,85 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{decode_repo_name, dist::decode_dist_name},
+    },
+};
+
+/// The stored `Release` file of a distribution.
+///
+/// This is the same content that is published to the repository, so it can be
+/// used to inspect index state without access to the repository's storage.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReleaseFileResponse {
+    /// The contents of the `Release` file. This is empty if the distribution
+    /// has never been published.
+    pub contents: String,
+    /// The contents of the `InRelease` file, if the distribution is signed.
+    pub clearsigned: Option<String>,
+    /// The contents of the `Release.gpg` file, if the distribution is signed.
+    pub detached: Option<String>,
+}
+
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path((repository_name, distribution_name)): Path<(String, String)>,
+) -> Result<Json<ReleaseFileResponse>, ErrorResponse> {
+    let repository_name = decode_repo_name(&repository_name)?;
+    let distribution_name = decode_dist_name(&distribution_name)?;
+
+    let mut tx = state.db.begin().await.unwrap();
+    let repo = sqlx::query!(
+        r#"
+        SELECT id
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        repository_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::builder()
+            .status(axum::http::StatusCode::NOT_FOUND)
+            .error("REPO_NOT_FOUND")
+            .message("repository not found")
+            .build()
+    })?;
+
+    let release = sqlx::query_as!(
+        ReleaseFileResponse,
+        r#"
+        SELECT contents, clearsigned, detached
+        FROM debian_repository_release
+        WHERE repository_id = $1 AND distribution = $2
+        "#,
+        repo.id,
+        distribution_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::builder()
+            .status(axum::http::StatusCode::NOT_FOUND)
+            .error("DISTRIBUTION_NOT_FOUND")
+            .message("distribution not found")
+            .build()
+    })?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    Ok(Json(release))
+}
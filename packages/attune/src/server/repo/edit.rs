@@ -5,20 +5,52 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
+use url::Url;
 
 use crate::{
     api::{ErrorResponse, TenantID},
-    server::{ServerState, repo::decode_repo_name},
+    server::{
+        ServerState,
+        repo::{decode_repo_name, validate_allowlists},
+        webhook::generate_secret,
+    },
 };
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Repository {
     pub name: String,
+    #[serde(default)]
+    pub allowed_architectures: Vec<String>,
+    #[serde(default)]
+    pub allowed_components: Vec<String>,
+    /// The URL that notifications of published changes are sent to, if any.
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// The secret that notifications are signed with. This is only returned
+    /// when the webhook URL is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_secret: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EditRepositoryRequest {
     pub new_name: Option<String>,
+    /// If set, replaces the architectures that packages can be added in. Pass
+    /// an empty list to allow every architecture. Packages that are already
+    /// published aren't affected.
+    #[serde(default)]
+    pub allowed_architectures: Option<Vec<String>>,
+    /// If set, replaces the components that packages can be added to. Pass an
+    /// empty list to allow every component.
+    #[serde(default)]
+    pub allowed_components: Option<Vec<String>>,
+    /// If set, notifications of published changes are sent to this URL. Pass
+    /// an empty string to stop sending them.
+    ///
+    /// The first time a webhook URL is set, a secret to sign notifications
+    /// with is generated. See [`crate::server::webhook`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -36,24 +68,60 @@ pub async fn handler(
 ) -> Result<Json<EditRepositoryResponse>, ErrorResponse> {
     // The repository name in the path is percent-encoded.
     let name = decode_repo_name(&name)?;
+    let allowed_architectures = match &req.allowed_architectures {
+        Some(architectures) => Some(validate_allowlists(&state.db, architectures, &[]).await?),
+        None => None,
+    };
+    if let Some(components) = &req.allowed_components {
+        validate_allowlists(&state.db, &[], components).await?;
+    }
+    if let Some(url) = &req.webhook_url {
+        validate_webhook_url(url)?;
+    }
 
     let updated = sqlx::query!(
         r#"
         UPDATE debian_repository
-        SET name = $3
+        SET
+            name = $3,
+            allowed_architectures = COALESCE($4::TEXT[]::debian_repository_architecture[], allowed_architectures),
+            allowed_components = COALESCE($5, allowed_components),
+            webhook_url = CASE WHEN $6::TEXT IS NULL THEN webhook_url ELSE NULLIF($6, '') END,
+            webhook_secret = CASE
+                WHEN $6::TEXT IS NULL THEN webhook_secret
+                WHEN $6 = '' THEN NULL
+                ELSE COALESCE(webhook_secret, $7)
+            END,
+            updated_at = NOW()
         WHERE tenant_id = $1 AND name = $2
-        RETURNING id, name
+        RETURNING
+            id,
+            name,
+            allowed_architectures::TEXT[] AS "allowed_architectures!",
+            allowed_components AS "allowed_components!",
+            webhook_url,
+            webhook_secret
         "#,
         tenant_id.0,
         &name,
         req.new_name.unwrap_or(name.to_string()),
+        allowed_architectures.as_deref(),
+        req.allowed_components.as_deref(),
+        req.webhook_url.as_deref(),
+        generate_secret(),
     )
     .fetch_optional(&state.db)
     .await
     .map_err(ErrorResponse::from)?;
     match updated {
         Some(updated) => Ok(Json(EditRepositoryResponse {
-            result: Repository { name: updated.name },
+            result: Repository {
+                name: updated.name,
+                allowed_architectures: updated.allowed_architectures,
+                allowed_components: updated.allowed_components,
+                webhook_secret: updated.webhook_secret.filter(|_| req.webhook_url.is_some()),
+                webhook_url: updated.webhook_url,
+            },
         })),
         None => Err(ErrorResponse::new(
             StatusCode::NOT_FOUND,
@@ -62,3 +130,17 @@ pub async fn handler(
         )),
     }
 }
+
+fn validate_webhook_url(url: &str) -> Result<(), ErrorResponse> {
+    if url.is_empty() {
+        return Ok(());
+    }
+    match Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+        _ => Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_WEBHOOK_URL",
+            "webhook URL must be an http or https URL",
+        )),
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{decode_repo_name, usage::query_usage},
+    },
+};
+
+/// The published objects of a repository, which together form a tree that APT
+/// can consume directly.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepositoryObjectsResponse {
+    /// The paths of the objects relative to the repository root, such as
+    /// `dists/stable/InRelease` or `pool/main/f/foo/foo_1.0_amd64.deb`, in
+    /// sorted order.
+    pub paths: Vec<String>,
+}
+
+/// List the objects that the repository publishes.
+///
+/// The list is computed from the database, so objects under the repository's
+/// S3 prefix that no distribution refers to (such as leftovers of interrupted
+/// changes) are not included.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn list_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repository_name): Path<String>,
+) -> Result<Json<RepositoryObjectsResponse>, ErrorResponse> {
+    // The repository name in the path is percent-encoded.
+    let repository_name = decode_repo_name(&repository_name)?;
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    let repo = query_repo(&mut tx, tenant_id, &repository_name).await?;
+    let (_, expected_keys) = query_usage(&mut tx, &tenant_id, &repository_name, repo.id).await?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    let prefix = format!("{}/", repo.s3_prefix);
+    let mut paths = expected_keys
+        .into_iter()
+        .filter_map(|key| key.strip_prefix(&prefix).map(String::from))
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(Json(RepositoryObjectsResponse { paths }))
+}
+
+/// Download one object of the repository, by its path relative to the
+/// repository root.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn object_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path((repository_name, path)): Path<(String, String)>,
+) -> Result<impl IntoResponse, ErrorResponse> {
+    // The repository name in the path is percent-encoded.
+    let repository_name = decode_repo_name(&repository_name)?;
+    if path
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_PATH",
+            format!("invalid object path: {path:?}"),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    let repo = query_repo(&mut tx, tenant_id, &repository_name).await?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    let object = state
+        .s3
+        .get_object()
+        .bucket(&repo.s3_bucket)
+        .key(format!("{}/{path}", repo.s3_prefix))
+        .send()
+        .await
+        .map_err(|err| {
+            let err = err.into_service_error();
+            if err.is_no_such_key() {
+                ErrorResponse::new(
+                    StatusCode::NOT_FOUND,
+                    "OBJECT_NOT_FOUND",
+                    format!("object {path:?} not found"),
+                )
+            } else {
+                ErrorResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "S3_ERROR",
+                    format!("could not read object {path:?}: {err}"),
+                )
+            }
+        })?;
+
+    // Stream the object rather than buffering it, since pool objects can be
+    // large.
+    let body = futures_util::stream::unfold(object.body, |mut body| async move {
+        body.next().await.map(|chunk| (chunk, body))
+    });
+    Ok((
+        [(header::CONTENT_TYPE, "application/octet-stream")],
+        Body::from_stream(body),
+    ))
+}
+
+struct Repository {
+    id: i64,
+    s3_bucket: String,
+    s3_prefix: String,
+}
+
+async fn query_repo(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tenant_id: TenantID,
+    repository_name: &str,
+) -> Result<Repository, ErrorResponse> {
+    sqlx::query_as!(
+        Repository,
+        r#"
+        SELECT id, s3_bucket, s3_prefix
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        repository_name,
+    )
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND".to_string(),
+            "repository not found".to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::test_server_state;
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn list_published_objects(pool: sqlx::PgPool) {
+        // The fixture's checksums are placeholders, but the repository state
+        // decodes them as hex.
+        sqlx::query!(
+            "UPDATE debian_repository_index_packages SET sha256sum = encode(sha256(contents), 'hex')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "UPDATE debian_repository_package SET sha256sum = encode(sha256(sha256sum::BYTEA), 'hex')"
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let Json(objects) = list_handler(
+            State(test_server_state(pool)),
+            TenantID(1),
+            Path(String::from("test-multi-arch")),
+        )
+        .await
+        .unwrap();
+        // Paths are relative to the repository root, so the tree can be
+        // served as-is.
+        assert!(
+            objects
+                .paths
+                .iter()
+                .all(|path| path.starts_with("dists/stable/") || path.starts_with("pool/main/"))
+        );
+        assert!(objects.paths.is_sorted());
+        assert!(objects.paths.contains(&String::from(
+            "pool/main/t/test-package/test-package_1.0.0_amd64.deb"
+        )));
+        assert!(
+            objects
+                .paths
+                .contains(&String::from("dists/stable/Release"))
+        );
+    }
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn reject_relative_object_paths(pool: sqlx::PgPool) {
+        let err = object_handler(
+            State(test_server_state(pool)),
+            TenantID(1),
+            Path((
+                String::from("test-multi-arch"),
+                String::from("dists/../../other-repo/dists/stable/Release"),
+            )),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.error, "INVALID_PATH");
+    }
+}
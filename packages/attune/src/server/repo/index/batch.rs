@@ -0,0 +1,382 @@
+//! Changes to several packages in a distribution that are signed together.
+//!
+//! A batch of changes produces a single Release file, so adding many packages
+//! takes one signature instead of one per package. The changes are applied in
+//! order, each to the state left by the previous one, in a single transaction:
+//! either all of them are published or none are.
+
+use std::time::Instant;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use time::OffsetDateTime;
+use tracing::{debug, instrument};
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{
+            decode_repo_name,
+            dist::{validate_component_name, validate_dist_name},
+            index::{
+                ChangedPackagesIndex, ContentsEncoding, PackageChange, PackageChangeAction,
+                PackageChangeResult, check_clock_skew,
+                generate::GenerateIndexResponse,
+                generate_release_file_with_change,
+                sign::{
+                    PreviousByHashIndexes, Repository, SignIndexRequest, SignIndexResponse,
+                    fingerprints, invalidate_cdn, notify_webhook, publish_indexes,
+                    save_change_to_db, update_pool, verify_detached_signature, verify_public_keys,
+                },
+                validate_release_ts,
+            },
+            validate_repo_name_matches,
+        },
+    },
+};
+
+/// The most changes that can be made in one batch.
+pub const MAX_BATCH_CHANGES: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GenerateBatchIndexRequest {
+    /// The changes to make, in order. They must all change the same component
+    /// of the same distribution.
+    pub changes: Vec<PackageChange>,
+    /// The timestamp to date the Release file with. Defaults to now.
+    #[serde(default)]
+    pub release_ts: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignBatchIndexRequest {
+    pub changes: Vec<PackageChange>,
+    pub release_ts: OffsetDateTime,
+    /// The `InRelease` file, signed over the Release file that results from
+    /// every change.
+    pub clearsigned: String,
+    /// The `Release.gpg` file.
+    pub detachsigned: String,
+    /// The armored public keys that made the signatures.
+    pub public_key_cert: String,
+}
+
+/// Generate the Release file that results from a batch of changes.
+#[axum::debug_handler]
+#[instrument(skip(state, req))]
+pub async fn generate_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repo_name): Path<String>,
+    // FIXME: This is a GET request with a body.
+    Json(req): Json<GenerateBatchIndexRequest>,
+) -> Result<Json<GenerateIndexResponse>, ErrorResponse> {
+    let repo_name = decode_repo_name(&repo_name)?;
+    validate_batch(&repo_name, &req.changes)?;
+    let release_ts = match req.release_ts {
+        Some(release_ts) => {
+            validate_release_ts(release_ts)?;
+            release_ts
+        }
+        None => OffsetDateTime::now_utc(),
+    };
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    check_clock_skew(&mut tx).await?;
+
+    // Each change is generated from the state left by the previous one, so
+    // the changes have to be saved to find the final Release file. They're
+    // rolled back, since nothing has been signed yet.
+    let requests = sign_requests(req.changes, release_ts, Signatures::default());
+    let applied = replay_changes(
+        &mut tx,
+        &tenant_id,
+        &requests,
+        state.index_contents_encoding,
+    )
+    .await?;
+    let release = applied
+        .last()
+        .expect("batch is not empty")
+        .0
+        .release_file
+        .contents
+        .clone();
+    tx.rollback().await.map_err(ErrorResponse::from)?;
+
+    Ok(Json(GenerateIndexResponse {
+        release,
+        release_ts,
+    }))
+}
+
+/// Apply a signed batch of changes, and publish the resulting indexes.
+#[axum::debug_handler]
+#[instrument(skip(state, req))]
+pub async fn sign_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repo_name): Path<String>,
+    Json(req): Json<SignBatchIndexRequest>,
+) -> Result<Json<SignIndexResponse>, ErrorResponse> {
+    debug!(?req, "signing batch index");
+    let start = Instant::now();
+    let repo_name = decode_repo_name(&repo_name)?;
+    validate_batch(&repo_name, &req.changes)?;
+    validate_release_ts(req.release_ts)?;
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    check_clock_skew(&mut tx).await?;
+    let repo = Repository::query(&mut tx, &tenant_id, &repo_name).await?;
+
+    let signatures = Signatures {
+        clearsigned: req.clearsigned,
+        detachsigned: req.detachsigned,
+        public_key_cert: req.public_key_cert,
+    };
+    let requests = sign_requests(req.changes, req.release_ts, signatures);
+    let last = requests.last().expect("batch is not empty");
+    let public_keys = verify_public_keys(last)?;
+    let applied = replay_changes(
+        &mut tx,
+        &tenant_id,
+        &requests,
+        state.index_contents_encoding,
+    )
+    .await?;
+
+    // Only the final Release file is signed, and only it is published.
+    let (last_result, _) = applied.last().expect("batch is not empty");
+    verify_detached_signature(last, &public_keys, &last_result.release_file.contents)?;
+
+    // As with single changes, a failed commit is most likely a concurrent
+    // index change, which the client retries.
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    let (results, previous_by_hash_indexes): (Vec<_>, Vec<_>) = applied.into_iter().unzip();
+    for (req, result) in requests.iter().zip(&results) {
+        update_pool(
+            state.object_store.as_ref(),
+            &state.s3_concurrency,
+            &repo,
+            req,
+            result,
+        )
+        .await?;
+    }
+
+    // Publish the final state of each changed Packages index. Intermediate
+    // states were never published, so deleting their by-hash files along with
+    // those of the original indexes is harmless.
+    let mut changed_packages_indexes = Vec::<&ChangedPackagesIndex>::new();
+    for changed in results
+        .iter()
+        .rev()
+        .flat_map(|result| &result.changed_packages_indexes)
+    {
+        let architecture = &changed.packages_index.meta.architecture;
+        if !changed_packages_indexes
+            .iter()
+            .any(|published| &published.packages_index.meta.architecture == architecture)
+        {
+            changed_packages_indexes.push(changed);
+        }
+    }
+    let last_result = results.last().expect("batch is not empty");
+    publish_indexes(
+        &state.db,
+        state.object_store.as_ref(),
+        &state.s3_concurrency,
+        state.index_deletion_grace,
+        &repo,
+        last,
+        &changed_packages_indexes,
+        &last_result.release_file,
+        previous_by_hash_indexes.into_iter().flatten().collect(),
+    )
+    .await;
+    invalidate_cdn(state.cdn.as_ref(), &repo, last, &changed_packages_indexes).await;
+    for (req, result) in requests.iter().zip(&results) {
+        notify_webhook(&repo, req, Some(&result.changed_package.package));
+    }
+    if let Some(metrics) = &state.metrics {
+        metrics.record_index_sign(true, start.elapsed());
+    }
+
+    Ok(Json(SignIndexResponse {
+        fingerprints: fingerprints(&last.public_key_cert)?,
+    }))
+}
+
+/// Check that a batch is non-empty, and that all of its changes add or remove
+/// packages in the same component of the same distribution.
+fn validate_batch(repo_name: &str, changes: &[PackageChange]) -> Result<(), ErrorResponse> {
+    let Some(first) = changes.first() else {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "EMPTY_BATCH",
+            "a batch must contain at least one change",
+        ));
+    };
+    if changes.len() > MAX_BATCH_CHANGES {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "BATCH_TOO_LARGE",
+            format!("a batch can contain at most {MAX_BATCH_CHANGES} changes"),
+        ));
+    }
+    validate_repo_name_matches(repo_name, &first.repository)?;
+    validate_dist_name(&first.distribution)?;
+    validate_component_name(&first.component)?;
+    for change in changes {
+        if change.repository != first.repository
+            || change.distribution != first.distribution
+            || change.component != first.component
+        {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "BATCH_SCOPE_MISMATCH",
+                "every change in a batch must be to the same repository, distribution, and component",
+            ));
+        }
+        if let PackageChangeAction::Resign = change.action {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_CHANGE",
+                "re-signing can't be part of a batch",
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Signatures {
+    clearsigned: String,
+    detachsigned: String,
+    public_key_cert: String,
+}
+
+/// Build a sign request for each change, all carrying the batch's signatures,
+/// so that each change can be saved in the same way as a single change.
+fn sign_requests(
+    changes: Vec<PackageChange>,
+    release_ts: OffsetDateTime,
+    signatures: Signatures,
+) -> Vec<SignIndexRequest> {
+    changes
+        .into_iter()
+        .map(|change| SignIndexRequest {
+            change,
+            release_ts,
+            clearsigned: signatures.clearsigned.clone(),
+            detachsigned: signatures.detachsigned.clone(),
+            public_key_cert: signatures.public_key_cert.clone(),
+        })
+        .collect()
+}
+
+/// Generate and save each change in order, returning the result of each and
+/// the hashes of the Packages indexes it replaced.
+async fn replay_changes(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &TenantID,
+    requests: &[SignIndexRequest],
+    contents_encoding: Option<ContentsEncoding>,
+) -> Result<Vec<(PackageChangeResult, Vec<PreviousByHashIndexes>)>, ErrorResponse> {
+    let mut applied = Vec::with_capacity(requests.len());
+    for req in requests {
+        let result =
+            generate_release_file_with_change(tx, tenant_id, &req.change, req.release_ts).await?;
+        let previous = save_change_to_db(tx, tenant_id, req, &result, contents_encoding).await?;
+        applied.push((result, previous));
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{AttuneTestServer, AttuneTestServerConfig};
+
+    fn remove(architecture: &str, component: &str) -> PackageChange {
+        PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: component.to_string(),
+            architecture: None,
+            action: PackageChangeAction::Remove {
+                name: String::from("test-package"),
+                version: String::from("1.0.0"),
+                architecture: architecture.to_string(),
+            },
+        }
+    }
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn generate_batch(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool.clone(),
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        let generate = async |changes: Vec<PackageChange>| {
+            server
+                .http
+                .get("/api/v0/repositories/test-multi-arch/index/batch")
+                .add_header("authorization", "Bearer test-api-token")
+                .json(&GenerateBatchIndexRequest {
+                    changes,
+                    release_ts: None,
+                })
+                .await
+        };
+
+        // The Release file reflects every change, so removing both packages
+        // leaves no Packages indexes.
+        let response = generate(vec![remove("amd64", "main"), remove("arm64", "main")]).await;
+        response.assert_status_ok();
+        let release = response.json::<GenerateIndexResponse>().release;
+        assert!(!release.contains("binary-amd64"), "{release}");
+        assert!(!release.contains("binary-arm64"), "{release}");
+
+        // Generating doesn't save the changes.
+        let remaining = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) AS "count!" FROM debian_repository_component_package"#
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(remaining, 2);
+
+        // Every change must be to the same component.
+        let response = generate(vec![remove("amd64", "main"), remove("arm64", "contrib")]).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json::<ErrorResponse>().error,
+            "BATCH_SCOPE_MISMATCH"
+        );
+
+        // Later changes see the state left by earlier ones.
+        let response = generate(vec![remove("amd64", "main"), remove("amd64", "main")]).await;
+        response.assert_status(StatusCode::NOT_FOUND);
+    }
+}
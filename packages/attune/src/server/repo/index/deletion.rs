@@ -0,0 +1,282 @@
+//! Deferred deletion of replaced index files.
+//!
+//! Publishing a change replaces some of a distribution's Packages indexes, and
+//! the new Release file no longer refers to the by-hash copies of the replaced
+//! indexes. Clients that fetched the previous Release file moments earlier may
+//! still request them, though, so instead of deleting them right away, they
+//! are recorded as pending deletions and deleted by [`sweep`] once they have
+//! been pending for a grace period.
+
+use std::{sync::Arc, time::Duration};
+
+use axum::http::StatusCode;
+use itertools::Itertools as _;
+use sqlx::PgPool;
+use tracing::{debug, error, info};
+
+use crate::{
+    api::ErrorResponse,
+    server::{object_store::ObjectStore, s3_concurrency::S3Concurrency},
+};
+
+/// How long replaced index files are kept by default.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(10 * 60);
+
+/// How often the background sweep looks for expired deletions.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The maximum number of files deleted by each sweep, which is also the most
+/// that a single `delete_objects` call accepts.
+const SWEEP_BATCH_SIZE: i64 = 1000;
+
+/// Stop any pending deletion of files that are about to be published again.
+///
+/// This happens when a change restores an index to an earlier state, e.g. when
+/// a package is added and then removed. It must happen _before_ the files are
+/// uploaded: a sweep that is deleting them holds their rows locked until the
+/// deletion is done, so this waits for it instead of racing it.
+pub(super) async fn cancel(db: &PgPool, s3_bucket: &str, keys: &[String]) {
+    if keys.is_empty() {
+        return;
+    }
+    let cancelled = sqlx::query!(
+        r#"
+        DELETE FROM pending_index_deletion
+        WHERE s3_bucket = $1 AND s3_key = ANY($2)
+        "#,
+        s3_bucket,
+        keys,
+    )
+    .execute(db)
+    .await;
+    match cancelled {
+        Ok(result) if result.rows_affected() > 0 => {
+            debug!(
+                cancelled = result.rows_affected(),
+                "cancelled pending index deletions"
+            );
+        }
+        Ok(_) => {}
+        Err(err) => error!(?err, "could not cancel pending index deletions"),
+    }
+}
+
+/// Delete files that are no longer referenced by their distribution's Release
+/// file once `grace` has passed. With no grace period, they are deleted right
+/// away.
+pub(super) async fn defer(
+    db: &PgPool,
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    grace: Duration,
+    repository_id: i64,
+    s3_bucket: &str,
+    keys: Vec<String>,
+) {
+    if keys.is_empty() {
+        return;
+    }
+    if grace.is_zero() {
+        // S3 only allows up to 1000 objects per delete request, but we're
+        // dealing with low ones of keys.
+        let deletion = concurrency
+            .run(store.delete_objects(s3_bucket, &keys))
+            .await;
+        if let Err(err) = deletion {
+            error!("Failed to delete objects: {err:?}");
+        }
+        return;
+    }
+
+    // Files that are already pending keep their original deadline.
+    let deferred = sqlx::query!(
+        r#"
+        INSERT INTO pending_index_deletion (repository_id, s3_bucket, s3_key)
+        SELECT $1, $2, UNNEST($3::TEXT[])
+        ON CONFLICT (s3_bucket, s3_key) DO NOTHING
+        "#,
+        repository_id,
+        s3_bucket,
+        &keys,
+    )
+    .execute(db)
+    .await;
+    if let Err(err) = deferred {
+        error!(?err, ?keys, "could not record pending index deletions");
+    }
+}
+
+/// Delete the files that have been pending deletion for longer than `grace`,
+/// returning how many were deleted.
+///
+/// Each call deletes at most [`SWEEP_BATCH_SIZE`] files. If the files can't be
+/// deleted, they stay pending, and are retried by the next sweep.
+pub async fn sweep(
+    db: &PgPool,
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    grace: Duration,
+) -> Result<usize, ErrorResponse> {
+    // The rows stay locked until the files are deleted, so that concurrent
+    // sweeps skip them, and so that publishing them again waits for the
+    // deletion to finish (see `cancel`).
+    let mut tx = db.begin().await.map_err(ErrorResponse::from)?;
+    let expired = sqlx::query!(
+        r#"
+        DELETE FROM pending_index_deletion
+        WHERE id IN (
+            SELECT id
+            FROM pending_index_deletion
+            WHERE created_at < NOW() - make_interval(secs => $1)
+            ORDER BY created_at
+            LIMIT $2
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING s3_bucket, s3_key
+        "#,
+        grace.as_secs_f64(),
+        SWEEP_BATCH_SIZE,
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+    let count = expired.len();
+
+    let by_bucket = expired
+        .into_iter()
+        .into_group_map_by(|row| row.s3_bucket.clone());
+    for (s3_bucket, rows) in by_bucket {
+        let keys = rows.into_iter().map(|row| row.s3_key).collect::<Vec<_>>();
+        debug!(?s3_bucket, ?keys, "deleting expired index files");
+        concurrency
+            .run(store.delete_objects(&s3_bucket, &keys))
+            .await
+            .map_err(|err| {
+                ErrorResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    String::from("S3_ERROR"),
+                    format!("could not delete expired index files: {err}"),
+                )
+            })?;
+    }
+
+    tx.commit().await.map_err(ErrorResponse::from)?;
+    Ok(count)
+}
+
+/// Sweep expired deletions in the background for as long as the server runs.
+pub fn spawn_sweeper(
+    db: PgPool,
+    store: Arc<dyn ObjectStore>,
+    concurrency: S3Concurrency,
+    grace: Duration,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            // Keep sweeping while there are full batches of expired files.
+            loop {
+                match sweep(&db, store.as_ref(), &concurrency, grace).await {
+                    Ok(count) => {
+                        if count > 0 {
+                            info!(count, "deleted expired index files");
+                        }
+                        if (count as i64) < SWEEP_BATCH_SIZE {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        error!(?err, "could not sweep pending index deletions");
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use async_tempfile::TempDir;
+    use bytes::Bytes;
+    use sha2::{Digest as _, Sha256};
+
+    use super::*;
+    use crate::server::object_store::{FilesystemObjectStore, ObjectStoreError};
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn delete_after_grace(pool: sqlx::PgPool) {
+        let dir = TempDir::new().await.unwrap();
+        let store = FilesystemObjectStore::new(dir.dir_path());
+        let concurrency = S3Concurrency::default();
+        let sha256sum = hex::encode(Sha256::digest(b"index"));
+        let keys = ["by-hash/SHA256/a", "by-hash/SHA256/b"].map(String::from);
+        for key in &keys {
+            store
+                .put_object("bucket", key, Bytes::from_static(b"index"), &sha256sum)
+                .await
+                .unwrap();
+        }
+        let exists = async |key: &str| {
+            !matches!(
+                store.head_object_checksum("bucket", key).await,
+                Err(ObjectStoreError::NotFound)
+            )
+        };
+
+        // Both files are pending, but the second is published again before
+        // the grace period ends.
+        defer(
+            &pool,
+            &store,
+            &concurrency,
+            DEFAULT_GRACE,
+            1000,
+            "bucket",
+            keys.to_vec(),
+        )
+        .await;
+        cancel(&pool, "bucket", &keys[1..]).await;
+
+        // Nothing is deleted until the grace period ends.
+        assert_eq!(
+            sweep(&pool, &store, &concurrency, DEFAULT_GRACE)
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(exists(&keys[0]).await);
+
+        assert_eq!(
+            sweep(&pool, &store, &concurrency, Duration::ZERO)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(!exists(&keys[0]).await);
+        assert!(exists(&keys[1]).await);
+        assert_eq!(
+            sweep(&pool, &store, &concurrency, Duration::ZERO)
+                .await
+                .unwrap(),
+            0
+        );
+
+        // Without a grace period, files are deleted right away.
+        defer(
+            &pool,
+            &store,
+            &concurrency,
+            Duration::ZERO,
+            1000,
+            "bucket",
+            keys[1..].to_vec(),
+        )
+        .await;
+        assert!(!exists(&keys[1]).await);
+    }
+}
@@ -1,7 +1,6 @@
 use axum::{
     Json,
     extract::{Path, State},
-    http::StatusCode,
 };
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
@@ -13,7 +12,12 @@ use crate::{
         ServerState,
         repo::{
             decode_repo_name,
-            index::{PackageChange, generate_release_file_with_change},
+            dist::{validate_component_name, validate_dist_name},
+            index::{
+                PackageChange, PackageChangeAction, check_clock_skew,
+                generate_release_file_with_change, validate_release_ts,
+            },
+            validate_repo_name_matches,
         },
     },
 };
@@ -26,6 +30,10 @@ pub struct Repository {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GenerateIndexRequest {
     pub change: PackageChange,
+    /// The timestamp to date the Release file with, such as the time of the
+    /// source commit for reproducible builds. Defaults to now.
+    #[serde(default)]
+    pub release_ts: Option<OffsetDateTime>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -45,12 +53,11 @@ pub async fn handler(
 ) -> Result<Json<GenerateIndexResponse>, ErrorResponse> {
     // The repository name in the path is percent-encoded.
     let repo_name = decode_repo_name(&repo_name)?;
-    if repo_name != req.change.repository {
-        return Err(ErrorResponse::new(
-            StatusCode::BAD_REQUEST,
-            "REPOSITORY_MISMATCH".to_string(),
-            "repository name in path does not match repository name in request".to_string(),
-        ));
+    validate_repo_name_matches(&repo_name, &req.change.repository)?;
+    validate_dist_name(&req.change.distribution)?;
+    // Re-signing doesn't change any component, so its component is unused.
+    if !matches!(req.change.action, PackageChangeAction::Resign) {
+        validate_component_name(&req.change.component)?;
     }
 
     let mut tx = state.db.begin().await.unwrap();
@@ -58,8 +65,15 @@ pub async fn handler(
         .execute(&mut *tx)
         .await
         .map_err(ErrorResponse::from)?;
+    check_clock_skew(&mut tx).await?;
 
-    let release_ts = OffsetDateTime::now_utc();
+    let release_ts = match req.release_ts {
+        Some(release_ts) => {
+            validate_release_ts(release_ts)?;
+            release_ts
+        }
+        None => OffsetDateTime::now_utc(),
+    };
     let result =
         generate_release_file_with_change(&mut tx, &tenant_id, &req.change, release_ts).await?;
 
@@ -70,3 +84,218 @@ pub async fn handler(
         release_ts,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use sha2::{Digest as _, Sha256};
+    use time::Duration;
+
+    use super::*;
+    use crate::{
+        server::repo::index::PackageChangeAction,
+        testing::{AttuneTestServer, AttuneTestServerConfig},
+    };
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn reject_repository_mismatch(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "reject_repository_mismatch";
+        const OTHER_REPO_NAME: &str = "reject_repository_mismatch_other";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+        server.create_repository(tenant_id, OTHER_REPO_NAME).await;
+
+        let request = GenerateIndexRequest {
+            change: PackageChange {
+                repository: String::from(OTHER_REPO_NAME),
+                distribution: String::from("stable"),
+                component: String::from("main"),
+                architecture: None,
+                action: PackageChangeAction::Add {
+                    package_sha256sum: String::from("dummy-sha256sum"),
+                    replace: false,
+                },
+            },
+            release_ts: None,
+        };
+        let response = server
+            .http
+            .get(&format!("/api/v0/repositories/{REPO_NAME}/index"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&request)
+            .await;
+        assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+        let error = response.json::<ErrorResponse>();
+        assert_eq!(error.error, "REPOSITORY_MISMATCH");
+    }
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn accept_percent_encoded_repository(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "accept percent/encoded repository";
+        let (tenant_id, api_token) = server
+            .create_test_tenant("accept_percent_encoded_repository")
+            .await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        let request = GenerateIndexRequest {
+            change: PackageChange {
+                repository: String::from(REPO_NAME),
+                distribution: String::from("stable"),
+                component: String::from("main"),
+                architecture: None,
+                action: PackageChangeAction::Add {
+                    package_sha256sum: String::from("dummy-sha256sum"),
+                    replace: false,
+                },
+            },
+            release_ts: None,
+        };
+        let response = server
+            .http
+            .get("/api/v0/repositories/accept%20percent%2Fencoded%20repository/index")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&request)
+            .await;
+
+        // The request fails further along because the package doesn't exist,
+        // but the decoded path must match the repository in the body.
+        let error = response.json::<ErrorResponse>();
+        assert_ne!(error.error, "REPOSITORY_MISMATCH");
+    }
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn reject_release_ts_out_of_range(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "reject_release_ts_out_of_range";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        let now = OffsetDateTime::now_utc();
+        for release_ts in [now + Duration::days(1), now - Duration::days(400)] {
+            let request = GenerateIndexRequest {
+                change: PackageChange {
+                    repository: String::from(REPO_NAME),
+                    distribution: String::from("stable"),
+                    component: String::from("main"),
+                    architecture: None,
+                    action: PackageChangeAction::Add {
+                        package_sha256sum: String::from("dummy-sha256sum"),
+                        replace: false,
+                    },
+                },
+                release_ts: Some(release_ts),
+            };
+            let response = server
+                .http
+                .get(&format!("/api/v0/repositories/{REPO_NAME}/index"))
+                .add_header("authorization", format!("Bearer {api_token}"))
+                .json(&request)
+                .await;
+            assert_eq!(response.status_code(), StatusCode::BAD_REQUEST);
+            let error = response.json::<ErrorResponse>();
+            assert_eq!(error.error, "INVALID_RELEASE_TIMESTAMP");
+        }
+    }
+
+    /// Adding a rebuild of a published package requires replacing it, and
+    /// replacing it is refused while another distribution still publishes the
+    /// old build, since both share its pool file.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn reject_replacing_package_in_use(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool.clone(),
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const API_TOKEN: &str = "reject_replacing_package_in_use";
+        sqlx::query!(
+            r#"
+            INSERT INTO attune_tenant_api_token (tenant_id, name, token, created_at, updated_at)
+            VALUES (1, 'TEST_TENANT_API_TOKEN', $1, NOW(), NOW())
+            "#,
+            Sha256::digest(API_TOKEN).as_slice().to_vec(),
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // Upload a rebuild of the fixture's amd64 package, and publish the
+        // original build in a second distribution.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_package (id, tenant_id, package, version, architecture, maintainer, description, paragraph, size, s3_bucket, md5sum, sha1sum, sha256sum, created_at, updated_at)
+            SELECT 1003, tenant_id, package, version, architecture, maintainer, 'Rebuilt test package for amd64', paragraph, size, s3_bucket, 'rebuiltmd5sum', 'rebuiltsha1sum', 'rebuiltsha256sum', NOW(), NOW()
+            FROM debian_repository_package
+            WHERE id = 1001
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            WITH release AS (
+                INSERT INTO debian_repository_release (repository_id, distribution, suite, codename, contents, created_at, updated_at)
+                VALUES (1000, 'testing', 'testing', 'testing', 'dummy content', NOW(), NOW())
+                RETURNING id
+            ), component AS (
+                INSERT INTO debian_repository_component (release_id, name, created_at, updated_at)
+                SELECT id, 'main', NOW(), NOW() FROM release
+                RETURNING id
+            )
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            SELECT id, 1001, 'pool/main/t/test-package/test-package_1.0.0_amd64.deb', NOW(), NOW()
+            FROM component
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        for (replace, code) in [
+            (false, "PACKAGE_VERSION_CONFLICT"),
+            (true, "REPLACED_PACKAGE_IN_USE"),
+        ] {
+            let request = GenerateIndexRequest {
+                change: PackageChange {
+                    repository: String::from("test-multi-arch"),
+                    distribution: String::from("stable"),
+                    component: String::from("main"),
+                    architecture: None,
+                    action: PackageChangeAction::Add {
+                        package_sha256sum: String::from("rebuiltsha256sum"),
+                        replace,
+                    },
+                },
+                release_ts: None,
+            };
+            let response = server
+                .http
+                .get("/api/v0/repositories/test-multi-arch/index")
+                .add_header("authorization", format!("Bearer {API_TOKEN}"))
+                .json(&request)
+                .await;
+            assert_eq!(response.status_code(), StatusCode::CONFLICT);
+            let error = response.json::<ErrorResponse>();
+            assert_eq!(error.error, code);
+        }
+    }
+}
@@ -1,47 +1,287 @@
-use std::iter::once;
+use std::{
+    collections::BTreeSet,
+    io::{Read as _, Write as _},
+    iter::once,
+};
 
+use axum::http::StatusCode;
+use flate2::{Compression as GzCompression, read::GzDecoder, write::GzEncoder};
 use serde::{Deserialize, Serialize};
 use sqlx::{Postgres, Transaction};
-use time::OffsetDateTime;
-use tracing::instrument;
+use time::{Duration, OffsetDateTime};
+use tracing::{instrument, warn};
 
 use crate::{
     api::{ErrorResponse, TenantID},
-    apt::{Package, PackagesIndex, PackagesIndexMeta, PublishedPackage, ReleaseFile, ReleaseMeta},
+    apt::{
+        CompressedPackagesIndex, DEFAULT_INDEX_COMPRESSION, Package, PackagesIndex,
+        PackagesIndexMeta, PublishedPackage, ReleaseFile, ReleaseMeta, normalize_architecture,
+    },
 };
 
+pub mod batch;
+pub mod deletion;
 pub mod generate;
 pub mod sign;
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A change to a single package in a distribution.
+///
+/// Each change only modifies the Packages index of its component and the
+/// package's architecture; the distribution's other Packages indexes are left
+/// exactly as they are. Packages with `Architecture: all` are the exception:
+/// they are published in the component's Packages index of every architecture
+/// in the distribution, so changing one modifies all of those indexes. However,
+/// the Release file lists the checksums of every Packages index in the
+/// distribution, so every change still produces (and requires a signature over)
+/// the whole Release file. This is what lets architectures be published
+/// independently, e.g. amd64 now and arm64 later, while keeping a single valid
+/// Release signature.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PackageChange {
     pub repository: String,
     pub distribution: String,
     pub component: String,
+    /// If set, scopes the change to the Packages index of this architecture,
+    /// and the change is rejected if it would modify any other index.
+    #[serde(default)]
+    pub architecture: Option<String>,
 
     pub action: PackageChangeAction,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum PackageChangeAction {
     Add {
         package_sha256sum: String,
+        /// If another package with the same name, version, and architecture is
+        /// already in the component, remove it as part of this change, so that
+        /// the index switches directly from the old package to the new one.
+        ///
+        /// Otherwise, the change is rejected with `PACKAGE_VERSION_CONFLICT`.
+        #[serde(default)]
+        replace: bool,
     },
     Remove {
         name: String,
         version: String,
         architecture: String,
     },
+    /// Re-sign the distribution's current Release file without changing any
+    /// packages, e.g. to rotate the signing key.
+    Resign,
+}
+
+/// How the contents of a Packages index are encoded when stored in the
+/// database.
+///
+/// This only saves space in the database. Indexes are always hashed,
+/// compared, and published in their decoded form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, sqlx::Type)]
+#[sqlx(
+    type_name = "debian_repository_contents_encoding",
+    rename_all = "lowercase"
+)]
+pub enum ContentsEncoding {
+    Gzip,
+}
+
+/// Encode index contents for storage. Contents without an encoding are stored
+/// as-is.
+pub fn encode_contents(encoding: Option<ContentsEncoding>, contents: &[u8]) -> Vec<u8> {
+    match encoding {
+        None => contents.to_vec(),
+        Some(ContentsEncoding::Gzip) => {
+            let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+            encoder
+                .write_all(contents)
+                .expect("could not compress index contents");
+            encoder.finish().expect("could not compress index contents")
+        }
+    }
+}
+
+/// Decode stored index contents back into their canonical bytes.
+pub fn decode_contents(
+    encoding: Option<ContentsEncoding>,
+    contents: Vec<u8>,
+) -> Result<Vec<u8>, ErrorResponse> {
+    match encoding {
+        None => Ok(contents),
+        Some(ContentsEncoding::Gzip) => {
+            let mut decoded = Vec::new();
+            GzDecoder::new(contents.as_slice())
+                .read_to_end(&mut decoded)
+                .map_err(|err| {
+                    ErrorResponse::new(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "INVALID_STORED_INDEX",
+                        format!("could not decompress stored index contents: {err}"),
+                    )
+                })?;
+            Ok(decoded)
+        }
+    }
+}
+
+/// How far in the future a client-supplied Release timestamp may be, to allow
+/// for clock skew. Clients reject Release files dated in the future.
+const MAX_RELEASE_TS_SKEW: Duration = Duration::minutes(5);
+
+/// How far in the past a client-supplied Release timestamp may be.
+const MAX_RELEASE_TS_AGE: Duration = Duration::days(365);
+
+/// Check that a client-supplied Release timestamp is close enough to now that
+/// clients will accept the Release file.
+pub fn validate_release_ts(release_ts: OffsetDateTime) -> Result<(), ErrorResponse> {
+    match release_ts_problem(release_ts, OffsetDateTime::now_utc()) {
+        Some(problem) => Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_RELEASE_TIMESTAMP",
+            problem,
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Describe why a Release file dated `release_ts` is implausible at `now`, if
+/// it is. APT rejects Release files that are dated in the future.
+pub fn release_ts_problem(release_ts: OffsetDateTime, now: OffsetDateTime) -> Option<String> {
+    if release_ts > now + MAX_RELEASE_TS_SKEW {
+        return Some(format!("release timestamp {release_ts} is in the future"));
+    }
+    if release_ts < now - MAX_RELEASE_TS_AGE {
+        return Some(format!(
+            "release timestamp {release_ts} is more than {} days in the past",
+            MAX_RELEASE_TS_AGE.whole_days()
+        ));
+    }
+    None
+}
+
+/// Check the server's clock against the database's clock.
+///
+/// Release files are dated, and their dates are validated, using the server's
+/// clock. If it is skewed, every published Release file would be rejected by
+/// clients (or valid ones would be refused), so changes are refused until the
+/// clock is fixed.
+pub async fn check_clock_skew(tx: &mut Transaction<'_, Postgres>) -> Result<(), ErrorResponse> {
+    let reference = sqlx::query_scalar!(r#"SELECT NOW() AS "now!""#)
+        .fetch_one(&mut **tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    clock_skew_problem(OffsetDateTime::now_utc(), reference)
+}
+
+fn clock_skew_problem(now: OffsetDateTime, reference: OffsetDateTime) -> Result<(), ErrorResponse> {
+    let skew = now - reference;
+    if skew.abs() > MAX_RELEASE_TS_SKEW {
+        warn!(%now, %reference, %skew, "server clock is skewed from database clock");
+        return Err(ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "CLOCK_SKEW",
+            format!(
+                "server clock is {skew} off from the database clock, so Release files would have the wrong date"
+            ),
+        ));
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
 struct PackageChangeResult {
     release_file: ReleaseFile,
-    changed_packages_index: PackagesIndex,
+    /// The changed Packages indexes. This is a single index, unless the changed
+    /// package has `Architecture: all`.
+    changed_packages_indexes: Vec<ChangedPackagesIndex>,
     changed_package: PublishedPackage,
+    /// The package that the added package replaces, if the change replaces
+    /// one. It has the same pool filename as the added package.
+    replaced_package: Option<PublishedPackage>,
     orphaned_pool_filename: bool,
 }
 
+#[derive(Debug)]
+struct ChangedPackagesIndex {
+    packages_index: PackagesIndex,
+    /// Compressed variants of the changed Packages index. This is empty if the
+    /// changed index is empty.
+    compressed_indexes: Vec<CompressedPackagesIndex>,
+}
+
+/// Check a package that is being added against the repository's allowlists.
+/// Empty allowlists allow everything.
+fn check_allowed(
+    allowed_architectures: &[String],
+    allowed_components: &[String],
+    architecture: &str,
+    component: &str,
+) -> Result<(), ErrorResponse> {
+    if !allowed_architectures.is_empty()
+        && !allowed_architectures
+            .iter()
+            .any(|allowed| allowed == architecture)
+    {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "ARCHITECTURE_NOT_ALLOWED",
+            format!(
+                "architecture {architecture:?} is not allowed in this repository, only: {}",
+                allowed_architectures.join(", ")
+            ),
+        ));
+    }
+    if !allowed_components.is_empty()
+        && !allowed_components
+            .iter()
+            .any(|allowed| allowed == component)
+    {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "COMPONENT_NOT_ALLOWED",
+            format!(
+                "component {component:?} is not allowed in this repository, only: {}",
+                allowed_components.join(", ")
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Explain why a package to be removed wasn't found. If the package version is
+/// published in other architectures, list them, since the requested
+/// architecture is most likely a typo.
+async fn package_not_found(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &TenantID,
+    change: &PackageChange,
+    name: &str,
+    version: &str,
+    architecture: &str,
+) -> ErrorResponse {
+    let published = PublishedPackage::query_architectures(
+        tx,
+        tenant_id,
+        &change.repository,
+        &change.distribution,
+        &change.component,
+        name,
+        version,
+    )
+    .await;
+    match published {
+        Ok(published) if !published.is_empty() => ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "PACKAGE_NOT_FOUND",
+            format!(
+                "package {name} {version} is not published for architecture {architecture:?}, only for: {}",
+                published.join(", ")
+            ),
+        ),
+        Ok(_) => ErrorResponse::not_found("package"),
+        Err(err) => err,
+    }
+}
+
 /// Given a single package change, generate the new release file and the changed
 /// Packages index based off of the current state of the repository.
 #[instrument(skip(tx))]
@@ -51,9 +291,27 @@ async fn generate_release_file_with_change(
     change: &PackageChange,
     release_ts: OffsetDateTime,
 ) -> Result<PackageChangeResult, ErrorResponse> {
+    // Re-signing keeps the current Release file, so there's nothing to
+    // generate.
+    if let PackageChangeAction::Resign = change.action {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "INVALID_CHANGE".to_string(),
+            "re-signing does not generate a new Release file; sign the current one instead"
+                .to_string(),
+        ));
+    }
+
     // Load the repository. If it does not exist, return an error.
-    sqlx::query!(
-        "SELECT id FROM debian_repository WHERE tenant_id = $1 AND name = $2",
+    let repo = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            allowed_architectures::TEXT[] AS "allowed_architectures!",
+            allowed_components AS "allowed_components!"
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
         tenant_id.0,
         change.repository
     )
@@ -78,11 +336,20 @@ async fn generate_release_file_with_change(
         version: None,
         suite: change.distribution.clone(),
         codename: change.distribution.clone(),
+        index_compression: DEFAULT_INDEX_COMPRESSION.to_vec(),
+        declared_architectures: Vec::new(),
+        declared_components: Vec::new(),
+        sha256_only: false,
+        valid_for_seconds: None,
+        not_automatic: false,
+        but_automatic_upgrades: false,
     });
 
     // Load the package to be added. If it does not exist, return an error.
     let changed_package = match &change.action {
-        PackageChangeAction::Add { package_sha256sum } => {
+        PackageChangeAction::Add {
+            package_sha256sum, ..
+        } => {
             let package = Package::query_from_sha256sum(&mut *tx, tenant_id, package_sha256sum)
                 .await?
                 .ok_or(ErrorResponse::not_found("package"))?;
@@ -92,51 +359,184 @@ async fn generate_release_file_with_change(
             name,
             version,
             architecture,
-        } => PublishedPackage::query_from_meta(
+        } => {
+            let architecture = normalize_architecture(architecture);
+            let package = PublishedPackage::query_from_meta(
+                &mut *tx,
+                tenant_id,
+                &change.repository,
+                &change.distribution,
+                &change.component,
+                name,
+                version,
+                &architecture,
+            )
+            .await?;
+            match package {
+                Some(package) => package,
+                None => {
+                    return Err(package_not_found(
+                        tx,
+                        tenant_id,
+                        change,
+                        name,
+                        version,
+                        &architecture,
+                    )
+                    .await);
+                }
+            }
+        }
+        PackageChangeAction::Resign => unreachable!("re-signing is rejected above"),
+    };
+
+    // Make sure the package is allowed in the repository. Removals aren't
+    // checked, so that packages added before the allowlist was set can still
+    // be removed.
+    if let PackageChangeAction::Add { .. } = &change.action {
+        check_allowed(
+            &repo.allowed_architectures,
+            &repo.allowed_components,
+            &changed_package.package.architecture,
+            &change.component,
+        )?;
+    }
+
+    // Make sure the change stays within its scope, if it has one.
+    if let Some(architecture) = change.architecture.as_deref().map(normalize_architecture)
+        && architecture != changed_package.package.architecture
+    {
+        return Err(ErrorResponse::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .error("ARCHITECTURE_MISMATCH")
+            .message(format!(
+                "change is scoped to architecture {architecture:?}, but package has architecture {:?}",
+                changed_package.package.architecture
+            ))
+            .build());
+    }
+
+    // Determine the architectures of the Packages indexes that will be
+    // changed.
+    let architectures = if changed_package.package.architecture == "all" {
+        let architectures = all_package_architectures(tx, tenant_id, change, &release).await?;
+        if architectures.is_empty() {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "NO_ARCHITECTURES",
+                format!(
+                    "distribution {:?} has no architectures to publish Architecture: all packages in; declare its architectures or add an architecture-specific package first",
+                    change.distribution
+                ),
+            ));
+        }
+        architectures
+    } else {
+        vec![changed_package.package.architecture.clone()]
+    };
+    if let Some(architecture) = change.architecture.as_deref().map(normalize_architecture)
+        && architectures != [architecture.as_str()]
+    {
+        return Err(ErrorResponse::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .error("ARCHITECTURE_MISMATCH")
+            .message(format!(
+                "change is scoped to architecture {architecture:?}, but would change the Packages indexes of: {}",
+                architectures.join(", ")
+            ))
+            .build());
+    }
+
+    // Load the Packages indexes that will be changed.
+    //
+    // Note that an index might have no packages if this is the first package
+    // to be added to its (distribution, component, architecture) tuple. But
+    // that's okay, because it will just end up constructing an empty
+    // PackagesIndex.
+    let mut changed_packages_indexes = Vec::with_capacity(architectures.len());
+    for architecture in &architectures {
+        let packages_index_packages = PublishedPackage::query_from_packages_index(
             &mut *tx,
             tenant_id,
             &change.repository,
             &change.distribution,
             &change.component,
-            name,
-            version,
             architecture,
         )
-        .await?
-        .ok_or(ErrorResponse::not_found("package"))?,
-    };
+        .await?;
+        changed_packages_indexes.push(PackagesIndex::from_packages(
+            &change.component,
+            architecture,
+            packages_index_packages,
+        ));
+    }
 
-    // Load the Packages index that will be changed.
-    //
-    // Note that `packages_index_packages` might be empty if this is the first
-    // package to be added to this (distribution, component, architecture)
-    // tuple. But that's okay, because it will just end up constructing an empty
-    // PackagesIndex.
-    let packages_index_packages = PublishedPackage::query_from_packages_index(
-        &mut *tx,
-        tenant_id,
-        &change.repository,
-        &change.distribution,
-        &change.component,
-        &changed_package.package.architecture,
-    )
-    .await?;
-    let mut changed_packages_index = PackagesIndex::from_packages(
-        &change.component,
-        &changed_package.package.architecture,
-        packages_index_packages,
-    );
-
-    // Modify the changed Packages index.
-    match &change.action {
-        PackageChangeAction::Add { .. } => {
-            changed_packages_index.add_package(changed_package.package.clone());
-        }
-        PackageChangeAction::Remove { .. } => {
-            changed_packages_index.remove_package(changed_package.clone());
+    // Find the package being replaced, if any. Replacing a package with itself
+    // is a no-op, just like re-adding it.
+    let conflicting = changed_packages_indexes
+        .iter()
+        .flat_map(|index| index.packages())
+        .find(|p| {
+            p.package.name == changed_package.package.name
+                && p.package.version == changed_package.package.version
+                && p.package.architecture == changed_package.package.architecture
+                && p.package.sha256sum != changed_package.package.sha256sum
+        })
+        .cloned();
+    let replaced_package = match &change.action {
+        PackageChangeAction::Add { replace: true, .. } => conflicting,
+        PackageChangeAction::Add { replace: false, .. } if conflicting.is_some() => {
+            return Err(ErrorResponse::new(
+                StatusCode::CONFLICT,
+                "PACKAGE_VERSION_CONFLICT",
+                format!(
+                    "a different build of {} {} ({}) is already in this component; replace it to add this one",
+                    changed_package.package.name,
+                    changed_package.package.version,
+                    changed_package.package.architecture
+                ),
+            ));
         }
+        _ => None,
+    };
+    if let Some(replaced) = &replaced_package {
+        check_replaceable(tx, repo.id, replaced).await?;
     }
 
+    // Modify the changed Packages indexes, and compress each of them into each
+    // configured format. Empty indexes are removed from the Release file, so
+    // they have no variants.
+    let changed_packages_indexes = changed_packages_indexes
+        .into_iter()
+        .map(|mut packages_index| {
+            match &change.action {
+                PackageChangeAction::Add { .. } => {
+                    if let Some(replaced) = &replaced_package {
+                        packages_index.remove_package(replaced.clone());
+                    }
+                    packages_index.add_package(changed_package.package.clone());
+                }
+                PackageChangeAction::Remove { .. } => {
+                    packages_index.remove_package(changed_package.clone());
+                }
+                PackageChangeAction::Resign => unreachable!("re-signing is rejected above"),
+            }
+            let compressed_indexes = if packages_index.contents.is_empty() {
+                Vec::new()
+            } else {
+                release
+                    .index_compression
+                    .iter()
+                    .map(|compression| packages_index.compressed(*compression))
+                    .collect()
+            };
+            ChangedPackagesIndex {
+                packages_index,
+                compressed_indexes,
+            }
+        })
+        .collect::<Vec<_>>();
+
     // Load all Packages indexes in the Release file.
     let packages_indexes = PackagesIndexMeta::query_from_release(
         &mut *tx,
@@ -148,7 +548,7 @@ async fn generate_release_file_with_change(
 
     // Update the set of Packages indexes in the Release file.
     let packages_indexes =
-        update_release_package_indexes(packages_indexes, &changed_packages_index);
+        update_release_package_indexes(packages_indexes, &changed_packages_indexes);
 
     // Construct the new Release file.
     let release_file = ReleaseFile::from_indexes(release, release_ts, &packages_indexes);
@@ -185,24 +585,68 @@ async fn generate_release_file_with_change(
 
     Ok(PackageChangeResult {
         release_file,
-        changed_packages_index,
+        changed_packages_indexes,
         changed_package,
+        replaced_package,
         orphaned_pool_filename: remaining_component_packages.count == 0,
     })
 }
 
+/// Check that a package can be replaced in place.
+///
+/// The replacing package is copied over the replaced package's pool file. Pool
+/// files are shared between distributions, so this is only safe if no other
+/// distribution still publishes the replaced package.
+async fn check_replaceable(
+    tx: &mut Transaction<'_, Postgres>,
+    repository_id: i64,
+    replaced: &PublishedPackage,
+) -> Result<(), ErrorResponse> {
+    let published = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!: i64"
+        FROM
+            debian_repository_release
+            JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository_component_package ON debian_repository_component_package.component_id = debian_repository_component.id
+            JOIN debian_repository_package ON debian_repository_package.id = debian_repository_component_package.package_id
+        WHERE
+            debian_repository_release.repository_id = $1
+            AND debian_repository_component_package.filename = $2
+            AND debian_repository_package.sha256sum = $3
+        "#,
+        repository_id,
+        &replaced.filename,
+        &replaced.package.sha256sum,
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+    if published.count > 1 {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            "REPLACED_PACKAGE_IN_USE",
+            format!(
+                "package {} {} ({}) is also published in other distributions, which share its pool file; remove it from them before replacing it",
+                replaced.package.name, replaced.package.version, replaced.package.architecture
+            ),
+        ));
+    }
+    Ok(())
+}
+
 // Update the set of `Packages` indexes in the Release file. This function is
 // refactored out for purity so we can unit test it.
 fn update_release_package_indexes(
     packages_indexes: Vec<PackagesIndexMeta>,
-    changed_packages_index: &PackagesIndex,
+    changed_packages_indexes: &[ChangedPackagesIndex],
 ) -> Vec<PackagesIndexMeta> {
     // TODO: Should we add assertions here for preconditions? For example, no
     // element in `packages_indexes` should be an index for the same component
     // and architecture as another, and none of them should be empty. We could
     // also do the same for post-conditions.
 
-    // There are three cases to handle here:
+    // There are three cases to handle here for each changed index:
     //
     // 1. If the index didn't previously exist, it should be added to the Release
     //    file.
@@ -211,21 +655,82 @@ fn update_release_package_indexes(
     //    removed all packages in it), it should be removed from the Release file.
     //
     // To do this, we first remove any existing Packages index for the same
-    // component and architecture (notice that this is a no-op if the index
-    // doesn't yet exist). Then, we add our new index if it's non-empty.
+    // component and architecture, including its compressed variants (notice
+    // that this is a no-op if the index doesn't yet exist). Then, we add our
+    // new indexes and their compressed variants if they're non-empty.
     let packages_indexes = packages_indexes.into_iter().filter(|pi| {
-        !(pi.component == changed_packages_index.meta.component
-            && pi.architecture == changed_packages_index.meta.architecture)
+        !changed_packages_indexes.iter().any(|changed| {
+            pi.component == changed.packages_index.meta.component
+                && pi.architecture == changed.packages_index.meta.architecture
+        })
     });
 
-    // Add the new `Packages` index if it's non-empty.
-    if changed_packages_index.contents.is_empty() {
-        packages_indexes.collect()
-    } else {
-        packages_indexes
-            .chain(once(changed_packages_index.meta.clone()))
-            .collect()
-    }
+    // Add the new `Packages` indexes that are non-empty.
+    packages_indexes
+        .chain(
+            changed_packages_indexes
+                .iter()
+                .filter(|changed| !changed.packages_index.contents.is_empty())
+                .flat_map(|changed| {
+                    once(changed.packages_index.meta.clone()).chain(
+                        changed
+                            .compressed_indexes
+                            .iter()
+                            .map(|compressed| compressed.meta.clone()),
+                    )
+                }),
+        )
+        .collect()
+}
+
+/// The architectures whose Packages indexes an `Architecture: all` package is
+/// published in.
+///
+/// When adding, these are the architectures that the distribution publishes
+/// or declares. When removing, these are the architectures of the component's
+/// existing indexes, which are the ones that the package was published in.
+async fn all_package_architectures(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &TenantID,
+    change: &PackageChange,
+    release: &ReleaseMeta,
+) -> Result<Vec<String>, ErrorResponse> {
+    let removing = matches!(change.action, PackageChangeAction::Remove { .. });
+    let published = sqlx::query_scalar!(
+        r#"
+        SELECT DISTINCT debian_repository_index_packages.architecture::TEXT AS "architecture!: String"
+        FROM
+            debian_repository
+            JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
+            JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository_index_packages ON debian_repository_index_packages.component_id = debian_repository_component.id
+        WHERE
+            debian_repository.tenant_id = $1
+            AND debian_repository.name = $2
+            AND debian_repository_release.distribution = $3
+            AND ($4::TEXT IS NULL OR debian_repository_component.name = $4)
+        "#,
+        tenant_id.0,
+        change.repository,
+        change.distribution,
+        removing.then_some(&change.component),
+    )
+    .fetch_all(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    let declared = release
+        .declared_architectures
+        .iter()
+        .filter(|_| !removing)
+        .cloned();
+    Ok(published
+        .into_iter()
+        .chain(declared)
+        .filter(|architecture| architecture != "all")
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect())
 }
 
 #[cfg(test)]
@@ -246,8 +751,10 @@ mod tests {
             repository: String::from("test-multi-arch"),
             distribution: String::from("stable"),
             component: String::from("main"),
+            architecture: None,
             action: PackageChangeAction::Add {
                 package_sha256sum: String::from("amd64sha256sum"),
+                replace: false,
             },
         };
         let amd64_result =
@@ -255,21 +762,25 @@ mod tests {
                 .await
                 .expect("Failed to generate release file for amd64");
         assert!(
-            amd64_result
-                .changed_packages_index
+            amd64_result.changed_packages_indexes[0]
+                .packages_index
                 .contents
                 .contains("Architecture: amd64"),
             "amd64 index should contain amd64 package"
         );
         assert!(
-            !amd64_result
-                .changed_packages_index
+            !amd64_result.changed_packages_indexes[0]
+                .packages_index
                 .contents
                 .contains("Architecture: arm64"),
             "amd64 index should NOT contain arm64 package"
         );
         assert_eq!(
-            amd64_result.changed_packages_index.meta.architecture, "amd64",
+            amd64_result.changed_packages_indexes[0]
+                .packages_index
+                .meta
+                .architecture,
+            "amd64",
             "Index should be for amd64 architecture"
         );
 
@@ -277,8 +788,10 @@ mod tests {
             repository: String::from("test-multi-arch"),
             distribution: String::from("stable"),
             component: String::from("main"),
+            architecture: None,
             action: PackageChangeAction::Add {
                 package_sha256sum: String::from("arm64sha256sum"),
+                replace: false,
             },
         };
         let arm64_result =
@@ -286,21 +799,25 @@ mod tests {
                 .await
                 .expect("Failed to generate release file for arm64");
         assert!(
-            arm64_result
-                .changed_packages_index
+            arm64_result.changed_packages_indexes[0]
+                .packages_index
                 .contents
                 .contains("Architecture: arm64"),
             "arm64 index should contain arm64 package"
         );
         assert!(
-            !arm64_result
-                .changed_packages_index
+            !arm64_result.changed_packages_indexes[0]
+                .packages_index
                 .contents
                 .contains("Architecture: amd64"),
             "arm64 index should NOT contain amd64 package"
         );
         assert_eq!(
-            arm64_result.changed_packages_index.meta.architecture, "arm64",
+            arm64_result.changed_packages_indexes[0]
+                .packages_index
+                .meta
+                .architecture,
+            "arm64",
             "Index should be for arm64 architecture"
         );
 
@@ -318,6 +835,7 @@ mod tests {
             repository: String::from("test-multi-arch"),
             distribution: String::from("stable"),
             component: String::from("main"),
+            architecture: None,
             action: PackageChangeAction::Remove {
                 name: String::from("test-package"),
                 version: String::from("1.0.0"),
@@ -333,15 +851,26 @@ mod tests {
         .await
         .expect("Failed to generate release file for removal");
         assert!(
-            remove_result.changed_packages_index.contents.is_empty(),
+            remove_result.changed_packages_indexes[0]
+                .packages_index
+                .contents
+                .is_empty(),
             "amd64 index should be empty after removing all amd64 packages"
         );
         assert_eq!(
-            remove_result.changed_packages_index.meta.architecture, "amd64",
+            remove_result.changed_packages_indexes[0]
+                .packages_index
+                .meta
+                .architecture,
+            "amd64",
             "Index should still be for amd64 architecture"
         );
         assert_eq!(
-            remove_result.changed_packages_index.meta.size, 0,
+            remove_result.changed_packages_indexes[0]
+                .packages_index
+                .meta
+                .size,
+            0,
             "Index size should be 0"
         );
 
@@ -355,6 +884,129 @@ mod tests {
         tx.rollback().await.unwrap();
     }
 
+    /// Packages can only be added in the architectures and components that
+    /// the repository allows, but disallowed packages can still be removed.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn enforce_repository_allowlists(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let release_ts = OffsetDateTime::now_utc();
+        sqlx::query!(
+            r#"
+            UPDATE debian_repository
+            SET allowed_architectures = '{amd64}', allowed_components = '{main}'
+            WHERE id = 1000
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let add = |sha256sum: &str, component: &str| PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from(component),
+            architecture: None,
+            action: PackageChangeAction::Add {
+                package_sha256sum: String::from(sha256sum),
+                replace: false,
+            },
+        };
+
+        generate_release_file_with_change(
+            &mut tx,
+            &tenant_id,
+            &add("amd64sha256sum", "main"),
+            release_ts,
+        )
+        .await
+        .expect("allowed package should be added");
+
+        let err = generate_release_file_with_change(
+            &mut tx,
+            &tenant_id,
+            &add("arm64sha256sum", "main"),
+            release_ts,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.error, "ARCHITECTURE_NOT_ALLOWED");
+
+        let err = generate_release_file_with_change(
+            &mut tx,
+            &tenant_id,
+            &add("amd64sha256sum", "contrib"),
+            release_ts,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.error, "COMPONENT_NOT_ALLOWED");
+
+        let remove = PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Remove {
+                name: String::from("test-package"),
+                version: String::from("1.0.0"),
+                architecture: String::from("arm64"),
+            },
+        };
+        generate_release_file_with_change(&mut tx, &tenant_id, &remove, release_ts)
+            .await
+            .expect("disallowed package should be removed");
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Removals accept common aliases of the package's architecture, and
+    /// explain which architectures are published when none match.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn remove_package_by_architecture_alias(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let release_ts = OffsetDateTime::now_utc();
+        let remove = |architecture: &str| PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Remove {
+                name: String::from("test-package"),
+                version: String::from("1.0.0"),
+                architecture: String::from(architecture),
+            },
+        };
+
+        let result =
+            generate_release_file_with_change(&mut tx, &tenant_id, &remove("x86_64"), release_ts)
+                .await
+                .expect("Failed to generate release file for removal");
+        assert_eq!(result.changed_package.package.architecture, "amd64");
+
+        let err =
+            generate_release_file_with_change(&mut tx, &tenant_id, &remove("riscv64"), release_ts)
+                .await
+                .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        assert!(
+            err.message.ends_with("only for: amd64, arm64"),
+            "unexpected message: {}",
+            err.message
+        );
+
+        // Names that aren't Debian architectures at all are also not found.
+        let err =
+            generate_release_file_with_change(&mut tx, &tenant_id, &remove("nonsense"), release_ts)
+                .await
+                .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+
+        tx.rollback().await.unwrap();
+    }
+
     /// The release file should list all architecture indexes.
     #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
     async fn release_file_lists_all_architectures(pool: sqlx::PgPool) {
@@ -367,8 +1019,10 @@ mod tests {
             repository: String::from("test-multi-arch"),
             distribution: String::from("stable"),
             component: String::from("main"),
+            architecture: None,
             action: PackageChangeAction::Add {
                 package_sha256sum: String::from("amd64sha256sum"),
+                replace: false,
             },
         };
         let result = generate_release_file_with_change(&mut tx, &tenant_id, &change, release_ts)
@@ -398,4 +1052,437 @@ mod tests {
 
         tx.rollback().await.unwrap();
     }
+
+    /// A change scoped to one architecture only modifies that architecture's
+    /// Packages index, but still produces a whole Release file that lists the
+    /// unchanged indexes of every other architecture.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn scoped_change_keeps_other_architectures_in_release(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let release_ts = OffsetDateTime::now_utc();
+
+        let change = PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: Some(String::from("amd64")),
+            action: PackageChangeAction::Add {
+                package_sha256sum: String::from("amd64sha256sum"),
+                replace: false,
+            },
+        };
+        let result = generate_release_file_with_change(&mut tx, &tenant_id, &change, release_ts)
+            .await
+            .expect("Failed to generate release file");
+        let release = &result.release_file.contents;
+        assert_eq!(
+            result.changed_packages_indexes[0]
+                .packages_index
+                .meta
+                .architecture,
+            "amd64"
+        );
+
+        // The arm64 index is listed with its stored checksums, since the change
+        // didn't touch it.
+        assert!(
+            release.contains("oldarm64sha256"),
+            "Release file should list the unchanged arm64 index"
+        );
+        // The amd64 index is listed with its new checksums.
+        assert!(
+            !release.contains("oldamd64sha256"),
+            "Release file should not list the previous amd64 index"
+        );
+        assert!(
+            release.contains(
+                &result.changed_packages_indexes[0]
+                    .packages_index
+                    .meta
+                    .sha256sum
+            ),
+            "Release file should list the changed amd64 index"
+        );
+
+        // A change outside of its scope is rejected.
+        let change = PackageChange {
+            architecture: Some(String::from("arm64")),
+            ..change
+        };
+        let error = generate_release_file_with_change(&mut tx, &tenant_id, &change, release_ts)
+            .await
+            .expect_err("change outside of scope should be rejected");
+        assert_eq!(error.error, "ARCHITECTURE_MISMATCH");
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// A Release file generated with a skewed clock is dated in the future,
+    /// which clients reject, so it is flagged once published.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn detect_skewed_release_date(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let now = OffsetDateTime::now_utc();
+        let change = PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Add {
+                package_sha256sum: String::from("amd64sha256sum"),
+                replace: false,
+            },
+        };
+
+        let result = generate_release_file_with_change(&mut tx, &tenant_id, &change, now)
+            .await
+            .unwrap();
+        let date = ReleaseFile::parse_date(&result.release_file.contents).unwrap();
+        assert_eq!(release_ts_problem(date, now), None);
+
+        let skewed = now + Duration::hours(1);
+        let result = generate_release_file_with_change(&mut tx, &tenant_id, &change, skewed)
+            .await
+            .unwrap();
+        let date = ReleaseFile::parse_date(&result.release_file.contents).unwrap();
+        let problem = release_ts_problem(date, now).unwrap();
+        assert!(problem.contains("in the future"), "{problem}");
+        let problem = release_ts_problem(now - Duration::days(400), now).unwrap();
+        assert!(problem.contains("in the past"), "{problem}");
+
+        // The server refuses to date Release files at all if its own clock is
+        // skewed from the database's.
+        assert!(clock_skew_problem(now, now + Duration::seconds(30)).is_ok());
+        let err = clock_skew_problem(skewed, now).unwrap_err();
+        assert_eq!(err.error, "CLOCK_SKEW");
+        assert!(check_clock_skew(&mut tx).await.is_ok());
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Replacing a package swaps it for a rebuild with the same name, version,
+    /// and architecture in a single change, unless another distribution still
+    /// publishes the old package.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn replace_package_version(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let release_ts = OffsetDateTime::now_utc();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_package (id, tenant_id, package, version, architecture, maintainer, description, paragraph, size, s3_bucket, md5sum, sha1sum, sha256sum, created_at, updated_at)
+            SELECT 1003, tenant_id, package, version, architecture, maintainer, 'Rebuilt test package for amd64', paragraph, size, s3_bucket, 'rebuiltmd5sum', 'rebuiltsha1sum', 'rebuiltsha256sum', NOW(), NOW()
+            FROM debian_repository_package
+            WHERE id = 1001
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let add = |replace: bool| PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Add {
+                package_sha256sum: String::from("rebuiltsha256sum"),
+                replace,
+            },
+        };
+        let published = |result: &PackageChangeResult| {
+            result.changed_packages_indexes[0]
+                .packages_index
+                .contents
+                .lines()
+                .filter_map(|line| line.strip_prefix("SHA256: "))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        // Without replacing, adding a different build of a published package
+        // is a conflict.
+        let err = generate_release_file_with_change(&mut tx, &tenant_id, &add(false), release_ts)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert_eq!(err.error, "PACKAGE_VERSION_CONFLICT");
+
+        let result = generate_release_file_with_change(&mut tx, &tenant_id, &add(true), release_ts)
+            .await
+            .unwrap();
+        assert_eq!(published(&result), ["rebuiltsha256sum"]);
+        let replaced = result.replaced_package.unwrap();
+        assert_eq!(replaced.package.sha256sum, "amd64sha256sum");
+        assert_eq!(replaced.filename, result.changed_package.filename);
+
+        // Once the old package is also published in another distribution,
+        // overwriting its pool file would break that distribution.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_release (id, repository_id, distribution, suite, codename, contents, created_at, updated_at)
+            VALUES (1001, 1000, 'testing', 'testing', 'testing', 'dummy content', NOW(), NOW())
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component (id, release_id, name, created_at, updated_at)
+            VALUES (1001, 1001, 'main', NOW(), NOW())
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            VALUES (1001, 1001, 'pool/main/t/test-package/test-package_1.0.0_amd64.deb', NOW(), NOW())
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let err = generate_release_file_with_change(&mut tx, &tenant_id, &add(true), release_ts)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert_eq!(err.error, "REPLACED_PACKAGE_IN_USE");
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Packages with `Architecture: all` are published in the index of every
+    /// architecture, and removed from all of them.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn all_packages_published_in_every_architecture(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let release_ts = OffsetDateTime::now_utc();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_package (id, tenant_id, package, version, architecture, maintainer, description, paragraph, size, s3_bucket, md5sum, sha1sum, sha256sum, created_at, updated_at)
+            VALUES (
+                1004,
+                1,
+                'test-data',
+                '1.0.0',
+                'all',
+                'test@example.com',
+                'Test data package',
+                '{"Package": "test-data", "Version": "1.0.0", "Architecture": "all"}'::jsonb,
+                1024,
+                'attune-test-0',
+                'allmd5sum',
+                'allsha1sum',
+                'allsha256sum',
+                NOW(),
+                NOW()
+            )
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let add = PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Add {
+                package_sha256sum: String::from("allsha256sum"),
+                replace: false,
+            },
+        };
+        let result = generate_release_file_with_change(&mut tx, &tenant_id, &add, release_ts)
+            .await
+            .unwrap();
+        let architectures = result
+            .changed_packages_indexes
+            .iter()
+            .map(|changed| changed.packages_index.meta.architecture.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(architectures, ["amd64", "arm64"]);
+        for changed in &result.changed_packages_indexes {
+            let packages = changed.packages_index.packages();
+            assert!(packages.iter().any(|p| p.package.name == "test-data"));
+            assert!(
+                changed
+                    .packages_index
+                    .contents
+                    .contains("Architecture: all\n")
+            );
+        }
+        assert!(
+            result
+                .release_file
+                .contents
+                .contains("\nArchitectures: amd64 arm64\n")
+        );
+        assert!(!result.release_file.contents.contains("binary-all"));
+
+        // Scoped changes can't change more than one index.
+        let err = generate_release_file_with_change(
+            &mut tx,
+            &tenant_id,
+            &PackageChange {
+                architecture: Some(String::from("all")),
+                ..add
+            },
+            release_ts,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.error, "ARCHITECTURE_MISMATCH");
+
+        // Once published, removing the package removes it from every index.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            VALUES (1000, 1004, 'pool/main/t/test-data/test-data_1.0.0_all.deb', NOW(), NOW())
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let remove = PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Remove {
+                name: String::from("test-data"),
+                version: String::from("1.0.0"),
+                architecture: String::from("all"),
+            },
+        };
+        let result = generate_release_file_with_change(&mut tx, &tenant_id, &remove, release_ts)
+            .await
+            .unwrap();
+        assert_eq!(result.changed_packages_indexes.len(), 2);
+        for changed in &result.changed_packages_indexes {
+            assert!(!changed.packages_index.contents.contains("test-data"));
+            assert!(changed.packages_index.contents.contains("test-package"));
+        }
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// `Architecture: all` packages can't be added to a distribution without
+    /// any architectures, since they wouldn't be published in any index.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn all_packages_need_architectures(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_package (id, tenant_id, package, version, architecture, maintainer, description, paragraph, size, s3_bucket, md5sum, sha1sum, sha256sum, created_at, updated_at)
+            SELECT 1004, tenant_id, 'test-data', version, 'all', maintainer, description, '{"Package": "test-data", "Version": "1.0.0", "Architecture": "all"}'::jsonb, size, s3_bucket, 'allmd5sum', 'allsha1sum', 'allsha256sum', NOW(), NOW()
+            FROM debian_repository_package
+            WHERE id = 1001
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let err = generate_release_file_with_change(
+            &mut tx,
+            &tenant_id,
+            &PackageChange {
+                repository: String::from("test-multi-arch"),
+                distribution: String::from("unstable"),
+                component: String::from("main"),
+                architecture: None,
+                action: PackageChangeAction::Add {
+                    package_sha256sum: String::from("allsha256sum"),
+                    replace: false,
+                },
+            },
+            OffsetDateTime::now_utc(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.error, "NO_ARCHITECTURES");
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Distributions that are created by their first package publish the
+    /// default compressed indexes.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn default_index_compression(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = crate::api::TenantID(1);
+        let change = PackageChange {
+            repository: String::from("test-multi-arch"),
+            distribution: String::from("unstable"),
+            component: String::from("main"),
+            architecture: None,
+            action: PackageChangeAction::Add {
+                package_sha256sum: String::from("amd64sha256sum"),
+                replace: false,
+            },
+        };
+        let result = generate_release_file_with_change(
+            &mut tx,
+            &tenant_id,
+            &change,
+            OffsetDateTime::now_utc(),
+        )
+        .await
+        .unwrap();
+        let [changed] = result.changed_packages_indexes.as_slice() else {
+            panic!("expected one changed index");
+        };
+        assert_eq!(
+            changed
+                .compressed_indexes
+                .iter()
+                .map(|index| index.meta.compression)
+                .collect::<Vec<_>>(),
+            DEFAULT_INDEX_COMPRESSION
+                .iter()
+                .copied()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+        for compression in DEFAULT_INDEX_COMPRESSION {
+            assert!(
+                result.release_file.contents.contains(&format!(
+                    "main/binary-amd64/Packages{}\n",
+                    compression.extension()
+                )),
+                "{}",
+                result.release_file.contents
+            );
+        }
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Each component and architecture has at most one uncompressed Packages
+    /// index, even though its compression is NULL.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR", fixtures("setup_multi_arch"))]
+    async fn unique_uncompressed_index(pool: sqlx::PgPool) {
+        let err = sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_index_packages (component_id, architecture, compression, size, contents, md5sum, sha1sum, sha256sum, created_at, updated_at)
+            VALUES (1000, 'amd64', NULL, 0, ''::bytea, 'md5', 'sha1', 'sha256', NOW(), NOW())
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap_err();
+        assert!(
+            err.as_database_error()
+                .is_some_and(|err| err.is_unique_violation()),
+            "{err}"
+        );
+    }
 }
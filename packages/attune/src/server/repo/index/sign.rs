@@ -1,31 +1,43 @@
-use aws_sdk_s3::types::ChecksumAlgorithm;
+use std::{
+    iter::once,
+    time::{Duration, Instant},
+};
+
 use axum::{
     Json,
     extract::{Path, State},
     http::StatusCode,
 };
-use base64::Engine as _;
-use lazy_regex::lazy_regex;
-use md5::{Digest as _, Md5};
+use bytes::Bytes;
 use pgp::composed::{
     CleartextSignedMessage, Deserializable as _, SignedPublicKey, StandaloneSignature,
 };
+use pgp::types::KeyDetails as _;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
+use sha2::{Digest as _, Sha256};
+use sqlx::PgPool;
 use time::OffsetDateTime;
 use tracing::{debug, instrument};
 
 use crate::{
     api::{ErrorResponse, TenantID},
+    apt::{Compression, Package, ReleaseFile},
     server::{
         ServerState,
+        cdn::{CloudFrontInvalidator, invalidation_paths},
+        object_store::ObjectStore,
         repo::{
             decode_repo_name,
+            dist::{validate_component_name, validate_dist_name},
             index::{
-                PackageChange, PackageChangeAction, PackageChangeResult,
-                generate_release_file_with_change,
+                ChangedPackagesIndex, ContentsEncoding, PackageChange, PackageChangeAction,
+                PackageChangeResult, check_clock_skew, deletion, encode_contents,
+                generate_release_file_with_change, validate_release_ts,
             },
+            validate_repo_name_matches,
         },
+        s3_concurrency::S3Concurrency,
+        webhook::{PackageMeta, PublishAction, PublishEvent, Webhook},
     },
 };
 
@@ -33,13 +45,22 @@ use crate::{
 pub struct SignIndexRequest {
     pub change: PackageChange,
     pub release_ts: OffsetDateTime,
+    /// The `InRelease` file. This may hold signatures from several keys,
+    /// e.g. the old and new keys during key rotation.
     pub clearsigned: String,
+    /// The `Release.gpg` file, which may hold several signatures in the same
+    /// way as `clearsigned`.
     pub detachsigned: String,
+    /// The armored public keys that made the signatures.
     pub public_key_cert: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct SignIndexResponse {}
+pub struct SignIndexResponse {
+    /// The hex-encoded fingerprints of the keys that signed the Release file.
+    #[serde(default)]
+    pub fingerprints: Vec<String>,
+}
 
 #[axum::debug_handler]
 #[instrument(skip(state, req))]
@@ -50,25 +71,18 @@ pub async fn handler(
     Json(req): Json<SignIndexRequest>,
 ) -> Result<Json<SignIndexResponse>, ErrorResponse> {
     debug!(?req, "signing index");
+    let start = Instant::now();
 
     // The repository name in the path is percent-encoded.
     let repo_name = decode_repo_name(&repo_name)?;
-    if repo_name != req.change.repository {
-        return Err(ErrorResponse::new(
-            StatusCode::BAD_REQUEST,
-            "REPOSITORY_MISMATCH".to_string(),
-            "repository name in path does not match repository name in request".to_string(),
-        ));
-    }
-
-    if !lazy_regex!(r"^[a-zA-Z0-9_-]+$").is_match(&req.change.component) {
-        return Err(ErrorResponse::new(
-            StatusCode::BAD_REQUEST,
-            String::from("INVALID_COMPONENT_NAME"),
-            String::from(
-                "component name must contain only letters, numbers, underscores, and hyphens",
-            ),
-        ));
+    validate_repo_name_matches(&repo_name, &req.change.repository)?;
+    validate_release_ts(req.release_ts)?;
+
+    validate_dist_name(&req.change.distribution)?;
+    // Re-signing doesn't change any component, so its component is unused.
+    let resign = matches!(req.change.action, PackageChangeAction::Resign);
+    if !resign {
+        validate_component_name(&req.change.component)?;
     }
 
     // Start a Serializable database transaction.
@@ -77,25 +91,34 @@ pub async fn handler(
         .execute(&mut *tx)
         .await
         .map_err(ErrorResponse::from)?;
-
-    // Load the repository. If it does not exist, return an error.
-    let repo = sqlx::query_as!(
-        Repository,
-        r#"
-        SELECT s3_bucket, s3_prefix
-        FROM debian_repository
-        WHERE tenant_id = $1 AND name = $2
-        "#,
-        tenant_id.0,
-        repo_name
-    )
-    .fetch_optional(&mut *tx)
-    .await
-    .map_err(ErrorResponse::from)?
-    .ok_or(ErrorResponse::not_found("repository"))?;
+    check_clock_skew(&mut tx).await?;
+
+    let repo = Repository::query(&mut tx, &tenant_id, &repo_name).await?;
+
+    if resign {
+        let release = resign_in_db(&mut tx, &tenant_id, &req).await?;
+        tx.commit().await.map_err(ErrorResponse::from)?;
+        upload_release_files(
+            state.object_store.as_ref(),
+            &state.s3_concurrency,
+            &repo,
+            &req,
+            release,
+        )
+        .await;
+        invalidate_cdn(state.cdn.as_ref(), &repo, &req, &[]).await;
+        notify_webhook(&repo, &req, None);
+        if let Some(metrics) = &state.metrics {
+            metrics.record_index_sign(false, start.elapsed());
+        }
+        return Ok(Json(SignIndexResponse {
+            fingerprints: fingerprints(&req.public_key_cert)?,
+        }));
+    }
 
     // Apply the change to the database.
-    let (result, previous_by_hash_indexes) = apply_change_to_db(&mut tx, &tenant_id, &req).await?;
+    let (result, previous_by_hash_indexes) =
+        apply_change_to_db(&mut tx, &tenant_id, &req, state.index_contents_encoding).await?;
 
     // Commit the transaction. At this point, the transaction may abort because
     // of a concurrent index change. This should trigger the client to retry.
@@ -123,37 +146,222 @@ pub async fn handler(
     // unlikely, but there is no good mitigation here besides a cron job. Note
     // that any _subsequent_ upload will still upload the correct indexes,
     // because the _database_ state is transactionally consistent.
-    apply_change_to_s3(&state.s3, &repo, &req, &result, previous_by_hash_indexes).await;
+    apply_change_to_s3(
+        &state.db,
+        state.object_store.as_ref(),
+        &state.s3_concurrency,
+        state.index_deletion_grace,
+        state.cdn.as_ref(),
+        &repo,
+        &req,
+        &result,
+        previous_by_hash_indexes,
+    )
+    .await?;
+    if let Some(metrics) = &state.metrics {
+        metrics.record_index_sign(false, start.elapsed());
+    }
 
-    Ok(Json(SignIndexResponse {}))
+    Ok(Json(SignIndexResponse {
+        fingerprints: fingerprints(&req.public_key_cert)?,
+    }))
 }
 
-async fn apply_change_to_db(
-    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    tenant_id: &TenantID,
+/// Verify the request's public keys and clearsigned Release file, returning the
+/// public keys.
+pub(super) fn verify_public_keys(
     req: &SignIndexRequest,
-) -> Result<(PackageChangeResult, Option<PreviousByHashIndexes>), ErrorResponse> {
-    // Verify the request cleartext signature.
-    let (public_key, _headers) = SignedPublicKey::from_string(&req.public_key_cert)
-        .expect("could not parse public key certificate");
-    debug!(?public_key, "public key");
-    if let Err(e) = public_key.verify() {
+) -> Result<Vec<SignedPublicKey>, ErrorResponse> {
+    let public_keys = parse_public_keys(&req.public_key_cert)?;
+    debug!(?public_keys, "public keys");
+    if public_keys.is_empty() {
         return Err(ErrorResponse::new(
             StatusCode::BAD_REQUEST,
             "PUBLIC_KEY_VERIFICATION_FAILED".to_string(),
-            format!("could not verify public key: {e}"),
+            "no public key provided".to_string(),
         ));
     }
-    let (clearsigned, _headers) = CleartextSignedMessage::from_string(&req.clearsigned)
-        .expect("could not parse clearsigned index");
+    for public_key in &public_keys {
+        if let Err(e) = public_key.verify() {
+            return Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "PUBLIC_KEY_VERIFICATION_FAILED".to_string(),
+                format!("could not verify public key: {e}"),
+            ));
+        }
+    }
+    let (clearsigned, _headers) =
+        CleartextSignedMessage::from_string(&req.clearsigned).map_err(|e| {
+            ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "CLEARSIGN_VERIFICATION_FAILED".to_string(),
+                format!("could not parse clearsigned index: {e}"),
+            )
+        })?;
     debug!(clearsigned = ?clearsigned.text(), "clearsigned index");
-    if let Err(e) = clearsigned.verify(&public_key) {
+    let signed_text = clearsigned.signed_text();
+    if let Err(e) = verify_signatures(
+        clearsigned.signatures(),
+        signed_text.as_bytes(),
+        &public_keys,
+    ) {
         return Err(ErrorResponse::new(
             StatusCode::BAD_REQUEST,
             "CLEARSIGN_VERIFICATION_FAILED".to_string(),
             format!("could not verify clearsigned index: {e}"),
         ));
     }
+    Ok(public_keys)
+}
+
+/// Verify that the request's detached signatures are signatures over the given
+/// Release file contents.
+pub(super) fn verify_detached_signature(
+    req: &SignIndexRequest,
+    public_keys: &[SignedPublicKey],
+    contents: &str,
+) -> Result<(), ErrorResponse> {
+    let parse_error = |e: pgp::errors::Error| {
+        ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "DETACHED_SIGNATURE_VERIFICATION_FAILED".to_string(),
+            format!("could not parse detached signature: {e}"),
+        )
+    };
+    let (detachsigned, _headers) =
+        StandaloneSignature::from_string_many(&req.detachsigned).map_err(parse_error)?;
+    let detachsigned = detachsigned
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(parse_error)?;
+    debug!(index = ?contents, ?detachsigned, "detachsigned index");
+    if let Err(e) = verify_signatures(&detachsigned, contents.as_bytes(), public_keys) {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "DETACHED_SIGNATURE_VERIFICATION_FAILED".to_string(),
+            format!(
+                "could not verify detached signature (index content mismatch or signature invalid): {e}"
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that there is at least one signature over the data, and that every
+/// signature was made by one of the keys.
+fn verify_signatures(
+    signatures: &[StandaloneSignature],
+    data: &[u8],
+    public_keys: &[SignedPublicKey],
+) -> Result<(), String> {
+    if signatures.is_empty() {
+        return Err(String::from("no signatures found"));
+    }
+    for signature in signatures {
+        if !public_keys
+            .iter()
+            .any(|public_key| signature.verify(public_key, data).is_ok())
+        {
+            return Err(format!(
+                "signature by {:?} does not match any public key",
+                signature.signature.issuer()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parse every public key in an armored public key certificate.
+fn parse_public_keys(public_key_cert: &str) -> Result<Vec<SignedPublicKey>, ErrorResponse> {
+    let parse_error = |e: pgp::errors::Error| {
+        ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "PUBLIC_KEY_VERIFICATION_FAILED".to_string(),
+            format!("could not parse public key certificate: {e}"),
+        )
+    };
+    let (public_keys, _headers) =
+        SignedPublicKey::from_string_many(public_key_cert).map_err(parse_error)?;
+    public_keys
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(parse_error)
+}
+
+/// The hex-encoded fingerprints of the keys in a verified public key
+/// certificate.
+pub(super) fn fingerprints(public_key_cert: &str) -> Result<Vec<String>, ErrorResponse> {
+    Ok(parse_public_keys(public_key_cert)?
+        .iter()
+        .map(|public_key| hex::encode_upper(public_key.fingerprint().as_bytes()))
+        .collect())
+}
+
+/// Replace the signatures of the distribution's current Release file without
+/// changing its contents, returning the Release file contents.
+async fn resign_in_db(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tenant_id: &TenantID,
+    req: &SignIndexRequest,
+) -> Result<String, ErrorResponse> {
+    let release = sqlx::query!(
+        r#"
+        SELECT debian_repository_release.id, debian_repository_release.contents
+        FROM
+            debian_repository
+            JOIN debian_repository_release ON debian_repository_release.repository_id = debian_repository.id
+        WHERE
+            debian_repository.tenant_id = $1
+            AND debian_repository.name = $2
+            AND debian_repository_release.distribution = $3
+        "#,
+        tenant_id.0,
+        req.change.repository,
+        req.change.distribution,
+    )
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or(ErrorResponse::not_found("distribution"))?;
+    if release.contents.is_empty() {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "DISTRIBUTION_NOT_PUBLISHED".to_string(),
+            "the distribution has no Release file to re-sign".to_string(),
+        ));
+    }
+
+    // The Release file isn't regenerated, so the signatures must be over its
+    // current contents. This also rejects signatures over a Release file that
+    // was changed concurrently.
+    let public_keys = verify_public_keys(req)?;
+    verify_detached_signature(req, &public_keys, &release.contents)?;
+
+    sqlx::query!(
+        r#"
+        UPDATE debian_repository_release
+        SET
+            clearsigned = $2,
+            detached = $3,
+            updated_at = NOW()
+        WHERE id = $1
+        "#,
+        release.id,
+        req.clearsigned,
+        req.detachsigned,
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+    Ok(release.contents)
+}
+
+async fn apply_change_to_db(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tenant_id: &TenantID,
+    req: &SignIndexRequest,
+    contents_encoding: Option<ContentsEncoding>,
+) -> Result<(PackageChangeResult, Vec<PreviousByHashIndexes>), ErrorResponse> {
+    // Verify the request cleartext signature.
+    let public_keys = verify_public_keys(req)?;
 
     // Replay the diff onto the current state of the index. Since index
     // generation is deterministic, this should yield the same index that was
@@ -165,40 +373,58 @@ async fn apply_change_to_db(
     // Compare the replayed index with the signed index.
     // If the signatures match, this validates that the index signed by the client
     // is the same as the one we replayed.
-    let (detachsigned, _headers) = StandaloneSignature::from_string(&req.detachsigned)
-        .expect("could not parse detached signature");
-    debug!(index = ?result.release_file.contents, ?detachsigned, "detachsigned index");
-    if let Err(e) = detachsigned.verify(&public_key, result.release_file.contents.as_bytes()) {
-        return Err(ErrorResponse::new(
-            StatusCode::BAD_REQUEST,
-            "DETACHED_SIGNATURE_VERIFICATION_FAILED".to_string(),
-            format!(
-                "could not verify detached signature (index content mismatch or signature invalid): {e}"
-            ),
-        ));
-    }
+    verify_detached_signature(req, &public_keys, &result.release_file.contents)?;
+
+    let previous_by_hash_indexes =
+        save_change_to_db(tx, tenant_id, req, &result, contents_encoding).await?;
+    Ok((result, previous_by_hash_indexes))
+}
 
-    // Save the new state to the database.
-    let previous_by_hash_indexes = match req.change.action {
-        PackageChangeAction::Add { .. } => add_package_to_db(tx, tenant_id, req, &result).await?,
+/// Save the state produced by a change to the database, returning the hashes
+/// of the Packages indexes it replaced.
+pub(super) async fn save_change_to_db(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    tenant_id: &TenantID,
+    req: &SignIndexRequest,
+    result: &PackageChangeResult,
+    contents_encoding: Option<ContentsEncoding>,
+) -> Result<Vec<PreviousByHashIndexes>, ErrorResponse> {
+    match req.change.action {
+        PackageChangeAction::Add { .. } => {
+            add_package_to_db(tx, tenant_id, req, result, contents_encoding).await
+        }
         PackageChangeAction::Remove {
             ref name,
             ref version,
-            ref architecture,
-        } => Some(
-            remove_package_from_db(tx, tenant_id, req, &result, name, version, architecture)
-                .await?,
-        ),
-    };
-
-    Ok((result, previous_by_hash_indexes))
+            ..
+        } => {
+            // The requested architecture may be an alias, so use the
+            // architecture of the package that was found.
+            let architecture = &result.changed_package.package.architecture;
+            remove_package_from_db(
+                tx,
+                tenant_id,
+                req,
+                result,
+                contents_encoding,
+                name,
+                version,
+                architecture,
+            )
+            .await
+        }
+        PackageChangeAction::Resign => unreachable!("re-signing doesn't apply a change"),
+    }
 }
 
 #[derive(Debug)]
-struct PreviousByHashIndexes {
+pub(super) struct PreviousByHashIndexes {
+    architecture: String,
+    compression: Option<Compression>,
     md5sum: String,
     sha1sum: String,
     sha256sum: String,
+    sha512sum: Option<String>,
 }
 
 async fn add_package_to_db(
@@ -206,7 +432,8 @@ async fn add_package_to_db(
     tenant_id: &TenantID,
     req: &SignIndexRequest,
     update: &PackageChangeResult,
-) -> Result<Option<PreviousByHashIndexes>, ErrorResponse> {
+    contents_encoding: Option<ContentsEncoding>,
+) -> Result<Vec<PreviousByHashIndexes>, ErrorResponse> {
     // First, we update-or-create the Release. Remember, it's possible that no
     // package has ever been added to this distribution, so the Release may not
     // exist.
@@ -389,10 +616,93 @@ async fn add_package_to_db(
         }
     };
 
-    // Then, we update-or-create the Packages index of the changed package.
+    // Then, we update-or-create the changed Packages indexes.
+    let mut previous_by_hash_indexes = Vec::new();
+    for changed in &update.changed_packages_indexes {
+        previous_by_hash_indexes
+            .extend(upsert_packages_index(tx, component_id, changed, contents_encoding).await?);
+    }
+
+    // If the package replaces another, remove the replaced component-package.
+    if let Some(replaced) = &update.replaced_package {
+        sqlx::query!(
+            r#"
+            DELETE FROM debian_repository_component_package
+            USING debian_repository_package
+            WHERE
+                debian_repository_component_package.package_id = debian_repository_package.id
+                AND debian_repository_component_package.component_id = $1
+                AND debian_repository_package.tenant_id = $2
+                AND debian_repository_package.sha256sum = $3
+            "#,
+            component_id,
+            tenant_id.0,
+            replaced.package.sha256sum,
+        )
+        .execute(&mut **tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    }
+
+    // Lastly, we create the component-package.
+    //
+    // This record should not previously exist, but we use ON CONFLICT DO
+    // NOTHING because we consider re-adding an identical package to be a no-op
+    // rather than an error. The conflict target is deliberately narrow: a
+    // different build with the same pool filename still violates the unique
+    // (component_id, filename) index, since a component can only publish one
+    // build of each package version.
+    sqlx::query!(
+        r#"
+        WITH package_cte AS (
+            SELECT id
+            FROM debian_repository_package
+            WHERE
+                tenant_id = $1
+                AND sha256sum = $2
+            LIMIT 1
+        )
+        INSERT INTO debian_repository_component_package (
+            component_id,
+            package_id,
+            filename,
+            created_at,
+            updated_at
+        )
+        SELECT
+            $3,
+            package_cte.id,
+            $4,
+            NOW(),
+            NOW()
+        FROM package_cte
+        ON CONFLICT (component_id, package_id) DO NOTHING
+        "#,
+        tenant_id.0,
+        update.changed_package.package.sha256sum,
+        component_id,
+        update.changed_package.filename,
+    )
+    .execute(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    Ok(previous_by_hash_indexes)
+}
+
+/// Update-or-create a Packages index that a package was added to, returning
+/// the hashes of its previous variants so that their by-hash files can be
+/// deleted.
+async fn upsert_packages_index(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    component_id: i64,
+    changed: &ChangedPackagesIndex,
+    contents_encoding: Option<ContentsEncoding>,
+) -> Result<Vec<PreviousByHashIndexes>, ErrorResponse> {
+    // Update-or-create the uncompressed index.
     let previous_by_hash_indexes = match sqlx::query!(
         r#"
-        SELECT id, md5sum, sha1sum, sha256sum
+        SELECT id, md5sum, sha1sum, sha256sum, sha512sum
         FROM debian_repository_index_packages
         WHERE
             component_id = $1
@@ -401,7 +711,7 @@ async fn add_package_to_db(
         LIMIT 1
         "#,
         component_id,
-        update.changed_packages_index.meta.architecture as _,
+        changed.packages_index.meta.architecture as _,
     )
     .fetch_optional(&mut **tx)
     .await
@@ -412,9 +722,12 @@ async fn add_package_to_db(
             // previous Packages index since its by-hash files need to be
             // deleted after the update.
             let previous_by_hash_indexes = PreviousByHashIndexes {
+                architecture: changed.packages_index.meta.architecture.clone(),
+                compression: None,
                 md5sum: index.md5sum,
                 sha1sum: index.sha1sum,
                 sha256sum: index.sha256sum,
+                sha512sum: index.sha512sum,
             };
 
             // No need to check whether an update is needed - we know already
@@ -424,19 +737,26 @@ async fn add_package_to_db(
                 UPDATE debian_repository_index_packages
                 SET
                     contents = $2,
+                    contents_encoding = $7,
                     size = $3,
                     md5sum = $4,
                     sha1sum = $5,
                     sha256sum = $6,
+                    sha512sum = $8,
                     updated_at = NOW()
                 WHERE id = $1
                 "#,
                 index.id,
-                update.changed_packages_index.contents.as_bytes(),
-                update.changed_packages_index.meta.size,
-                update.changed_packages_index.meta.md5sum,
-                update.changed_packages_index.meta.sha1sum,
-                update.changed_packages_index.meta.sha256sum,
+                encode_contents(
+                    contents_encoding,
+                    changed.packages_index.contents.as_bytes()
+                ),
+                changed.packages_index.meta.size,
+                changed.packages_index.meta.md5sum,
+                changed.packages_index.meta.sha1sum,
+                changed.packages_index.meta.sha256sum,
+                contents_encoding as _,
+                changed.packages_index.meta.sha512sum,
             )
             .execute(&mut **tx)
             .await
@@ -453,9 +773,11 @@ async fn add_package_to_db(
                     compression,
                     size,
                     contents,
+                    contents_encoding,
                     md5sum,
                     sha1sum,
                     sha256sum,
+                    sha512sum,
                     created_at,
                     updated_at
                 )
@@ -468,18 +790,25 @@ async fn add_package_to_db(
                     $5,
                     $6,
                     $7,
+                    $8,
+                    $9,
                     NOW(),
                     NOW()
                 )
                 "#,
                 component_id,
-                update.changed_packages_index.meta.architecture as _,
+                changed.packages_index.meta.architecture as _,
                 // compression = NULL,
-                update.changed_packages_index.meta.size,
-                update.changed_packages_index.contents.as_bytes(),
-                update.changed_packages_index.meta.md5sum,
-                update.changed_packages_index.meta.sha1sum,
-                update.changed_packages_index.meta.sha256sum,
+                changed.packages_index.meta.size,
+                encode_contents(
+                    contents_encoding,
+                    changed.packages_index.contents.as_bytes()
+                ),
+                contents_encoding as _,
+                changed.packages_index.meta.md5sum,
+                changed.packages_index.meta.sha1sum,
+                changed.packages_index.meta.sha256sum,
+                changed.packages_index.meta.sha512sum,
             )
             .execute(&mut **tx)
             .await
@@ -488,58 +817,24 @@ async fn add_package_to_db(
         }
     };
 
-    // Lastly, we create the component-package.
-    //
-    // This record should not previously exist, but we use ON CONFLICT DO
-    // NOTHING because we consider re-adding an identical package to be a no-op
-    // rather than an error.
-    sqlx::query!(
-        r#"
-        WITH package_cte AS (
-            SELECT id
-            FROM debian_repository_package
-            WHERE
-                tenant_id = $1
-                AND sha256sum = $2
-            LIMIT 1
-        )
-        INSERT INTO debian_repository_component_package (
-            component_id,
-            package_id,
-            filename,
-            created_at,
-            updated_at
-        )
-        SELECT
-            $3,
-            package_cte.id,
-            $4,
-            NOW(),
-            NOW()
-        FROM package_cte
-        ON CONFLICT DO NOTHING
-        "#,
-        tenant_id.0,
-        update.changed_package.package.sha256sum,
-        component_id,
-        update.changed_package.filename,
-    )
-    .execute(&mut **tx)
-    .await
-    .map_err(ErrorResponse::from)?;
-
-    Ok(previous_by_hash_indexes)
+    // Replace the compressed variants of the Packages index.
+    Ok(previous_by_hash_indexes
+        .into_iter()
+        .chain(replace_compressed_indexes(tx, component_id, changed).await?)
+        .collect())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn remove_package_from_db(
     tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
     tenant_id: &TenantID,
     req: &SignIndexRequest,
     update: &PackageChangeResult,
+    contents_encoding: Option<ContentsEncoding>,
     package: &str,
     version: &str,
     architecture: &str,
-) -> Result<PreviousByHashIndexes, ErrorResponse> {
+) -> Result<Vec<PreviousByHashIndexes>, ErrorResponse> {
     // Load the component-package, which should be there if the package exists.
     let component_package = sqlx::query!(
         r#"
@@ -589,6 +884,62 @@ async fn remove_package_from_db(
     .await
     .map_err(ErrorResponse::from)?;
 
+    // Update the changed Packages indexes.
+    let mut previous_by_hash_indexes = Vec::new();
+    for changed in &update.changed_packages_indexes {
+        previous_by_hash_indexes.extend(
+            update_packages_index(
+                tx,
+                component_package.component_id,
+                changed,
+                contents_encoding,
+            )
+            .await?,
+        );
+    }
+
+    // Delete the Component if it's orphaned.
+    let remaining_component_packages = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!: i64"
+        FROM debian_repository_component_package
+        WHERE component_id = $1
+        "#,
+        component_package.component_id,
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+    if remaining_component_packages.count == 0 {
+        sqlx::query!(
+            r#"
+            DELETE FROM debian_repository_component
+            WHERE id = $1
+        "#,
+            component_package.component_id,
+        )
+        .execute(&mut **tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    }
+
+    // We do not delete the Release even if it's orphaned, because
+    // clients may still be pointing to the release file, and we don't
+    // want them to be broken. The error they should get from APT is
+    // "package missing", rather than "repository not found".
+
+    Ok(previous_by_hash_indexes)
+}
+
+/// Update a Packages index that a package was removed from, or delete it if
+/// it's now empty, returning the hashes of its previous variants so that their
+/// by-hash files can be deleted.
+async fn update_packages_index(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    component_id: i64,
+    changed: &ChangedPackagesIndex,
+    contents_encoding: Option<ContentsEncoding>,
+) -> Result<Vec<PreviousByHashIndexes>, ErrorResponse> {
     // Load the current state of the changed Packages index. We need to record
     // its hashes so that we can delete the by-hash files after we update this
     // index.
@@ -597,7 +948,8 @@ async fn remove_package_from_db(
         SELECT
             md5sum,
             sha1sum,
-            sha256sum
+            sha256sum,
+            sha512sum
         FROM debian_repository_index_packages
         WHERE
             component_id = $1
@@ -605,20 +957,29 @@ async fn remove_package_from_db(
             AND compression IS NULL
         LIMIT 1
         "#,
-        component_package.component_id,
-        architecture as _,
+        component_id,
+        changed.packages_index.meta.architecture as _,
     )
     .fetch_one(&mut **tx)
     .await
     .map_err(ErrorResponse::from)?;
     let previous_by_hash_indexes = PreviousByHashIndexes {
+        architecture: changed.packages_index.meta.architecture.clone(),
+        compression: None,
         md5sum: previous_by_hash_indexes.md5sum,
         sha1sum: previous_by_hash_indexes.sha1sum,
         sha256sum: previous_by_hash_indexes.sha256sum,
+        sha512sum: previous_by_hash_indexes.sha512sum,
     };
 
+    // Replace the compressed variants of the Packages index. If the index is
+    // now orphaned, this removes all of them.
+    let previous_by_hash_indexes = once(previous_by_hash_indexes)
+        .chain(replace_compressed_indexes(tx, component_id, changed).await?)
+        .collect();
+
     // Update the Packages index, or delete if it's orphaned.
-    if update.changed_packages_index.contents.is_empty() {
+    if changed.packages_index.contents.is_empty() {
         sqlx::query!(
             r#"
             DELETE FROM debian_repository_index_packages
@@ -626,8 +987,8 @@ async fn remove_package_from_db(
                 component_id = $1
                 AND architecture = $2::debian_repository_architecture
         "#,
-            component_package.component_id,
-            architecture as _,
+            component_id,
+            changed.packages_index.meta.architecture as _,
         )
         .execute(&mut **tx)
         .await
@@ -638,109 +999,314 @@ async fn remove_package_from_db(
             UPDATE debian_repository_index_packages
             SET
                 contents = $1,
+                contents_encoding = $8,
                 size = $2,
                 md5sum = $3,
                 sha1sum = $4,
                 sha256sum = $5,
+                sha512sum = $9,
                 updated_at = NOW()
             WHERE
                 component_id = $6
                 AND architecture = $7::debian_repository_architecture
                 AND compression IS NULL
             "#,
-            update.changed_packages_index.contents.as_bytes(),
-            update.changed_packages_index.meta.size,
-            update.changed_packages_index.meta.md5sum,
-            update.changed_packages_index.meta.sha1sum,
-            update.changed_packages_index.meta.sha256sum,
-            component_package.component_id,
-            architecture as _,
+            encode_contents(
+                contents_encoding,
+                changed.packages_index.contents.as_bytes()
+            ),
+            changed.packages_index.meta.size,
+            changed.packages_index.meta.md5sum,
+            changed.packages_index.meta.sha1sum,
+            changed.packages_index.meta.sha256sum,
+            component_id,
+            changed.packages_index.meta.architecture as _,
+            contents_encoding as _,
+            changed.packages_index.meta.sha512sum,
         )
         .execute(&mut **tx)
         .await
         .map_err(ErrorResponse::from)?;
     }
 
-    // Delete the Component if it's orphaned.
-    let remaining_component_packages = sqlx::query!(
+    Ok(previous_by_hash_indexes)
+}
+
+/// Replace the compressed variants of the changed Packages index with newly
+/// generated ones, returning the hashes of the replaced variants so that their
+/// by-hash files can be deleted.
+///
+/// Variants are replaced wholesale rather than updated in place, because the
+/// configured set of compressions may have changed since the index was last
+/// published.
+async fn replace_compressed_indexes(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    component_id: i64,
+    changed: &ChangedPackagesIndex,
+) -> Result<Vec<PreviousByHashIndexes>, ErrorResponse> {
+    let previous = sqlx::query!(
         r#"
-        SELECT COUNT(*) AS "count!: i64"
-        FROM debian_repository_component_package
-        WHERE component_id = $1
+        DELETE FROM debian_repository_index_packages
+        WHERE
+            component_id = $1
+            AND architecture = $2::debian_repository_architecture
+            AND compression IS NOT NULL
+        RETURNING
+            compression AS "compression!: Compression",
+            md5sum,
+            sha1sum,
+            sha256sum,
+            sha512sum
         "#,
-        component_package.component_id,
+        component_id,
+        changed.packages_index.meta.architecture as _,
     )
-    .fetch_one(&mut **tx)
+    .fetch_all(&mut **tx)
     .await
-    .map_err(ErrorResponse::from)?;
-    if remaining_component_packages.count == 0 {
+    .map_err(ErrorResponse::from)?
+    .into_iter()
+    .map(|index| PreviousByHashIndexes {
+        architecture: changed.packages_index.meta.architecture.clone(),
+        compression: Some(index.compression),
+        md5sum: index.md5sum,
+        sha1sum: index.sha1sum,
+        sha256sum: index.sha256sum,
+        sha512sum: index.sha512sum,
+    })
+    .collect();
+
+    for compressed in &changed.compressed_indexes {
         sqlx::query!(
             r#"
-            DELETE FROM debian_repository_component
-            WHERE id = $1
-        "#,
-            component_package.component_id,
+            INSERT INTO debian_repository_index_packages (
+                component_id,
+                architecture,
+                compression,
+                size,
+                contents,
+                md5sum,
+                sha1sum,
+                sha256sum,
+                sha512sum,
+                created_at,
+                updated_at
+            )
+            VALUES (
+                $1,
+                $2::debian_repository_architecture,
+                $3,
+                $4,
+                $5,
+                $6,
+                $7,
+                $8,
+                $9,
+                NOW(),
+                NOW()
+            )
+            "#,
+            component_id,
+            compressed.meta.architecture as _,
+            compressed.meta.compression as _,
+            compressed.meta.size,
+            compressed.contents,
+            compressed.meta.md5sum,
+            compressed.meta.sha1sum,
+            compressed.meta.sha256sum,
+            compressed.meta.sha512sum,
         )
         .execute(&mut **tx)
         .await
         .map_err(ErrorResponse::from)?;
     }
 
-    // We do not delete the Release even if it's orphaned, because
-    // clients may still be pointing to the release file, and we don't
-    // want them to be broken. The error they should get from APT is
-    // "package missing", rather than "repository not found".
-
-    Ok(previous_by_hash_indexes)
+    Ok(previous)
 }
 
-struct Repository {
+pub(super) struct Repository {
+    id: i64,
     s3_bucket: String,
     s3_prefix: String,
+    webhook_url: Option<String>,
+    webhook_secret: Option<String>,
+}
+
+impl Repository {
+    /// Load the repository. If it does not exist, return an error.
+    pub(super) async fn query(
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        tenant_id: &TenantID,
+        name: &str,
+    ) -> Result<Self, ErrorResponse> {
+        sqlx::query_as!(
+            Repository,
+            r#"
+            SELECT id, s3_bucket, s3_prefix, webhook_url, webhook_secret
+            FROM debian_repository
+            WHERE tenant_id = $1 AND name = $2
+            "#,
+            tenant_id.0,
+            name
+        )
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(ErrorResponse::from)?
+        .ok_or(ErrorResponse::not_found("repository"))
+    }
+
+    fn webhook(&self) -> Option<Webhook> {
+        match (&self.webhook_url, &self.webhook_secret) {
+            (Some(url), Some(secret)) => Some(Webhook {
+                url: url.clone(),
+                secret: secret.clone(),
+            }),
+            _ => None,
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn apply_change_to_s3(
-    s3: &aws_sdk_s3::Client,
+    db: &PgPool,
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    deletion_grace: Duration,
+    cdn: Option<&CloudFrontInvalidator>,
     repo: &Repository,
     req: &SignIndexRequest,
     result: &PackageChangeResult,
-    previous_by_hash_indexes: Option<PreviousByHashIndexes>,
+    previous_by_hash_indexes: Vec<PreviousByHashIndexes>,
+) -> Result<(), ErrorResponse> {
+    update_pool(store, concurrency, repo, req, result).await?;
+    let changed_packages_indexes = result.changed_packages_indexes.iter().collect::<Vec<_>>();
+    publish_indexes(
+        db,
+        store,
+        concurrency,
+        deletion_grace,
+        repo,
+        req,
+        &changed_packages_indexes,
+        &result.release_file,
+        previous_by_hash_indexes,
+    )
+    .await;
+    invalidate_cdn(cdn, repo, req, &changed_packages_indexes).await;
+    notify_webhook(repo, req, Some(&result.changed_package.package));
+    Ok(())
+}
+
+/// Invalidate the CDN's cached copies of the files that a change published:
+/// the distribution's Release files, and the changed Packages indexes.
+///
+/// This runs once every upload and deletion is done, so that the CDN can't
+/// cache the old files again while they are being replaced.
+pub(super) async fn invalidate_cdn(
+    cdn: Option<&CloudFrontInvalidator>,
+    repo: &Repository,
+    req: &SignIndexRequest,
+    changed_packages_indexes: &[&ChangedPackagesIndex],
 ) {
+    let Some(cdn) = cdn else {
+        return;
+    };
+    let paths = invalidation_paths(
+        &repo.s3_prefix,
+        &req.change.distribution,
+        &req.change.component,
+        changed_packages_indexes
+            .iter()
+            .map(|changed| changed.packages_index.meta.architecture.as_str()),
+    );
+    cdn.invalidate(paths).await;
+}
+
+/// Notify the repository's webhook, if it has one, that a change was
+/// published. `package` is the added or removed package, if any.
+pub(super) fn notify_webhook(repo: &Repository, req: &SignIndexRequest, package: Option<&Package>) {
+    let Some(webhook) = repo.webhook() else {
+        return;
+    };
+    let action = match req.change.action {
+        PackageChangeAction::Add { .. } => PublishAction::Add,
+        PackageChangeAction::Remove { .. } => PublishAction::Remove,
+        PackageChangeAction::Resign => PublishAction::Resign,
+    };
+    webhook.notify(PublishEvent {
+        repository: req.change.repository.clone(),
+        distribution: req.change.distribution.clone(),
+        component: req.change.component.clone(),
+        action,
+        package: package.map(|package| PackageMeta {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            architecture: package.architecture.clone(),
+        }),
+    });
+}
+
+/// Copy an added package into the repository pool, or delete a removed
+/// package's pool file if no other distribution uses it.
+pub(super) async fn update_pool(
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    repo: &Repository,
+    req: &SignIndexRequest,
+    result: &PackageChangeResult,
+) -> Result<(), ErrorResponse> {
     // Copy the package from its canonical storage location into the repository
     // pool.
+    //
+    // A replaced package has the same pool filename, so this also overwrites
+    // its pool file.
     match req.change.action {
         PackageChangeAction::Add { .. } => {
-            let source_key = format!(
-                "{}/packages/{}",
-                result.changed_package.package.s3_bucket, result.changed_package.package.sha256sum,
-            );
+            let source_key = result.changed_package.package.copy_source();
             let destination_key = format!("{}/{}", repo.s3_prefix, result.changed_package.filename);
             debug!(?source_key, ?destination_key, "copy package to pool");
-            s3.copy_object()
-                .bucket(&repo.s3_bucket)
-                .key(destination_key)
-                .copy_source(source_key)
-                .send()
-                .await
-                .unwrap();
+            copy_package_to_pool(
+                store,
+                concurrency,
+                &repo.s3_bucket,
+                &source_key,
+                &destination_key,
+                &result.changed_package.package.sha256sum,
+            )
+            .await?;
         }
         PackageChangeAction::Remove { .. } => {
             // Delete the pool file from S3 if it's fully orphaned.
             let key = format!("{}/{}", repo.s3_prefix, result.changed_package.filename);
             debug!(?key, "delete pool file from S3");
             if result.orphaned_pool_filename {
-                s3.delete_object()
-                    .bucket(&repo.s3_bucket)
-                    .key(key)
-                    .send()
+                concurrency
+                    .run(store.delete_objects(&repo.s3_bucket, &[key]))
                     .await
                     .unwrap();
             }
         }
+        PackageChangeAction::Resign => unreachable!("re-signing doesn't apply a change"),
     }
+    Ok(())
+}
 
-    // Upload the updated package index files to standard path and all by-hash
-    // paths concurrently.
+/// Upload the changed Packages indexes and the signed Release file, then
+/// delete the by-hash files of the indexes they replaced once
+/// `deletion_grace` has passed.
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn publish_indexes(
+    db: &PgPool,
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    deletion_grace: Duration,
+    repo: &Repository,
+    req: &SignIndexRequest,
+    changed_packages_indexes: &[&ChangedPackagesIndex],
+    release_file: &ReleaseFile,
+    previous_by_hash_indexes: Vec<PreviousByHashIndexes>,
+) {
+    // Upload the updated package index files, and their compressed variants,
+    // to standard paths and all by-hash paths concurrently.
     //
     // Index modifications are split on both sides of the release file upload:
     // - Before release files are uploaded, we upload new index contents.
@@ -748,67 +1314,166 @@ async fn apply_change_to_s3(
     //
     // The intention here is that the current release file _always points to
     // valid files_.
-    let by_hash_prefix = format!(
-        "{}/dists/{}/{}/binary-{}/by-hash",
-        repo.s3_prefix,
-        req.change.distribution,
-        result.changed_packages_index.meta.component,
-        result.changed_packages_index.meta.architecture
-    );
-    if !result.changed_packages_index.contents.is_empty() {
-        let uploads = [
-            format!(
-                "{}/dists/{}/{}/binary-{}/Packages",
-                repo.s3_prefix,
-                req.change.distribution,
-                result.changed_packages_index.meta.component,
-                result.changed_packages_index.meta.architecture
-            ),
-            format!(
-                "{}/SHA256/{}",
-                by_hash_prefix, result.changed_packages_index.meta.sha256sum
-            ),
-            format!(
-                "{}/SHA1/{}",
-                by_hash_prefix, result.changed_packages_index.meta.sha1sum
-            ),
-            format!(
-                "{}/MD5Sum/{}",
-                by_hash_prefix, result.changed_packages_index.meta.md5sum
-            ),
-        ]
-        .into_iter()
-        .map(|key: String| {
-            let bucket = &repo.s3_bucket;
-            let contents = &result.changed_packages_index.contents;
-            let sha256sum = &result.changed_packages_index.meta.sha256sum;
-
-            async move {
-                debug!(?key, content = %contents, "uploading index file");
-                s3.put_object()
-                    .bucket(bucket)
-                    .key(key)
-                    .content_md5(
-                        base64::engine::general_purpose::STANDARD
-                            .encode(Md5::digest(contents.as_bytes())),
-                    )
-                    .checksum_algorithm(ChecksumAlgorithm::Sha256)
-                    .checksum_sha256(
-                        base64::engine::general_purpose::STANDARD
-                            .encode(hex::decode(sha256sum).unwrap()),
-                    )
-                    .body(contents.as_bytes().to_vec().into())
-                    .send()
-                    .await
-            }
-        });
-        for upload in futures_util::future::join_all(uploads).await {
-            upload.unwrap();
+    let index_prefix = |architecture: &str| {
+        format!(
+            "{}/dists/{}/{}/binary-{}",
+            repo.s3_prefix, req.change.distribution, req.change.component, architecture
+        )
+    };
+    let changed_indexes = changed_packages_indexes
+        .iter()
+        .filter(|changed| !changed.packages_index.contents.is_empty())
+        .flat_map(|changed| {
+            once((
+                &changed.packages_index.meta,
+                changed.packages_index.contents.as_bytes(),
+            ))
+            .chain(
+                changed
+                    .compressed_indexes
+                    .iter()
+                    .map(|compressed| (&compressed.meta, compressed.contents.as_slice())),
+            )
+        })
+        .collect::<Vec<_>>();
+    //
+    // Distributions that only publish SHA256 checksums don't get the legacy
+    // by-hash copies, since the Release file doesn't list those hashes.
+    let sha256_only = release_file.meta.sha256_only;
+    let uploads = changed_indexes
+        .iter()
+        .flat_map(|(meta, contents)| {
+            let index_prefix = index_prefix(&meta.architecture);
+            let by_hash_prefix = format!("{index_prefix}/by-hash");
+            let standard = [
+                format!(
+                    "{}/Packages{}",
+                    index_prefix,
+                    meta.compression.map(|c| c.extension()).unwrap_or_default()
+                ),
+                format!("{}/SHA256/{}", by_hash_prefix, meta.sha256sum),
+            ];
+            let sha512 = meta
+                .sha512sum
+                .as_ref()
+                .map(|sha512sum| format!("{by_hash_prefix}/SHA512/{sha512sum}"));
+            let legacy = [
+                format!("{}/SHA1/{}", by_hash_prefix, meta.sha1sum),
+                format!("{}/MD5Sum/{}", by_hash_prefix, meta.md5sum),
+            ];
+            standard
+                .into_iter()
+                .chain(sha512)
+                .chain(legacy.into_iter().filter(|_| !sha256_only))
+                .map(|key| (key, *meta, *contents))
+        })
+        .collect::<Vec<_>>();
+    let uploaded_keys = uploads
+        .iter()
+        .map(|(key, _, _)| key.clone())
+        .collect::<Vec<_>>();
+    // Earlier changes may have replaced files that this change publishes
+    // again, so they must no longer be deleted.
+    deletion::cancel(db, &repo.s3_bucket, &uploaded_keys).await;
+    let uploads = uploads.into_iter().map(|(key, meta, contents)| {
+        let bucket = &repo.s3_bucket;
+        let sha256sum = &meta.sha256sum;
+
+        async move {
+            debug!(?key, content = %String::from_utf8_lossy(contents), "uploading index file");
+            store
+                .put_object(bucket, &key, Bytes::copy_from_slice(contents), sha256sum)
+                .await
         }
+    });
+    for upload in concurrency.join_all(uploads).await {
+        upload.unwrap();
     }
 
     // Upload the updated Release files. This must happen after package uploads
     // and index uploads so that all files are in place for Acquire-By-Hash.
+    upload_release_files(store, concurrency, repo, req, release_file.contents.clone()).await;
+
+    // Now we can do deletions: the release files are uploaded and are no longer
+    // pointing at the by-hash Packages indexes that we're about to delete.
+    // Clients may still be using the previous release files, though, so the
+    // deletions are deferred.
+    let deletions = previous_by_hash_indexes
+        .into_iter()
+        .flat_map(
+            |PreviousByHashIndexes {
+                 architecture,
+                 compression,
+                 md5sum,
+                 sha1sum,
+                 sha256sum,
+                 sha512sum,
+             }| {
+                let index_prefix = index_prefix(&architecture);
+                let by_hash_prefix = format!("{index_prefix}/by-hash");
+                let current = changed_indexes.iter().map(|(meta, _)| *meta).find(|meta| {
+                    meta.architecture == architecture && meta.compression == compression
+                });
+                let by_hash = [
+                    (md5sum, current.map(|meta| &meta.md5sum), "MD5Sum"),
+                    (sha1sum, current.map(|meta| &meta.sha1sum), "SHA1"),
+                    (sha256sum, current.map(|meta| &meta.sha256sum), "SHA256"),
+                ]
+                .into_iter()
+                .chain(sha512sum.map(|sha512sum| {
+                    (
+                        sha512sum,
+                        current.and_then(|meta| meta.sha512sum.as_ref()),
+                        "SHA512",
+                    )
+                }))
+                // This step is needed because the old hash might equal the new
+                // hash! This can occur if you upload a package that was already
+                // in the index, in which case adding the package to the index
+                // is a no-op. In that case, we don't want to delete the "old"
+                // (but actually still up-to-date) index.
+                .filter(|(old_hash, new_hash, _)| Some(old_hash) != *new_hash)
+                .map(|(old_hash, _, hash_type)| format!("{by_hash_prefix}/{hash_type}/{old_hash}"));
+
+                // Compressed variants that are no longer published (because the
+                // index is now empty, or because the compression is no longer
+                // configured) are no longer referenced by the release files, so
+                // their standard paths are also deleted.
+                let standard = compression
+                    .filter(|_| current.is_none())
+                    .map(|compression| {
+                        format!("{index_prefix}/Packages{}", compression.extension())
+                    });
+
+                by_hash.chain(standard).collect::<Vec<_>>()
+            },
+        )
+        .collect::<Vec<_>>();
+    debug!(?deletions, "deletions");
+    deletion::defer(
+        db,
+        store,
+        concurrency,
+        deletion_grace,
+        repo.id,
+        &repo.s3_bucket,
+        deletions,
+    )
+    .await;
+}
+
+/// The number of times to try copying a package into the pool before giving
+/// up.
+const POOL_COPY_ATTEMPTS: usize = 3;
+
+/// Upload the signed and unsigned Release files of a distribution.
+async fn upload_release_files(
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    repo: &Repository,
+    req: &SignIndexRequest,
+    release_contents: String,
+) {
     let uploads = [
         (
             format!(
@@ -822,7 +1487,7 @@ async fn apply_change_to_s3(
                 "{}/dists/{}/Release",
                 repo.s3_prefix, req.change.distribution
             ),
-            result.release_file.contents.as_bytes().to_vec(),
+            release_contents.into_bytes(),
         ),
         (
             format!(
@@ -833,91 +1498,75 @@ async fn apply_change_to_s3(
         ),
     ]
     .into_iter()
-    .map(|(key, content)| {
+    .map(|(key, content)| async move {
         debug!(?key, content = %String::from_utf8_lossy(&content), "uploading release file");
-        s3.put_object()
-            .bucket(&repo.s3_bucket)
-            .key(key)
-            .content_md5(base64::engine::general_purpose::STANDARD.encode(Md5::digest(&content)))
-            .checksum_algorithm(ChecksumAlgorithm::Sha256)
-            .checksum_sha256(
-                base64::engine::general_purpose::STANDARD.encode(Sha256::digest(&content)),
-            )
-            .body(content.into())
-            .send()
+        let sha256sum = hex::encode(Sha256::digest(&content));
+        store
+            .put_object(&repo.s3_bucket, &key, content.into(), &sha256sum)
+            .await
     });
-    for upload in futures_util::future::join_all(uploads).await {
+    for upload in concurrency.join_all(uploads).await {
         upload.unwrap();
     }
+}
 
-    // Now we can do deletions: the release files are uploaded and are no longer
-    // pointing at the by-hash Packages indexes that we're about to delete.
-    let deletions = match previous_by_hash_indexes {
-        None => Vec::new(),
-        Some(PreviousByHashIndexes {
-            md5sum,
-            sha1sum,
-            sha256sum,
-        }) => [
-            (md5sum, &result.changed_packages_index.meta.md5sum, "MD5Sum"),
-            (sha1sum, &result.changed_packages_index.meta.sha1sum, "SHA1"),
-            (
-                sha256sum,
-                &result.changed_packages_index.meta.sha256sum,
-                "SHA256",
-            ),
-        ]
-        .into_iter()
-        // This step is needed because the old hash might equal the new hash!
-        // This can occur if you upload a package that was already in the index,
-        // in which case adding the package to the index is a no-op. In that
-        // case, we don't want to delete the "old" (but actually still
-        // up-to-date) index.
-        .filter(|(old_hash, new_hash, _)| &old_hash != new_hash)
-        .map(|(old_hash, _, hash_type)| format!("{by_hash_prefix}/{hash_type}/{old_hash}"))
-        .collect::<Vec<_>>(),
-    };
-    debug!(?deletions, "deletions");
-
-    // S3 only allows up to 1000 objects per delete request, but we're dealing
-    // with low ones of keys.
-    let keys = deletions
-        .into_iter()
-        .map(|key| {
-            aws_sdk_s3::types::ObjectIdentifier::builder()
-                .key(key)
-                .build()
-                .unwrap()
-        })
-        .collect::<Vec<_>>();
-    if !keys.is_empty() {
-        let delete = aws_sdk_s3::types::Delete::builder()
-            .set_objects(Some(keys))
-            .build()
+/// Copy a package into the repository pool, verifying the copy's SHA256
+/// checksum and retrying if it doesn't match.
+///
+/// S3 copies can (rarely) complete with the wrong contents under some
+/// misconfigurations, and a bad pool file would otherwise only be noticed by
+/// clients failing to install the package.
+async fn copy_package_to_pool(
+    store: &dyn ObjectStore,
+    concurrency: &S3Concurrency,
+    bucket: &str,
+    source_key: &str,
+    destination_key: &str,
+    sha256sum: &str,
+) -> Result<(), ErrorResponse> {
+    let expected = sha256sum;
+    for attempt in 1..=POOL_COPY_ATTEMPTS {
+        concurrency
+            .run(store.copy_object(bucket, destination_key, source_key))
+            .await
             .unwrap();
-        let deletion = s3
-            .delete_objects()
-            .bucket(&repo.s3_bucket)
-            .delete(delete)
-            .send()
-            .await;
-        if let Err(err) = deletion {
-            tracing::error!("Failed to delete objects: {err:?}");
+        let actual = concurrency
+            .run(store.head_object_checksum(bucket, destination_key))
+            .await
+            .unwrap();
+        if actual.as_deref() == Some(expected) {
+            return Ok(());
         }
+        tracing::warn!(
+            ?destination_key,
+            ?actual,
+            ?expected,
+            attempt,
+            "pool copy checksum mismatch"
+        );
     }
+    Err(ErrorResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "PUBLISH_PARTIAL",
+        format!(
+            "package copy to {destination_key:?} failed checksum verification after {POOL_COPY_ATTEMPTS} attempts; the index was updated, so resync the distribution once the problem is fixed"
+        ),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use std::iter::once;
-
+    use aws_sdk_s3::types::ChecksumAlgorithm;
     use axum_test::multipart::{MultipartForm, Part};
+    use base64::Engine as _;
     use gpgme::ExportMode;
+    use md5::Md5;
     use tracing::info;
 
     use super::*;
     use crate::{
         server::{
+            object_store::S3ObjectStore,
             pkg::upload::PackageUploadResponse,
             repo::{
                 index::generate::{GenerateIndexRequest, GenerateIndexResponse},
@@ -961,6 +1610,56 @@ mod tests {
         (clearsigned, detachsigned, public_key_cert)
     }
 
+    #[test]
+    fn verify_signatures_from_multiple_keys() {
+        use p256::elliptic_curve::rand_core::OsRng;
+        use pgp::{
+            composed::{KeyType, SecretKeyParamsBuilder, SignedSecretKey},
+            packet::{SignatureConfig, SignatureType},
+            types::{Password, SecretKeyTrait as _},
+        };
+
+        let generate = |user_id: &str| {
+            SecretKeyParamsBuilder::default()
+                .key_type(KeyType::Ed25519Legacy)
+                .can_sign(true)
+                .primary_user_id(user_id.to_string())
+                .build()
+                .expect("build key params")
+                .generate(OsRng)
+                .expect("generate key")
+                .sign(OsRng, &Password::empty())
+                .expect("self-sign key")
+        };
+        let sign = |key: &SignedSecretKey, data: &[u8]| {
+            let config = SignatureConfig::v4(
+                SignatureType::Binary,
+                key.primary_key.algorithm(),
+                key.primary_key.hash_alg(),
+            );
+            StandaloneSignature::new(
+                config
+                    .sign(&key.primary_key, &Password::empty(), data)
+                    .expect("sign data"),
+            )
+        };
+
+        let data = b"Origin: test\n";
+        let old = generate("old key");
+        let new = generate("new key");
+        let other = generate("other key");
+        let public_keys = [old.signed_public_key(), new.signed_public_key()];
+
+        let signatures = [sign(&old, data), sign(&new, data)];
+        verify_signatures(&signatures, data, &public_keys).expect("both signatures verify");
+
+        let signatures = [sign(&old, data), sign(&other, data)];
+        verify_signatures(&signatures, data, &public_keys)
+            .expect_err("signature by an unsubmitted key is rejected");
+
+        verify_signatures(&[], data, &public_keys).expect_err("no signatures is rejected");
+    }
+
     #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
     #[test_log::test]
     async fn resync_mitigates_partial_upload(pool: sqlx::PgPool) {
@@ -1001,11 +1700,14 @@ mod tests {
                 repository: String::from(REPO_NAME),
                 distribution: String::from("stable"),
                 component: String::from("main"),
+                architecture: None,
 
                 action: PackageChangeAction::Add {
                     package_sha256sum: package_sha256sum.clone(),
+                    replace: false,
                 },
             },
+            release_ts: None,
         };
 
         let res = server
@@ -1041,7 +1743,11 @@ mod tests {
                 repository: String::from(REPO_NAME),
                 distribution: String::from("stable"),
                 component: String::from("main"),
-                action: PackageChangeAction::Add { package_sha256sum },
+                architecture: None,
+                action: PackageChangeAction::Add {
+                    package_sha256sum,
+                    replace: false,
+                },
             },
             clearsigned,
             detachsigned,
@@ -1049,7 +1755,9 @@ mod tests {
             release_ts,
         };
         let mut tx = server.db.begin().await.unwrap();
-        let (result, _) = apply_change_to_db(&mut tx, &tenant_id, &req).await.unwrap();
+        let (result, _) = apply_change_to_db(&mut tx, &tenant_id, &req, None)
+            .await
+            .unwrap();
         tx.commit().await.unwrap();
 
         // Partially upload the index changes. In this case, we upload the
@@ -1082,22 +1790,38 @@ mod tests {
                 "{}/dists/{}/{}/binary-{}/Packages",
                 s3_prefix,
                 req.change.distribution,
-                result.changed_packages_index.meta.component,
-                result.changed_packages_index.meta.architecture
+                result.changed_packages_indexes[0]
+                    .packages_index
+                    .meta
+                    .component,
+                result.changed_packages_indexes[0]
+                    .packages_index
+                    .meta
+                    .architecture
             ))
             .content_md5(
                 base64::engine::general_purpose::STANDARD.encode(Md5::digest(
-                    result.changed_packages_index.contents.as_bytes(),
+                    result.changed_packages_indexes[0]
+                        .packages_index
+                        .contents
+                        .as_bytes(),
                 )),
             )
             .checksum_algorithm(ChecksumAlgorithm::Sha256)
             .checksum_sha256(
-                base64::engine::general_purpose::STANDARD
-                    .encode(hex::decode(&result.changed_packages_index.meta.sha256sum).unwrap()),
+                base64::engine::general_purpose::STANDARD.encode(
+                    hex::decode(
+                        &result.changed_packages_indexes[0]
+                            .packages_index
+                            .meta
+                            .sha256sum,
+                    )
+                    .unwrap(),
+                ),
             )
             .body(
-                result
-                    .changed_packages_index
+                result.changed_packages_indexes[0]
+                    .packages_index
                     .contents
                     .as_bytes()
                     .to_vec()
@@ -1232,6 +1956,13 @@ mod tests {
 
         // Set up an empty repository.
         let s3_prefix = server.create_repository(tenant_id, REPO_NAME).await;
+        let repository_id = sqlx::query_scalar!(
+            "SELECT id FROM debian_repository WHERE s3_prefix = $1",
+            s3_prefix
+        )
+        .fetch_one(&server.db)
+        .await
+        .unwrap();
 
         // Upload packages.
         let package_file_a = fixtures::TEST_PACKAGE_AMD64;
@@ -1278,11 +2009,14 @@ mod tests {
                 repository: String::from(REPO_NAME),
                 distribution: String::from("stable"),
                 component: String::from("main"),
+                architecture: None,
 
                 action: PackageChangeAction::Add {
                     package_sha256sum: package_a_sha256sum.clone(),
+                    replace: false,
                 },
             },
+            release_ts: None,
         };
         let res = server
             .http
@@ -1304,8 +2038,10 @@ mod tests {
                 repository: String::from(REPO_NAME),
                 distribution: String::from("stable"),
                 component: String::from("main"),
+                architecture: None,
                 action: PackageChangeAction::Add {
                     package_sha256sum: package_a_sha256sum,
+                    replace: false,
                 },
             },
             clearsigned,
@@ -1315,7 +2051,7 @@ mod tests {
         };
         let mut tx = server.db.begin().await.unwrap();
         let (result_a, previous_by_hash_indexes_a) =
-            apply_change_to_db(&mut tx, &tenant_id, &req_a)
+            apply_change_to_db(&mut tx, &tenant_id, &req_a, None)
                 .await
                 .unwrap();
         debug!(?result_a, "applied change to database");
@@ -1327,11 +2063,14 @@ mod tests {
                 repository: String::from(REPO_NAME),
                 distribution: String::from("stable"),
                 component: String::from("main"),
+                architecture: None,
 
                 action: PackageChangeAction::Add {
                     package_sha256sum: package_b_sha256sum.clone(),
+                    replace: false,
                 },
             },
+            release_ts: None,
         };
         let res = server
             .http
@@ -1353,8 +2092,10 @@ mod tests {
                 repository: String::from(REPO_NAME),
                 distribution: String::from("stable"),
                 component: String::from("main"),
+                architecture: None,
                 action: PackageChangeAction::Add {
                     package_sha256sum: package_b_sha256sum,
+                    replace: false,
                 },
             },
             clearsigned,
@@ -1364,7 +2105,7 @@ mod tests {
         };
         let mut tx = server.db.begin().await.unwrap();
         let (result_b, previous_by_hash_indexes_b) =
-            apply_change_to_db(&mut tx, &tenant_id, &req_b)
+            apply_change_to_db(&mut tx, &tenant_id, &req_b, None)
                 .await
                 .unwrap();
         debug!(?result_b, "applied change to database");
@@ -1372,29 +2113,45 @@ mod tests {
 
         // Upload package 2 to the repository.
         apply_change_to_s3(
-            &server.s3,
+            &server.db,
+            &S3ObjectStore::new(server.s3.clone()),
+            &S3Concurrency::default(),
+            deletion::DEFAULT_GRACE,
+            None,
             &Repository {
+                id: repository_id,
                 s3_bucket: server.s3_bucket_name.clone(),
                 s3_prefix: s3_prefix.clone(),
+                webhook_url: None,
+                webhook_secret: None,
             },
             &req_b,
             &result_b,
             previous_by_hash_indexes_b,
         )
-        .await;
+        .await
+        .unwrap();
 
         // Upload package 1 to the repository.
         apply_change_to_s3(
-            &server.s3,
+            &server.db,
+            &S3ObjectStore::new(server.s3.clone()),
+            &S3Concurrency::default(),
+            deletion::DEFAULT_GRACE,
+            None,
             &Repository {
+                id: repository_id,
                 s3_bucket: server.s3_bucket_name.clone(),
                 s3_prefix: s3_prefix.clone(),
+                webhook_url: None,
+                webhook_secret: None,
             },
             &req_a,
             &result_a,
             previous_by_hash_indexes_a,
         )
-        .await;
+        .await
+        .unwrap();
 
         // Check that we can detect the desynchronization.
         let res = server
@@ -1485,6 +2242,56 @@ mod tests {
         );
     }
 
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn resign_requires_published_distribution(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "resign_requires_published_distribution";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        let change = || PackageChange {
+            repository: String::from(REPO_NAME),
+            distribution: String::from("stable"),
+            component: String::new(),
+            architecture: None,
+            action: PackageChangeAction::Resign,
+        };
+
+        // Re-signing doesn't generate a new Release file.
+        let res = server
+            .http
+            .get(&format!("/api/v0/repositories/{REPO_NAME}/index"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&GenerateIndexRequest {
+                change: change(),
+                release_ts: None,
+            })
+            .await;
+        assert_eq!(res.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.json::<ErrorResponse>().error, "INVALID_CHANGE");
+
+        // There's no Release file to re-sign.
+        let res = server
+            .http
+            .post(&format!("/api/v0/repositories/{REPO_NAME}/index"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(&SignIndexRequest {
+                change: change(),
+                release_ts: OffsetDateTime::now_utc(),
+                clearsigned: String::from("dummy-clearsigned"),
+                detachsigned: String::from("dummy-detachsigned"),
+                public_key_cert: String::from("dummy-public-key"),
+            })
+            .await;
+        assert_eq!(res.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(res.json::<ErrorResponse>().error, "DISTRIBUTION_NOT_FOUND");
+    }
+
     #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
     async fn reject_invalid_component_names(pool: sqlx::PgPool) {
         let server = AttuneTestServer::new(AttuneTestServerConfig {
@@ -1512,8 +2319,10 @@ mod tests {
                     repository: String::from(REPO_NAME),
                     distribution: String::from("stable"),
                     component: String::from(invalid_component),
+                    architecture: None,
                     action: PackageChangeAction::Add {
                         package_sha256sum: String::from("dummy-sha256sum"),
+                        replace: false,
                     },
                 },
                 release_ts: OffsetDateTime::now_utc(),
@@ -1559,8 +2368,10 @@ mod tests {
                     repository: String::from(REPO_NAME),
                     distribution: String::from("stable"),
                     component: String::from(valid_component),
+                    architecture: None,
                     action: PackageChangeAction::Add {
                         package_sha256sum: String::from("dummy-sha256sum"),
+                        replace: false,
                     },
                 },
                 release_ts: OffsetDateTime::now_utc(),
@@ -1584,4 +2395,60 @@ mod tests {
             );
         }
     }
+
+    /// Public key certificates and signatures that can't be parsed are
+    /// rejected as bad requests.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn reject_garbage_public_key_cert(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "reject_garbage_public_key_cert";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        let req = |public_key_cert: &str| SignIndexRequest {
+            change: PackageChange {
+                repository: String::from(REPO_NAME),
+                distribution: String::from("stable"),
+                component: String::from("main"),
+                architecture: None,
+                action: PackageChangeAction::Add {
+                    package_sha256sum: "0".repeat(64),
+                    replace: false,
+                },
+            },
+            release_ts: OffsetDateTime::now_utc(),
+            clearsigned: String::from("dummy-clearsigned"),
+            detachsigned: String::from("dummy-detachsigned"),
+            public_key_cert: String::from(public_key_cert),
+        };
+        for public_key_cert in [
+            "dummy-public-key",
+            "-----BEGIN PGP PUBLIC KEY BLOCK-----\n\ngarbage\n-----END PGP PUBLIC KEY BLOCK-----\n",
+        ] {
+            let res = server
+                .http
+                .post(&format!("/api/v0/repositories/{REPO_NAME}/index"))
+                .add_header("authorization", format!("Bearer {api_token}"))
+                .json(&req(public_key_cert))
+                .await;
+            assert_eq!(res.status_code(), StatusCode::BAD_REQUEST);
+            assert_eq!(
+                res.json::<ErrorResponse>().error,
+                "PUBLIC_KEY_VERIFICATION_FAILED"
+            );
+            assert_eq!(
+                fingerprints(public_key_cert).unwrap_err().error,
+                "PUBLIC_KEY_VERIFICATION_FAILED"
+            );
+        }
+
+        let err = verify_detached_signature(&req("dummy-public-key"), &[], "").unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert_eq!(err.error, "DETACHED_SIGNATURE_VERIFICATION_FAILED");
+    }
 }
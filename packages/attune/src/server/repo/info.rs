@@ -14,6 +14,12 @@ use crate::{
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RepositoryInfoResponse {
     pub name: String,
+    /// If not empty, only packages of these architectures can be added.
+    #[serde(default)]
+    pub allowed_architectures: Vec<String>,
+    /// If not empty, packages can only be added to these components.
+    #[serde(default)]
+    pub allowed_components: Vec<String>,
 }
 
 #[axum::debug_handler]
@@ -28,7 +34,10 @@ pub async fn handler(
 
     let repo = sqlx::query!(
         r#"
-        SELECT name
+        SELECT
+            name,
+            allowed_architectures::TEXT[] AS "allowed_architectures!",
+            allowed_components AS "allowed_components!"
         FROM debian_repository
         WHERE tenant_id = $1 AND name = $2
         LIMIT 1
@@ -40,7 +49,11 @@ pub async fn handler(
     .await
     .map_err(ErrorResponse::from)?;
     match repo {
-        Some(repo) => Ok(Json(RepositoryInfoResponse { name: repo.name })),
+        Some(repo) => Ok(Json(RepositoryInfoResponse {
+            name: repo.name,
+            allowed_architectures: repo.allowed_architectures,
+            allowed_components: repo.allowed_components,
+        })),
         None => Err(ErrorResponse::new(
             StatusCode::NOT_FOUND,
             "REPO_NOT_FOUND".to_string(),
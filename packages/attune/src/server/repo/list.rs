@@ -1,13 +1,16 @@
-use axum::{Json, extract::State};
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::{
-    api::{ErrorResponse, TenantID},
+    api::{ErrorResponse, PageParams, TenantID, TokenScopes, pagination::next_cursor},
     server::ServerState,
 };
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Repository {
     pub id: i64,
     pub name: String,
@@ -23,6 +26,9 @@ pub struct ListRepositoryRequest {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ListRepositoryResponse {
     pub repositories: Vec<Repository>,
+    /// The cursor of the next page, if there are more repositories.
+    #[serde(default)]
+    pub next_cursor: Option<String>,
 }
 
 #[axum::debug_handler]
@@ -30,25 +36,36 @@ pub struct ListRepositoryResponse {
 pub async fn handler(
     State(state): State<ServerState>,
     tenant_id: TenantID,
+    Extension(scopes): Extension<TokenScopes>,
+    Query(page): Query<PageParams>,
     Json(req): Json<ListRepositoryRequest>,
 ) -> Result<Json<ListRepositoryResponse>, ErrorResponse> {
     // TODO: In the managed cloud version of this CLI, we should hide the S3
     // bucket and prefix fields because they're irrelevant.
-    let repositories = sqlx::query!(
+    let limit = page.limit()?;
+    let after = page.after::<i64>()?;
+    let mut repositories = sqlx::query!(
         r#"
         SELECT id, name, s3_bucket, s3_prefix
         FROM debian_repository
         WHERE
             tenant_id = $1
             AND name LIKE '%' || $2 || '%'
-        ORDER BY created_at ASC
+            AND id > $3
+            AND (name = ANY($5) OR $5 IS NULL)
+        ORDER BY id ASC
+        LIMIT $4
         "#,
         tenant_id.0,
         req.name.unwrap_or_default(),
+        after.unwrap_or_default(),
+        limit + 1,
+        scopes.repositories() as Option<Vec<String>>,
     )
     .fetch_all(&state.db)
     .await
     .map_err(ErrorResponse::from)?;
+    let next_cursor = next_cursor(&mut repositories, limit, |r| r.id);
     let repositories = repositories
         .into_iter()
         .map(|r| Repository {
@@ -58,5 +75,64 @@ pub async fn handler(
             s3_prefix: r.s3_prefix,
         })
         .collect();
-    Ok(Json(ListRepositoryResponse { repositories }))
+    Ok(Json(ListRepositoryResponse {
+        repositories,
+        next_cursor,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::repo::create::{self, CreateRepositoryRequest};
+    use crate::testing::test_server_state;
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn paginate_repositories(pool: sqlx::PgPool) {
+        let state = test_server_state(pool);
+        for name in ["second", "third"] {
+            let Json(_) = create::handler(
+                State(state.clone()),
+                TenantID(1),
+                Json(CreateRepositoryRequest {
+                    name: name.to_string(),
+                    allowed_architectures: Vec::new(),
+                    allowed_components: Vec::new(),
+                }),
+            )
+            .await
+            .unwrap();
+        }
+        let list = |cursor: Option<String>| {
+            handler(
+                State(state.clone()),
+                TenantID(1),
+                Extension(TokenScopes::default()),
+                Query(PageParams {
+                    limit: Some(2),
+                    cursor,
+                }),
+                Json(ListRepositoryRequest { name: None }),
+            )
+        };
+
+        let Json(first) = list(None).await.unwrap();
+        assert_eq!(first.repositories.len(), 2);
+        let Json(second) = list(first.next_cursor).await.unwrap();
+        assert_eq!(second.repositories.len(), 1);
+        assert_eq!(second.next_cursor, None);
+
+        // Every repository is listed exactly once across the pages.
+        let mut names = first
+            .repositories
+            .iter()
+            .chain(&second.repositories)
+            .map(|r| r.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["second", "test-multi-arch", "third"]);
+    }
 }
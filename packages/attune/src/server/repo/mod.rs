@@ -1,16 +1,26 @@
 use axum::http::StatusCode;
 use percent_encoding::percent_decode_str;
+use sqlx::{Executor, Postgres};
 
-use crate::api::ErrorResponse;
+use crate::{
+    api::{ErrorResponse, TenantID},
+    apt::normalize_architecture,
+};
 
+pub mod changelog;
+pub mod clone;
 pub mod create;
 pub mod delete;
 pub mod dist;
 pub mod edit;
+pub mod export;
 pub mod index;
 pub mod info;
 pub mod list;
+pub mod stats;
+pub mod status;
 pub mod sync;
+pub mod usage;
 
 fn decode_repo_name(name: &str) -> Result<String, ErrorResponse> {
     // The repository name in the path is percent-encoded.
@@ -23,3 +33,92 @@ fn decode_repo_name(name: &str) -> Result<String, ErrorResponse> {
         )),
     }
 }
+
+/// Check that the repository named in a request body matches the repository
+/// named in the request path.
+///
+/// Endpoints that take a repository in both places must call this, so that a
+/// client bug can't silently operate on a different repository than the one
+/// the request was routed to. `path_name` must already be percent-decoded.
+fn validate_repo_name_matches(path_name: &str, body_name: &str) -> Result<(), ErrorResponse> {
+    if path_name != body_name {
+        return Err(ErrorResponse::new(
+            StatusCode::BAD_REQUEST,
+            "REPOSITORY_MISMATCH".to_string(),
+            "repository name in path does not match repository name in request".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check the architectures and components that a repository allows packages
+/// of, returning the architectures with aliases normalized.
+async fn validate_allowlists<'c, E>(
+    executor: E,
+    architectures: &[String],
+    components: &[String],
+) -> Result<Vec<String>, ErrorResponse>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    for component in components {
+        dist::validate_component_name(component)?;
+    }
+    if architectures.is_empty() {
+        return Ok(Vec::new());
+    }
+    let known = sqlx::query_scalar!(
+        r#"SELECT unnest(enum_range(NULL::debian_repository_architecture))::TEXT AS "architecture!""#
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(ErrorResponse::from)?;
+    architectures
+        .iter()
+        .map(|architecture| {
+            let normalized = normalize_architecture(architecture);
+            if known.contains(&normalized) {
+                Ok(normalized)
+            } else {
+                Err(ErrorResponse::new(
+                    StatusCode::BAD_REQUEST,
+                    "INVALID_ARCHITECTURE",
+                    format!("unknown architecture {architecture:?}"),
+                ))
+            }
+        })
+        .collect()
+}
+
+/// Check that the tenant can create another repository without exceeding the
+/// server's per-tenant limit, if one is configured.
+///
+/// This must be called in the transaction that creates the repository, so that
+/// concurrent creations can't both pass the check.
+async fn check_repository_quota<'c, E>(
+    executor: E,
+    tenant_id: TenantID,
+    max_repos_per_tenant: Option<u32>,
+) -> Result<(), ErrorResponse>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    let Some(limit) = max_repos_per_tenant else {
+        return Ok(());
+    };
+    let count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM debian_repository WHERE tenant_id = $1"#,
+        tenant_id.0,
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(ErrorResponse::from)?;
+    if count >= i64::from(limit) {
+        return Err(ErrorResponse::new(
+            StatusCode::CONFLICT,
+            "REPOSITORY_QUOTA_EXCEEDED",
+            format!("tenant already has the maximum of {limit} repositories"),
+        ));
+    }
+    Ok(())
+}
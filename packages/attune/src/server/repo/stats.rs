@@ -0,0 +1,250 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Postgres};
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{ServerState, repo::decode_repo_name},
+};
+
+/// Counts of published packages.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PackageStats {
+    pub packages: u64,
+    /// The number of distinct package names, e.g. every version and
+    /// architecture of `curl` counts once.
+    pub package_names: u64,
+    /// The total size of the package files.
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ComponentArchitectureStats {
+    pub distribution: String,
+    pub component: String,
+    pub architecture: String,
+    #[serde(flatten)]
+    pub stats: PackageStats,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepositoryStatsResponse {
+    /// Stats of the whole repository. Packages that are published in several
+    /// distributions or components are only counted once.
+    pub total: PackageStats,
+    pub breakdown: Vec<ComponentArchitectureStats>,
+}
+
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repository_name): Path<String>,
+) -> Result<Json<RepositoryStatsResponse>, ErrorResponse> {
+    // The repository name in the path is percent-encoded.
+    let repository_name = decode_repo_name(&repository_name)?;
+
+    match query_stats(&state.db, &tenant_id, &repository_name).await? {
+        Some(stats) => Ok(Json(stats)),
+        None => Err(ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND".to_string(),
+            "repository not found".to_string(),
+        )),
+    }
+}
+
+/// Compute the repository's stats in a single query, or return `None` if the
+/// repository doesn't exist.
+async fn query_stats<'c, E>(
+    executor: E,
+    tenant_id: &TenantID,
+    repository_name: &str,
+) -> Result<Option<RepositoryStatsResponse>, ErrorResponse>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    // The first row is the repository's total, which exists even if nothing is
+    // published, so there are no rows only if the repository doesn't exist.
+    let rows = sqlx::query!(
+        r#"
+        WITH
+            repository AS (
+                SELECT id
+                FROM debian_repository
+                WHERE tenant_id = $1 AND name = $2
+            ),
+            published AS (
+                SELECT
+                    debian_repository_release.distribution,
+                    debian_repository_component.name AS component,
+                    debian_repository_package.architecture::TEXT AS architecture,
+                    debian_repository_package.id,
+                    debian_repository_package.package,
+                    debian_repository_package.size
+                FROM
+                    repository
+                    JOIN debian_repository_release ON debian_repository_release.repository_id = repository.id
+                    JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+                    JOIN debian_repository_component_package ON debian_repository_component_package.component_id = debian_repository_component.id
+                    JOIN debian_repository_package ON debian_repository_package.id = debian_repository_component_package.package_id
+            )
+        SELECT
+            NULL::TEXT AS distribution,
+            NULL::TEXT AS component,
+            NULL::TEXT AS architecture,
+            COUNT(package.id) AS "packages!",
+            COUNT(DISTINCT package.package) AS "package_names!",
+            COALESCE(SUM(package.size), 0)::BIGINT AS "bytes!"
+        FROM
+            repository
+            LEFT JOIN (SELECT DISTINCT id, package, size FROM published) AS package ON TRUE
+        GROUP BY repository.id
+        UNION ALL
+        SELECT
+            distribution,
+            component,
+            architecture,
+            COUNT(*),
+            COUNT(DISTINCT package),
+            SUM(size)::BIGINT
+        FROM published
+        GROUP BY distribution, component, architecture
+        ORDER BY distribution NULLS FIRST, component, architecture
+        "#,
+        tenant_id.0,
+        repository_name,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    let mut rows = rows.into_iter().map(|row| {
+        let stats = PackageStats {
+            packages: row.packages as u64,
+            package_names: row.package_names as u64,
+            bytes: row.bytes as u64,
+        };
+        (row.distribution, row.component, row.architecture, stats)
+    });
+    let Some((_, _, _, total)) = rows.next() else {
+        return Ok(None);
+    };
+    let breakdown = rows
+        .filter_map(|(distribution, component, architecture, stats)| {
+            Some(ComponentArchitectureStats {
+                distribution: distribution?,
+                component: component?,
+                architecture: architecture?,
+                stats,
+            })
+        })
+        .collect();
+    Ok(Some(RepositoryStatsResponse { total, breakdown }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn shared_packages_counted_once(pool: sqlx::PgPool) {
+        let tenant_id = TenantID(1);
+
+        // Publish the amd64 package in a second distribution.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_release (id, repository_id, distribution, suite, codename, contents, created_at, updated_at)
+            VALUES (1001, 1000, 'unstable', 'unstable', 'unstable', 'dummy content', NOW(), NOW());
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component (id, release_id, name, created_at, updated_at)
+            VALUES (1001, 1001, 'main', NOW(), NOW());
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            VALUES (1001, 1001, 'pool/main/t/test-package/test-package_1.0.0_amd64.deb', NOW(), NOW());
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let stats = query_stats(&pool, &tenant_id, "test-multi-arch")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            stats.total,
+            PackageStats {
+                packages: 2,
+                package_names: 1,
+                bytes: 2048,
+            }
+        );
+        let breakdown = stats
+            .breakdown
+            .iter()
+            .map(|row| {
+                (
+                    row.distribution.as_str(),
+                    row.component.as_str(),
+                    row.architecture.as_str(),
+                    row.stats.packages,
+                    row.stats.bytes,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            breakdown,
+            [
+                ("stable", "main", "amd64", 1, 1024),
+                ("stable", "main", "arm64", 1, 1024),
+                ("unstable", "main", "amd64", 1, 1024),
+            ]
+        );
+
+        // Repositories without packages have empty stats, and missing
+        // repositories have none.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository (id, tenant_id, name, s3_bucket, s3_prefix, created_at, updated_at)
+            VALUES (1001, 1, 'empty', 'attune-test-0', '1/empty', NOW(), NOW());
+            "#
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let stats = query_stats(&pool, &tenant_id, "empty")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stats.total, PackageStats::default());
+        assert!(stats.breakdown.is_empty());
+        assert!(
+            query_stats(&pool, &tenant_id, "missing")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
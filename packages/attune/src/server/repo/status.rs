@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use time::OffsetDateTime;
+use tracing::instrument;
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{decode_repo_name, dist::staging_distribution},
+    },
+};
+
+/// A package in a staging distribution, and what promoting the staging
+/// distribution would do with it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StagedPackage {
+    pub component: String,
+    pub name: String,
+    pub version: String,
+    pub architecture: String,
+    pub sha256sum: String,
+    pub action: StagedAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StagedAction {
+    /// The package isn't in the distribution yet, so promoting adds it.
+    Add,
+    /// The package is already in the distribution, so promoting only removes
+    /// it from the staging distribution. This happens when a promotion was
+    /// interrupted after adding the package.
+    Remove,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DistributionStatus {
+    pub distribution: String,
+    /// When the distribution's Release file last changed.
+    pub updated_at: OffsetDateTime,
+    /// The distribution that packages are staged in before being promoted into
+    /// this one, if it exists.
+    pub staging_distribution: Option<String>,
+    pub staged: Vec<StagedPackage>,
+}
+
+/// The state of each distribution of a repository, and the changes that are
+/// staged for it.
+///
+/// Package changes are only recorded once their index is signed, so a change
+/// that was interrupted before signing was not applied at all, and shows up
+/// here as not published.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepositoryStatusResponse {
+    pub distributions: Vec<DistributionStatus>,
+}
+
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repository_name): Path<String>,
+) -> Result<Json<RepositoryStatusResponse>, ErrorResponse> {
+    // The repository name in the path is percent-encoded.
+    let repository_name = decode_repo_name(&repository_name)?;
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    let repo = sqlx::query!(
+        r#"
+        SELECT id
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        &repository_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND".to_string(),
+            "repository not found".to_string(),
+        )
+    })?;
+    let distributions = query_status(&mut tx, repo.id).await?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    Ok(Json(RepositoryStatusResponse { distributions }))
+}
+
+async fn query_status(
+    conn: &mut PgConnection,
+    repository_id: i64,
+) -> Result<Vec<DistributionStatus>, ErrorResponse> {
+    let releases = sqlx::query!(
+        r#"
+        SELECT distribution, updated_at
+        FROM debian_repository_release
+        WHERE repository_id = $1
+        ORDER BY distribution
+        "#,
+        repository_id,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ErrorResponse::from)?;
+    let packages = sqlx::query!(
+        r#"
+        SELECT
+            debian_repository_release.distribution,
+            debian_repository_component.name AS component,
+            debian_repository_package.package,
+            debian_repository_package.version,
+            debian_repository_package.architecture::TEXT AS "architecture!: String",
+            debian_repository_package.sha256sum
+        FROM
+            debian_repository_release
+            JOIN debian_repository_component ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository_component_package ON debian_repository_component_package.component_id = debian_repository_component.id
+            JOIN debian_repository_package ON debian_repository_package.id = debian_repository_component_package.package_id
+        WHERE debian_repository_release.repository_id = $1
+        ORDER BY
+            debian_repository_component.name,
+            debian_repository_package.package,
+            debian_repository_package.version,
+            debian_repository_package.architecture
+        "#,
+        repository_id,
+    )
+    .fetch_all(&mut *conn)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    // Promotion adds packages to the same component, so a staged package is
+    // already published if the distribution has the same package file in the
+    // same component.
+    let mut published = BTreeMap::<String, BTreeSet<(String, String)>>::new();
+    let mut by_distribution = BTreeMap::<String, Vec<StagedPackage>>::new();
+    for package in packages {
+        published
+            .entry(package.distribution.clone())
+            .or_default()
+            .insert((package.component.clone(), package.sha256sum.clone()));
+        by_distribution
+            .entry(package.distribution)
+            .or_default()
+            .push(StagedPackage {
+                component: package.component,
+                name: package.package,
+                version: package.version,
+                architecture: package.architecture,
+                sha256sum: package.sha256sum,
+                action: StagedAction::Add,
+            });
+    }
+
+    let distributions = releases
+        .iter()
+        .map(|release| release.distribution.clone())
+        .collect::<BTreeSet<_>>();
+    Ok(releases
+        .into_iter()
+        .map(|release| {
+            let staging = staging_distribution(&release.distribution);
+            let (staging_distribution, staged) = if distributions.contains(&staging) {
+                let published = published.get(&release.distribution);
+                let staged = by_distribution
+                    .get(&staging)
+                    .cloned()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|mut package| {
+                        let key = (package.component.clone(), package.sha256sum.clone());
+                        if published.is_some_and(|published| published.contains(&key)) {
+                            package.action = StagedAction::Remove;
+                        }
+                        package
+                    })
+                    .collect();
+                (Some(staging), staged)
+            } else {
+                (None, Vec::new())
+            };
+            DistributionStatus {
+                distribution: release.distribution,
+                updated_at: release.updated_at,
+                staging_distribution,
+                staged,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn staged_packages(pool: sqlx::PgPool) {
+        let mut conn = pool.acquire().await.unwrap();
+
+        // Stage both packages, and promote the amd64 package without removing
+        // it from staging, as if the promotion was interrupted.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_release (id, repository_id, distribution, suite, codename, contents, created_at, updated_at)
+            VALUES (1001, 1000, 'stable-staging', 'stable-staging', 'stable-staging', 'dummy content', NOW(), NOW());
+            "#
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component (id, release_id, name, created_at, updated_at)
+            VALUES (1001, 1001, 'main', NOW(), NOW());
+            "#
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            VALUES
+                (1001, 1001, 'pool/main/t/test-package/test-package_1.0.0_amd64.deb', NOW(), NOW()),
+                (1001, 1002, 'pool/main/t/test-package/test-package_1.0.0_arm64.deb', NOW(), NOW());
+            "#
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            DELETE FROM debian_repository_component_package
+            WHERE component_id = 1000 AND package_id = 1002
+            "#
+        )
+        .execute(&mut *conn)
+        .await
+        .unwrap();
+
+        let status = query_status(&mut conn, 1000).await.unwrap();
+        let names = status
+            .iter()
+            .map(|dist| dist.distribution.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["stable", "stable-staging"]);
+
+        let stable = &status[0];
+        assert_eq!(
+            stable.staging_distribution.as_deref(),
+            Some("stable-staging")
+        );
+        let staged = stable
+            .staged
+            .iter()
+            .map(|package| (package.architecture.as_str(), package.action))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            staged,
+            [
+                ("amd64", StagedAction::Remove),
+                ("arm64", StagedAction::Add)
+            ]
+        );
+
+        let staging = &status[1];
+        assert_eq!(staging.staging_distribution, None);
+        assert!(staging.staged.is_empty());
+    }
+}
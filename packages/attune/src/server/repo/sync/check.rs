@@ -1,25 +1,50 @@
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use tracing::{debug, instrument};
+use time::OffsetDateTime;
+use tracing::{debug, instrument, warn};
 
 use crate::{
     api::{ErrorResponse, TenantID},
+    apt::ReleaseFile,
     server::{
         ServerState,
         repo::{
             decode_repo_name,
-            sync::{InconsistentSummary, check_s3_consistency, query_repository_state},
+            index::release_ts_problem,
+            sync::{
+                Expected, InconsistentSummary, SyncScope, check_s3_consistency,
+                query_repository_state,
+            },
         },
     },
 };
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CheckParams {
+    /// The hex-encoded SHA256 sum of the Release file that is expected to be
+    /// published, such as the one a client has just signed.
+    ///
+    /// If set, the published Release file is checked against this sum rather
+    /// than against the current database state, so that a client can wait for
+    /// its own change to become visible.
+    #[serde(default)]
+    pub release_sha256: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CheckConsistencyResponse {
     #[serde(flatten)]
     pub status: InconsistentSummary,
+    /// Why the published Release file's `Date` is implausible, if it is. This
+    /// usually means that the clock was skewed when the Release file was
+    /// generated, and resyncing can't fix it: the distribution has to be
+    /// republished.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release_date_problem: Option<String>,
 }
 
 #[axum::debug_handler]
@@ -28,10 +53,23 @@ pub async fn handler(
     State(state): State<ServerState>,
     tenant_id: TenantID,
     Path((repo_name, release_name)): Path<(String, String)>,
+    Query(scope): Query<SyncScope>,
+    Query(params): Query<CheckParams>,
 ) -> Result<Json<CheckConsistencyResponse>, ErrorResponse> {
     // The repository name in the path is percent-encoded.
     let repo_name = decode_repo_name(&repo_name)?;
     let release_name = decode_repo_name(&release_name)?;
+    let release_sha256 = params
+        .release_sha256
+        .map(|sha256| match hex::decode(&sha256) {
+            Ok(sha256) if sha256.len() == 32 => Ok(sha256),
+            _ => Err(ErrorResponse::new(
+                StatusCode::BAD_REQUEST,
+                "INVALID_SHA256",
+                format!("invalid release SHA256 sum: {sha256:?}"),
+            )),
+        })
+        .transpose()?;
 
     // Get current repository state.
     let mut tx = state.db.begin().await.unwrap();
@@ -39,15 +77,137 @@ pub async fn handler(
         .execute(&mut *tx)
         .await
         .map_err(ErrorResponse::from)?;
-    let repo = query_repository_state(&mut tx, &tenant_id, repo_name, release_name).await?;
+    let repo = query_repository_state(&mut tx, &tenant_id, repo_name, release_name, &scope).await?;
     tx.commit().await.map_err(ErrorResponse::from)?;
+    let Some(mut repo) = repo else {
+        // The distribution has never been published, so there is nothing to
+        // sync.
+        return Ok(Json(CheckConsistencyResponse {
+            status: InconsistentSummary::default(),
+            release_date_problem: None,
+        }));
+    };
     debug!(?repo, "loaded repository state");
+    let release_date_problem =
+        release_date_problem(&repo.release_contents, OffsetDateTime::now_utc());
+    if let Some(problem) = &release_date_problem {
+        warn!(?problem, "published Release file has an implausible date");
+    }
+    if let Some(release_sha256) = release_sha256
+        && let Expected::Exists { sha256sum, .. } = &mut repo.release_contents
+    {
+        *sha256sum = release_sha256;
+    }
 
     // Check which S3 objects are inconsistent.
-    let inconsistent_objects = check_s3_consistency(&state.s3, repo).await?;
+    let inconsistent_objects = check_s3_consistency(state.object_store.as_ref(), repo).await?;
     debug!(?inconsistent_objects, "checked S3");
 
     Ok(Json(CheckConsistencyResponse {
         status: InconsistentSummary::from(&inconsistent_objects),
+        release_date_problem,
     }))
 }
+
+/// Check the `Date` of the Release file that should be published.
+fn release_date_problem(release_contents: &Expected, now: OffsetDateTime) -> Option<String> {
+    let Expected::Exists { contents, .. } = release_contents else {
+        return None;
+    };
+    match ReleaseFile::parse_date(&String::from_utf8_lossy(contents)) {
+        Some(date) => release_ts_problem(date, now),
+        None => Some(String::from("release file has no valid Date field")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::ErrorResponse,
+        server::repo::dist::create::CreateDistributionRequest,
+        testing::{AttuneTestServer, AttuneTestServerConfig},
+    };
+
+    /// A distribution that was created but never published has nothing to
+    /// sync, so it is trivially consistent.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn unpublished_distribution_is_consistent(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "unpublished_distribution_is_consistent";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        server
+            .http
+            .post(&format!("/api/v0/repositories/{REPO_NAME}/distributions"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .json(
+                &CreateDistributionRequest::builder()
+                    .name("stable")
+                    .suite("stable")
+                    .codename("bookworm")
+                    .build(),
+            )
+            .await
+            .assert_status_ok();
+
+        let response = server
+            .http
+            .get(&format!(
+                "/api/v0/repositories/{REPO_NAME}/distributions/stable/sync"
+            ))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_ok();
+        assert!(
+            response
+                .json::<CheckConsistencyResponse>()
+                .status
+                .is_consistent()
+        );
+
+        // A missing repository is still an error.
+        let response = server
+            .http
+            .get("/api/v0/repositories/does-not-exist/distributions/stable/sync")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_not_found();
+        assert_eq!(
+            response.json::<ErrorResponse>().error,
+            "REPOSITORY_NOT_FOUND"
+        );
+    }
+
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn reject_invalid_release_sha256(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "reject_invalid_release_sha256";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        for sha256 in ["not-hex", "abcd"] {
+            let response = server
+                .http
+                .get(&format!(
+                    "/api/v0/repositories/{REPO_NAME}/distributions/stable/sync"
+                ))
+                .add_query_param("release_sha256", sha256)
+                .add_header("authorization", format!("Bearer {api_token}"))
+                .await;
+            response.assert_status_bad_request();
+            assert_eq!(response.json::<ErrorResponse>().error, "INVALID_SHA256");
+        }
+    }
+}
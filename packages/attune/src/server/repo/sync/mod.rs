@@ -1,8 +1,8 @@
 pub mod check;
 pub mod resync;
 
-use aws_sdk_s3::types::ChecksumMode;
-use base64::Engine;
+use std::collections::{BTreeMap, HashMap};
+
 use derivative::Derivative;
 use hex;
 use http::StatusCode;
@@ -11,7 +11,15 @@ use sha2::{Digest as _, Sha256};
 use sqlx::{Postgres, Transaction};
 use tracing::{Level, debug, instrument};
 
-use crate::api::{ErrorResponse, TenantID};
+use crate::{
+    api::{ErrorResponse, TenantID},
+    apt::Compression,
+    server::{
+        object_store::{ObjectStore, ObjectStoreError},
+        pkg::{canonical_key, copy_source},
+        repo::index::{ContentsEncoding, decode_contents},
+    },
+};
 
 #[derive(Derivative)]
 #[derivative(Debug, Clone)]
@@ -24,7 +32,8 @@ pub enum Expected {
         ///
         /// For packages, this is the CopyObject key (which includes the bucket
         /// name) to the canonical package object.
-        contents: String,
+        #[derivative(Debug(format_with = "display_lossy"))]
+        contents: Vec<u8>,
         /// The SHA256 sum of the object, used to determine whether the object
         /// has changed.
         #[derivative(Debug(format_with = "display_hex"))]
@@ -39,6 +48,10 @@ fn display_hex(hex: &Vec<u8>, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Resu
     write!(f, "{:?}", hex::encode(hex))
 }
 
+fn display_lossy(contents: &[u8], f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:?}", String::from_utf8_lossy(contents))
+}
+
 impl Expected {
     pub fn key(&self) -> &str {
         match self {
@@ -48,6 +61,44 @@ impl Expected {
     }
 }
 
+/// Why an object in S3 doesn't match its expected state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Inconsistency {
+    /// The object should exist, but doesn't (or couldn't be read).
+    Missing,
+    /// The object exists, but its contents don't match the database.
+    ///
+    /// Both checksums are hex-encoded SHA256 sums. The actual checksum is
+    /// `None` if S3 didn't report one for the object.
+    ChecksumMismatch {
+        expected: String,
+        actual: Option<String>,
+    },
+    /// The object should have been deleted, but still exists.
+    ShouldNotExist,
+}
+
+impl std::fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inconsistency::Missing => write!(f, "missing"),
+            Inconsistency::ChecksumMismatch {
+                expected,
+                actual: Some(actual),
+            } => write!(f, "checksum mismatch (expected {expected}, found {actual})"),
+            Inconsistency::ChecksumMismatch {
+                expected,
+                actual: None,
+            } => write!(
+                f,
+                "checksum mismatch (expected {expected}, found no checksum)"
+            ),
+            Inconsistency::ShouldNotExist => write!(f, "should not exist"),
+        }
+    }
+}
+
 /// Intended repository state given the current database state.
 ///
 /// You should think of this as the "expected" state of the repository.
@@ -78,15 +129,35 @@ pub struct InconsistentObjects {
     pub release_clearsigned: Option<Expected>,
     pub packages_indexes: Vec<Expected>,
     pub packages: Vec<Expected>,
+    /// Why each object is inconsistent, by S3 key. This is empty for forced
+    /// resyncs, which rewrite objects without checking them.
+    pub reasons: HashMap<String, Inconsistency>,
+}
+
+/// Limits a consistency check or resync to the Packages indexes and packages of
+/// a single component and/or architecture.
+///
+/// The Release files are always included regardless of scope, because the
+/// Release file covers every Packages index in the distribution and its
+/// signature is only valid for the whole file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SyncScope {
+    pub component: Option<String>,
+    pub architecture: Option<String>,
 }
 
+/// Load the intended state of a distribution from the database.
+///
+/// Returns `None` if the distribution has never been published (or has been
+/// cleared), since it then has no objects to keep in sync.
 #[instrument(level = Level::DEBUG, skip(tx))]
 pub async fn query_repository_state(
     tx: &mut Transaction<'_, Postgres>,
     tenant_id: &TenantID,
     repository_name: String,
     release_name: String,
-) -> Result<RepositoryState, ErrorResponse> {
+    scope: &SyncScope,
+) -> Result<Option<RepositoryState>, ErrorResponse> {
     let repo = sqlx::query!(
         r#"
         SELECT id, name, s3_bucket, s3_prefix
@@ -107,7 +178,7 @@ pub async fn query_repository_state(
 
     let release = sqlx::query!(
         r#"
-        SELECT id, contents, clearsigned, detached
+        SELECT id, contents, clearsigned, detached, sha256_only
         FROM debian_repository_release
         WHERE repository_id = $1 AND distribution = $2
     "#,
@@ -122,17 +193,20 @@ pub async fn query_repository_state(
         "RELEASE_NOT_FOUND".to_string(),
         "release not found".to_string(),
     ))?;
+    if release.contents.is_empty() {
+        return Ok(None);
+    }
     let release_contents = Expected::Exists {
         key: format!("{}/dists/{}/Release", &repo.s3_prefix, &release_name),
         sha256sum: Sha256::digest(&release.contents).to_vec(),
-        contents: release.contents,
+        contents: release.contents.into_bytes(),
     };
     let release_clearsigned = release
         .clearsigned
         .map(|clearsigned| Expected::Exists {
             key: format!("{}/dists/{}/InRelease", &repo.s3_prefix, &release_name),
             sha256sum: Sha256::digest(&clearsigned).to_vec(),
-            contents: clearsigned,
+            contents: clearsigned.into_bytes(),
         })
         .unwrap_or(Expected::DoesNotExist {
             key: format!("{}/dists/{}/InRelease", &repo.s3_prefix, &release_name),
@@ -142,7 +216,7 @@ pub async fn query_repository_state(
         .map(|detached| Expected::Exists {
             key: format!("{}/dists/{}/Release.gpg", &repo.s3_prefix, &release_name),
             sha256sum: Sha256::digest(&detached).to_vec(),
-            contents: detached,
+            contents: detached.into_bytes(),
         })
         .unwrap_or(Expected::DoesNotExist {
             key: format!("{}/dists/{}/Release.gpg", &repo.s3_prefix, &release_name),
@@ -153,22 +227,38 @@ pub async fn query_repository_state(
         SELECT
             debian_repository_component.name AS "component",
             debian_repository_index_packages.architecture::TEXT AS "architecture!: String",
+            debian_repository_index_packages.compression AS "compression: Compression",
             debian_repository_index_packages.md5sum,
             debian_repository_index_packages.sha1sum,
             debian_repository_index_packages.sha256sum,
-            debian_repository_index_packages.contents
+            debian_repository_index_packages.sha512sum,
+            debian_repository_index_packages.contents,
+            debian_repository_index_packages.contents_encoding AS "contents_encoding: ContentsEncoding"
         FROM
             debian_repository_index_packages
             JOIN debian_repository_component ON debian_repository_index_packages.component_id = debian_repository_component.id
         WHERE
             debian_repository_component.release_id = $1
+            AND ($2::TEXT IS NULL OR debian_repository_component.name = $2)
+            AND ($3::TEXT IS NULL OR debian_repository_index_packages.architecture::TEXT = $3)
     "#,
         &release.id,
+        scope.component,
+        scope.architecture,
     )
     .fetch_all(&mut **tx)
     .await
     .map_err(ErrorResponse::from)?;
     let packages_indexes = packages_indexes
+        .into_iter()
+        .map(|mut packages_index| {
+            // Indexes may be stored compressed, but are published (and hashed)
+            // in their decoded form.
+            packages_index.contents =
+                decode_contents(packages_index.contents_encoding, packages_index.contents)?;
+            Ok(packages_index)
+        })
+        .collect::<Result<Vec<_>, ErrorResponse>>()?
         .into_iter()
         .flat_map(|packages_index| {
             let by_hash_prefix = format!(
@@ -180,24 +270,41 @@ pub async fn query_repository_state(
             );
             let sha256sum = hex::decode(&packages_index.sha256sum)
                 .expect("could not decode Packages index SHA256 sum");
-            let contents = String::from_utf8(packages_index.contents).unwrap();
-            [
+            let contents = packages_index.contents;
+            let standard = [
                 format!(
-                    "{}/dists/{}/{}/binary-{}/Packages",
+                    "{}/dists/{}/{}/binary-{}/Packages{}",
                     &repo.s3_prefix,
                     &release_name,
                     &packages_index.component,
-                    &packages_index.architecture
+                    &packages_index.architecture,
+                    packages_index
+                        .compression
+                        .map(|c| c.extension())
+                        .unwrap_or_default()
                 ),
                 format!("{}/SHA256/{}", by_hash_prefix, packages_index.sha256sum),
+            ];
+            // Indexes without a SHA512 sum aren't listed in the Release
+            // file's SHA512 section, so they have no SHA512 by-hash copy.
+            let sha512 = packages_index
+                .sha512sum
+                .map(|sha512sum| format!("{by_hash_prefix}/SHA512/{sha512sum}"));
+            // Distributions that only publish SHA256 checksums don't have the
+            // legacy by-hash copies.
+            let legacy = [
                 format!("{}/SHA1/{}", by_hash_prefix, packages_index.sha1sum),
                 format!("{}/MD5Sum/{}", by_hash_prefix, packages_index.md5sum),
-            ]
-            .map(|key| Expected::Exists {
-                key,
-                sha256sum: sha256sum.clone(),
-                contents: contents.clone(),
-            })
+            ];
+            standard
+                .into_iter()
+                .chain(sha512)
+                .chain(legacy.into_iter().filter(|_| !release.sha256_only))
+                .map(move |key| Expected::Exists {
+                    key,
+                    sha256sum: sha256sum.clone(),
+                    contents: contents.clone(),
+                })
         })
         .collect::<Vec<_>>();
 
@@ -206,6 +313,7 @@ pub async fn query_repository_state(
         r#"
         SELECT
             debian_repository_package.s3_bucket,
+            debian_repository_package.s3_key,
             debian_repository_package.sha256sum,
             debian_repository_component_package.filename
         FROM
@@ -214,8 +322,12 @@ pub async fn query_repository_state(
             JOIN debian_repository_component ON debian_repository_component_package.component_id = debian_repository_component.id
         WHERE
             debian_repository_component.release_id = $1
+            AND ($2::TEXT IS NULL OR debian_repository_component.name = $2)
+            AND ($3::TEXT IS NULL OR debian_repository_package.architecture::TEXT = $3)
         "#,
         &release.id,
+        scope.component,
+        scope.architecture,
     )
     .fetch_all(&mut **tx)
     .await
@@ -224,102 +336,93 @@ pub async fn query_repository_state(
         .into_iter()
         .map(|package| Expected::Exists {
             key: format!("{}/{}", repo.s3_prefix, package.filename),
-            contents: format!("{}/packages/{}", package.s3_bucket, package.sha256sum),
+            contents: copy_source(
+                &package.s3_bucket,
+                &canonical_key(package.s3_key.as_deref(), &package.sha256sum),
+            )
+            .into_bytes(),
             sha256sum: hex::decode(&package.sha256sum)
                 .expect("could not decode package SHA256 sum"),
         })
         .collect::<Vec<_>>();
 
-    Ok(RepositoryState {
+    Ok(Some(RepositoryState {
         s3_bucket: repo.s3_bucket,
         release_contents,
         release_detachsigned,
         release_clearsigned,
         packages_indexes,
         packages,
-    })
+    }))
 }
 
-#[instrument(level = Level::DEBUG, skip(s3))]
+/// Check an object in S3 against its expected state, returning why it is
+/// inconsistent, or `None` if it is consistent.
+#[instrument(level = Level::DEBUG, skip(store))]
 async fn s3_object_consistent(
-    s3: &aws_sdk_s3::Client,
+    store: &dyn ObjectStore,
     s3_bucket: &str,
     expected: &Expected,
-) -> Result<bool, ErrorResponse> {
+) -> Result<Option<Inconsistency>, ErrorResponse> {
+    let checksum = store.head_object_checksum(s3_bucket, expected.key()).await;
     Ok(match expected {
-        Expected::Exists { key, sha256sum, .. } => s3
-            .head_object()
-            .bucket(s3_bucket)
-            .key(key)
-            .checksum_mode(ChecksumMode::Enabled)
-            .send()
-            .await
-            .map(|head| {
-                head.checksum_sha256()
-                    .map(|checksum| {
-                        let expected = base64::engine::general_purpose::STANDARD.encode(sha256sum);
-                        debug!(actual = ?checksum, ?expected, "checking object sha256 checksum");
-                        checksum == expected
-                    })
-                    .unwrap_or_else(|| {
-                        debug!("could not read object sha256 checksum");
-                        false
-                    })
-            })
-            .unwrap_or_else(|err| {
+        Expected::Exists { sha256sum, .. } => match checksum {
+            Ok(actual) => {
+                let expected = hex::encode(sha256sum);
+                debug!(?actual, ?expected, "checking object sha256 checksum");
+                if actual.as_ref() == Some(&expected) {
+                    None
+                } else {
+                    Some(Inconsistency::ChecksumMismatch { expected, actual })
+                }
+            }
+            // Objects that can't be read are rewritten just like missing
+            // objects, so they are reported the same way.
+            Err(err) => {
                 debug!(?err, "could not get object");
-                false
-            }),
-        Expected::DoesNotExist { key } => s3
-            .head_object()
-            .bucket(s3_bucket)
-            .key(key)
-            .checksum_mode(ChecksumMode::Enabled)
-            .send()
-            .await
-            .is_err_and(|err| err.into_service_error().is_not_found()),
+                Some(Inconsistency::Missing)
+            }
+        },
+        Expected::DoesNotExist { .. } => match checksum {
+            Err(ObjectStoreError::NotFound) => None,
+            _ => Some(Inconsistency::ShouldNotExist),
+        },
     })
 }
 
-#[instrument(level = Level::DEBUG, skip(s3))]
+#[instrument(level = Level::DEBUG, skip(store))]
 pub async fn check_s3_consistency(
-    s3: &aws_sdk_s3::Client,
+    store: &dyn ObjectStore,
     state: RepositoryState,
 ) -> Result<InconsistentObjects, ErrorResponse> {
+    let mut reasons = HashMap::new();
+    let mut check = async |expected: Expected| -> Result<Option<Expected>, ErrorResponse> {
+        Ok(
+            match s3_object_consistent(store, &state.s3_bucket, &expected).await? {
+                Some(reason) => {
+                    reasons.insert(expected.key().to_string(), reason);
+                    Some(expected)
+                }
+                None => None,
+            },
+        )
+    };
+
     // Check release files for consistency.
-    let release_contents =
-        if s3_object_consistent(s3, &state.s3_bucket, &state.release_contents).await? {
-            None
-        } else {
-            Some(state.release_contents)
-        };
-    let release_clearsigned =
-        if s3_object_consistent(s3, &state.s3_bucket, &state.release_clearsigned).await? {
-            None
-        } else {
-            Some(state.release_clearsigned)
-        };
-    let release_detachsigned =
-        if s3_object_consistent(s3, &state.s3_bucket, &state.release_detachsigned).await? {
-            None
-        } else {
-            Some(state.release_detachsigned)
-        };
+    let release_contents = check(state.release_contents).await?;
+    let release_clearsigned = check(state.release_clearsigned).await?;
+    let release_detachsigned = check(state.release_detachsigned).await?;
 
     // Check package indexes for consistency.
     let mut packages_indexes = Vec::new();
     for packages_index in state.packages_indexes {
-        if !s3_object_consistent(s3, &state.s3_bucket, &packages_index).await? {
-            packages_indexes.push(packages_index);
-        }
+        packages_indexes.extend(check(packages_index).await?);
     }
 
     // Check packages for consistency.
     let mut packages = Vec::new();
     for package in state.packages {
-        if !s3_object_consistent(s3, &state.s3_bucket, &package).await? {
-            packages.push(package);
-        }
+        packages.extend(check(package).await?);
     }
 
     Ok(InconsistentObjects {
@@ -329,22 +432,133 @@ pub async fn check_s3_consistency(
         release_detachsigned,
         packages_indexes,
         packages,
+        reasons,
     })
 }
 
+/// Treat every expected object as inconsistent, so that resyncing rewrites
+/// all of them regardless of their current state in S3.
+impl From<RepositoryState> for InconsistentObjects {
+    fn from(state: RepositoryState) -> Self {
+        Self {
+            s3_bucket: state.s3_bucket,
+            release_contents: Some(state.release_contents),
+            release_clearsigned: Some(state.release_clearsigned),
+            release_detachsigned: Some(state.release_detachsigned),
+            packages_indexes: state.packages_indexes,
+            packages: state.packages,
+            reasons: HashMap::new(),
+        }
+    }
+}
+
 /// This Summary object is safe to serialize and send to clients, because it is
 /// reasonably sized and doesn't leak implementation details (like S3 prefixes).
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct InconsistentSummary {
     pub release: bool,
     pub release_clearsigned: bool,
     pub release_detachsigned: bool,
     pub packages_indexes: Vec<String>,
     pub packages: Vec<String>,
+    /// Why each object is inconsistent, keyed by its path in the summary
+    /// (e.g. `dists/stable/Release`). Objects without a reason, like those
+    /// rewritten by a forced resync, aren't listed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reasons: BTreeMap<String, Inconsistency>,
+}
+
+impl InconsistentSummary {
+    /// Add the inconsistent objects of another distribution to this summary.
+    pub fn extend(&mut self, other: &InconsistentSummary) {
+        self.release |= other.release;
+        self.release_clearsigned |= other.release_clearsigned;
+        self.release_detachsigned |= other.release_detachsigned;
+        self.packages_indexes
+            .extend(other.packages_indexes.iter().cloned());
+        // Distributions can share pool objects, so a package may be reported
+        // by several of them.
+        for package in &other.packages {
+            if !self.packages.contains(package) {
+                self.packages.push(package.clone());
+            }
+        }
+        self.reasons
+            .extend(other.reasons.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// The objects of the summary, with the reason each is inconsistent if
+    /// known, in the order they are listed.
+    pub fn objects(&self) -> Vec<(&str, Option<&Inconsistency>)> {
+        let release_files = [
+            (self.release, "Release"),
+            (self.release_clearsigned, "InRelease"),
+            (self.release_detachsigned, "Release.gpg"),
+        ];
+        let mut objects = Vec::new();
+        for (inconsistent, name) in release_files {
+            if !inconsistent {
+                continue;
+            }
+            // The summary only records whether each Release file is
+            // inconsistent, so list them by the paths that have reasons, or by
+            // name if there are none.
+            let mut reasons = self
+                .reasons
+                .iter()
+                .filter(|(path, _)| {
+                    path.starts_with("dists/")
+                        && path.rsplit_once('/').is_some_and(|(_, file)| file == name)
+                })
+                .peekable();
+            if reasons.peek().is_none() {
+                objects.push((name, None));
+            }
+            objects.extend(reasons.map(|(path, reason)| (path.as_str(), Some(reason))));
+        }
+        for object in self.packages_indexes.iter().chain(&self.packages) {
+            objects.push((object.as_str(), self.reasons.get(object)));
+        }
+        objects
+    }
+
+    /// Whether every checked object was consistent.
+    pub fn is_consistent(&self) -> bool {
+        !self.release
+            && !self.release_clearsigned
+            && !self.release_detachsigned
+            && self.packages_indexes.is_empty()
+            && self.packages.is_empty()
+    }
+}
+
+/// The path of an object in the summary. The S3 prefix of indexes is removed
+/// to avoid leaking information.
+fn summary_path(expected: &Expected) -> String {
+    let key = expected.key();
+    match key.split_once("/dists/") {
+        Some((_, suffix)) => format!("dists/{suffix}"),
+        None => key.to_string(),
+    }
 }
 
 impl From<&InconsistentObjects> for InconsistentSummary {
     fn from(inconsistent_objects: &InconsistentObjects) -> Self {
+        let release_files = [
+            &inconsistent_objects.release_contents,
+            &inconsistent_objects.release_clearsigned,
+            &inconsistent_objects.release_detachsigned,
+        ];
+        let reasons = release_files
+            .into_iter()
+            .flatten()
+            .chain(&inconsistent_objects.packages_indexes)
+            .chain(&inconsistent_objects.packages)
+            .filter_map(|expected| {
+                let reason = inconsistent_objects.reasons.get(expected.key())?;
+                Some((summary_path(expected), reason.clone()))
+            })
+            .collect();
         Self {
             release: inconsistent_objects.release_contents.is_some(),
             release_clearsigned: inconsistent_objects.release_clearsigned.is_some(),
@@ -352,18 +566,431 @@ impl From<&InconsistentObjects> for InconsistentSummary {
             packages_indexes: inconsistent_objects
                 .packages_indexes
                 .iter()
-                .map(|pi| {
-                    // Remove the S3 prefix to avoid leaking information.
-                    let path = pi.key();
-                    let (_, suffix) = path.split_once("/dists/").unwrap();
-                    format!("dists/{suffix}")
-                })
+                .map(summary_path)
                 .collect(),
             packages: inconsistent_objects
                 .packages
                 .iter()
                 .map(|p| p.key().to_string())
                 .collect(),
+            reasons,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::repo::index::encode_contents;
+
+    #[test]
+    fn summary_reports_reasons() {
+        let exists = |key: &str| Expected::Exists {
+            key: key.to_string(),
+            contents: Vec::new(),
+            sha256sum: vec![0; 32],
+        };
+        let mismatch = Inconsistency::ChecksumMismatch {
+            expected: hex::encode([0; 32]),
+            actual: Some(hex::encode([1; 32])),
+        };
+        let objects = InconsistentObjects {
+            s3_bucket: String::from("bucket"),
+            release_contents: Some(exists("1/repo/dists/stable/Release")),
+            release_detachsigned: None,
+            release_clearsigned: None,
+            packages_indexes: vec![
+                exists("1/repo/dists/stable/main/binary-amd64/Packages"),
+                Expected::DoesNotExist {
+                    key: String::from("1/repo/dists/stable/main/binary-amd64/by-hash/SHA256/ab"),
+                },
+            ],
+            packages: vec![exists("1/repo/pool/main/t/test/test_1.0_amd64.deb")],
+            reasons: HashMap::from([
+                (
+                    String::from("1/repo/dists/stable/Release"),
+                    mismatch.clone(),
+                ),
+                (
+                    String::from("1/repo/dists/stable/main/binary-amd64/Packages"),
+                    Inconsistency::Missing,
+                ),
+                (
+                    String::from("1/repo/dists/stable/main/binary-amd64/by-hash/SHA256/ab"),
+                    Inconsistency::ShouldNotExist,
+                ),
+            ]),
+        };
+
+        let summary = InconsistentSummary::from(&objects);
+        assert_eq!(
+            summary.objects(),
+            vec![
+                ("dists/stable/Release", Some(&mismatch)),
+                (
+                    "dists/stable/main/binary-amd64/Packages",
+                    Some(&Inconsistency::Missing)
+                ),
+                (
+                    "dists/stable/main/binary-amd64/by-hash/SHA256/ab",
+                    Some(&Inconsistency::ShouldNotExist)
+                ),
+                ("1/repo/pool/main/t/test/test_1.0_amd64.deb", None),
+            ]
+        );
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(
+            json["reasons"]["dists/stable/Release"],
+            serde_json::json!({
+                "reason": "checksum_mismatch",
+                "expected": hex::encode([0; 32]),
+                "actual": hex::encode([1; 32]),
+            })
+        );
+        assert_eq!(
+            json["reasons"]["dists/stable/main/binary-amd64/Packages"],
+            serde_json::json!({ "reason": "missing" })
+        );
+    }
+
+    /// An object store that only records the checksums of its objects. Checking
+    /// consistency only reads checksums, so writes fail with an error naming
+    /// the unexpected call.
+    #[derive(Debug, Default)]
+    struct ChecksumStore(HashMap<String, Option<String>>);
+
+    impl ChecksumStore {
+        fn unsupported(call: String) -> ObjectStoreError {
+            ObjectStoreError::Other(format!(
+                "ChecksumStore only supports head_object_checksum, but got {call}"
+            ))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ObjectStore for ChecksumStore {
+        async fn put_object(
+            &self,
+            bucket: &str,
+            key: &str,
+            _: bytes::Bytes,
+            _: &str,
+        ) -> Result<(), ObjectStoreError> {
+            Err(Self::unsupported(format!(
+                "put_object({bucket:?}, {key:?})"
+            )))
+        }
+
+        async fn copy_object(
+            &self,
+            bucket: &str,
+            key: &str,
+            source: &str,
+        ) -> Result<(), ObjectStoreError> {
+            Err(Self::unsupported(format!(
+                "copy_object({bucket:?}, {key:?}, {source:?})"
+            )))
+        }
+
+        async fn delete_objects(
+            &self,
+            bucket: &str,
+            keys: &[String],
+        ) -> Result<(), ObjectStoreError> {
+            Err(Self::unsupported(format!(
+                "delete_objects({bucket:?}, {keys:?})"
+            )))
+        }
+
+        async fn head_object_checksum(
+            &self,
+            _: &str,
+            key: &str,
+        ) -> Result<Option<String>, ObjectStoreError> {
+            self.0.get(key).cloned().ok_or(ObjectStoreError::NotFound)
+        }
+    }
+
+    #[tokio::test]
+    async fn check_reports_each_inconsistency() {
+        let exists = |key: &str, contents: &[u8]| Expected::Exists {
+            key: key.to_string(),
+            contents: contents.to_vec(),
+            sha256sum: Sha256::digest(contents).to_vec(),
+        };
+        let checksum = |contents: &[u8]| Some(hex::encode(Sha256::digest(contents)));
+        let state = RepositoryState {
+            s3_bucket: String::from("bucket"),
+            release_contents: exists("r/dists/stable/Release", b"release"),
+            release_clearsigned: exists("r/dists/stable/InRelease", b"clearsigned"),
+            release_detachsigned: Expected::DoesNotExist {
+                key: String::from("r/dists/stable/Release.gpg"),
+            },
+            packages_indexes: vec![
+                exists("r/dists/stable/main/binary-amd64/Packages", b"index"),
+                Expected::DoesNotExist {
+                    key: String::from("r/dists/stable/main/binary-amd64/by-hash/SHA256/old"),
+                },
+            ],
+            packages: vec![exists("r/pool/main/t/test/test_1.0_amd64.deb", b"deb")],
+        };
+        let store = ChecksumStore(HashMap::from([
+            (String::from("r/dists/stable/Release"), checksum(b"release")),
+            (String::from("r/dists/stable/InRelease"), checksum(b"stale")),
+            (
+                String::from("r/dists/stable/main/binary-amd64/by-hash/SHA256/old"),
+                checksum(b"old"),
+            ),
+            (String::from("r/pool/main/t/test/test_1.0_amd64.deb"), None),
+        ]));
+
+        let summary =
+            InconsistentSummary::from(&check_s3_consistency(&store, state).await.unwrap());
+        assert!(!summary.release && summary.release_clearsigned && !summary.release_detachsigned);
+        assert_eq!(
+            summary.objects(),
+            vec![
+                (
+                    "dists/stable/InRelease",
+                    Some(&Inconsistency::ChecksumMismatch {
+                        expected: checksum(b"clearsigned").unwrap(),
+                        actual: checksum(b"stale"),
+                    })
+                ),
+                (
+                    "dists/stable/main/binary-amd64/Packages",
+                    Some(&Inconsistency::Missing)
+                ),
+                (
+                    "dists/stable/main/binary-amd64/by-hash/SHA256/old",
+                    Some(&Inconsistency::ShouldNotExist)
+                ),
+                (
+                    "r/pool/main/t/test/test_1.0_amd64.deb",
+                    Some(&Inconsistency::ChecksumMismatch {
+                        expected: checksum(b"deb").unwrap(),
+                        actual: None,
+                    })
+                ),
+            ]
+        );
+    }
+
+    /// Scoping the repository state only limits the Packages indexes and
+    /// packages; the Release files are always included.
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "../index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn scope_limits_indexes_and_packages(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = TenantID(1);
+
+        // The fixture's checksums are placeholders, but the repository state
+        // decodes them as hex.
+        sqlx::query!(
+            "UPDATE debian_repository_index_packages SET sha256sum = encode(sha256(contents), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "UPDATE debian_repository_package SET sha256sum = encode(sha256(sha256sum::BYTEA), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let state = query_repository_state(
+            &mut tx,
+            &tenant_id,
+            String::from("test-multi-arch"),
+            String::from("stable"),
+            &SyncScope::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        // Each index has a canonical key and three by-hash keys.
+        assert_eq!(state.packages_indexes.len(), 8);
+        assert_eq!(state.packages.len(), 2);
+
+        // Forced resyncs treat every object as inconsistent.
+        let forced = InconsistentSummary::from(&InconsistentObjects::from(state));
+        assert!(forced.release && forced.release_clearsigned && forced.release_detachsigned);
+        assert_eq!(forced.packages_indexes.len(), 8);
+        assert_eq!(forced.packages.len(), 2);
+
+        let state = query_repository_state(
+            &mut tx,
+            &tenant_id,
+            String::from("test-multi-arch"),
+            String::from("stable"),
+            &SyncScope {
+                component: Some(String::from("main")),
+                architecture: Some(String::from("arm64")),
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            state.release_contents.key(),
+            "1/test-multi-arch/dists/stable/Release"
+        );
+        assert_eq!(state.packages_indexes.len(), 4);
+        assert!(
+            state
+                .packages_indexes
+                .iter()
+                .all(|index| index.key().contains("/binary-arm64/"))
+        );
+        assert_eq!(state.packages.len(), 1);
+        assert_eq!(
+            state.packages[0].key(),
+            "1/test-multi-arch/pool/main/t/test-package/test-package_1.0.0_arm64.deb"
+        );
+
+        let state = query_repository_state(
+            &mut tx,
+            &tenant_id,
+            String::from("test-multi-arch"),
+            String::from("stable"),
+            &SyncScope {
+                component: Some(String::from("contrib")),
+                architecture: None,
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(state.packages_indexes.is_empty());
+        assert!(state.packages.is_empty());
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Distributions that only publish SHA256 checksums don't expect the
+    /// legacy by-hash copies of their indexes.
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "../index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn sha256_only_omits_legacy_by_hash(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = TenantID(1);
+
+        sqlx::query!(
+            "UPDATE debian_repository_index_packages SET sha256sum = encode(sha256(contents), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "UPDATE debian_repository_package SET sha256sum = encode(sha256(sha256sum::BYTEA), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!("UPDATE debian_repository_release SET sha256_only = true WHERE id = 1000")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+
+        let state = query_repository_state(
+            &mut tx,
+            &tenant_id,
+            String::from("test-multi-arch"),
+            String::from("stable"),
+            &SyncScope::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        // Each index has a canonical key and a SHA256 by-hash key.
+        assert_eq!(state.packages_indexes.len(), 4);
+        assert!(state.packages_indexes.iter().all(|index| {
+            !index.key().contains("/by-hash/MD5Sum/") && !index.key().contains("/by-hash/SHA1/")
+        }));
+
+        tx.rollback().await.unwrap();
+    }
+
+    /// Indexes that are stored compressed in the database are resynced in
+    /// their decoded form, matching the checksums in the Release file.
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "../index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn resync_decodes_stored_index_contents(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = TenantID(1);
+
+        sqlx::query!(
+            "UPDATE debian_repository_index_packages SET sha256sum = encode(sha256(contents), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "UPDATE debian_repository_package SET sha256sum = encode(sha256(sha256sum::BYTEA), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let contents = sqlx::query_scalar!(
+            r#"
+            SELECT contents
+            FROM debian_repository_index_packages
+            WHERE component_id = 1000 AND architecture = 'arm64'
+            "#
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        let stored = encode_contents(Some(ContentsEncoding::Gzip), &contents);
+        assert_ne!(stored, contents);
+        sqlx::query!(
+            r#"
+            UPDATE debian_repository_index_packages
+            SET contents = $1, contents_encoding = 'gzip'
+            WHERE component_id = 1000 AND architecture = 'arm64'
+            "#,
+            stored,
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let state = query_repository_state(
+            &mut tx,
+            &tenant_id,
+            String::from("test-multi-arch"),
+            String::from("stable"),
+            &SyncScope {
+                component: None,
+                architecture: Some(String::from("arm64")),
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let resynced = InconsistentObjects::from(state);
+        assert_eq!(resynced.packages_indexes.len(), 4);
+        for index in &resynced.packages_indexes {
+            let Expected::Exists {
+                sha256sum,
+                contents: resynced_contents,
+                ..
+            } = index
+            else {
+                panic!("expected index {:?} to exist", index.key());
+            };
+            assert_eq!(resynced_contents, &contents);
+            assert_eq!(sha256sum, &Sha256::digest(&contents).to_vec());
         }
+
+        tx.rollback().await.unwrap();
     }
 }
@@ -1,65 +1,222 @@
-use aws_sdk_s3::types::ChecksumAlgorithm;
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::StatusCode,
 };
-use base64::Engine;
-use md5::{Digest as _, Md5};
 use serde::{Deserialize, Serialize};
-use tracing::{Level, debug, instrument};
+use tracing::{Level, debug, instrument, warn};
 
 use crate::{
     api::{ErrorResponse, TenantID},
     server::{
         ServerState,
+        object_store::ObjectStore,
         repo::{
             decode_repo_name,
             sync::{
-                Expected, InconsistentObjects, InconsistentSummary, check_s3_consistency,
-                query_repository_state,
+                Expected, InconsistentObjects, InconsistentSummary, SyncScope,
+                check_s3_consistency, query_repository_state,
             },
         },
     },
 };
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResyncParams {
+    /// Rewrite every object of the distribution from the database state, even
+    /// if it is already consistent.
+    ///
+    /// This is useful for applying changes to how objects are written (such
+    /// as their metadata) to existing repositories.
+    #[serde(default)]
+    pub force: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ResyncRepositoryResponse {
     #[serde(flatten)]
     pub status: InconsistentSummary,
 }
 
+/// The result of resyncing every distribution of a repository.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResyncAllDistributionsResponse {
+    /// Every object that was inconsistent, across all distributions that were
+    /// resynced.
+    pub total: InconsistentSummary,
+    pub distributions: Vec<DistributionResyncResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributionResyncResult {
+    pub distribution: String,
+    #[serde(flatten)]
+    pub outcome: DistributionResyncOutcome,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum DistributionResyncOutcome {
+    Consistent,
+    Resynced {
+        #[serde(flatten)]
+        status: InconsistentSummary,
+    },
+    Failed {
+        error: String,
+    },
+}
+
 #[axum::debug_handler]
 #[instrument(skip(state))]
 pub async fn handler(
     State(state): State<ServerState>,
     tenant_id: TenantID,
     Path((repo_name, release_name)): Path<(String, String)>,
+    Query(scope): Query<SyncScope>,
+    Query(params): Query<ResyncParams>,
 ) -> Result<Json<ResyncRepositoryResponse>, ErrorResponse> {
     // The repository name in the path is percent-encoded.
     let repo_name = decode_repo_name(&repo_name)?;
     let release_name = decode_repo_name(&release_name)?;
+    Ok(Json(
+        resync_distribution(
+            &state,
+            &tenant_id,
+            repo_name,
+            release_name,
+            &scope,
+            params.force,
+        )
+        .await?,
+    ))
+}
+
+/// Resync every distribution of a repository.
+///
+/// A distribution that fails to resync doesn't stop the others; its error is
+/// reported in the response instead.
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn repository_handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repo_name): Path<String>,
+    Query(params): Query<ResyncParams>,
+) -> Result<Json<ResyncAllDistributionsResponse>, ErrorResponse> {
+    // The repository name in the path is percent-encoded.
+    let repo_name = decode_repo_name(&repo_name)?;
+
+    let repo = sqlx::query!(
+        r#"
+        SELECT id
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        &repo_name,
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "REPOSITORY_NOT_FOUND",
+            format!("repository {repo_name:?} not found"),
+        )
+    })?;
+    let distributions = sqlx::query_scalar!(
+        r#"
+        SELECT distribution
+        FROM debian_repository_release
+        WHERE repository_id = $1
+        ORDER BY distribution
+        "#,
+        repo.id,
+    )
+    .fetch_all(&state.db)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    let mut total = InconsistentSummary::default();
+    let mut results = Vec::with_capacity(distributions.len());
+    for distribution in distributions {
+        let resynced = resync_distribution(
+            &state,
+            &tenant_id,
+            repo_name.clone(),
+            distribution.clone(),
+            &SyncScope::default(),
+            params.force,
+        )
+        .await;
+        let outcome = match resynced {
+            Ok(res) if res.status.is_consistent() => DistributionResyncOutcome::Consistent,
+            Ok(res) => {
+                total.extend(&res.status);
+                DistributionResyncOutcome::Resynced { status: res.status }
+            }
+            Err(err) => {
+                warn!(?distribution, ?err, "could not resync distribution");
+                DistributionResyncOutcome::Failed { error: err.message }
+            }
+        };
+        results.push(DistributionResyncResult {
+            distribution,
+            outcome,
+        });
+    }
+
+    Ok(Json(ResyncAllDistributionsResponse {
+        total,
+        distributions: results,
+    }))
+}
 
+/// Check a distribution against the database, and rewrite its inconsistent
+/// objects (or all of its objects, if forced).
+async fn resync_distribution(
+    state: &ServerState,
+    tenant_id: &TenantID,
+    repo_name: String,
+    release_name: String,
+    scope: &SyncScope,
+    force: bool,
+) -> Result<ResyncRepositoryResponse, ErrorResponse> {
     // Get current repository state.
     let mut tx = state.db.begin().await.unwrap();
     sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
         .execute(&mut *tx)
         .await
         .map_err(ErrorResponse::from)?;
-    let repo = query_repository_state(&mut tx, &tenant_id, repo_name, release_name).await?;
+    let repo = query_repository_state(&mut tx, tenant_id, repo_name, release_name, scope).await?;
     tx.commit().await.map_err(ErrorResponse::from)?;
+    let Some(repo) = repo else {
+        // The distribution has never been published, so there is nothing to
+        // sync.
+        return Ok(ResyncRepositoryResponse {
+            status: InconsistentSummary::default(),
+        });
+    };
     debug!(?repo, "loaded repository state");
 
-    // Check which S3 objects are inconsistent.
-    let inconsistent_objects = check_s3_consistency(&state.s3, repo).await?;
-    debug!(?inconsistent_objects, "checked S3");
+    // Check which S3 objects are inconsistent, or treat them all as
+    // inconsistent if forced.
+    let inconsistent_objects = if force {
+        InconsistentObjects::from(repo)
+    } else {
+        check_s3_consistency(state.object_store.as_ref(), repo).await?
+    };
+    debug!(?inconsistent_objects, force, "checked S3");
 
     // Resync inconsistent objects.
-    Ok(Json(resync_s3(&state.s3, inconsistent_objects).await?))
+    resync_s3(state.object_store.as_ref(), inconsistent_objects).await
 }
 
-#[instrument(level = Level::DEBUG, skip(s3))]
+#[instrument(level = Level::DEBUG, skip(store))]
 async fn resync_index(
-    s3: &aws_sdk_s3::Client,
+    store: &dyn ObjectStore,
     s3_bucket: &str,
     expected: Expected,
 ) -> Result<(), ErrorResponse> {
@@ -68,84 +225,148 @@ async fn resync_index(
             key,
             sha256sum,
             contents,
-        } => {
-            s3.put_object()
-                .bucket(s3_bucket)
-                .key(key)
-                .content_md5(
-                    base64::engine::general_purpose::STANDARD
-                        .encode(Md5::digest(contents.as_bytes())),
-                )
-                .checksum_algorithm(ChecksumAlgorithm::Sha256)
-                .checksum_sha256(base64::engine::general_purpose::STANDARD.encode(sha256sum))
-                .body(contents.as_bytes().to_vec().into())
-                .send()
-                .await
-                .unwrap();
-        }
-        Expected::DoesNotExist { key } => {
-            s3.delete_object()
-                .bucket(s3_bucket)
-                .key(key)
-                .send()
-                .await
-                .unwrap();
-        }
+        } => store
+            .put_object(s3_bucket, &key, contents.into(), &hex::encode(sha256sum))
+            .await
+            .map_err(|err| s3_error(&key, err)),
+        Expected::DoesNotExist { key } => delete_object(store, s3_bucket, key).await,
     }
-    Ok(())
 }
 
 /// Like `resync_index`, but for packages (which are copied from their canonical
 /// location, rather than uploaded directly).
-#[instrument(level = Level::DEBUG, skip(s3))]
+#[instrument(level = Level::DEBUG, skip(store))]
 async fn resync_package(
-    s3: &aws_sdk_s3::Client,
+    store: &dyn ObjectStore,
     s3_bucket: &str,
     expected: Expected,
 ) -> Result<(), ErrorResponse> {
     match expected {
         Expected::Exists { key, contents, .. } => {
-            s3.copy_object()
-                .bucket(s3_bucket)
-                .key(key)
-                .copy_source(contents)
-                .send()
+            let source =
+                String::from_utf8(contents).expect("package copy source is not valid UTF-8");
+            store
+                .copy_object(s3_bucket, &key, &source)
                 .await
-                .unwrap();
-        }
-        Expected::DoesNotExist { key } => {
-            s3.delete_object()
-                .bucket(s3_bucket)
-                .key(key)
-                .send()
-                .await
-                .unwrap();
+                .map_err(|err| s3_error(&key, err))
         }
+        Expected::DoesNotExist { key } => delete_object(store, s3_bucket, key).await,
     }
-    Ok(())
 }
 
-#[instrument(level = Level::DEBUG, skip(s3))]
+async fn delete_object(
+    store: &dyn ObjectStore,
+    s3_bucket: &str,
+    key: String,
+) -> Result<(), ErrorResponse> {
+    let keys = [key];
+    store
+        .delete_objects(s3_bucket, &keys)
+        .await
+        .map_err(|err| s3_error(&keys[0], err))
+}
+
+fn s3_error(key: &str, err: impl std::error::Error) -> ErrorResponse {
+    ErrorResponse::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "S3_ERROR",
+        format!("could not resync object {key:?}: {err}"),
+    )
+}
+
+#[instrument(level = Level::DEBUG, skip(store))]
 pub async fn resync_s3(
-    s3: &aws_sdk_s3::Client,
+    store: &dyn ObjectStore,
     inconsistent_objects: InconsistentObjects,
 ) -> Result<ResyncRepositoryResponse, ErrorResponse> {
     let status = InconsistentSummary::from(&inconsistent_objects);
     let s3_bucket = inconsistent_objects.s3_bucket;
     if let Some(release_contents) = inconsistent_objects.release_contents {
-        resync_index(s3, &s3_bucket, release_contents).await?;
+        resync_index(store, &s3_bucket, release_contents).await?;
     }
     if let Some(release_clearsigned) = inconsistent_objects.release_clearsigned {
-        resync_index(s3, &s3_bucket, release_clearsigned).await?;
+        resync_index(store, &s3_bucket, release_clearsigned).await?;
     }
     if let Some(release_detachsigned) = inconsistent_objects.release_detachsigned {
-        resync_index(s3, &s3_bucket, release_detachsigned).await?;
+        resync_index(store, &s3_bucket, release_detachsigned).await?;
     }
     for packages_index in inconsistent_objects.packages_indexes {
-        resync_index(s3, &s3_bucket, packages_index).await?;
+        resync_index(store, &s3_bucket, packages_index).await?;
     }
     for package in inconsistent_objects.packages {
-        resync_package(s3, &s3_bucket, package).await?;
+        resync_package(store, &s3_bucket, package).await?;
     }
     Ok(ResyncRepositoryResponse { status })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        server::repo::dist::create::CreateDistributionRequest,
+        testing::{AttuneTestServer, AttuneTestServerConfig},
+    };
+
+    /// Every distribution of the repository is reported, and unpublished ones
+    /// have nothing to resync.
+    #[sqlx::test(migrator = "crate::testing::MIGRATOR")]
+    async fn resync_all_distributions(pool: sqlx::PgPool) {
+        let server = AttuneTestServer::new(AttuneTestServerConfig {
+            db: pool,
+            s3_bucket_name: None,
+            http_api_token: None,
+        })
+        .await;
+        const REPO_NAME: &str = "resync_all_distributions";
+        let (tenant_id, api_token) = server.create_test_tenant(REPO_NAME).await;
+        server.create_repository(tenant_id, REPO_NAME).await;
+
+        for name in ["unstable", "stable"] {
+            server
+                .http
+                .post(&format!("/api/v0/repositories/{REPO_NAME}/distributions"))
+                .add_header("authorization", format!("Bearer {api_token}"))
+                .json(
+                    &CreateDistributionRequest::builder()
+                        .name(name)
+                        .suite(name)
+                        .codename(name)
+                        .build(),
+                )
+                .await
+                .assert_status_ok();
+        }
+
+        let response = server
+            .http
+            .post(&format!("/api/v0/repositories/{REPO_NAME}/sync"))
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_ok();
+        let response = response.json::<ResyncAllDistributionsResponse>();
+        assert!(response.total.is_consistent());
+        let distributions = response
+            .distributions
+            .iter()
+            .map(|dist| {
+                assert!(matches!(
+                    dist.outcome,
+                    DistributionResyncOutcome::Consistent
+                ));
+                dist.distribution.as_str()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(distributions, ["stable", "unstable"]);
+
+        let response = server
+            .http
+            .post("/api/v0/repositories/does-not-exist/sync")
+            .add_header("authorization", format!("Bearer {api_token}"))
+            .await;
+        response.assert_status_not_found();
+        assert_eq!(
+            response.json::<ErrorResponse>().error,
+            "REPOSITORY_NOT_FOUND"
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashSet};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use tracing::{debug, instrument};
+
+use crate::{
+    api::{ErrorResponse, TenantID},
+    server::{
+        ServerState,
+        repo::{
+            decode_repo_name,
+            sync::{Expected, SyncScope, query_repository_state},
+        },
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RepositoryUsageParams {
+    /// Also list the objects under the repository's S3 prefix, to report the
+    /// storage that is actually used, including orphaned objects that the
+    /// repository no longer refers to.
+    #[serde(default)]
+    pub list_objects: bool,
+}
+
+/// Storage used by published repository objects, as recorded in the database.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// Package files under `pool/`.
+    pub pool_objects: u64,
+    pub pool_bytes: u64,
+    /// Release files and Packages indexes under `dists/`, including the
+    /// by-hash copies of each index.
+    pub index_objects: u64,
+    pub index_bytes: u64,
+}
+
+impl Usage {
+    pub fn total_bytes(&self) -> u64 {
+        self.pool_bytes + self.index_bytes
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct DistributionUsage {
+    pub distribution: String,
+    #[serde(flatten)]
+    pub usage: Usage,
+}
+
+/// Storage used under the repository's S3 prefix, as listed from S3.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ObjectUsage {
+    pub objects: u64,
+    pub bytes: u64,
+    /// Objects that no distribution of the repository refers to, such as
+    /// leftovers of interrupted changes.
+    pub orphaned_objects: u64,
+    pub orphaned_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepositoryUsageResponse {
+    /// Usage of the whole repository. Pool objects that are shared by several
+    /// distributions are only counted once.
+    pub total: Usage,
+    pub distributions: Vec<DistributionUsage>,
+    /// Set if the objects were listed from S3.
+    #[serde(default)]
+    pub objects: Option<ObjectUsage>,
+}
+
+#[axum::debug_handler]
+#[instrument(skip(state))]
+pub async fn handler(
+    State(state): State<ServerState>,
+    tenant_id: TenantID,
+    Path(repository_name): Path<String>,
+    Query(params): Query<RepositoryUsageParams>,
+) -> Result<Json<RepositoryUsageResponse>, ErrorResponse> {
+    // The repository name in the path is percent-encoded.
+    let repository_name = decode_repo_name(&repository_name)?;
+
+    let mut tx = state.db.begin().await.map_err(ErrorResponse::from)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL SERIALIZABLE")
+        .execute(&mut *tx)
+        .await
+        .map_err(ErrorResponse::from)?;
+    let repo = sqlx::query!(
+        r#"
+        SELECT id, s3_bucket, s3_prefix
+        FROM debian_repository
+        WHERE tenant_id = $1 AND name = $2
+        "#,
+        tenant_id.0,
+        &repository_name,
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(ErrorResponse::from)?
+    .ok_or_else(|| {
+        ErrorResponse::new(
+            StatusCode::NOT_FOUND,
+            "REPO_NOT_FOUND".to_string(),
+            "repository not found".to_string(),
+        )
+    })?;
+    let (usage, expected_keys) =
+        query_usage(&mut tx, &tenant_id, &repository_name, repo.id).await?;
+    tx.commit().await.map_err(ErrorResponse::from)?;
+
+    let objects = if params.list_objects {
+        Some(list_object_usage(&state.s3, &repo.s3_bucket, &repo.s3_prefix, &expected_keys).await?)
+    } else {
+        None
+    };
+
+    Ok(Json(RepositoryUsageResponse { objects, ..usage }))
+}
+
+/// Compute the repository's usage from the database, along with the S3 keys of
+/// every object that the repository refers to.
+pub(super) async fn query_usage(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &TenantID,
+    repository_name: &str,
+    repository_id: i64,
+) -> Result<(RepositoryUsageResponse, HashSet<String>), ErrorResponse> {
+    let distributions = sqlx::query!(
+        r#"
+        SELECT distribution
+        FROM debian_repository_release
+        WHERE repository_id = $1
+        ORDER BY distribution
+        "#,
+        repository_id,
+    )
+    .fetch_all(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+
+    // Pool filenames don't include the distribution, so distributions that
+    // publish the same package in the same component share its pool object.
+    let pool = sqlx::query!(
+        r#"
+        SELECT DISTINCT
+            debian_repository_release.distribution,
+            debian_repository_component_package.filename,
+            debian_repository_package.size
+        FROM
+            debian_repository_component_package
+            JOIN debian_repository_component ON debian_repository_component_package.component_id = debian_repository_component.id
+            JOIN debian_repository_release ON debian_repository_component.release_id = debian_repository_release.id
+            JOIN debian_repository_package ON debian_repository_component_package.package_id = debian_repository_package.id
+        WHERE debian_repository_release.repository_id = $1
+        "#,
+        repository_id,
+    )
+    .fetch_all(&mut **tx)
+    .await
+    .map_err(ErrorResponse::from)?;
+    let mut pool_by_distribution = BTreeMap::<String, BTreeMap<String, i64>>::new();
+    for object in pool {
+        pool_by_distribution
+            .entry(object.distribution)
+            .or_default()
+            .insert(object.filename, object.size);
+    }
+
+    let mut total = Usage::default();
+    let mut repository_pool = BTreeMap::new();
+    let mut expected_keys = HashSet::new();
+    let mut usages = Vec::with_capacity(distributions.len());
+    for distribution in distributions {
+        let mut usage = Usage::default();
+        let state = query_repository_state(
+            tx,
+            tenant_id,
+            repository_name.to_string(),
+            distribution.distribution.clone(),
+            &SyncScope::default(),
+        )
+        .await?;
+        if let Some(state) = state {
+            let indexes = [
+                &state.release_contents,
+                &state.release_clearsigned,
+                &state.release_detachsigned,
+            ]
+            .into_iter()
+            .chain(&state.packages_indexes);
+            for index in indexes {
+                if let Expected::Exists { key, contents, .. } = index {
+                    usage.index_objects += 1;
+                    usage.index_bytes += contents.len() as u64;
+                    expected_keys.insert(key.clone());
+                }
+            }
+            expected_keys.extend(state.packages.iter().map(|p| p.key().to_string()));
+        }
+        for (filename, size) in pool_by_distribution
+            .remove(&distribution.distribution)
+            .unwrap_or_default()
+        {
+            usage.pool_objects += 1;
+            usage.pool_bytes += size as u64;
+            repository_pool.insert(filename, size);
+        }
+
+        total.index_objects += usage.index_objects;
+        total.index_bytes += usage.index_bytes;
+        usages.push(DistributionUsage {
+            distribution: distribution.distribution,
+            usage,
+        });
+    }
+    total.pool_objects = repository_pool.len() as u64;
+    total.pool_bytes = repository_pool.values().map(|size| *size as u64).sum();
+
+    Ok((
+        RepositoryUsageResponse {
+            total,
+            distributions: usages,
+            objects: None,
+        },
+        expected_keys,
+    ))
+}
+
+/// Total the objects under the repository's prefix, and those of them that
+/// aren't expected.
+#[instrument(skip(s3, expected_keys))]
+async fn list_object_usage(
+    s3: &aws_sdk_s3::Client,
+    s3_bucket: &str,
+    s3_prefix: &str,
+    expected_keys: &HashSet<String>,
+) -> Result<ObjectUsage, ErrorResponse> {
+    let mut pages = s3
+        .list_objects_v2()
+        .bucket(s3_bucket)
+        .prefix(format!("{s3_prefix}/"))
+        .into_paginator()
+        .send();
+    let mut usage = ObjectUsage::default();
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(|err| {
+            ErrorResponse::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .error("S3_LIST_FAILED")
+                .message(format!("could not list repository objects: {err}"))
+                .build()
+        })?;
+        for object in page.contents.unwrap_or_default() {
+            let size = object.size.unwrap_or_default() as u64;
+            usage.objects += 1;
+            usage.bytes += size;
+            if !object
+                .key
+                .as_ref()
+                .is_some_and(|key| expected_keys.contains(key))
+            {
+                debug!(key = ?object.key, "found orphaned object");
+                usage.orphaned_objects += 1;
+                usage.orphaned_bytes += size;
+            }
+        }
+    }
+    Ok(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[sqlx::test(
+        migrator = "crate::testing::MIGRATOR",
+        fixtures(path = "index/fixtures", scripts("setup_multi_arch"))
+    )]
+    async fn shared_pool_objects_counted_once(pool: sqlx::PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = TenantID(1);
+
+        // The fixture's checksums are placeholders, but the repository state
+        // decodes them as hex.
+        sqlx::query!(
+            "UPDATE debian_repository_index_packages SET sha256sum = encode(sha256(contents), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "UPDATE debian_repository_package SET sha256sum = encode(sha256(sha256sum::BYTEA), 'hex')"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        // Publish the amd64 package in a second distribution.
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_release (id, repository_id, distribution, suite, codename, contents, created_at, updated_at)
+            VALUES (1001, 1000, 'unstable', 'unstable', 'unstable', 'dummy content', NOW(), NOW());
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component (id, release_id, name, created_at, updated_at)
+            VALUES (1001, 1001, 'main', NOW(), NOW());
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            r#"
+            INSERT INTO debian_repository_component_package (component_id, package_id, filename, created_at, updated_at)
+            VALUES (1001, 1001, 'pool/main/t/test-package/test-package_1.0.0_amd64.deb', NOW(), NOW());
+            "#
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let (usage, expected_keys) = query_usage(&mut tx, &tenant_id, "test-multi-arch", 1000)
+            .await
+            .unwrap();
+        let distributions = usage
+            .distributions
+            .iter()
+            .map(|dist| (dist.distribution.as_str(), dist.usage.pool_objects))
+            .collect::<Vec<_>>();
+        assert_eq!(distributions, [("stable", 2), ("unstable", 1)]);
+        assert_eq!(usage.total.pool_objects, 2);
+        assert_eq!(usage.total.pool_bytes, 2048);
+
+        // Each distribution has a Release file, and stable's two indexes each
+        // have a canonical key and three by-hash keys.
+        assert_eq!(usage.total.index_objects, 10);
+        assert!(
+            expected_keys.contains(
+                "1/test-multi-arch/pool/main/t/test-package/test-package_1.0.0_arm64.deb"
+            )
+        );
+        assert!(expected_keys.contains("1/test-multi-arch/dists/unstable/Release"));
+    }
+}
@@ -0,0 +1,88 @@
+//! Bounded concurrency for S3 requests.
+//!
+//! Changes to a repository fan out into many S3 requests (one per index file,
+//! by-hash path, and Release file). Making all of them at once can exceed S3's
+//! request rate limits and get the server throttled, so requests are run
+//! through a semaphore that is shared by every request the server handles.
+
+use std::{future::Future, sync::Arc};
+
+use tokio::sync::Semaphore;
+
+/// A shared limit on the number of S3 requests in flight.
+#[derive(Clone, Debug)]
+pub struct S3Concurrency {
+    permits: Arc<Semaphore>,
+}
+
+impl S3Concurrency {
+    /// The default maximum number of S3 requests in flight.
+    pub const DEFAULT_LIMIT: usize = 32;
+
+    /// Allow at most `limit` S3 requests in flight at once.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limit.max(1))),
+        }
+    }
+
+    /// Run a future once a permit is available.
+    ///
+    /// The future is not polled (and so, for S3 requests, not sent) until the
+    /// permit is acquired.
+    pub async fn run<F: Future>(&self, future: F) -> F::Output {
+        // The semaphore is never closed, so acquiring a permit can't fail.
+        let _permit = self.permits.acquire().await.unwrap();
+        future.await
+    }
+
+    /// Like [`futures_util::future::join_all`], but with each future run
+    /// through [`S3Concurrency::run`].
+    pub async fn join_all<F: Future>(
+        &self,
+        futures: impl IntoIterator<Item = F>,
+    ) -> Vec<F::Output> {
+        futures_util::future::join_all(futures.into_iter().map(|future| self.run(future))).await
+    }
+}
+
+impl Default for S3Concurrency {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// The number of futures in flight never exceeds the limit.
+    #[tokio::test]
+    async fn bounds_in_flight_requests() {
+        const LIMIT: usize = 3;
+        let concurrency = S3Concurrency::new(LIMIT);
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+
+        let results = concurrency
+            .join_all((0..20).map(|i| {
+                let in_flight = &in_flight;
+                let max_in_flight = &max_in_flight;
+                async move {
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    for _ in 0..5 {
+                        tokio::task::yield_now().await;
+                    }
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    i
+                }
+            }))
+            .await;
+
+        assert_eq!(results, (0..20).collect::<Vec<_>>());
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), LIMIT);
+    }
+}
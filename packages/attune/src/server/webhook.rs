@@ -0,0 +1,230 @@
+//! Webhook notifications for published changes.
+//!
+//! A repository can have a webhook URL, which the server POSTs a JSON
+//! [`PublishEvent`] to after each change to one of the repository's
+//! distributions is published. Each notification is signed with HMAC-SHA256,
+//! keyed by the repository's webhook secret, so that receivers can check that
+//! it came from Attune: the `X-Attune-Signature` header is `sha256=` followed
+//! by the hex-encoded signature of the request body.
+//!
+//! Delivery is best-effort. Notifications are sent in the background, and are
+//! retried a few times if the receiver is unreachable or returns a server
+//! error, but failures never fail the change.
+
+use std::{
+    sync::{
+        LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use hmac::{Hmac, Mac as _};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{debug, warn};
+
+use crate::retry::{retry_delay_default, retry_infinite};
+
+pub const SIGNATURE_HEADER: &str = "X-Attune-Signature";
+
+/// How many times to try delivering a notification before giving up.
+const MAX_DELIVERY_ATTEMPTS: usize = 5;
+
+static HTTP: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("could not build webhook HTTP client")
+});
+
+/// The body of a webhook notification.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PublishEvent {
+    pub repository: String,
+    pub distribution: String,
+    pub component: String,
+    pub action: PublishAction,
+    /// The package that was added or removed. Re-signs don't change any
+    /// package, so this is unset for them.
+    pub package: Option<PackageMeta>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PublishAction {
+    Add,
+    Remove,
+    Resign,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PackageMeta {
+    pub name: String,
+    pub version: String,
+    pub architecture: String,
+}
+
+/// A repository's webhook.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    pub url: String,
+    pub secret: String,
+}
+
+impl Webhook {
+    /// Deliver a notification in the background.
+    pub fn notify(&self, event: PublishEvent) {
+        let webhook = self.clone();
+        tokio::spawn(async move { webhook.deliver(&event).await });
+    }
+
+    /// Deliver a notification, retrying a few times if the receiver is
+    /// unreachable or returns a server error.
+    async fn deliver(&self, event: &PublishEvent) {
+        let body = Bytes::from(serde_json::to_vec(event).expect("could not serialize event"));
+        let signature = signature(&self.secret, &body);
+        let attempts = AtomicUsize::new(0);
+        let result = retry_infinite(
+            || {
+                let attempt = attempts.fetch_add(1, Ordering::Relaxed) + 1;
+                let body = body.clone();
+                let signature = &signature;
+                async move {
+                    self.send(body, signature)
+                        .await
+                        .map_err(|err| (attempt, err))
+                }
+            },
+            |(attempt, err)| {
+                debug!(url = %self.url, attempt, err = %err.message, "webhook delivery failed");
+                err.retryable && *attempt < MAX_DELIVERY_ATTEMPTS
+            },
+            retry_delay_default,
+        )
+        .await;
+        match result {
+            Ok(()) => debug!(url = %self.url, ?event, "delivered webhook"),
+            Err((attempts, err)) => warn!(
+                url = %self.url,
+                ?event,
+                attempts,
+                err = %err.message,
+                "could not deliver webhook"
+            ),
+        }
+    }
+
+    async fn send(&self, body: Bytes, signature: &str) -> Result<(), DeliveryError> {
+        let response = HTTP
+            .post(&self.url)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| DeliveryError {
+                message: err.to_string(),
+                retryable: true,
+            })?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(DeliveryError {
+            message: format!("receiver returned {status}"),
+            retryable: status.is_server_error() || status == http::StatusCode::TOO_MANY_REQUESTS,
+        })
+    }
+}
+
+struct DeliveryError {
+    message: String,
+    retryable: bool,
+}
+
+/// The value of the signature header for a request body.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Generate a new webhook secret.
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Router, extract::State, http::HeaderMap, routing::post};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn sign_with_hmac_sha256() {
+        // RFC 4231, test case 2.
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn deliver_signed_notification() {
+        type Received = Arc<Mutex<Vec<(Option<String>, Bytes)>>>;
+        let received = Received::default();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State(received): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                        let signature = headers
+                            .get(SIGNATURE_HEADER)
+                            .map(|value| value.to_str().unwrap().to_string());
+                        received.lock().unwrap().push((signature, body));
+                    },
+                ),
+            )
+            .with_state(received.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let webhook = Webhook {
+            url: format!("http://{address}/hook"),
+            secret: String::from("secret"),
+        };
+        let event = PublishEvent {
+            repository: String::from("debian"),
+            distribution: String::from("stable"),
+            component: String::from("main"),
+            action: PublishAction::Add,
+            package: Some(PackageMeta {
+                name: String::from("curl"),
+                version: String::from("8.0.0"),
+                architecture: String::from("amd64"),
+            }),
+        };
+        webhook.deliver(&event).await;
+
+        let received = received.lock().unwrap();
+        let [(signature, body)] = received.as_slice() else {
+            panic!("expected one notification, got {received:?}");
+        };
+        assert_eq!(
+            signature.as_deref(),
+            Some(super::signature("secret", body).as_str())
+        );
+        assert_eq!(serde_json::from_slice::<PublishEvent>(body).unwrap(), event);
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(body).unwrap()["action"],
+            "add"
+        );
+    }
+}
@@ -1,10 +1,53 @@
+use std::sync::Arc;
+
 use aws_config::BehaviorVersion;
 use axum_test::TestServer;
 use reqwest::Url;
 use sha2::{Digest as _, Sha256};
 use uuid::{ContextV7, Timestamp};
 
-use crate::api::TenantID;
+use crate::{
+    api::TenantID,
+    server::{ServerState, object_store::S3ObjectStore},
+};
+
+/// A server state with default settings, for tests that call handlers
+/// directly or need to override a setting, e.g.:
+///
+/// ```ignore
+/// let state = ServerState {
+///     max_repos_per_tenant: Some(2),
+///     ..test_server_state(pool)
+/// };
+/// ```
+///
+/// The S3 client isn't configured from the environment, so only use this in
+/// tests that don't make S3 requests.
+pub fn test_server_state(db: sqlx::PgPool) -> ServerState {
+    let s3 = aws_sdk_s3::Client::from_conf(
+        aws_sdk_s3::Config::builder()
+            .behavior_version_latest()
+            .build(),
+    );
+    server_state(db, s3, String::from("attune-test-0"))
+}
+
+fn server_state(db: sqlx::PgPool, s3: aws_sdk_s3::Client, s3_bucket_name: String) -> ServerState {
+    ServerState {
+        db,
+        object_store: Arc::new(S3ObjectStore::new(s3.clone())),
+        s3,
+        s3_bucket_name,
+        s3_concurrency: Default::default(),
+        public_base_url: None,
+        max_repos_per_tenant: None,
+        index_contents_encoding: None,
+        package_key_scheme: Default::default(),
+        cdn: None,
+        metrics: None,
+        index_deletion_grace: crate::server::repo::index::deletion::DEFAULT_GRACE,
+    }
+}
 
 /// A test server for Attune, and all its parts for manual validation/testing.
 pub struct AttuneTestServer {
@@ -53,14 +96,12 @@ impl AttuneTestServer {
             .unwrap_or(String::from("test-api-token"));
 
         let app = crate::server::new(
-            crate::server::ServerState {
-                db: config.db.clone(),
-                s3: s3.clone(),
-                s3_bucket_name: s3_bucket_name.clone(),
-            },
+            server_state(config.db.clone(), s3.clone(), s3_bucket_name.clone()),
             // TODO: Migrate all tests to use `create_test_tenant`, and then set
             // this to `None` to remove the footgun.
             Some(http_api_token.clone()),
+            crate::server::Timeouts::default(),
+            None,
         )
         .await;
 
@@ -292,6 +292,27 @@ async fn e2e_run() {
             }
         }
     }
+
+    // Also publish a distribution that only lists SHA256 checksums, to check
+    // that APT accepts its Release file.
+    let (_, _, exit_code) = exec(format!(
+        "{ATTUNE_CLI_PATH} apt dist create --repo {} --name modern --sha256-only",
+        repo.name
+    ));
+    assert!(exit_code.success());
+    let (_, _, exit_code) = exec(format!(
+        "{ATTUNE_CLI_PATH} apt package add --key-id {key_id} --repo {} --distribution modern --component main {WORKSPACE_ROOT}/scripts/fixtures/{}",
+        repo.name, PACKAGES[0]
+    ));
+    assert!(exit_code.success());
+    let (release, _, exit_code) = exec(format!(
+        "{ATTUNE_CLI_PATH} apt dist show --repo {} --name modern --release",
+        repo.name
+    ));
+    assert!(exit_code.success());
+    assert!(release.contains("SHA256:"));
+    assert!(!release.contains("MD5Sum:"));
+
     debug!("waiting for uploads to complete");
 
     let results = uploads.join_all().await;
@@ -321,17 +342,25 @@ async fn e2e_run() {
         .unwrap();
     debug!(?image, "built testinstall image");
 
-    let repo_url = format!(
-        "http://localhost:9000/{}/{}",
-        repo.s3_bucket, repo.s3_prefix
-    );
+    let repo_url = repo.base_url.clone().unwrap_or_else(|| {
+        format!(
+            "http://localhost:9000/{}/{}",
+            repo.s3_bucket, repo.s3_prefix
+        )
+    });
     let container = image
         .with_cmd(vec!["sleep", "infinity"])
         .with_copy_to("/etc/apt/keyrings/attune.asc", pubkey.into_bytes())
         .with_copy_to(
             "/etc/apt/sources.list",
-            format!("deb [signed-by=/etc/apt/keyrings/attune.asc] {repo_url} stable main\n",)
-                .into_bytes(),
+            format!(
+                concat!(
+                    "deb [signed-by=/etc/apt/keyrings/attune.asc] {repo_url} stable main\n",
+                    "deb [signed-by=/etc/apt/keyrings/attune.asc] {repo_url} modern main\n",
+                ),
+                repo_url = repo_url
+            )
+            .into_bytes(),
         )
         .with_network("host")
         .start()
@@ -437,6 +466,7 @@ struct AttuneRepository {
     name: String,
     s3_bucket: String,
     s3_prefix: String,
+    base_url: Option<String>,
 }
 
 impl AttuneRepository {
@@ -457,6 +487,7 @@ impl AttuneRepository {
             name,
             s3_bucket: res.s3_bucket,
             s3_prefix: res.s3_prefix,
+            base_url: res.base_url,
         }
     }
 }